@@ -0,0 +1,75 @@
+//! Benchmark of the construction of the node graph: signals, gates and connections between them.
+//!
+//! Run with `cargo bench --bench connections`. Each size builds a chain of `n` multiplications,
+//! the output of each one connected to the input of the next, like the outputs of components
+//! assigned to the inputs of the next component, and reports the time taken by each step.
+
+use circom_2_arithc::{a_gate_type::AGateType, compiler::Compiler};
+use std::time::{Duration, Instant};
+
+const SIZES: [u32; 4] = [1_000, 4_000, 16_000, 64_000];
+
+/// Signal ids of the input and output of the `i`-th multiplication.
+fn io(i: u32) -> (u32, u32) {
+    (2 * i, 2 * i + 1)
+}
+
+/// Adds the signals and gates of the chain, returning the time taken by the gates.
+fn build(n: u32) -> (Compiler, Duration) {
+    let mut compiler = Compiler::new();
+    for i in 0..n {
+        let (input, output) = io(i);
+        compiler
+            .add_signal(input, format!("0.in[{}]", i), None)
+            .unwrap();
+        compiler
+            .add_signal(output, format!("0.out[{}]", i), None)
+            .unwrap();
+    }
+
+    let start = Instant::now();
+    for i in 0..n {
+        let (input, output) = io(i);
+        compiler
+            .add_gate(AGateType::AMul, input, input, output)
+            .unwrap();
+    }
+
+    (compiler, start.elapsed())
+}
+
+fn connections(n: u32) -> Vec<(u32, u32)> {
+    (1..n).map(|i| (io(i - 1).1, io(i).0)).collect()
+}
+
+fn main() {
+    println!(
+        "{:>8} {:>12} {:>16} {:>16} {:>16}",
+        "gates", "add_gate", "add_connection", "add_connections", "resolve_aliases"
+    );
+
+    for n in SIZES {
+        let (mut compiler, gates) = build(n);
+        let start = Instant::now();
+        for (a, b) in connections(n) {
+            compiler.add_connection(a, b).unwrap();
+        }
+        let sequential = start.elapsed();
+
+        let (mut compiler, _) = build(n);
+        let start = Instant::now();
+        compiler.add_connections(&connections(n)).unwrap();
+        let batched = start.elapsed();
+
+        let (mut compiler, _) = build(n);
+        let start = Instant::now();
+        compiler.add_aliases(&connections(n));
+        compiler.resolve_aliases().unwrap();
+        let aliases = start.elapsed();
+
+        println!(
+            "{:>8} {:>12.2?} {:>16.2?} {:>16.2?} {:>16.2?}",
+            n, gates, sequential, batched, aliases
+        );
+    }
+}
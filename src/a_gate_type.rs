@@ -1,5 +1,11 @@
+use crate::{
+    cli::{Prime, ValueType},
+    field::FieldElement,
+    process::execute_op,
+};
 use circom_program_structure::ast::ExpressionInfixOpcode;
 use serde::{Deserialize, Serialize};
+use std::str::FromStr;
 use strum_macros::{Display as StrumDisplay, EnumString};
 
 /// The supported Arithmetic gate types.
@@ -25,6 +31,248 @@ pub enum AGateType {
     ABoolAnd,
     ABitOr,
     ABitAnd,
+    /// Arithmetic right shift, filling with the sign bit.
+    AShiftRArith,
+    /// Signed comparisons, on two's complement values.
+    ASLt,
+    ASLEq,
+    ASGt,
+    ASGEq,
+    /// Multiplication by a constant. The right input is a constant node, inlined into the gate
+    /// when the circuit is built, e.g. `1 1 3 4 ACMul:5` for `wire 4 = wire 3 * 5`.
+    ACMul,
+}
+
+/// Separates the operation of a Bristol gate from the constant inlined into it, as in `ACMul:5`.
+const INLINE_CONSTANT_SEPARATOR: char = ':';
+
+/// Returns the operation of a Bristol gate without the constant inlined into it, e.g. `ACMul` for
+/// `ACMul:5`.
+pub fn bristol_op_name(op: &str) -> &str {
+    op.split_once(INLINE_CONSTANT_SEPARATOR)
+        .map_or(op, |(name, _)| name)
+}
+
+/// Rough cost of a gate in an arithmetic MPC backend.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum CostClass {
+    /// Computed locally, e.g. additions.
+    Linear,
+    /// A single multiplication.
+    Multiplication,
+    /// Needs the bits of its inputs, e.g. comparisons and shifts.
+    BitDecomposition,
+    /// Iterates over the bits of its inputs, e.g. divisions and powers.
+    Iterative,
+}
+
+impl CostClass {
+    /// Returns the default cost of the class, in multiplications of 32-bit values.
+    pub fn default_cost(&self) -> u32 {
+        match self {
+            CostClass::Linear => 0,
+            CostClass::Multiplication => 1,
+            CostClass::BitDecomposition => 32,
+            CostClass::Iterative => 1024,
+        }
+    }
+}
+
+impl AGateType {
+    /// Returns the number of inputs of the gate.
+    pub fn arity(&self) -> usize {
+        2
+    }
+
+    /// Returns whether swapping the inputs of the gate leaves its result unchanged.
+    pub fn is_commutative(&self) -> bool {
+        matches!(
+            self,
+            AGateType::AAdd
+                | AGateType::AMul
+                | AGateType::AEq
+                | AGateType::ANeq
+                | AGateType::AXor
+                | AGateType::ABoolOr
+                | AGateType::ABoolAnd
+                | AGateType::ABitOr
+                | AGateType::ABitAnd
+        )
+    }
+
+    /// Returns whether the gate compares its inputs, signed or not.
+    pub fn is_comparison(&self) -> bool {
+        matches!(
+            self,
+            AGateType::AEq
+                | AGateType::ANeq
+                | AGateType::ALt
+                | AGateType::ALEq
+                | AGateType::AGt
+                | AGateType::AGEq
+                | AGateType::ASLt
+                | AGateType::ASLEq
+                | AGateType::ASGt
+                | AGateType::ASGEq
+        )
+    }
+
+    /// Returns whether the output of the gate is always 0 or 1.
+    pub fn result_is_boolean(&self) -> bool {
+        self.is_comparison() || matches!(self, AGateType::ABoolAnd | AGateType::ABoolOr)
+    }
+
+    /// Returns the cost class of the gate.
+    pub fn cost_class(&self) -> CostClass {
+        match self {
+            AGateType::AAdd | AGateType::ASub | AGateType::ACMul => CostClass::Linear,
+            AGateType::AMul | AGateType::ABoolAnd | AGateType::ABoolOr => CostClass::Multiplication,
+            AGateType::ADiv | AGateType::AIntDiv | AGateType::AMod | AGateType::APow => {
+                CostClass::Iterative
+            }
+            _ => CostClass::BitDecomposition,
+        }
+    }
+
+    /// Returns the default cost of the gate, in multiplications of 32-bit values.
+    pub fn default_cost(&self) -> u32 {
+        self.cost_class().default_cost()
+    }
+
+    /// Returns the variant of the gate matching the signedness of the value type, e.g. `ASLt`
+    /// instead of `ALt` for signed values.
+    pub fn for_value_type(self, value_type: ValueType) -> Self {
+        if !value_type.is_signed() {
+            return self;
+        }
+
+        match self {
+            AGateType::AShiftR => AGateType::AShiftRArith,
+            AGateType::ALt => AGateType::ASLt,
+            AGateType::ALEq => AGateType::ASLEq,
+            AGateType::AGt => AGateType::ASGt,
+            AGateType::AGEq => AGateType::ASGEq,
+            _ => self,
+        }
+    }
+
+    /// Returns whether the gate is the signed variant of a comparison or right shift.
+    pub fn is_signed(self) -> bool {
+        matches!(
+            self,
+            AGateType::AShiftRArith
+                | AGateType::ASLt
+                | AGateType::ASLEq
+                | AGateType::ASGt
+                | AGateType::ASGEq
+        )
+    }
+
+    /// Returns the operation of a Bristol gate, followed by the constant inlined into it if any.
+    pub fn bristol_op(&self, constant: Option<u32>) -> String {
+        match constant {
+            Some(constant) => format!("{}{}{}", self, INLINE_CONSTANT_SEPARATOR, constant),
+            None => self.to_string(),
+        }
+    }
+
+    /// Parses the operation of a Bristol gate, along with the constant inlined into it if any.
+    pub fn parse_bristol_op(op: &str) -> Option<(Self, Option<u32>)> {
+        match op.split_once(INLINE_CONSTANT_SEPARATOR) {
+            Some((op, constant)) => Some((Self::from_str(op).ok()?, Some(constant.parse().ok()?))),
+            None => Some((Self::from_str(op).ok()?, None)),
+        }
+    }
+
+    /// Evaluates the gate on 32-bit values of the given type, wrapping on overflow.
+    /// Returns `None` when the result is undefined, e.g. on division by zero. Signed gates
+    /// always treat their inputs as signed, while the signedness of the other shifts and
+    /// comparisons follows the value type.
+    pub fn evaluate(&self, lhs: u32, rhs: u32, value_type: ValueType) -> Option<u32> {
+        let signed = value_type.is_signed();
+        let (slhs, srhs) = (lhs as i32, rhs as i32);
+
+        let res = match self {
+            AGateType::AAdd => lhs.wrapping_add(rhs),
+            AGateType::ADiv | AGateType::AIntDiv if signed => slhs.checked_div(srhs)? as u32,
+            AGateType::ADiv | AGateType::AIntDiv => lhs.checked_div(rhs)?,
+            AGateType::AEq => (lhs == rhs) as u32,
+            AGateType::AGEq if signed => (slhs >= srhs) as u32,
+            AGateType::AGEq => (lhs >= rhs) as u32,
+            AGateType::AGt if signed => (slhs > srhs) as u32,
+            AGateType::AGt => (lhs > rhs) as u32,
+            AGateType::ALEq if signed => (slhs <= srhs) as u32,
+            AGateType::ALEq => (lhs <= rhs) as u32,
+            AGateType::ALt if signed => (slhs < srhs) as u32,
+            AGateType::ALt => (lhs < rhs) as u32,
+            AGateType::AMul | AGateType::ACMul => lhs.wrapping_mul(rhs),
+            AGateType::ANeq => (lhs != rhs) as u32,
+            AGateType::ASub => lhs.wrapping_sub(rhs),
+            AGateType::AXor => lhs ^ rhs,
+            AGateType::APow => lhs.wrapping_pow(rhs),
+            AGateType::AMod if signed => slhs.checked_rem(srhs)? as u32,
+            AGateType::AMod => lhs.checked_rem(rhs)?,
+            AGateType::AShiftL => lhs.checked_shl(rhs).unwrap_or(0),
+            // Arithmetic shift, shifting out every bit leaves the sign
+            AGateType::AShiftR if signed => slhs.checked_shr(rhs).unwrap_or(slhs >> 31) as u32,
+            AGateType::AShiftR => lhs.checked_shr(rhs).unwrap_or(0),
+            AGateType::ABoolOr => (lhs != 0 || rhs != 0) as u32,
+            AGateType::ABoolAnd => (lhs != 0 && rhs != 0) as u32,
+            AGateType::ABitOr => lhs | rhs,
+            AGateType::ABitAnd => lhs & rhs,
+            AGateType::AShiftRArith => slhs.checked_shr(rhs).unwrap_or(slhs >> 31) as u32,
+            AGateType::ASLt => (slhs < srhs) as u32,
+            AGateType::ASLEq => (slhs <= srhs) as u32,
+            AGateType::ASGt => (slhs > srhs) as u32,
+            AGateType::ASGEq => (slhs >= srhs) as u32,
+        };
+
+        Some(res)
+    }
+
+    /// Evaluates the gate on field elements, with the compile-time semantics of its operator, see
+    /// `execute_op`, except that subtraction wraps modulo the prime for every value type.
+    /// Returns `None` when the result is undefined, e.g. on division by zero. Signed gates always
+    /// treat their inputs as signed.
+    pub fn evaluate_field(
+        &self,
+        lhs: &FieldElement,
+        rhs: &FieldElement,
+        value_type: ValueType,
+        prime: Prime,
+    ) -> Option<FieldElement> {
+        let (op, value_type) = match self {
+            AGateType::ASub => return Some(lhs.sub(rhs, prime)),
+            AGateType::ACMul => (ExpressionInfixOpcode::Mul, value_type),
+            AGateType::AShiftRArith => (ExpressionInfixOpcode::ShiftR, ValueType::Sint),
+            AGateType::ASLt => (ExpressionInfixOpcode::Lesser, ValueType::Sint),
+            AGateType::ASLEq => (ExpressionInfixOpcode::LesserEq, ValueType::Sint),
+            AGateType::ASGt => (ExpressionInfixOpcode::Greater, ValueType::Sint),
+            AGateType::ASGEq => (ExpressionInfixOpcode::GreaterEq, ValueType::Sint),
+            AGateType::AAdd => (ExpressionInfixOpcode::Add, value_type),
+            AGateType::ADiv => (ExpressionInfixOpcode::Div, value_type),
+            AGateType::AEq => (ExpressionInfixOpcode::Eq, value_type),
+            AGateType::AGEq => (ExpressionInfixOpcode::GreaterEq, value_type),
+            AGateType::AGt => (ExpressionInfixOpcode::Greater, value_type),
+            AGateType::ALEq => (ExpressionInfixOpcode::LesserEq, value_type),
+            AGateType::ALt => (ExpressionInfixOpcode::Lesser, value_type),
+            AGateType::AMul => (ExpressionInfixOpcode::Mul, value_type),
+            AGateType::ANeq => (ExpressionInfixOpcode::NotEq, value_type),
+            AGateType::AXor => (ExpressionInfixOpcode::BitXor, value_type),
+            AGateType::APow => (ExpressionInfixOpcode::Pow, value_type),
+            AGateType::AIntDiv => (ExpressionInfixOpcode::IntDiv, value_type),
+            AGateType::AMod => (ExpressionInfixOpcode::Mod, value_type),
+            AGateType::AShiftL => (ExpressionInfixOpcode::ShiftL, value_type),
+            AGateType::AShiftR => (ExpressionInfixOpcode::ShiftR, value_type),
+            AGateType::ABoolOr => (ExpressionInfixOpcode::BoolOr, value_type),
+            AGateType::ABoolAnd => (ExpressionInfixOpcode::BoolAnd, value_type),
+            AGateType::ABitOr => (ExpressionInfixOpcode::BitOr, value_type),
+            AGateType::ABitAnd => (ExpressionInfixOpcode::BitAnd, value_type),
+        };
+
+        execute_op(lhs, rhs, &op, value_type, prime).ok()
+    }
 }
 
 impl From<&ExpressionInfixOpcode> for AGateType {
@@ -53,3 +301,185 @@ impl From<&ExpressionInfixOpcode> for AGateType {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evaluate() {
+        let uint = ValueType::Uint;
+        assert_eq!(AGateType::AAdd.evaluate(3, 4, uint), Some(7));
+        assert_eq!(AGateType::ASub.evaluate(3, 4, uint), Some(u32::MAX));
+        assert_eq!(AGateType::AMul.evaluate(6, 7, uint), Some(42));
+        assert_eq!(AGateType::AIntDiv.evaluate(7, 2, uint), Some(3));
+        assert_eq!(AGateType::AMod.evaluate(7, 2, uint), Some(1));
+        assert_eq!(AGateType::APow.evaluate(2, 4, uint), Some(16));
+        assert_eq!(AGateType::AShiftL.evaluate(1, 40, uint), Some(0));
+        assert_eq!(AGateType::ALt.evaluate(2, 3, uint), Some(1));
+        assert_eq!(AGateType::ABoolAnd.evaluate(2, 0, uint), Some(0));
+        assert_eq!(AGateType::AXor.evaluate(1, 3, uint), Some(2));
+        assert_eq!(AGateType::ACMul.evaluate(6, 7, uint), Some(42));
+    }
+
+    #[test]
+    fn test_bristol_op() {
+        assert_eq!(AGateType::AMul.bristol_op(None), "AMul");
+        assert_eq!(AGateType::ACMul.bristol_op(Some(5)), "ACMul:5");
+        assert_eq!(
+            AGateType::parse_bristol_op("ACMul:5"),
+            Some((AGateType::ACMul, Some(5)))
+        );
+        assert_eq!(
+            AGateType::parse_bristol_op("AAdd"),
+            Some((AGateType::AAdd, None))
+        );
+        assert_eq!(AGateType::parse_bristol_op("ACMul:x"), None);
+        assert_eq!(AGateType::parse_bristol_op("XOR"), None);
+        assert_eq!(bristol_op_name("ACMul:5"), "ACMul");
+        assert_eq!(bristol_op_name("XOR"), "XOR");
+    }
+
+    #[test]
+    fn test_evaluate_signed() {
+        let minus_two = -2i32 as u32;
+        let (sint, uint) = (ValueType::Sint, ValueType::Uint);
+
+        assert_eq!(AGateType::ALt.evaluate(minus_two, 1, sint), Some(1));
+        assert_eq!(AGateType::ALt.evaluate(minus_two, 1, uint), Some(0));
+        assert_eq!(AGateType::AGEq.evaluate(minus_two, 1, sint), Some(0));
+        assert_eq!(AGateType::AGEq.evaluate(minus_two, 1, uint), Some(1));
+        assert_eq!(
+            AGateType::AShiftR.evaluate(minus_two, 1, sint),
+            Some(u32::MAX)
+        );
+        assert_eq!(
+            AGateType::AShiftR.evaluate(minus_two, 1, uint),
+            Some(i32::MAX as u32)
+        );
+        assert_eq!(
+            AGateType::AShiftR.evaluate(minus_two, 40, sint),
+            Some(u32::MAX)
+        );
+        assert_eq!(
+            AGateType::AIntDiv.evaluate(minus_two, 2, sint),
+            Some(-1i32 as u32)
+        );
+        assert_eq!(
+            AGateType::AIntDiv.evaluate(minus_two, 2, uint),
+            Some(i32::MAX as u32)
+        );
+    }
+
+    #[test]
+    fn test_evaluate_signed_gates() {
+        let minus_two = -2i32 as u32;
+        let uint = ValueType::Uint;
+
+        assert_eq!(AGateType::ASLt.evaluate(minus_two, 1, uint), Some(1));
+        assert_eq!(AGateType::ASLEq.evaluate(1, minus_two, uint), Some(0));
+        assert_eq!(AGateType::ASGt.evaluate(1, minus_two, uint), Some(1));
+        assert_eq!(AGateType::ASGEq.evaluate(minus_two, 1, uint), Some(0));
+        assert_eq!(
+            AGateType::AShiftRArith.evaluate(minus_two, 1, uint),
+            Some(u32::MAX)
+        );
+        assert_eq!(
+            AGateType::AShiftRArith.evaluate(minus_two, 40, uint),
+            Some(u32::MAX)
+        );
+        assert_eq!(AGateType::AShiftRArith.evaluate(8, 2, uint), Some(2));
+    }
+
+    #[test]
+    fn test_evaluate_field() {
+        let (uint, prime) = (ValueType::Uint, Prime::Bn128);
+        let evaluate = |op: AGateType, lhs: u32, rhs: u32| {
+            op.evaluate_field(&lhs.into(), &rhs.into(), uint, prime)
+        };
+        let minus_one = FieldElement::from_word(-1i32 as u32, ValueType::Sint, prime);
+
+        // Values aren't bounded by a word, and subtraction wraps modulo the prime
+        assert_eq!(
+            evaluate(AGateType::AMul, 1 << 20, 1 << 20),
+            Some(FieldElement::from(1 << 20).pow(&FieldElement::from(2), prime))
+        );
+        assert_eq!(evaluate(AGateType::ASub, 3, 4), Some(minus_one.clone()));
+        assert_eq!(
+            evaluate(AGateType::ADiv, 1, 2).map(|half| half.mul(&FieldElement::from(2), prime)),
+            Some(FieldElement::from(1))
+        );
+        assert_eq!(
+            evaluate(AGateType::AIntDiv, 7, 2),
+            Some(FieldElement::from(3))
+        );
+        assert_eq!(evaluate(AGateType::AIntDiv, 7, 0), None);
+        assert_eq!(
+            evaluate(AGateType::ACMul, 6, 7),
+            Some(FieldElement::from(42))
+        );
+
+        // Signed gates treat their inputs as signed
+        let lt = AGateType::ASLt.evaluate_field(&minus_one, &FieldElement::from(1), uint, prime);
+        assert_eq!(lt, Some(FieldElement::from(1)));
+        let lt = AGateType::ALt.evaluate_field(&minus_one, &FieldElement::from(1), uint, prime);
+        assert_eq!(lt, Some(FieldElement::from(0)));
+    }
+
+    #[test]
+    fn test_metadata() {
+        assert_eq!(AGateType::ASub.arity(), 2);
+        assert!(AGateType::AMul.is_commutative());
+        assert!(!AGateType::ASub.is_commutative());
+        assert!(!AGateType::ALt.is_commutative());
+        assert!(AGateType::ASGEq.is_comparison());
+        assert!(!AGateType::ABoolAnd.is_comparison());
+        assert!(AGateType::ABoolAnd.result_is_boolean());
+        assert!(!AGateType::ABitAnd.result_is_boolean());
+
+        assert_eq!(AGateType::AAdd.cost_class(), CostClass::Linear);
+        assert_eq!(AGateType::AMul.cost_class(), CostClass::Multiplication);
+        assert_eq!(AGateType::ALt.cost_class(), CostClass::BitDecomposition);
+        assert_eq!(AGateType::AShiftR.cost_class(), CostClass::BitDecomposition);
+        assert_eq!(AGateType::AMod.cost_class(), CostClass::Iterative);
+        assert_eq!(AGateType::ACMul.cost_class(), CostClass::Linear);
+        assert_eq!(AGateType::AAdd.default_cost(), 0);
+        assert!(AGateType::AMul.default_cost() < AGateType::AEq.default_cost());
+    }
+
+    #[test]
+    fn test_for_value_type() {
+        assert_eq!(
+            AGateType::ALt.for_value_type(ValueType::Sint),
+            AGateType::ASLt
+        );
+        assert_eq!(
+            AGateType::AShiftR.for_value_type(ValueType::Sint),
+            AGateType::AShiftRArith
+        );
+        assert_eq!(
+            AGateType::ALt.for_value_type(ValueType::Uint),
+            AGateType::ALt
+        );
+        assert_eq!(
+            AGateType::AAdd.for_value_type(ValueType::Sint),
+            AGateType::AAdd
+        );
+        assert!(AGateType::ASGEq.is_signed());
+        assert!(!AGateType::AGEq.is_signed());
+        assert!(!AGateType::AMul.is_signed());
+    }
+
+    #[test]
+    fn test_evaluate_undefined() {
+        assert_eq!(AGateType::ADiv.evaluate(1, 0, ValueType::Uint), None);
+        assert_eq!(AGateType::AIntDiv.evaluate(1, 0, ValueType::Uint), None);
+        assert_eq!(AGateType::AMod.evaluate(1, 0, ValueType::Uint), None);
+        assert_eq!(AGateType::AMod.evaluate(1, 0, ValueType::Sint), None);
+        // Overflows i32
+        assert_eq!(
+            AGateType::AIntDiv.evaluate(i32::MIN as u32, -1i32 as u32, ValueType::Sint),
+            None
+        );
+    }
+}
@@ -0,0 +1,277 @@
+//! # Artifacts Module
+//!
+//! This module describes the artifacts of a compilation in a manifest, `manifest.json`, written
+//! along with them, and loads the artifacts of an output directory back into typed structs for
+//! downstream tooling.
+
+use crate::{
+    bristol_reader::BristolReader,
+    cli::{build_output, OutputFormat},
+    compiler::CircuitReport,
+    conversions::ConversionPlan,
+    deps::DependencyGraph,
+    json_circuit::JsonCircuit,
+    log_trace::LogEntry,
+    program::{Artifacts, ProgramError},
+    provenance::WireProvenance,
+    signal_map::SignalMap,
+    wire_types::WireType,
+};
+use bristol_circuit::{BristolCircuit, CircuitInfo};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+/// Version of the layout of the artifacts, increased when an artifact changes incompatibly.
+pub const ARTIFACTS_VERSION: u32 = 1;
+
+/// Artifacts of a compilation, `manifest.json`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArtifactManifest {
+    /// Layout version of the artifacts, see `ARTIFACTS_VERSION`.
+    pub version: u32,
+    /// Name and version of the compiler that wrote the artifacts.
+    pub compiler: String,
+    pub format: OutputFormat,
+    /// File of each artifact in the output directory, keyed by the `Artifacts` field it is
+    /// written to, e.g. `"report": "report.json"`.
+    pub files: BTreeMap<String, String>,
+    /// Metadata given with `--meta`, e.g. `"model_version": "1.2"`.
+    #[serde(default)]
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl ArtifactManifest {
+    /// Describes the artifacts written to `dir`. Files written elsewhere, e.g. the metrics file,
+    /// aren't listed.
+    pub fn new(artifacts: &Artifacts, format: OutputFormat, dir: &Path) -> Self {
+        let paths = [
+            ("circuit", Some(&artifacts.circuit)),
+            ("circuit_info", Some(&artifacts.circuit_info)),
+            ("sym", artifacts.sym.as_ref()),
+            ("report", Some(&artifacts.report)),
+            ("card", Some(&artifacts.card)),
+            ("deps", Some(&artifacts.deps)),
+            ("wire_types", artifacts.wire_types.as_ref()),
+            ("inputs_template", artifacts.inputs_template.as_ref()),
+            ("simd_groups", artifacts.simd_groups.as_ref()),
+            ("signal_map", artifacts.signal_map.as_ref()),
+            ("graphviz", artifacts.graphviz.as_ref()),
+            ("provenance", artifacts.provenance.as_ref()),
+            ("conversions", artifacts.conversions.as_ref()),
+            ("log", artifacts.log.as_ref()),
+        ];
+
+        let files = paths
+            .into_iter()
+            .filter_map(|(kind, path)| {
+                let file = path?.strip_prefix(dir).ok()?.to_str()?;
+                Some((kind.to_string(), file.to_string()))
+            })
+            .collect();
+
+        Self {
+            version: ARTIFACTS_VERSION,
+            compiler: format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+            format,
+            files,
+            metadata: BTreeMap::new(),
+        }
+    }
+}
+
+/// Artifacts loaded from an output directory by `Artifacts::load`, `None` for those that weren't
+/// written.
+#[derive(Debug, Default)]
+pub struct LoadedArtifacts {
+    pub manifest: Option<ArtifactManifest>,
+    /// Bristol circuit with its IO wires, only loaded along with `circuit_info.json`, or JSON
+    /// circuit. Constraint systems aren't loaded.
+    pub circuit: Option<BristolCircuit>,
+    pub circuit_info: Option<CircuitInfo>,
+    pub report: Option<CircuitReport>,
+    pub deps: Option<DependencyGraph>,
+    pub wire_types: Option<Vec<WireType>>,
+    pub signal_map: Option<SignalMap>,
+    pub provenance: Option<Vec<WireProvenance>>,
+    pub conversions: Option<ConversionPlan>,
+    pub log: Option<Vec<LogEntry>>,
+}
+
+impl Artifacts {
+    /// Loads the artifacts found in an output directory. Files are found through the manifest if
+    /// there is one, under their default names otherwise, and artifacts of a newer layout than
+    /// `ARTIFACTS_VERSION` are rejected.
+    pub fn load(dir: impl AsRef<Path>) -> Result<LoadedArtifacts, ProgramError> {
+        let dir = dir.as_ref();
+        let manifest_path = build_output(dir, "manifest", "json");
+        let manifest = if manifest_path.exists() {
+            Some(read_json::<ArtifactManifest>(&manifest_path)?)
+        } else {
+            None
+        };
+        if let Some(manifest) = &manifest {
+            if manifest.version > ARTIFACTS_VERSION {
+                return Err(ProgramError::UnsupportedArtifacts {
+                    version: manifest.version,
+                });
+            }
+        }
+
+        // Returns the file of an artifact, if it was written
+        let find = |kind: &str, default: &str| -> Option<PathBuf> {
+            let path = match manifest
+                .as_ref()
+                .and_then(|manifest| manifest.files.get(kind))
+            {
+                Some(file) => dir.join(file),
+                None => dir.join(default),
+            };
+            path.exists().then_some(path)
+        };
+
+        let mut loaded = LoadedArtifacts {
+            circuit_info: find("circuit_info", "circuit_info.json")
+                .map(|path| read_json(&path))
+                .transpose()?,
+            report: find("report", "report.json")
+                .map(CircuitReport::from_path)
+                .transpose()?,
+            deps: find("deps", "deps.json")
+                .map(|path| read_json(&path))
+                .transpose()?,
+            wire_types: find("wire_types", "wire_types.json")
+                .map(|path| read_json(&path))
+                .transpose()?,
+            signal_map: find("signal_map", "signals.sym")
+                .map(|path| SignalMap::read(BufReader::new(File::open(path)?)))
+                .transpose()?,
+            provenance: find("provenance", "provenance.json")
+                .map(|path| read_json(&path))
+                .transpose()?,
+            conversions: find("conversions", "conversions.json")
+                .map(|path| read_json(&path))
+                .transpose()?,
+            log: find("log", "log.json")
+                .map(|path| read_json(&path))
+                .transpose()?,
+            ..Default::default()
+        };
+
+        let circuit = find("circuit", "circuit.txt");
+        match circuit.as_ref().and_then(|path| path.extension()) {
+            Some(ext) if ext == "json" => {
+                let path = circuit.as_ref().unwrap();
+                loaded.circuit = Some(JsonCircuit::from_path(path)?.into_circuit()?);
+            }
+            Some(ext) if ext == "txt" => {
+                if let (Some(path), Some(info)) = (&circuit, &loaded.circuit_info) {
+                    loaded.circuit = Some(BristolReader::open(path)?.read_circuit(info)?);
+                }
+            }
+            _ => {}
+        }
+        loaded.manifest = manifest;
+
+        Ok(loaded)
+    }
+}
+
+/// Serializes the metadata of an artifact as canonical JSON: pretty-printed, with the keys of
+/// every object sorted, so that the same compilation always writes the same bytes regardless of
+/// the iteration order of the maps it was built from.
+pub fn to_canonical_json<T: Serialize + ?Sized>(value: &T) -> Result<String, serde_json::Error> {
+    serde_json::to_string_pretty(&sort_keys(serde_json::to_value(value)?))
+}
+
+/// Sorts the keys of every object of a JSON value. Objects are only sorted by serde_json itself
+/// as long as no crate enables its `preserve_order` feature.
+fn sort_keys(value: Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let mut entries = map.into_iter().collect::<Vec<_>>();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            Value::Object(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key, sort_keys(value)))
+                    .collect(),
+            )
+        }
+        Value::Array(values) => Value::Array(values.into_iter().map(sort_keys).collect()),
+        value => value,
+    }
+}
+
+fn read_json<T: DeserializeOwned>(path: &Path) -> Result<T, ProgramError> {
+    Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_canonical_json() {
+        let inputs = |order: &mut dyn Iterator<Item = usize>| {
+            let inputs = order
+                .map(|i| (format!("0.in[{}]", i), i))
+                .collect::<HashMap<String, usize>>();
+            HashMap::from([("inputs", inputs)])
+        };
+        let json = to_canonical_json(&inputs(&mut (0..32))).unwrap();
+
+        // Keys are sorted as strings, e.g. `0.in[10]` before `0.in[2]`
+        let keys = json
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("\"0.in["))
+            .collect::<Vec<_>>();
+        assert_eq!(keys.len(), 32);
+        assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(
+            to_canonical_json(&inputs(&mut (0..32).rev())).unwrap(),
+            json
+        );
+    }
+
+    #[test]
+    fn test_load_artifacts() {
+        let dir = std::env::temp_dir().join("circom_2_arithc_load_artifacts");
+        fs::create_dir_all(&dir).unwrap();
+        for file in ["manifest.json", "circuit_info.json", "signals.sym"] {
+            let _ = fs::remove_file(dir.join(file));
+        }
+
+        // Nothing is loaded from an empty directory
+        let loaded = Artifacts::load(&dir).unwrap();
+        assert!(loaded.manifest.is_none() && loaded.circuit_info.is_none());
+
+        fs::write(dir.join("signals.sym"), "0,0.in,Main\n").unwrap();
+        let loaded = Artifacts::load(&dir).unwrap();
+        assert_eq!(loaded.signal_map.unwrap().wire_names(0), vec!["0.in"]);
+        assert!(loaded.report.is_none());
+
+        let manifest = ArtifactManifest {
+            version: ARTIFACTS_VERSION + 1,
+            compiler: String::from("circom-2-arithc 9.0.0"),
+            format: OutputFormat::Bristol,
+            files: BTreeMap::new(),
+            metadata: BTreeMap::new(),
+        };
+        fs::write(
+            dir.join("manifest.json"),
+            serde_json::to_string(&manifest).unwrap(),
+        )
+        .unwrap();
+        assert!(matches!(
+            Artifacts::load(&dir),
+            Err(ProgramError::UnsupportedArtifacts { version }) if version == ARTIFACTS_VERSION + 1
+        ));
+    }
+}
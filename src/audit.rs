@@ -0,0 +1,138 @@
+//! # Audit Module
+//!
+//! This module records the structure of a circuit as it is built: every gate and connection added
+//! to the compiler, with the statement it comes from, appended to a JSON lines file. The SHA-256
+//! hash of the entries of a compilation ties its artifacts to the log, for environments where each
+//! gate must be traceable to the source.
+
+use crate::a_gate_type::AGateType;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+/// A mutation of the circuit. Signals are given by name, `source` is the `file:line` of the
+/// statement being processed, if any.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "entry", rename_all = "snake_case")]
+pub enum AuditEntry<'a> {
+    Gate {
+        op: AGateType,
+        lhs: &'a str,
+        rhs: &'a str,
+        out: &'a str,
+        source: Option<&'a str>,
+    },
+    Connection {
+        a: &'a str,
+        b: &'a str,
+        source: Option<&'a str>,
+    },
+}
+
+/// Append-only audit log, one JSON object per line. Entries are buffered and only written to the
+/// file when the buffer is full or the log is finished, so recording stays cheap. Recording doesn't
+/// fail, the first write error is returned when the log is finished.
+#[derive(Debug)]
+pub struct AuditLog {
+    writer: BufWriter<File>,
+    /// Hash of the lines appended since the log was opened.
+    hasher: Sha256,
+    line: Vec<u8>,
+    error: Option<io::Error>,
+}
+
+impl AuditLog {
+    /// Opens the log at the given path, appending to it if it exists.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            writer: BufWriter::new(file),
+            hasher: Sha256::new(),
+            line: Vec::new(),
+            error: None,
+        })
+    }
+
+    /// Appends an entry.
+    pub fn record(&mut self, entry: &AuditEntry) {
+        if self.error.is_some() {
+            return;
+        }
+
+        self.line.clear();
+        let result = serde_json::to_writer(&mut self.line, entry)
+            .map_err(io::Error::from)
+            .and_then(|_| {
+                self.line.push(b'\n');
+                self.hasher.update(&self.line);
+                self.writer.write_all(&self.line)
+            });
+        self.error = result.err();
+    }
+
+    /// Flushes the log and returns the SHA-256 hash, in hex, of the entries appended since it was
+    /// opened. The entries of earlier compilations are left out, so the hash only depends on the
+    /// compilation.
+    pub fn finish(mut self) -> io::Result<String> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        self.writer.flush()?;
+        Ok(format!("{:x}", self.hasher.finalize()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// Returns the SHA-256 hash of the bytes, in hex.
+    fn sha256(bytes: &[u8]) -> String {
+        format!("{:x}", Sha256::digest(bytes))
+    }
+
+    #[test]
+    fn test_audit_log() {
+        let path = std::env::temp_dir().join("circom_2_arithc_audit_log.jsonl");
+        let _ = fs::remove_file(&path);
+
+        let mut log = AuditLog::open(&path).unwrap();
+        log.record(&AuditEntry::Gate {
+            op: AGateType::AAdd,
+            lhs: "0.a",
+            rhs: "0.b",
+            out: "0.out",
+            source: Some("sum.circom:8"),
+        });
+        let hash = log.finish().unwrap();
+
+        let content = fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            content,
+            "{\"entry\":\"gate\",\"op\":\"AAdd\",\"lhs\":\"0.a\",\"rhs\":\"0.b\",\"out\":\"0.out\",\"source\":\"sum.circom:8\"}\n"
+        );
+        assert_eq!(hash, sha256(content.as_bytes()));
+
+        // Later compilations append to the log
+        let mut log = AuditLog::open(&path).unwrap();
+        log.record(&AuditEntry::Connection {
+            a: "0.out",
+            b: "1.in",
+            source: None,
+        });
+        let appended_hash = log.finish().unwrap();
+
+        let appended = fs::read_to_string(&path).unwrap();
+        assert!(appended.starts_with(&content));
+        let connection =
+            "{\"entry\":\"connection\",\"a\":\"0.out\",\"b\":\"1.in\",\"source\":null}\n";
+        assert!(appended.ends_with(connection));
+        // The hash only covers the entries of the later compilation
+        assert_eq!(appended_hash, sha256(connection.as_bytes()));
+    }
+}
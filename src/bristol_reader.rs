@@ -0,0 +1,294 @@
+//! # Bristol Reader Module
+//!
+//! This module reads Bristol circuits line by line, so artifacts of several GB can be inspected
+//! with bounded memory instead of being loaded into a single `String`.
+
+use bristol_circuit::{BristolCircuit, CircuitInfo, Gate};
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::Path,
+};
+use thiserror::Error;
+
+/// A line of a Bristol circuit.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BristolLine {
+    /// First line, with the number of gates and wires.
+    Header {
+        gate_count: usize,
+        wire_count: usize,
+    },
+    /// Widths of the inputs.
+    Inputs(Vec<usize>),
+    /// Widths of the outputs.
+    Outputs(Vec<usize>),
+    /// Gate with its input and output wires.
+    Gate {
+        inputs: Vec<usize>,
+        outputs: Vec<usize>,
+        op: String,
+    },
+}
+
+/// Callback receiving the number of bytes read so far.
+pub type ProgressCallback = Box<dyn FnMut(u64)>;
+
+/// Streaming reader of Bristol circuits, yielding one `BristolLine` at a time.
+/// A single line buffer is reused, so memory usage doesn't depend on the size of the circuit.
+pub struct BristolReader<R> {
+    reader: R,
+    buffer: String,
+    line_number: usize,
+    bytes_read: u64,
+    progress: Option<ProgressCallback>,
+}
+
+impl BristolReader<BufReader<File>> {
+    /// Opens the Bristol circuit at the given path.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: BufRead> BristolReader<R> {
+    /// Creates a reader over the given buffered reader.
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buffer: String::new(),
+            line_number: 0,
+            bytes_read: 0,
+            progress: None,
+        }
+    }
+
+    /// Sets a callback notified with the number of bytes read after each line.
+    pub fn with_progress(mut self, progress: ProgressCallback) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Returns the number of bytes read so far.
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+
+    /// Reads the rest of the circuit into a `BristolCircuit` with the given IO wires. Only the
+    /// gates are kept, the text of the circuit is never loaded whole.
+    pub fn read_circuit(self, info: &CircuitInfo) -> Result<BristolCircuit, BristolReadError> {
+        let mut circuit = BristolCircuit {
+            wire_count: 0,
+            info: info.clone(),
+            gates: Vec::new(),
+            io_widths: None,
+        };
+        let mut input_widths = None;
+        for line in self {
+            match line? {
+                BristolLine::Header {
+                    gate_count,
+                    wire_count,
+                } => {
+                    circuit.wire_count = wire_count;
+                    circuit.gates.reserve(gate_count);
+                }
+                BristolLine::Inputs(widths) => input_widths = Some(widths),
+                BristolLine::Outputs(widths) => {
+                    circuit.io_widths = input_widths.take().map(|inputs| (inputs, widths));
+                }
+                BristolLine::Gate {
+                    inputs,
+                    outputs,
+                    op,
+                } => circuit.gates.push(Gate {
+                    inputs,
+                    outputs,
+                    op,
+                }),
+            }
+        }
+
+        Ok(circuit)
+    }
+
+    /// Reads the next non-empty line into the buffer, returning false at the end of the input.
+    fn read_line(&mut self) -> io::Result<bool> {
+        loop {
+            self.buffer.clear();
+            let read = self.reader.read_line(&mut self.buffer)?;
+            if read == 0 {
+                return Ok(false);
+            }
+
+            self.bytes_read += read as u64;
+            if let Some(progress) = &mut self.progress {
+                progress(self.bytes_read);
+            }
+
+            if !self.buffer.trim().is_empty() {
+                self.line_number += 1;
+                return Ok(true);
+            }
+        }
+    }
+
+    /// Parses the line in the buffer.
+    fn parse_line(&self) -> Result<BristolLine, BristolReadError> {
+        let error = |message: &str| BristolReadError::Parse {
+            line: self.line_number,
+            message: message.to_string(),
+        };
+        let mut tokens = self.buffer.split_whitespace();
+        let mut next_number = || -> Result<usize, BristolReadError> {
+            tokens
+                .next()
+                .ok_or_else(|| error("missing value"))?
+                .parse()
+                .map_err(|_| error("invalid number"))
+        };
+
+        match self.line_number {
+            1 => Ok(BristolLine::Header {
+                gate_count: next_number()?,
+                wire_count: next_number()?,
+            }),
+            2 | 3 => {
+                let count = next_number()?;
+                let widths = (0..count)
+                    .map(|_| next_number())
+                    .collect::<Result<Vec<usize>, _>>()?;
+                if self.line_number == 2 {
+                    Ok(BristolLine::Inputs(widths))
+                } else {
+                    Ok(BristolLine::Outputs(widths))
+                }
+            }
+            _ => {
+                let input_count = next_number()?;
+                let output_count = next_number()?;
+                let inputs = (0..input_count)
+                    .map(|_| next_number())
+                    .collect::<Result<Vec<usize>, _>>()?;
+                let outputs = (0..output_count)
+                    .map(|_| next_number())
+                    .collect::<Result<Vec<usize>, _>>()?;
+                let op = tokens.next().ok_or_else(|| error("missing gate op"))?;
+
+                Ok(BristolLine::Gate {
+                    inputs,
+                    outputs,
+                    op: op.to_string(),
+                })
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for BristolReader<R> {
+    type Item = Result<BristolLine, BristolReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.read_line() {
+            Ok(true) => Some(self.parse_line()),
+            Ok(false) => None,
+            Err(e) => Some(Err(e.into())),
+        }
+    }
+}
+
+/// Bristol reading errors
+#[derive(Error, Debug)]
+pub enum BristolReadError {
+    #[error("IO error: {0}")]
+    IOError(#[from] io::Error),
+    #[error("Parse error at line {line}: {message}")]
+    Parse { line: usize, message: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::Cell, collections::HashMap, rc::Rc};
+
+    const CIRCUIT: &str = "2 5\n2 1 1\n1 1\n\n2 1 0 1 2 AAdd\n2 1 2 1 3 AMul\n";
+
+    #[test]
+    fn test_bristol_reader() {
+        let lines = BristolReader::new(CIRCUIT.as_bytes())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(
+            lines,
+            vec![
+                BristolLine::Header {
+                    gate_count: 2,
+                    wire_count: 5
+                },
+                BristolLine::Inputs(vec![1, 1]),
+                BristolLine::Outputs(vec![1]),
+                BristolLine::Gate {
+                    inputs: vec![0, 1],
+                    outputs: vec![2],
+                    op: "AAdd".to_string()
+                },
+                BristolLine::Gate {
+                    inputs: vec![2, 1],
+                    outputs: vec![3],
+                    op: "AMul".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_circuit() {
+        let info = CircuitInfo {
+            input_name_to_wire_index: HashMap::from([
+                (String::from("0.a"), 0),
+                (String::from("0.b"), 1),
+            ]),
+            constants: HashMap::new(),
+            output_name_to_wire_index: HashMap::from([(String::from("0.out"), 3)]),
+        };
+        let circuit = BristolReader::new(CIRCUIT.as_bytes())
+            .read_circuit(&info)
+            .unwrap();
+
+        assert_eq!(
+            circuit.info.output_name_to_wire_index,
+            info.output_name_to_wire_index
+        );
+        assert_eq!(circuit.wire_count, 5);
+        assert_eq!(circuit.io_widths, Some((vec![1, 1], vec![1])));
+        assert_eq!(circuit.gates.len(), 2);
+        assert_eq!(circuit.gates[1].inputs, vec![2, 1]);
+        assert_eq!(circuit.gates[1].op, "AMul");
+    }
+
+    #[test]
+    fn test_bristol_reader_progress() {
+        let progress = Rc::new(Cell::new(0));
+        let callback_progress = progress.clone();
+        let mut reader = BristolReader::new(CIRCUIT.as_bytes())
+            .with_progress(Box::new(move |bytes| callback_progress.set(bytes)));
+
+        assert_eq!(reader.by_ref().count(), 5);
+        assert_eq!(reader.bytes_read(), CIRCUIT.len() as u64);
+        assert_eq!(progress.get(), CIRCUIT.len() as u64);
+    }
+
+    #[test]
+    fn test_bristol_reader_errors() {
+        let mut reader = BristolReader::new("2 5\n2 1 1\n1 1\n2 1 0 x 2 AAdd\n".as_bytes());
+        let error = reader.nth(3).unwrap().unwrap_err();
+
+        assert_eq!(error.to_string(), "Parse error at line 4: invalid number");
+
+        let mut reader = BristolReader::new("2 5\n2 1 1\n1 1\n2 1 0 1 2\n".as_bytes());
+        let error = reader.nth(3).unwrap().unwrap_err();
+
+        assert_eq!(error.to_string(), "Parse error at line 4: missing gate op");
+    }
+}
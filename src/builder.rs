@@ -0,0 +1,307 @@
+//! # Builder Module
+//!
+//! This module is the entry point for embedding the compiler in other Rust tools: `CompilerBuilder`
+//! configures a compilation of a file or of an in-memory source, and returns the compiler, its
+//! report and the built circuit, without constructing the CLI arguments.
+
+use crate::{
+    cli::{Args, Prime, Target, ValueType},
+    compiler::{CircuitReport, Compiler},
+    events::EventSink,
+    passes::{Pass, PassRegistry},
+    program::{boolify_circuit, compile_with_passes, runtime_for, ProgramError},
+    sources::includes,
+};
+use bristol_circuit::BristolCircuit;
+use std::{
+    collections::HashSet,
+    fs, io,
+    path::{Component, Path, PathBuf},
+    process,
+    rc::Rc,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Resolves the source of an included file from its path, relative to the main source and
+/// normalized, e.g. `gates/scale.circom` for `include "scale.circom"` in `gates/double.circom`.
+/// Returns `None` to leave the include to the parser, e.g. for files of the link libraries.
+pub type IncludeResolver = Rc<dyn Fn(&str) -> Option<String>>;
+
+/// Number of in-memory sources written so far, to give each one its own directory.
+static SOURCE_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Name of the main source in its source tree.
+const MAIN_SOURCE: &str = "main.circom";
+
+/// Program to compile.
+#[derive(Clone, Debug)]
+enum Source {
+    Path(PathBuf),
+    Code(String),
+}
+
+/// An in-memory source and the includes resolved for it, written to a scratch directory because
+/// the parser only reads files. The directory is removed when the tree is dropped.
+pub(crate) struct SourceTree {
+    dir: PathBuf,
+}
+
+impl SourceTree {
+    /// Writes the main source along with the includes the resolver provides, recursively.
+    pub(crate) fn write(code: &str, resolver: Option<&IncludeResolver>) -> io::Result<Self> {
+        let dir = std::env::temp_dir().join(format!(
+            "circom_2_arithc_source_{}_{}",
+            process::id(),
+            SOURCE_COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&dir)?;
+        let tree = SourceTree { dir };
+
+        let mut pending = vec![(String::from(MAIN_SOURCE), code.to_string())];
+        let mut resolved = HashSet::from([String::from(MAIN_SOURCE)]);
+        while let Some((path, code)) = pending.pop() {
+            if let Some(resolver) = resolver {
+                let parent = Path::new(&path).parent().unwrap_or(Path::new(""));
+                for include in includes(&code) {
+                    let Some(include) = normalize(&parent.join(include)) else {
+                        continue;
+                    };
+                    if resolved.insert(include.clone()) {
+                        if let Some(code) = resolver(&include) {
+                            pending.push((include, code));
+                        }
+                    }
+                }
+            }
+
+            let file = tree.dir.join(&path);
+            if let Some(parent) = file.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(file, code)?;
+        }
+
+        Ok(tree)
+    }
+
+    pub(crate) fn main(&self) -> PathBuf {
+        self.dir.join(MAIN_SOURCE)
+    }
+}
+
+impl Drop for SourceTree {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Normalizes a relative path with `/` separators, or returns `None` if it is absolute or leaves
+/// the source tree.
+fn normalize(path: &Path) -> Option<String> {
+    let mut parts = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_str()?),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                parts.pop()?;
+            }
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+
+    Some(parts.join("/"))
+}
+
+/// Configuration of a compilation.
+///
+/// ```no_run
+/// use circom_2_arithc::{builder::CompilerBuilder, cli::ValueType};
+///
+/// let compilation = CompilerBuilder::from_path("circuits/main.circom")
+///     .value_type(ValueType::Uint)
+///     .link_library("node_modules/circomlib/circuits")
+///     .compile()
+///     .unwrap();
+/// println!("{} gates", compilation.circuit.gates.len());
+/// ```
+#[derive(Clone)]
+pub struct CompilerBuilder {
+    source: Source,
+    include_resolver: Option<IncludeResolver>,
+    passes: PassRegistry,
+    args: Args,
+}
+
+impl CompilerBuilder {
+    /// Compiles the program at the given path.
+    pub fn from_path(input: impl Into<PathBuf>) -> Self {
+        Self::new(Source::Path(input.into()))
+    }
+
+    /// Compiles the given program source. Its includes are resolved by the include resolver if
+    /// set, then in the link libraries.
+    pub fn from_source(code: impl Into<String>) -> Self {
+        Self::new(Source::Code(code.into()))
+    }
+
+    fn new(source: Source) -> Self {
+        Self {
+            source,
+            include_resolver: None,
+            passes: PassRegistry::default(),
+            args: Args::new(PathBuf::new(), PathBuf::new(), ValueType::default(), None),
+        }
+    }
+
+    /// Sets the type of the values in the MPC backend, `Sint` by default.
+    pub fn value_type(mut self, value_type: ValueType) -> Self {
+        self.args.value_type = value_type;
+        self
+    }
+
+    /// Sets the prime field compile-time values are computed in, `Bn128` by default.
+    pub fn prime(mut self, prime: Prime) -> Self {
+        self.args.prime = prime;
+        self
+    }
+
+    /// Sets the engine the circuit is compiled for, `Generic` by default.
+    pub fn target(mut self, target: Target) -> Self {
+        self.args.target = target;
+        self
+    }
+
+    /// Converts the circuit to a boolean circuit by using integers with this number of bits.
+    pub fn boolify_width(mut self, width: usize) -> Self {
+        self.args.boolify_width = Some(width);
+        self
+    }
+
+    /// Processes the components instantiated by the main component on up to this number of
+    /// worker threads at once.
+    pub fn instance_workers(mut self, workers: usize) -> Self {
+        self.args.instance_workers = Some(workers);
+        self
+    }
+
+    /// Adds a directory searched for included files.
+    pub fn link_library(mut self, path: impl Into<PathBuf>) -> Self {
+        self.args.link_libraries.push(path.into());
+        self
+    }
+
+    /// Sets the resolver of the files included by an in-memory source, so a program split over
+    /// several files can be compiled without reading them from disk. Ignored for programs read
+    /// from a path.
+    pub fn include_resolver(mut self, resolver: impl Fn(&str) -> Option<String> + 'static) -> Self {
+        self.include_resolver = Some(Rc::new(resolver));
+        self
+    }
+
+    /// Adds a pass run on the processed graph after the passes of its stage registered so far.
+    pub fn pass(mut self, pass: impl Pass + 'static) -> Self {
+        self.passes.register(pass);
+        self
+    }
+
+    /// Replaces the passes run on the processed graph, the built-in ones by default.
+    pub fn passes(mut self, passes: PassRegistry) -> Self {
+        self.passes = passes;
+        self
+    }
+
+    /// Compiles the program and builds its circuit.
+    pub fn compile(&self) -> Result<Compilation, ProgramError> {
+        let compile = |args: &Args| compile_with_passes(args, runtime_for(args)?, &self.passes);
+        let compiler = match &self.source {
+            Source::Path(path) => compile(&Args {
+                input: path.clone(),
+                ..self.args.clone()
+            })?,
+            Source::Code(code) => {
+                let tree = SourceTree::write(code, self.include_resolver.as_ref())?;
+                compile(&Args {
+                    input: tree.main(),
+                    ..self.args.clone()
+                })?
+            }
+        };
+
+        let report = compiler.generate_circuit_report()?;
+        let mut circuit = compiler.build_circuit()?;
+        if let Some(width) = self.args.boolify_width {
+            circuit = boolify_circuit(&circuit, width, &report, &mut EventSink::create(None)?)?;
+        }
+
+        Ok(Compilation {
+            compiler,
+            report,
+            circuit,
+        })
+    }
+}
+
+/// Result of a compilation.
+#[derive(Debug)]
+pub struct Compilation {
+    /// Compiler holding the arithmetic circuit, e.g. to evaluate outputs.
+    pub compiler: Compiler,
+    /// Report of the inputs and outputs, as written to `report.json`.
+    pub report: CircuitReport,
+    /// Built circuit, boolean if a boolify width is set.
+    pub circuit: BristolCircuit,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(
+            normalize(Path::new("gates/./scale.circom")).as_deref(),
+            Some("gates/scale.circom")
+        );
+        assert_eq!(
+            normalize(Path::new("gates/../scale.circom")).as_deref(),
+            Some("scale.circom")
+        );
+        assert_eq!(normalize(Path::new("../scale.circom")), None);
+        assert_eq!(normalize(Path::new("/lib/scale.circom")), None);
+    }
+
+    #[test]
+    fn test_source_tree() {
+        let sources = HashMap::from([
+            (
+                String::from("gates/double.circom"),
+                String::from(
+                    "pragma circom 2.1.0; include\"scale.circom\";\ninclude \"../gates/double.circom\";",
+                ),
+            ),
+            (String::from("gates/scale.circom"), String::from("// scale")),
+        ]);
+        let resolver: IncludeResolver = Rc::new(move |path: &str| {
+            // Commented-out includes aren't resolved
+            assert_ne!(path, "gates/old.circom");
+            sources.get(path).cloned()
+        });
+
+        let main = "include \"gates/double.circom\";\ninclude \"comparators.circom\";\n// include \"gates/old.circom\";";
+        let tree = SourceTree::write(main, Some(&resolver)).unwrap();
+        let dir = tree.dir.clone();
+
+        assert_eq!(fs::read_to_string(tree.main()).unwrap(), main);
+        assert_eq!(
+            fs::read_to_string(dir.join("gates/scale.circom")).unwrap(),
+            "// scale"
+        );
+        // Unresolved includes are left to the parser
+        assert!(!dir.join("comparators.circom").exists());
+
+        drop(tree);
+        assert!(!dir.exists());
+    }
+}
@@ -0,0 +1,460 @@
+//! # Card Module
+//!
+//! This module summarizes a compiled circuit in a Markdown circuit card, `CIRCUIT_CARD.md`, meant
+//! to be included in audits and MPC deployment documents.
+
+use crate::{
+    a_gate_type::{bristol_op_name, AGateType, CostClass},
+    cli::Args,
+    compiler::{CircuitReport, ConstantRange},
+    naming::fnv1a,
+};
+use bristol_circuit::BristolCircuit;
+use clap::ValueEnum;
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{self, Write},
+};
+
+/// An input or output signal of the circuit, with array elements grouped under their base name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CardSignal {
+    /// Name of the signal, without indices, e.g. `0.a` for `0.a[1][2]`.
+    pub name: String,
+    /// Length of each dimension, empty for scalars.
+    pub shape: Vec<usize>,
+    /// Wires of the elements, sorted.
+    pub wires: Vec<usize>,
+}
+
+/// Summary of a compiled circuit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CircuitCard {
+    /// Template of the main component.
+    pub template: String,
+    /// FNV-1a hash of the gates and IO wires, to tell whether two builds are the same circuit.
+    pub hash: u64,
+    pub inputs: Vec<CardSignal>,
+    pub outputs: Vec<CardSignal>,
+    /// Inputs removed because no gate reads them.
+    pub pruned_inputs: Vec<String>,
+    /// Number of instances by template and parameters, e.g. `Dense(nInputs=2, nOutputs=10)`.
+    pub instances: BTreeMap<String, usize>,
+    /// Number of gates by operation.
+    pub gates: BTreeMap<String, usize>,
+    pub gate_count: usize,
+    pub wire_count: usize,
+    /// Number of gates on the longest path from an input to an output.
+    pub depth: usize,
+    /// Number of non-linear gates on the path with the most of them.
+    pub multiplicative_depth: usize,
+    /// Range of the values known at compile time, `None` if there are none.
+    pub constant_range: Option<ConstantRange>,
+    /// Compiler options the circuit was built with, in display order.
+    pub config: Vec<(String, String)>,
+}
+
+impl CircuitCard {
+    /// Builds the card of a circuit compiled from the given main template with the given
+    /// arguments.
+    pub fn new(
+        template: &str,
+        circuit: &BristolCircuit,
+        report: &CircuitReport,
+        args: &Args,
+    ) -> Self {
+        let mut gates = BTreeMap::new();
+        for gate in &circuit.gates {
+            *gates
+                .entry(bristol_op_name(&gate.op).to_string())
+                .or_default() += 1;
+        }
+        let (depth, multiplicative_depth) = depths(circuit);
+        let mut instances = BTreeMap::new();
+        for instance in report.instances() {
+            *instances.entry(instance.to_string()).or_default() += 1;
+        }
+
+        let boolify_width = args
+            .boolify_width
+            .map_or_else(|| String::from("none"), |width| width.to_string());
+        let config = vec![
+            (
+                String::from("Compiler"),
+                format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+            ),
+            (String::from("Value type"), value_name(&report.value_type())),
+            (String::from("Prime"), value_name(&report.prime())),
+            (String::from("Target"), value_name(&report.target())),
+            (String::from("Gate order"), value_name(&report.gate_order())),
+            (String::from("Naming"), value_name(&args.naming)),
+            (String::from("Boolify width"), boolify_width),
+            (String::from("Format"), value_name(&args.format)),
+        ];
+
+        CircuitCard {
+            template: template.to_string(),
+            hash: circuit_hash(circuit),
+            inputs: group_signals(&circuit.info.input_name_to_wire_index),
+            outputs: group_signals(&circuit.info.output_name_to_wire_index),
+            pruned_inputs: report.pruned_inputs().to_vec(),
+            instances,
+            gates,
+            gate_count: circuit.gates.len(),
+            wire_count: circuit.wire_count,
+            depth,
+            multiplicative_depth,
+            constant_range: report.constant_range().cloned(),
+            config,
+        }
+    }
+
+    /// Writes the card as Markdown.
+    pub fn write_markdown<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "# Circuit Card: {}", self.template)?;
+        writeln!(writer)?;
+        writeln!(writer, "| Property | Value |")?;
+        writeln!(writer, "|---|---|")?;
+        writeln!(writer, "| Template | `{}` |", self.template)?;
+        writeln!(writer, "| Hash | `fnv1a:{:016x}` |", self.hash)?;
+        writeln!(writer, "| Gates | {} |", self.gate_count)?;
+        writeln!(writer, "| Wires | {} |", self.wire_count)?;
+        writeln!(writer, "| Depth | {} |", self.depth)?;
+        writeln!(
+            writer,
+            "| Multiplicative depth | {} |",
+            self.multiplicative_depth
+        )?;
+        match &self.constant_range {
+            Some(range) => writeln!(
+                writer,
+                "| Max constant | {} ({} bits) |",
+                range.max_magnitude(),
+                range.width()
+            )?,
+            None => writeln!(writer, "| Max constant | none |")?,
+        }
+
+        write_signals(writer, "Inputs", &self.inputs)?;
+        write_signals(writer, "Outputs", &self.outputs)?;
+
+        if !self.pruned_inputs.is_empty() {
+            writeln!(writer)?;
+            writeln!(writer, "## Pruned Inputs")?;
+            writeln!(writer)?;
+            for name in &self.pruned_inputs {
+                writeln!(writer, "- `{}`", name)?;
+            }
+        }
+
+        if !self.instances.is_empty() {
+            writeln!(writer)?;
+            writeln!(writer, "## Template Instances")?;
+            writeln!(writer)?;
+            writeln!(writer, "| Instance | Count |")?;
+            writeln!(writer, "|---|---|")?;
+            for (instance, count) in &self.instances {
+                writeln!(writer, "| `{}` | {} |", instance, count)?;
+            }
+        }
+
+        writeln!(writer)?;
+        writeln!(writer, "## Gates")?;
+        writeln!(writer)?;
+        writeln!(writer, "| Operation | Count |")?;
+        writeln!(writer, "|---|---|")?;
+        for (op, count) in &self.gates {
+            writeln!(writer, "| {} | {} |", op, count)?;
+        }
+
+        writeln!(writer)?;
+        writeln!(writer, "## Configuration")?;
+        writeln!(writer)?;
+        writeln!(writer, "| Option | Value |")?;
+        writeln!(writer, "|---|---|")?;
+        for (option, value) in &self.config {
+            writeln!(writer, "| {} | {} |", option, value)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes a table of signals under the given heading.
+fn write_signals<W: Write>(
+    writer: &mut W,
+    heading: &str,
+    signals: &[CardSignal],
+) -> io::Result<()> {
+    writeln!(writer)?;
+    writeln!(writer, "## {}", heading)?;
+    writeln!(writer)?;
+    writeln!(writer, "| Signal | Shape | Wires |")?;
+    writeln!(writer, "|---|---|---|")?;
+    for signal in signals {
+        let shape = if signal.shape.is_empty() {
+            String::from("scalar")
+        } else {
+            signal
+                .shape
+                .iter()
+                .map(|length| format!("[{}]", length))
+                .collect()
+        };
+        let wires = match (signal.wires.first(), signal.wires.last()) {
+            (Some(first), Some(last)) if first != last => format!("{}..={}", first, last),
+            (Some(first), _) => first.to_string(),
+            _ => String::new(),
+        };
+        writeln!(writer, "| `{}` | {} | {} |", signal.name, shape, wires)?;
+    }
+
+    Ok(())
+}
+
+/// Returns the CLI name of an option value, e.g. `sint`.
+fn value_name<T: ValueEnum>(value: &T) -> String {
+    value
+        .to_possible_value()
+        .map_or_else(String::new, |value| value.get_name().to_string())
+}
+
+/// Groups the elements of array signals under their base name, sorted by their first wire.
+fn group_signals(names: &HashMap<String, usize>) -> Vec<CardSignal> {
+    let mut signals = BTreeMap::<&str, CardSignal>::new();
+    for (name, &wire) in names {
+        let (base, indices) = split_indices(name);
+        let signal = signals.entry(base).or_insert_with(|| CardSignal {
+            name: base.to_string(),
+            shape: vec![0; indices.len()],
+            wires: Vec::new(),
+        });
+        for (length, index) in signal.shape.iter_mut().zip(&indices) {
+            *length = (*length).max(index + 1);
+        }
+        signal.wires.push(wire);
+    }
+
+    let mut signals = signals
+        .into_values()
+        .map(|mut signal| {
+            signal.wires.sort_unstable();
+            signal
+        })
+        .collect::<Vec<_>>();
+    signals.sort_by_key(|signal| signal.wires.first().copied());
+    signals
+}
+
+/// Splits the trailing indices off a signal name, e.g. `0.a[1][2]` into `0.a` and `[1, 2]`.
+fn split_indices(name: &str) -> (&str, Vec<usize>) {
+    let mut base = name;
+    let mut indices = Vec::new();
+    while let Some(open) = base.strip_suffix(']').and_then(|rest| rest.rfind('[')) {
+        match base[open + 1..base.len() - 1].parse() {
+            Ok(index) => indices.push(index),
+            Err(_) => break,
+        }
+        base = &base[..open];
+    }
+    indices.reverse();
+
+    (base, indices)
+}
+
+/// Returns the depth and multiplicative depth of the circuit. Gates are in topological order, so
+/// the depth of their inputs is known when they are reached.
+fn depths(circuit: &BristolCircuit) -> (usize, usize) {
+    let mut wire_depths = vec![(0, 0); circuit.wire_count];
+    let (mut depth, mut multiplicative_depth) = (0, 0);
+    for gate in &circuit.gates {
+        let (input_depth, input_multiplicative_depth) = gate
+            .inputs
+            .iter()
+            .filter_map(|&wire| wire_depths.get(wire))
+            .fold((0, 0), |(d, m), &(wd, wm)| (d.max(wd), m.max(wm)));
+        let gate_depth = input_depth + 1;
+        let gate_multiplicative_depth =
+            input_multiplicative_depth + is_non_linear(&gate.op) as usize;
+
+        for &wire in &gate.outputs {
+            if let Some(wire_depth) = wire_depths.get_mut(wire) {
+                *wire_depth = (gate_depth, gate_multiplicative_depth);
+            }
+        }
+        depth = depth.max(gate_depth);
+        multiplicative_depth = multiplicative_depth.max(gate_multiplicative_depth);
+    }
+
+    (depth, multiplicative_depth)
+}
+
+/// Returns whether a gate operation needs interaction in MPC, i.e. isn't an addition,
+/// subtraction or linear boolean gate.
+fn is_non_linear(op: &str) -> bool {
+    match AGateType::parse_bristol_op(op) {
+        Some((gate_type, _)) => gate_type.cost_class() != CostClass::Linear,
+        // Gates of boolean circuits
+        None => !matches!(op, "XOR" | "INV" | "NOT" | "EQW"),
+    }
+}
+
+/// Hashes the wire count, gates and IO wires of the circuit, names sorted so the hash is
+/// deterministic.
+fn circuit_hash(circuit: &BristolCircuit) -> u64 {
+    let mut text = format!("{}\n", circuit.wire_count);
+    for gate in &circuit.gates {
+        text.push_str(&format!(
+            "{} {:?} {:?}\n",
+            gate.op, gate.inputs, gate.outputs
+        ));
+    }
+    let info = &circuit.info;
+    for names in [
+        &info.input_name_to_wire_index,
+        &info.output_name_to_wire_index,
+    ] {
+        let names = names.iter().collect::<BTreeMap<_, _>>();
+        for (name, wire) in names {
+            text.push_str(&format!("{} {}\n", name, wire));
+        }
+    }
+    let constants = info.constants.iter().collect::<BTreeMap<_, _>>();
+    for (name, constant) in constants {
+        text.push_str(&format!(
+            "{} {} {}\n",
+            name, constant.value, constant.wire_index
+        ));
+    }
+
+    fnv1a(text.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cli::ValueType,
+        compiler::{Compiler, InstanceReport, TemplateParam},
+    };
+    use std::path::PathBuf;
+
+    fn compiler() -> Compiler {
+        // out[i] = (a[i] + b) * b
+        let mut compiler = Compiler::new();
+        compiler
+            .add_signal(1, String::from("0.a[0]"), None)
+            .unwrap();
+        compiler
+            .add_signal(2, String::from("0.a[1]"), None)
+            .unwrap();
+        compiler.add_signal(3, String::from("0.b"), None).unwrap();
+        compiler
+            .add_signal(4, String::from("0.t[0]"), None)
+            .unwrap();
+        compiler
+            .add_signal(5, String::from("0.t[1]"), None)
+            .unwrap();
+        compiler
+            .add_signal(6, String::from("0.out[0]"), None)
+            .unwrap();
+        compiler
+            .add_signal(7, String::from("0.out[1]"), None)
+            .unwrap();
+        compiler.add_gate(AGateType::AAdd, 1, 3, 4).unwrap();
+        compiler.add_gate(AGateType::AAdd, 2, 3, 5).unwrap();
+        compiler.add_gate(AGateType::AMul, 4, 3, 6).unwrap();
+        compiler.add_gate(AGateType::AMul, 5, 3, 7).unwrap();
+        compiler.add_inputs(HashMap::from([
+            (1, String::from("0.a[0]")),
+            (2, String::from("0.a[1]")),
+            (3, String::from("0.b")),
+        ]));
+        compiler.add_outputs(HashMap::from([
+            (6, String::from("0.out[0]")),
+            (7, String::from("0.out[1]")),
+        ]));
+        compiler
+    }
+
+    #[test]
+    fn test_split_indices() {
+        assert_eq!(split_indices("0.a[1][2]"), ("0.a", vec![1, 2]));
+        assert_eq!(split_indices("0.c[3].x"), ("0.c[3].x", vec![]));
+        assert_eq!(split_indices("0.b"), ("0.b", vec![]));
+        assert_eq!(split_indices("0.b[i]"), ("0.b[i]", vec![]));
+    }
+
+    #[test]
+    fn test_circuit_card() {
+        let mut compiler = compiler();
+        for index in 0..2 {
+            compiler.add_instance(InstanceReport {
+                instance: format!("Scale_{}", index),
+                template: String::from("Scale"),
+                component: Some(format!("scale[{}]", index)),
+                params: vec![TemplateParam {
+                    name: String::from("factor"),
+                    value: String::from("-3"),
+                }],
+                parallel: false,
+            });
+        }
+        let circuit = compiler.build_circuit().unwrap();
+        let report = compiler.generate_circuit_report().unwrap();
+        let args = Args::new(
+            PathBuf::from("main.circom"),
+            PathBuf::from("output"),
+            ValueType::Sint,
+            None,
+        );
+        let card = CircuitCard::new("Main", &circuit, &report, &args);
+
+        assert_eq!(card.template, "Main");
+        assert_eq!(card.inputs.len(), 2);
+        assert_eq!(card.inputs[0].name, "0.a");
+        assert_eq!(card.inputs[0].shape, [2]);
+        assert_eq!(card.inputs[1].name, "0.b");
+        assert!(card.inputs[1].shape.is_empty());
+        assert_eq!(card.outputs[0].name, "0.out");
+        assert_eq!(card.outputs[0].shape, [2]);
+        assert_eq!(
+            card.gates,
+            BTreeMap::from([(String::from("AAdd"), 2), (String::from("AMul"), 2)])
+        );
+        assert_eq!(
+            card.instances,
+            BTreeMap::from([(String::from("Scale(factor=-3)"), 2)])
+        );
+        assert_eq!(card.depth, 2);
+        assert_eq!(card.multiplicative_depth, 1);
+        assert_eq!(
+            card.hash,
+            CircuitCard::new("Main", &circuit, &report, &args).hash
+        );
+
+        let mut buffer = Vec::new();
+        card.write_markdown(&mut buffer).unwrap();
+        let markdown = String::from_utf8(buffer).unwrap();
+        assert!(markdown.starts_with("# Circuit Card: Main\n"));
+        assert!(markdown.contains("| `0.a` | [2] | 0..=1 |"));
+        assert!(markdown.contains("| `0.b` | scalar | 2 |"));
+        assert!(markdown.contains("| AMul | 2 |"));
+        assert!(markdown.contains("| `Scale(factor=-3)` | 2 |"));
+        assert!(markdown.contains("| Max constant | none |"));
+        assert!(markdown.contains("| Value type | sint |"));
+        assert!(markdown.contains("| Prime | bn128 |"));
+        assert!(markdown.contains("| Target | generic |"));
+        assert!(markdown.contains("| Boolify width | none |"));
+    }
+
+    #[test]
+    fn test_circuit_hash() {
+        let circuit = compiler().build_circuit().unwrap();
+        let mut changed = circuit.clone();
+        changed.gates[0].op = String::from("ASub");
+
+        assert_eq!(circuit_hash(&circuit), circuit_hash(&circuit.clone()));
+        assert_ne!(circuit_hash(&circuit), circuit_hash(&changed));
+    }
+}
@@ -0,0 +1,352 @@
+//! # Check Module
+//!
+//! This module validates Bristol circuits against their IO wires, `circuit_info.json`, before they
+//! are run in MPC: the gates must be in topological order and well-formed, every wire in range and
+//! computed once, and the IO widths must match. Hand-edited and third-party circuits are read line
+//! by line, so large circuits are checked with memory proportional to their wire count.
+
+use crate::{
+    a_gate_type::{bristol_op_name, AGateType},
+    bristol_reader::{BristolLine, BristolReadError, BristolReader},
+    program::ProgramError,
+};
+use bristol_circuit::CircuitInfo;
+use serde::Serialize;
+use std::{collections::BTreeMap, fs, io::BufRead, path::Path};
+use thiserror::Error;
+
+/// Number of problems recorded by `check_bristol`, further problems are only counted.
+pub const MAX_PROBLEMS: usize = 100;
+
+/// Operations of boolean circuits, e.g. written with `--boolify-width`, with their number of
+/// inputs.
+const BOOLEAN_OPS: [(&str, usize); 5] =
+    [("AND", 2), ("XOR", 2), ("INV", 1), ("NOT", 1), ("EQW", 1)];
+
+/// A problem found in a circuit.
+#[derive(Error, Clone, Debug, PartialEq, Eq, Serialize)]
+pub enum CheckProblem {
+    #[error("Missing circuit header")]
+    MissingHeader,
+    #[error("Header declares {declared} gates, the circuit has {actual}")]
+    GateCountMismatch { declared: usize, actual: usize },
+    #[error("Circuit has {circuit} {kind} wires, circuit_info.json has {info}")]
+    IoWidthMismatch {
+        kind: String,
+        circuit: usize,
+        info: usize,
+    },
+    #[error("{name} is mapped to wire {wire}, out of range")]
+    IoWireOutOfRange { name: String, wire: usize },
+    #[error("{name} is mapped to wire {wire}, already assigned to another input or constant")]
+    IoWireConflict { name: String, wire: usize },
+    #[error("Gate {gate}: unknown operation {op}")]
+    UnknownOp { gate: usize, op: String },
+    #[error(
+        "Gate {gate}: {op} takes {expected} inputs and 1 output, found {inputs} and {outputs}"
+    )]
+    ArityMismatch {
+        gate: usize,
+        op: String,
+        expected: usize,
+        inputs: usize,
+        outputs: usize,
+    },
+    #[error("Gate {gate}: wire {wire} is out of range")]
+    WireOutOfRange { gate: usize, wire: usize },
+    #[error(
+        "Gate {gate}: wire {wire} is read before it is computed, gates aren't in topological order"
+    )]
+    UnorderedGate { gate: usize, wire: usize },
+    #[error("Gate {gate}: wire {wire} is already assigned")]
+    ReassignedWire { gate: usize, wire: usize },
+    #[error("Output {name} on wire {wire} is never computed")]
+    UncomputedOutput { name: String, wire: usize },
+}
+
+/// Summary of a checked circuit, with the problems found.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct CircuitCheck {
+    pub gate_count: usize,
+    pub wire_count: usize,
+    pub input_count: usize,
+    pub output_count: usize,
+    pub constant_count: usize,
+    /// Number of gates by operation.
+    pub gates: BTreeMap<String, usize>,
+    /// First `MAX_PROBLEMS` problems, in the order they were found.
+    pub problems: Vec<CheckProblem>,
+    /// Number of problems found, including those not recorded.
+    pub problem_count: usize,
+}
+
+impl CircuitCheck {
+    /// Whether no problem was found.
+    pub fn is_valid(&self) -> bool {
+        self.problem_count == 0
+    }
+
+    fn report(&mut self, problem: CheckProblem) {
+        if self.problems.len() < MAX_PROBLEMS {
+            self.problems.push(problem);
+        }
+        self.problem_count += 1;
+    }
+}
+
+/// Checks a Bristol circuit read line by line against its IO wires. Malformed lines are errors,
+/// inconsistent circuits are reported as problems of the returned check.
+pub fn check_bristol<R: BufRead>(
+    reader: BristolReader<R>,
+    info: &CircuitInfo,
+) -> Result<CircuitCheck, BristolReadError> {
+    let mut check = CircuitCheck {
+        input_count: info.input_name_to_wire_index.len(),
+        output_count: info.output_name_to_wire_index.len(),
+        constant_count: info.constants.len(),
+        ..Default::default()
+    };
+    let mut declared_gates = None;
+    // Whether each wire is assigned, by an input, a constant or a gate
+    let mut assigned = Vec::new();
+
+    for line in reader {
+        match line? {
+            BristolLine::Header {
+                gate_count,
+                wire_count,
+            } => {
+                declared_gates = Some(gate_count);
+                check.wire_count = wire_count;
+                assigned = vec![false; wire_count];
+
+                let mut io_wires = info
+                    .input_name_to_wire_index
+                    .iter()
+                    .map(|(name, &wire)| (name, wire))
+                    .chain(
+                        info.constants
+                            .iter()
+                            .map(|(name, constant)| (name, constant.wire_index)),
+                    )
+                    .collect::<Vec<_>>();
+                io_wires.sort();
+                for (name, wire) in io_wires {
+                    match assigned.get_mut(wire) {
+                        None => check.report(CheckProblem::IoWireOutOfRange {
+                            name: name.clone(),
+                            wire,
+                        }),
+                        Some(true) => check.report(CheckProblem::IoWireConflict {
+                            name: name.clone(),
+                            wire,
+                        }),
+                        Some(slot) => *slot = true,
+                    }
+                }
+            }
+            BristolLine::Inputs(widths) => check_widths(
+                &mut check,
+                "input",
+                &widths,
+                info.input_name_to_wire_index.len(),
+            ),
+            BristolLine::Outputs(widths) => check_widths(
+                &mut check,
+                "output",
+                &widths,
+                info.output_name_to_wire_index.len(),
+            ),
+            BristolLine::Gate {
+                inputs,
+                outputs,
+                op,
+            } => {
+                let gate = check.gate_count;
+                check.gate_count += 1;
+                *check
+                    .gates
+                    .entry(bristol_op_name(&op).to_string())
+                    .or_default() += 1;
+
+                match op_arity(&op) {
+                    None => check.report(CheckProblem::UnknownOp {
+                        gate,
+                        op: op.clone(),
+                    }),
+                    Some(expected) if inputs.len() != expected || outputs.len() != 1 => check
+                        .report(CheckProblem::ArityMismatch {
+                            gate,
+                            op: op.clone(),
+                            expected,
+                            inputs: inputs.len(),
+                            outputs: outputs.len(),
+                        }),
+                    Some(_) => {}
+                }
+                for &wire in &inputs {
+                    match assigned.get(wire) {
+                        None => check.report(CheckProblem::WireOutOfRange { gate, wire }),
+                        Some(false) => check.report(CheckProblem::UnorderedGate { gate, wire }),
+                        Some(true) => {}
+                    }
+                }
+                for &wire in &outputs {
+                    match assigned.get_mut(wire) {
+                        None => check.report(CheckProblem::WireOutOfRange { gate, wire }),
+                        Some(true) => check.report(CheckProblem::ReassignedWire { gate, wire }),
+                        Some(slot) => *slot = true,
+                    }
+                }
+            }
+        }
+    }
+
+    let Some(declared) = declared_gates else {
+        check.report(CheckProblem::MissingHeader);
+        return Ok(check);
+    };
+    if declared != check.gate_count {
+        check.report(CheckProblem::GateCountMismatch {
+            declared,
+            actual: check.gate_count,
+        });
+    }
+    let mut outputs = info.output_name_to_wire_index.iter().collect::<Vec<_>>();
+    outputs.sort();
+    for (name, &wire) in outputs {
+        if !assigned.get(wire).copied().unwrap_or(false) {
+            check.report(CheckProblem::UncomputedOutput {
+                name: name.clone(),
+                wire,
+            });
+        }
+    }
+
+    Ok(check)
+}
+
+/// Checks the Bristol circuit at `circuit` against its IO wires in `info` (`circuit_info.json`).
+pub fn check_files(circuit: &Path, info: &Path) -> Result<CircuitCheck, ProgramError> {
+    let info: CircuitInfo = serde_json::from_str(&fs::read_to_string(info)?)?;
+    Ok(check_bristol(BristolReader::open(circuit)?, &info)?)
+}
+
+/// Reports IO widths whose total doesn't match the number of IO wires of `circuit_info.json`.
+fn check_widths(check: &mut CircuitCheck, kind: &str, widths: &[usize], info: usize) {
+    let circuit = widths.iter().sum();
+    if circuit != info {
+        check.report(CheckProblem::IoWidthMismatch {
+            kind: kind.to_string(),
+            circuit,
+            info,
+        });
+    }
+}
+
+/// Returns the number of inputs of a gate operation, `None` if it is unknown.
+fn op_arity(op: &str) -> Option<usize> {
+    match AGateType::parse_bristol_op(op) {
+        // Constants are inlined as the second operand
+        Some((_, Some(_))) => Some(1),
+        Some((_, None)) => Some(2),
+        None => BOOLEAN_OPS
+            .iter()
+            .find(|(name, _)| *name == op)
+            .map(|(_, arity)| *arity),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bristol_circuit::ConstantInfo;
+    use std::collections::HashMap;
+
+    /// `out = (a + b) * 3`
+    fn info() -> CircuitInfo {
+        CircuitInfo {
+            input_name_to_wire_index: HashMap::from([
+                (String::from("0.a"), 0),
+                (String::from("0.b"), 1),
+            ]),
+            constants: HashMap::from([(
+                String::from("const_signal_3"),
+                ConstantInfo {
+                    value: String::from("3"),
+                    wire_index: 2,
+                },
+            )]),
+            output_name_to_wire_index: HashMap::from([(String::from("0.out"), 4)]),
+        }
+    }
+
+    fn check_str(bristol: &str) -> CircuitCheck {
+        check_bristol(BristolReader::new(bristol.as_bytes()), &info()).unwrap()
+    }
+
+    #[test]
+    fn test_check_bristol() {
+        let check = check_str("2 5\n2 1 1\n1 1\n\n2 1 0 1 3 AAdd\n2 1 3 2 4 AMul\n");
+        assert!(check.is_valid(), "{:?}", check.problems);
+        assert_eq!(check.gate_count, 2);
+        assert_eq!(check.wire_count, 5);
+        assert_eq!((check.input_count, check.output_count), (2, 1));
+        assert_eq!(check.constant_count, 1);
+        assert_eq!(
+            check.gates,
+            BTreeMap::from([(String::from("AAdd"), 1), (String::from("AMul"), 1)])
+        );
+
+        // Constants inlined in the operation, and boolean gates
+        assert!(check_str("2 5\n2 1 1\n1 1\n\n2 1 0 1 3 AAdd\n1 1 3 4 AMul:3\n").is_valid());
+        assert!(check_str("2 5\n2 1 1\n1 1\n\n2 1 0 1 3 XOR\n1 1 3 4 INV\n").is_valid());
+    }
+
+    #[test]
+    fn test_check_problems() {
+        // Gates swapped, so the multiplication reads the sum before it is computed
+        let check = check_str("3 5\n2 1 1\n2 1 1\n\n2 1 3 2 4 AMul\n2 1 0 1 3 AAdd\n");
+        assert_eq!(
+            check.problems,
+            vec![
+                CheckProblem::IoWidthMismatch {
+                    kind: String::from("output"),
+                    circuit: 2,
+                    info: 1
+                },
+                CheckProblem::UnorderedGate { gate: 0, wire: 3 },
+                CheckProblem::GateCountMismatch {
+                    declared: 3,
+                    actual: 2
+                },
+            ]
+        );
+        assert_eq!(
+            check.problems[1].to_string(),
+            "Gate 0: wire 3 is read before it is computed, gates aren't in topological order"
+        );
+
+        let check = check_bristol(
+            BristolReader::new("2 5\n2 1 1\n1 1\n\n2 1 0 9 3 AFoo\n2 1 0 1 0 AAdd\n".as_bytes()),
+            &info(),
+        )
+        .unwrap();
+        assert_eq!(
+            check.problems,
+            vec![
+                CheckProblem::UnknownOp {
+                    gate: 0,
+                    op: String::from("AFoo")
+                },
+                CheckProblem::WireOutOfRange { gate: 0, wire: 9 },
+                CheckProblem::ReassignedWire { gate: 1, wire: 0 },
+                CheckProblem::UncomputedOutput {
+                    name: String::from("0.out"),
+                    wire: 4
+                },
+            ]
+        );
+        assert_eq!(check.problem_count, 4);
+    }
+}
@@ -1,10 +1,13 @@
-use crate::{circom::VERSION, cli::Args, program::ProgramError};
+use crate::{circom::VERSION, cli::Args, logging::PARSER, program::ProgramError};
 use circom_parser::run_parser;
 use circom_program_structure::{error_definition::Report, program_archive::ProgramArchive};
+use log::debug;
+use std::path::{Path, PathBuf};
 
 pub fn parse_project(args: &Args) -> Result<ProgramArchive, ProgramError> {
     let initial_file = args.input.to_str().unwrap().to_string();
-    match run_parser(initial_file, VERSION, vec![]) {
+    debug!(target: PARSER, "Parsing {}", initial_file);
+    match run_parser(initial_file, VERSION, args.link_libraries.clone()) {
         Result::Err((file_library, report_collection)) => {
             Report::print_reports(&report_collection, &file_library);
             Result::Err(ProgramError::ParsingError)
@@ -15,3 +18,12 @@ pub fn parse_project(args: &Args) -> Result<ProgramArchive, ProgramError> {
         }
     }
 }
+
+/// Parses a program without printing its reports, returning `None` if it doesn't parse.
+pub fn try_parse(initial_file: &Path, link_libraries: Vec<PathBuf>) -> Option<ProgramArchive> {
+    let initial_file = initial_file.to_str()?.to_string();
+    debug!(target: PARSER, "Parsing {}", initial_file);
+    run_parser(initial_file, VERSION, link_libraries)
+        .ok()
+        .map(|(program_archive, _)| program_archive)
+}
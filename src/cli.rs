@@ -1,6 +1,10 @@
-use std::path::{Path, PathBuf};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
 
-use clap::{Parser, ValueEnum};
+use crate::{a_gate_type::AGateType, evaluate::Watchpoint};
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Serialize, Deserialize, Default)]
@@ -11,12 +15,272 @@ pub enum ValueType {
     Sint,
     #[serde(rename = "sfloat")]
     Sfloat,
+    #[serde(rename = "uint")]
+    Uint,
 }
 
-#[derive(Parser)]
+impl ValueType {
+    /// Whether values are signed, i.e. 32-bit words are interpreted in two's complement.
+    /// This affects comparisons, divisions and right shifts.
+    pub fn is_signed(&self) -> bool {
+        match self {
+            ValueType::Sint | ValueType::Sfloat => true,
+            ValueType::Uint => false,
+        }
+    }
+}
+
+/// Prime field the compile-time values are computed in, named as in circom's `--prime`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Prime {
+    /// BN254 scalar field, the default of circom
+    #[default]
+    Bn128,
+    /// BLS12-381 scalar field
+    Bls12381,
+    /// 64-bit Goldilocks field, `2^64 - 2^32 + 1`
+    Goldilocks,
+    /// Grumpkin scalar field, the BN254 base field
+    Grumpkin,
+    /// Pallas scalar field
+    Pallas,
+    /// Vesta scalar field
+    Vesta,
+    /// secq256r1 scalar field, the P-256 base field
+    Secq256r1,
+}
+
+/// Naming used for the contexts of template instances.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum InstanceNaming {
+    /// Contexts are named after their template, e.g. `Dense.out`
+    #[default]
+    Template,
+    /// Contexts are named after the component they are assigned to, e.g. `dense[3].out`
+    Index,
+}
+
+/// Handling of constraint equalities, `a === b`. Circom uses them to constrain the witness, the
+/// values of an arithmetic circuit are computed by the assignments instead.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ConstraintEquality {
+    /// Constraint equalities are skipped
+    #[default]
+    Ignore,
+    /// Sides known at compile time must be equal, the checks of signals are combined into a single
+    /// `0.constraints_ok` output, 1 if every check holds
+    Assert,
+    /// Sides known at compile time must be equal, each check of signals is an output
+    /// `0.constraints[i]`, 1 if its sides are equal
+    Output,
+}
+
+/// Naming style used for signals, constants and IO in the generated artifacts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum NamingStyle {
+    /// Hierarchical names, e.g. `0.out[1]`
+    #[default]
+    Hierarchical,
+    /// Names derived from the signal id, e.g. `s12`
+    FlatNumeric,
+    /// Stable names derived from a hash of the hierarchical name
+    Hash,
+}
+
+/// Format of the written circuit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    /// Bristol circuit `circuit.txt` and its IO wires `circuit_info.json`
+    #[default]
+    Bristol,
+    /// Rank-1 constraint system `circuit.r1cs` and its IO wires `circuit.sym`
+    R1cs,
+    /// JSON circuit `circuit.json` with its IO names and constants
+    Json,
+}
+
+/// Order of the gates in the Bristol circuit. Every order is topological, they differ in how close
+/// the producers of a wire are to its consumers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum GateOrder {
+    /// Dependencies first, following the order in which gates were created
+    #[default]
+    Insertion,
+    /// Depth-first from each output, so the cone of each output is contiguous
+    DfsCone,
+    /// Level by level, from the gates reading only inputs and constants to the outputs
+    BfsLevel,
+}
+
+/// Divisions by a constant reduced to cheaper gates with `--reduce-divisions`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum DivisionReduction {
+    /// Divisions by a power of two become shifts, truncating toward zero like the division
+    #[default]
+    Integer,
+    /// Also, `/` by a constant becomes a multiplication by its inverse modulo the prime with field
+    /// constants, as in circom's field division. With words, only when the dividend is a constant
+    /// multiple of an odd divisor, by its inverse modulo 2^32
+    Field,
+}
+
+/// Engine the circuit is compiled for. Targets other than `generic` are presets: they enable the
+/// passes the engine benefits from, and reject the gates it can't evaluate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Target {
+    /// Any engine, no preset
+    #[default]
+    Generic,
+    /// Arithmetic MPC engines evaluating additions, subtractions and multiplications, e.g. mpz
+    Mpz,
+}
+
+impl Target {
+    /// Returns the gate types the engine evaluates, `None` if it evaluates all of them.
+    pub fn supported_gates(&self) -> Option<&'static [AGateType]> {
+        match self {
+            Target::Generic => None,
+            Target::Mpz => Some(&[AGateType::AAdd, AGateType::ASub, AGateType::AMul]),
+        }
+    }
+
+    /// Returns whether the engine evaluates the given gate type.
+    pub fn supports(&self, op: AGateType) -> bool {
+        self.supported_gates()
+            .is_none_or(|supported| supported.contains(&op))
+    }
+
+    /// Whether chains of additions are rebalanced into adder trees, as with `--balance-additions`.
+    pub fn balances_additions(&self) -> bool {
+        match self {
+            Target::Generic => false,
+            Target::Mpz => true,
+        }
+    }
+
+    /// Whether the gates the engine doesn't evaluate are lowered into gates it does, when they can
+    /// be, see `Compiler::lower_for_target`.
+    pub fn lowers_gates(&self) -> bool {
+        match self {
+            Target::Generic => false,
+            Target::Mpz => true,
+        }
+    }
+}
+
+/// Normalization of the names of input values, e.g. in the weights or inputs file, applied when
+/// they don't match an input name exactly.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NameNormalization {
+    /// Names may omit the `0.` prefix of the main component, e.g. `in` for `0.in`
+    Prefix,
+    /// Names are matched case-insensitively
+    Case,
+    /// Array indices may use underscores, e.g. `in_0_1` for `in[0][1]`
+    Brackets,
+}
+
+/// Subcommands, the input file is compiled if none is given.
+#[derive(Subcommand, Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    /// Scaffold a project: an example circuit, its inputs template and a test harness
+    Init {
+        /// Directory of the project, created if it doesn't exist
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+    },
+    /// Evaluate a compiled Bristol circuit on the values of its inputs, writing its outputs as JSON
+    Evaluate {
+        /// Bristol circuit, e.g. `output/circuit.txt`
+        #[arg(long)]
+        circuit: PathBuf,
+        /// Optional: IO wires of the circuit, `circuit_info.json` next to the circuit by default
+        #[arg(long)]
+        info: Option<PathBuf>,
+        /// JSON file of input values keyed by name, arrays are accepted like for `--weights`
+        #[arg(long)]
+        inputs: PathBuf,
+        /// Optional: JSON file the output values are written to, instead of the standard output
+        #[arg(long)]
+        outputs: Option<PathBuf>,
+        /// Optional: Signal whose value is printed every time a gate computes it, e.g.
+        /// `0.relu.out`, stopping the evaluation if a condition is given, e.g. `0.relu.out>=1000`.
+        /// Can be repeated
+        #[arg(long)]
+        watch: Vec<Watchpoint>,
+        /// Optional: Signal map resolving watched intermediate signals, `signals.sym` next to the
+        /// circuit by default
+        #[arg(long)]
+        signals: Option<PathBuf>,
+        /// Optional: JSON file the watched values are written to
+        #[arg(long)]
+        watch_log: Option<PathBuf>,
+    },
+    /// Compare the cone of an output in two compiled Bristol circuits, writing the added, removed
+    /// and changed gates as a DOT graph
+    Diff {
+        /// Bristol circuit of the previous version, with `circuit_info.json` next to it
+        #[arg(long)]
+        old: PathBuf,
+        /// Bristol circuit of the new version, with `circuit_info.json` next to it
+        #[arg(long)]
+        new: PathBuf,
+        /// Output whose cone is compared, e.g. `0.out[2]`
+        #[arg(long)]
+        output: String,
+        /// Optional: DOT file the graph is written to, instead of the standard output
+        #[arg(long)]
+        dot: Option<PathBuf>,
+    },
+    /// Validate a Bristol circuit against its IO wires before running it: topological order, wire
+    /// count, IO mapping and gate operations
+    Check {
+        /// Bristol circuit, e.g. `output/circuit.txt`
+        #[arg(long)]
+        circuit: PathBuf,
+        /// Optional: IO wires of the circuit, `circuit_info.json` next to the circuit by default
+        #[arg(long)]
+        info: Option<PathBuf>,
+    },
+    /// Encode real-valued inputs into the fixed-point integers of a circuit, following the scale
+    /// factors of an IO schema, or list the range of each input and output of the schema
+    Encode {
+        /// JSON IO schema, `{ "inputs": { "0.x": { "scale": 65536, "min": -1, "max": 1 } } }`
+        #[arg(long)]
+        schema: PathBuf,
+        /// Optional: JSON file of real input values keyed by name, arrays are accepted like for
+        /// `--weights`
+        #[arg(long)]
+        inputs: Option<PathBuf>,
+        /// Optional: JSON file the encoded inputs are written to, instead of the standard output
+        #[arg(long)]
+        encoded: Option<PathBuf>,
+    },
+    /// Compile the template instances of a manifest into IR archives in the output directory,
+    /// keeping the archives already up to date
+    Prebuild {
+        /// JSON manifest of the instances, `{ "templates": [{ "name", "include", "template", "args" }] }`
+        #[arg(long)]
+        manifest: PathBuf,
+    },
+}
+
+#[derive(Parser, Clone)]
 #[clap(name = "Arithmetic Circuits Compiler")]
 #[command(disable_help_subcommand = true)]
 pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// Input file to process
     #[arg(
         short,
@@ -36,20 +300,322 @@ pub struct Args {
     pub output: PathBuf,
 
     #[arg(
-        short,
+        short = 'l',
+        long = "link-library",
+        help = "Optional: Directory searched for included files, can be repeated"
+    )]
+    pub link_libraries: Vec<PathBuf>,
+
+    #[arg(
         long,
+        global = true,
         value_enum,
         help = "Type that'll be used for values in MPC backend",
         default_value_t = ValueType::Sint,
     )]
     pub value_type: ValueType,
 
+    #[arg(
+        long,
+        help = "Optional: Emit signed comparisons (ASLt, ASLEq, ASGt, ASGEq) and arithmetic right shifts (AShiftRArith) for signed value types, boolify doesn't support them"
+    )]
+    pub signed_gates: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        value_delimiter = ',',
+        help = "Optional: Normalizations (prefix, case, brackets) applied to the names of input values that don't match an input exactly"
+    )]
+    pub normalize_names: Vec<NameNormalization>,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "Prime field compile-time values are computed in",
+        default_value_t = Prime::Bn128,
+    )]
+    pub prime: Prime,
+
     #[arg(
         long,
         help = "Optional: Convert to a boolean circuit by using integers with this number of bits",
         default_value = None,
     )]
     pub boolify_width: Option<usize>,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "Naming style for signals, constants and IO in the output",
+        default_value_t = NamingStyle::Hierarchical,
+    )]
+    pub naming: NamingStyle,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "Order of the gates in the circuit, streaming evaluators benefit from producers being close to their consumers",
+        default_value_t = GateOrder::Insertion,
+    )]
+    pub gate_order: GateOrder,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "Format of the written circuit, r1cs only supports additions, subtractions and multiplications",
+        default_value_t = OutputFormat::Bristol,
+    )]
+    pub format: OutputFormat,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "Engine the circuit is compiled for, a preset enabling the passes it benefits from and rejecting the gates it can't evaluate",
+        default_value_t = Target::Generic,
+    )]
+    pub target: Target,
+
+    #[arg(
+        long,
+        help = "Optional: Write compilation metrics to this file in Prometheus textfile format",
+        default_value = None,
+    )]
+    pub metrics_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Optional: Write compilation events (phases, counts and warnings) to this file as JSON lines",
+        default_value = None,
+    )]
+    pub events_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Optional: Append every gate and connection, with the statement it comes from, to this file as JSON lines",
+        default_value = None,
+    )]
+    pub audit_log: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Validate the internal consistency of the circuit after each compilation pass"
+    )]
+    pub self_check: bool,
+
+    #[arg(long, help = "Fail instead of pruning inputs that no gate reads")]
+    pub strict: bool,
+
+    #[arg(
+        long,
+        help = "Rebalance chains of additions (e.g. `sum += x[i]`) into adder trees of logarithmic depth"
+    )]
+    pub balance_additions: bool,
+
+    #[arg(
+        long,
+        help = "Evaluate the gates only reading constants (e.g. products of hard-coded scale factors) and emit their results as constants"
+    )]
+    pub fold_constants: bool,
+
+    #[arg(
+        long,
+        help = "Fuse multiplications by a constant (e.g. by a weight) into ACMul gates carrying the constant inline, lowered back to AMul for targets without ACMul"
+    )]
+    pub fuse_constant_mul: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        num_args = 0..=1,
+        default_missing_value = "integer",
+        help = "Reduce divisions by a constant to shifts (integer) or also to multiplications by the inverse of the divisor modulo the prime (field), for backends without division"
+    )]
+    pub reduce_divisions: Option<DivisionReduction>,
+
+    #[arg(
+        long,
+        help = "Keep the gates whose output doesn't reach any output of the circuit"
+    )]
+    pub no_prune: bool,
+
+    #[arg(
+        long,
+        help = "Skip statements that fail and record their errors in diagnostics.json, to report several errors at once"
+    )]
+    pub recover: bool,
+
+    #[arg(
+        long,
+        help = "Optional: JSON file mapping input names to party indices, used to group the inputs by party",
+        default_value = None,
+    )]
+    pub party_mapping: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Optional: JSON file binding input names (or input array names) to constant values, emitted as constants instead of inputs",
+        default_value = None,
+    )]
+    pub weights: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Optional: JSON file of pre-processing stages attached to the inputs and post-processing stages attached to the outputs",
+        default_value = None,
+    )]
+    pub stages: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Optional: JSON file of outputs released with differential privacy noise, each getting noise inputs added to it",
+        default_value = None,
+    )]
+    pub noise: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Optional: JSON file of wires needed in both arithmetic and boolean forms, planned as A2B and B2A conversions in conversions.json",
+        default_value = None,
+    )]
+    pub conversions: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "Naming of template instance contexts in signal names",
+        default_value_t = InstanceNaming::Template,
+    )]
+    pub instance_naming: InstanceNaming,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "Handling of constraint equalities (`a === b`): skip them, combine their checks into a `0.constraints_ok` output, or emit each check as an output",
+        default_value_t = ConstraintEquality::Ignore,
+    )]
+    pub constraint_equality: ConstraintEquality,
+
+    #[arg(
+        long,
+        help = "Optional: Maximum number of statements and expressions evaluated at compile time",
+        default_value = None,
+    )]
+    pub max_steps: Option<u64>,
+
+    #[arg(
+        long,
+        help = "Optional: JSON file mapping template names to the maximum number of gates an instance may contribute",
+        default_value = None,
+    )]
+    pub gate_budgets: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Optional: Maximum number of wires the engine loading the circuit supports",
+        default_value = None,
+    )]
+    pub max_wires: Option<usize>,
+
+    #[arg(
+        long,
+        help = "Optional: Maximum number of gates the engine loading the circuit supports",
+        default_value = None,
+    )]
+    pub max_gates: Option<usize>,
+
+    #[arg(
+        long,
+        help = "Optional: Stop processing after this number of template instances, or after the first instance of this template or instance name, and emit the partial circuit",
+        default_value = None,
+    )]
+    pub stop_after: Option<String>,
+
+    #[arg(
+        long,
+        help = "Write the inferred type of each wire (bool, uint or field) to wire_types.json"
+    )]
+    pub wire_types: bool,
+
+    #[arg(
+        long,
+        help = "Write every input name of the circuit with a null value to inputs.json, a skeleton of the inputs to evaluate it with"
+    )]
+    pub inputs_template: bool,
+
+    #[arg(
+        long,
+        help = "Write the subcircuits repeated in the circuit, with the wires of each instance, to simd_groups.json for batched evaluation"
+    )]
+    pub simd_groups: bool,
+
+    #[arg(
+        long,
+        help = "Write the Circom signals carried by each wire, with the templates they were declared in, to signals.sym"
+    )]
+    pub signal_map: bool,
+
+    #[arg(
+        long,
+        help = "Write the gate graph, nodes labeled with their signals and gates with their operation, to circuit.dot for Graphviz"
+    )]
+    pub emit_graphviz: bool,
+
+    #[arg(
+        long,
+        help = "Optional: JSON manifest of several circuits to compile into a package, the input file is ignored",
+        default_value = None,
+    )]
+    pub package: Option<PathBuf>,
+
+    #[arg(
+        long,
+        conflicts_with = "package",
+        help = "Optional: Directory of circuits to compile in one invocation, each .circom file declaring a main component into its own subdirectory of the output directory, the input file is ignored"
+    )]
+    pub input_dir: Option<PathBuf>,
+
+    #[arg(
+        short = 'j',
+        long,
+        help = "Optional: Number of circuits of --package or --input-dir compiled in parallel, the number of CPUs by default"
+    )]
+    pub jobs: Option<usize>,
+
+    #[arg(
+        long,
+        help = "Optional: Number of worker threads processing the components instantiated by the main component in parallel, ignored with --stop-after, --recover and --audit-log"
+    )]
+    pub instance_workers: Option<usize>,
+
+    #[arg(
+        long,
+        help = "Optional: Directory of IR archives written by `prebuild`, linked in place of the template instances they hold when compiled with the same options from the same sources"
+    )]
+    pub prebuilt: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "KEY=VALUE",
+        value_parser = parse_meta,
+        help = "Optional: Metadata recorded in manifest.json and report.json, e.g. a model version or dataset hash, can be repeated"
+    )]
+    pub meta: Vec<(String, String)>,
+
+    #[arg(
+        long,
+        help = "Print the predicted gate and wire counts and artifact sizes as JSON instead of writing the artifacts"
+    )]
+    pub estimate: bool,
+
+    #[arg(
+        short,
+        long,
+        action = ArgAction::Count,
+        help = "Increase the log level (-v for debug, -vv for trace)"
+    )]
+    pub verbose: u8,
 }
 
 impl Args {
@@ -60,11 +626,71 @@ impl Args {
         boolify_width: Option<usize>,
     ) -> Self {
         Self {
+            command: None,
             input,
             output,
+            link_libraries: Vec::new(),
             value_type,
+            signed_gates: false,
+            normalize_names: Vec::new(),
+            prime: Prime::default(),
             boolify_width,
+            naming: NamingStyle::default(),
+            gate_order: GateOrder::default(),
+            format: OutputFormat::default(),
+            target: Target::default(),
+            metrics_file: None,
+            events_file: None,
+            audit_log: None,
+            self_check: false,
+            strict: false,
+            balance_additions: false,
+            fold_constants: false,
+            fuse_constant_mul: false,
+            reduce_divisions: None,
+            no_prune: false,
+            recover: false,
+            party_mapping: None,
+            weights: None,
+            stages: None,
+            noise: None,
+            conversions: None,
+            instance_naming: InstanceNaming::default(),
+            constraint_equality: ConstraintEquality::default(),
+            max_steps: None,
+            gate_budgets: None,
+            max_wires: None,
+            max_gates: None,
+            stop_after: None,
+            wire_types: false,
+            inputs_template: false,
+            simd_groups: false,
+            signal_map: false,
+            emit_graphviz: false,
+            package: None,
+            input_dir: None,
+            jobs: None,
+            instance_workers: None,
+            prebuilt: None,
+            meta: Vec::new(),
+            estimate: false,
+            verbose: 0,
+        }
+    }
+
+    /// Returns the metadata of `--meta`, by key. A key given several times keeps its last value.
+    pub fn metadata(&self) -> BTreeMap<String, String> {
+        self.meta.iter().cloned().collect()
+    }
+}
+
+/// Parses a `--meta` pair, `key=value`.
+fn parse_meta(pair: &str) -> Result<(String, String), String> {
+    match pair.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => {
+            Ok((key.trim().to_string(), value.to_string()))
         }
+        _ => Err(format!("expected KEY=VALUE, found {:?}", pair)),
     }
 }
 
@@ -90,4 +716,28 @@ mod tests {
 
         assert_eq!(result, expected);
     }
+
+    #[test]
+    fn test_meta() {
+        let args = Args::try_parse_from([
+            "circom-2-arithc",
+            "--meta",
+            "model=resnet",
+            "--meta",
+            "commit=abc=1",
+            "--meta",
+            "model=mobilenet",
+        ])
+        .unwrap();
+        assert_eq!(
+            args.metadata(),
+            BTreeMap::from([
+                (String::from("commit"), String::from("abc=1")),
+                (String::from("model"), String::from("mobilenet")),
+            ])
+        );
+
+        assert!(Args::try_parse_from(["circom-2-arithc", "--meta", "model"]).is_err());
+        assert!(Args::try_parse_from(["circom-2-arithc", "--meta", "=resnet"]).is_err());
+    }
 }
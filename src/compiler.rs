@@ -3,32 +3,71 @@
 //! This module defines the data structures used to represent the arithmetic circuit.
 
 use crate::{
-    a_gate_type::AGateType, cli::ValueType, program::ProgramError,
-    topological_sort::topological_sort,
+    a_gate_type::{AGateType, CostClass},
+    audit::{AuditEntry, AuditLog},
+    cli::{
+        ConstraintEquality, DivisionReduction, GateOrder, NamingStyle, Prime, Target, ValueType,
+    },
+    field::FieldElement,
+    log_trace::LogEntry,
+    logging::GRAPH,
+    lowering::{lower_gate, lowered_ops, GateSink, Operand, LOWERED_GATES},
+    naming::naming_strategy,
+    passes::PassTiming,
+    program::ProgramError,
+    r1cs::R1cs,
+    rewrite::{apply_rules, GateGraph, RewriteRule, RewriteStats},
+    runtime::{is_internal_name, INTERNAL_PREFIX},
+    signal_map::{SignalMap, SignalMapEntry},
+    topological_sort::{level_order, topological_sort, topological_sort_from},
+    union_find::UnionFind,
+    wire_types::{infer_circuit_wire_types, WireType},
 };
 use bristol_circuit::{BristolCircuit, CircuitInfo, ConstantInfo, Gate};
 use log::debug;
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+    fs::File,
+    io::{self, BufReader, Write},
+    path::Path,
+    sync::Arc,
+};
 use thiserror::Error;
 
 /// Represents a signal in the circuit, with a name and an optional value.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Signal {
     name: String,
-    value: Option<u32>,
+    value: Option<FieldElement>,
+    /// Templates the signal was declared in, outermost first, e.g. `Main/Dense/ReLU`.
+    #[serde(default)]
+    template: String,
+    /// Statement the signal was declared in, e.g. `main.circom:5`.
+    #[serde(skip)]
+    source: Option<Arc<str>>,
+    /// Tags of the declaration, e.g. `binary` for `signal input in{binary}`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
 }
 
 impl Signal {
     /// Creates a new signal.
-    pub fn new(name: String, value: Option<u32>) -> Self {
-        Self { name, value }
+    pub fn new(name: String, value: Option<FieldElement>) -> Self {
+        Self {
+            name,
+            value,
+            template: String::new(),
+            source: None,
+            tags: Vec::new(),
+        }
     }
 }
 
 /// Represents a node in the circuit, a collection of signals.
 /// The `is_const` and `is_out` fields saves us some iterations over the signals and gates.
-#[derive(Default, Debug, Serialize, Deserialize)]
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
 pub struct Node {
     is_const: bool,
     is_out: bool,
@@ -81,7 +120,7 @@ impl Node {
 }
 
 /// Represents a circuit gate, with a left-hand input, right-hand input, and output node identifiers.
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
 pub struct ArithmeticGate {
     pub op: AGateType,
     pub lh_in: u32,
@@ -101,6 +140,20 @@ impl ArithmeticGate {
     }
 }
 
+/// Names of the signals connected to each node of a gate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GateSignalNames<'a> {
+    pub lh_in: Vec<&'a str>,
+    pub rh_in: Vec<&'a str>,
+    pub out: Vec<&'a str>,
+}
+
+/// Visitor used to walk the gates of the compiled circuit.
+pub trait GateVisitor {
+    /// Called once per gate, in insertion order.
+    fn visit_gate(&mut self, gate: &ArithmeticGate, names: &GateSignalNames);
+}
+
 /// Compilation data structure representing an arithmetic circuit with extra information, including
 /// a set of variables and gates.
 #[derive(Default, Debug, Serialize, Deserialize)]
@@ -108,10 +161,37 @@ pub struct Compiler {
     node_count: u32,
     inputs: HashMap<u32, String>,
     outputs: HashMap<u32, String>,
+    input_order: Vec<u32>,
+    output_order: Vec<u32>,
     signals: HashMap<u32, Signal>,
     nodes: HashMap<u32, Node>,
     gates: Vec<ArithmeticGate>,
+    aliases: Vec<(u32, u32)>,
     value_type: ValueType,
+    #[serde(default)]
+    signed_gates: bool,
+    #[serde(default)]
+    field_constants: bool,
+    prime: Prime,
+    target: Target,
+    naming: NamingStyle,
+    gate_order: GateOrder,
+    party_mapping: Option<HashMap<String, usize>>,
+    pruned_inputs: Vec<String>,
+    constraint_checks: Vec<u32>,
+    log_entries: Vec<LogEntry>,
+    #[serde(default)]
+    instances: Vec<InstanceReport>,
+    #[serde(skip)]
+    audit_log: Option<AuditLog>,
+    #[serde(skip)]
+    source: Option<Arc<str>>,
+    #[serde(skip)]
+    pass_timings: Vec<PassTiming>,
+    #[serde(skip)]
+    node_index: NodeIndex,
+    #[serde(skip)]
+    template_path: Vec<String>,
 }
 
 impl Compiler {
@@ -120,19 +200,74 @@ impl Compiler {
             node_count: 0,
             inputs: HashMap::new(),
             outputs: HashMap::new(),
+            input_order: Vec::new(),
+            output_order: Vec::new(),
             signals: HashMap::new(),
             nodes: HashMap::new(),
             gates: Vec::new(),
+            aliases: Vec::new(),
             value_type: Default::default(),
+            signed_gates: false,
+            field_constants: false,
+            prime: Prime::default(),
+            target: Target::default(),
+            naming: Default::default(),
+            gate_order: GateOrder::default(),
+            party_mapping: None,
+            pruned_inputs: Vec::new(),
+            constraint_checks: Vec::new(),
+            log_entries: Vec::new(),
+            instances: Vec::new(),
+            audit_log: None,
+            source: None,
+            pass_timings: Vec::new(),
+            node_index: NodeIndex::default(),
+            template_path: Vec::new(),
         }
     }
 
+    /// Copies the circuit, e.g. a prebuilt instance linked more than once. The audit log isn't
+    /// copied, the copy doesn't record anything.
+    pub fn duplicate(&self) -> Compiler {
+        Compiler {
+            node_count: self.node_count,
+            inputs: self.inputs.clone(),
+            outputs: self.outputs.clone(),
+            input_order: self.input_order.clone(),
+            output_order: self.output_order.clone(),
+            signals: self.signals.clone(),
+            nodes: self.nodes.clone(),
+            gates: self.gates.clone(),
+            aliases: self.aliases.clone(),
+            value_type: self.value_type,
+            signed_gates: self.signed_gates,
+            field_constants: self.field_constants,
+            prime: self.prime,
+            target: self.target,
+            naming: self.naming,
+            gate_order: self.gate_order,
+            party_mapping: self.party_mapping.clone(),
+            pruned_inputs: self.pruned_inputs.clone(),
+            constraint_checks: self.constraint_checks.clone(),
+            log_entries: self.log_entries.clone(),
+            instances: self.instances.clone(),
+            audit_log: None,
+            source: self.source.clone(),
+            pass_timings: self.pass_timings.clone(),
+            node_index: self.node_index.clone(),
+            template_path: self.template_path.clone(),
+        }
+    }
+
+    /// Adds inputs to the circuit. Inputs are ordered by insertion in the artifacts, the signals
+    /// added together (e.g. the elements of an array) are ordered by id.
     pub fn add_inputs(&mut self, inputs: HashMap<u32, String>) {
-        self.inputs.extend(inputs);
+        extend_io(&mut self.inputs, &mut self.input_order, inputs);
     }
 
+    /// Adds outputs to the circuit, ordered like the inputs.
     pub fn add_outputs(&mut self, outputs: HashMap<u32, String>) {
-        self.outputs.extend(outputs);
+        extend_io(&mut self.outputs, &mut self.output_order, outputs);
     }
 
     /// Adds a new signal to the circuit.
@@ -140,7 +275,7 @@ impl Compiler {
         &mut self,
         id: u32,
         name: String,
-        value: Option<u32>,
+        value: Option<FieldElement>,
     ) -> Result<(), CircuitError> {
         // Check that the signal isn't already declared
         if self.signals.contains_key(&id) {
@@ -148,14 +283,31 @@ impl Compiler {
         }
 
         // Create a new signal
-        let signal = Signal::new(name, value);
+        let is_const = value.is_some();
+        let mut signal = Signal::new(name, value);
+        signal.template = self.template_path.join("/");
+        signal.source = self.source.clone();
         self.signals.insert(id, signal);
 
         // Create a new node
-        let node = Node::new_with_signal(id, value.is_some(), false);
-        debug!("{:?}", node);
+        let node = Node::new_with_signal(id, is_const, false);
+        debug!(target: GRAPH, "{:?}", node);
         let node_id = self.get_node_id();
         self.nodes.insert(node_id, node);
+        if self.node_index.built {
+            self.node_index.signal_nodes.insert(id, node_id);
+        }
+
+        Ok(())
+    }
+
+    /// Sets the tags of a signal.
+    pub fn set_signal_tags(&mut self, id: u32, tags: Vec<String>) -> Result<(), CircuitError> {
+        let signal = self
+            .signals
+            .get_mut(&id)
+            .ok_or_else(|| CircuitError::UnknownSignal(id.to_string()))?;
+        signal.tags = tags;
 
         Ok(())
     }
@@ -170,6 +322,17 @@ impl Compiler {
         ret
     }
 
+    /// Gets the signals of the item `name`: the signal itself, or every element of a signal array.
+    /// Unlike `get_signals`, other items sharing the prefix (e.g. `0.in` and `0.index`) don't match.
+    pub fn get_item_signals(&self, name: &str) -> HashMap<u32, String> {
+        let array_prefix = format!("{}[", name);
+        self.signals
+            .iter()
+            .filter(|(_, signal)| signal.name == name || signal.name.starts_with(&array_prefix))
+            .map(|(&signal_id, signal)| (signal_id, signal.name.to_string()))
+            .collect()
+    }
+
     /// Adds a new gate to the circuit.
     pub fn add_gate(
         &mut self,
@@ -179,617 +342,5105 @@ impl Compiler {
         output_signal_id: u32,
     ) -> Result<(), CircuitError> {
         // Get the signal node ids
-        let node_ids = {
-            let mut nodes: [u32; 3] = [0; 3];
-
-            for (&id, node) in self.nodes.iter() {
-                if node.contains_signal(&lhs_signal_id) {
-                    nodes[0] = id;
-                }
-                if node.contains_signal(&rhs_signal_id) {
-                    nodes[1] = id;
-                }
-                if node.contains_signal(&output_signal_id) {
-                    nodes[2] = id;
-                }
-            }
-
-            nodes
-        };
+        let node_ids = [lhs_signal_id, rhs_signal_id, output_signal_id]
+            .map(|signal_id| self.signal_node(signal_id).unwrap_or(0));
 
         // Set the output node as an output node
         self.nodes.get_mut(&node_ids[2]).unwrap().set_output(true);
 
+        if let Some(audit_log) = &mut self.audit_log {
+            audit_log.record(&AuditEntry::Gate {
+                op: gate_type,
+                lhs: signal_name(&self.signals, lhs_signal_id),
+                rhs: signal_name(&self.signals, rhs_signal_id),
+                out: signal_name(&self.signals, output_signal_id),
+                source: self.source.as_deref(),
+            });
+        }
+
         // Create gate
         let gate = ArithmeticGate::new(gate_type, node_ids[0], node_ids[1], node_ids[2]);
-        debug!("{:?}", gate);
+        debug!(target: GRAPH, "{:?}", gate);
+        self.node_index.add_gate_uses(self.gates.len(), &gate);
         self.gates.push(gate);
 
         Ok(())
     }
 
-    /// Creates a connection between two signals in the circuit.
-    /// This is finding the nodes that contain these signals and merging them.
-    pub fn add_connection(&mut self, a: u32, b: u32) -> Result<(), CircuitError> {
-        // Get the signal node ids
-        let n = Node::new();
-        let nodes = {
-            let mut nodes: [(u32, &Node); 2] = [(0, &n); 2];
+    /// Records the time taken by the passes run on the graph.
+    pub fn set_pass_timings(&mut self, pass_timings: Vec<PassTiming>) {
+        self.pass_timings = pass_timings;
+    }
 
-            for (&id, node) in self.nodes.iter() {
-                if node.contains_signal(&a) {
-                    nodes[0] = (id, node);
-                }
-                if node.contains_signal(&b) {
-                    nodes[1] = (id, node);
-                }
-            }
+    /// Returns the time taken by each pass run on the graph, in order.
+    pub fn pass_timings(&self) -> &[PassTiming] {
+        &self.pass_timings
+    }
+
+    /// Returns the gates of the circuit, in insertion order.
+    pub fn gates(&self) -> &[ArithmeticGate] {
+        &self.gates
+    }
 
-            nodes
+    /// Returns the names of the signals merged into a node, sorted by signal id.
+    pub fn node_signal_names(&self, node_id: u32) -> Vec<&str> {
+        let mut signal_ids = match self.nodes.get(&node_id) {
+            Some(node) => node.get_signals().clone(),
+            None => return Vec::new(),
         };
+        signal_ids.sort_unstable();
 
-        let (node_a_id, node_a) = nodes[0];
-        let (node_b_id, node_b) = nodes[1];
+        signal_ids
+            .iter()
+            .filter_map(|id| self.signals.get(id))
+            .map(|signal| signal.name.as_str())
+            .collect()
+    }
 
-        // If both signals are in the same node, no action is needed
-        if node_a_id == node_b_id {
-            return Ok(());
-        }
-        // Check for output and constant nodes
-        if node_a.is_out && node_b.is_out {
-            return Err(CircuitError::CannotMergeOutputNodes);
+    /// Describes a signal for errors, with the statement it was declared in, e.g.
+    /// `0.out (main.circom:5)`. Signals generated for intermediate values are described as such.
+    fn describe_signal(&self, signal_id: u32) -> String {
+        let signal = match self.signals.get(&signal_id) {
+            Some(signal) => signal,
+            None => return format!("signal {}", signal_id),
+        };
+        let name = if is_internal_name(&signal.name) {
+            "intermediate value"
+        } else {
+            &signal.name
+        };
+
+        match &signal.source {
+            Some(source) => format!("{} ({})", name, source),
+            None => name.to_string(),
         }
+    }
+
+    /// Describes the IO signals named `name` for errors, see `describe_signal`.
+    fn describe_io(&self, io: &HashMap<u32, String>, name: &str) -> String {
+        let naming = naming_strategy(self.naming);
+        let mut signal_ids = io
+            .iter()
+            .filter(|&(&id, io_name)| naming.signal_name(io_name, id) == name)
+            .map(|(&id, _)| id)
+            .collect::<Vec<u32>>();
+        signal_ids.sort_unstable();
+
+        signal_ids
+            .into_iter()
+            .map(|id| self.describe_signal(id))
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
 
-        if node_a.is_const && node_b.is_const {
-            return Err(CircuitError::CannotMergeConstantNodes);
+    /// Describes a node for errors by its first named signal, see `describe_signal`.
+    fn describe_node(&self, node_id: u32) -> String {
+        let mut signal_ids = match self.nodes.get(&node_id) {
+            Some(node) => node.get_signals().clone(),
+            None => return format!("node {}", node_id),
+        };
+        signal_ids.sort_unstable();
+
+        let named = signal_ids.iter().find(|id| {
+            self.signals
+                .get(id)
+                .is_some_and(|signal| !is_internal_name(&signal.name))
+        });
+        match named.or(signal_ids.first()) {
+            Some(&signal_id) => self.describe_signal(signal_id),
+            None => format!("node {}", node_id),
         }
+    }
 
-        // Merge the nodes into a new node
-        let mut merged_node = Node::new();
+    /// Walks the gates of the circuit in insertion order, along with their signal names.
+    pub fn visit_gates(&self, visitor: &mut dyn GateVisitor) {
+        for gate in &self.gates {
+            let names = GateSignalNames {
+                lh_in: self.node_signal_names(gate.lh_in),
+                rh_in: self.node_signal_names(gate.rh_in),
+                out: self.node_signal_names(gate.out),
+            };
+            visitor.visit_gate(gate, &names);
+        }
+    }
 
-        // Set the new node as an output and constant
-        merged_node.set_output(node_a.is_out || node_b.is_out);
-        merged_node.set_const(node_a.is_const || node_b.is_const);
+    /// Creates a connection between two signals in the circuit.
+    /// This is finding the nodes that contain these signals and merging them.
+    pub fn add_connection(&mut self, a: u32, b: u32) -> Result<(), CircuitError> {
+        self.add_connections(&[(a, b)])
+    }
 
-        merged_node.add_signals(node_a.get_signals());
-        merged_node.add_signals(node_b.get_signals());
+    /// Creates connections between several pairs of signals.
+    /// Equivalent to calling `add_connection` for each pair. The nodes containing the signals are
+    /// found through the node index, and only the gates using the merged nodes are updated, so the
+    /// cost of a connection doesn't grow with the size of the circuit.
+    pub fn add_connections(&mut self, connections: &[(u32, u32)]) -> Result<(), CircuitError> {
+        self.audit_connections(connections);
 
-        let merged_node_id = self.get_node_id();
+        for &(a, b) in connections {
+            let node_a_id = self
+                .signal_node(a)
+                .ok_or(CircuitError::DisconnectedSignal)?;
+            let node_b_id = self
+                .signal_node(b)
+                .ok_or(CircuitError::DisconnectedSignal)?;
 
-        // Update connections in gates to point to the new merged node
-        self.gates.iter_mut().for_each(|gate| {
-            if gate.lh_in == node_a_id || gate.lh_in == node_b_id {
-                gate.lh_in = merged_node_id;
+            // If both signals are in the same node, no action is needed
+            if node_a_id == node_b_id {
+                continue;
             }
-            if gate.rh_in == node_a_id || gate.rh_in == node_b_id {
-                gate.rh_in = merged_node_id;
+
+            // Extend the larger signal list
+            if self.nodes[&node_a_id].signals.len() >= self.nodes[&node_b_id].signals.len() {
+                self.merge_nodes(&[node_a_id, node_b_id])?;
+            } else {
+                self.merge_nodes(&[node_b_id, node_a_id])?;
             }
-            if gate.out == node_a_id || gate.out == node_b_id {
-                gate.out = merged_node_id;
+        }
+
+        Ok(())
+    }
+
+    /// Merges nodes into a new node, whose signals are the signals of the nodes in order.
+    /// Returns the id of the new node.
+    fn merge_nodes(&mut self, node_ids: &[u32]) -> Result<u32, CircuitError> {
+        // Check for output and constant nodes
+        let conflicting = |is_conflicting: fn(&Node) -> bool| {
+            let conflicting = node_ids
+                .iter()
+                .filter(|id| is_conflicting(&self.nodes[id]))
+                .map(|&id| self.describe_node(id))
+                .collect::<Vec<String>>();
+            (conflicting.len() > 1).then(|| conflicting.join(", "))
+        };
+        if let Some(signals) = conflicting(|node| node.is_out) {
+            return Err(CircuitError::CannotMergeOutputNodes { signals });
+        }
+        if let Some(signals) = conflicting(|node| node.is_const) {
+            return Err(CircuitError::CannotMergeConstantNodes { signals });
+        }
+
+        let mut merged_node = Node::new();
+        for node_id in node_ids {
+            let node = self.nodes.remove(node_id).unwrap();
+            merged_node.set_output(merged_node.is_out || node.is_out);
+            merged_node.set_const(merged_node.is_const || node.is_const);
+            if merged_node.signals.is_empty() {
+                merged_node.signals = node.signals;
+            } else {
+                merged_node.add_signals(&node.signals);
             }
-        });
+        }
 
-        // Remove the old nodes and insert the new merged node
-        self.nodes.remove(&node_a_id);
-        self.nodes.remove(&node_b_id);
+        let merged_node_id = self.get_node_id();
         self.nodes.insert(merged_node_id, merged_node);
 
-        Ok(())
+        // Update connections in the gates using the merged nodes
+        let mut uses = Vec::new();
+        for &node_id in node_ids {
+            self.node_index.merged.link(node_id, merged_node_id);
+            uses.extend(
+                self.node_index
+                    .gate_uses
+                    .remove(&node_id)
+                    .unwrap_or_default(),
+            );
+        }
+        for &gate_id in &uses {
+            let gate = &mut self.gates[gate_id];
+            for id in [&mut gate.lh_in, &mut gate.rh_in, &mut gate.out] {
+                if node_ids.contains(id) {
+                    *id = merged_node_id;
+                }
+            }
+        }
+        self.node_index.gate_uses.insert(merged_node_id, uses);
+
+        Ok(merged_node_id)
     }
 
-    pub fn update_type(&mut self, value_type: ValueType) -> Result<(), CircuitError> {
-        self.value_type = value_type;
+    /// Returns the id of the node containing a signal.
+    fn signal_node(&mut self, signal_id: u32) -> Option<u32> {
+        if !self.node_index.built {
+            self.node_index = NodeIndex::build(&self.nodes, &self.gates);
+        }
 
-        Ok(())
+        let node_id = *self.node_index.signal_nodes.get(&signal_id)?;
+        Some(self.node_index.merged.find(node_id))
     }
 
-    /// Generates a circuit report with input and output signals information.
-    pub fn generate_circuit_report(&self) -> Result<CircuitReport, CircuitError> {
-        // Split input and output nodes
-        let mut input_nodes = Vec::new();
-        let mut output_nodes = Vec::new();
-        self.nodes.iter().for_each(|(&id, node)| {
-            if node.is_out {
-                output_nodes.push(id);
-            } else {
-                input_nodes.push(id);
-            }
-        });
+    /// Records pure connections between signals, to be resolved by `resolve_aliases`.
+    /// Unlike `add_connection`, no nodes are merged until the aliases are resolved.
+    pub fn add_aliases(&mut self, aliases: &[(u32, u32)]) {
+        self.audit_connections(aliases);
+        self.aliases.extend_from_slice(aliases);
+    }
 
-        // Remove output nodes that are inputs to gates
-        output_nodes.retain(|&id| {
-            self.gates
-                .iter()
-                .all(|gate| gate.lh_in != id && gate.rh_in != id)
-        });
+    /// Records every gate and connection added from now on in the audit log.
+    pub fn set_audit_log(&mut self, audit_log: AuditLog) {
+        self.audit_log = Some(audit_log);
+    }
 
-        // Sort
-        input_nodes.sort_unstable();
-        output_nodes.sort_unstable();
+    /// Whether gates and connections are recorded in an audit log.
+    pub fn is_auditing(&self) -> bool {
+        self.audit_log.is_some()
+    }
 
-        // Generate reports
-        let inputs = self.generate_signal_reports(&input_nodes);
-        let outputs = self.generate_signal_reports(&output_nodes);
+    /// Sets the statement the next signals, gates and connections are attributed to, in errors
+    /// and in the audit log, returning the previous one so it can be restored.
+    pub fn replace_source(&mut self, source: Option<Arc<str>>) -> Option<Arc<str>> {
+        std::mem::replace(&mut self.source, source)
+    }
 
-        Ok(CircuitReport {
-            inputs,
-            outputs,
-            value_type: self.value_type,
-        })
+    /// Records a `log` call made by the statement being processed.
+    pub fn add_log_entry(&mut self, mut entry: LogEntry) {
+        entry.source = self.source.as_deref().map(String::from);
+        self.log_entries.push(entry);
     }
 
-    pub fn build_circuit(&self) -> Result<BristolCircuit, CircuitError> {
-        // First build up these maps so we can easily see which node id to use
-        let mut input_to_node_id = HashMap::<String, u32>::new();
-        let mut constant_to_node_id_and_value = HashMap::<String, (u32, String)>::new();
-        let mut output_to_node_id = HashMap::<String, u32>::new();
+    /// Returns the `log` calls of the program, in the order they were made.
+    pub fn log_entries(&self) -> &[LogEntry] {
+        &self.log_entries
+    }
 
-        for (node_id, node) in self.nodes.iter() {
-            // Each node has a list of signal ids which all correspond to that node
-            // The compiler associates IO with signals, so here we bridge the gap so we get
-            // IO <=> node instead of IO <=> signal <=> node
-            for signal_id in node.get_signals() {
-                if let Some(input_name) = self.inputs.get(signal_id) {
-                    let prev = input_to_node_id.insert(input_name.clone(), *node_id);
+    /// Records a template instance with its evaluated parameters.
+    pub fn add_instance(&mut self, instance: InstanceReport) {
+        self.instances.push(instance);
+    }
 
-                    if prev.is_some() {
-                        return Err(CircuitError::Inconsistency {
-                            message: format!("Duplicate input {}", input_name),
-                        });
-                    }
-                }
+    /// Returns the template instances of the program, each before the instances it creates.
+    pub fn instances(&self) -> &[InstanceReport] {
+        &self.instances
+    }
 
-                if let Some(output_name) = self.outputs.get(signal_id) {
-                    let prev = output_to_node_id.insert(output_name.clone(), *node_id);
+    /// Returns the statement a signal was declared in, e.g. `main.circom:5`.
+    pub fn signal_source(&self, signal_id: u32) -> Option<&str> {
+        self.signals.get(&signal_id)?.source.as_deref()
+    }
 
-                    if prev.is_some() {
-                        return Err(CircuitError::Inconsistency {
-                            message: format!("Duplicate output {}", output_name),
-                        });
-                    }
-                }
+    /// Enters an instance of a template, the signals added until it is exited are declared in it.
+    pub fn enter_template(&mut self, template: &str) {
+        self.template_path.push(template.to_string());
+    }
 
-                let signal = &self.signals[signal_id];
+    /// Exits the innermost template instance.
+    pub fn exit_template(&mut self) {
+        self.template_path.pop();
+    }
 
-                if let Some(value) = signal.value {
-                    constant_to_node_id_and_value.insert(
-                        format!("{}_{}", signal.name.clone(), signal_id),
-                        (*node_id, value.to_string()),
-                    );
-                }
+    /// Flushes the audit log and returns the hash of the file, `None` if there is no audit log.
+    pub fn finish_audit_log(&mut self) -> Result<Option<String>, CircuitError> {
+        Ok(self.audit_log.take().map(AuditLog::finish).transpose()?)
+    }
+
+    fn audit_connections(&mut self, connections: &[(u32, u32)]) {
+        if let Some(audit_log) = &mut self.audit_log {
+            for &(a, b) in connections {
+                audit_log.record(&AuditEntry::Connection {
+                    a: signal_name(&self.signals, a),
+                    b: signal_name(&self.signals, b),
+                    source: self.source.as_deref(),
+                });
             }
         }
+    }
 
-        {
-            // We want inputs at the start and outputs at the end
-            // We won't be able to do that if a node is used for both input and output
-            // That shouldn't happen, so we check here that it doesn't happen
+    /// Resolves the pending aliases. Signals connected through chains of aliases are grouped
+    /// first, so each group is merged into a single node at once instead of pair by pair.
+    pub fn resolve_aliases(&mut self) -> Result<AliasStats, CircuitError> {
+        let aliases = std::mem::take(&mut self.aliases);
+        let mut stats = AliasStats {
+            aliases: aliases.len(),
+            ..Default::default()
+        };
 
-            let node_id_to_input_name = input_to_node_id
-                .iter()
-                .map(|(name, node_id)| (node_id, name))
-                .collect::<HashMap<_, _>>();
+        // Group the aliased signals
+        let mut sets = UnionFind::new();
+        for &(a, b) in &aliases {
+            sets.union(a, b);
+        }
 
-            for (output_name, output_node_id) in &output_to_node_id {
-                if let Some(input_name) = node_id_to_input_name.get(output_node_id) {
-                    return Err(CircuitError::Inconsistency {
-                        message: format!(
-                            "Node {} used for both input {} and output {}",
-                            output_node_id, input_name, output_name
-                        ),
-                    });
+        let mut groups = HashMap::<u32, Vec<u32>>::new();
+        for &(a, b) in &aliases {
+            for signal_id in [a, b] {
+                let root = sets.find(signal_id);
+                let group = groups.entry(root).or_default();
+                if !group.contains(&signal_id) {
+                    group.push(signal_id);
                 }
             }
         }
 
-        // Now node ids are like wire ids, but the compiler generates them in a way that leaves a
-        // lot of gaps. So we assign new wire ids so they'll be sequential instead. We also do this
-        // ensure inputs are at the start and outputs are at the end.
-        let mut node_id_to_wire_id = HashMap::<u32, u32>::new();
-        let mut next_wire_id = 0;
+        // Merge the nodes of each group, sorted to keep node ids deterministic
+        let mut groups = groups.into_values().collect::<Vec<Vec<u32>>>();
+        groups.iter_mut().for_each(|group| group.sort_unstable());
+        groups.sort_unstable();
 
-        // First inputs
-        for node_id in input_to_node_id.values() {
-            node_id_to_wire_id.insert(*node_id, next_wire_id);
-            next_wire_id += 1;
-        }
+        for group in groups {
+            let mut node_ids = Vec::new();
+            for &signal_id in &group {
+                let node_id = self
+                    .signal_node(signal_id)
+                    .ok_or(CircuitError::DisconnectedSignal)?;
+                if !node_ids.contains(&node_id) {
+                    node_ids.push(node_id);
+                }
+            }
 
-        // For the intermediate nodes, we need the gates in topological order so that the wires are
-        // assigned in the order they are needed. The topological order is also needed to comply
-        // with bristol format and allow for easy evaluation.
+            if node_ids.len() < 2 {
+                continue;
+            }
 
-        let mut node_id_to_required_gate = HashMap::<u32, usize>::new();
+            self.merge_nodes(&node_ids)?;
+            stats.chains_collapsed += 1;
+        }
 
-        for (gate_id, gate) in self.gates.iter().enumerate() {
-            // the gate.out node depends on this gate
-            node_id_to_required_gate.insert(gate.out, gate_id);
+        Ok(stats)
+    }
+
+    /// Finds the outputs whose cones contain only constants, i.e. outputs that don't depend on any
+    /// input. Returns the output names, sorted, along with the value they evaluate to, if defined.
+    pub fn constant_outputs(&self) -> Vec<(String, Option<FieldElement>)> {
+        let mut signal_to_node = HashMap::<u32, u32>::new();
+        for (&id, node) in self.nodes.iter() {
+            for &signal_id in node.get_signals() {
+                signal_to_node.insert(signal_id, id);
+            }
         }
 
-        let sorted_gate_ids = topological_sort(self.gates.len(), &|gate_id: usize| {
-            let gate = &self.gates[gate_id];
-            let mut deps = Vec::<usize>::new();
+        let node_to_gate = self
+            .gates
+            .iter()
+            .map(|gate| (gate.out, gate))
+            .collect::<HashMap<u32, &ArithmeticGate>>();
 
-            if let Some(required_gate_id) = node_id_to_required_gate.get(&gate.lh_in) {
-                deps.push(*required_gate_id);
+        let mut values = HashMap::<u32, NodeValue>::new();
+        let mut constant_outputs = self
+            .outputs
+            .iter()
+            .filter_map(|(signal_id, name)| {
+                let node_id = signal_to_node.get(signal_id)?;
+                match self.node_value(*node_id, &node_to_gate, &mut values) {
+                    NodeValue::Constant(value) => Some((name.clone(), value)),
+                    NodeValue::Variable => None,
+                }
+            })
+            .collect::<Vec<_>>();
+        constant_outputs.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        constant_outputs
+    }
+
+    /// Returns the range of the values known at compile time, i.e. constants and gates that only
+    /// depend on constants, or `None` if the circuit has no such value.
+    pub fn constant_range(&self) -> Option<ConstantRange> {
+        let node_to_gate = self
+            .gates
+            .iter()
+            .map(|gate| (gate.out, gate))
+            .collect::<HashMap<u32, &ArithmeticGate>>();
+
+        let mut values = HashMap::<u32, NodeValue>::new();
+        self.nodes
+            .keys()
+            .filter_map(
+                |&node_id| match self.node_value(node_id, &node_to_gate, &mut values) {
+                    NodeValue::Constant(Some(value)) => {
+                        Some(ConstantRange::of(&value, self.value_type, self.prime))
+                    }
+                    _ => None,
+                },
+            )
+            .reduce(ConstantRange::union)
+    }
+
+    /// Evaluates a node if it only depends on constants, memoizing the results. The cone is
+    /// walked with an explicit stack, so deep circuits don't overflow the call stack.
+    fn node_value(
+        &self,
+        node_id: u32,
+        node_to_gate: &HashMap<u32, &ArithmeticGate>,
+        values: &mut HashMap<u32, NodeValue>,
+    ) -> NodeValue {
+        // Nodes are visited before their gate inputs and evaluated once both inputs are known
+        let mut stack = vec![(node_id, false)];
+        while let Some((node_id, inputs_known)) = stack.pop() {
+            if inputs_known {
+                let gate = node_to_gate[&node_id];
+                let value = match (&values[&gate.lh_in], &values[&gate.rh_in]) {
+                    (NodeValue::Constant(lhs), NodeValue::Constant(rhs)) => NodeValue::Constant(
+                        lhs.as_ref()
+                            .zip(rhs.as_ref())
+                            .and_then(|(l, r)| self.evaluate_gate(gate.op, l, r)),
+                    ),
+                    _ => NodeValue::Variable,
+                };
+                values.insert(node_id, value);
+                continue;
+            }
+            if values.contains_key(&node_id) {
+                continue;
             }
 
-            if let Some(required_gate_id) = node_id_to_required_gate.get(&gate.rh_in) {
-                deps.push(*required_gate_id);
+            let node = &self.nodes[&node_id];
+            if node.is_const {
+                // Without field constants, constants that don't fit in a word are left to the
+                // field backends
+                let value = match self.node_field_constant(node_id) {
+                    Some(value)
+                        if self.field_constants
+                            || value.to_word(self.value_type, self.prime).is_some() =>
+                    {
+                        NodeValue::Constant(Some(value.clone()))
+                    }
+                    _ => NodeValue::Variable,
+                };
+                values.insert(node_id, value);
+            } else if let Some(gate) = node_to_gate.get(&node_id) {
+                // Mark the node while visiting it, so cycles are treated as variable
+                values.insert(node_id, NodeValue::Variable);
+                stack.push((node_id, true));
+                stack.push((gate.rh_in, false));
+                stack.push((gate.lh_in, false));
+            } else {
+                values.insert(node_id, NodeValue::Variable);
             }
+        }
 
-            deps
-        })?;
+        values[&node_id].clone()
+    }
 
-        let output_node_ids = output_to_node_id.values().collect::<HashSet<_>>();
+    /// Evaluates the cone of a signal, i.e. only the gates it depends on, from the given input
+    /// values keyed by signal name. Returns the value of the signal along with the values of the
+    /// inputs of the gate producing it.
+    pub fn evaluate_cone(
+        &self,
+        signal_name: &str,
+        inputs: &HashMap<String, FieldElement>,
+    ) -> Result<ConeEvaluation, CircuitError> {
+        let signal_id = self
+            .signals
+            .iter()
+            .find(|(_, signal)| signal.name == signal_name)
+            .map(|(&id, _)| id)
+            .ok_or_else(|| CircuitError::UnknownSignal(signal_name.to_string()))?;
+        let node_id = self
+            .nodes
+            .iter()
+            .find(|(_, node)| node.contains_signal(&signal_id))
+            .map(|(&id, _)| id)
+            .ok_or(CircuitError::DisconnectedSignal)?;
 
-        // Now that the gates are in order, we can assign wire ids to each node in the order they
-        // are seen
-        for gate_id in &sorted_gate_ids {
-            let gate = &self.gates[*gate_id];
+        let node_to_gate = self
+            .gates
+            .iter()
+            .map(|gate| (gate.out, gate))
+            .collect::<HashMap<u32, &ArithmeticGate>>();
 
-            for node_id in &[gate.lh_in, gate.rh_in, gate.out] {
-                if output_node_ids.contains(node_id) {
-                    // Output wires are excluded so that they can all be at the end
-                    continue;
-                }
+        // Depth-first evaluation with an explicit stack, so deep cones don't overflow the call stack
+        let mut values = HashMap::<u32, FieldElement>::new();
+        let mut visiting = HashSet::<u32>::new();
+        let mut stack = vec![(node_id, false)];
+        while let Some((id, expanded)) = stack.pop() {
+            if values.contains_key(&id) {
+                continue;
+            }
 
-                if node_id_to_wire_id.contains_key(node_id) {
+            let node = self.nodes.get(&id).ok_or(CircuitError::UnprocessedNode)?;
+            let value = if node.is_const {
+                self.node_field_constant(id).cloned()
+            } else if let Some(gate) = node_to_gate.get(&id) {
+                if !expanded {
+                    if !visiting.insert(id) {
+                        return Err(CircuitError::CyclicDependency {
+                            message: format!("node {} depends on itself", id),
+                        });
+                    }
+                    stack.push((id, true));
+                    stack.push((gate.rh_in, false));
+                    stack.push((gate.lh_in, false));
                     continue;
                 }
+                visiting.remove(&id);
+                self.evaluate_gate(gate.op, &values[&gate.lh_in], &values[&gate.rh_in])
+            } else {
+                let value = self
+                    .node_signal_names(id)
+                    .iter()
+                    .find_map(|name| inputs.get(*name).cloned());
+                if value.is_none() {
+                    return Err(CircuitError::MissingInputValue(self.node_name(id)));
+                }
+                value
+            };
 
-                node_id_to_wire_id.insert(*node_id, next_wire_id);
-                next_wire_id += 1;
-            }
+            match value {
+                Some(value) => values.insert(id, value),
+                None => return Err(CircuitError::UndefinedValue(self.node_name(id))),
+            };
         }
 
-        // Assign wire ids to output nodes
-        for node_id in output_to_node_id.values() {
-            node_id_to_wire_id.insert(*node_id, next_wire_id);
-            next_wire_id += 1;
+        let gate = node_to_gate.get(&node_id).map(|gate| ConeGate {
+            op: gate.op,
+            lh_in: values[&gate.lh_in].clone(),
+            rh_in: values[&gate.rh_in].clone(),
+        });
+
+        Ok(ConeEvaluation {
+            value: values.remove(&node_id).unwrap(),
+            gate,
+        })
+    }
+
+    /// Builds the circuit and writes it as a rank-1 constraint system in the `.r1cs` format, along
+    /// with the names of its IO wires in the `.sym` format. Fails on gates other than additions,
+    /// subtractions and multiplications, see `R1cs::from_circuit`.
+    pub fn write_r1cs<W: Write, S: Write>(
+        &self,
+        r1cs: &mut W,
+        sym: &mut S,
+    ) -> Result<(), CircuitError> {
+        let (circuit, _) = self.build_wires(true)?;
+        let r1cs_system = R1cs::from_circuit(&circuit, self.value_type, self.prime)?;
+        r1cs_system.write(r1cs)?;
+        r1cs_system.write_sym(sym)
+    }
+
+    /// Evaluates the outputs of the circuit from the given input values keyed by signal name,
+    /// returning the value of each output keyed by name.
+    pub fn evaluate_outputs(
+        &self,
+        inputs: &HashMap<String, FieldElement>,
+    ) -> Result<HashMap<String, FieldElement>, CircuitError> {
+        self.outputs
+            .values()
+            .map(|name| Ok((name.clone(), self.evaluate_cone(name, inputs)?.value)))
+            .collect()
+    }
+
+    /// Infers the type of each node that gets a wire, see `infer_circuit_wire_types`. Inputs are
+    /// `Field`.
+    pub fn wire_types(&self) -> Result<HashMap<u32, WireType>, CircuitError> {
+        let (circuit, node_wires) = self.build_circuit_wires()?;
+        let types = infer_circuit_wire_types(&circuit, self.value_type);
+
+        Ok(node_wires
+            .into_iter()
+            .map(|(node_id, wire)| (node_id, types[wire as usize]))
+            .collect())
+    }
+
+    /// Returns the name of the first signal of a node, or its id if it has none.
+    fn node_name(&self, node_id: u32) -> String {
+        self.node_signal_names(node_id)
+            .first()
+            .map(|name| name.to_string())
+            .unwrap_or_else(|| format!("node {}", node_id))
+    }
+
+    /// Writes the gate graph as a Graphviz DOT graph, from the inputs at the bottom to the outputs
+    /// at the top, to inspect small circuits. Nodes are labeled with their signal names, the
+    /// first few of them for nodes merging many signals, and gates with their operation. Inputs
+    /// are blue, outputs green and constants show their value.
+    pub fn write_dot<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        const MAX_NAMES: usize = 3;
+
+        let mut node_ids = self
+            .gates
+            .iter()
+            .flat_map(|gate| [gate.lh_in, gate.rh_in, gate.out])
+            .chain(self.nodes.iter().filter_map(|(&node_id, node)| {
+                node.signals
+                    .iter()
+                    .any(|id| self.inputs.contains_key(id) || self.outputs.contains_key(id))
+                    .then_some(node_id)
+            }))
+            .collect::<Vec<u32>>();
+        node_ids.sort_unstable();
+        node_ids.dedup();
+
+        writeln!(writer, "digraph circuit {{")?;
+        writeln!(writer, "    rankdir=BT;")?;
+        for node_id in node_ids {
+            let names = self.node_signal_names(node_id);
+            let mut label = names
+                .iter()
+                .take(MAX_NAMES)
+                .copied()
+                .collect::<Vec<&str>>()
+                .join("\\n");
+            if names.len() > MAX_NAMES {
+                label.push_str(&format!("\\n+{} more", names.len() - MAX_NAMES));
+            }
+            if let Some(value) = self.node_constant(node_id) {
+                label.push_str(&format!(" = {}", value));
+            }
+
+            let signals = self
+                .nodes
+                .get(&node_id)
+                .map_or(&[][..], |node| &node.signals[..]);
+            let style = if signals.iter().any(|id| self.outputs.contains_key(id)) {
+                ", color=green"
+            } else if signals.iter().any(|id| self.inputs.contains_key(id)) {
+                ", color=blue"
+            } else {
+                ""
+            };
+            writeln!(
+                writer,
+                "    n{} [label=\"{}\"{}];",
+                node_id,
+                label.replace('"', "\\\""),
+                style
+            )?;
+        }
+        for (gate_id, gate) in self.gates.iter().enumerate() {
+            writeln!(
+                writer,
+                "    g{} [label=\"{}\", shape=box];",
+                gate_id, gate.op
+            )?;
+            writeln!(writer, "    n{} -> g{};", gate.lh_in, gate_id)?;
+            writeln!(writer, "    n{} -> g{};", gate.rh_in, gate_id)?;
+            writeln!(writer, "    g{} -> n{};", gate_id, gate.out)?;
         }
+        writeln!(writer, "}}")
+    }
 
-        // Now we can create the new gates using topological order and the new wire ids
-        let mut new_gates = Vec::<Gate>::new();
-        for gate_id in sorted_gate_ids {
-            let gate = &self.gates[gate_id];
+    /// Renames signal ids according to the given map, leaving unmapped signals untouched.
+    /// Fails without modifying the circuit if two signals would end up sharing an id.
+    pub fn remap_signals(&mut self, map: &HashMap<u32, u32>) -> Result<(), CircuitError> {
+        let remap = |id: &u32| *map.get(id).unwrap_or(id);
 
-            new_gates.push(Gate {
-                inputs: vec![
-                    node_id_to_wire_id[&gate.lh_in] as usize,
-                    node_id_to_wire_id[&gate.rh_in] as usize,
-                ],
-                outputs: vec![node_id_to_wire_id[&gate.out] as usize],
-                op: gate.op.to_string(),
+        // Check that the remapped ids are unique
+        let mut remapped_ids = HashSet::with_capacity(self.signals.len());
+        for id in self.signals.keys() {
+            let new_id = remap(id);
+            if !remapped_ids.insert(new_id) {
+                return Err(CircuitError::SignalIdCollision(new_id));
+            }
+        }
+
+        self.signals = self
+            .signals
+            .drain()
+            .map(|(id, signal)| (remap(&id), signal))
+            .collect();
+        self.inputs = self
+            .inputs
+            .drain()
+            .map(|(id, name)| (remap(&id), name))
+            .collect();
+        self.outputs = self
+            .outputs
+            .drain()
+            .map(|(id, name)| (remap(&id), name))
+            .collect();
+        self.input_order.iter_mut().for_each(|id| *id = remap(id));
+        self.output_order.iter_mut().for_each(|id| *id = remap(id));
+        self.constraint_checks
+            .iter_mut()
+            .for_each(|id| *id = remap(id));
+        for entry in &mut self.log_entries {
+            for signal in &mut entry.signals {
+                signal.signal = remap(&signal.signal);
+            }
+        }
+        for node in self.nodes.values_mut() {
+            node.signals.iter_mut().for_each(|id| *id = remap(id));
+        }
+        self.node_index = NodeIndex::default();
+
+        Ok(())
+    }
+
+    /// Renumbers the signals densely from 0, keeping their relative order.
+    /// The runtime allocates ids for variables and intermediate values too, so the ids of the signals
+    /// that reach the compiler are sparse.
+    pub fn compact_signals(&mut self) -> Result<CompactionStats, CircuitError> {
+        let mut ids = self.signals.keys().copied().collect::<Vec<u32>>();
+        ids.sort_unstable();
+
+        let stats = CompactionStats {
+            signals: ids.len(),
+            id_span: ids.last().map_or(0, |&id| id as usize + 1),
+        };
+
+        let map = ids
+            .into_iter()
+            .enumerate()
+            .filter(|&(new_id, id)| new_id as u32 != id)
+            .map(|(new_id, id)| (id, new_id as u32))
+            .collect::<HashMap<u32, u32>>();
+        self.remap_signals(&map)?;
+
+        Ok(stats)
+    }
+
+    /// Creates an empty circuit with the settings of this one, whose signals are declared in the
+    /// current template instance, e.g. to build a component on a worker thread.
+    pub fn fork(&self) -> Compiler {
+        Compiler {
+            value_type: self.value_type,
+            signed_gates: self.signed_gates,
+            field_constants: self.field_constants,
+            prime: self.prime,
+            target: self.target,
+            source: self.source.clone(),
+            template_path: self.template_path.clone(),
+            ..Compiler::new()
+        }
+    }
+
+    /// Merges a circuit built separately into this one. The signals of both circuits must use
+    /// disjoint id ranges, e.g. from `SignalGen::partition`. The nodes of `other` are renumbered
+    /// after the nodes of this circuit and its IO is appended to the IO of this circuit, so merging
+    /// the same circuits in the same order always gives the same result. Both circuits must be
+    /// compiled with the same value type, prime, target and signed gates.
+    pub fn merge(&mut self, other: Compiler) -> Result<(), CircuitError> {
+        let settings = |compiler: &Compiler| {
+            (
+                compiler.value_type,
+                compiler.prime,
+                compiler.target,
+                compiler.signed_gates,
+            )
+        };
+        if settings(&other) != settings(self) {
+            return Err(CircuitError::Inconsistency {
+                message: format!(
+                    "Cannot merge a {:?} circuit into a {:?} circuit",
+                    settings(&other),
+                    settings(self)
+                ),
             });
         }
+        if let Some(&id) = other
+            .signals
+            .keys()
+            .find(|id| self.signals.contains_key(id))
+        {
+            return Err(CircuitError::SignalIdCollision(id));
+        }
 
-        let mut constants = HashMap::<String, ConstantInfo>::new();
+        let offset = self.node_count;
+        self.node_count += other.node_count;
 
-        for (name, (node_id, value)) in constant_to_node_id_and_value {
-            constants.insert(
-                name,
-                ConstantInfo {
-                    value,
-                    wire_index: node_id_to_wire_id[&node_id] as usize,
-                },
+        self.signals.extend(other.signals);
+        self.nodes.extend(
+            other
+                .nodes
+                .into_iter()
+                .map(|(node_id, node)| (node_id + offset, node)),
+        );
+        self.gates
+            .extend(other.gates.into_iter().map(|gate| ArithmeticGate {
+                lh_in: gate.lh_in + offset,
+                rh_in: gate.rh_in + offset,
+                out: gate.out + offset,
+                ..gate
+            }));
+        self.aliases.extend(other.aliases);
+        self.constraint_checks.extend(other.constraint_checks);
+        self.log_entries.extend(other.log_entries);
+        self.instances.extend(other.instances);
+        self.pruned_inputs.extend(other.pruned_inputs);
+        self.node_index = NodeIndex::default();
+
+        // Keep the IO order of the merged circuit
+        for id in other.input_order {
+            extend_io(
+                &mut self.inputs,
+                &mut self.input_order,
+                HashMap::from([(id, other.inputs[&id].clone())]),
+            );
+        }
+        for id in other.output_order {
+            extend_io(
+                &mut self.outputs,
+                &mut self.output_order,
+                HashMap::from([(id, other.outputs[&id].clone())]),
             );
         }
 
-        Ok(BristolCircuit {
-            wire_count: next_wire_id as usize,
-            info: CircuitInfo {
-                input_name_to_wire_index: input_to_node_id
-                    .iter()
-                    .map(|(name, node_id)| (name.clone(), node_id_to_wire_id[node_id] as usize))
-                    .collect(),
-                constants,
-                output_name_to_wire_index: output_to_node_id
-                    .iter()
-                    .map(|(name, node_id)| (name.clone(), node_id_to_wire_id[node_id] as usize))
-                    .collect(),
-            },
-            gates: new_gates,
-            io_widths: None,
-        })
+        Ok(())
     }
 
-    /// Returns a node id and increments the count.
-    fn get_node_id(&mut self) -> u32 {
-        self.node_count += 1;
-        self.node_count
+    /// Same as `merge`, but the `shared` signals are declared in both circuits, e.g. the inputs and
+    /// outputs of a component built by a worker, also declared in the circuit wiring it. They keep
+    /// their declaration in `other`, and the nodes holding them are merged like connected signals.
+    pub fn merge_instance(
+        &mut self,
+        mut other: Compiler,
+        shared: &[u32],
+    ) -> Result<(), CircuitError> {
+        // Take the shared signals out of `other`, each is held by a single node once merged
+        let offset = self.node_count;
+        let mut shared_nodes = Vec::new();
+        let mut shared_signals = Vec::new();
+        for &signal_id in shared {
+            let node_id = other
+                .signal_node(signal_id)
+                .ok_or(CircuitError::DisconnectedSignal)?;
+            if let Some(node) = other.nodes.get_mut(&node_id) {
+                node.signals.retain(|&id| id != signal_id);
+            }
+            if let Some(signal) = other.signals.remove(&signal_id) {
+                shared_signals.push((signal_id, signal));
+            }
+            shared_nodes.push((signal_id, node_id + offset));
+        }
+
+        self.merge(other)?;
+        self.signals.extend(shared_signals);
+
+        for (signal_id, node_id) in shared_nodes {
+            let node_a_id = self
+                .signal_node(signal_id)
+                .ok_or(CircuitError::DisconnectedSignal)?;
+            let node_b_id = self.node_index.merged.find(node_id);
+            if node_a_id != node_b_id {
+                self.merge_nodes(&[node_a_id, node_b_id])?;
+            }
+        }
+
+        Ok(())
     }
 
-    /// Generates signal reports for a set of node IDs.
-    fn generate_signal_reports(&self, nodes: &[u32]) -> Vec<SignalReport> {
-        nodes
+    /// Links a circuit compiled with a template instance as its main component, e.g. an archive
+    /// written by `prebuild`, as the instance `instance` of the current template. The signals of
+    /// `other` are renumbered with `map`, those of its inputs and outputs to the signals declared
+    /// for them in this circuit, which are merged like in `merge_instance`. The IO of `other`
+    /// isn't part of the IO of this circuit, except the constraint checks it exposed as outputs,
+    /// which are checks of this circuit.
+    pub fn link_instance(
+        &mut self,
+        mut other: Compiler,
+        instance: &str,
+        map: &HashMap<u32, u32>,
+    ) -> Result<(), CircuitError> {
+        other.remap_signals(map)?;
+        let remap = |id: &u32| *map.get(id).unwrap_or(id);
+        for (a, b) in &mut other.aliases {
+            *a = remap(a);
+            *b = remap(b);
+        }
+
+        // The main component of `other` is the instance
+        let rename = |name: &str| match name.strip_prefix("0.") {
+            Some(path) => format!("{}.{}", instance, path),
+            None => name.to_string(),
+        };
+        let template_path = self.template_path.join("/");
+        for signal in other.signals.values_mut() {
+            signal.name = rename(&signal.name);
+            if !template_path.is_empty() && !signal.template.is_empty() {
+                signal.template = format!("{}/{}", template_path, signal.template);
+            }
+            signal.source = self.source.clone();
+        }
+        for entry in &mut other.log_entries {
+            entry.context = match entry.context.as_str() {
+                "0" => instance.to_string(),
+                context => rename(context),
+            };
+        }
+        // The instance itself is reported by this circuit
+        other.instances.retain(|report| report.instance != "0");
+        for report in &mut other.instances {
+            report.instance = rename(&report.instance);
+        }
+
+        for id in std::mem::take(&mut other.output_order) {
+            if other.outputs[&id].starts_with("0.constraints") {
+                other.constraint_checks.push(id);
+            }
+        }
+        other.inputs.clear();
+        other.outputs.clear();
+        other.input_order.clear();
+        other.pruned_inputs.clear();
+
+        let mut shared = other
+            .signals
+            .keys()
+            .filter(|id| self.signals.contains_key(id))
+            .copied()
+            .collect::<Vec<u32>>();
+        shared.sort_unstable();
+        self.merge_instance(other, &shared)
+    }
+
+    /// Binds inputs to constant values, e.g. model weights supplied outside of the circom source.
+    /// The bound signals are no longer inputs, they are emitted as constants instead.
+    pub fn bind_constants(
+        &mut self,
+        values: &HashMap<String, FieldElement>,
+    ) -> Result<(), CircuitError> {
+        let input_ids = self
+            .inputs
             .iter()
-            .map(|&id| {
-                let signals = self
-                    .nodes
-                    .get(&id)
-                    .expect("Node ID not found in node map")
-                    .get_signals();
+            .map(|(&signal_id, name)| (name.as_str(), signal_id))
+            .collect::<HashMap<&str, u32>>();
+        let mut signal_to_node = HashMap::<u32, u32>::new();
+        for (&id, node) in self.nodes.iter() {
+            for &signal_id in node.get_signals() {
+                signal_to_node.insert(signal_id, id);
+            }
+        }
 
-                let (names, value) = signals.iter().fold((Vec::new(), None), |mut acc, &sig_id| {
-                    let signal = self
-                        .signals
-                        .get(&sig_id)
-                        .expect("Signal ID not found in signal map");
+        let mut bound = HashSet::new();
+        for (name, value) in values {
+            let signal_id = *input_ids
+                .get(name.as_str())
+                .ok_or_else(|| CircuitError::UnknownSignal(name.clone()))?;
+            let node_id = *signal_to_node
+                .get(&signal_id)
+                .ok_or(CircuitError::DisconnectedSignal)?;
 
-                    if !signal.name.contains("random_") {
-                        acc.0.push(signal.name.clone());
-                    }
-                    if signal.value.is_some() {
-                        acc.1 = signal.value;
-                    }
-                    acc
-                });
+            let node = self.nodes.get_mut(&node_id).unwrap();
+            if node.is_const {
+                return Err(CircuitError::ConstantValueAlreadySet);
+            }
+            node.set_const(true);
+            self.signals.get_mut(&signal_id).unwrap().value = Some(value.clone());
+            bound.insert(signal_id);
+        }
 
-                SignalReport { id, names, value }
+        self.inputs
+            .retain(|signal_id, _| !bound.contains(signal_id));
+        self.input_order
+            .retain(|signal_id| !bound.contains(signal_id));
+
+        Ok(())
+    }
+
+    /// Returns the names of the inputs of the circuit.
+    pub fn input_names(&self) -> impl Iterator<Item = &str> {
+        self.inputs.values().map(String::as_str)
+    }
+
+    /// Returns the names of the outputs of the circuit.
+    pub fn output_names(&self) -> impl Iterator<Item = &str> {
+        self.outputs.values().map(String::as_str)
+    }
+
+    /// Returns an id above every signal of the circuit, for signals added after processing.
+    pub fn next_signal_id(&self) -> u32 {
+        self.signals.keys().max().map_or(0, |&id| id + 1)
+    }
+
+    /// Renames a signal.
+    pub fn rename_signal(&mut self, id: u32, name: String) -> Result<(), CircuitError> {
+        let signal = self
+            .signals
+            .get_mut(&id)
+            .ok_or_else(|| CircuitError::UnknownSignal(id.to_string()))?;
+        signal.name = name;
+        Ok(())
+    }
+
+    /// Returns the input signals of the item `name`, the input itself or the elements of an
+    /// input array, in IO order.
+    pub fn input_signals(&self, name: &str) -> Vec<u32> {
+        item_io_signals(&self.inputs, &self.input_order, name)
+    }
+
+    /// Returns the output signals of the item `name`, in IO order.
+    pub fn output_signals(&self, name: &str) -> Vec<u32> {
+        item_io_signals(&self.outputs, &self.output_order, name)
+    }
+
+    /// Returns the input or output name of a signal.
+    pub fn io_name(&self, signal_id: u32) -> Option<&str> {
+        self.inputs
+            .get(&signal_id)
+            .or_else(|| self.outputs.get(&signal_id))
+            .map(String::as_str)
+    }
+
+    /// Replaces inputs or outputs with a new signal named `name`, which takes the position of the
+    /// first of them in the IO order, e.g. to attach a processing stage to the circuit IO.
+    pub fn replace_io(&mut self, old: &[u32], new: u32, name: String) -> Result<(), CircuitError> {
+        let (io, order) = if old.iter().all(|id| self.inputs.contains_key(id)) {
+            (&mut self.inputs, &mut self.input_order)
+        } else if old.iter().all(|id| self.outputs.contains_key(id)) {
+            (&mut self.outputs, &mut self.output_order)
+        } else {
+            return Err(CircuitError::UnknownSignal(name));
+        };
+        if !self.signals.contains_key(&new) {
+            return Err(CircuitError::UnknownSignal(name));
+        }
+
+        let position = order
+            .iter()
+            .position(|id| old.contains(id))
+            .ok_or_else(|| CircuitError::UnknownSignal(name.clone()))?;
+        order[position] = new;
+        order.retain(|id| !old.contains(id));
+        io.retain(|id, _| !old.contains(id));
+        io.insert(new, name);
+
+        Ok(())
+    }
+
+    /// Returns the names of the inputs whose node isn't read by any gate, sorted.
+    /// Some backends reject circuits with such inputs. Inputs passed through to an output, e.g.
+    /// `out <== in`, drive that output and aren't unused.
+    pub fn unused_inputs(&self) -> Vec<String> {
+        let used = self
+            .gates
+            .iter()
+            .flat_map(|gate| [gate.lh_in, gate.rh_in])
+            .collect::<HashSet<u32>>();
+
+        let mut unused = self
+            .nodes
+            .iter()
+            .filter(|(node_id, node)| {
+                !used.contains(node_id)
+                    && !node
+                        .get_signals()
+                        .iter()
+                        .any(|signal_id| self.outputs.contains_key(signal_id))
             })
-            .collect()
+            .flat_map(|(_, node)| node.get_signals())
+            .filter_map(|signal_id| self.inputs.get(signal_id).cloned())
+            .collect::<Vec<String>>();
+        unused.sort();
+
+        unused
     }
-}
 
-/// The full circuit report, containing input and output signals information.
-#[derive(Debug, Serialize, Deserialize)]
-pub struct CircuitReport {
-    inputs: Vec<SignalReport>,
-    outputs: Vec<SignalReport>,
-    value_type: ValueType,
-}
+    /// Records the signal holding the result of a constraint equality check, 1 if its sides are
+    /// equal, see `add_constraint_outputs`.
+    pub fn add_constraint_check(&mut self, signal_id: u32) {
+        self.constraint_checks.push(signal_id);
+    }
 
-/// A single node report, with a list of signal names and an optional value.
-#[derive(Debug, Serialize, Deserialize)]
-pub struct SignalReport {
-    id: u32,
-    names: Vec<String>,
-    value: Option<u32>,
-}
+    /// Exposes the recorded constraint equality checks as outputs, after the declared outputs:
+    /// each check as `0.constraints[i]` for `ConstraintEquality::Output`, or the product of the
+    /// checks as `0.constraints_ok` for `ConstraintEquality::Assert`.
+    pub fn add_constraint_outputs(&mut self, mode: ConstraintEquality) -> Result<(), CircuitError> {
+        let checks = std::mem::take(&mut self.constraint_checks);
+        match mode {
+            ConstraintEquality::Ignore => {}
+            ConstraintEquality::Output => {
+                for (i, &check) in checks.iter().enumerate() {
+                    self.add_outputs(HashMap::from([(check, format!("0.constraints[{}]", i))]));
+                }
+            }
+            ConstraintEquality::Assert => {
+                let mut checks = checks.into_iter();
+                let Some(mut all) = checks.next() else {
+                    return Ok(());
+                };
+                // The checks are 0 or 1, so their product is 1 only if all of them hold
+                for check in checks {
+                    let product = self.next_signal_id();
+                    self.add_signal(product, format!("0.constraints_ok_{}", product), None)?;
+                    self.add_gate(AGateType::AMul, all, check, product)?;
+                    all = product;
+                }
+                self.add_outputs(HashMap::from([(all, String::from("0.constraints_ok"))]));
+            }
+        }
 
-#[derive(Debug, Error)]
-pub enum CircuitError {
-    #[error("Cannot merge constant nodes")]
-    CannotMergeConstantNodes,
-    #[error("Cannot merge output nodes")]
-    CannotMergeOutputNodes,
-    #[error("Constant value already set for variable")]
-    ConstantValueAlreadySet,
-    #[error("Signal is not connected to any node")]
-    DisconnectedSignal,
-    #[error(transparent)]
-    IOError(#[from] std::io::Error),
-    #[error(transparent)]
-    ParseIntError(#[from] std::num::ParseIntError),
-    #[error("Signal already declared")]
-    SignalAlreadyDeclared,
-    #[error("unsupported gate type: {0}")]
-    UnsupportedGateType(String),
-    #[error("Unprocessed node")]
-    UnprocessedNode,
-    #[error("Cyclic dependency: {message}")]
-    CyclicDependency { message: String },
-    #[error("Inconsistency: {message}")]
-    Inconsistency { message: String },
-    #[error("Parsing error: {message}")]
-    ParsingError { message: String },
-}
+        Ok(())
+    }
 
-impl From<CircuitError> for ProgramError {
-    fn from(e: CircuitError) -> Self {
-        ProgramError::CircuitError(e)
+    /// Removes the given inputs from the circuit IO, listing them in the report.
+    /// Their signals are kept, but no wire is assigned to them.
+    pub fn prune_inputs(&mut self, names: &[String]) {
+        let pruned = self
+            .inputs
+            .iter()
+            .filter(|(_, name)| names.contains(name))
+            .map(|(&signal_id, _)| signal_id)
+            .collect::<HashSet<u32>>();
+
+        self.inputs
+            .retain(|signal_id, _| !pruned.contains(signal_id));
+        self.input_order
+            .retain(|signal_id| !pruned.contains(signal_id));
+        self.pruned_inputs.extend_from_slice(names);
+    }
+
+    /// Validates the cross-references between signals, nodes, gates and IO.
+    /// This is an internal invariant checker, a failure indicates a bug in the compiler.
+    pub fn debug_assert_valid(&self) -> Result<(), CircuitError> {
+        let inconsistency = |message: String| Err(CircuitError::Inconsistency { message });
+
+        if !self.aliases.is_empty() {
+            return inconsistency(format!("{} unresolved aliases", self.aliases.len()));
+        }
+
+        // Each signal belongs to exactly one node
+        let mut signal_to_node = HashMap::<u32, u32>::new();
+        for (&node_id, node) in &self.nodes {
+            if node_id > self.node_count {
+                return inconsistency(format!("Node {} exceeds the node count", node_id));
+            }
+            if node.signals.is_empty() {
+                return inconsistency(format!("Node {} has no signals", node_id));
+            }
+
+            let mut has_value = false;
+            for signal_id in &node.signals {
+                let signal = match self.signals.get(signal_id) {
+                    Some(signal) => signal,
+                    None => {
+                        return inconsistency(format!(
+                            "Node {} references undeclared signal {}",
+                            node_id, signal_id
+                        ))
+                    }
+                };
+                has_value |= signal.value.is_some();
+
+                if let Some(other_node_id) = signal_to_node.insert(*signal_id, node_id) {
+                    return inconsistency(format!(
+                        "Signal {} belongs to nodes {} and {}",
+                        signal_id, other_node_id, node_id
+                    ));
+                }
+            }
+
+            if node.is_const != has_value {
+                return inconsistency(format!(
+                    "Node {} has an inconsistent constant flag",
+                    node_id
+                ));
+            }
+        }
+
+        if let Some(signal_id) = self
+            .signals
+            .keys()
+            .find(|id| !signal_to_node.contains_key(id))
+        {
+            return inconsistency(format!("Signal {} doesn't belong to any node", signal_id));
+        }
+
+        // Gates reference existing nodes
+        for (gate_id, gate) in self.gates.iter().enumerate() {
+            for node_id in [gate.lh_in, gate.rh_in, gate.out] {
+                if !self.nodes.contains_key(&node_id) {
+                    return inconsistency(format!(
+                        "Gate {} references missing node {}",
+                        gate_id, node_id
+                    ));
+                }
+            }
+            if !self.nodes[&gate.out].is_out {
+                return inconsistency(format!(
+                    "Gate {} output node {} isn't marked as an output",
+                    gate_id, gate.out
+                ));
+            }
+        }
+
+        // IO reference existing signals
+        for (signal_id, name) in self.inputs.iter().chain(self.outputs.iter()) {
+            if !self.signals.contains_key(signal_id) {
+                return inconsistency(format!(
+                    "IO {} references undeclared signal {}",
+                    name, signal_id
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn update_type(&mut self, value_type: ValueType) -> Result<(), CircuitError> {
+        self.value_type = value_type;
+
+        Ok(())
+    }
+
+    /// Returns the type of the values in the circuit.
+    pub fn value_type(&self) -> ValueType {
+        self.value_type
+    }
+
+    /// Emits the signed variants of comparisons and right shifts for signed value types.
+    pub fn set_signed_gates(&mut self, signed_gates: bool) {
+        self.signed_gates = signed_gates;
+    }
+
+    /// Writes the constants of the built circuit as field elements, for backends over the prime
+    /// field like R1CS, instead of words of the value type.
+    pub fn set_field_constants(&mut self, field_constants: bool) {
+        self.field_constants = field_constants;
+    }
+
+    /// Returns the gate emitted for an operation, its signed variant if signed gates are enabled,
+    /// see `AGateType::for_value_type`.
+    pub fn gate_variant(&self, gate_type: AGateType) -> AGateType {
+        if self.signed_gates {
+            gate_type.for_value_type(self.value_type)
+        } else {
+            gate_type
+        }
+    }
+
+    /// Sets the prime field the compile-time values are computed in.
+    pub fn set_prime(&mut self, prime: Prime) {
+        self.prime = prime;
+    }
+
+    /// Returns the prime field the compile-time values are computed in.
+    pub fn prime(&self) -> Prime {
+        self.prime
+    }
+
+    /// Checks that the values of the signals are elements of the prime field, e.g. once the
+    /// circuit is deserialized, its elements being read without their prime.
+    pub fn check_field_elements(&self) -> Result<(), CircuitError> {
+        let unreduced = self
+            .signals
+            .iter()
+            .filter(|(_, signal)| {
+                signal
+                    .value
+                    .as_ref()
+                    .is_some_and(|value| !value.is_reduced(self.prime))
+            })
+            .min_by_key(|(&id, _)| id);
+        match unreduced {
+            Some((_, signal)) => Err(CircuitError::ValueOutOfRange {
+                value: format!("{} of {}", signal.value.as_ref().unwrap(), signal.name),
+                target: format!("the {:?} field", self.prime),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Sets the engine the circuit is compiled for, recorded in the report.
+    pub fn set_target(&mut self, target: Target) {
+        self.target = target;
+    }
+
+    /// Sets the naming style used for signals, constants and IO in the generated artifacts.
+    pub fn set_naming(&mut self, naming: NamingStyle) {
+        self.naming = naming;
+    }
+
+    /// Sets the order of the gates in the built circuit.
+    pub fn set_gate_order(&mut self, gate_order: GateOrder) {
+        self.gate_order = gate_order;
+    }
+
+    /// Sets the party of each input, used to group the circuit inputs by party.
+    /// Keys are input names, either complete (`0.a[1]`) or the name of an input array (`0.a`).
+    pub fn set_party_mapping(&mut self, party_mapping: HashMap<String, usize>) {
+        self.party_mapping = Some(party_mapping);
+    }
+
+    /// Generates a circuit report with input and output signals information.
+    pub fn generate_circuit_report(&self) -> Result<CircuitReport, CircuitError> {
+        // Split input and output nodes
+        let mut input_nodes = Vec::new();
+        let mut output_nodes = Vec::new();
+        self.nodes.iter().for_each(|(&id, node)| {
+            if node.is_out {
+                output_nodes.push(id);
+            } else {
+                input_nodes.push(id);
+            }
+        });
+
+        // Remove output nodes that are inputs to gates
+        output_nodes.retain(|&id| {
+            self.gates
+                .iter()
+                .all(|gate| gate.lh_in != id && gate.rh_in != id)
+        });
+
+        // Sort IO nodes in the order they were added, then the rest by id
+        let input_ranks = io_ranks(&self.input_order);
+        let output_ranks = io_ranks(&self.output_order);
+        input_nodes.sort_unstable_by_key(|&id| (self.node_io_rank(id, &input_ranks), id));
+        output_nodes.sort_unstable_by_key(|&id| (self.node_io_rank(id, &output_ranks), id));
+
+        // Generate reports
+        let inputs = self.generate_signal_reports(&input_nodes);
+        let outputs = self.generate_signal_reports(&output_nodes);
+        let high_fanout = self.generate_fanout_reports(HIGH_FANOUT_REPORT_SIZE);
+
+        let mut gate_counts = BTreeMap::new();
+        for gate in &self.gates {
+            *gate_counts.entry(gate.op.to_string()).or_default() += 1;
+        }
+        let wires = self.wire_counts();
+
+        Ok(CircuitReport {
+            inputs,
+            outputs,
+            pruned_inputs: self.pruned_inputs.clone(),
+            high_fanout,
+            value_type: self.value_type,
+            prime: self.prime,
+            target: self.target,
+            gate_order: self.gate_order,
+            constant_range: self.constant_range(),
+            gate_counts,
+            multiplicative_depth: self.multiplicative_depth()?,
+            constant_count: wires.constants,
+            wire_count: wires.total,
+            metadata: BTreeMap::new(),
+            instances: self.instances.clone(),
+        })
+    }
+
+    /// Returns a skeleton of the inputs of the circuit, e.g. for `inputs.json`: the name of every
+    /// input wire, as in `circuit_info.json`, without a value. The inputs are those of the report,
+    /// so inputs bound to constants, e.g. by `--weights`, and pruned inputs aren't listed.
+    pub fn generate_input_template(&self) -> Result<BTreeMap<String, Option<u32>>, CircuitError> {
+        let naming = naming_strategy(self.naming);
+        let input_names = self
+            .inputs
+            .iter()
+            .map(|(&signal_id, name)| naming.signal_name(name, signal_id))
+            .collect::<HashSet<String>>();
+
+        // Input nodes also carry the signals of the components they are connected to
+        let report = self.generate_circuit_report()?;
+        Ok(report
+            .inputs()
+            .iter()
+            .filter(|input| input.value().is_none())
+            .flat_map(SignalReport::names)
+            .filter(|name| input_names.contains(*name))
+            .map(|name| (name.clone(), None))
+            .collect())
+    }
+
+    /// Returns the number of non-linear gates on the path with the most of them, i.e. the number
+    /// of communication rounds an MPC engine needs to evaluate the circuit.
+    pub fn multiplicative_depth(&self) -> Result<usize, CircuitError> {
+        let producers = self
+            .gates
+            .iter()
+            .enumerate()
+            .map(|(gate_id, gate)| (gate.out, gate_id))
+            .collect::<HashMap<u32, usize>>();
+        let sorted_gate_ids = topological_sort(self.gates.len(), &|gate_id: usize| {
+            let gate = &self.gates[gate_id];
+            [gate.lh_in, gate.rh_in]
+                .iter()
+                .filter_map(|node_id| producers.get(node_id).copied())
+                .collect()
+        })?;
+
+        let mut node_depths = HashMap::new();
+        let mut depth = 0;
+        for gate_id in sorted_gate_ids {
+            let gate = &self.gates[gate_id];
+            let input_depth = [gate.lh_in, gate.rh_in]
+                .iter()
+                .filter_map(|node_id| node_depths.get(node_id).copied())
+                .max()
+                .unwrap_or(0);
+            let gate_depth = input_depth + (gate.op.cost_class() != CostClass::Linear) as usize;
+            node_depths.insert(gate.out, gate_depth);
+            depth = depth.max(gate_depth);
+        }
+
+        Ok(depth)
+    }
+
+    pub fn build_circuit(&self) -> Result<BristolCircuit, CircuitError> {
+        self.build_circuit_wires().map(|(circuit, _)| circuit)
+    }
+
+    /// Builds the circuit along with the signals carried by each of its wires. Signals generated
+    /// for intermediate values, and signals that no wire carries, aren't mapped.
+    pub fn build_circuit_with_signal_map(
+        &self,
+    ) -> Result<(BristolCircuit, SignalMap), CircuitError> {
+        let (circuit, node_id_to_wire_id) = self.build_circuit_wires()?;
+        Ok((circuit, self.signal_map(&node_id_to_wire_id)))
+    }
+
+    /// Builds the circuit along with the `log` calls of the program, their signals traced to its
+    /// wires.
+    pub fn build_circuit_with_log_trace(
+        &self,
+    ) -> Result<(BristolCircuit, Vec<LogEntry>), CircuitError> {
+        let (circuit, node_id_to_wire_id) = self.build_circuit_wires()?;
+        Ok((circuit, self.log_trace(&node_id_to_wire_id)))
+    }
+
+    /// Returns the signals carried by each wire, given the wire assigned to each node.
+    pub(crate) fn signal_map(&self, node_id_to_wire_id: &HashMap<u32, u32>) -> SignalMap {
+        let mut entries = Vec::new();
+        for (node_id, node) in &self.nodes {
+            let wire = match node_id_to_wire_id.get(node_id) {
+                Some(&wire) => wire as usize,
+                None => continue,
+            };
+
+            for signal_id in node.get_signals() {
+                let signal = &self.signals[signal_id];
+                if !is_internal_name(&signal.name) {
+                    entries.push(SignalMapEntry {
+                        wire,
+                        name: signal.name.clone(),
+                        template: signal.template.clone(),
+                    });
+                }
+            }
+        }
+
+        SignalMap::new(entries)
+    }
+
+    /// Returns the tags of each wire, the tags of the signals it carries, given the wire assigned
+    /// to each node. Wires without tags are left out.
+    pub(crate) fn wire_tags(
+        &self,
+        node_id_to_wire_id: &HashMap<u32, u32>,
+    ) -> BTreeMap<usize, Vec<String>> {
+        let mut wire_tags = BTreeMap::new();
+        for (node_id, node) in &self.nodes {
+            let wire = match node_id_to_wire_id.get(node_id) {
+                Some(&wire) => wire as usize,
+                None => continue,
+            };
+
+            let mut tags: Vec<String> = node
+                .get_signals()
+                .iter()
+                .flat_map(|signal_id| self.signals[signal_id].tags.iter().cloned())
+                .collect();
+            if !tags.is_empty() {
+                tags.sort();
+                tags.dedup();
+                wire_tags.insert(wire, tags);
+            }
+        }
+
+        wire_tags
+    }
+
+    /// Returns the `log` calls of the program with the wires of their signals, given the wire
+    /// assigned to each node.
+    pub(crate) fn log_trace(&self, node_id_to_wire_id: &HashMap<u32, u32>) -> Vec<LogEntry> {
+        let mut signal_wires = HashMap::new();
+        for (node_id, node) in &self.nodes {
+            if let Some(&wire) = node_id_to_wire_id.get(node_id) {
+                for &signal_id in node.get_signals() {
+                    signal_wires.insert(signal_id, wire as usize);
+                }
+            }
+        }
+
+        let mut entries = self.log_entries.clone();
+        for signal in entries.iter_mut().flat_map(|entry| &mut entry.signals) {
+            signal.wire = signal_wires.get(&signal.signal).copied();
+        }
+
+        entries
+    }
+
+    /// Builds the circuit, returning the wire assigned to each node along with it.
+    pub(crate) fn build_circuit_wires(
+        &self,
+    ) -> Result<(BristolCircuit, HashMap<u32, u32>), CircuitError> {
+        self.build_wires(self.field_constants)
+    }
+
+    /// Builds the circuit with its constants written as field elements or as words of the value
+    /// type. Constants that don't fit in a word fail with `ValueOutOfRange` in the latter case.
+    fn build_wires(
+        &self,
+        field_constants: bool,
+    ) -> Result<(BristolCircuit, HashMap<u32, u32>), CircuitError> {
+        if !self.aliases.is_empty() {
+            return Err(CircuitError::Inconsistency {
+                message: format!("{} unresolved aliases", self.aliases.len()),
+            });
+        }
+
+        let naming = naming_strategy(self.naming);
+
+        // First build up these maps so we can easily see which node id to use
+        let mut input_to_node_id = HashMap::<String, u32>::new();
+        let mut constant_to_node_id_and_value = HashMap::<String, (u32, String)>::new();
+        let mut output_to_node_id = HashMap::<String, u32>::new();
+        let input_ranks = io_ranks(&self.input_order);
+        let output_ranks = io_ranks(&self.output_order);
+        let mut input_nodes = Vec::<(usize, u32, u32)>::new();
+        let mut output_nodes = Vec::<(usize, u32, u32)>::new();
+        let mut input_parties = Vec::<(usize, usize, u32)>::new();
+
+        for (node_id, node) in self.nodes.iter() {
+            // Each node has a list of signal ids which all correspond to that node
+            // The compiler associates IO with signals, so here we bridge the gap so we get
+            // IO <=> node instead of IO <=> signal <=> node
+            for signal_id in node.get_signals() {
+                if let Some(input_name) = self.inputs.get(signal_id) {
+                    if let Some(party_mapping) = &self.party_mapping {
+                        let party = party_for_input(party_mapping, input_name)
+                            .ok_or_else(|| CircuitError::UnmappedInput(input_name.clone()))?;
+                        input_parties.push((party, input_ranks[signal_id], *node_id));
+                    }
+                    input_nodes.push((input_ranks[signal_id], *signal_id, *node_id));
+
+                    let input_name = naming.signal_name(input_name, *signal_id);
+                    let prev = input_to_node_id.insert(input_name.clone(), *node_id);
+
+                    if prev.is_some() {
+                        return Err(CircuitError::Inconsistency {
+                            message: format!(
+                                "Duplicate input {}: {}",
+                                input_name,
+                                self.describe_io(&self.inputs, &input_name)
+                            ),
+                        });
+                    }
+                }
+
+                if let Some(output_name) = self.outputs.get(signal_id) {
+                    output_nodes.push((output_ranks[signal_id], *signal_id, *node_id));
+                    let output_name = naming.signal_name(output_name, *signal_id);
+                    let prev = output_to_node_id.insert(output_name.clone(), *node_id);
+
+                    if prev.is_some() {
+                        return Err(CircuitError::Inconsistency {
+                            message: format!(
+                                "Duplicate output {}: {}",
+                                output_name,
+                                self.describe_io(&self.outputs, &output_name)
+                            ),
+                        });
+                    }
+                }
+
+                let signal = &self.signals[signal_id];
+
+                if let Some(value) = &signal.value {
+                    let value = if field_constants {
+                        value.to_string()
+                    } else {
+                        value
+                            .to_word(self.value_type, self.prime)
+                            .ok_or_else(|| CircuitError::ValueOutOfRange {
+                                value: value.to_string(),
+                                target: format!("{:?} wires", self.value_type),
+                            })?
+                            .to_string()
+                    };
+                    constant_to_node_id_and_value.insert(
+                        naming.constant_name(&signal.name, *signal_id),
+                        (*node_id, value),
+                    );
+                }
+            }
+        }
+
+        {
+            // We want inputs at the start and outputs at the end
+            // We won't be able to do that if a node is used for both input and output
+            // That shouldn't happen, so we check here that it doesn't happen
+
+            let node_id_to_input_name = input_to_node_id
+                .iter()
+                .map(|(name, node_id)| (node_id, name))
+                .collect::<HashMap<_, _>>();
+
+            for (output_name, output_node_id) in &output_to_node_id {
+                if let Some(input_name) = node_id_to_input_name.get(output_node_id) {
+                    return Err(CircuitError::Inconsistency {
+                        message: format!(
+                            "Node {} used for both input {} and output {}: {}, {}",
+                            output_node_id,
+                            input_name,
+                            output_name,
+                            self.describe_io(&self.inputs, input_name),
+                            self.describe_io(&self.outputs, output_name)
+                        ),
+                    });
+                }
+            }
+        }
+
+        // Now node ids are like wire ids, but the compiler generates them in a way that leaves a
+        // lot of gaps. So we assign new wire ids so they'll be sequential instead. We also do this
+        // ensure inputs are at the start and outputs are at the end.
+        let mut node_id_to_wire_id = HashMap::<u32, u32>::new();
+        let mut next_wire_id = 0;
+
+        // First inputs in the order they were added, grouped by party when a party mapping is provided
+        input_nodes.sort_unstable();
+        output_nodes.sort_unstable();
+
+        // Outputs connected to the same node share its wire, e.g. a value feeding two outputs
+        let mut seen_output_nodes = HashSet::new();
+        output_nodes.retain(|(_, _, node_id)| seen_output_nodes.insert(*node_id));
+
+        let mut io_widths = None;
+        if self.party_mapping.is_some() {
+            input_parties.sort_unstable();
+
+            let party_count = input_parties.last().map_or(0, |(party, _, _)| party + 1);
+            let mut input_widths = vec![0; party_count];
+            for (party, _, node_id) in &input_parties {
+                node_id_to_wire_id.insert(*node_id, next_wire_id);
+                next_wire_id += 1;
+                input_widths[*party] += 1;
+            }
+
+            io_widths = Some((input_widths, vec![output_nodes.len()]));
+        } else {
+            for (_, _, node_id) in &input_nodes {
+                node_id_to_wire_id.insert(*node_id, next_wire_id);
+                next_wire_id += 1;
+            }
+        }
+
+        // For the intermediate nodes, we need the gates in topological order so that the wires are
+        // assigned in the order they are needed. The topological order is also needed to comply
+        // with bristol format and allow for easy evaluation.
+
+        let mut node_id_to_required_gate = HashMap::<u32, usize>::new();
+
+        for (gate_id, gate) in self.gates.iter().enumerate() {
+            // the gate.out node depends on this gate
+            node_id_to_required_gate.insert(gate.out, gate_id);
+        }
+
+        let get_deps = |gate_id: usize| {
+            let gate = &self.gates[gate_id];
+            let mut deps = Vec::<usize>::new();
+
+            if let Some(required_gate_id) = node_id_to_required_gate.get(&gate.lh_in) {
+                deps.push(*required_gate_id);
+            }
+
+            if let Some(required_gate_id) = node_id_to_required_gate.get(&gate.rh_in) {
+                deps.push(*required_gate_id);
+            }
+
+            deps
+        };
+
+        let sorted_gate_ids = match self.gate_order {
+            GateOrder::Insertion => topological_sort(self.gates.len(), &get_deps)?,
+            GateOrder::DfsCone => {
+                let roots = output_nodes
+                    .iter()
+                    .filter_map(|(_, _, node_id)| node_id_to_required_gate.get(node_id).copied())
+                    .collect::<Vec<usize>>();
+                topological_sort_from(self.gates.len(), &roots, &get_deps)?
+            }
+            GateOrder::BfsLevel => {
+                level_order(topological_sort(self.gates.len(), &get_deps)?, &get_deps)
+            }
+        };
+
+        let output_node_ids = output_to_node_id.values().collect::<HashSet<_>>();
+
+        // Multiplications by a constant carry it inline, unless the target only has `AMul`
+        let inline_constants = self.target.supports(AGateType::ACMul);
+
+        // Now that the gates are in order, we can assign wire ids to each node in the order they
+        // are seen
+        for gate_id in &sorted_gate_ids {
+            let gate = &self.gates[*gate_id];
+
+            for node_id in &[gate.lh_in, gate.rh_in, gate.out] {
+                if inline_constants && gate.op == AGateType::ACMul && *node_id == gate.rh_in {
+                    // Inlined constants only get a wire if another gate reads them
+                    continue;
+                }
+
+                if output_node_ids.contains(node_id) {
+                    // Output wires are excluded so that they can all be at the end
+                    continue;
+                }
+
+                if node_id_to_wire_id.contains_key(node_id) {
+                    continue;
+                }
+
+                node_id_to_wire_id.insert(*node_id, next_wire_id);
+                next_wire_id += 1;
+            }
+        }
+
+        // Assign wire ids to output nodes
+        for (_, _, node_id) in &output_nodes {
+            node_id_to_wire_id.insert(*node_id, next_wire_id);
+            next_wire_id += 1;
+        }
+
+        // Now we can create the new gates using topological order and the new wire ids
+        let mut new_gates = Vec::<Gate>::new();
+        for gate_id in sorted_gate_ids {
+            let gate = &self.gates[gate_id];
+            let lh_wire = node_id_to_wire_id[&gate.lh_in] as usize;
+            let rh_wire = || node_id_to_wire_id[&gate.rh_in] as usize;
+
+            let (inputs, op) = match gate.op {
+                AGateType::ACMul if inline_constants => {
+                    let constant = self.node_constant(gate.rh_in).ok_or_else(|| {
+                        CircuitError::Inconsistency {
+                            message: format!(
+                                "ACMul gate reads the non-constant node {}",
+                                gate.rh_in
+                            ),
+                        }
+                    })?;
+                    (vec![lh_wire], gate.op.bristol_op(Some(constant)))
+                }
+                // Lowered to a multiplication by the wire of the constant
+                AGateType::ACMul => (vec![lh_wire, rh_wire()], AGateType::AMul.to_string()),
+                op => (vec![lh_wire, rh_wire()], op.to_string()),
+            };
+
+            new_gates.push(Gate {
+                inputs,
+                outputs: vec![node_id_to_wire_id[&gate.out] as usize],
+                op,
+            });
+        }
+
+        let mut constants = HashMap::<String, ConstantInfo>::new();
+
+        for (name, (node_id, value)) in constant_to_node_id_and_value {
+            // Constants that no gate reads don't get a wire
+            if let Some(&wire_index) = node_id_to_wire_id.get(&node_id) {
+                constants.insert(
+                    name,
+                    ConstantInfo {
+                        value,
+                        wire_index: wire_index as usize,
+                    },
+                );
+            }
+        }
+
+        let circuit = BristolCircuit {
+            wire_count: next_wire_id as usize,
+            info: CircuitInfo {
+                input_name_to_wire_index: input_to_node_id
+                    .iter()
+                    .map(|(name, node_id)| (name.clone(), node_id_to_wire_id[node_id] as usize))
+                    .collect(),
+                constants,
+                output_name_to_wire_index: output_to_node_id
+                    .iter()
+                    .map(|(name, node_id)| (name.clone(), node_id_to_wire_id[node_id] as usize))
+                    .collect(),
+            },
+            gates: new_gates,
+            io_widths,
+        };
+
+        Ok((circuit, node_id_to_wire_id))
+    }
+
+    /// Returns a node id and increments the count.
+    fn get_node_id(&mut self) -> u32 {
+        self.node_count += 1;
+        self.node_count
+    }
+
+    /// Applies the rewrite rules to the gates until none of them matches, see `rewrite::apply_rules`.
+    /// The values of the circuit outputs are preserved, and nodes left unused are removed with
+    /// their signals.
+    pub fn rewrite(&mut self, rules: &[&dyn RewriteRule], max_iterations: usize) -> RewriteStats {
+        let protected = self
+            .nodes
+            .iter()
+            .filter(|(_, node)| {
+                node.signals
+                    .iter()
+                    .any(|signal_id| self.outputs.contains_key(signal_id))
+            })
+            .map(|(&node_id, _)| node_id)
+            .collect::<HashSet<u32>>();
+
+        let mut graph = GateGraph::new(std::mem::take(&mut self.gates), protected)
+            .with_next_node(self.node_count + 1);
+        let stats = apply_rules(&mut graph, rules, max_iterations);
+
+        let (gates, dead, added) = graph.into_parts();
+        self.gates = gates;
+        self.node_index = NodeIndex::default();
+        // Fresh nodes get an internal signal, like the intermediate values of the program
+        for (signal_id, node_id) in (self.next_signal_id()..).zip(added) {
+            let name = format!("{}rewrite_{}", INTERNAL_PREFIX, node_id);
+            self.signals.insert(signal_id, Signal::new(name, None));
+            self.nodes
+                .insert(node_id, Node::new_with_signal(signal_id, false, true));
+            self.node_count = self.node_count.max(node_id);
+        }
+        for node_id in dead {
+            if let Some(node) = self.nodes.remove(&node_id) {
+                for signal_id in node.signals {
+                    self.signals.remove(&signal_id);
+                }
+            }
+        }
+
+        stats
+    }
+
+    /// Removes the gates whose output doesn't reach any output of the circuit. Their nodes no
+    /// longer get a wire, unless they are inputs or read by another gate. Returns the number of
+    /// removed gates.
+    pub fn prune_dead_gates(&mut self) -> usize {
+        let node_to_gate = self
+            .gates
+            .iter()
+            .enumerate()
+            .map(|(gate_id, gate)| (gate.out, gate_id))
+            .collect::<HashMap<u32, usize>>();
+
+        // Walk back from the output nodes through the gates producing them
+        let mut stack = self
+            .nodes
+            .iter()
+            .filter(|(_, node)| {
+                node.get_signals()
+                    .iter()
+                    .any(|signal_id| self.outputs.contains_key(signal_id))
+            })
+            .map(|(&node_id, _)| node_id)
+            .collect::<Vec<u32>>();
+        let mut live = vec![false; self.gates.len()];
+        while let Some(node_id) = stack.pop() {
+            if let Some(&gate_id) = node_to_gate.get(&node_id) {
+                if !live[gate_id] {
+                    live[gate_id] = true;
+                    let gate = &self.gates[gate_id];
+                    stack.extend([gate.lh_in, gate.rh_in]);
+                }
+            }
+        }
+
+        let gate_count = self.gates.len();
+        let mut live = live.into_iter();
+        self.gates.retain(|_| live.next().unwrap_or(true));
+        self.node_index = NodeIndex::default();
+
+        gate_count - self.gates.len()
+    }
+
+    /// Evaluates the gates that only depend on constants, removing them and turning their outputs
+    /// into constants. Gates are evaluated in the field with field constants, and on words of the
+    /// value type otherwise, see `evaluate_gate`. Gates whose value is undefined, e.g. a division
+    /// by zero, are kept. Returns the number of removed gates.
+    pub fn fold_constants(&mut self) -> usize {
+        let node_to_gate = self
+            .gates
+            .iter()
+            .map(|gate| (gate.out, gate))
+            .collect::<HashMap<u32, &ArithmeticGate>>();
+
+        let mut values = HashMap::<u32, NodeValue>::new();
+        let mut folded = HashMap::new();
+        for gate in &self.gates {
+            if let NodeValue::Constant(Some(value)) =
+                self.node_value(gate.out, &node_to_gate, &mut values)
+            {
+                // The value is carried by the first signal of the node
+                if let Some(&signal_id) = self.nodes[&gate.out].get_signals().first() {
+                    folded.insert(gate.out, (signal_id, value));
+                }
+            }
+        }
+        if folded.is_empty() {
+            return 0;
+        }
+
+        let gate_count = self.gates.len();
+        self.gates.retain(|gate| !folded.contains_key(&gate.out));
+        for (node_id, (signal_id, value)) in folded {
+            self.nodes.get_mut(&node_id).unwrap().set_const(true);
+            self.signals.get_mut(&signal_id).unwrap().value = Some(value);
+        }
+        self.node_index = NodeIndex::default();
+
+        gate_count - self.gates.len()
+    }
+
+    /// Fuses the multiplications by a constant into `ACMul` gates, moving the constant to the
+    /// right input. Returns the number of fused gates.
+    pub fn fuse_constant_multiplications(&mut self) -> usize {
+        let fusable = self
+            .gates
+            .iter()
+            .enumerate()
+            .filter(|(_, gate)| gate.op == AGateType::AMul)
+            .filter_map(|(gate_id, gate)| {
+                match (
+                    self.node_constant(gate.lh_in),
+                    self.node_constant(gate.rh_in),
+                ) {
+                    (None, Some(_)) => Some((gate_id, false)),
+                    (Some(_), None) => Some((gate_id, true)),
+                    _ => None,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        for &(gate_id, swap) in &fusable {
+            let gate = &mut self.gates[gate_id];
+            if swap {
+                std::mem::swap(&mut gate.lh_in, &mut gate.rh_in);
+            }
+            gate.op = AGateType::ACMul;
+        }
+
+        fusable.len()
+    }
+
+    /// Reduces the divisions by a constant, which many MPC backends don't support, to cheaper
+    /// gates. Divisions by a power of two become right shifts, negative dividends of signed values
+    /// being rounded up first so the result still truncates toward zero. In
+    /// `DivisionReduction::Field` mode, `ADiv` gates also become multiplications by the inverse of
+    /// the divisor: modulo the prime with field constants, where `/` is the field division, and
+    /// modulo 2^32 for an odd divisor of words, only when the dividend is a constant it divides so
+    /// the result still matches the truncating division. `sfloat` divisions are kept. Returns the
+    /// number of reduced gates.
+    pub fn reduce_divisions(&mut self, mode: DivisionReduction) -> usize {
+        if self.value_type == ValueType::Sfloat {
+            return 0;
+        }
+
+        let signed = self.value_type.is_signed();
+        let divisions = self
+            .gates
+            .iter()
+            .enumerate()
+            .filter(|(_, gate)| matches!(gate.op, AGateType::ADiv | AGateType::AIntDiv))
+            .filter_map(|(gate_id, gate)| {
+                Some((gate_id, self.node_field_constant(gate.rh_in)?.clone()))
+            })
+            .collect::<Vec<_>>();
+
+        let (value_type, prime) = (self.value_type, self.prime);
+        let word = |value: u32| Some(FieldElement::from_word(value, value_type, prime));
+        let mut next_signal = self.next_signal_id();
+        let mut add_node =
+            |compiler: &mut Self, out: u32, suffix: &str, value: Option<FieldElement>| {
+                let name = format!("{}.{}", compiler.node_name(out), suffix);
+                let is_const = value.is_some();
+                compiler
+                    .signals
+                    .insert(next_signal, Signal::new(name, value));
+                let node_id = compiler.get_node_id();
+                let node = Node::new_with_signal(next_signal, is_const, !is_const);
+                compiler.nodes.insert(node_id, node);
+                next_signal += 1;
+                node_id
+            };
+
+        let mut reduced = 0;
+        for (gate_id, divisor) in divisions {
+            let (op, lh_in, out) = {
+                let gate = &self.gates[gate_id];
+                (gate.op, gate.lh_in, gate.out)
+            };
+            let field = mode == DivisionReduction::Field && op == AGateType::ADiv;
+            if field && self.field_constants {
+                let Some(inverse) = FieldElement::from(1).div(&divisor, prime) else {
+                    continue;
+                };
+                let inverse = add_node(self, out, "div_inverse", Some(inverse));
+                self.gates[gate_id] = ArithmeticGate::new(AGateType::AMul, lh_in, inverse, out);
+                reduced += 1;
+                continue;
+            }
+
+            let Some(divisor) = divisor.to_word(value_type, prime) else {
+                continue;
+            };
+            let positive = if signed {
+                divisor as i32 > 0
+            } else {
+                divisor > 0
+            };
+            // Whether the dividend is a constant the divisor divides
+            let divisible = self.node_constant(lh_in).is_some_and(|dividend| {
+                if signed {
+                    (dividend as i32).checked_rem(divisor as i32) == Some(0)
+                } else {
+                    dividend.checked_rem(divisor) == Some(0)
+                }
+            });
+
+            let (op, lh_in, rh_in) = if positive && divisor.is_power_of_two() {
+                let shift = divisor.trailing_zeros();
+                let shift_node = add_node(self, out, &format!("div_shift_{}", shift), word(shift));
+                if !signed {
+                    (AGateType::AShiftR, lh_in, shift_node)
+                } else if shift == 0 {
+                    (AGateType::AShiftRArith, lh_in, shift_node)
+                } else {
+                    // Adds `divisor - 1` to negative dividends: their sign spread over every bit,
+                    // masked to the bits shifted out
+                    let sign_shift = add_node(self, out, "div_shift_31", word(31));
+                    let mask = add_node(self, out, "div_mask", word(divisor - 1));
+                    let sign = add_node(self, out, "div_sign", None);
+                    let bias = add_node(self, out, "div_bias", None);
+                    let biased = add_node(self, out, "div_biased", None);
+                    self.gates.extend([
+                        ArithmeticGate::new(AGateType::AShiftRArith, lh_in, sign_shift, sign),
+                        ArithmeticGate::new(AGateType::ABitAnd, sign, mask, bias),
+                        ArithmeticGate::new(AGateType::AAdd, lh_in, bias, biased),
+                    ]);
+                    (AGateType::AShiftRArith, biased, shift_node)
+                }
+            } else if field && divisor % 2 == 1 && divisible {
+                let inverse = add_node(self, out, "div_inverse", word(inverse_mod_2_32(divisor)));
+                (AGateType::AMul, lh_in, inverse)
+            } else {
+                continue;
+            };
+
+            self.gates[gate_id] = ArithmeticGate::new(op, lh_in, rh_in, out);
+            reduced += 1;
+        }
+        self.node_index = NodeIndex::default();
+
+        reduced
+    }
+
+    /// Lowers the gates boolify doesn't support into gates it does, on integers of the given
+    /// width, see `lowering::lower_gate`. Returns the number of lowered gates.
+    pub fn lower_for_boolify(&mut self, width: usize) -> usize {
+        self.lower_gates(width, None)
+    }
+
+    /// Lowers the gates the target doesn't support into 32-bit gates it does, when all of them
+    /// are, e.g. powers by a constant into multiplications. The other gates are kept, for
+    /// `CheckTarget` to reject. Returns the number of lowered gates.
+    pub fn lower_for_target(&mut self) -> usize {
+        match self.target.supported_gates() {
+            Some(supported) => self.lower_gates(32, Some(supported)),
+            None => 0,
+        }
+    }
+
+    /// Lowers the gates of `LOWERED_GATES`, only those lowered into supported gates if `supported`
+    /// is set.
+    fn lower_gates(&mut self, width: usize, supported: Option<&[AGateType]>) -> usize {
+        let operand = |compiler: &Compiler, node_id| match compiler.node_constant(node_id) {
+            Some(value) => Operand::Constant(value),
+            None => Operand::Node(node_id),
+        };
+        let lowered = self
+            .gates
+            .iter()
+            .enumerate()
+            .filter(|(_, gate)| LOWERED_GATES.contains(&gate.op))
+            .filter(|(_, gate)| {
+                supported.is_none_or(|supported| {
+                    let (lhs, rhs) = (operand(self, gate.lh_in), operand(self, gate.rh_in));
+                    lowered_ops(gate.op, lhs, rhs, width)
+                        .iter()
+                        .all(|op| supported.contains(op))
+                })
+            })
+            .map(|(gate_id, _)| gate_id)
+            .collect::<Vec<usize>>();
+        if lowered.is_empty() {
+            return 0;
+        }
+
+        let next_signal = self.next_signal_id();
+        let mut sink = GraphSink {
+            compiler: self,
+            prefix: String::new(),
+            next_signal,
+            constants: HashMap::new(),
+            gates: Vec::new(),
+        };
+        for &gate_id in &lowered {
+            let (op, lh_in, rh_in, out) = {
+                let gate = &sink.compiler.gates[gate_id];
+                (gate.op, gate.lh_in, gate.rh_in, gate.out)
+            };
+            let (lhs, rhs) = (operand(sink.compiler, lh_in), operand(sink.compiler, rh_in));
+            sink.prefix = sink.compiler.node_name(out);
+
+            let result = lower_gate(&mut sink, op, lhs, rhs, width);
+            // The last gate writes to the output of the lowered gate, unless the result is
+            // carried by another node and copied
+            let last = sink.gates.last().map(|gate| gate.out);
+            let gate = match result {
+                Operand::Node(node_id) if last == Some(node_id) => {
+                    let gate = sink.gates.pop().unwrap();
+                    if let Some(node) = sink.compiler.nodes.remove(&node_id) {
+                        for signal_id in node.get_signals() {
+                            sink.compiler.signals.remove(signal_id);
+                        }
+                    }
+                    ArithmeticGate::new(gate.op, gate.lh_in, gate.rh_in, out)
+                }
+                result => {
+                    let lh_in = match result {
+                        Operand::Constant(value) => sink.constant(value),
+                        Operand::Node(node_id) => node_id,
+                    };
+                    ArithmeticGate::new(AGateType::AAdd, lh_in, sink.constant(0), out)
+                }
+            };
+            sink.compiler.gates[gate_id] = gate;
+            let gates = std::mem::take(&mut sink.gates);
+            sink.compiler.gates.extend(gates);
+        }
+        self.node_index = NodeIndex::default();
+
+        lowered.len()
+    }
+
+    /// Returns the constant inlined into a gate when the circuit is built, i.e. the constant of an
+    /// `ACMul` gate if the target supports them.
+    pub fn inlined_constant(&self, gate: &ArithmeticGate) -> Option<u32> {
+        if gate.op != AGateType::ACMul || !self.target.supports(AGateType::ACMul) {
+            return None;
+        }
+
+        self.node_constant(gate.rh_in)
+    }
+
+    /// Counts the wires the circuit is built with, without building it.
+    pub fn wire_counts(&self) -> WireCounts {
+        let mut wires = HashSet::new();
+        for gate in &self.gates {
+            wires.insert(gate.lh_in);
+            if self.inlined_constant(gate).is_none() {
+                wires.insert(gate.rh_in);
+            }
+            wires.insert(gate.out);
+        }
+
+        let mut counts = WireCounts::default();
+        for (node_id, node) in &self.nodes {
+            let signals = node.get_signals();
+            let is_input = signals.iter().any(|id| self.inputs.contains_key(id));
+            let is_output = signals.iter().any(|id| self.outputs.contains_key(id));
+            if is_input {
+                counts.inputs += 1;
+            }
+            if is_output {
+                counts.outputs += 1;
+            }
+            if is_input || is_output {
+                wires.insert(*node_id);
+            } else if node.is_const && wires.contains(node_id) {
+                counts.constants += 1;
+            }
+        }
+        counts.total = wires.len();
+
+        counts
+    }
+
+    /// Returns the value of a constant node as a word of the value type, `None` if it doesn't fit.
+    fn node_constant(&self, node_id: u32) -> Option<u32> {
+        self.node_field_constant(node_id)?
+            .to_word(self.value_type, self.prime)
+    }
+
+    /// Returns the value of a constant node.
+    fn node_field_constant(&self, node_id: u32) -> Option<&FieldElement> {
+        let node = self.nodes.get(&node_id)?;
+        if !node.is_const {
+            return None;
+        }
+
+        node.get_signals()
+            .iter()
+            .find_map(|signal_id| self.signals.get(signal_id)?.value.as_ref())
+    }
+
+    /// Returns the field element of a word of the value type.
+    fn word_element(&self, word: u32) -> FieldElement {
+        FieldElement::from_word(word, self.value_type, self.prime)
+    }
+
+    /// Evaluates a gate on constant inputs. Gates are evaluated in the field with field constants,
+    /// and on words of the value type for the Bristol word backends, `None` if an input doesn't
+    /// fit in a word.
+    fn evaluate_gate(
+        &self,
+        op: AGateType,
+        lhs: &FieldElement,
+        rhs: &FieldElement,
+    ) -> Option<FieldElement> {
+        if self.field_constants {
+            return op.evaluate_field(lhs, rhs, self.value_type, self.prime);
+        }
+
+        let lhs = lhs.to_word(self.value_type, self.prime)?;
+        let rhs = rhs.to_word(self.value_type, self.prime)?;
+        op.evaluate(lhs, rhs, self.value_type)
+            .map(|value| self.word_element(value))
+    }
+
+    /// Returns the number of gates using each node as an input.
+    pub fn node_fanout(&self) -> HashMap<u32, usize> {
+        let mut fanout = HashMap::<u32, usize>::new();
+        for gate in &self.gates {
+            *fanout.entry(gate.lh_in).or_default() += 1;
+            if gate.rh_in != gate.lh_in {
+                *fanout.entry(gate.rh_in).or_default() += 1;
+            }
+        }
+
+        fanout
+    }
+
+    /// Returns the lowest IO rank of the signals of the node, `usize::MAX` if it has no IO signal.
+    fn node_io_rank(&self, node_id: u32, ranks: &HashMap<u32, usize>) -> usize {
+        self.nodes[&node_id]
+            .get_signals()
+            .iter()
+            .filter_map(|signal_id| ranks.get(signal_id).copied())
+            .min()
+            .unwrap_or(usize::MAX)
+    }
+
+    /// Generates reports for the nodes with the highest fanout, used by more than one gate.
+    fn generate_fanout_reports(&self, count: usize) -> Vec<FanoutReport> {
+        let mut fanout = self
+            .node_fanout()
+            .into_iter()
+            .filter(|&(_, fanout)| fanout > 1)
+            .collect::<Vec<(u32, usize)>>();
+        fanout.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        fanout.truncate(count);
+
+        let nodes = fanout.iter().map(|&(id, _)| id).collect::<Vec<u32>>();
+        self.generate_signal_reports(&nodes)
+            .into_iter()
+            .zip(fanout)
+            .map(|(node, (_, fanout))| FanoutReport { node, fanout })
+            .collect()
+    }
+
+    /// Generates signal reports for a set of node IDs.
+    fn generate_signal_reports(&self, nodes: &[u32]) -> Vec<SignalReport> {
+        let naming = naming_strategy(self.naming);
+
+        nodes
+            .iter()
+            .map(|&id| {
+                let signals = self
+                    .nodes
+                    .get(&id)
+                    .expect("Node ID not found in node map")
+                    .get_signals();
+
+                let (names, value) = signals.iter().fold((Vec::new(), None), |mut acc, &sig_id| {
+                    let signal = self
+                        .signals
+                        .get(&sig_id)
+                        .expect("Signal ID not found in signal map");
+
+                    if !is_internal_name(&signal.name) {
+                        acc.0.push(naming.signal_name(&signal.name, sig_id));
+                    }
+                    if let Some(value) = &signal.value {
+                        acc.1 = Some(value.clone());
+                    }
+                    acc
+                });
+
+                SignalReport { id, names, value }
+            })
+            .collect()
+    }
+}
+
+/// Returns the party of an input, matching either its complete name or the name of its array.
+fn party_for_input(party_mapping: &HashMap<String, usize>, input_name: &str) -> Option<usize> {
+    if let Some(&party) = party_mapping.get(input_name) {
+        return Some(party);
+    }
+
+    // Find the closest enclosing array, e.g. `0.a[1]` before `0.a` for `0.a[1][0]`
+    let mut name = input_name;
+    while let Some(index) = name.rfind('[') {
+        name = &name[..index];
+        if let Some(&party) = party_mapping.get(name) {
+            return Some(party);
+        }
+    }
+
+    None
+}
+
+/// Value of a node when evaluated from constants only.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum NodeValue {
+    /// The node only depends on constants, the value is `None` if it is undefined.
+    Constant(Option<FieldElement>),
+    /// The node depends on inputs.
+    Variable,
+}
+
+/// Result of the evaluation of a signal cone.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConeEvaluation {
+    /// Value of the signal.
+    pub value: FieldElement,
+    /// Gate producing the signal, `None` for inputs and constants.
+    pub gate: Option<ConeGate>,
+}
+
+/// Gate producing an evaluated signal, with the values of its inputs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConeGate {
+    pub op: AGateType,
+    pub lh_in: FieldElement,
+    pub rh_in: FieldElement,
+}
+
+/// Statistics of the alias resolution.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AliasStats {
+    /// Number of aliases recorded.
+    pub aliases: usize,
+    /// Number of alias chains collapsed into a single node.
+    pub chains_collapsed: usize,
+}
+
+/// Statistics of the signal id compaction.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CompactionStats {
+    /// Number of signals in the circuit.
+    pub signals: usize,
+    /// Size of the signal id space before the compaction.
+    pub id_span: usize,
+}
+
+/// Wires of the circuit, counted by `Compiler::wire_counts`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WireCounts {
+    pub inputs: usize,
+    /// Output wires, outputs connected to the same node sharing a wire.
+    pub outputs: usize,
+    /// Constants read by a gate, the others don't get a wire.
+    pub constants: usize,
+    /// Every wire, including intermediate ones.
+    pub total: usize,
+}
+
+/// Largest wire count of a written circuit: wire ids are read back as `u32` by most backends.
+pub const MAX_WIRE_COUNT: usize = u32::MAX as usize;
+
+/// Size limits of the engine a circuit is written for, checked before writing it so that the
+/// circuit fails to compile instead of failing to load.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CircuitLimits {
+    /// Maximum number of wires, `MAX_WIRE_COUNT` if unset.
+    pub max_wires: Option<usize>,
+    /// Maximum number of gates, unlimited if unset.
+    pub max_gates: Option<usize>,
+}
+
+impl CircuitLimits {
+    /// Fails with `CircuitError::LimitExceeded` if the circuit exceeds a limit.
+    pub fn check(&self, circuit: &BristolCircuit) -> Result<(), CircuitError> {
+        let max_wires = self.max_wires.unwrap_or(MAX_WIRE_COUNT).min(MAX_WIRE_COUNT);
+        check_limit("wires", circuit.wire_count, max_wires)?;
+        if let Some(max_gates) = self.max_gates {
+            check_limit("gates", circuit.gates.len(), max_gates)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Fails with `CircuitError::LimitExceeded` if the count is above the limit.
+fn check_limit(what: &str, count: usize, limit: usize) -> Result<(), CircuitError> {
+    if count > limit {
+        return Err(CircuitError::LimitExceeded {
+            what: what.to_string(),
+            count,
+            limit,
+        });
+    }
+
+    Ok(())
+}
+
+/// Converts a count to the `u32` of a binary format, failing with `CircuitError::LimitExceeded`
+/// instead of truncating it.
+pub fn checked_u32(what: &str, count: usize) -> Result<u32, CircuitError> {
+    check_limit(what, count, u32::MAX as usize)?;
+    Ok(count as u32)
+}
+
+/// Adds IO signals to the map, appending the new ones to the order sorted by id.
+fn extend_io(io: &mut HashMap<u32, String>, order: &mut Vec<u32>, signals: HashMap<u32, String>) {
+    let mut ids = signals
+        .keys()
+        .filter(|id| !io.contains_key(id))
+        .copied()
+        .collect::<Vec<u32>>();
+    ids.sort_unstable();
+    order.extend(ids);
+    io.extend(signals);
+}
+
+/// Returns the IO signals of an item, the signal itself or the elements of an array, in IO order.
+fn item_io_signals(io: &HashMap<u32, String>, order: &[u32], name: &str) -> Vec<u32> {
+    let array_prefix = format!("{}[", name);
+    order
+        .iter()
+        .filter(|id| {
+            io.get(id)
+                .is_some_and(|io_name| io_name == name || io_name.starts_with(&array_prefix))
+        })
+        .copied()
+        .collect()
+}
+
+/// Maps each IO signal to its position in the order.
+fn io_ranks(order: &[u32]) -> HashMap<u32, usize> {
+    order
+        .iter()
+        .enumerate()
+        .map(|(rank, &id)| (id, rank))
+        .collect()
+}
+
+/// Returns the name of a signal, empty if it isn't declared.
+fn signal_name(signals: &HashMap<u32, Signal>, id: u32) -> &str {
+    signals.get(&id).map_or("", |signal| signal.name.as_str())
+}
+
+/// Returns the inverse of an odd number modulo 2^32, by Newton's iteration: `x * d = 1` modulo
+/// `2^n` gives `x * (2 - x * d) * d = 1` modulo `2^2n`, and `d * d = 1` modulo 8.
+fn inverse_mod_2_32(divisor: u32) -> u32 {
+    let mut inverse = divisor;
+    for _ in 0..4 {
+        inverse = inverse.wrapping_mul(2u32.wrapping_sub(divisor.wrapping_mul(inverse)));
+    }
+
+    inverse
+}
+
+/// Adds the nodes and gates of a lowered gate to the graph, the nodes named after the output of the
+/// lowered gate. Constant nodes are shared by the lowered gates.
+struct GraphSink<'a> {
+    compiler: &'a mut Compiler,
+    prefix: String,
+    next_signal: u32,
+    constants: HashMap<u32, u32>,
+    gates: Vec<ArithmeticGate>,
+}
+
+impl GraphSink<'_> {
+    fn add_node(&mut self, name: String, value: Option<u32>) -> u32 {
+        let element = value.map(|value| self.compiler.word_element(value));
+        self.compiler
+            .signals
+            .insert(self.next_signal, Signal::new(name, element));
+        let node_id = self.compiler.get_node_id();
+        let node = Node::new_with_signal(self.next_signal, value.is_some(), value.is_none());
+        self.compiler.nodes.insert(node_id, node);
+        self.next_signal += 1;
+        node_id
+    }
+}
+
+impl GateSink for GraphSink<'_> {
+    fn constant(&mut self, value: u32) -> u32 {
+        if let Some(&node_id) = self.constants.get(&value) {
+            return node_id;
+        }
+
+        let node_id = self.add_node(
+            format!("{}.{}lower_const_{}", self.prefix, INTERNAL_PREFIX, value),
+            Some(value),
+        );
+        self.constants.insert(value, node_id);
+        node_id
+    }
+
+    fn gate(&mut self, op: AGateType, lh_in: u32, rh_in: u32) -> u32 {
+        let out = self.add_node(
+            format!(
+                "{}.{}lower_{}",
+                self.prefix,
+                INTERNAL_PREFIX,
+                self.gates.len()
+            ),
+            None,
+        );
+        self.gates.push(ArithmeticGate::new(op, lh_in, rh_in, out));
+        out
+    }
+}
+
+/// Lookups from the signals to the nodes containing them, and from the nodes to the gates using
+/// them, so connecting signals doesn't scan the whole graph. Nodes are merged into new nodes, so
+/// the node a signal was added to is followed through `merged` to the node containing it now.
+/// The index is built on first use and kept up to date by `add_signal`, `add_gate` and the node
+/// merges; other changes to the graph drop it, to be rebuilt.
+#[derive(Clone, Debug, Default)]
+struct NodeIndex {
+    built: bool,
+    signal_nodes: HashMap<u32, u32>,
+    merged: UnionFind,
+    gate_uses: HashMap<u32, Vec<usize>>,
+}
+
+impl NodeIndex {
+    fn build(nodes: &HashMap<u32, Node>, gates: &[ArithmeticGate]) -> Self {
+        let mut index = NodeIndex {
+            built: true,
+            ..Default::default()
+        };
+        for (&node_id, node) in nodes {
+            for &signal_id in node.get_signals() {
+                index.signal_nodes.insert(signal_id, node_id);
+            }
+        }
+        for (gate_id, gate) in gates.iter().enumerate() {
+            index.add_gate_uses(gate_id, gate);
+        }
+
+        index
+    }
+
+    /// Records the nodes used by a gate, once per node.
+    fn add_gate_uses(&mut self, gate_id: usize, gate: &ArithmeticGate) {
+        if !self.built {
+            return;
+        }
+
+        for (i, node_id) in [gate.lh_in, gate.rh_in, gate.out].into_iter().enumerate() {
+            if ![gate.lh_in, gate.rh_in][..i].contains(&node_id) {
+                self.gate_uses.entry(node_id).or_default().push(gate_id);
+            }
+        }
+    }
+}
+
+/// Number of nodes listed in the high fanout section of the circuit report.
+const HIGH_FANOUT_REPORT_SIZE: usize = 10;
+
+/// The full circuit report, containing input and output signals information.
+/// Written to `report.json`, and read back with `CircuitReport::from_path`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CircuitReport {
+    inputs: Vec<SignalReport>,
+    outputs: Vec<SignalReport>,
+    /// Inputs removed because no gate reads them.
+    #[serde(default)]
+    pruned_inputs: Vec<String>,
+    high_fanout: Vec<FanoutReport>,
+    value_type: ValueType,
+    #[serde(default)]
+    prime: Prime,
+    #[serde(default)]
+    target: Target,
+    #[serde(default)]
+    gate_order: GateOrder,
+    /// Range of the values known at compile time.
+    #[serde(default)]
+    constant_range: Option<ConstantRange>,
+    /// Number of gates by operation, e.g. `AMul`.
+    #[serde(default)]
+    gate_counts: BTreeMap<String, usize>,
+    /// Number of non-linear gates on the path with the most of them.
+    #[serde(default)]
+    multiplicative_depth: usize,
+    /// Number of constant wires.
+    #[serde(default)]
+    constant_count: usize,
+    #[serde(default)]
+    wire_count: usize,
+    /// Metadata given with `--meta`.
+    #[serde(default)]
+    metadata: BTreeMap<String, String>,
+    /// Template instances with their evaluated parameters.
+    #[serde(default)]
+    instances: Vec<InstanceReport>,
+}
+
+impl CircuitReport {
+    /// Reads a report written to a JSON file, e.g. `report.json`.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, CircuitError> {
+        let file = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    /// Returns the input nodes, in the order their wires are assigned.
+    pub fn inputs(&self) -> &[SignalReport] {
+        &self.inputs
+    }
+
+    /// Returns the output nodes, in the order their wires are assigned.
+    pub fn outputs(&self) -> &[SignalReport] {
+        &self.outputs
+    }
+
+    /// Returns the names of the inputs removed because no gate reads them.
+    pub fn pruned_inputs(&self) -> &[String] {
+        &self.pruned_inputs
+    }
+
+    /// Returns the nodes used by the most gates, in decreasing order of fanout.
+    pub fn high_fanout(&self) -> &[FanoutReport] {
+        &self.high_fanout
+    }
+
+    pub fn value_type(&self) -> ValueType {
+        self.value_type
+    }
+
+    pub fn prime(&self) -> Prime {
+        self.prime
+    }
+
+    pub fn target(&self) -> Target {
+        self.target
+    }
+
+    pub fn gate_order(&self) -> GateOrder {
+        self.gate_order
+    }
+
+    /// Returns the range of the values known at compile time, `None` if there are none.
+    pub fn constant_range(&self) -> Option<&ConstantRange> {
+        self.constant_range.as_ref()
+    }
+
+    /// Returns the number of gates by operation, e.g. `AMul`.
+    pub fn gate_counts(&self) -> &BTreeMap<String, usize> {
+        &self.gate_counts
+    }
+
+    /// Returns the number of non-linear gates on the path with the most of them.
+    pub fn multiplicative_depth(&self) -> usize {
+        self.multiplicative_depth
+    }
+
+    /// Adds gates emitted outside of the compiler to the counts, e.g. conversion pseudo-gates.
+    pub(crate) fn add_gate_counts(&mut self, counts: BTreeMap<String, usize>) {
+        for (op, count) in counts {
+            *self.gate_counts.entry(op).or_default() += count;
+        }
+    }
+
+    /// Returns the template instances with their evaluated parameters, each before the instances
+    /// it creates.
+    pub fn instances(&self) -> &[InstanceReport] {
+        &self.instances
+    }
+
+    /// Returns the metadata given with `--meta`, by key.
+    pub fn metadata(&self) -> &BTreeMap<String, String> {
+        &self.metadata
+    }
+
+    /// Records metadata given by the user, e.g. the version of the model.
+    pub(crate) fn set_metadata(&mut self, metadata: BTreeMap<String, String>) {
+        self.metadata = metadata;
+    }
+
+    /// Returns the number of constant wires.
+    pub fn constant_count(&self) -> usize {
+        self.constant_count
+    }
+
+    pub fn wire_count(&self) -> usize {
+        self.wire_count
+    }
+}
+
+/// A template instance with the values its parameters were evaluated to, e.g.
+/// `Dense(nInputs=2, nOutputs=10)`, to check that a generated program is parameterized as
+/// intended.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstanceReport {
+    /// Name of the instance context, e.g. `dense[0]` with `--instance-naming index`.
+    pub instance: String,
+    pub template: String,
+    /// Component the instance is assigned to, e.g. `dense[0]`, if known.
+    pub component: Option<String>,
+    /// Parameters with their values, in declaration order.
+    pub params: Vec<TemplateParam>,
+    /// Whether the instance is annotated for parallel witness generation, e.g.
+    /// `c = parallel Foo()` or `template parallel Foo()`. The annotation doesn't change the
+    /// circuit.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub parallel: bool,
+}
+
+/// A template parameter and its evaluated value, signed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TemplateParam {
+    pub name: String,
+    pub value: String,
+}
+
+impl fmt::Display for InstanceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let params = self
+            .params
+            .iter()
+            .map(|param| format!("{}={}", param.name, param.value))
+            .collect::<Vec<String>>();
+        write!(f, "{}({})", self.template, params.join(", "))
+    }
+}
+
+/// A single node report, with a list of signal names and an optional value.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignalReport {
+    id: u32,
+    names: Vec<String>,
+    value: Option<FieldElement>,
+}
+
+impl SignalReport {
+    /// Returns the id of the node.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Returns the names of the signals of the node.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Returns the value of the node, if it is a constant.
+    pub fn value(&self) -> Option<&FieldElement> {
+        self.value.as_ref()
+    }
+}
+
+/// A node report along with the number of gates using the node as an input.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FanoutReport {
+    #[serde(flatten)]
+    node: SignalReport,
+    fanout: usize,
+}
+
+impl FanoutReport {
+    /// Returns the report of the node.
+    pub fn node(&self) -> &SignalReport {
+        &self.node
+    }
+
+    /// Returns the number of gate inputs reading the node.
+    pub fn fanout(&self) -> usize {
+        self.fanout
+    }
+}
+
+/// Largest value known at compile time, and the word size needed to represent all of them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConstantRange {
+    max_magnitude: FieldElement,
+    width: u32,
+}
+
+impl ConstantRange {
+    /// Returns the range of a single value, in two's complement for signed value types.
+    pub fn of(value: &FieldElement, value_type: ValueType, prime: Prime) -> Self {
+        let (magnitude, negative) = value.to_integer(value_type.is_signed(), prime);
+        // One more bit than the magnitude for the sign, e.g. 4 bits for 7 and -8
+        let width = match (value_type.is_signed(), negative) {
+            (false, _) => magnitude.bits().max(1),
+            (true, false) => magnitude.bits() + 1,
+            (true, true) => (magnitude.clone() - 1u32).bits() + 1,
+        };
+        Self {
+            max_magnitude: FieldElement::from_integer(&magnitude, false, prime),
+            width: width as u32,
+        }
+    }
+
+    /// Returns the smallest range covering both ranges.
+    pub fn union(self, other: Self) -> Self {
+        let max_magnitude =
+            if other.max_magnitude.representative() > self.max_magnitude.representative() {
+                other.max_magnitude
+            } else {
+                self.max_magnitude
+            };
+        Self {
+            max_magnitude,
+            width: self.width.max(other.width),
+        }
+    }
+
+    /// Returns the largest magnitude, i.e. absolute value for signed value types.
+    pub fn max_magnitude(&self) -> &FieldElement {
+        &self.max_magnitude
+    }
+
+    /// Returns the number of bits needed to represent every value, the minimal safe
+    /// `--boolify-width`.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum CircuitError {
+    #[error("Cannot merge constant nodes: {signals}")]
+    CannotMergeConstantNodes { signals: String },
+    #[error("Cannot merge output nodes: {signals}")]
+    CannotMergeOutputNodes { signals: String },
+    #[error("Constant value already set for variable")]
+    ConstantValueAlreadySet,
+    #[error("Signal is not connected to any node")]
+    DisconnectedSignal,
+    #[error(transparent)]
+    IOError(#[from] std::io::Error),
+    #[error(transparent)]
+    ParseIntError(#[from] std::num::ParseIntError),
+    #[error(transparent)]
+    JsonError(#[from] serde_json::Error),
+    #[error("Signal already declared")]
+    SignalAlreadyDeclared,
+    #[error("Unknown signal {0}")]
+    UnknownSignal(String),
+    #[error("Missing value for input {0}")]
+    MissingInputValue(String),
+    #[error("Undefined value for {0}")]
+    UndefinedValue(String),
+    #[error("Value {value} is out of range for {target}")]
+    ValueOutOfRange { value: String, target: String },
+    #[error("Input {0} isn't assigned to any party")]
+    UnmappedInput(String),
+    #[error("Signal id collision: {0}")]
+    SignalIdCollision(u32),
+    #[error("unsupported gate type: {0}")]
+    UnsupportedGateType(String),
+    #[error("Unprocessed node")]
+    UnprocessedNode,
+    #[error("Cyclic dependency: {message}")]
+    CyclicDependency { message: String },
+    #[error("Inconsistency: {message}")]
+    Inconsistency { message: String },
+    #[error("Parsing error: {message}")]
+    ParsingError { message: String },
+    #[error("Circuit has {count} {what}, more than the limit of {limit}")]
+    LimitExceeded {
+        what: String,
+        count: usize,
+        limit: usize,
+    },
+}
+
+impl From<CircuitError> for ProgramError {
+    fn from(e: CircuitError) -> Self {
+        ProgramError::CircuitError(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        evaluate::evaluate_circuit,
+        field::modulus,
+        rewrite::{BalanceAdditions, FactorCommonOperand},
+    };
+    use circom_circom_algebra::num_bigint::BigUint;
+    use sha2::{Digest, Sha256};
+
+    /// Evaluates the outputs of the circuit on words of its value type.
+    fn word_outputs(compiler: &Compiler, inputs: &HashMap<String, u32>) -> HashMap<String, u32> {
+        let inputs = inputs
+            .iter()
+            .map(|(name, &value)| (name.clone(), compiler.word_element(value)))
+            .collect();
+        compiler
+            .evaluate_outputs(&inputs)
+            .unwrap()
+            .into_iter()
+            .map(|(name, value)| {
+                let word = value.to_word(compiler.value_type, compiler.prime).unwrap();
+                (name, word)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_node_with_signal() {
+        let node = Node::new_with_signal(1, true, false);
+        assert_eq!(node.signals.len(), 1);
+        assert_eq!(node.signals[0], 1);
+        assert!(node.is_const);
+        assert!(!node.is_out);
+    }
+
+    #[test]
+    fn test_node_add_signal() {
+        let mut node = Node::new();
+        node.add_signals(&vec![1, 2, 3]);
+        assert_eq!(node.signals.len(), 3);
+        assert!(node.contains_signal(&1));
+        assert!(node.contains_signal(&2));
+        assert!(node.contains_signal(&3));
+    }
+
+    #[test]
+    fn test_node_contains_signal() {
+        let node = Node::new_with_signal(1, true, false);
+        assert!(node.contains_signal(&1));
+        assert!(!node.contains_signal(&2));
+    }
+
+    #[test]
+    fn test_node_set_output() {
+        let mut node = Node::new();
+        node.set_output(true);
+        assert!(node.is_out);
+    }
+
+    #[test]
+    fn test_node_set_const() {
+        let mut node = Node::new();
+        node.set_const(true);
+        assert!(node.is_const);
+    }
+
+    #[test]
+    fn test_compiler_add_inputs() {
+        let mut compiler = Compiler::new();
+        let mut inputs = HashMap::new();
+        inputs.insert(1, String::from("input1"));
+        inputs.insert(2, String::from("input2"));
+        compiler.add_inputs(inputs);
+
+        assert_eq!(compiler.inputs.len(), 2);
+        assert_eq!(compiler.inputs[&1], "input1");
+        assert_eq!(compiler.inputs[&2], "input2");
+    }
+
+    #[test]
+    fn test_compiler_add_outputs() {
+        let mut compiler = Compiler::new();
+        let mut outputs = HashMap::new();
+        outputs.insert(3, String::from("output1"));
+        outputs.insert(4, String::from("output2"));
+        compiler.add_outputs(outputs);
+
+        assert_eq!(compiler.outputs.len(), 2);
+        assert_eq!(compiler.outputs[&3], "output1");
+        assert_eq!(compiler.outputs[&4], "output2");
+    }
+
+    #[test]
+    fn test_compiler_add_signal() {
+        let mut compiler = Compiler::new();
+        let result = compiler.add_signal(1, String::from("signal1"), None);
+
+        assert!(result.is_ok());
+        assert_eq!(compiler.signals.len(), 1);
+        assert!(compiler.signals.contains_key(&1));
+        assert_eq!(compiler.signals[&1].name, "signal1");
+    }
+
+    #[test]
+    fn test_compiler_add_duplicated_signal() {
+        let mut compiler = Compiler::new();
+        compiler
+            .add_signal(1, String::from("signal1"), None)
+            .unwrap();
+        let result = compiler.add_signal(1, String::from("signal1"), None);
+
+        assert!(matches!(result, Err(CircuitError::SignalAlreadyDeclared)));
+    }
+
+    #[test]
+    fn test_compiler_get_signals() {
+        let mut compiler = Compiler::new();
+        compiler
+            .add_signal(1, String::from("signal1"), None)
+            .unwrap();
+        compiler
+            .add_signal(2, String::from("filter_signal"), None)
+            .unwrap();
+        let filtered_signals = compiler.get_signals(String::from("filter"));
+
+        assert_eq!(filtered_signals.len(), 1);
+        assert_eq!(filtered_signals[&2], "filter_signal");
+    }
+
+    #[test]
+    fn test_compiler_get_item_signals() {
+        let mut compiler = Compiler::new();
+        compiler.add_signal(1, String::from("0.c"), None).unwrap();
+        compiler
+            .add_signal(2, String::from("0.complementC"), None)
+            .unwrap();
+        compiler
+            .add_signal(3, String::from("0.in[0]"), None)
+            .unwrap();
+        compiler
+            .add_signal(4, String::from("0.in[1]"), None)
+            .unwrap();
+        compiler
+            .add_signal(5, String::from("0.index"), None)
+            .unwrap();
+
+        assert_eq!(
+            compiler.get_item_signals("0.c"),
+            HashMap::from([(1, String::from("0.c"))])
+        );
+        assert_eq!(
+            compiler.get_item_signals("0.in"),
+            HashMap::from([(3, String::from("0.in[0]")), (4, String::from("0.in[1]"))])
+        );
+    }
+
+    #[test]
+    fn test_compiler_add_gate() {
+        let mut compiler = Compiler::new();
+        compiler
+            .add_signal(1, String::from("signal1"), None)
+            .unwrap();
+        compiler
+            .add_signal(2, String::from("signal2"), None)
+            .unwrap();
+        compiler
+            .add_signal(3, String::from("signal3"), None)
+            .unwrap();
+
+        let result = compiler.add_gate(AGateType::AAdd, 1, 2, 3);
+
+        assert!(result.is_ok());
+        assert_eq!(compiler.gates.len(), 1);
+        let gate = &compiler.gates[0];
+        assert_eq!(gate.op, AGateType::AAdd);
+        assert_eq!(gate.lh_in, 1);
+        assert_eq!(gate.rh_in, 2);
+        assert_eq!(gate.out, 3);
+    }
+
+    #[test]
+    fn test_compiler_add_connection() {
+        let mut compiler = Compiler::new();
+        compiler
+            .add_signal(1, String::from("signal1"), None)
+            .unwrap();
+        compiler
+            .add_signal(2, String::from("signal2"), None)
+            .unwrap();
+        compiler
+            .add_signal(3, String::from("signal3"), None)
+            .unwrap();
+
+        // Adding connection between signals 1 and 2
+        let result = compiler.add_connection(1, 2);
+
+        assert!(result.is_ok());
+        assert_eq!(compiler.nodes.len(), 2);
+
+        // Assert new node contains both signals
+        let node = compiler.nodes.get(&4).unwrap();
+        assert_eq!(node.signals.len(), 2);
+        assert!(node.contains_signal(&1));
+        assert!(node.contains_signal(&2));
+    }
+
+    #[test]
+    fn test_compiler_add_connection_same_node() {
+        let mut compiler = Compiler::new();
+        compiler
+            .add_signal(1, String::from("signal1"), None)
+            .unwrap();
+        compiler
+            .add_signal(2, String::from("signal2"), None)
+            .unwrap();
+
+        compiler.add_connection(1, 2).unwrap();
+        // Connect the same node
+        let result = compiler.add_connection(1, 2);
+
+        assert!(result.is_ok());
+        // No change in number of nodes
+        assert_eq!(compiler.nodes.len(), 1);
+    }
+
+    #[test]
+    fn test_compiler_add_connection_output_nodes() {
+        let mut compiler = Compiler::new();
+        compiler
+            .add_signal(1, String::from("signal1"), None)
+            .unwrap();
+        compiler
+            .add_signal(2, String::from("signal2"), None)
+            .unwrap();
+
+        // Set both nodes as output nodes
+        compiler.nodes.get_mut(&1).unwrap().set_output(true);
+        compiler.nodes.get_mut(&2).unwrap().set_output(true);
+
+        let result = compiler.add_connection(1, 2);
+
+        assert!(matches!(
+            result,
+            Err(CircuitError::CannotMergeOutputNodes { .. })
+        ));
+    }
+
+    #[test]
+    fn test_compiler_add_connection_constant_nodes() {
+        let mut compiler = Compiler::new();
+        compiler
+            .add_signal(1, String::from("signal1"), Some(FieldElement::from(1)))
+            .unwrap();
+        compiler
+            .add_signal(2, String::from("signal2"), Some(FieldElement::from(2)))
+            .unwrap();
+
+        let result = compiler.add_connection(1, 2);
+        assert!(matches!(
+            result,
+            Err(CircuitError::CannotMergeConstantNodes { .. })
+        ));
+    }
+
+    #[test]
+    fn test_compiler_add_connections() {
+        let mut compiler = Compiler::new();
+        for id in 1..=5 {
+            compiler
+                .add_signal(id, format!("signal{}", id), None)
+                .unwrap();
+        }
+        compiler.add_gate(AGateType::AAdd, 1, 2, 3).unwrap();
+
+        let result = compiler.add_connections(&[(1, 4), (4, 5), (3, 5)]);
+
+        assert!(result.is_ok());
+        assert_eq!(compiler.nodes.len(), 2);
+
+        // Every gate reference is updated to the final merged node
+        let gate = &compiler.gates[0];
+        assert_eq!(gate.lh_in, gate.out);
+        let node = compiler.nodes.get(&gate.out).unwrap();
+        assert!(node.is_out);
+        for id in [1, 3, 4, 5] {
+            assert!(node.contains_signal(&id));
+        }
+        assert!(compiler.nodes.get(&gate.rh_in).unwrap().contains_signal(&2));
+    }
+
+    #[test]
+    fn test_compiler_add_connections_matches_add_connection() {
+        let mut batched = Compiler::new();
+        let mut sequential = Compiler::new();
+        for compiler in [&mut batched, &mut sequential] {
+            for id in 1..=6 {
+                compiler
+                    .add_signal(id, format!("signal{}", id), None)
+                    .unwrap();
+            }
+            compiler.add_gate(AGateType::AMul, 1, 2, 3).unwrap();
+        }
+
+        let connections = [(3, 4), (5, 6), (4, 6), (1, 1)];
+        batched.add_connections(&connections).unwrap();
+        for (a, b) in connections {
+            sequential.add_connection(a, b).unwrap();
+        }
+
+        assert_eq!(batched.gates, sequential.gates);
+        assert_eq!(batched.nodes.len(), sequential.nodes.len());
+        for (id, node) in &sequential.nodes {
+            let mut expected = node.signals.clone();
+            let mut actual = batched.nodes[id].signals.clone();
+            expected.sort_unstable();
+            actual.sort_unstable();
+            assert_eq!(actual, expected);
+        }
+    }
+
+    #[test]
+    fn test_compiler_add_connections_errors() {
+        let mut compiler = Compiler::new();
+        compiler
+            .add_signal(1, String::from("signal1"), Some(FieldElement::from(1)))
+            .unwrap();
+        compiler
+            .add_signal(2, String::from("signal2"), Some(FieldElement::from(2)))
+            .unwrap();
+
+        let result = compiler.add_connections(&[(1, 3)]);
+        assert!(matches!(result, Err(CircuitError::DisconnectedSignal)));
+
+        let result = compiler.add_connections(&[(1, 2)]);
+        assert!(matches!(
+            result,
+            Err(CircuitError::CannotMergeConstantNodes { .. })
+        ));
+    }
+
+    #[test]
+    fn test_compiler_node_index() {
+        let mut compiler = Compiler::new();
+        for id in 1..=6 {
+            compiler
+                .add_signal(id, format!("signal{}", id), None)
+                .unwrap();
+        }
+        compiler.add_gate(AGateType::AAdd, 1, 2, 5).unwrap();
+        compiler.add_connection(1, 3).unwrap();
+
+        // Aliases of signals in merged nodes are resolved through the merges
+        compiler.add_aliases(&[(3, 4), (2, 4)]);
+        compiler.resolve_aliases().unwrap();
+        let gate = &compiler.gates[0];
+        assert_eq!(gate.lh_in, gate.rh_in);
+        for id in 1..=4 {
+            assert!(compiler.nodes[&gate.lh_in].contains_signal(&id));
+        }
+
+        // The index is rebuilt once signals are renumbered
+        compiler
+            .remap_signals(&HashMap::from([(6, 16), (5, 15)]))
+            .unwrap();
+        compiler.add_connection(15, 16).unwrap();
+        let gate = &compiler.gates[0];
+        assert!(compiler.nodes[&gate.out].contains_signal(&16));
+        assert_eq!(compiler.nodes.len(), 2);
+    }
+
+    #[test]
+    fn test_compiler_signal_map() {
+        let mut compiler = Compiler::new();
+        compiler.enter_template("Main");
+        compiler.add_signal(1, String::from("0.a"), None).unwrap();
+        compiler.add_signal(2, String::from("0.b"), None).unwrap();
+        compiler.add_signal(3, String::from("0.out"), None).unwrap();
+        compiler.enter_template("Mul");
+        compiler.add_signal(4, String::from("mul.a"), None).unwrap();
+        compiler
+            .add_signal(5, String::from("mul.%random_7"), None)
+            .unwrap();
+        compiler.exit_template();
+        compiler
+            .add_signal(6, String::from("0.random_seed"), None)
+            .unwrap();
+        compiler.add_gate(AGateType::AMul, 1, 2, 5).unwrap();
+        compiler.add_connection(1, 4).unwrap();
+        compiler.add_connection(5, 3).unwrap();
+        compiler.add_connection(2, 6).unwrap();
+        compiler.add_inputs(HashMap::from([
+            (1, String::from("0.a")),
+            (2, String::from("0.b")),
+        ]));
+        compiler.add_outputs(HashMap::from([(3, String::from("0.out"))]));
+
+        let (circuit, signal_map) = compiler.build_circuit_with_signal_map().unwrap();
+        let wire = circuit.info.input_name_to_wire_index["0.a"];
+        assert_eq!(signal_map.wire_names(wire), vec!["0.a", "mul.a"]);
+        assert_eq!(signal_map.entries.len(), 5);
+        assert!(signal_map
+            .entries
+            .iter()
+            .any(|entry| entry.name == "mul.a" && entry.template == "Main/Mul"));
+
+        // Intermediate signals are left out
+        let wire = circuit.info.output_name_to_wire_index["0.out"];
+        assert_eq!(signal_map.wire_names(wire), vec!["0.out"]);
+
+        // Only generated names are intermediate, not user names that look alike
+        let wire = circuit.info.input_name_to_wire_index["0.b"];
+        assert_eq!(signal_map.wire_names(wire), vec!["0.b", "0.random_seed"]);
+    }
+
+    #[test]
+    fn test_compiler_generate_input_template() {
+        let mut compiler = Compiler::new();
+        compiler.add_signal(1, String::from("0.a"), None).unwrap();
+        compiler
+            .add_signal(2, String::from("0.b[0]"), None)
+            .unwrap();
+        compiler
+            .add_signal(3, String::from("0.b[1]"), None)
+            .unwrap();
+        compiler.add_signal(4, String::from("0.out"), None).unwrap();
+        compiler.add_signal(5, String::from("mul.a"), None).unwrap();
+        compiler.add_gate(AGateType::AMul, 1, 2, 4).unwrap();
+        compiler.add_connection(1, 5).unwrap();
+        compiler.add_inputs(HashMap::from([
+            (1, String::from("0.a")),
+            (2, String::from("0.b[0]")),
+            (3, String::from("0.b[1]")),
+        ]));
+        compiler.add_outputs(HashMap::from([(4, String::from("0.out"))]));
+        compiler.prune_inputs(&[String::from("0.b[1]")]);
+
+        // Only the names of the inputs are listed, not the signals connected to them
+        assert_eq!(
+            compiler.generate_input_template().unwrap(),
+            BTreeMap::from([(String::from("0.a"), None), (String::from("0.b[0]"), None)])
+        );
+    }
+
+    #[test]
+    fn test_compiler_wire_tags() {
+        let mut compiler = Compiler::new();
+        compiler.add_signal(1, String::from("0.a"), None).unwrap();
+        compiler.add_signal(2, String::from("0.b"), None).unwrap();
+        compiler.add_signal(3, String::from("0.out"), None).unwrap();
+        compiler
+            .add_signal(4, String::from("not.in"), None)
+            .unwrap();
+        compiler
+            .set_signal_tags(1, vec![String::from("binary")])
+            .unwrap();
+        compiler
+            .set_signal_tags(4, vec![String::from("binary"), String::from("maxbit")])
+            .unwrap();
+        compiler.add_gate(AGateType::AMul, 1, 2, 3).unwrap();
+        compiler.add_connection(1, 4).unwrap();
+        compiler.add_inputs(HashMap::from([
+            (1, String::from("0.a")),
+            (2, String::from("0.b")),
+        ]));
+        compiler.add_outputs(HashMap::from([(3, String::from("0.out"))]));
+        assert!(matches!(
+            compiler.set_signal_tags(5, Vec::new()),
+            Err(CircuitError::UnknownSignal(_))
+        ));
+
+        // The tags of the signals a wire carries are merged
+        let (circuit, node_id_to_wire_id) = compiler.build_circuit_wires().unwrap();
+        let wire_tags = compiler.wire_tags(&node_id_to_wire_id);
+        let wire = circuit.info.input_name_to_wire_index["0.a"];
+        assert_eq!(
+            wire_tags,
+            BTreeMap::from([(wire, vec![String::from("binary"), String::from("maxbit")])])
+        );
+    }
+
+    #[test]
+    fn test_compiler_build_circuit_naming() {
+        let mut compiler = Compiler::new();
+        compiler.add_signal(1, String::from("0.a"), None).unwrap();
+        compiler
+            .add_signal(
+                2,
+                String::from("0.const_signal_3"),
+                Some(FieldElement::from(3)),
+            )
+            .unwrap();
+        compiler.add_signal(3, String::from("0.out"), None).unwrap();
+        compiler.add_gate(AGateType::AMul, 1, 2, 3).unwrap();
+        compiler.add_inputs(HashMap::from([(1, String::from("0.a"))]));
+        compiler.add_outputs(HashMap::from([(3, String::from("0.out"))]));
+
+        let circuit = compiler.build_circuit().unwrap();
+        assert!(circuit.info.input_name_to_wire_index.contains_key("0.a"));
+        assert!(circuit.info.constants.contains_key("0.const_signal_3_2"));
+        assert!(circuit.info.output_name_to_wire_index.contains_key("0.out"));
+
+        compiler.set_naming(NamingStyle::FlatNumeric);
+        let circuit = compiler.build_circuit().unwrap();
+        assert!(circuit.info.input_name_to_wire_index.contains_key("s1"));
+        assert!(circuit.info.constants.contains_key("c2"));
+        assert!(circuit.info.output_name_to_wire_index.contains_key("s3"));
+    }
+
+    #[test]
+    fn test_compiler_remap_signals() {
+        let mut compiler = Compiler::new();
+        compiler.add_signal(1, String::from("0.a"), None).unwrap();
+        compiler.add_signal(2, String::from("0.b"), None).unwrap();
+        compiler.add_signal(3, String::from("0.out"), None).unwrap();
+        compiler.add_gate(AGateType::AAdd, 1, 2, 3).unwrap();
+        compiler.add_inputs(HashMap::from([(1, String::from("0.a"))]));
+        compiler.add_outputs(HashMap::from([(3, String::from("0.out"))]));
+
+        compiler
+            .remap_signals(&HashMap::from([(1, 10), (3, 30)]))
+            .unwrap();
+
+        assert_eq!(compiler.signals[&10].name, "0.a");
+        assert_eq!(compiler.signals[&2].name, "0.b");
+        assert_eq!(compiler.signals[&30].name, "0.out");
+        assert_eq!(compiler.inputs[&10], "0.a");
+        assert_eq!(compiler.outputs[&30], "0.out");
+        assert!(compiler.nodes[&1].contains_signal(&10));
+        assert!(compiler.debug_assert_valid().is_ok());
+    }
+
+    #[test]
+    fn test_compiler_remap_signals_collision() {
+        let mut compiler = Compiler::new();
+        compiler.add_signal(1, String::from("0.a"), None).unwrap();
+        compiler.add_signal(2, String::from("0.b"), None).unwrap();
+
+        let result = compiler.remap_signals(&HashMap::from([(1, 2)]));
+
+        assert!(matches!(result, Err(CircuitError::SignalIdCollision(2))));
+        // The circuit is left untouched
+        assert_eq!(compiler.signals[&1].name, "0.a");
+        assert_eq!(compiler.signals[&2].name, "0.b");
+    }
+
+    #[test]
+    fn test_compiler_compact_signals() {
+        let mut compiler = Compiler::new();
+        compiler.add_signal(4, String::from("0.a"), None).unwrap();
+        compiler.add_signal(9, String::from("0.b"), None).unwrap();
+        compiler.add_signal(1, String::from("0.out"), None).unwrap();
+        compiler.add_gate(AGateType::AAdd, 4, 9, 1).unwrap();
+        compiler.add_inputs(HashMap::from([(4, String::from("0.a"))]));
+        compiler.add_outputs(HashMap::from([(1, String::from("0.out"))]));
+
+        let stats = compiler.compact_signals().unwrap();
+
+        assert_eq!(
+            stats,
+            CompactionStats {
+                signals: 3,
+                id_span: 10
+            }
+        );
+        assert_eq!(compiler.signals[&0].name, "0.out");
+        assert_eq!(compiler.signals[&1].name, "0.a");
+        assert_eq!(compiler.signals[&2].name, "0.b");
+        assert_eq!(compiler.inputs[&1], "0.a");
+        assert_eq!(compiler.outputs[&0], "0.out");
+        assert!(compiler.debug_assert_valid().is_ok());
+    }
+
+    #[test]
+    fn test_compiler_rewrite() {
+        let mut compiler = Compiler::new();
+        for (id, name) in [(1, "0.a"), (2, "0.b"), (3, "0.c"), (4, "0.ab"), (5, "0.ac")] {
+            compiler.add_signal(id, String::from(name), None).unwrap();
+        }
+        compiler.add_signal(6, String::from("0.out"), None).unwrap();
+        compiler.add_gate(AGateType::AMul, 1, 2, 4).unwrap();
+        compiler.add_gate(AGateType::AMul, 1, 3, 5).unwrap();
+        compiler.add_gate(AGateType::AAdd, 4, 5, 6).unwrap();
+        compiler.add_inputs(HashMap::from([
+            (1, String::from("0.a")),
+            (2, String::from("0.b")),
+            (3, String::from("0.c")),
+        ]));
+        compiler.add_outputs(HashMap::from([(6, String::from("0.out"))]));
+
+        let stats = compiler.rewrite(&[&FactorCommonOperand], 10);
+
+        assert_eq!(stats.rewrites["factor_common_operand"], 1);
+        assert_eq!(compiler.gates.len(), 2);
+        assert!(compiler.debug_assert_valid().is_ok());
+
+        // The named products are removed instead of being bound to the sum b + c
+        assert!(!compiler.signals.contains_key(&4));
+        assert!(!compiler.signals.contains_key(&5));
+        let sum = compiler
+            .gates
+            .iter()
+            .find(|gate| gate.op == AGateType::AAdd);
+        let sum_signals = compiler.nodes[&sum.unwrap().out].get_signals();
+        assert!(sum_signals
+            .iter()
+            .all(|id| is_internal_name(&compiler.signals[id].name)));
+    }
+
+    #[test]
+    fn test_compiler_rewrite_balance_additions() {
+        // out = (((a + b) + c) + d) + e, with the partial sums named
+        let mut compiler = Compiler::new();
+        for (id, name) in [
+            "0.a", "0.b", "0.c", "0.d", "0.e", "0.s1", "0.s2", "0.s3", "0.out",
+        ]
+        .iter()
+        .enumerate()
+        {
+            compiler
+                .add_signal(id as u32, name.to_string(), None)
+                .unwrap();
+        }
+        compiler.add_gate(AGateType::AAdd, 0, 1, 5).unwrap();
+        compiler.add_gate(AGateType::AAdd, 5, 2, 6).unwrap();
+        compiler.add_gate(AGateType::AAdd, 6, 3, 7).unwrap();
+        compiler.add_gate(AGateType::AAdd, 7, 4, 8).unwrap();
+        compiler.add_outputs(HashMap::from([(8, String::from("0.out"))]));
+
+        let stats = compiler.rewrite(&[&BalanceAdditions], 2);
+
+        assert_eq!(stats.rewrites["balance_additions"], 1);
+        assert_eq!(compiler.gates.len(), 4);
+        assert!(compiler.debug_assert_valid().is_ok());
+
+        // The named partial sums are removed instead of being bound to the sums of the tree
+        for signal_id in 5..8 {
+            assert!(!compiler.signals.contains_key(&signal_id));
+        }
+    }
+
+    #[test]
+    fn test_compiler_prune_dead_gates() {
+        // out = a * b, while dead = (a + b) - a is never read
+        let mut compiler = Compiler::new();
+        for (id, name) in ["0.a", "0.b", "0.out", "0.sum", "0.dead"]
+            .iter()
+            .enumerate()
+        {
+            compiler
+                .add_signal(id as u32, name.to_string(), None)
+                .unwrap();
+        }
+        compiler.add_gate(AGateType::AAdd, 0, 1, 3).unwrap();
+        compiler.add_gate(AGateType::AMul, 0, 1, 2).unwrap();
+        compiler.add_gate(AGateType::ASub, 3, 0, 4).unwrap();
+        compiler.add_inputs(HashMap::from([
+            (0, String::from("0.a")),
+            (1, String::from("0.b")),
+        ]));
+        compiler.add_outputs(HashMap::from([(2, String::from("0.out"))]));
+
+        assert_eq!(compiler.prune_dead_gates(), 2);
+        assert_eq!(compiler.gates().len(), 1);
+        assert_eq!(compiler.gates()[0].op, AGateType::AMul);
+        assert_eq!(compiler.prune_dead_gates(), 0);
+        assert!(compiler.debug_assert_valid().is_ok());
+
+        let circuit = compiler.build_circuit().unwrap();
+        assert_eq!(circuit.wire_count, 3);
+        let inputs = HashMap::from([(String::from("0.a"), 6), (String::from("0.b"), 7)]);
+        let outputs = evaluate_circuit(&circuit, &inputs, ValueType::Uint).unwrap();
+        assert_eq!(outputs["0.out"], 42);
+    }
+
+    #[test]
+    fn test_compiler_fold_constants() {
+        // out = in * (2 * 3) + 4 / 0
+        let mut compiler = Compiler::new();
+        compiler.add_signal(1, String::from("0.in"), None).unwrap();
+        compiler
+            .add_signal(2, String::from("0.two"), Some(FieldElement::from(2)))
+            .unwrap();
+        compiler
+            .add_signal(3, String::from("0.three"), Some(FieldElement::from(3)))
+            .unwrap();
+        compiler
+            .add_signal(4, String::from("0.scale"), None)
+            .unwrap();
+        compiler
+            .add_signal(5, String::from("0.scaled"), None)
+            .unwrap();
+        compiler
+            .add_signal(6, String::from("0.four"), Some(FieldElement::from(4)))
+            .unwrap();
+        compiler
+            .add_signal(7, String::from("0.zero"), Some(FieldElement::from(0)))
+            .unwrap();
+        compiler
+            .add_signal(8, String::from("0.undefined"), None)
+            .unwrap();
+        compiler.add_signal(9, String::from("0.out"), None).unwrap();
+        compiler.add_gate(AGateType::AMul, 2, 3, 4).unwrap();
+        compiler.add_gate(AGateType::AMul, 1, 4, 5).unwrap();
+        compiler.add_gate(AGateType::ADiv, 6, 7, 8).unwrap();
+        compiler.add_gate(AGateType::AAdd, 5, 8, 9).unwrap();
+        compiler.add_inputs(HashMap::from([(1, String::from("0.in"))]));
+        compiler.add_outputs(HashMap::from([(9, String::from("0.out"))]));
+
+        // The division by zero is kept
+        assert_eq!(compiler.fold_constants(), 1);
+        assert_eq!(compiler.gates().len(), 3);
+        assert_eq!(compiler.signals[&4].value, Some(FieldElement::from(6)));
+        assert_eq!(compiler.fold_constants(), 0);
+        assert!(compiler.debug_assert_valid().is_ok());
+
+        let circuit = compiler.build_circuit().unwrap();
+        let constants = circuit
+            .info
+            .constants
+            .values()
+            .map(|constant| constant.value.as_str())
+            .collect::<HashSet<_>>();
+        assert_eq!(constants, HashSet::from(["6", "4", "0"]));
+
+        // Outputs only depending on constants become constant wires
+        let mut compiler = Compiler::new();
+        compiler
+            .add_signal(1, String::from("0.a"), Some(FieldElement::from(5)))
+            .unwrap();
+        compiler.add_signal(2, String::from("0.out"), None).unwrap();
+        compiler.add_gate(AGateType::AAdd, 1, 1, 2).unwrap();
+        compiler.add_outputs(HashMap::from([(2, String::from("0.out"))]));
+        assert_eq!(compiler.fold_constants(), 1);
+        assert!(compiler.gates().is_empty());
+        assert_eq!(
+            compiler.constant_outputs(),
+            [(String::from("0.out"), Some(FieldElement::from(10)))]
+        );
+    }
+
+    #[test]
+    fn test_compiler_fold_constants_field() {
+        // big = 2^20 * 2^20, neg = 1 - 2, out = big / 3 + neg
+        let element = |value: i64| {
+            FieldElement::from_integer(
+                &BigUint::from(value.unsigned_abs()),
+                value < 0,
+                Prime::Bn128,
+            )
+        };
+        let mut compiler = Compiler::new();
+        compiler.set_field_constants(true);
+        for (id, name, value) in [
+            (1, "0.a", Some(1 << 20)),
+            (2, "0.big", None),
+            (3, "0.one", Some(1)),
+            (4, "0.two", Some(2)),
+            (5, "0.neg", None),
+            (6, "0.three", Some(3)),
+            (7, "0.third", None),
+            (8, "0.out", None),
+        ] {
+            compiler
+                .add_signal(id, String::from(name), value.map(element))
+                .unwrap();
+        }
+        compiler.add_gate(AGateType::AMul, 1, 1, 2).unwrap();
+        compiler.add_gate(AGateType::ASub, 3, 4, 5).unwrap();
+        compiler.add_gate(AGateType::ADiv, 2, 6, 7).unwrap();
+        compiler.add_gate(AGateType::AAdd, 7, 5, 8).unwrap();
+        compiler.add_outputs(HashMap::from([(8, String::from("0.out"))]));
+
+        // Values beyond a word and negatives are folded in the field
+        let third = element(1 << 40).div(&element(3), Prime::Bn128).unwrap();
+        assert_eq!(compiler.fold_constants(), 4);
+        assert_eq!(compiler.signals[&2].value, Some(element(1 << 40)));
+        assert_eq!(compiler.signals[&5].value, Some(element(-1)));
+        assert_eq!(
+            compiler.constant_outputs(),
+            [(
+                String::from("0.out"),
+                Some(third.sub(&FieldElement::from(1), Prime::Bn128))
+            )]
+        );
+
+        // Word backends keep the wrapping word semantics
+        let mut compiler = Compiler::new();
+        compiler
+            .add_signal(1, String::from("0.a"), Some(element(1 << 20)))
+            .unwrap();
+        compiler.add_signal(2, String::from("0.out"), None).unwrap();
+        compiler.add_gate(AGateType::AMul, 1, 1, 2).unwrap();
+        compiler.add_outputs(HashMap::from([(2, String::from("0.out"))]));
+        assert_eq!(compiler.fold_constants(), 1);
+        assert_eq!(compiler.signals[&2].value, Some(FieldElement::from(0)));
+    }
+
+    #[test]
+    fn test_compiler_write_dot() {
+        // out = in * 3
+        let mut compiler = Compiler::new();
+        compiler.add_signal(1, String::from("0.in"), None).unwrap();
+        compiler
+            .add_signal(
+                2,
+                String::from("0.const_signal_3"),
+                Some(FieldElement::from(3)),
+            )
+            .unwrap();
+        compiler.add_signal(3, String::from("0.out"), None).unwrap();
+        compiler
+            .add_signal(4, String::from("0.unused"), None)
+            .unwrap();
+        compiler.add_gate(AGateType::AMul, 1, 2, 3).unwrap();
+        compiler.add_inputs(HashMap::from([(1, String::from("0.in"))]));
+        compiler.add_outputs(HashMap::from([(3, String::from("0.out"))]));
+
+        let mut dot = Vec::new();
+        compiler.write_dot(&mut dot).unwrap();
+        let dot = String::from_utf8(dot).unwrap();
+        let [input, constant, output] = [1, 2, 3].map(|id| compiler.signal_node(id).unwrap());
+
+        assert!(dot.starts_with("digraph circuit {"));
+        assert!(dot.contains(&format!("n{} [label=\"0.in\", color=blue];", input)));
+        assert!(dot.contains(&format!("n{} [label=\"0.const_signal_3 = 3\"];", constant)));
+        assert!(dot.contains(&format!("n{} [label=\"0.out\", color=green];", output)));
+        assert!(dot.contains("g0 [label=\"AMul\", shape=box];"));
+        assert!(dot.contains(&format!("n{} -> g0;", constant)));
+        assert!(dot.contains(&format!("g0 -> n{};", output)));
+        assert!(!dot.contains("0.unused"));
+    }
+
+    #[test]
+    fn test_compiler_reduce_divisions() {
+        // quarter = in / 4, third = in / 3
+        let compiler = |value_type: ValueType, mode: DivisionReduction, reduced: usize| {
+            let mut compiler = Compiler::new();
+            compiler.update_type(value_type).unwrap();
+            compiler.add_signal(1, String::from("0.in"), None).unwrap();
+            compiler
+                .add_signal(
+                    2,
+                    String::from("0.const_signal_4"),
+                    Some(FieldElement::from(4)),
+                )
+                .unwrap();
+            compiler
+                .add_signal(
+                    3,
+                    String::from("0.const_signal_3"),
+                    Some(FieldElement::from(3)),
+                )
+                .unwrap();
+            compiler
+                .add_signal(4, String::from("0.quarter"), None)
+                .unwrap();
+            compiler
+                .add_signal(5, String::from("0.third"), None)
+                .unwrap();
+            compiler.add_gate(AGateType::AIntDiv, 1, 2, 4).unwrap();
+            compiler.add_gate(AGateType::ADiv, 1, 3, 5).unwrap();
+            compiler.add_inputs(HashMap::from([(1, String::from("0.in"))]));
+            compiler.add_outputs(HashMap::from([
+                (4, String::from("0.quarter")),
+                (5, String::from("0.third")),
+            ]));
+            assert_eq!(compiler.reduce_divisions(mode), reduced);
+            compiler
+        };
+        let outputs = |compiler: &Compiler, value: i32| {
+            let inputs = HashMap::from([(String::from("0.in"), value as u32)]);
+            let outputs = word_outputs(compiler, &inputs);
+            let circuit = compiler.build_circuit().unwrap();
+            let value_type = compiler.value_type();
+            assert_eq!(
+                evaluate_circuit(&circuit, &inputs, value_type).unwrap(),
+                outputs.clone().into_iter().collect::<BTreeMap<_, _>>()
+            );
+            (outputs["0.quarter"] as i32, outputs["0.third"] as i32)
+        };
+
+        // Signed divisions truncate toward zero
+        let sint = compiler(ValueType::Sint, DivisionReduction::Integer, 1);
+        assert!(sint
+            .gates()
+            .iter()
+            .all(|gate| gate.op != AGateType::AIntDiv));
+        assert_eq!(outputs(&sint, 7), (1, 2));
+        assert_eq!(outputs(&sint, -7), (-1, -2));
+        assert_eq!(outputs(&sint, -8), (-2, -2));
+
+        let uint = compiler(ValueType::Uint, DivisionReduction::Integer, 1);
+        assert_eq!(uint.gates()[0].op, AGateType::AShiftR);
+        assert_eq!(outputs(&uint, 7), (1, 2));
+
+        // Words aren't provably multiples of the divisor, so their divisions still truncate
+        let field = compiler(ValueType::Sint, DivisionReduction::Field, 1);
+        assert_eq!(field.gates()[1].op, AGateType::ADiv);
+        assert_eq!(outputs(&field, 9), (2, 3));
+        assert_eq!(outputs(&field, 7), (1, 2));
+        assert_eq!(outputs(&field, -7), (-1, -2));
+        assert_eq!(inverse_mod_2_32(3).wrapping_mul(3), 1);
+        assert_eq!(inverse_mod_2_32(u32::MAX), u32::MAX);
+
+        compiler(ValueType::Sfloat, DivisionReduction::Field, 0);
+
+        // Constant multiples of the divisor are, and field constants divide by the inverse modulo
+        // the prime
+        let mut constant = Compiler::new();
+        for (id, name, value) in [(1, "0.a", 21), (2, "0.b", 3), (3, "0.c", 7)] {
+            constant
+                .add_signal(id, name.to_string(), Some(FieldElement::from(value)))
+                .unwrap();
+        }
+        constant
+            .add_signal(4, String::from("0.exact"), None)
+            .unwrap();
+        constant
+            .add_signal(5, String::from("0.inexact"), None)
+            .unwrap();
+        constant.add_gate(AGateType::ADiv, 1, 2, 4).unwrap();
+        constant.add_gate(AGateType::ADiv, 3, 2, 5).unwrap();
+        assert_eq!(constant.reduce_divisions(DivisionReduction::Field), 1);
+        assert_eq!(constant.gates()[0].op, AGateType::AMul);
+        assert_eq!(constant.gates()[1].op, AGateType::ADiv);
+
+        let mut field = compiler(ValueType::Sint, DivisionReduction::Integer, 1);
+        field.set_field_constants(true);
+        assert_eq!(field.reduce_divisions(DivisionReduction::Field), 1);
+        let gate = &field.gates()[1];
+        assert_eq!(gate.op, AGateType::AMul);
+        let inverse = field.node_field_constant(gate.rh_in).unwrap();
+        assert_eq!(
+            inverse.mul(&FieldElement::from(3), Prime::Bn128),
+            FieldElement::from(1)
+        );
+    }
+
+    #[test]
+    fn test_compiler_lower_for_boolify() {
+        // pow = a ** b, quotient = a \ b, remainder = a % 7, left = a << b, right = a >> b
+        let mut compiler = Compiler::new();
+        compiler.update_type(ValueType::Uint).unwrap();
+        for (id, name) in [(1, "0.a"), (2, "0.b")] {
+            compiler.add_signal(id, name.to_string(), None).unwrap();
+        }
+        compiler
+            .add_signal(
+                3,
+                String::from("0.const_signal_7"),
+                Some(FieldElement::from(7)),
+            )
+            .unwrap();
+        let outputs = [
+            (4, "0.pow", AGateType::APow, 2),
+            (5, "0.quotient", AGateType::AIntDiv, 2),
+            (6, "0.remainder", AGateType::AMod, 3),
+            (7, "0.left", AGateType::AShiftL, 2),
+            (8, "0.right", AGateType::AShiftR, 2),
+        ];
+        for (id, name, op, rh_in) in outputs {
+            compiler.add_signal(id, name.to_string(), None).unwrap();
+            compiler.add_gate(op, 1, rh_in, id).unwrap();
+        }
+        compiler.add_inputs(HashMap::from([
+            (1, String::from("0.a")),
+            (2, String::from("0.b")),
+        ]));
+        compiler.add_outputs(
+            outputs
+                .iter()
+                .map(|&(id, name, _, _)| (id, name.to_string()))
+                .collect(),
+        );
+
+        let values = [(1000, 3), (u32::MAX, 31), (12345, 1), (5, 40)];
+        let expected = values
+            .iter()
+            .map(|&(a, b)| {
+                let inputs = HashMap::from([(String::from("0.a"), a), (String::from("0.b"), b)]);
+                word_outputs(&compiler, &inputs)
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(compiler.lower_for_boolify(32), 5);
+        assert!(compiler
+            .gates()
+            .iter()
+            .all(|gate| !LOWERED_GATES.contains(&gate.op)));
+        compiler.debug_assert_valid().unwrap();
+        let circuit = compiler.build_circuit().unwrap();
+        for (&(a, b), expected) in values.iter().zip(expected) {
+            let inputs = HashMap::from([(String::from("0.a"), a), (String::from("0.b"), b)]);
+            assert_eq!(
+                evaluate_circuit(&circuit, &inputs, ValueType::Uint).unwrap(),
+                expected.into_iter().collect::<BTreeMap<_, _>>()
+            );
+        }
+        assert_eq!(compiler.lower_for_boolify(32), 0);
+    }
+
+    #[test]
+    fn test_compiler_lower_for_target() {
+        // cube = a ** 3, third = a \ 3
+        let mut compiler = Compiler::new();
+        compiler.add_signal(1, String::from("0.a"), None).unwrap();
+        compiler
+            .add_signal(2, String::from("0.three"), Some(FieldElement::from(3)))
+            .unwrap();
+        compiler
+            .add_signal(3, String::from("0.cube"), None)
+            .unwrap();
+        compiler
+            .add_signal(4, String::from("0.third"), None)
+            .unwrap();
+        compiler.add_gate(AGateType::APow, 1, 2, 3).unwrap();
+        compiler.add_gate(AGateType::AIntDiv, 1, 2, 4).unwrap();
+        compiler.add_inputs(HashMap::from([(1, String::from("0.a"))]));
+        compiler.add_outputs(HashMap::from([
+            (3, String::from("0.cube")),
+            (4, String::from("0.third")),
+        ]));
+
+        assert_eq!(compiler.lower_for_target(), 0);
+
+        // The division needs comparisons, so only the power is lowered
+        compiler.set_target(Target::Mpz);
+        assert_eq!(compiler.lower_for_target(), 1);
+        let mut ops = compiler
+            .gates()
+            .iter()
+            .map(|gate| gate.op)
+            .collect::<Vec<_>>();
+        ops.dedup();
+        assert_eq!(ops, [AGateType::AMul, AGateType::AIntDiv, AGateType::AMul]);
+        compiler.debug_assert_valid().unwrap();
+        let circuit = compiler.build_circuit().unwrap();
+        let inputs = HashMap::from([(String::from("0.a"), 5)]);
+        assert_eq!(
+            evaluate_circuit(&circuit, &inputs, ValueType::Sint).unwrap()["0.cube"],
+            125
+        );
+    }
+
+    #[test]
+    fn test_compiler_fuse_constant_multiplications() {
+        // out = 3 * in + in * in
+        let fused_compiler = || {
+            let mut compiler = Compiler::new();
+            compiler.add_signal(1, String::from("0.in"), None).unwrap();
+            compiler
+                .add_signal(
+                    2,
+                    String::from("0.const_signal_3"),
+                    Some(FieldElement::from(3)),
+                )
+                .unwrap();
+            compiler
+                .add_signal(3, String::from("0.scaled"), None)
+                .unwrap();
+            compiler
+                .add_signal(4, String::from("0.square"), None)
+                .unwrap();
+            compiler.add_signal(5, String::from("0.out"), None).unwrap();
+            compiler.add_gate(AGateType::AMul, 2, 1, 3).unwrap();
+            compiler.add_gate(AGateType::AMul, 1, 1, 4).unwrap();
+            compiler.add_gate(AGateType::AAdd, 3, 4, 5).unwrap();
+            compiler.add_inputs(HashMap::from([(1, String::from("0.in"))]));
+            compiler.add_outputs(HashMap::from([(5, String::from("0.out"))]));
+            assert_eq!(compiler.fuse_constant_multiplications(), 1);
+            compiler
+        };
+        let inputs = HashMap::from([(String::from("0.in"), 5)]);
+
+        let compiler = fused_compiler();
+        assert_eq!(compiler.gates()[0].op, AGateType::ACMul);
+        assert_eq!(compiler.gates()[1].op, AGateType::AMul);
+        assert_eq!(word_outputs(&compiler, &inputs)["0.out"], 40);
+
+        // The constant is inlined, without a wire
+        let circuit = compiler.build_circuit().unwrap();
+        assert_eq!(circuit.wire_count, 4);
+        assert!(circuit.info.constants.is_empty());
+        let fused = circuit.gates.iter().find(|gate| gate.op == "ACMul:3");
+        assert_eq!(fused.unwrap().inputs.len(), 1);
+        let outputs = evaluate_circuit(&circuit, &inputs, ValueType::Uint).unwrap();
+        assert_eq!(outputs["0.out"], 40);
+        assert_eq!(compiler.wire_counts().total, circuit.wire_count);
+        assert_eq!(compiler.wire_counts().constants, 0);
+
+        // Lowered back to a multiplication by the constant wire for targets without ACMul
+        let mut compiler = fused_compiler();
+        compiler.set_target(Target::Mpz);
+        let circuit = compiler.build_circuit().unwrap();
+        assert_eq!(circuit.wire_count, 5);
+        assert_eq!(circuit.info.constants.len(), 1);
+        assert_eq!(
+            compiler.wire_counts(),
+            WireCounts {
+                inputs: 1,
+                outputs: 1,
+                constants: 1,
+                total: 5,
+            }
+        );
+        assert!(circuit.gates.iter().all(|gate| gate.inputs.len() == 2));
+        assert_eq!(
+            circuit
+                .gates
+                .iter()
+                .filter(|gate| gate.op == "AMul")
+                .count(),
+            2
+        );
+        let outputs = evaluate_circuit(&circuit, &inputs, ValueType::Uint).unwrap();
+        assert_eq!(outputs["0.out"], 40);
+    }
+
+    #[test]
+    fn test_compiler_debug_assert_valid() {
+        let mut compiler = Compiler::new();
+        compiler.add_signal(1, String::from("0.a"), None).unwrap();
+        compiler
+            .add_signal(2, String::from("0.b"), Some(FieldElement::from(2)))
+            .unwrap();
+        compiler.add_signal(3, String::from("0.out"), None).unwrap();
+        compiler.add_gate(AGateType::AMul, 1, 2, 3).unwrap();
+        assert!(compiler.debug_assert_valid().is_ok());
+
+        // A gate referencing a missing node
+        compiler
+            .gates
+            .push(ArithmeticGate::new(AGateType::AAdd, 1, 9, 3));
+        assert!(matches!(
+            compiler.debug_assert_valid(),
+            Err(CircuitError::Inconsistency { .. })
+        ));
+        compiler.gates.pop();
+
+        // A signal in two nodes
+        compiler.nodes.get_mut(&1).unwrap().add_signals(&vec![2]);
+        assert!(matches!(
+            compiler.debug_assert_valid(),
+            Err(CircuitError::Inconsistency { .. })
+        ));
+    }
+
+    #[test]
+    fn test_compiler_visit_gates() {
+        struct Collector(Vec<(AGateType, Vec<String>)>);
+
+        impl GateVisitor for Collector {
+            fn visit_gate(&mut self, gate: &ArithmeticGate, names: &GateSignalNames) {
+                let out = names.out.iter().map(|name| name.to_string()).collect();
+                self.0.push((gate.op, out));
+            }
+        }
+
+        let mut compiler = Compiler::new();
+        compiler.add_signal(1, String::from("0.a"), None).unwrap();
+        compiler.add_signal(2, String::from("0.b"), None).unwrap();
+        compiler.add_signal(3, String::from("0.c"), None).unwrap();
+        compiler.add_signal(4, String::from("0.out"), None).unwrap();
+        compiler.add_gate(AGateType::AMul, 1, 2, 3).unwrap();
+        compiler.add_connection(3, 4).unwrap();
+
+        assert_eq!(compiler.gates().len(), 1);
+
+        let mut collector = Collector(Vec::new());
+        compiler.visit_gates(&mut collector);
+
+        assert_eq!(
+            collector.0,
+            vec![(
+                AGateType::AMul,
+                vec![String::from("0.c"), String::from("0.out")]
+            )]
+        );
+    }
+
+    #[test]
+    fn test_compiler_resolve_aliases() {
+        let mut compiler = Compiler::new();
+        for id in 1..=6 {
+            compiler
+                .add_signal(id, format!("signal{}", id), None)
+                .unwrap();
+        }
+        compiler.add_gate(AGateType::AAdd, 1, 2, 3).unwrap();
+
+        // Chain 3 <- 4 <- 5 and an unrelated alias 1 <- 6
+        compiler.add_aliases(&[(4, 3), (5, 4), (6, 1)]);
+        assert!(compiler.build_circuit().is_err());
+
+        let stats = compiler.resolve_aliases().unwrap();
+
+        assert_eq!(stats.aliases, 3);
+        assert_eq!(stats.chains_collapsed, 2);
+        assert_eq!(compiler.nodes.len(), 3);
+
+        let gate = &compiler.gates[0];
+        let out_node = &compiler.nodes[&gate.out];
+        assert!(out_node.is_out);
+        for id in [3, 4, 5] {
+            assert!(out_node.contains_signal(&id));
+        }
+        assert!(compiler.nodes[&gate.lh_in].contains_signal(&6));
+        assert!(compiler.debug_assert_valid().is_ok());
+    }
+
+    #[test]
+    fn test_compiler_error_sources() {
+        let mut compiler = Compiler::new();
+        compiler.replace_source(Some(Arc::from("main.circom:3")));
+        compiler.add_signal(1, String::from("0.a"), None).unwrap();
+        compiler.add_signal(2, String::from("0.b"), None).unwrap();
+        compiler.replace_source(Some(Arc::from("main.circom:5")));
+        compiler.add_signal(3, String::from("0.c"), None).unwrap();
+        compiler
+            .add_signal(4, String::from("0.%random_1"), None)
+            .unwrap();
+        compiler.add_gate(AGateType::AMul, 1, 2, 3).unwrap();
+        compiler.add_gate(AGateType::AAdd, 1, 2, 4).unwrap();
+        compiler.replace_source(None);
+        assert_eq!(compiler.signal_source(3), Some("main.circom:5"));
+
+        compiler.add_aliases(&[(3, 4)]);
+        assert_eq!(
+            compiler.resolve_aliases().unwrap_err().to_string(),
+            "Cannot merge output nodes: 0.c (main.circom:5), intermediate value (main.circom:5)"
+        );
+
+        let mut compiler = Compiler::new();
+        compiler.add_signal(1, String::from("0.a"), None).unwrap();
+        compiler.replace_source(Some(Arc::from("main.circom:7")));
+        compiler.add_signal(2, String::from("0.b"), None).unwrap();
+        compiler.add_gate(AGateType::AAdd, 1, 1, 2).unwrap();
+        compiler.add_inputs(HashMap::from([(1, String::from("0.in"))]));
+        compiler.add_inputs(HashMap::from([(2, String::from("0.in"))]));
+        assert_eq!(
+            compiler.build_circuit().unwrap_err().to_string(),
+            "Inconsistency: Duplicate input 0.in: 0.a, 0.b (main.circom:7)"
+        );
+    }
+
+    #[test]
+    fn test_compiler_resolve_aliases_output_nodes() {
+        let mut compiler = Compiler::new();
+        for id in 1..=6 {
+            compiler
+                .add_signal(id, format!("signal{}", id), None)
+                .unwrap();
+        }
+        compiler.add_gate(AGateType::AAdd, 1, 2, 3).unwrap();
+        compiler.add_gate(AGateType::AMul, 1, 2, 4).unwrap();
+
+        compiler.add_aliases(&[(3, 5), (5, 4)]);
+
+        assert!(matches!(
+            compiler.resolve_aliases(),
+            Err(CircuitError::CannotMergeOutputNodes { .. })
+        ));
+    }
+
+    #[test]
+    fn test_compiler_build_circuit_party_mapping() {
+        let mut compiler = Compiler::new();
+        compiler.add_signal(1, String::from("0.a"), None).unwrap();
+        compiler
+            .add_signal(2, String::from("0.b[0]"), None)
+            .unwrap();
+        compiler
+            .add_signal(3, String::from("0.b[1]"), None)
+            .unwrap();
+        compiler.add_signal(4, String::from("0.c"), None).unwrap();
+        compiler.add_signal(5, String::from("0.out"), None).unwrap();
+        compiler.add_gate(AGateType::AAdd, 1, 2, 4).unwrap();
+        compiler.add_gate(AGateType::AMul, 4, 3, 5).unwrap();
+        compiler.add_inputs(HashMap::from([
+            (1, String::from("0.a")),
+            (2, String::from("0.b[0]")),
+            (3, String::from("0.b[1]")),
+        ]));
+        compiler.add_outputs(HashMap::from([(5, String::from("0.out"))]));
+        compiler.set_party_mapping(HashMap::from([
+            (String::from("0.a"), 1),
+            (String::from("0.b"), 0),
+        ]));
+
+        let circuit = compiler.build_circuit().unwrap();
+        let inputs = &circuit.info.input_name_to_wire_index;
+
+        assert_eq!(inputs["0.b[0]"], 0);
+        assert_eq!(inputs["0.b[1]"], 1);
+        assert_eq!(inputs["0.a"], 2);
+        assert_eq!(circuit.io_widths, Some((vec![2, 1], vec![1])));
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_compiler_build_circuit_input_order() {
+        let mut compiler = Compiler::new();
+        compiler.add_signal(1, String::from("0.a"), None).unwrap();
+        compiler
+            .add_signal(2, String::from("0.b[0]"), None)
+            .unwrap();
+        compiler
+            .add_signal(3, String::from("0.b[1]"), None)
+            .unwrap();
+        compiler.add_signal(4, String::from("0.c"), None).unwrap();
+        compiler.add_signal(5, String::from("0.out"), None).unwrap();
+        compiler.add_gate(AGateType::AAdd, 1, 2, 4).unwrap();
+        compiler.add_gate(AGateType::AMul, 4, 3, 5).unwrap();
+        compiler.add_inputs(HashMap::from([
+            (3, String::from("0.b[1]")),
+            (2, String::from("0.b[0]")),
+        ]));
+        compiler.add_inputs(HashMap::from([(1, String::from("0.a"))]));
+        compiler.add_outputs(HashMap::from([(5, String::from("0.out"))]));
+
+        let circuit = compiler.build_circuit().unwrap();
+        let inputs = &circuit.info.input_name_to_wire_index;
+
+        assert_eq!(inputs["0.b[0]"], 0);
+        assert_eq!(inputs["0.b[1]"], 1);
+        assert_eq!(inputs["0.a"], 2);
+
+        let report = compiler.generate_circuit_report().unwrap();
+        let names = report
+            .inputs
+            .iter()
+            .map(|input| input.names[0].as_str())
+            .collect::<Vec<&str>>();
+
+        assert_eq!(names[..3], ["0.b[0]", "0.b[1]", "0.a"]);
+    }
 
     #[test]
-    fn test_node_with_signal() {
-        let node = Node::new_with_signal(1, true, false);
-        assert_eq!(node.signals.len(), 1);
-        assert_eq!(node.signals[0], 1);
-        assert!(node.is_const);
-        assert!(!node.is_out);
+    fn test_instance_report_parallel() {
+        let mut instance = InstanceReport {
+            instance: String::from("Square"),
+            template: String::from("Square"),
+            component: Some(String::from("square")),
+            params: Vec::new(),
+            parallel: false,
+        };
+        // Only parallel instances are annotated, and reports without annotations still load
+        let json = serde_json::to_value(&instance).unwrap();
+        assert!(json.get("parallel").is_none());
+        assert_eq!(
+            serde_json::from_value::<InstanceReport>(json).unwrap(),
+            instance
+        );
+
+        instance.parallel = true;
+        let json = serde_json::to_value(&instance).unwrap();
+        assert_eq!(json["parallel"], true);
+        assert_eq!(instance.to_string(), "Square()");
     }
 
     #[test]
-    fn test_node_add_signal() {
-        let mut node = Node::new();
-        node.add_signals(&vec![1, 2, 3]);
-        assert_eq!(node.signals.len(), 3);
-        assert!(node.contains_signal(&1));
-        assert!(node.contains_signal(&2));
-        assert!(node.contains_signal(&3));
+    fn test_circuit_report_from_path() {
+        let mut compiler = Compiler::new();
+        compiler.add_signal(1, String::from("0.a"), None).unwrap();
+        compiler
+            .add_signal(2, String::from("0.b"), Some(FieldElement::from(3)))
+            .unwrap();
+        compiler.add_signal(3, String::from("0.out"), None).unwrap();
+        compiler.add_gate(AGateType::AMul, 1, 2, 3).unwrap();
+        compiler.add_inputs(HashMap::from([(1, String::from("0.a"))]));
+        compiler.add_outputs(HashMap::from([(3, String::from("0.out"))]));
+
+        let report = compiler.generate_circuit_report().unwrap();
+        let path = std::env::temp_dir().join("circom_2_arithc_report.json");
+        std::fs::write(&path, serde_json::to_string_pretty(&report).unwrap()).unwrap();
+
+        let read = CircuitReport::from_path(&path).unwrap();
+        assert_eq!(read, report);
+        assert_eq!(read.inputs()[0].names(), ["0.a"]);
+        assert_eq!(read.outputs()[0].names(), ["0.out"]);
+        assert_eq!(read.value_type(), ValueType::default());
+        assert_eq!(read.gate_order(), GateOrder::Insertion);
+        assert_eq!(read.target(), Target::Generic);
+        assert!(read.pruned_inputs().is_empty());
+        assert_eq!(read.constant_range().map(|range| range.width()), Some(3));
+
+        // Reports written before pruning, gate orders, targets and constant ranges were recorded
+        // still load
+        let mut json = serde_json::to_value(&report).unwrap();
+        let object = json.as_object_mut().unwrap();
+        object.remove("pruned_inputs");
+        object.remove("gate_order");
+        object.remove("target");
+        std::fs::write(&path, json.to_string()).unwrap();
+        assert_eq!(CircuitReport::from_path(&path).unwrap(), report);
+
+        json.as_object_mut().unwrap().remove("constant_range");
+        std::fs::write(&path, json.to_string()).unwrap();
+        let read = CircuitReport::from_path(&path).unwrap();
+        assert_eq!(read.constant_range(), None);
+
+        // Nor reports written before the gate statistics
+        for key in [
+            "gate_counts",
+            "multiplicative_depth",
+            "constant_count",
+            "wire_count",
+        ] {
+            json.as_object_mut().unwrap().remove(key);
+        }
+        std::fs::write(&path, json.to_string()).unwrap();
+        let read = CircuitReport::from_path(&path).unwrap();
+        assert!(read.gate_counts().is_empty());
+        assert_eq!(read.wire_count(), 0);
     }
 
     #[test]
-    fn test_node_contains_signal() {
-        let node = Node::new_with_signal(1, true, false);
-        assert!(node.contains_signal(&1));
-        assert!(!node.contains_signal(&2));
+    fn test_circuit_report_gate_statistics() {
+        let mut compiler = Compiler::new();
+        compiler.add_inputs(HashMap::from([
+            (0, String::from("0.a")),
+            (1, String::from("0.b")),
+        ]));
+        compiler.add_outputs(HashMap::from([(5, String::from("0.out"))]));
+        for id in 0..6 {
+            compiler.add_signal(id, format!("0.s{}", id), None).unwrap();
+        }
+        compiler.add_gate(AGateType::AMul, 0, 1, 2).unwrap();
+        compiler.add_gate(AGateType::AAdd, 2, 0, 3).unwrap();
+        compiler.add_gate(AGateType::AMul, 3, 2, 4).unwrap();
+        compiler.add_gate(AGateType::ASub, 4, 1, 5).unwrap();
+
+        let report = compiler.generate_circuit_report().unwrap();
+        assert_eq!(
+            report.gate_counts(),
+            &BTreeMap::from([
+                (String::from("AAdd"), 1),
+                (String::from("AMul"), 2),
+                (String::from("ASub"), 1),
+            ])
+        );
+        assert_eq!(report.multiplicative_depth(), 2);
+        assert_eq!(report.constant_count(), 0);
+        assert_eq!(report.wire_count(), 6);
     }
 
     #[test]
-    fn test_node_set_output() {
-        let mut node = Node::new();
-        node.set_output(true);
-        assert!(node.is_out);
+    fn test_circuit_limits() {
+        let mut compiler = Compiler::new();
+        compiler.add_signal(1, String::from("0.a"), None).unwrap();
+        compiler.add_signal(2, String::from("0.b"), None).unwrap();
+        compiler.add_signal(3, String::from("0.out"), None).unwrap();
+        compiler.add_gate(AGateType::AMul, 1, 2, 3).unwrap();
+        compiler.add_inputs(HashMap::from([
+            (1, String::from("0.a")),
+            (2, String::from("0.b")),
+        ]));
+        compiler.add_outputs(HashMap::from([(3, String::from("0.out"))]));
+        let mut circuit = compiler.build_circuit().unwrap();
+
+        assert!(CircuitLimits::default().check(&circuit).is_ok());
+        let limits = CircuitLimits {
+            max_wires: Some(3),
+            max_gates: Some(1),
+        };
+        assert!(limits.check(&circuit).is_ok());
+
+        let limits = CircuitLimits {
+            max_wires: Some(2),
+            max_gates: None,
+        };
+        let error = limits.check(&circuit).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Circuit has 3 wires, more than the limit of 2"
+        );
+
+        let limits = CircuitLimits {
+            max_wires: None,
+            max_gates: Some(0),
+        };
+        assert!(matches!(
+            limits.check(&circuit),
+            Err(CircuitError::LimitExceeded {
+                count: 1,
+                limit: 0,
+                ..
+            })
+        ));
+
+        // Wire ids must fit in a u32 whatever the configured limit
+        circuit.wire_count = MAX_WIRE_COUNT + 1;
+        let limits = CircuitLimits {
+            max_wires: Some(usize::MAX),
+            max_gates: None,
+        };
+        assert!(limits.check(&circuit).is_err());
+        assert_eq!(checked_u32("wires", 7).unwrap(), 7);
     }
 
     #[test]
-    fn test_node_set_const() {
-        let mut node = Node::new();
-        node.set_const(true);
-        assert!(node.is_const);
+    fn test_compiler_build_circuit_shared_output() {
+        let mut compiler = Compiler::new();
+        compiler.add_signal(1, String::from("0.a"), None).unwrap();
+        compiler.add_signal(2, String::from("0.b"), None).unwrap();
+        compiler.add_signal(3, String::from("0.t"), None).unwrap();
+        compiler
+            .add_signal(4, String::from("0.out1"), None)
+            .unwrap();
+        compiler
+            .add_signal(5, String::from("0.out2"), None)
+            .unwrap();
+        compiler.add_gate(AGateType::AMul, 1, 2, 3).unwrap();
+        compiler.add_aliases(&[(4, 3), (5, 3)]);
+        compiler.resolve_aliases().unwrap();
+        compiler.add_inputs(HashMap::from([
+            (1, String::from("0.a")),
+            (2, String::from("0.b")),
+        ]));
+        compiler.add_outputs(HashMap::from([
+            (4, String::from("0.out1")),
+            (5, String::from("0.out2")),
+        ]));
+        compiler.set_party_mapping(HashMap::from([
+            (String::from("0.a"), 0),
+            (String::from("0.b"), 0),
+        ]));
+
+        let circuit = compiler.build_circuit().unwrap();
+        let outputs = &circuit.info.output_name_to_wire_index;
+
+        assert_eq!(circuit.wire_count, 3);
+        assert_eq!(outputs["0.out1"], 2);
+        assert_eq!(outputs["0.out2"], 2);
+        assert_eq!(circuit.gates[0].outputs, vec![2]);
+        assert_eq!(circuit.io_widths, Some((vec![2], vec![1])));
     }
 
     #[test]
-    fn test_compiler_add_inputs() {
+    fn test_compiler_build_circuit_gate_order() {
         let mut compiler = Compiler::new();
-        let mut inputs = HashMap::new();
-        inputs.insert(1, String::from("input1"));
-        inputs.insert(2, String::from("input2"));
-        compiler.add_inputs(inputs);
+        compiler.add_signal(1, String::from("0.a"), None).unwrap();
+        compiler.add_signal(2, String::from("0.b"), None).unwrap();
+        compiler.add_signal(3, String::from("0.x"), None).unwrap();
+        compiler
+            .add_signal(4, String::from("0.out1"), None)
+            .unwrap();
+        compiler.add_signal(5, String::from("0.y"), None).unwrap();
+        compiler
+            .add_signal(6, String::from("0.out2"), None)
+            .unwrap();
+        compiler.add_gate(AGateType::AAdd, 1, 2, 3).unwrap();
+        compiler.add_gate(AGateType::AMul, 3, 3, 4).unwrap();
+        compiler.add_gate(AGateType::ASub, 1, 2, 5).unwrap();
+        compiler.add_gate(AGateType::AXor, 5, 5, 6).unwrap();
+        compiler.add_inputs(HashMap::from([
+            (1, String::from("0.a")),
+            (2, String::from("0.b")),
+        ]));
+        compiler.add_outputs(HashMap::from([(6, String::from("0.out2"))]));
+        compiler.add_outputs(HashMap::from([(4, String::from("0.out1"))]));
 
-        assert_eq!(compiler.inputs.len(), 2);
-        assert_eq!(compiler.inputs[&1], "input1");
-        assert_eq!(compiler.inputs[&2], "input2");
+        let ops = |compiler: &Compiler| {
+            compiler
+                .build_circuit()
+                .unwrap()
+                .gates
+                .iter()
+                .map(|gate| gate.op.clone())
+                .collect::<Vec<String>>()
+        };
+
+        assert_eq!(ops(&compiler), vec!["AAdd", "AMul", "ASub", "AXor"]);
+
+        compiler.set_gate_order(GateOrder::DfsCone);
+        assert_eq!(ops(&compiler), vec!["ASub", "AXor", "AAdd", "AMul"]);
+
+        compiler.set_gate_order(GateOrder::BfsLevel);
+        assert_eq!(ops(&compiler), vec!["AAdd", "ASub", "AMul", "AXor"]);
+        assert_eq!(
+            compiler.generate_circuit_report().unwrap().gate_order,
+            GateOrder::BfsLevel
+        );
     }
 
     #[test]
-    fn test_compiler_add_outputs() {
+    fn test_compiler_build_circuit_unmapped_input() {
         let mut compiler = Compiler::new();
-        let mut outputs = HashMap::new();
-        outputs.insert(3, String::from("output1"));
-        outputs.insert(4, String::from("output2"));
-        compiler.add_outputs(outputs);
+        compiler.add_signal(1, String::from("0.a"), None).unwrap();
+        compiler.add_signal(2, String::from("0.b"), None).unwrap();
+        compiler.add_signal(3, String::from("0.out"), None).unwrap();
+        compiler.add_gate(AGateType::AAdd, 1, 2, 3).unwrap();
+        compiler.add_inputs(HashMap::from([
+            (1, String::from("0.a")),
+            (2, String::from("0.b")),
+        ]));
+        compiler.add_outputs(HashMap::from([(3, String::from("0.out"))]));
+        compiler.set_party_mapping(HashMap::from([(String::from("0.a"), 0)]));
 
-        assert_eq!(compiler.outputs.len(), 2);
-        assert_eq!(compiler.outputs[&3], "output1");
-        assert_eq!(compiler.outputs[&4], "output2");
+        let result = compiler.build_circuit();
+        assert!(matches!(result, Err(CircuitError::UnmappedInput(name)) if name == "0.b"));
     }
 
     #[test]
-    fn test_compiler_add_signal() {
+    fn test_compiler_evaluate_cone() {
         let mut compiler = Compiler::new();
-        let result = compiler.add_signal(1, String::from("signal1"), None);
+        compiler.add_signal(1, String::from("0.a"), None).unwrap();
+        compiler.add_signal(2, String::from("0.b"), None).unwrap();
+        compiler
+            .add_signal(3, String::from("0.three"), Some(FieldElement::from(3)))
+            .unwrap();
+        compiler.add_signal(4, String::from("0.x"), None).unwrap();
+        compiler.add_signal(5, String::from("0.y"), None).unwrap();
+        compiler.add_signal(6, String::from("0.z"), None).unwrap();
+        compiler.add_gate(AGateType::AMul, 1, 3, 4).unwrap();
+        compiler.add_gate(AGateType::AAdd, 4, 1, 5).unwrap();
+        compiler.add_gate(AGateType::AIntDiv, 2, 2, 6).unwrap();
 
-        assert!(result.is_ok());
-        assert_eq!(compiler.signals.len(), 1);
-        assert!(compiler.signals.contains_key(&1));
-        assert_eq!(compiler.signals[&1].name, "signal1");
+        // The cone of y doesn't depend on b
+        let inputs = HashMap::from([(String::from("0.a"), FieldElement::from(5))]);
+        assert_eq!(
+            compiler.evaluate_cone("0.y", &inputs).unwrap(),
+            ConeEvaluation {
+                value: FieldElement::from(20),
+                gate: Some(ConeGate {
+                    op: AGateType::AAdd,
+                    lh_in: FieldElement::from(15),
+                    rh_in: FieldElement::from(5)
+                }),
+            }
+        );
+        assert_eq!(
+            compiler.evaluate_cone("0.a", &inputs).unwrap(),
+            ConeEvaluation {
+                value: FieldElement::from(5),
+                gate: None
+            }
+        );
+
+        assert!(matches!(
+            compiler.evaluate_cone("0.z", &inputs),
+            Err(CircuitError::MissingInputValue(name)) if name == "0.b"
+        ));
+        let inputs = HashMap::from([(String::from("0.b"), FieldElement::from(0))]);
+        assert!(matches!(
+            compiler.evaluate_cone("0.z", &inputs),
+            Err(CircuitError::UndefinedValue(name)) if name == "0.z"
+        ));
+        assert!(matches!(
+            compiler.evaluate_cone("0.w", &inputs),
+            Err(CircuitError::UnknownSignal(name)) if name == "0.w"
+        ));
     }
 
     #[test]
-    fn test_compiler_add_duplicated_signal() {
+    fn test_compiler_wire_types() {
         let mut compiler = Compiler::new();
+        compiler.add_signal(1, String::from("0.a"), None).unwrap();
         compiler
-            .add_signal(1, String::from("signal1"), None)
+            .add_signal(2, String::from("0.mask"), Some(FieldElement::from(7)))
             .unwrap();
-        let result = compiler.add_signal(1, String::from("signal1"), None);
+        compiler.add_signal(3, String::from("0.lt"), None).unwrap();
+        compiler.add_signal(4, String::from("0.low"), None).unwrap();
+        compiler.add_signal(5, String::from("0.sum"), None).unwrap();
+        // Gates added out of topological order
+        compiler.add_gate(AGateType::AAdd, 3, 4, 5).unwrap();
+        compiler.add_gate(AGateType::ALt, 1, 2, 3).unwrap();
+        compiler.add_gate(AGateType::ABitAnd, 1, 2, 4).unwrap();
+        compiler.add_inputs(HashMap::from([(1, String::from("0.a"))]));
+        compiler.add_outputs(HashMap::from([(5, String::from("0.sum"))]));
 
-        assert!(matches!(result, Err(CircuitError::SignalAlreadyDeclared)));
+        let types = compiler.wire_types().unwrap();
+        let type_of = |name: &str| {
+            let (&node_id, _) = compiler
+                .nodes
+                .iter()
+                .find(|(&id, _)| compiler.node_signal_names(id) == vec![name])
+                .unwrap();
+            types[&node_id]
+        };
+
+        assert_eq!(type_of("0.a"), WireType::Field);
+        assert_eq!(type_of("0.mask"), WireType::Uint(3));
+        assert_eq!(type_of("0.lt"), WireType::Bool);
+        assert_eq!(type_of("0.low"), WireType::Uint(3));
+        assert_eq!(type_of("0.sum"), WireType::Field);
     }
 
     #[test]
-    fn test_compiler_get_signals() {
+    fn test_compiler_merge() {
+        let mut compiler = Compiler::new();
+        compiler.add_signal(1, String::from("0.a"), None).unwrap();
+        compiler.add_signal(2, String::from("0.b"), None).unwrap();
+        compiler.add_signal(3, String::from("0.c"), None).unwrap();
+        compiler.add_gate(AGateType::AAdd, 1, 2, 3).unwrap();
+        compiler.add_inputs(HashMap::from([
+            (1, String::from("0.a")),
+            (2, String::from("0.b")),
+        ]));
+
+        let mut other = Compiler::new();
+        other.add_signal(11, String::from("0.x"), None).unwrap();
+        other.add_signal(12, String::from("0.out"), None).unwrap();
+        other.add_gate(AGateType::AMul, 11, 11, 12).unwrap();
+        other.add_outputs(HashMap::from([(12, String::from("0.out"))]));
+        other.add_aliases(&[(3, 11)]);
+
+        compiler.merge(other).unwrap();
+        compiler.resolve_aliases().unwrap();
+        compiler.debug_assert_valid().unwrap();
+
+        assert_eq!(compiler.gates().len(), 2);
+        assert_eq!(compiler.input_order, vec![1, 2]);
+        assert_eq!(compiler.output_order, vec![12]);
+        assert_eq!(
+            compiler
+                .evaluate_cone(
+                    "0.out",
+                    &HashMap::from([
+                        (String::from("0.a"), FieldElement::from(2)),
+                        (String::from("0.b"), FieldElement::from(3))
+                    ])
+                )
+                .unwrap()
+                .value,
+            FieldElement::from(25)
+        );
+
+        let mut other = Compiler::new();
+        other.add_signal(2, String::from("0.y"), None).unwrap();
+        assert!(matches!(
+            compiler.merge(other),
+            Err(CircuitError::SignalIdCollision(2))
+        ));
+
+        // Circuits compiled with other settings aren't merged
+        for other in [
+            Compiler {
+                prime: Prime::Goldilocks,
+                ..Compiler::new()
+            },
+            Compiler {
+                value_type: ValueType::Uint,
+                ..Compiler::new()
+            },
+            Compiler {
+                target: Target::Mpz,
+                ..Compiler::new()
+            },
+            Compiler {
+                signed_gates: true,
+                ..Compiler::new()
+            },
+        ] {
+            assert!(matches!(
+                compiler.merge(other),
+                Err(CircuitError::Inconsistency { .. })
+            ));
+        }
+
+        let mut other = Compiler::new();
+        other.pruned_inputs.push(String::from("0.unused"));
+        compiler.merge(other).unwrap();
+        assert_eq!(compiler.pruned_inputs, vec![String::from("0.unused")]);
+    }
+
+    #[test]
+    fn test_compiler_merge_instance() {
+        // The wiring circuit declares the IO of the component, 11 and 12, to connect it
         let mut compiler = Compiler::new();
+        compiler.add_signal(1, String::from("0.a"), None).unwrap();
+        compiler.add_signal(2, String::from("0.out"), None).unwrap();
         compiler
-            .add_signal(1, String::from("signal1"), None)
+            .add_signal(11, String::from("0.c.in"), None)
             .unwrap();
         compiler
-            .add_signal(2, String::from("filter_signal"), None)
+            .add_signal(12, String::from("0.c.out"), None)
             .unwrap();
-        let filtered_signals = compiler.get_signals(String::from("filter"));
+        compiler.add_gate(AGateType::AAdd, 1, 1, 11).unwrap();
+        compiler.add_aliases(&[(2, 12)]);
+        compiler.add_inputs(HashMap::from([(1, String::from("0.a"))]));
+        compiler.add_outputs(HashMap::from([(2, String::from("0.out"))]));
 
-        assert_eq!(filtered_signals.len(), 1);
-        assert_eq!(filtered_signals[&2], "filter_signal");
+        let mut other = compiler.fork();
+        other.enter_template("Square");
+        other.add_signal(11, String::from("0.c.in"), None).unwrap();
+        other.add_signal(12, String::from("0.c.out"), None).unwrap();
+        other.add_gate(AGateType::AMul, 11, 11, 12).unwrap();
+
+        compiler.merge_instance(other, &[11, 12]).unwrap();
+        compiler.resolve_aliases().unwrap();
+        compiler.debug_assert_valid().unwrap();
+
+        // The shared signals keep the declaration of the component
+        assert_eq!(compiler.signals[&11].template, "Square");
+        assert_eq!(compiler.gates().len(), 2);
+        assert_eq!(
+            compiler
+                .evaluate_cone(
+                    "0.out",
+                    &HashMap::from([(String::from("0.a"), FieldElement::from(3))])
+                )
+                .unwrap()
+                .value,
+            FieldElement::from(36)
+        );
     }
 
     #[test]
-    fn test_compiler_add_gate() {
+    fn test_compiler_unused_inputs_passthrough() {
+        // out <== in, the input isn't read by a gate but is the only driver of the output
+        let mut compiler = Compiler::new();
+        compiler.add_signal(1, String::from("0.in"), None).unwrap();
+        compiler.add_signal(2, String::from("0.out"), None).unwrap();
+        compiler.add_connection(1, 2).unwrap();
+        compiler.add_inputs(HashMap::from([(1, String::from("0.in"))]));
+        compiler.add_outputs(HashMap::from([(2, String::from("0.out"))]));
+
+        assert!(compiler.unused_inputs().is_empty());
+    }
+
+    #[test]
+    fn test_compiler_prune_inputs() {
+        let mut compiler = Compiler::new();
+        compiler.add_signal(1, String::from("0.a"), None).unwrap();
+        compiler.add_signal(2, String::from("0.b"), None).unwrap();
+        compiler.add_signal(3, String::from("0.c"), None).unwrap();
+        compiler.add_signal(4, String::from("0.out"), None).unwrap();
+        compiler.add_gate(AGateType::AMul, 1, 1, 4).unwrap();
+        compiler.add_inputs(HashMap::from([
+            (1, String::from("0.a")),
+            (2, String::from("0.b")),
+            (3, String::from("0.c")),
+        ]));
+        compiler.add_outputs(HashMap::from([(4, String::from("0.out"))]));
+
+        let unused = compiler.unused_inputs();
+        assert_eq!(unused, vec![String::from("0.b"), String::from("0.c")]);
+
+        compiler.prune_inputs(&unused);
+        assert!(compiler.unused_inputs().is_empty());
+
+        let circuit = compiler.build_circuit().unwrap();
+        assert_eq!(circuit.wire_count, 2);
+        assert_eq!(
+            circuit.info.input_name_to_wire_index,
+            HashMap::from([(String::from("0.a"), 0)])
+        );
+        assert_eq!(
+            compiler.generate_circuit_report().unwrap().pruned_inputs,
+            unused
+        );
+    }
+
+    #[test]
+    fn test_compiler_constraint_outputs() {
+        // Checks of a == b and a == c, recorded in that order
+        let checked = |mode| {
+            let mut compiler = Compiler::new();
+            for (id, name) in ["0.a", "0.b", "0.c", "0.eq_b", "0.eq_c"].iter().enumerate() {
+                compiler
+                    .add_signal(id as u32, name.to_string(), None)
+                    .unwrap();
+            }
+            compiler.add_gate(AGateType::AEq, 0, 1, 3).unwrap();
+            compiler.add_gate(AGateType::AEq, 0, 2, 4).unwrap();
+            compiler.add_inputs(HashMap::from([
+                (0, String::from("0.a")),
+                (1, String::from("0.b")),
+                (2, String::from("0.c")),
+            ]));
+            compiler.add_constraint_check(3);
+            compiler.add_constraint_check(4);
+            compiler.add_constraint_outputs(mode).unwrap();
+            compiler.build_circuit().unwrap()
+        };
+        let inputs = HashMap::from([
+            (String::from("0.a"), 2),
+            (String::from("0.b"), 2),
+            (String::from("0.c"), 3),
+        ]);
+
+        let circuit = checked(ConstraintEquality::Output);
+        let outputs = evaluate_circuit(&circuit, &inputs, ValueType::Sint).unwrap();
+        assert_eq!(
+            outputs,
+            BTreeMap::from([
+                (String::from("0.constraints[0]"), 1),
+                (String::from("0.constraints[1]"), 0)
+            ])
+        );
+
+        let circuit = checked(ConstraintEquality::Assert);
+        assert_eq!(circuit.gates.len(), 3);
+        let outputs = evaluate_circuit(&circuit, &inputs, ValueType::Sint).unwrap();
+        assert_eq!(
+            outputs,
+            BTreeMap::from([(String::from("0.constraints_ok"), 0)])
+        );
+
+        let circuit = checked(ConstraintEquality::Ignore);
+        assert!(circuit.info.output_name_to_wire_index.is_empty());
+    }
+
+    #[test]
+    fn test_compiler_bind_constants() {
         let mut compiler = Compiler::new();
+        compiler.add_signal(1, String::from("0.x"), None).unwrap();
         compiler
-            .add_signal(1, String::from("signal1"), None)
+            .add_signal(2, String::from("0.w[0]"), None)
             .unwrap();
         compiler
-            .add_signal(2, String::from("signal2"), None)
+            .add_signal(3, String::from("0.w[1]"), None)
             .unwrap();
+        compiler.add_signal(4, String::from("0.out"), None).unwrap();
+        compiler.add_gate(AGateType::AMul, 1, 2, 4).unwrap();
+        compiler.add_inputs(HashMap::from([
+            (1, String::from("0.x")),
+            (2, String::from("0.w[0]")),
+            (3, String::from("0.w[1]")),
+        ]));
+        compiler.add_outputs(HashMap::from([(4, String::from("0.out"))]));
+
         compiler
-            .add_signal(3, String::from("signal3"), None)
+            .bind_constants(&HashMap::from([
+                (String::from("0.w[0]"), FieldElement::from(3)),
+                (String::from("0.w[1]"), FieldElement::from(4)),
+            ]))
             .unwrap();
+        assert_eq!(compiler.input_order, vec![1]);
 
-        let result = compiler.add_gate(AGateType::AAdd, 1, 2, 3);
+        let circuit = compiler.build_circuit().unwrap();
+        assert_eq!(
+            circuit.info.input_name_to_wire_index,
+            HashMap::from([(String::from("0.x"), 0)])
+        );
+        // The unused weight has no wire
+        assert_eq!(circuit.info.constants.len(), 1);
+        let constant = circuit.info.constants.values().next().unwrap();
+        assert_eq!(constant.value, "3");
+        assert_eq!(circuit.gates[0].inputs, vec![0, constant.wire_index]);
 
-        assert!(result.is_ok());
-        assert_eq!(compiler.gates.len(), 1);
-        let gate = &compiler.gates[0];
-        assert_eq!(gate.op, AGateType::AAdd);
-        assert_eq!(gate.lh_in, 1);
-        assert_eq!(gate.rh_in, 2);
-        assert_eq!(gate.out, 3);
+        let result = compiler.bind_constants(&HashMap::from([(
+            String::from("0.y"),
+            FieldElement::from(1),
+        )]));
+        assert!(matches!(result, Err(CircuitError::UnknownSignal(name)) if name == "0.y"));
     }
 
     #[test]
-    fn test_compiler_add_connection() {
+    fn test_party_for_input() {
+        let party_mapping = HashMap::from([(String::from("0.a"), 0), (String::from("0.a[1]"), 1)]);
+
+        assert_eq!(party_for_input(&party_mapping, "0.a"), Some(0));
+        assert_eq!(party_for_input(&party_mapping, "0.a[0][2]"), Some(0));
+        assert_eq!(party_for_input(&party_mapping, "0.a[1][2]"), Some(1));
+        assert_eq!(party_for_input(&party_mapping, "0.ab"), None);
+    }
+
+    #[test]
+    fn test_compiler_constant_outputs() {
         let mut compiler = Compiler::new();
+        compiler.add_signal(1, String::from("0.a"), None).unwrap();
         compiler
-            .add_signal(1, String::from("signal1"), None)
+            .add_signal(2, String::from("0.two"), Some(FieldElement::from(2)))
             .unwrap();
         compiler
-            .add_signal(2, String::from("signal2"), None)
+            .add_signal(3, String::from("0.three"), Some(FieldElement::from(3)))
             .unwrap();
+        compiler.add_signal(4, String::from("0.x"), None).unwrap();
+        compiler.add_signal(5, String::from("0.y"), None).unwrap();
+        compiler.add_signal(6, String::from("0.z"), None).unwrap();
+        compiler.add_gate(AGateType::AMul, 2, 3, 4).unwrap();
+        compiler.add_gate(AGateType::AAdd, 4, 1, 5).unwrap();
+        compiler.add_gate(AGateType::AIntDiv, 4, 2, 6).unwrap();
+        compiler.add_outputs(HashMap::from([
+            (4, String::from("0.x")),
+            (5, String::from("0.y")),
+            (6, String::from("0.z")),
+        ]));
+
+        assert_eq!(
+            compiler.constant_outputs(),
+            vec![
+                (String::from("0.x"), Some(FieldElement::from(6))),
+                (String::from("0.z"), Some(FieldElement::from(3)))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compiler_constant_outputs_deep_chain() {
+        // A chain deep enough to overflow the stack if evaluated recursively
+        const DEPTH: u32 = 100_000;
+        let mut compiler = Compiler::new();
         compiler
-            .add_signal(3, String::from("signal3"), None)
+            .add_signal(1, String::from("0.one"), Some(FieldElement::from(1)))
             .unwrap();
+        for id in 2..=DEPTH + 1 {
+            compiler.add_signal(id, format!("0.s{}", id), None).unwrap();
+            compiler.add_gate(AGateType::AAdd, id - 1, 1, id).unwrap();
+        }
+        compiler.add_outputs(HashMap::from([(DEPTH + 1, String::from("0.out"))]));
 
-        // Adding connection between signals 1 and 2
-        let result = compiler.add_connection(1, 2);
+        assert_eq!(
+            compiler.constant_outputs(),
+            vec![(String::from("0.out"), Some(FieldElement::from(DEPTH + 1)))]
+        );
+    }
 
-        assert!(result.is_ok());
-        assert_eq!(compiler.nodes.len(), 2);
+    #[test]
+    fn test_compiler_serde_primes() {
+        // -1 in the BLS12-381 field is above the BN254 modulus
+        let bls12381 = Prime::Bls12381;
+        let minus_one = FieldElement::from_integer(&BigUint::from(1u32), true, bls12381);
+        let mut compiler = Compiler::new();
+        compiler.set_prime(bls12381);
+        compiler
+            .add_signal(1, String::from("0.minus_one"), Some(minus_one.clone()))
+            .unwrap();
 
-        // Assert new node contains both signals
-        let node = compiler.nodes.get(&4).unwrap();
-        assert_eq!(node.signals.len(), 2);
-        assert!(node.contains_signal(&1));
-        assert!(node.contains_signal(&2));
+        let json = serde_json::to_string(&compiler).unwrap();
+        let mut read = serde_json::from_str::<Compiler>(&json).unwrap();
+        assert!(read.check_field_elements().is_ok());
+        assert_eq!(read.prime(), bls12381);
+        assert_eq!(read.signals[&1].value, Some(minus_one));
+
+        read.set_prime(Prime::Goldilocks);
+        assert_eq!(
+            read.check_field_elements().unwrap_err().to_string(),
+            format!(
+                "Value {} of 0.minus_one is out of range for the Goldilocks field",
+                modulus(bls12381) - 1u32
+            )
+        );
     }
 
     #[test]
-    fn test_compiler_add_connection_same_node() {
+    fn test_compiler_field_constants() {
         let mut compiler = Compiler::new();
+        let big = FieldElement::from_integer(&BigUint::from(10u32).pow(18), false, Prime::Bn128);
+        compiler.add_signal(1, String::from("0.a"), None).unwrap();
         compiler
-            .add_signal(1, String::from("signal1"), None)
+            .add_signal(2, String::from("0.big"), Some(big.clone()))
             .unwrap();
         compiler
-            .add_signal(2, String::from("signal2"), None)
+            .add_signal(3, String::from("0.one"), Some(FieldElement::from(1)))
             .unwrap();
+        compiler.add_signal(4, String::from("0.t"), None).unwrap();
+        compiler.add_signal(5, String::from("0.out"), None).unwrap();
+        compiler.add_inputs(HashMap::from([(1, String::from("0.a"))]));
+        compiler.add_outputs(HashMap::from([(5, String::from("0.out"))]));
+        compiler.add_gate(AGateType::AAdd, 2, 3, 4).unwrap();
+        compiler.add_gate(AGateType::AAdd, 1, 4, 5).unwrap();
 
-        compiler.add_connection(1, 2).unwrap();
-        // Connect the same node
-        let result = compiler.add_connection(1, 2);
+        // The big constant isn't a word, so its gate isn't folded
+        assert_eq!(compiler.fold_constants(), 0);
+        assert!(matches!(
+            compiler.build_circuit(),
+            Err(CircuitError::ValueOutOfRange { value, .. }) if value == big.to_string()
+        ));
 
-        assert!(result.is_ok());
-        // No change in number of nodes
-        assert_eq!(compiler.nodes.len(), 1);
+        compiler.set_field_constants(true);
+        let circuit = compiler.build_circuit().unwrap();
+        let mut values = circuit
+            .info
+            .constants
+            .values()
+            .map(|constant| constant.value.clone())
+            .collect::<Vec<_>>();
+        values.sort();
+        assert_eq!(values, ["1", "1000000000000000000"]);
     }
 
     #[test]
-    fn test_compiler_add_connection_output_nodes() {
+    fn test_compiler_constant_range() {
         let mut compiler = Compiler::new();
+        compiler.add_signal(1, String::from("0.a"), None).unwrap();
         compiler
-            .add_signal(1, String::from("signal1"), None)
+            .add_signal(2, String::from("0.big"), Some(FieldElement::from(200)))
             .unwrap();
+        let neg = compiler.word_element(-300i32 as u32);
         compiler
-            .add_signal(2, String::from("signal2"), None)
+            .add_signal(3, String::from("0.neg"), Some(neg))
             .unwrap();
+        compiler.add_signal(4, String::from("0.x"), None).unwrap();
+        compiler.add_signal(5, String::from("0.y"), None).unwrap();
+        compiler.add_gate(AGateType::AAdd, 1, 2, 4).unwrap();
+        compiler.add_gate(AGateType::AMul, 2, 3, 5).unwrap();
 
-        // Set both nodes as output nodes
-        compiler.nodes.get_mut(&1).unwrap().set_output(true);
-        compiler.nodes.get_mut(&2).unwrap().set_output(true);
+        // 200 * -300 is computed at compile time
+        let range = compiler.constant_range().unwrap();
+        assert_eq!(range.max_magnitude(), &FieldElement::from(60000));
+        assert_eq!(range.width(), 17);
 
-        let result = compiler.add_connection(1, 2);
+        assert_eq!(Compiler::new().constant_range(), None);
+    }
+
+    #[test]
+    fn test_constant_range_of() {
+        let range = |value: u32, value_type| {
+            let value = FieldElement::from_word(value, value_type, Prime::Bn128);
+            ConstantRange::of(&value, value_type, Prime::Bn128)
+        };
+        let width = |value: u32, value_type| range(value, value_type).width();
+        assert_eq!(width(0, ValueType::Sint), 1);
+        assert_eq!(width(7, ValueType::Sint), 4);
+        assert_eq!(width(-8i32 as u32, ValueType::Sint), 4);
+        assert_eq!(width(-9i32 as u32, ValueType::Sint), 5);
+        assert_eq!(width(i32::MIN as u32, ValueType::Sint), 32);
+        assert_eq!(width(0, ValueType::Uint), 1);
+        assert_eq!(width(7, ValueType::Uint), 3);
+        assert_eq!(width(u32::MAX, ValueType::Uint), 32);
 
-        assert!(matches!(result, Err(CircuitError::CannotMergeOutputNodes)));
+        // Field values aren't bounded by a word
+        let big = FieldElement::from_integer(&(BigUint::from(1u32) << 40), true, Prime::Bn128);
+        let big = ConstantRange::of(&big, ValueType::Sint, Prime::Bn128);
+        assert_eq!(big.width(), 41);
+
+        let eight = range(-8i32 as u32, ValueType::Sint);
+        assert_eq!(eight.max_magnitude(), &FieldElement::from(8));
+        assert_eq!(
+            eight.union(range(100, ValueType::Sint)),
+            ConstantRange {
+                max_magnitude: FieldElement::from(100),
+                width: 8
+            }
+        );
     }
 
     #[test]
-    fn test_compiler_add_connection_constant_nodes() {
+    fn test_compiler_fanout_report() {
         let mut compiler = Compiler::new();
-        compiler
-            .add_signal(1, String::from("signal1"), Some(1))
-            .unwrap();
-        compiler
-            .add_signal(2, String::from("signal2"), Some(2))
-            .unwrap();
+        compiler.add_signal(1, String::from("0.w"), None).unwrap();
+        compiler.add_signal(2, String::from("0.a"), None).unwrap();
+        compiler.add_signal(3, String::from("0.b"), None).unwrap();
+        compiler.add_signal(4, String::from("0.x"), None).unwrap();
+        compiler.add_signal(5, String::from("0.y"), None).unwrap();
+        compiler.add_signal(6, String::from("0.z"), None).unwrap();
+        compiler.add_gate(AGateType::AMul, 1, 2, 4).unwrap();
+        compiler.add_gate(AGateType::AMul, 1, 3, 5).unwrap();
+        compiler.add_gate(AGateType::AMul, 1, 1, 6).unwrap();
 
-        let result = compiler.add_connection(1, 2);
-        assert!(matches!(
-            result,
-            Err(CircuitError::CannotMergeConstantNodes)
-        ));
+        let fanout = compiler.node_fanout();
+        assert_eq!(fanout[&1], 3);
+        assert_eq!(fanout[&2], 1);
+
+        let reports = compiler.generate_fanout_reports(HIGH_FANOUT_REPORT_SIZE);
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].node.id, 1);
+        assert_eq!(reports[0].node.names, vec![String::from("0.w")]);
+        assert_eq!(reports[0].fanout, 3);
+    }
+
+    #[test]
+    fn test_compiler_audit_log() {
+        let path = std::env::temp_dir().join("circom_2_arithc_compiler_audit.jsonl");
+        let _ = std::fs::remove_file(&path);
+
+        let mut compiler = Compiler::new();
+        assert!(!compiler.is_auditing());
+        assert_eq!(compiler.finish_audit_log().unwrap(), None);
+        compiler.set_audit_log(AuditLog::open(&path).unwrap());
+        assert!(compiler.is_auditing());
+
+        compiler.add_signal(1, String::from("0.a"), None).unwrap();
+        compiler.add_signal(2, String::from("0.b"), None).unwrap();
+        compiler.add_signal(3, String::from("0.c"), None).unwrap();
+        compiler.add_signal(4, String::from("0.out"), None).unwrap();
+        compiler.replace_source(Some(Arc::from("main.circom:5")));
+        compiler.add_gate(AGateType::AMul, 1, 2, 3).unwrap();
+        let outer = compiler.replace_source(None);
+        assert_eq!(outer.as_deref(), Some("main.circom:5"));
+        compiler.add_aliases(&[(4, 3)]);
+
+        let hash = compiler.finish_audit_log().unwrap().unwrap();
+        assert!(!compiler.is_auditing());
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let entries = content
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["entry"], "gate");
+        assert_eq!(entries[0]["op"], "AMul");
+        assert_eq!(entries[0]["out"], "0.c");
+        assert_eq!(entries[0]["source"], "main.circom:5");
+        assert_eq!(entries[1]["entry"], "connection");
+        assert_eq!(entries[1]["a"], "0.out");
+        assert_eq!(entries[1]["source"], serde_json::Value::Null);
+        assert_eq!(hash, format!("{:x}", Sha256::digest(content.as_bytes())));
     }
 }
@@ -0,0 +1,294 @@
+//! # Conversions Module
+//!
+//! This module plans the conversions between arithmetic and boolean shares of hybrid MPC
+//! protocols. Wires requested by name are given a boolean form next to their arithmetic one, and
+//! each pair is linked by an explicit `A2B` or `B2A` pseudo-gate, so the runtime knows exactly
+//! where conversions occur. The plan is written to `conversions.json`, and the number of each
+//! conversion is added to the gate counts of `report.json`.
+
+use crate::{program::ProgramError, signal_map::SignalMap};
+use bristol_circuit::BristolCircuit;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt, fs, path::Path};
+
+/// Direction of a conversion.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConversionKind {
+    /// The wire is computed on arithmetic shares, and its bits are needed as boolean shares.
+    #[default]
+    A2b,
+    /// The bits of the wire are computed on boolean shares, and its value is needed as an
+    /// arithmetic share.
+    B2a,
+}
+
+impl fmt::Display for ConversionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConversionKind::A2b => write!(f, "A2B"),
+            ConversionKind::B2a => write!(f, "B2A"),
+        }
+    }
+}
+
+/// Signals needing a conversion, e.g. `0.relu[*].in`. `*` matches any sequence of characters
+/// and `?` a single one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConversionRequest {
+    pub signal: String,
+    #[serde(default)]
+    pub kind: ConversionKind,
+}
+
+/// Requested conversions, `{ "width": 32, "wires": [...] }`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConversionConfig {
+    /// Number of bits of the boolean form of a wire.
+    #[serde(default = "default_width")]
+    pub width: usize,
+    #[serde(default)]
+    pub wires: Vec<ConversionRequest>,
+}
+
+fn default_width() -> usize {
+    32
+}
+
+impl ConversionConfig {
+    /// Reads a conversion config from a JSON file.
+    pub fn from_path(path: &Path) -> Result<Self, ProgramError> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+}
+
+/// A conversion pseudo-gate between the arithmetic wire of the circuit and the wires of its bits,
+/// numbered after the wires of the circuit.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Conversion {
+    pub kind: ConversionKind,
+    /// Name of the first signal of the wire matching a request.
+    pub name: String,
+    pub arithmetic_wire: usize,
+    /// Bits of the wire, least significant first.
+    pub boolean_wires: Vec<usize>,
+}
+
+impl Conversion {
+    /// Formats the conversion as a Bristol gate, e.g. `1 2 4 10 11 A2B` or `2 1 10 11 4 B2A`.
+    pub fn bristol_gate(&self) -> String {
+        let bits = self
+            .boolean_wires
+            .iter()
+            .map(|wire| wire.to_string())
+            .collect::<Vec<_>>()
+            .join(" ");
+        match self.kind {
+            ConversionKind::A2b => format!(
+                "1 {} {} {} {}",
+                self.boolean_wires.len(),
+                self.arithmetic_wire,
+                bits,
+                self.kind
+            ),
+            ConversionKind::B2a => format!(
+                "{} 1 {} {} {}",
+                self.boolean_wires.len(),
+                bits,
+                self.arithmetic_wire,
+                self.kind
+            ),
+        }
+    }
+}
+
+/// Conversions of a circuit, sorted by arithmetic wire, see `plan_conversions`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConversionPlan {
+    pub width: usize,
+    /// Number of wires of the circuit and of the boolean forms together.
+    pub wire_count: usize,
+    pub conversions: Vec<Conversion>,
+}
+
+impl ConversionPlan {
+    /// Returns the number of conversions of each kind, e.g. `A2B`.
+    pub fn counts(&self) -> BTreeMap<String, usize> {
+        let mut counts = BTreeMap::new();
+        for conversion in &self.conversions {
+            *counts.entry(conversion.kind.to_string()).or_default() += 1;
+        }
+
+        counts
+    }
+}
+
+/// Plans the conversions of the wires carrying the requested signals. A wire matched by several
+/// requests of the same kind is converted once, and requests matching no signal are rejected.
+pub fn plan_conversions(
+    circuit: &BristolCircuit,
+    signal_map: &SignalMap,
+    config: &ConversionConfig,
+) -> Result<ConversionPlan, ProgramError> {
+    if config.width == 0 {
+        return Err(ProgramError::InvalidConversions(String::from(
+            "the width must be at least 1",
+        )));
+    }
+
+    // Wires to convert, with the name of their first signal
+    let mut wires = BTreeMap::new();
+    for request in &config.wires {
+        let mut matched = false;
+        for entry in &signal_map.entries {
+            if glob_match(&request.signal, &entry.name) {
+                matched = true;
+                wires
+                    .entry((entry.wire, request.kind))
+                    .or_insert_with(|| entry.name.clone());
+            }
+        }
+        if !matched {
+            return Err(ProgramError::InvalidConversions(format!(
+                "{} matches no signal",
+                request.signal
+            )));
+        }
+    }
+
+    let mut next_wire = circuit.wire_count;
+    let conversions = wires
+        .into_iter()
+        .map(|((arithmetic_wire, kind), name)| {
+            let boolean_wires = (next_wire..next_wire + config.width).collect();
+            next_wire += config.width;
+            Conversion {
+                kind,
+                name,
+                arithmetic_wire,
+                boolean_wires,
+            }
+        })
+        .collect();
+
+    Ok(ConversionPlan {
+        width: config.width,
+        wire_count: next_wire,
+        conversions,
+    })
+}
+
+/// Returns whether a name matches a pattern, where `*` matches any sequence of characters and `?`
+/// a single one.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<char>>();
+    let name = name.chars().collect::<Vec<char>>();
+
+    // Position of the last `*` in the pattern, and of the name when it was reached
+    let mut star = None;
+    let (mut p, mut n) = (0, 0);
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                // Let the last `*` match one more character
+                Some((star_p, star_n)) => {
+                    star = Some((star_p, star_n + 1));
+                    p = star_p + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signal_map::SignalMapEntry;
+    use bristol_circuit::CircuitInfo;
+    use std::collections::HashMap;
+
+    fn entry(wire: usize, name: &str) -> SignalMapEntry {
+        SignalMapEntry {
+            wire,
+            name: name.to_string(),
+            template: String::from("Main"),
+        }
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("0.relu[*].in", "0.relu[12].in"));
+        assert!(glob_match("0.*", "0.a"));
+        assert!(glob_match("0.a[?]", "0.a[3]"));
+        assert!(glob_match("*out*", "0.layer.out[1]"));
+        assert!(!glob_match("0.a[?]", "0.a[10]"));
+        assert!(!glob_match("0.relu[*].in", "0.relu[1].out"));
+        assert!(!glob_match("0.a", "0.ab"));
+    }
+
+    #[test]
+    fn test_plan_conversions() {
+        let circuit = BristolCircuit {
+            wire_count: 5,
+            info: CircuitInfo {
+                input_name_to_wire_index: HashMap::new(),
+                constants: HashMap::new(),
+                output_name_to_wire_index: HashMap::new(),
+            },
+            gates: Vec::new(),
+            io_widths: None,
+        };
+        let signal_map = SignalMap::new(vec![
+            entry(2, "0.relu[0].in"),
+            entry(3, "0.relu[1].in"),
+            entry(3, "0.relu[1].alias"),
+            entry(4, "0.cmp"),
+        ]);
+        let config: ConversionConfig = serde_json::from_str(
+            r#"{
+                "width": 2,
+                "wires": [
+                    { "signal": "0.relu[*].in" },
+                    { "signal": "0.relu[1].*" },
+                    { "signal": "0.cmp", "kind": "b2a" }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let plan = plan_conversions(&circuit, &signal_map, &config).unwrap();
+        assert_eq!(plan.wire_count, 11);
+        assert_eq!(plan.conversions.len(), 3);
+        assert_eq!(plan.conversions[1].name, "0.relu[1].in");
+        assert_eq!(plan.conversions[0].bristol_gate(), "1 2 2 5 6 A2B");
+        assert_eq!(plan.conversions[2].bristol_gate(), "2 1 9 10 4 B2A");
+        assert_eq!(
+            plan.counts(),
+            BTreeMap::from([(String::from("A2B"), 2), (String::from("B2A"), 1)])
+        );
+
+        let config = ConversionConfig {
+            width: 2,
+            wires: vec![ConversionRequest {
+                signal: String::from("0.missing"),
+                kind: ConversionKind::A2b,
+            }],
+        };
+        assert!(matches!(
+            plan_conversions(&circuit, &signal_map, &config),
+            Err(ProgramError::InvalidConversions(_))
+        ));
+    }
+}
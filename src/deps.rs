@@ -0,0 +1,199 @@
+//! # Dependencies Module
+//!
+//! This module builds the include graph of a compilation, listing every parsed file with a hash of
+//! its content, so build systems can invalidate precisely.
+
+use crate::{
+    circom::parser::parse_project, cli::Args, naming::fnv1a, program::ProgramError,
+    sources::includes,
+};
+use circom_program_structure::file_definition::FileLibrary;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    path::{Component, Path, PathBuf},
+};
+
+/// A parsed file.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DependencyFile {
+    pub path: String,
+    /// FNV-1a hash of the file content, in hex.
+    pub hash: String,
+}
+
+/// An `include` of the file `to` from the file `from`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IncludeEdge {
+    pub from: String,
+    pub to: String,
+}
+
+/// Files parsed in a compilation and the includes between them, written to `deps.json`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DependencyGraph {
+    pub files: Vec<DependencyFile>,
+    pub includes: Vec<IncludeEdge>,
+}
+
+impl DependencyGraph {
+    /// Builds the graph from the path and source of each parsed file.
+    /// Includes are resolved relative to the including file, and only kept if the included file was parsed.
+    pub fn from_sources(sources: &[(String, String)]) -> Self {
+        let resolved_paths = sources
+            .iter()
+            .map(|(path, _)| resolve(Path::new(path)))
+            .collect::<Vec<PathBuf>>();
+
+        let mut graph = DependencyGraph::default();
+        for ((path, source), resolved_path) in sources.iter().zip(&resolved_paths) {
+            graph.files.push(DependencyFile {
+                path: path.clone(),
+                hash: format!("{:016x}", fnv1a(source.as_bytes())),
+            });
+
+            let dir = resolved_path.parent().unwrap_or(Path::new(""));
+            for include in includes(source) {
+                let included = resolve(&dir.join(include));
+                let to = resolved_paths.iter().position(|path| *path == included);
+                if let Some(to) = to {
+                    graph.includes.push(IncludeEdge {
+                        from: path.clone(),
+                        to: sources[to].0.clone(),
+                    });
+                }
+            }
+        }
+
+        graph
+    }
+
+    /// Builds the graph from the files registered by the parser.
+    pub fn from_file_library(file_library: &FileLibrary) -> Self {
+        let storage = file_library.to_storage();
+        let sources = (0..)
+            .map_while(|file_id| storage.get(file_id).ok())
+            .map(|file| (file.name().clone(), file.source().clone()))
+            .collect::<Vec<(String, String)>>();
+
+        Self::from_sources(&sources)
+    }
+
+    /// Keeps the files reached from `path` through includes, `path` included.
+    pub fn reachable_from(&self, path: &str) -> Self {
+        let mut reached = HashSet::from([path]);
+        let mut pending = vec![path];
+        while let Some(from) = pending.pop() {
+            for edge in self.includes.iter().filter(|edge| edge.from == from) {
+                if reached.insert(&edge.to) {
+                    pending.push(&edge.to);
+                }
+            }
+        }
+
+        DependencyGraph {
+            files: self
+                .files
+                .iter()
+                .filter(|file| reached.contains(file.path.as_str()))
+                .cloned()
+                .collect(),
+            includes: self
+                .includes
+                .iter()
+                .filter(|edge| reached.contains(edge.from.as_str()))
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+/// Parses the program of the given arguments and returns its dependency graph, without compiling it.
+pub fn dependency_graph(args: &Args) -> Result<DependencyGraph, ProgramError> {
+    let program_archive = parse_project(args)?;
+    Ok(DependencyGraph::from_file_library(
+        &program_archive.file_library,
+    ))
+}
+
+/// Canonicalizes the path, so different spellings of the same file match.
+/// Paths that don't exist are normalized lexically.
+fn resolve(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| {
+        let mut resolved = PathBuf::new();
+        for component in path.components() {
+            match component {
+                Component::CurDir => {}
+                Component::ParentDir if resolved.file_name().is_some() => {
+                    resolved.pop();
+                }
+                component => resolved.push(component),
+            }
+        }
+        resolved
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dependency_graph() {
+        let sources = vec![
+            (
+                "circuits/main.circom".to_string(),
+                "pragma circom 2.1.0;\ninclude \"lib/dense.circom\";\ninclude \"missing.circom\";\n"
+                    .to_string(),
+            ),
+            (
+                "circuits/lib/dense.circom".to_string(),
+                "pragma circom 2.1.0; include\"../relu.circom\";\n// include \"ignored.circom\";\n"
+                    .to_string(),
+            ),
+            ("circuits/relu.circom".to_string(), String::new()),
+        ];
+
+        let graph = DependencyGraph::from_sources(&sources);
+
+        assert_eq!(graph.files.len(), 3);
+        assert_eq!(graph.files[2].hash, "cbf29ce484222325");
+        assert_eq!(
+            graph.includes,
+            vec![
+                IncludeEdge {
+                    from: "circuits/main.circom".to_string(),
+                    to: "circuits/lib/dense.circom".to_string(),
+                },
+                IncludeEdge {
+                    from: "circuits/lib/dense.circom".to_string(),
+                    to: "circuits/relu.circom".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reachable_from() {
+        let sources = vec![
+            (
+                "main.circom".to_string(),
+                "include \"lib.circom\";\n".to_string(),
+            ),
+            ("lib.circom".to_string(), String::new()),
+            (
+                "other.circom".to_string(),
+                "include \"lib.circom\";\n".to_string(),
+            ),
+        ];
+
+        let graph = DependencyGraph::from_sources(&sources).reachable_from("main.circom");
+        let paths = graph
+            .files
+            .iter()
+            .map(|file| file.path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, ["main.circom", "lib.circom"]);
+        assert_eq!(graph.includes.len(), 1);
+    }
+}
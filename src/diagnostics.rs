@@ -0,0 +1,242 @@
+//! # Diagnostics Module
+//!
+//! This module describes the errors and warnings of a compilation as diagnostics, with a
+//! severity, a stable error code and the span of source they point at, and renders them with a
+//! snippet of the source for the CLI.
+
+use crate::program::{ProgramError, SourceLocation};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Severity of a diagnostic.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    #[default]
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// Source a diagnostic points at, the start of a statement.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Span {
+    pub file: String,
+    pub line: usize,
+    /// Column, from 1.
+    pub column: usize,
+    /// Source line the span starts on.
+    pub text: String,
+}
+
+impl From<&SourceLocation> for Span {
+    fn from(location: &SourceLocation) -> Self {
+        Self {
+            file: location.file.clone(),
+            line: location.line,
+            column: location.column,
+            text: location.text.clone(),
+        }
+    }
+}
+
+/// An error or a warning of a compilation, along with the context it occurred in.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    #[serde(default)]
+    pub severity: Severity,
+    /// Code of the error, see `ProgramError::code`. Warnings have none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    /// Name of the template instance the diagnostic occurred in, e.g. `0.c[2]`. Empty if it
+    /// didn't occur while processing an instance.
+    pub context: String,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub span: Option<Span>,
+    /// Additional lines, e.g. the statements enclosing the span and the causes of the error.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    /// Creates an error diagnostic without a code or a span.
+    pub fn error(context: String, message: String) -> Self {
+        Self {
+            severity: Severity::Error,
+            code: None,
+            context,
+            message,
+            span: None,
+            notes: Vec::new(),
+        }
+    }
+
+    /// Creates a warning diagnostic.
+    pub fn warning(message: String) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(String::new(), message)
+        }
+    }
+
+    /// Describes an error: its code, its message, the innermost statement it occurred in as the
+    /// span, and the enclosing statements and the causes as notes.
+    pub fn from_error(error: &ProgramError) -> Self {
+        let locations = error.locations();
+        let mut notes = locations
+            .iter()
+            .skip(1)
+            .map(|location| format!("in {}", location))
+            .collect::<Vec<_>>();
+        notes.extend(
+            error
+                .causes()
+                .into_iter()
+                .map(|cause| format!("caused by: {}", cause)),
+        );
+
+        Self {
+            severity: Severity::Error,
+            code: Some(error.code().to_string()),
+            context: locations
+                .first()
+                .map_or_else(String::new, |location| location.instance.clone()),
+            message: error.root().to_string(),
+            span: locations.first().map(|&location| location.into()),
+            notes,
+        }
+    }
+
+    /// Returns the diagnostics of an error: those recorded in recovery mode, or the error itself.
+    pub fn from_errors(error: &ProgramError) -> Vec<Self> {
+        match error.root() {
+            ProgramError::Diagnostics(diagnostics) => diagnostics.clone(),
+            _ => vec![Self::from_error(error)],
+        }
+    }
+}
+
+/// Renders the diagnostic like rustc does, with the source line it points at, e.g.
+///
+/// ```text
+/// error[E0423]: Runtime error: Index out of bounds
+///   --> circuit.circom:9:9
+///    |
+///  9 |         out[i] <== in[i + 1];
+///    |         ^
+///    = in 0
+/// ```
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.severity)?;
+        if let Some(code) = &self.code {
+            write!(f, "[{}]", code)?;
+        }
+        write!(f, ": {}", self.message)?;
+
+        let width = self
+            .span
+            .as_ref()
+            .map_or(1, |span| span.line.to_string().len());
+        let pad = " ".repeat(width);
+        if let Some(span) = &self.span {
+            write!(
+                f,
+                "\n{}--> {}:{}:{}",
+                pad, span.file, span.line, span.column
+            )?;
+            if !span.text.is_empty() {
+                // Tabs are kept so the caret lines up with the source
+                let indent = span
+                    .text
+                    .chars()
+                    .take(span.column.saturating_sub(1))
+                    .map(|c| if c == '\t' { '\t' } else { ' ' })
+                    .collect::<String>();
+                write!(f, "\n{} |", pad)?;
+                write!(f, "\n{} | {}", span.line, span.text)?;
+                write!(f, "\n{} | {}^", pad, indent)?;
+            }
+        }
+        if !self.context.is_empty() {
+            write!(f, "\n{} = in {}", pad, self.context)?;
+        }
+        for note in &self.notes {
+            write!(f, "\n{} = {}", pad, note)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::RuntimeError;
+
+    fn location(line: usize, text: &str) -> SourceLocation {
+        SourceLocation {
+            file: String::from("circuit.circom"),
+            line,
+            column: 5,
+            text: text.to_string(),
+            instance: String::from("0.c"),
+        }
+    }
+
+    #[test]
+    fn test_diagnostic_from_error() {
+        let error = ProgramError::RuntimeError(RuntimeError::IndexOutOfBounds)
+            .in_statement(location(12, "    out <== in[3];"))
+            .in_statement(location(11, "    for (var i = 0; i < 2; i++) {"));
+        let diagnostic = Diagnostic::from_error(&error);
+
+        assert_eq!(diagnostic.severity, Severity::Error);
+        assert_eq!(diagnostic.code.as_deref(), Some("E0423"));
+        assert_eq!(diagnostic.context, "0.c");
+        assert_eq!(
+            diagnostic.to_string(),
+            "error[E0423]: Runtime error: Index out of bounds\n\
+             \x20 --> circuit.circom:12:5\n\
+             \x20  |\n\
+             12 |     out <== in[3];\n\
+             \x20  |     ^\n\
+             \x20  = in 0.c\n\
+             \x20  = in circuit.circom:11 (in 0.c)"
+        );
+        assert_eq!(Diagnostic::from_errors(&error), vec![diagnostic.clone()]);
+
+        let recovered = ProgramError::Diagnostics(vec![diagnostic.clone(), diagnostic]);
+        assert_eq!(Diagnostic::from_errors(&recovered).len(), 2);
+    }
+
+    #[test]
+    fn test_diagnostic_serialization() {
+        let warning = Diagnostic::warning(String::from("Output 0.out is constant"));
+        assert_eq!(warning.to_string(), "warning: Output 0.out is constant");
+
+        let json = serde_json::to_string(&warning).unwrap();
+        assert_eq!(
+            json,
+            r#"{"severity":"warning","context":"","message":"Output 0.out is constant"}"#
+        );
+        assert_eq!(serde_json::from_str::<Diagnostic>(&json).unwrap(), warning);
+
+        // Diagnostics written before severities and codes were added are errors
+        let diagnostic: Diagnostic =
+            serde_json::from_str(r#"{"context":"0","message":"Empty data item"}"#).unwrap();
+        assert_eq!(
+            diagnostic,
+            Diagnostic::error(String::from("0"), String::from("Empty data item"))
+        );
+    }
+}
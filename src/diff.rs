@@ -0,0 +1,471 @@
+//! # Diff Module
+//!
+//! This module compares two compilations of the same circuit family. The gates of the cone of an
+//! output are normalized into expressions over the named inputs and the constant values, so the
+//! comparison doesn't depend on wire numbering, and the added, removed and changed gates are
+//! rendered as a Graphviz DOT graph to see where a template refactor changed the circuit.
+
+use crate::{
+    a_gate_type::AGateType,
+    bristol_reader::{BristolLine, BristolReader},
+    compiler::CircuitError,
+    program::ProgramError,
+};
+use bristol_circuit::{BristolCircuit, CircuitInfo, Gate};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+/// How a node of the cone differs between the two circuits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Change {
+    Unchanged,
+    Added,
+    Removed,
+    /// Replaces a removed gate with the same operation or the same operands.
+    Changed,
+}
+
+impl Change {
+    /// Color of the nodes with this change in the DOT graph.
+    fn color(&self) -> &'static str {
+        match self {
+            Change::Unchanged => "gray",
+            Change::Added => "green",
+            Change::Removed => "red",
+            Change::Changed => "orange",
+        }
+    }
+}
+
+/// A node of the diffed cone: an input, a constant or a gate.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DiffNode {
+    /// Id of the normalized expression of the node, shared by both circuits.
+    pub id: usize,
+    /// Name of an input, value of a constant or operation of a gate.
+    pub label: String,
+    /// Ids of the operands of a gate.
+    pub operands: Vec<usize>,
+    pub is_gate: bool,
+    pub change: Change,
+    /// Operation of the gate a changed gate replaces, if it differs.
+    pub previous: Option<String>,
+}
+
+/// Differences between the cones of an output in two circuits, see `diff_cones`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConeDiff {
+    pub output: String,
+    /// Nodes of both cones, sorted by id.
+    pub nodes: Vec<DiffNode>,
+}
+
+impl ConeDiff {
+    /// Returns the number of gates of each change.
+    pub fn counts(&self) -> BTreeMap<Change, usize> {
+        let mut counts = BTreeMap::new();
+        for node in self.nodes.iter().filter(|node| node.is_gate) {
+            *counts.entry(node.change).or_default() += 1;
+        }
+
+        counts
+    }
+
+    /// Returns whether the cones are the same.
+    pub fn is_empty(&self) -> bool {
+        self.nodes
+            .iter()
+            .all(|node| node.change == Change::Unchanged)
+    }
+
+    /// Writes the cones as a DOT graph, from the inputs at the bottom to the output at the top.
+    /// Unchanged nodes are gray, added ones green, removed ones red and dashed, and changed gates
+    /// orange, labeled with the operation they replace.
+    pub fn write_dot<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "digraph diff {{")?;
+        writeln!(writer, "    rankdir=BT;")?;
+        writeln!(
+            writer,
+            "    label={:?};",
+            format!("Cone of {}", self.output)
+        )?;
+        for node in &self.nodes {
+            let label = match &node.previous {
+                Some(previous) => format!("{} (was {})", node.label, previous),
+                None => node.label.clone(),
+            };
+            let shape = if node.is_gate { "box" } else { "ellipse" };
+            let style = if node.change == Change::Removed {
+                ", style=dashed"
+            } else {
+                ""
+            };
+            writeln!(
+                writer,
+                "    n{} [label={:?}, shape={}, color={}{}];",
+                node.id,
+                label,
+                shape,
+                node.change.color(),
+                style
+            )?;
+            for operand in &node.operands {
+                writeln!(writer, "    n{} -> n{};", operand, node.id)?;
+            }
+        }
+        writeln!(writer, "}}")
+    }
+}
+
+/// Normalized expression of a wire.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Expression {
+    Input(String),
+    Constant(String),
+    Gate(String, Vec<usize>),
+    /// A wire neither input, constant nor produced by a gate.
+    Unknown(usize),
+}
+
+/// Ids of the expressions of both circuits, equal expressions getting the same id.
+#[derive(Default)]
+struct Interner {
+    ids: HashMap<Expression, usize>,
+    expressions: Vec<Expression>,
+}
+
+impl Interner {
+    fn intern(&mut self, expression: Expression) -> usize {
+        if let Some(&id) = self.ids.get(&expression) {
+            return id;
+        }
+
+        let id = self.expressions.len();
+        self.ids.insert(expression.clone(), id);
+        self.expressions.push(expression);
+        id
+    }
+
+    /// Returns the ids of the nodes of the cone of an output, i.e. the output and the nodes it
+    /// depends on. Gates are in topological order, as in every Bristol circuit, and are taken one
+    /// at a time, so a circuit read from a file doesn't have to be held in memory.
+    fn cone<E: From<CircuitError>>(
+        &mut self,
+        info: &CircuitInfo,
+        gates: impl IntoIterator<Item = Result<Gate, E>>,
+        output: &str,
+    ) -> Result<HashSet<usize>, E> {
+        let output_wire = *info
+            .output_name_to_wire_index
+            .get(output)
+            .ok_or_else(|| CircuitError::UnknownSignal(output.to_string()))?;
+
+        let mut wire_ids = HashMap::<usize, usize>::new();
+        let mut inputs = info.input_name_to_wire_index.iter().collect::<Vec<_>>();
+        inputs.sort();
+        for (name, &wire) in inputs {
+            wire_ids.insert(wire, self.intern(Expression::Input(name.clone())));
+        }
+        for constant in info.constants.values() {
+            let id = self.intern(Expression::Constant(constant.value.clone()));
+            wire_ids.insert(constant.wire_index, id);
+        }
+
+        for gate in gates {
+            let gate = gate?;
+            let mut operands = gate
+                .inputs
+                .iter()
+                .map(|&wire| match wire_ids.get(&wire) {
+                    Some(&id) => id,
+                    None => self.intern(Expression::Unknown(wire)),
+                })
+                .collect::<Vec<usize>>();
+            let commutative = AGateType::parse_bristol_op(&gate.op)
+                .is_some_and(|(op, constant)| constant.is_none() && op.is_commutative());
+            if commutative {
+                operands.sort_unstable();
+            }
+            let id = self.intern(Expression::Gate(gate.op, operands));
+            for &wire in &gate.outputs {
+                wire_ids.insert(wire, id);
+            }
+        }
+
+        let mut cone = HashSet::new();
+        let mut stack = vec![wire_ids
+            .get(&output_wire)
+            .copied()
+            .unwrap_or_else(|| self.intern(Expression::Unknown(output_wire)))];
+        while let Some(id) = stack.pop() {
+            if cone.insert(id) {
+                if let Expression::Gate(_, operands) = &self.expressions[id] {
+                    stack.extend(operands);
+                }
+            }
+        }
+
+        Ok(cone)
+    }
+
+    /// Compares the cones of an output in the old and the new circuit, see `diff_cones`.
+    fn diff(&self, old_cone: &HashSet<usize>, new_cone: &HashSet<usize>, output: &str) -> ConeDiff {
+        let mut nodes = old_cone
+            .union(new_cone)
+            .map(|&id| {
+                let (label, operands, is_gate) = match &self.expressions[id] {
+                    Expression::Input(name) => (name.clone(), Vec::new(), false),
+                    Expression::Constant(value) => (value.clone(), Vec::new(), false),
+                    Expression::Gate(op, operands) => (op.clone(), operands.clone(), true),
+                    Expression::Unknown(wire) => (format!("wire {}", wire), Vec::new(), false),
+                };
+                let change = match (old_cone.contains(&id), new_cone.contains(&id)) {
+                    (true, true) => Change::Unchanged,
+                    (true, false) => Change::Removed,
+                    _ => Change::Added,
+                };
+                DiffNode {
+                    id,
+                    label,
+                    operands,
+                    is_gate,
+                    change,
+                    previous: None,
+                }
+            })
+            .collect::<Vec<DiffNode>>();
+        nodes.sort_unstable_by_key(|node| node.id);
+
+        // Pair the removed gates with the added gates replacing them
+        let mut replaced = HashMap::new();
+        for removed in 0..nodes.len() {
+            if !nodes[removed].is_gate || nodes[removed].change != Change::Removed {
+                continue;
+            }
+            let replacement = nodes.iter().position(|node| {
+                let old = &nodes[removed];
+                node.is_gate
+                    && node.change == Change::Added
+                    && ((node.label == old.label
+                        && node.operands.iter().any(|id| old.operands.contains(id)))
+                        || (node.label != old.label && node.operands == old.operands))
+            });
+            if let Some(replacement) = replacement {
+                let previous = &nodes[removed].label;
+                nodes[replacement].previous =
+                    (*previous != nodes[replacement].label).then(|| previous.clone());
+                nodes[replacement].change = Change::Changed;
+                replaced.insert(nodes[removed].id, nodes[replacement].id);
+            }
+        }
+        // Removed gates read the gates replacing their removed operands
+        nodes.retain(|node| !replaced.contains_key(&node.id));
+        for node in &mut nodes {
+            for operand in &mut node.operands {
+                if let Some(&replacement) = replaced.get(operand) {
+                    *operand = replacement;
+                }
+            }
+        }
+
+        ConeDiff {
+            output: output.to_string(),
+            nodes,
+        }
+    }
+}
+
+/// Compares the cones of an output in two circuits. Gates computing the same expression are
+/// unchanged. A removed gate is paired with an added gate computing the same operation on an
+/// operand in common, or another operation on the same operands, and shown as changed.
+pub fn diff_cones(
+    old: &BristolCircuit,
+    new: &BristolCircuit,
+    output: &str,
+) -> Result<ConeDiff, CircuitError> {
+    let gates = |circuit: &BristolCircuit| circuit.gates.iter().cloned().map(Ok::<_, CircuitError>);
+    let mut interner = Interner::default();
+    let old_cone = interner.cone(&old.info, gates(old), output)?;
+    let new_cone = interner.cone(&new.info, gates(new), output)?;
+
+    Ok(interner.diff(&old_cone, &new_cone, output))
+}
+
+/// Compares the cones of an output in two Bristol circuits, each with its IO wires in
+/// `circuit_info.json` next to it. The circuits are read line by line with a `BristolReader`.
+pub fn diff_files(old: &Path, new: &Path, output: &str) -> Result<ConeDiff, ProgramError> {
+    let mut interner = Interner::default();
+    let mut cone = |path: &Path| -> Result<HashSet<usize>, ProgramError> {
+        let info: CircuitInfo = serde_json::from_str(&fs::read_to_string(
+            path.with_file_name("circuit_info.json"),
+        )?)?;
+        let gates = BristolReader::open(path)?.filter_map(|line| match line {
+            Ok(BristolLine::Gate {
+                inputs,
+                outputs,
+                op,
+            }) => Some(Ok(Gate {
+                inputs,
+                outputs,
+                op,
+            })),
+            Ok(_) => None,
+            Err(e) => Some(Err(e.into())),
+        });
+        interner.cone(&info, gates, output)
+    };
+    let old_cone = cone(old)?;
+    let new_cone = cone(new)?;
+
+    Ok(interner.diff(&old_cone, &new_cone, output))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bristol_circuit::{ConstantInfo, Gate};
+
+    fn gate(inputs: &[usize], output: usize, op: &str) -> Gate {
+        Gate {
+            inputs: inputs.to_vec(),
+            outputs: vec![output],
+            op: op.to_string(),
+        }
+    }
+
+    // out = (a * b) + c * 3, other = a - b
+    fn circuit(gates: Vec<Gate>, wire_count: usize) -> BristolCircuit {
+        BristolCircuit {
+            wire_count,
+            info: CircuitInfo {
+                input_name_to_wire_index: HashMap::from([
+                    (String::from("0.a"), 0),
+                    (String::from("0.b"), 1),
+                    (String::from("0.c"), 2),
+                ]),
+                constants: HashMap::from([(
+                    String::from("0.three"),
+                    ConstantInfo {
+                        value: String::from("3"),
+                        wire_index: 3,
+                    },
+                )]),
+                output_name_to_wire_index: HashMap::from([
+                    (String::from("0.out"), wire_count - 2),
+                    (String::from("0.other"), wire_count - 1),
+                ]),
+            },
+            gates,
+            io_widths: None,
+        }
+    }
+
+    #[test]
+    fn test_diff_cones() {
+        let old = circuit(
+            vec![
+                gate(&[0, 1], 4, "AMul"),
+                gate(&[2, 3], 5, "AMul"),
+                gate(&[4, 5], 6, "AAdd"),
+                gate(&[0, 1], 7, "ASub"),
+            ],
+            8,
+        );
+        // Renumbered, with commuted operands, and c * 3 replaced by c + 3
+        let new = circuit(
+            vec![
+                gate(&[0, 1], 7, "ASub"),
+                gate(&[2, 3], 4, "AAdd"),
+                gate(&[1, 0], 5, "AMul"),
+                gate(&[5, 4], 6, "AAdd"),
+            ],
+            8,
+        );
+
+        let same = diff_cones(&old, &old, "0.out").unwrap();
+        assert!(same.is_empty());
+        assert_eq!(same.counts(), BTreeMap::from([(Change::Unchanged, 3)]));
+
+        let diff = diff_cones(&old, &new, "0.out").unwrap();
+        assert!(!diff.is_empty());
+        // a * b is unchanged, c * 3 became c + 3, and the final addition reads it
+        assert_eq!(
+            diff.counts(),
+            BTreeMap::from([(Change::Unchanged, 1), (Change::Changed, 2)])
+        );
+        let changed = diff
+            .nodes
+            .iter()
+            .filter(|node| node.change == Change::Changed)
+            .map(|node| (node.label.as_str(), node.previous.as_deref()))
+            .collect::<Vec<_>>();
+        assert!(changed.contains(&("AAdd", Some("AMul"))));
+        assert!(changed.contains(&("AAdd", None)));
+        // The other output's cone is left out
+        assert!(diff.nodes.iter().all(|node| node.label != "ASub"));
+
+        let mut dot = Vec::new();
+        diff.write_dot(&mut dot).unwrap();
+        let dot = String::from_utf8(dot).unwrap();
+        assert!(dot.starts_with("digraph diff {"));
+        assert!(dot.contains("label=\"AAdd (was AMul)\", shape=box, color=orange"));
+        assert!(dot.contains("label=\"0.a\", shape=ellipse, color=gray"));
+
+        assert!(matches!(
+            diff_cones(&old, &new, "0.missing"),
+            Err(CircuitError::UnknownSignal(_))
+        ));
+    }
+
+    #[test]
+    fn test_diff_files() {
+        let old = circuit(
+            vec![
+                gate(&[0, 1], 4, "AMul"),
+                gate(&[2, 3], 5, "AMul"),
+                gate(&[4, 5], 6, "AAdd"),
+            ],
+            7,
+        );
+        let new = circuit(
+            vec![
+                gate(&[2, 3], 4, "AAdd"),
+                gate(&[1, 0], 5, "AMul"),
+                gate(&[5, 4], 6, "AAdd"),
+            ],
+            7,
+        );
+        let write = |name: &str, circuit: &BristolCircuit| {
+            let dir = std::env::temp_dir().join(name);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(
+                dir.join("circuit_info.json"),
+                serde_json::to_string(&circuit.info).unwrap(),
+            )
+            .unwrap();
+            let path = dir.join("circuit.txt");
+            circuit
+                .write_bristol(&mut fs::File::create(&path).unwrap())
+                .unwrap();
+            path
+        };
+        let old_path = write("circom_2_arithc_diff_old", &old);
+        let new_path = write("circom_2_arithc_diff_new", &new);
+
+        // Streaming the files gives the same diff as the circuits in memory
+        assert_eq!(
+            diff_files(&old_path, &new_path, "0.out").unwrap(),
+            diff_cones(&old, &new, "0.out").unwrap()
+        );
+        assert!(matches!(
+            diff_files(&old_path, &new_path, "0.missing"),
+            Err(ProgramError::CircuitError(CircuitError::UnknownSignal(_)))
+        ));
+    }
+}
@@ -0,0 +1,233 @@
+//! # Estimate Module
+//!
+//! This module predicts the gate and wire counts of a compiled program and the sizes of the
+//! artifacts it would be written to, without building the circuit or writing anything, to check
+//! quickly whether a large model fits the limits of an MPC engine.
+
+use crate::{
+    a_gate_type::AGateType,
+    cli::{Args, OutputFormat},
+    compiler::{ArithmeticGate, CircuitError, Compiler},
+    r1cs,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Bytes of a constant in `circuit_info.json`: its name, value and wire index, pretty-printed.
+const CONSTANT_INFO_BYTES: u64 = 64;
+
+/// Bytes of a constant in `circuit.json`: its name, value and wire index, compact.
+const CONSTANT_JSON_BYTES: u64 = 48;
+
+/// Predicted counts and artifact sizes of a compiled program, see `estimate_emission`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmissionEstimate {
+    pub format: OutputFormat,
+    pub gate_count: usize,
+    pub wire_count: usize,
+    pub input_count: usize,
+    pub output_count: usize,
+    pub constant_count: usize,
+    /// Predicted size in bytes of each circuit file, by file name.
+    pub files: BTreeMap<String, u64>,
+}
+
+impl EmissionEstimate {
+    /// Total predicted size in bytes of the circuit files.
+    pub fn total_bytes(&self) -> u64 {
+        self.files.values().sum()
+    }
+}
+
+/// Predicts the circuit and the sizes of the circuit files a compiled program is emitted as with
+/// the given arguments. The counts are exact, the sizes are within a few percent: wire ids are
+/// assumed to be spread evenly and constants to have short names.
+pub fn estimate_emission(
+    compiler: &Compiler,
+    args: &Args,
+) -> Result<EmissionEstimate, CircuitError> {
+    let wires = compiler.wire_counts();
+    let gates = compiler.gates();
+    let wire_digits = average_digits(wires.total as u64);
+
+    let io_names = compiler
+        .input_names()
+        .chain(compiler.output_names())
+        .map(|name| name.len() as u64)
+        .collect::<Vec<u64>>();
+
+    let mut files = BTreeMap::new();
+    match args.format {
+        OutputFormat::Bristol => {
+            // Gate and wire counts, then the width of each input and output
+            let mut bytes = digits(gates.len() as u64) + digits(wires.total as u64) + 2;
+            bytes += digits(wires.inputs as u64) + 2 * wires.inputs as u64 + 1;
+            bytes += digits(wires.outputs as u64) + 2 * wires.outputs as u64 + 2;
+            for gate in gates {
+                // `2 1 lhs rhs out op`, or `1 1 lhs out op:constant` with an inlined constant
+                let (inputs, op) = bristol_gate(compiler, gate);
+                bytes += 4 + (inputs + 1) * (wire_digits + 1) + op.len() as u64 + 1;
+            }
+            files.insert(String::from("circuit.txt"), bytes);
+        }
+        OutputFormat::Json => {
+            // Version and wire count, then `{"name":"...","wire":n},` per input and output
+            let mut bytes = 80 + digits(wires.total as u64);
+            bytes += io_names
+                .iter()
+                .map(|name| name + wire_digits + 20)
+                .sum::<u64>();
+            bytes += wires.constants as u64 * CONSTANT_JSON_BYTES;
+            for gate in gates {
+                // `{"op":"...","inputs":[lhs,rhs],"outputs":[out]},`
+                let (inputs, op) = bristol_gate(compiler, gate);
+                bytes += 34 + (inputs + 1) * wire_digits + inputs + op.len() as u64;
+            }
+            files.insert(String::from("circuit.json"), bytes);
+        }
+        OutputFormat::R1cs => {
+            let mut terms = 0;
+            for gate in gates {
+                terms += match gate.op {
+                    AGateType::AAdd | AGateType::ASub => 4,
+                    AGateType::AMul | AGateType::ACMul => 3,
+                    op => return Err(CircuitError::UnsupportedGateType(format!("{} in R1CS", op))),
+                };
+            }
+            // Constants become coefficients of the constant one wire
+            let wire_count = (wires.total - wires.constants) as u64 + 1;
+            files.insert(
+                String::from("circuit.r1cs"),
+                r1cs::file_size(wire_count, gates.len() as u64, terms, args.prime),
+            );
+
+            // `wire,wire,0,name` per input and output
+            let sym_digits = average_digits(wire_count);
+            let sym = io_names.iter().map(|name| 2 * sym_digits + 5 + name).sum();
+            files.insert(String::from("circuit.sym"), sym);
+        }
+    }
+
+    // `"name": wire,` per input and output, the constants, then the prime and target
+    let info = 64
+        + io_names
+            .iter()
+            .map(|name| name + wire_digits + 9)
+            .sum::<u64>()
+        + wires.constants as u64 * CONSTANT_INFO_BYTES;
+    files.insert(String::from("circuit_info.json"), info);
+
+    Ok(EmissionEstimate {
+        format: args.format,
+        gate_count: gates.len(),
+        wire_count: wires.total,
+        input_count: wires.inputs,
+        output_count: wires.outputs,
+        constant_count: wires.constants,
+        files,
+    })
+}
+
+/// Returns the number of input wires and the Bristol operation of a gate, with its constant
+/// operand inlined if it has one.
+fn bristol_gate(compiler: &Compiler, gate: &ArithmeticGate) -> (u64, String) {
+    match compiler.inlined_constant(gate) {
+        Some(constant) => (1, gate.op.bristol_op(Some(constant))),
+        None if gate.op == AGateType::ACMul => (2, AGateType::AMul.to_string()),
+        None => (2, gate.op.to_string()),
+    }
+}
+
+/// Returns the number of decimal digits of a number.
+fn digits(value: u64) -> u64 {
+    value.checked_ilog10().unwrap_or(0) as u64 + 1
+}
+
+/// Returns the average number of decimal digits of the numbers from 0 to `count - 1`, rounded up.
+fn average_digits(count: u64) -> u64 {
+    if count == 0 {
+        return 1;
+    }
+
+    let mut total = 0;
+    let mut start = 0;
+    let mut end = 10;
+    let mut width = 1;
+    while start < count {
+        total += (end.min(count) - start) * width;
+        start = end;
+        end = end.saturating_mul(10);
+        width += 1;
+    }
+
+    total.div_ceil(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{collections::HashMap, path::PathBuf};
+
+    fn args(format: OutputFormat) -> Args {
+        let mut args = Args::new(PathBuf::new(), PathBuf::new(), Default::default(), None);
+        args.format = format;
+        args
+    }
+
+    #[test]
+    fn test_average_digits() {
+        assert_eq!(digits(0), 1);
+        assert_eq!(digits(99), 2);
+        assert_eq!(digits(100), 3);
+        assert_eq!(average_digits(0), 1);
+        assert_eq!(average_digits(10), 1);
+        // 10 numbers of 1 digit and 90 of 2
+        assert_eq!(average_digits(100), 2);
+        assert_eq!(average_digits(1000), 3);
+    }
+
+    #[test]
+    fn test_estimate_emission() {
+        let mut compiler = Compiler::new();
+        compiler.add_inputs(HashMap::from([
+            (0, String::from("0.a")),
+            (1, String::from("0.b")),
+        ]));
+        compiler.add_outputs(HashMap::from([(3, String::from("0.out"))]));
+        for id in 0..5 {
+            compiler.add_signal(id, format!("s{}", id), None).unwrap();
+        }
+        compiler.add_gate(AGateType::AMul, 0, 1, 2).unwrap();
+        compiler.add_gate(AGateType::AAdd, 2, 0, 3).unwrap();
+
+        let estimate = estimate_emission(&compiler, &args(OutputFormat::Bristol)).unwrap();
+        assert_eq!(estimate.gate_count, 2);
+        assert_eq!(estimate.wire_count, 4);
+        assert_eq!(estimate.input_count, 2);
+        assert_eq!(estimate.output_count, 1);
+        assert_eq!(estimate.constant_count, 0);
+        // `2 4\n2 1 1\n1 1\n\n2 1 0 1 2 AMul\n2 1 2 0 3 AAdd\n`
+        assert_eq!(estimate.files["circuit.txt"], 45);
+        assert!(estimate.files.contains_key("circuit_info.json"));
+
+        let estimate = estimate_emission(&compiler, &args(OutputFormat::R1cs)).unwrap();
+        assert_eq!(
+            estimate.files["circuit.r1cs"],
+            r1cs::file_size(5, 2, 7, Default::default())
+        );
+        // `1,1,0,0.out\n2,2,0,0.a\n3,3,0,0.b\n`
+        assert_eq!(estimate.files["circuit.sym"], 32);
+        assert_eq!(estimate.total_bytes(), estimate.files.values().sum::<u64>());
+
+        let estimate = estimate_emission(&compiler, &args(OutputFormat::Json)).unwrap();
+        assert!(!estimate.files.contains_key("circuit.txt"));
+        // `{"op":"AMul","inputs":[0,1],"outputs":[2]},` per gate, after the IO names
+        assert!(estimate.files["circuit.json"] > 2 * 43 + 3 * 24);
+
+        compiler.add_gate(AGateType::ALt, 3, 0, 4).unwrap();
+        assert!(matches!(
+            estimate_emission(&compiler, &args(OutputFormat::R1cs)),
+            Err(CircuitError::UnsupportedGateType(_))
+        ));
+    }
+}
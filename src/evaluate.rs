@@ -0,0 +1,691 @@
+//! # Evaluate Module
+//!
+//! This module evaluates the Bristol circuits written by the compiler on concrete input values,
+//! so the outputs of a compiled circuit can be checked, or a witness computed, without
+//! implementing an executor. Gates have the same semantics as at compile time, see
+//! `AGateType::evaluate`. Watchpoints record the values of named signals as gates compute them,
+//! and can stop the evaluation on a condition, to chase down wrong intermediate values.
+
+use crate::{
+    a_gate_type::AGateType,
+    bristol_reader::{BristolLine, BristolReadError, BristolReader},
+    cli::{NameNormalization, ValueType},
+    io_names::resolve_names,
+    logging::EMIT,
+    program::{flatten_values, ProgramError},
+    signal_map::SignalMap,
+};
+use bristol_circuit::{BristolCircuit, CircuitInfo};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    fs::{self, File},
+    io::{BufRead, BufReader},
+    path::Path,
+    str::FromStr,
+};
+use thiserror::Error;
+
+/// Values of the wires of a circuit being evaluated, gate by gate.
+struct Evaluator {
+    wires: Vec<Option<u32>>,
+    value_type: ValueType,
+    gates: usize,
+}
+
+impl Evaluator {
+    /// Assigns the inputs, keyed by name, and the constants of a circuit to their wires.
+    fn new(
+        wire_count: usize,
+        info: &CircuitInfo,
+        inputs: &HashMap<String, u32>,
+        value_type: ValueType,
+    ) -> Result<Self, EvaluateError> {
+        let mut evaluator = Self {
+            wires: vec![None; wire_count],
+            value_type,
+            gates: 0,
+        };
+
+        for (name, &value) in inputs {
+            let wire = info
+                .input_name_to_wire_index
+                .get(name)
+                .ok_or_else(|| EvaluateError::UnknownInput(name.clone()))?;
+            evaluator.write(*wire, value)?;
+        }
+        for (name, &wire) in &info.input_name_to_wire_index {
+            if !inputs.contains_key(name) {
+                return Err(EvaluateError::MissingInput(name.clone()));
+            }
+            evaluator.read(wire)?;
+        }
+        for (name, constant) in &info.constants {
+            let value = constant
+                .value
+                .parse::<u32>()
+                .map_err(|_| EvaluateError::InvalidConstant(name.clone()))?;
+            evaluator.write(constant.wire_index, value)?;
+        }
+
+        Ok(evaluator)
+    }
+
+    /// Evaluates the next gate. Gates are expected in topological order, as they are written.
+    fn evaluate_gate(
+        &mut self,
+        op: &str,
+        inputs: &[usize],
+        outputs: &[usize],
+    ) -> Result<(), EvaluateError> {
+        let unsupported = || EvaluateError::UnsupportedGate(op.to_string());
+        let (gate_type, constant) = AGateType::parse_bristol_op(op).ok_or_else(unsupported)?;
+        let (lhs, rhs, out) = match (inputs, outputs, constant) {
+            (&[lhs, rhs], &[out], None) => (self.read(lhs)?, self.read(rhs)?, out),
+            // Multiplications by a constant carry it inline
+            (&[lhs], &[out], Some(constant)) => (self.read(lhs)?, constant, out),
+            _ => return Err(unsupported()),
+        };
+
+        let value = gate_type
+            .evaluate(lhs, rhs, self.value_type)
+            .ok_or(EvaluateError::UndefinedValue(self.gates))?;
+        self.write(out, value)?;
+        self.gates += 1;
+
+        Ok(())
+    }
+
+    /// Returns the value of each output, keyed by name.
+    fn outputs(&self, info: &CircuitInfo) -> Result<BTreeMap<String, u32>, EvaluateError> {
+        info.output_name_to_wire_index
+            .iter()
+            .map(|(name, &wire)| Ok((name.clone(), self.read(wire)?)))
+            .collect()
+    }
+
+    fn read(&self, wire: usize) -> Result<u32, EvaluateError> {
+        self.wires
+            .get(wire)
+            .copied()
+            .flatten()
+            .ok_or(EvaluateError::UnsetWire(wire))
+    }
+
+    fn write(&mut self, wire: usize, value: u32) -> Result<(), EvaluateError> {
+        let slot = self
+            .wires
+            .get_mut(wire)
+            .ok_or(EvaluateError::UnsetWire(wire))?;
+        *slot = Some(value);
+        Ok(())
+    }
+}
+
+/// Comparison of a break condition.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CompareOp {
+    /// Operators by their symbol, two-character symbols first so `<=` isn't read as `<`.
+    const SYMBOLS: [(&'static str, CompareOp); 6] = [
+        ("==", CompareOp::Eq),
+        ("!=", CompareOp::Ne),
+        ("<=", CompareOp::Le),
+        (">=", CompareOp::Ge),
+        ("<", CompareOp::Lt),
+        (">", CompareOp::Gt),
+    ];
+
+    fn holds(self, lhs: i64, rhs: i64) -> bool {
+        match self {
+            CompareOp::Eq => lhs == rhs,
+            CompareOp::Ne => lhs != rhs,
+            CompareOp::Lt => lhs < rhs,
+            CompareOp::Le => lhs <= rhs,
+            CompareOp::Gt => lhs > rhs,
+            CompareOp::Ge => lhs >= rhs,
+        }
+    }
+}
+
+/// A named signal whose value is recorded every time a gate computes its wire, e.g. `0.relu.out`.
+/// With a condition, e.g. `0.relu.out>=1000`, the evaluation stops at the first gate where it
+/// holds. Values are compared as signed integers with the `sint` value type.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub name: String,
+    pub condition: Option<(CompareOp, i64)>,
+}
+
+impl FromStr for Watchpoint {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        for (symbol, op) in CompareOp::SYMBOLS {
+            if let Some((name, value)) = spec.split_once(symbol) {
+                let value = value
+                    .trim()
+                    .parse::<i64>()
+                    .map_err(|_| format!("expected NAME{}VALUE, found {:?}", symbol, spec))?;
+                return Ok(Watchpoint {
+                    name: name.trim().to_string(),
+                    condition: Some((op, value)),
+                });
+            }
+        }
+
+        Ok(Watchpoint {
+            name: spec.trim().to_string(),
+            condition: None,
+        })
+    }
+}
+
+/// A value computed for a watched signal.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WatchHit {
+    /// Index of the gate that computed the value.
+    pub gate: usize,
+    pub name: String,
+    pub wire: usize,
+    /// Value, signed with the `sint` value type.
+    pub value: i64,
+    /// Whether the condition of the watchpoint holds, stopping the evaluation.
+    pub breaks: bool,
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "gate {}: {} (wire {}) = {}",
+            self.gate, self.name, self.wire, self.value
+        )
+    }
+}
+
+/// Watchpoints resolved to wires, with the values recorded so far.
+#[derive(Debug, Default)]
+pub struct Watches {
+    by_wire: HashMap<usize, Vec<Watchpoint>>,
+    hits: Vec<WatchHit>,
+    /// Index of the first hit of the last gate whose condition holds.
+    break_index: Option<usize>,
+}
+
+impl Watches {
+    /// Resolves watchpoints to wires, by IO name or through the signal map for intermediate
+    /// signals.
+    pub fn new(
+        watchpoints: &[Watchpoint],
+        info: &CircuitInfo,
+        signal_map: Option<&SignalMap>,
+    ) -> Result<Self, EvaluateError> {
+        let mut watches = Self::default();
+        for watchpoint in watchpoints {
+            let name = watchpoint.name.as_str();
+            let wire = info
+                .input_name_to_wire_index
+                .get(name)
+                .or_else(|| info.output_name_to_wire_index.get(name))
+                .copied()
+                .or_else(|| {
+                    signal_map?
+                        .entries
+                        .iter()
+                        .find(|entry| entry.name == name)
+                        .map(|entry| entry.wire)
+                })
+                .ok_or_else(|| EvaluateError::UnknownSignal(name.to_string()))?;
+            watches
+                .by_wire
+                .entry(wire)
+                .or_default()
+                .push(watchpoint.clone());
+        }
+
+        Ok(watches)
+    }
+
+    /// Returns the values recorded so far, in evaluation order.
+    pub fn hits(&self) -> &[WatchHit] {
+        &self.hits
+    }
+
+    /// Returns the hit that stopped the evaluation at the last gate, if any.
+    pub fn break_hit(&self) -> Option<&WatchHit> {
+        self.hits.get(self.break_index?)
+    }
+
+    /// Records the watched outputs of the last evaluated gate, returning whether a condition holds.
+    fn record(&mut self, evaluator: &Evaluator, outputs: &[usize]) -> bool {
+        self.break_index = None;
+        for wire in outputs {
+            let (Some(watchpoints), Ok(value)) = (self.by_wire.get(wire), evaluator.read(*wire))
+            else {
+                continue;
+            };
+            let value = if evaluator.value_type.is_signed() {
+                value as i32 as i64
+            } else {
+                value as i64
+            };
+            for watchpoint in watchpoints {
+                let breaks = watchpoint
+                    .condition
+                    .is_some_and(|(op, rhs)| op.holds(value, rhs));
+                if breaks && self.break_index.is_none() {
+                    self.break_index = Some(self.hits.len());
+                }
+                self.hits.push(WatchHit {
+                    gate: evaluator.gates - 1,
+                    name: watchpoint.name.clone(),
+                    wire: *wire,
+                    value,
+                    breaks,
+                });
+            }
+        }
+
+        self.break_index.is_some()
+    }
+}
+
+/// Evaluates a circuit one gate at a time, recording the watched signals.
+pub struct Stepper<'a> {
+    circuit: &'a BristolCircuit,
+    evaluator: Evaluator,
+    watches: Watches,
+}
+
+impl<'a> Stepper<'a> {
+    /// Assigns the inputs, keyed by name, and the constants of a circuit, before its first gate.
+    pub fn new(
+        circuit: &'a BristolCircuit,
+        inputs: &HashMap<String, u32>,
+        value_type: ValueType,
+        watches: Watches,
+    ) -> Result<Self, EvaluateError> {
+        Ok(Self {
+            circuit,
+            evaluator: Evaluator::new(circuit.wire_count, &circuit.info, inputs, value_type)?,
+            watches,
+        })
+    }
+
+    /// Returns the index of the next gate to evaluate.
+    pub fn position(&self) -> usize {
+        self.evaluator.gates
+    }
+
+    /// Evaluates the next gate, returning whether a watch condition holds, or `None` once every
+    /// gate is evaluated.
+    pub fn step(&mut self) -> Result<Option<bool>, EvaluateError> {
+        let Some(gate) = self.circuit.gates.get(self.evaluator.gates) else {
+            return Ok(None);
+        };
+        self.evaluator
+            .evaluate_gate(&gate.op, &gate.inputs, &gate.outputs)?;
+
+        Ok(Some(self.watches.record(&self.evaluator, &gate.outputs)))
+    }
+
+    /// Evaluates gates until a watch condition holds, returning its hit, or every gate is
+    /// evaluated. Evaluation continues after the break with the next call.
+    pub fn run(&mut self) -> Result<Option<&WatchHit>, EvaluateError> {
+        while let Some(breaks) = self.step()? {
+            if breaks {
+                return Ok(self.watches.break_hit());
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Returns the value of a wire, if it has been computed.
+    pub fn value(&self, wire: usize) -> Option<u32> {
+        self.evaluator.read(wire).ok()
+    }
+
+    pub fn watches(&self) -> &Watches {
+        &self.watches
+    }
+
+    /// Returns the value of each output, keyed by name, once every gate is evaluated.
+    pub fn outputs(&self) -> Result<BTreeMap<String, u32>, EvaluateError> {
+        self.evaluator.outputs(&self.circuit.info)
+    }
+}
+
+/// Evaluates a circuit on the given input values keyed by name, returning the value of each output
+/// keyed by name.
+pub fn evaluate_circuit(
+    circuit: &BristolCircuit,
+    inputs: &HashMap<String, u32>,
+    value_type: ValueType,
+) -> Result<BTreeMap<String, u32>, EvaluateError> {
+    let mut evaluator = Evaluator::new(circuit.wire_count, &circuit.info, inputs, value_type)?;
+    for gate in &circuit.gates {
+        evaluator.evaluate_gate(&gate.op, &gate.inputs, &gate.outputs)?;
+    }
+
+    evaluator.outputs(&circuit.info)
+}
+
+/// Same as `evaluate_circuit`, but reads the gates of a written Bristol circuit one at a time, so
+/// the circuit isn't loaded into memory.
+pub fn evaluate_bristol<R: BufRead>(
+    reader: BristolReader<R>,
+    info: &CircuitInfo,
+    inputs: &HashMap<String, u32>,
+    value_type: ValueType,
+) -> Result<BTreeMap<String, u32>, EvaluateError> {
+    watch_bristol(reader, info, inputs, value_type, &mut Watches::default())
+        .map(|outputs| outputs.unwrap_or_default())
+}
+
+/// Same as `evaluate_bristol`, recording the watched signals. Returns `None` if the evaluation
+/// stopped on a watch condition, see `Watches::break_hit`.
+pub fn watch_bristol<R: BufRead>(
+    reader: BristolReader<R>,
+    info: &CircuitInfo,
+    inputs: &HashMap<String, u32>,
+    value_type: ValueType,
+    watches: &mut Watches,
+) -> Result<Option<BTreeMap<String, u32>>, EvaluateError> {
+    let mut evaluator: Option<Evaluator> = None;
+    for line in reader {
+        match line? {
+            BristolLine::Header { wire_count, .. } => {
+                evaluator = Some(Evaluator::new(wire_count, info, inputs, value_type)?);
+            }
+            BristolLine::Gate {
+                inputs,
+                outputs,
+                op,
+            } => {
+                let evaluator = evaluator.as_mut().ok_or(EvaluateError::MissingHeader)?;
+                evaluator.evaluate_gate(&op, &inputs, &outputs)?;
+                if watches.record(evaluator, &outputs) {
+                    return Ok(None);
+                }
+            }
+            BristolLine::Inputs(_) | BristolLine::Outputs(_) => {}
+        }
+    }
+
+    evaluator
+        .ok_or(EvaluateError::MissingHeader)?
+        .outputs(info)
+        .map(Some)
+}
+
+/// Evaluates the Bristol circuit at `circuit`, with its IO wires in `info` (`circuit_info.json`),
+/// on the inputs of a JSON file. Inputs are keyed by name, and arrays of values are accepted like
+/// for `--weights`, e.g. `{"0.in": [1, 2]}`. Names that don't match an input exactly are matched
+/// after applying the given normalizations, with a warning.
+pub fn evaluate_files(
+    circuit: &Path,
+    info: &Path,
+    inputs: &Path,
+    value_type: ValueType,
+    normalizations: &[NameNormalization],
+) -> Result<BTreeMap<String, u32>, ProgramError> {
+    let (outputs, _) = watch_files(circuit, info, inputs, value_type, normalizations, &[], None)?;
+    Ok(outputs.unwrap_or_default())
+}
+
+/// Same as `evaluate_files`, recording the watched signals. Intermediate signals are resolved
+/// through the signal map at `signal_map` (`signals.sym`). Returns `None` for the outputs if the
+/// evaluation stopped on a watch condition, see `Watches::break_hit`.
+pub fn watch_files(
+    circuit: &Path,
+    info: &Path,
+    inputs: &Path,
+    value_type: ValueType,
+    normalizations: &[NameNormalization],
+    watchpoints: &[Watchpoint],
+    signal_map: Option<&Path>,
+) -> Result<(Option<BTreeMap<String, u32>>, Watches), ProgramError> {
+    let info: CircuitInfo = serde_json::from_str(&fs::read_to_string(info)?)?;
+    let inputs = resolve_names(
+        flatten_values(&serde_json::from_str(&fs::read_to_string(inputs)?)?)?,
+        info.input_name_to_wire_index.keys().map(String::as_str),
+        normalizations,
+    )?;
+    for (name, input) in &inputs.normalized {
+        warn!(
+            target: EMIT,
+            "Value of {} assigned to input {} after normalizing its name", name, input
+        );
+    }
+    let inputs = inputs.values;
+
+    let signal_map = signal_map
+        .map(|path| SignalMap::read(BufReader::new(File::open(path)?)))
+        .transpose()?;
+    let mut watches = Watches::new(watchpoints, &info, signal_map.as_ref())?;
+    let outputs = watch_bristol(
+        BristolReader::open(circuit)?,
+        &info,
+        &inputs,
+        value_type,
+        &mut watches,
+    )?;
+
+    Ok((outputs, watches))
+}
+
+/// Evaluation errors
+#[derive(Error, Debug)]
+pub enum EvaluateError {
+    #[error(transparent)]
+    BristolReadError(#[from] BristolReadError),
+    #[error("Invalid value for constant {0}")]
+    InvalidConstant(String),
+    #[error("Missing circuit header")]
+    MissingHeader,
+    #[error("Missing value for input {0}")]
+    MissingInput(String),
+    #[error("Undefined result of gate {0}, e.g. a division by zero")]
+    UndefinedValue(usize),
+    #[error("Unknown input {0}")]
+    UnknownInput(String),
+    #[error("Unknown signal {0}, intermediate signals need a signal map")]
+    UnknownSignal(String),
+    #[error("Wire {0} is read before it is assigned, or out of range")]
+    UnsetWire(usize),
+    #[error("Unsupported gate {0}")]
+    UnsupportedGate(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signal_map::SignalMapEntry;
+    use bristol_circuit::{ConstantInfo, Gate};
+
+    /// `out = (a - b) * 3`, and `b / a`.
+    fn circuit() -> BristolCircuit {
+        let gate = |inputs: Vec<usize>, output: usize, op: &str| Gate {
+            inputs,
+            outputs: vec![output],
+            op: op.to_string(),
+        };
+
+        BristolCircuit {
+            wire_count: 6,
+            info: CircuitInfo {
+                input_name_to_wire_index: HashMap::from([
+                    (String::from("0.a"), 0),
+                    (String::from("0.b"), 1),
+                ]),
+                constants: HashMap::from([(
+                    String::from("const_signal_3"),
+                    ConstantInfo {
+                        value: String::from("3"),
+                        wire_index: 2,
+                    },
+                )]),
+                output_name_to_wire_index: HashMap::from([
+                    (String::from("0.out"), 4),
+                    (String::from("0.quotient"), 5),
+                ]),
+            },
+            io_widths: None,
+            gates: vec![
+                gate(vec![0, 1], 3, "ASub"),
+                gate(vec![3, 2], 4, "AMul"),
+                gate(vec![1, 0], 5, "ADiv"),
+            ],
+        }
+    }
+
+    fn inputs(a: u32, b: u32) -> HashMap<String, u32> {
+        HashMap::from([(String::from("0.a"), a), (String::from("0.b"), b)])
+    }
+
+    #[test]
+    fn test_evaluate_circuit() {
+        let outputs = evaluate_circuit(&circuit(), &inputs(2, 7), ValueType::Sint).unwrap();
+        assert_eq!(
+            outputs,
+            BTreeMap::from([
+                (String::from("0.out"), -15i32 as u32),
+                (String::from("0.quotient"), 3)
+            ])
+        );
+    }
+
+    #[test]
+    fn test_evaluate_bristol() {
+        let bristol = "3 6\n2 1 1\n2 1 1\n\n2 1 0 1 3 ASub\n2 1 3 2 4 AMul\n2 1 1 0 5 ADiv\n";
+        let outputs = evaluate_bristol(
+            BristolReader::new(bristol.as_bytes()),
+            &circuit().info,
+            &inputs(2, 7),
+            ValueType::Sint,
+        )
+        .unwrap();
+        assert_eq!(
+            outputs,
+            evaluate_circuit(&circuit(), &inputs(2, 7), ValueType::Sint).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_watchpoint() {
+        assert_eq!(
+            "0.relu.out".parse::<Watchpoint>().unwrap(),
+            Watchpoint {
+                name: String::from("0.relu.out"),
+                condition: None
+            }
+        );
+        assert_eq!(
+            "0.relu.out <= -3".parse::<Watchpoint>().unwrap().condition,
+            Some((CompareOp::Le, -3))
+        );
+        assert_eq!(
+            "0.a!=0".parse::<Watchpoint>().unwrap().condition,
+            Some((CompareOp::Ne, 0))
+        );
+        assert!("0.a>=x".parse::<Watchpoint>().is_err());
+    }
+
+    #[test]
+    fn test_stepper() {
+        let circuit = circuit();
+        let signal_map = SignalMap::new(vec![SignalMapEntry {
+            wire: 3,
+            name: String::from("0.diff"),
+            template: String::from("Main"),
+        }]);
+        let watchpoints = ["0.diff<0", "0.out"].map(|spec| spec.parse().unwrap());
+        let watches = Watches::new(&watchpoints, &circuit.info, Some(&signal_map)).unwrap();
+        let mut stepper = Stepper::new(&circuit, &inputs(2, 7), ValueType::Sint, watches).unwrap();
+
+        // a - b = -5 breaks at the first gate, the evaluation then continues
+        let hit = stepper.run().unwrap().unwrap().clone();
+        assert_eq!((hit.gate, hit.wire, hit.value), (0, 3, -5));
+        assert_eq!(stepper.position(), 1);
+        assert_eq!(stepper.value(4), None);
+        assert_eq!(stepper.step().unwrap(), Some(false));
+        assert_eq!(stepper.value(4), Some(-15i32 as u32));
+        assert!(stepper.run().unwrap().is_none());
+        assert_eq!(stepper.step().unwrap(), None);
+        assert_eq!(
+            stepper.watches().hits()[1].to_string(),
+            "gate 1: 0.out (wire 4) = -15"
+        );
+        assert_eq!(
+            stepper.outputs().unwrap(),
+            evaluate_circuit(&circuit, &inputs(2, 7), ValueType::Sint).unwrap()
+        );
+
+        // Intermediate signals are only known through the signal map
+        assert!(matches!(
+            Watches::new(&watchpoints, &circuit.info, None),
+            Err(EvaluateError::UnknownSignal(name)) if name == "0.diff"
+        ));
+    }
+
+    #[test]
+    fn test_watch_bristol() {
+        let bristol = "3 6\n2 1 1\n2 1 1\n\n2 1 0 1 3 ASub\n2 1 3 2 4 AMul\n2 1 1 0 5 ADiv\n";
+        let watchpoints = ["0.out>10".parse().unwrap()];
+        let mut watches = Watches::new(&watchpoints, &circuit().info, None).unwrap();
+
+        // Unsigned values wrap, stopping before the division
+        let outputs = watch_bristol(
+            BristolReader::new(bristol.as_bytes()),
+            &circuit().info,
+            &inputs(2, 7),
+            ValueType::Uint,
+            &mut watches,
+        )
+        .unwrap();
+        assert!(outputs.is_none());
+        assert_eq!(watches.break_hit().unwrap().gate, 1);
+        assert_eq!(watches.hits().len(), 1);
+    }
+
+    #[test]
+    fn test_evaluate_errors() {
+        let circuit = circuit();
+        let error = |inputs: &HashMap<String, u32>| {
+            evaluate_circuit(&circuit, inputs, ValueType::Sint)
+                .unwrap_err()
+                .to_string()
+        };
+
+        assert_eq!(
+            error(&HashMap::from([(String::from("0.a"), 1)])),
+            "Missing value for input 0.b"
+        );
+        let mut unknown = inputs(1, 2);
+        unknown.insert(String::from("0.c"), 3);
+        assert_eq!(error(&unknown), "Unknown input 0.c");
+        assert_eq!(
+            error(&inputs(0, 2)),
+            "Undefined result of gate 2, e.g. a division by zero"
+        );
+
+        let mut boolean = circuit.clone();
+        boolean.gates[0].op = String::from("XOR");
+        assert!(matches!(
+            evaluate_circuit(&boolean, &inputs(1, 2), ValueType::Sint),
+            Err(EvaluateError::UnsupportedGate(op)) if op == "XOR"
+        ));
+    }
+}
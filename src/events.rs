@@ -0,0 +1,189 @@
+//! # Events Module
+//!
+//! This module writes a stream of compilation events in the JSON lines format, so orchestrators can
+//! follow the progress of long compilations and collect telemetry without parsing the logs.
+
+use crate::diagnostics::Diagnostic;
+use log::warn;
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// A compilation event.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    PhaseStarted {
+        phase: &'a str,
+    },
+    PhaseFinished {
+        phase: &'a str,
+        elapsed_ms: u64,
+    },
+    PassFinished {
+        pass: &'a str,
+        elapsed_ms: u64,
+        gates: u64,
+    },
+    Count {
+        name: &'a str,
+        value: u64,
+    },
+    Warning {
+        target: &'a str,
+        message: &'a str,
+    },
+}
+
+/// An event along with the time it was emitted at.
+#[derive(Serialize)]
+struct TimedEvent<'a> {
+    /// Milliseconds since the Unix epoch.
+    time_ms: u64,
+    #[serde(flatten)]
+    event: &'a Event<'a>,
+}
+
+/// Destination of the events, one JSON object per line. Events are discarded if no writer is set.
+/// Each event is flushed as soon as it is written, so it can be read while compilation runs.
+#[derive(Default)]
+pub struct EventSink {
+    writer: Option<Box<dyn Write>>,
+    phases: HashMap<String, Instant>,
+    warnings: Vec<Diagnostic>,
+}
+
+impl EventSink {
+    /// Creates a sink writing to the given writer.
+    pub fn new(writer: Box<dyn Write>) -> Self {
+        Self {
+            writer: Some(writer),
+            phases: HashMap::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Creates a sink writing to the given file, or discarding the events if there is none.
+    pub fn create(path: Option<&Path>) -> io::Result<Self> {
+        match path {
+            Some(path) => Ok(Self::new(Box::new(BufWriter::new(File::create(path)?)))),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Writes an event.
+    pub fn emit(&mut self, event: &Event) -> io::Result<()> {
+        let writer = match &mut self.writer {
+            Some(writer) => writer,
+            None => return Ok(()),
+        };
+
+        let time_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_millis() as u64);
+        serde_json::to_writer(&mut *writer, &TimedEvent { time_ms, event })?;
+        writer.write_all(b"\n")?;
+        writer.flush()
+    }
+
+    /// Marks the start of a phase.
+    pub fn phase_started(&mut self, phase: &str) -> io::Result<()> {
+        self.phases.insert(phase.to_string(), Instant::now());
+        self.emit(&Event::PhaseStarted { phase })
+    }
+
+    /// Marks the end of a phase, along with the time elapsed since its start.
+    pub fn phase_finished(&mut self, phase: &str) -> io::Result<()> {
+        let elapsed_ms = self
+            .phases
+            .remove(phase)
+            .map_or(0, |start| start.elapsed().as_millis() as u64);
+        self.emit(&Event::PhaseFinished { phase, elapsed_ms })
+    }
+
+    /// Marks the end of a pass, along with its duration and the number of gates it left.
+    pub fn pass_finished(&mut self, pass: &str, elapsed: Duration, gates: u64) -> io::Result<()> {
+        self.emit(&Event::PassFinished {
+            pass,
+            elapsed_ms: elapsed.as_millis() as u64,
+            gates,
+        })
+    }
+
+    /// Records a named count, e.g. the number of gates.
+    pub fn count(&mut self, name: &str, value: u64) -> io::Result<()> {
+        self.emit(&Event::Count { name, value })
+    }
+
+    /// Logs a warning and records it as an event.
+    pub fn warn(&mut self, target: &str, message: &str) -> io::Result<()> {
+        warn!(target: target, "{}", message);
+        self.warnings.push(Diagnostic::warning(message.to_string()));
+        self.emit(&Event::Warning { target, message })
+    }
+
+    /// Returns the warnings recorded so far, even if the events are discarded.
+    pub fn warnings(&self) -> &[Diagnostic] {
+        &self.warnings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    #[test]
+    fn test_event_sink() {
+        let path = std::env::temp_dir().join("circom_2_arithc_events.jsonl");
+        let mut events = EventSink::create(Some(&path)).unwrap();
+
+        events.phase_started("process").unwrap();
+        events.count("gates", 42).unwrap();
+        events
+            .warn("arithc::graph", "Output 0.out is constant")
+            .unwrap();
+        events.phase_finished("process").unwrap();
+        drop(events);
+
+        let lines = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect::<Vec<Value>>();
+
+        assert_eq!(lines.len(), 4);
+        assert!(lines.iter().all(|line| line["time_ms"].is_u64()));
+        assert_eq!(lines[0]["event"], "phase_started");
+        assert_eq!(lines[0]["phase"], "process");
+        assert_eq!(lines[1]["event"], "count");
+        assert_eq!(lines[1]["name"], "gates");
+        assert_eq!(lines[1]["value"], 42);
+        assert_eq!(lines[2]["event"], "warning");
+        assert_eq!(lines[2]["message"], "Output 0.out is constant");
+        assert_eq!(lines[3]["event"], "phase_finished");
+        assert!(lines[3]["elapsed_ms"].is_u64());
+    }
+
+    #[test]
+    fn test_event_sink_disabled() {
+        let mut events = EventSink::create(None).unwrap();
+        events.phase_started("parse").unwrap();
+        events
+            .warn("arithc::graph", "Output 0.out is constant")
+            .unwrap();
+        events.phase_finished("parse").unwrap();
+
+        assert_eq!(
+            events.warnings(),
+            [Diagnostic::warning(String::from(
+                "Output 0.out is constant"
+            ))]
+        );
+    }
+}
@@ -0,0 +1,201 @@
+//! # FFI Module
+//!
+//! This module exposes a minimal C ABI to embed the compiler in non-Rust stacks.
+//!
+//! ```c
+//! typedef struct ArithcArtifacts ArithcArtifacts;
+//!
+//! int32_t arithc_compile_file(const char *input_path, ArithcArtifacts **artifacts);
+//! int32_t arithc_get_artifact(const ArithcArtifacts *artifacts, const char *name,
+//!                             const uint8_t **data, size_t *len);
+//! void arithc_free(ArithcArtifacts *artifacts);
+//! ```
+//!
+//! Every function returning an `int32_t` returns one of the [`ErrorCode`] values.
+
+use crate::{
+    artifacts::to_canonical_json,
+    cli::{Args, ValueType},
+    program::{circuit_info_json, compile},
+};
+use std::{
+    collections::HashMap,
+    ffi::{c_char, CStr},
+    panic::{catch_unwind, AssertUnwindSafe},
+    path::PathBuf,
+};
+
+/// Stable error codes returned across the C ABI.
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    Ok = 0,
+    NullPointer = 1,
+    InvalidUtf8 = 2,
+    CompilationFailed = 3,
+    CircuitBuildFailed = 4,
+    SerializationFailed = 5,
+    ArtifactNotFound = 6,
+    Panic = 7,
+}
+
+/// Compilation artifacts, keyed by file name (`circuit.txt`, `circuit_info.json` and `report.json`).
+pub struct ArithcArtifacts {
+    files: HashMap<String, Vec<u8>>,
+}
+
+impl ArithcArtifacts {
+    /// Compiles the circom program at the given path and collects the artifacts in memory.
+    fn compile(input_path: PathBuf) -> Result<Self, ErrorCode> {
+        let args = Args::new(input_path, PathBuf::from("./"), ValueType::Sint, None);
+
+        let compiler = compile(&args).map_err(|_| ErrorCode::CompilationFailed)?;
+        let report = compiler
+            .generate_circuit_report()
+            .map_err(|_| ErrorCode::CircuitBuildFailed)?;
+        let (circuit, node_wires) = compiler
+            .build_circuit_wires()
+            .map_err(|_| ErrorCode::CircuitBuildFailed)?;
+
+        let mut bristol = Vec::new();
+        circuit
+            .write_bristol(&mut bristol)
+            .map_err(|_| ErrorCode::SerializationFailed)?;
+        let info = circuit_info_json(&circuit.info, &compiler.wire_tags(&node_wires), &args)
+            .map_err(|_| ErrorCode::SerializationFailed)?;
+        let report = to_canonical_json(&report).map_err(|_| ErrorCode::SerializationFailed)?;
+
+        Ok(Self {
+            files: HashMap::from([
+                ("circuit.txt".to_string(), bristol),
+                ("circuit_info.json".to_string(), info.into_bytes()),
+                ("report.json".to_string(), report.into_bytes()),
+            ]),
+        })
+    }
+}
+
+/// Compiles a circom file and stores a handle to the artifacts in `artifacts`.
+/// The handle must be released with `arithc_free`.
+///
+/// # Safety
+///
+/// `input_path` must be a valid NUL-terminated string and `artifacts` a valid pointer to write to.
+#[no_mangle]
+pub unsafe extern "C" fn arithc_compile_file(
+    input_path: *const c_char,
+    artifacts: *mut *mut ArithcArtifacts,
+) -> i32 {
+    if input_path.is_null() || artifacts.is_null() {
+        return ErrorCode::NullPointer as i32;
+    }
+
+    let input_path = match CStr::from_ptr(input_path).to_str() {
+        Ok(path) => PathBuf::from(path),
+        Err(_) => return ErrorCode::InvalidUtf8 as i32,
+    };
+
+    match catch_unwind(AssertUnwindSafe(|| ArithcArtifacts::compile(input_path))) {
+        Ok(Ok(compiled)) => {
+            *artifacts = Box::into_raw(Box::new(compiled));
+            ErrorCode::Ok as i32
+        }
+        Ok(Err(code)) => code as i32,
+        Err(_) => ErrorCode::Panic as i32,
+    }
+}
+
+/// Gets the content of an artifact by file name.
+/// The returned buffer is owned by the artifacts and valid until `arithc_free` is called.
+///
+/// # Safety
+///
+/// `artifacts` must come from `arithc_compile_file`, `name` must be a valid NUL-terminated string
+/// and `data` and `len` valid pointers to write to.
+#[no_mangle]
+pub unsafe extern "C" fn arithc_get_artifact(
+    artifacts: *const ArithcArtifacts,
+    name: *const c_char,
+    data: *mut *const u8,
+    len: *mut usize,
+) -> i32 {
+    if artifacts.is_null() || name.is_null() || data.is_null() || len.is_null() {
+        return ErrorCode::NullPointer as i32;
+    }
+
+    let name = match CStr::from_ptr(name).to_str() {
+        Ok(name) => name,
+        Err(_) => return ErrorCode::InvalidUtf8 as i32,
+    };
+
+    match (*artifacts).files.get(name) {
+        Some(file) => {
+            *data = file.as_ptr();
+            *len = file.len();
+            ErrorCode::Ok as i32
+        }
+        None => ErrorCode::ArtifactNotFound as i32,
+    }
+}
+
+/// Releases the artifacts returned by `arithc_compile_file`. Passing NULL is a no-op.
+///
+/// # Safety
+///
+/// `artifacts` must come from `arithc_compile_file` and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn arithc_free(artifacts: *mut ArithcArtifacts) {
+    if !artifacts.is_null() {
+        drop(Box::from_raw(artifacts));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{ffi::CString, ptr};
+
+    #[test]
+    fn test_ffi_null_pointers() {
+        let path = CString::new("circuit.circom").unwrap();
+        let mut artifacts: *mut ArithcArtifacts = ptr::null_mut();
+
+        unsafe {
+            assert_eq!(
+                arithc_compile_file(ptr::null(), &mut artifacts),
+                ErrorCode::NullPointer as i32
+            );
+            assert_eq!(
+                arithc_compile_file(path.as_ptr(), ptr::null_mut()),
+                ErrorCode::NullPointer as i32
+            );
+            arithc_free(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn test_ffi_get_artifact() {
+        let artifacts = Box::into_raw(Box::new(ArithcArtifacts {
+            files: HashMap::from([("report.json".to_string(), b"{}".to_vec())]),
+        }));
+        let report = CString::new("report.json").unwrap();
+        let missing = CString::new("missing.json").unwrap();
+        let mut data: *const u8 = ptr::null();
+        let mut len = 0;
+
+        unsafe {
+            assert_eq!(
+                arithc_get_artifact(artifacts, report.as_ptr(), &mut data, &mut len),
+                ErrorCode::Ok as i32
+            );
+            assert_eq!(std::slice::from_raw_parts(data, len), b"{}");
+
+            assert_eq!(
+                arithc_get_artifact(artifacts, missing.as_ptr(), &mut data, &mut len),
+                ErrorCode::ArtifactNotFound as i32
+            );
+
+            arithc_free(artifacts);
+        }
+    }
+}
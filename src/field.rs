@@ -0,0 +1,444 @@
+//! # Field Module
+//!
+//! This module implements the elements of the prime field selected with `--prime`, the BN254
+//! scalar field by default. Compile-time values are field elements, so constants of any size (e.g.
+//! `p - 1` or `10**18`) can be computed with before they are emitted as wires of the value type.
+
+use crate::{
+    cli::{Prime, ValueType},
+    r1cs::BN254_PRIME,
+};
+use circom_circom_algebra::{
+    num_bigint::{BigInt, BigUint, Sign},
+    num_traits::{One, ToPrimitive, Zero},
+};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{cmp::Ordering, fmt, sync::OnceLock};
+
+/// Number of bits the signed bitwise operations are computed on, more than the bits of the largest
+/// prime so that negative values are sign extended.
+const BITWISE_BITS: usize = 320;
+
+/// Returns the modulus of the prime field.
+pub fn modulus(prime: Prime) -> &'static BigUint {
+    static BN128: OnceLock<BigUint> = OnceLock::new();
+    static BLS12381: OnceLock<BigUint> = OnceLock::new();
+    static GOLDILOCKS: OnceLock<BigUint> = OnceLock::new();
+    static GRUMPKIN: OnceLock<BigUint> = OnceLock::new();
+    static PALLAS: OnceLock<BigUint> = OnceLock::new();
+    static VESTA: OnceLock<BigUint> = OnceLock::new();
+    static SECQ256R1: OnceLock<BigUint> = OnceLock::new();
+
+    let decimal = |digits: &str| BigUint::parse_bytes(digits.as_bytes(), 10).unwrap_or_default();
+    match prime {
+        Prime::Bn128 => BN128.get_or_init(|| BigUint::from_bytes_le(&BN254_PRIME)),
+        Prime::Bls12381 => BLS12381.get_or_init(|| {
+            decimal("52435875175126190479447740508185965837690552500527637822603658699938581184513")
+        }),
+        Prime::Goldilocks => GOLDILOCKS.get_or_init(|| decimal("18446744069414584321")),
+        Prime::Grumpkin => GRUMPKIN.get_or_init(|| {
+            decimal("21888242871839275222246405745257275088696311157297823662689037894645226208583")
+        }),
+        Prime::Pallas => PALLAS.get_or_init(|| {
+            decimal("28948022309329048855892746252171976963363056481941560715954676764349967630337")
+        }),
+        Prime::Vesta => VESTA.get_or_init(|| {
+            decimal("28948022309329048855892746252171976963363056481941647379679742748393362948097")
+        }),
+        Prime::Secq256r1 => SECQ256R1.get_or_init(|| {
+            decimal(
+                "115792089210356248762697446949407573530086143415290314195533631308867097853951",
+            )
+        }),
+    }
+}
+
+/// An element of a prime field, stored as its representative in `[0, p)`. The prime isn't stored
+/// with the element, operations take the prime of the compilation.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Default)]
+pub struct FieldElement(BigUint);
+
+impl FieldElement {
+    /// Reduces an integer, given as its magnitude and sign, modulo the prime.
+    pub fn from_integer(magnitude: &BigUint, negative: bool, prime: Prime) -> Self {
+        let modulus = modulus(prime);
+        let value = magnitude % modulus;
+        if negative && !value.is_zero() {
+            FieldElement(modulus - value)
+        } else {
+            FieldElement(value)
+        }
+    }
+
+    /// Reduces an integer literal modulo the prime.
+    pub fn from_bigint(value: &BigInt, prime: Prime) -> Self {
+        match value.sign() {
+            Sign::Minus => {
+                let magnitude = (-value).to_biguint().unwrap_or_default();
+                FieldElement::from_integer(&magnitude, true, prime)
+            }
+            _ => FieldElement::from_integer(&value.to_biguint().unwrap_or_default(), false, prime),
+        }
+    }
+
+    /// Parses the decimal representative of an element, `None` unless it is in `[0, p)`.
+    pub fn from_decimal(value: &str, prime: Prime) -> Option<Self> {
+        let value = FieldElement(value.parse().ok()?);
+        value.is_reduced(prime).then_some(value)
+    }
+
+    /// Converts a 32-bit word of the given value type, in two's complement for signed types.
+    pub fn from_word(word: u32, value_type: ValueType, prime: Prime) -> Self {
+        if value_type.is_signed() {
+            let value = word as i32;
+            FieldElement::from_integer(&BigUint::from(value.unsigned_abs()), value < 0, prime)
+        } else {
+            FieldElement::from_integer(&BigUint::from(word), false, prime)
+        }
+    }
+
+    /// Converts the element to a 32-bit word of the given value type, `None` if it doesn't fit.
+    /// Elements above `p / 2` are negative for signed types, as in circom.
+    pub fn to_word(&self, value_type: ValueType, prime: Prime) -> Option<u32> {
+        if !value_type.is_signed() {
+            return self.0.to_u32();
+        }
+
+        let (magnitude, negative) = self.to_integer(true, prime);
+        let magnitude = magnitude.to_u64()?;
+        let value = if negative {
+            -(magnitude as i64)
+        } else {
+            magnitude as i64
+        };
+        i32::try_from(value).ok().map(|value| value as u32)
+    }
+
+    /// Returns the representative of the element if it fits in 32 bits, e.g. for indices.
+    pub fn to_u32(&self) -> Option<u32> {
+        self.0.to_u32()
+    }
+
+    /// Returns the representative of the element.
+    pub fn representative(&self) -> &BigUint {
+        &self.0
+    }
+
+    /// Returns whether the representative is in `[0, p)`, e.g. for an element read without its
+    /// prime.
+    pub fn is_reduced(&self, prime: Prime) -> bool {
+        self.0 < *modulus(prime)
+    }
+
+    /// Returns the integer the element stands for, as its magnitude and sign. Signed elements
+    /// above `p / 2` are negative, unsigned elements are their representative.
+    pub fn to_integer(&self, signed: bool, prime: Prime) -> (BigUint, bool) {
+        let modulus = modulus(prime);
+        if signed && self.0 > modulus >> 1 {
+            (modulus - &self.0, true)
+        } else {
+            (self.0.clone(), false)
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0.is_zero()
+    }
+
+    pub fn add(&self, rhs: &FieldElement, prime: Prime) -> Self {
+        FieldElement((&self.0 + &rhs.0) % modulus(prime))
+    }
+
+    pub fn sub(&self, rhs: &FieldElement, prime: Prime) -> Self {
+        let modulus = modulus(prime);
+        FieldElement((&self.0 + modulus - &rhs.0) % modulus)
+    }
+
+    pub fn mul(&self, rhs: &FieldElement, prime: Prime) -> Self {
+        FieldElement((&self.0 * &rhs.0) % modulus(prime))
+    }
+
+    /// Raises the element to the power of the representative of `exponent`.
+    pub fn pow(&self, exponent: &FieldElement, prime: Prime) -> Self {
+        FieldElement(self.0.modpow(&exponent.0, modulus(prime)))
+    }
+
+    /// Divides the element by `rhs`, multiplying it by the inverse `rhs^(p-2)` as circom's `/`
+    /// does. `None` if `rhs` is zero.
+    pub fn div(&self, rhs: &FieldElement, prime: Prime) -> Option<Self> {
+        if rhs.is_zero() {
+            return None;
+        }
+        let modulus = modulus(prime);
+        let inverse = rhs.0.modpow(&(modulus - 2u32), modulus);
+        Some(FieldElement((&self.0 * inverse) % modulus))
+    }
+
+    /// Multiplies the element by `2^shift`.
+    pub fn shl(&self, shift: &BigUint, prime: Prime) -> Self {
+        let modulus = modulus(prime);
+        let two = BigUint::from(2u32);
+        FieldElement((&self.0 * two.modpow(shift, modulus)) % modulus)
+    }
+
+    /// Shifts the integer the element stands for right, rounding towards negative infinity.
+    pub fn shr(&self, shift: &BigUint, signed: bool, prime: Prime) -> Self {
+        let (magnitude, negative) = self.to_integer(signed, prime);
+        let shift = match shift.to_usize() {
+            Some(shift) if shift < BITWISE_BITS => shift,
+            // Every bit is shifted out
+            _ => {
+                return FieldElement::from_integer(&BigUint::from(negative as u32), negative, prime)
+            }
+        };
+
+        if negative {
+            // floor(-m / 2^k) = -(((m - 1) >> k) + 1)
+            let shifted = ((magnitude - BigUint::one()) >> shift) + BigUint::one();
+            FieldElement::from_integer(&shifted, true, prime)
+        } else {
+            FieldElement(magnitude >> shift)
+        }
+    }
+
+    /// Applies a bitwise operation to the integers the elements stand for, in two's complement
+    /// for signed elements.
+    pub fn bitwise<F>(&self, rhs: &FieldElement, signed: bool, prime: Prime, op: F) -> Self
+    where
+        F: Fn(&BigUint, &BigUint) -> BigUint,
+    {
+        if !signed {
+            return FieldElement::from_integer(&op(&self.0, &rhs.0), false, prime);
+        }
+
+        let modulus = BigUint::one() << BITWISE_BITS;
+        let encode = |element: &FieldElement| match element.to_integer(true, prime) {
+            (magnitude, true) => &modulus - magnitude,
+            (magnitude, false) => magnitude,
+        };
+        let result = op(&encode(self), &encode(rhs)) % &modulus;
+        if result >= &modulus >> 1 {
+            FieldElement::from_integer(&(&modulus - result), true, prime)
+        } else {
+            FieldElement::from_integer(&result, false, prime)
+        }
+    }
+
+    /// Compares the integers the elements stand for.
+    pub fn cmp_integer(&self, rhs: &FieldElement, signed: bool, prime: Prime) -> Ordering {
+        match (
+            self.to_integer(signed, prime),
+            rhs.to_integer(signed, prime),
+        ) {
+            ((lhs, false), (rhs, false)) => lhs.cmp(&rhs),
+            ((lhs, true), (rhs, true)) => rhs.cmp(&lhs),
+            ((_, negative), _) if negative => Ordering::Less,
+            _ => Ordering::Greater,
+        }
+    }
+}
+
+// Every supported prime is above `2^32`, so a `u32` is its own representative
+impl From<u32> for FieldElement {
+    fn from(value: u32) -> Self {
+        FieldElement(BigUint::from(value))
+    }
+}
+
+impl From<bool> for FieldElement {
+    fn from(value: bool) -> Self {
+        FieldElement::from(value as u32)
+    }
+}
+
+impl fmt::Display for FieldElement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+// Elements are serialized as the decimal string of their representative, JSON numbers being
+// rounded above `2^53` by most readers
+impl Serialize for FieldElement {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+// The prime isn't serialized with the element, so any representative is read, and checked against
+// the prime of the data holding it once loaded, see `Compiler::check_field_elements`
+impl<'de> Deserialize<'de> for FieldElement {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        value.parse().map(FieldElement).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::ValueEnum;
+
+    const BN128: Prime = Prime::Bn128;
+
+    fn minus(value: u32) -> FieldElement {
+        FieldElement::from_integer(&BigUint::from(value), true, BN128)
+    }
+
+    #[test]
+    fn test_field_element_reduction() {
+        let p = BigInt::from_biguint(Sign::Plus, modulus(BN128).clone());
+        assert_eq!(
+            modulus(BN128).to_string(),
+            "21888242871839275222246405745257275088548364400416034343698204186575808495617"
+        );
+        assert_eq!(FieldElement::from_bigint(&p, BN128), FieldElement::from(0));
+        assert_eq!(
+            FieldElement::from_bigint(&(&p + BigInt::from(5)), BN128),
+            FieldElement::from(5)
+        );
+        assert_eq!(
+            FieldElement::from_bigint(&(&p - BigInt::from(1)), BN128),
+            minus(1)
+        );
+        assert_eq!(
+            FieldElement::from_bigint(&BigInt::from(-3), BN128),
+            minus(3)
+        );
+    }
+
+    #[test]
+    fn test_field_element_words() {
+        assert_eq!(
+            FieldElement::from_word(-2i32 as u32, ValueType::Sint, BN128),
+            minus(2)
+        );
+        assert_eq!(minus(2).to_word(ValueType::Sint, BN128), Some(-2i32 as u32));
+        assert_eq!(minus(2).to_word(ValueType::Uint, BN128), None);
+        assert_eq!(
+            FieldElement::from(u32::MAX).to_word(ValueType::Uint, BN128),
+            Some(u32::MAX)
+        );
+        assert_eq!(
+            FieldElement::from(u32::MAX).to_word(ValueType::Sint, BN128),
+            None
+        );
+
+        // Big values computed at compile time can still be emitted once they are back in range
+        let big = FieldElement::from(10).pow(&FieldElement::from(18), BN128);
+        assert_eq!(big.to_word(ValueType::Sint, BN128), None);
+        let scaled = big
+            .mul(&FieldElement::from(3), BN128)
+            .sub(&big.mul(&FieldElement::from(3), BN128), BN128);
+        assert_eq!(scaled.to_word(ValueType::Sint, BN128), Some(0));
+    }
+
+    #[test]
+    fn test_field_element_arithmetic() {
+        assert_eq!(
+            minus(1).add(&FieldElement::from(1), BN128),
+            FieldElement::from(0)
+        );
+        assert_eq!(
+            FieldElement::from(3).sub(&FieldElement::from(5), BN128),
+            minus(2)
+        );
+        assert_eq!(minus(2).mul(&minus(3), BN128), FieldElement::from(6));
+        assert_eq!(
+            FieldElement::from(1)
+                .shl(&BigUint::from(40u32), BN128)
+                .to_string(),
+            "1099511627776"
+        );
+        assert_eq!(minus(5).shr(&BigUint::from(1u32), true, BN128), minus(3));
+        assert_eq!(minus(1).shr(&BigUint::from(300u32), true, BN128), minus(1));
+        assert_eq!(
+            FieldElement::from(5).shr(&BigUint::from(300u32), true, BN128),
+            FieldElement::from(0)
+        );
+        assert_eq!(
+            minus(6).bitwise(&FieldElement::from(3), true, BN128, |a, b| a & b),
+            FieldElement::from(2)
+        );
+        assert_eq!(
+            minus(1).bitwise(&minus(6), true, BN128, |a, b| a ^ b),
+            FieldElement::from(5)
+        );
+        assert_eq!(
+            minus(2).cmp_integer(&FieldElement::from(1), true, BN128),
+            Ordering::Less
+        );
+        assert_eq!(
+            minus(2).cmp_integer(&FieldElement::from(1), false, BN128),
+            Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn test_field_element_primes() {
+        let goldilocks = Prime::Goldilocks;
+        assert_eq!(modulus(goldilocks).to_string(), "18446744069414584321");
+
+        // 2^64 wraps around to 2^32 - 1 in the Goldilocks field
+        let wrapped = FieldElement::from(1).shl(&BigUint::from(64u32), goldilocks);
+        assert_eq!(wrapped, FieldElement::from(u32::MAX));
+        assert_ne!(
+            FieldElement::from(1).shl(&BigUint::from(64u32), BN128),
+            FieldElement::from(u32::MAX)
+        );
+
+        // The sign of an element depends on the half of the prime it is in
+        let minus_one = FieldElement::from_integer(&BigUint::from(1u32), true, goldilocks);
+        assert_eq!(minus_one.to_string(), "18446744069414584320");
+        assert_eq!(
+            minus_one.to_word(ValueType::Sint, goldilocks),
+            Some(-1i32 as u32)
+        );
+        assert_eq!(minus_one.to_word(ValueType::Sint, BN128), None);
+
+        for prime in Prime::value_variants() {
+            let minus_one = FieldElement::from_integer(&BigUint::from(1u32), true, *prime);
+            assert_eq!(
+                minus_one.add(&FieldElement::from(1), *prime),
+                FieldElement::from(0)
+            );
+            assert!(minus_one
+                .cmp_integer(&FieldElement::from(0), true, *prime)
+                .is_lt());
+        }
+    }
+
+    #[test]
+    fn test_field_element_serde() {
+        let element = FieldElement::from_integer(&BigUint::from(1u32), true, BN128);
+        let json = serde_json::to_string(&element).unwrap();
+        assert_eq!(json, format!("\"{}\"", modulus(BN128) - 1u32));
+        assert_eq!(
+            serde_json::from_str::<FieldElement>(&json).unwrap(),
+            element
+        );
+        assert!(serde_json::from_str::<FieldElement>("\"-1\"").is_err());
+
+        // Elements of larger fields are read, and checked against their prime
+        let vesta = Prime::Vesta;
+        let minus_one = FieldElement::from_integer(&BigUint::from(1u32), true, vesta);
+        let read =
+            serde_json::from_str::<FieldElement>(&serde_json::to_string(&minus_one).unwrap())
+                .unwrap();
+        assert_eq!(read, minus_one);
+        assert!(read.is_reduced(vesta));
+        assert!(!read.is_reduced(BN128));
+        assert_eq!(
+            FieldElement::from_decimal("18446744069414584321", Prime::Goldilocks),
+            None
+        );
+        assert_eq!(
+            FieldElement::from_decimal("18446744069414584320", Prime::Goldilocks),
+            Some(FieldElement::from_integer(
+                &BigUint::from(1u32),
+                true,
+                Prime::Goldilocks
+            ))
+        );
+    }
+}
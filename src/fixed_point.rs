@@ -0,0 +1,370 @@
+//! # Fixed Point Module
+//!
+//! This module converts real-valued data into the fixed-point integers compiled circuits expect,
+//! e.g. the inputs of a model quantized with a scale of 2^16, following an IO schema declaring the
+//! scale factor of each input and output. Values are rounded to the nearest integer and checked
+//! against the bounds of the schema and the range of the value type.
+
+use crate::cli::ValueType;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::BTreeMap, fmt, fs, io, path::Path};
+use thiserror::Error;
+
+/// Fixed-point encoding of an input or output: a real value `x` is encoded as `round(x * scale)`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FixedPoint {
+    pub scale: f64,
+    /// Smallest real value accepted, the range of the value type by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+    /// Largest real value accepted, the range of the value type by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
+}
+
+impl FixedPoint {
+    /// Returns the range of real values the encoding accepts for the value type: the bounds of
+    /// the schema, narrowed to the integers of the value type.
+    pub fn range(&self, value_type: ValueType) -> (f64, f64) {
+        let (min, max) = integer_range(value_type);
+        let min = (min as f64 / self.scale).max(self.min.unwrap_or(f64::MIN));
+        let max = (max as f64 / self.scale).min(self.max.unwrap_or(f64::MAX));
+        (min, max)
+    }
+
+    /// Encodes a real value, rejecting values out of range.
+    pub fn encode(
+        &self,
+        name: &str,
+        value: f64,
+        value_type: ValueType,
+    ) -> Result<i64, FixedPointError> {
+        let (min, max) = self.range(value_type);
+        let encoded = (value * self.scale).round();
+        let (integer_min, integer_max) = integer_range(value_type);
+        if !(min..=max).contains(&value)
+            || !(integer_min as f64..=integer_max as f64).contains(&encoded)
+        {
+            return Err(FixedPointError::OutOfRange {
+                name: name.to_string(),
+                value,
+                min,
+                max,
+            });
+        }
+
+        Ok(encoded as i64)
+    }
+
+    /// Decodes a 32-bit word of the value type into a real value.
+    pub fn decode(&self, word: u32, value_type: ValueType) -> f64 {
+        let value = if value_type.is_signed() {
+            word as i32 as f64
+        } else {
+            word as f64
+        };
+        value / self.scale
+    }
+}
+
+/// Fixed-point encodings of the inputs and outputs of a circuit, keyed by signal, e.g.
+/// `{ "inputs": { "0.x": { "scale": 65536, "min": -1, "max": 1 } }, "outputs": { ... } }`. The
+/// encoding of a signal applies to its elements, e.g. `0.w` to `0.w[0][1]`, and the encoding of an
+/// element takes precedence over the encoding of its array.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct IoSchema {
+    #[serde(default)]
+    pub inputs: BTreeMap<String, FixedPoint>,
+    #[serde(default)]
+    pub outputs: BTreeMap<String, FixedPoint>,
+}
+
+/// Range of real values an input or output accepts, and the step between consecutive encoded
+/// values.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FixedPointRange {
+    pub signal: String,
+    pub scale: f64,
+    pub min: f64,
+    pub max: f64,
+    pub resolution: f64,
+}
+
+impl fmt::Display for FixedPointRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: scale {}, [{}, {}] in steps of {}",
+            self.signal, self.scale, self.min, self.max, self.resolution
+        )
+    }
+}
+
+impl IoSchema {
+    /// Reads an IO schema from a JSON file, rejecting scales that aren't positive.
+    pub fn from_path(path: &Path) -> Result<Self, FixedPointError> {
+        let schema: Self = serde_json::from_str(&fs::read_to_string(path)?)?;
+        for (signal, encoding) in schema.inputs.iter().chain(&schema.outputs) {
+            if !(encoding.scale.is_finite() && encoding.scale > 0.0) {
+                return Err(FixedPointError::InvalidScale {
+                    signal: signal.clone(),
+                    scale: encoding.scale,
+                });
+            }
+        }
+
+        Ok(schema)
+    }
+
+    /// Returns the encoding of an input or of one of its elements, if any.
+    pub fn input(&self, name: &str) -> Option<&FixedPoint> {
+        lookup(&self.inputs, name)
+    }
+
+    /// Returns the encoding of an output or of one of its elements, if any.
+    pub fn output(&self, name: &str) -> Option<&FixedPoint> {
+        lookup(&self.outputs, name)
+    }
+
+    /// Encodes real input values into the integers of the value type, keyed by element. Values
+    /// are numbers or nested arrays of numbers, flattened like `--weights`, e.g. `{"0.w": [0.5]}`
+    /// into `0.w[0]`. Inputs without an encoding are kept as they are, and must be integers.
+    pub fn encode_inputs(
+        &self,
+        json: &Value,
+        value_type: ValueType,
+    ) -> Result<BTreeMap<String, i64>, FixedPointError> {
+        let object = json
+            .as_object()
+            .ok_or_else(|| FixedPointError::InvalidValue(String::from("<root>")))?;
+        let mut values = BTreeMap::new();
+        for (name, value) in object {
+            flatten(name.clone(), value, &mut values)?;
+        }
+
+        values
+            .into_iter()
+            .map(|(name, value)| {
+                let encoded = match self.input(&name) {
+                    Some(encoding) => encoding.encode(&name, value, value_type)?,
+                    None => IDENTITY
+                        .encode(&name, value, value_type)
+                        .and_then(|encoded| {
+                            if encoded as f64 == value {
+                                Ok(encoded)
+                            } else {
+                                Err(FixedPointError::MissingScale {
+                                    name: name.clone(),
+                                    value,
+                                })
+                            }
+                        })?,
+                };
+                Ok((name, encoded))
+            })
+            .collect()
+    }
+
+    /// Decodes the output values of an evaluation into real values. Outputs without an encoding
+    /// are only interpreted as integers of the value type.
+    pub fn decode_outputs(
+        &self,
+        outputs: &BTreeMap<String, u32>,
+        value_type: ValueType,
+    ) -> BTreeMap<String, f64> {
+        outputs
+            .iter()
+            .map(|(name, &word)| {
+                let encoding = self.output(name).unwrap_or(&IDENTITY);
+                (name.clone(), encoding.decode(word, value_type))
+            })
+            .collect()
+    }
+
+    /// Returns the range of each input and output of the schema for the value type, inputs first.
+    pub fn ranges(&self, value_type: ValueType) -> Vec<FixedPointRange> {
+        self.inputs
+            .iter()
+            .chain(&self.outputs)
+            .map(|(signal, encoding)| {
+                let (min, max) = encoding.range(value_type);
+                FixedPointRange {
+                    signal: signal.clone(),
+                    scale: encoding.scale,
+                    min,
+                    max,
+                    resolution: 1.0 / encoding.scale,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Encoding of the values without a scale.
+const IDENTITY: FixedPoint = FixedPoint {
+    scale: 1.0,
+    min: None,
+    max: None,
+};
+
+/// Returns the encoding of a signal, or of the closest array containing it.
+fn lookup<'a>(encodings: &'a BTreeMap<String, FixedPoint>, name: &str) -> Option<&'a FixedPoint> {
+    let mut name = name;
+    loop {
+        if let Some(encoding) = encodings.get(name) {
+            return Some(encoding);
+        }
+        name = &name[..name.rfind('[')?];
+    }
+}
+
+/// Returns the range of the integers of the value type.
+fn integer_range(value_type: ValueType) -> (i64, i64) {
+    if value_type.is_signed() {
+        (i32::MIN as i64, i32::MAX as i64)
+    } else {
+        (0, u32::MAX as i64)
+    }
+}
+
+/// Flattens a number or nested arrays of numbers into the value of each element.
+fn flatten(
+    name: String,
+    value: &Value,
+    values: &mut BTreeMap<String, f64>,
+) -> Result<(), FixedPointError> {
+    match value {
+        Value::Array(elements) => {
+            for (index, element) in elements.iter().enumerate() {
+                flatten(format!("{}[{}]", name, index), element, values)?;
+            }
+        }
+        Value::Number(number) => {
+            let value = number
+                .as_f64()
+                .ok_or_else(|| FixedPointError::InvalidValue(name.clone()))?;
+            values.insert(name, value);
+        }
+        _ => return Err(FixedPointError::InvalidValue(name)),
+    }
+
+    Ok(())
+}
+
+#[derive(Error, Debug)]
+pub enum FixedPointError {
+    #[error("Invalid scale {scale} for {signal}: expected a positive number")]
+    InvalidScale { signal: String, scale: f64 },
+    #[error("Invalid value {0}: expected a number or an array")]
+    InvalidValue(String),
+    #[error("{name} = {value} has no scale in the schema and isn't an integer")]
+    MissingScale { name: String, value: f64 },
+    #[error("{name} = {value} is out of the range [{min}, {max}]")]
+    OutOfRange {
+        name: String,
+        value: f64,
+        min: f64,
+        max: f64,
+    },
+    #[error(transparent)]
+    IOError(#[from] io::Error),
+    #[error(transparent)]
+    JsonError(#[from] serde_json::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn schema() -> IoSchema {
+        serde_json::from_value(json!({
+            "inputs": {
+                "0.x": { "scale": 65536, "min": -1, "max": 1 },
+                "0.w": { "scale": 100 },
+                "0.w[1]": { "scale": 10 }
+            },
+            "outputs": { "0.out": { "scale": 256 } }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_encode_inputs() {
+        let schema = schema();
+        let values = schema
+            .encode_inputs(
+                &json!({ "0.x": -0.5, "0.w": [[0.123, 2.5]], "0.n": 7 }),
+                ValueType::Sint,
+            )
+            .unwrap();
+        assert_eq!(
+            values,
+            BTreeMap::from([
+                (String::from("0.n"), 7),
+                (String::from("0.w[0][0]"), 12),
+                (String::from("0.w[0][1]"), 250),
+                (String::from("0.x"), -32768),
+            ])
+        );
+
+        // The encoding of an element takes precedence over its array
+        let values = schema
+            .encode_inputs(&json!({ "0.w": [0.5, 0.5] }), ValueType::Sint)
+            .unwrap();
+        assert_eq!(values["0.w[0]"], 50);
+        assert_eq!(values["0.w[1]"], 5);
+    }
+
+    #[test]
+    fn test_encode_inputs_out_of_range() {
+        let schema = schema();
+        assert!(matches!(
+            schema.encode_inputs(&json!({ "0.x": 1.5 }), ValueType::Sint),
+            Err(FixedPointError::OutOfRange { max, .. }) if max == 1.0
+        ));
+        // Negative values don't fit unsigned integers
+        assert!(matches!(
+            schema.encode_inputs(&json!({ "0.w": [-0.1] }), ValueType::Uint),
+            Err(FixedPointError::OutOfRange { min, .. }) if min == 0.0
+        ));
+        // Real values need a scale
+        assert!(matches!(
+            schema.encode_inputs(&json!({ "0.n": 0.5 }), ValueType::Sint),
+            Err(FixedPointError::MissingScale { .. })
+        ));
+        assert!(matches!(
+            schema.encode_inputs(&json!({ "0.n": "1" }), ValueType::Sint),
+            Err(FixedPointError::InvalidValue(_))
+        ));
+    }
+
+    #[test]
+    fn test_decode_outputs() {
+        let outputs = BTreeMap::from([
+            (String::from("0.out"), -128i32 as u32),
+            (String::from("0.count"), 3),
+        ]);
+        assert_eq!(
+            schema().decode_outputs(&outputs, ValueType::Sint),
+            BTreeMap::from([
+                (String::from("0.count"), 3.0),
+                (String::from("0.out"), -0.5)
+            ])
+        );
+    }
+
+    #[test]
+    fn test_ranges() {
+        let ranges = schema().ranges(ValueType::Sint);
+        assert_eq!(ranges.len(), 4);
+        assert_eq!((ranges[2].min, ranges[2].max), (-1.0, 1.0));
+        assert_eq!(ranges[2].resolution, 1.0 / 65536.0);
+        assert_eq!(ranges[3].max, i32::MAX as f64 / 256.0);
+        assert_eq!(
+            ranges[2].to_string(),
+            "0.x: scale 65536, [-1, 1] in steps of 0.0000152587890625"
+        );
+    }
+}
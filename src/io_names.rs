@@ -0,0 +1,210 @@
+//! # IO Names Module
+//!
+//! This module matches the names given to input values, e.g. in a weights or inputs file, with the
+//! IO names of a circuit. Names match exactly by default, and can be normalized to accept the
+//! spellings of other tools, e.g. `in_0` or `IN[0]` for `0.in[0]`.
+
+use crate::cli::NameNormalization;
+use std::collections::HashMap;
+use thiserror::Error;
+
+/// Prefix of the IO names of the main component.
+const MAIN_PREFIX: &str = "0.";
+
+/// Values keyed by IO names, along with the names that only matched after normalization.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedNames<T> {
+    pub values: HashMap<String, T>,
+    /// Given names and the IO names they were matched with, sorted by given name.
+    pub normalized: Vec<(String, String)>,
+}
+
+/// Normalizes a name, e.g. `0.in[0]` into `IN_0` with every normalization.
+pub fn normalize_name(name: &str, normalizations: &[NameNormalization]) -> String {
+    let mut name = name;
+    if normalizations.contains(&NameNormalization::Prefix) {
+        name = name.strip_prefix(MAIN_PREFIX).unwrap_or(name);
+    }
+
+    let mut normalized = String::with_capacity(name.len());
+    for c in name.chars() {
+        match c {
+            '[' if normalizations.contains(&NameNormalization::Brackets) => normalized.push('_'),
+            ']' if normalizations.contains(&NameNormalization::Brackets) => {}
+            c if normalizations.contains(&NameNormalization::Case) => {
+                normalized.extend(c.to_uppercase())
+            }
+            c => normalized.push(c),
+        }
+    }
+
+    normalized
+}
+
+/// Keys the given values by the IO names they match. Names matching an IO name exactly are kept,
+/// the others are normalized and matched with the normalized IO names. Names matching no IO name
+/// are kept as they are, so the caller reports them as unknown.
+pub fn resolve_names<'a, T>(
+    values: HashMap<String, T>,
+    io_names: impl IntoIterator<Item = &'a str>,
+    normalizations: &[NameNormalization],
+) -> Result<ResolvedNames<T>, NameError> {
+    let io_names = io_names.into_iter().collect::<Vec<&str>>();
+    let mut normalized_names = HashMap::<String, Vec<&str>>::new();
+    if !normalizations.is_empty() {
+        for &name in &io_names {
+            normalized_names
+                .entry(normalize_name(name, normalizations))
+                .or_default()
+                .push(name);
+        }
+    }
+
+    let mut resolved = ResolvedNames {
+        values: HashMap::with_capacity(values.len()),
+        normalized: Vec::new(),
+    };
+    let mut given_names = HashMap::<String, String>::new();
+    for (name, value) in values {
+        let io_name = if io_names.contains(&name.as_str()) {
+            name.clone()
+        } else {
+            match normalized_names
+                .get(&normalize_name(&name, normalizations))
+                .map(Vec::as_slice)
+            {
+                Some([io_name]) => {
+                    resolved
+                        .normalized
+                        .push((name.clone(), io_name.to_string()));
+                    io_name.to_string()
+                }
+                Some(io_names) => {
+                    let mut matches = io_names.to_vec();
+                    matches.sort_unstable();
+                    return Err(NameError::Ambiguous {
+                        name,
+                        matches: matches.join(", "),
+                    });
+                }
+                None => name.clone(),
+            }
+        };
+
+        if let Some(other) = given_names.insert(io_name.clone(), name.clone()) {
+            let (first, second) = if other < name {
+                (other, name)
+            } else {
+                (name, other)
+            };
+            return Err(NameError::Duplicate {
+                first,
+                second,
+                io_name,
+            });
+        }
+        resolved.values.insert(io_name, value);
+    }
+    resolved.normalized.sort_unstable();
+
+    Ok(resolved)
+}
+
+/// Name matching errors
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum NameError {
+    #[error("Name {name} matches several IO names after normalization: {matches}")]
+    Ambiguous { name: String, matches: String },
+    #[error("Names {first} and {second} both match {io_name}")]
+    Duplicate {
+        first: String,
+        second: String,
+        io_name: String,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: &[NameNormalization] = &[
+        NameNormalization::Prefix,
+        NameNormalization::Case,
+        NameNormalization::Brackets,
+    ];
+
+    #[test]
+    fn test_normalize_name() {
+        assert_eq!(normalize_name("0.in[0][1]", &[]), "0.in[0][1]");
+        assert_eq!(
+            normalize_name("0.in[0][1]", &[NameNormalization::Prefix]),
+            "in[0][1]"
+        );
+        assert_eq!(
+            normalize_name("0.in[0]", &[NameNormalization::Brackets]),
+            "0.in_0"
+        );
+        assert_eq!(normalize_name("0.Dense.in[0]", ALL), "DENSE.IN_0");
+    }
+
+    #[test]
+    fn test_resolve_names() {
+        let io_names = ["0.in[0]", "0.in[1]", "0.bias"];
+        let values = HashMap::from([
+            (String::from("0.in[0]"), 1),
+            (String::from("IN_1"), 2),
+            (String::from("Bias"), 3),
+            (String::from("0.out"), 4),
+        ]);
+
+        let resolved = resolve_names(values.clone(), io_names, ALL).unwrap();
+        assert_eq!(
+            resolved.values,
+            HashMap::from([
+                (String::from("0.in[0]"), 1),
+                (String::from("0.in[1]"), 2),
+                (String::from("0.bias"), 3),
+                (String::from("0.out"), 4),
+            ])
+        );
+        assert_eq!(
+            resolved.normalized,
+            vec![
+                (String::from("Bias"), String::from("0.bias")),
+                (String::from("IN_1"), String::from("0.in[1]")),
+            ]
+        );
+
+        // Names are matched exactly without normalization
+        let resolved = resolve_names(values, io_names, &[]).unwrap();
+        assert!(resolved.values.contains_key("IN_1"));
+        assert!(resolved.normalized.is_empty());
+    }
+
+    #[test]
+    fn test_resolve_names_errors() {
+        let ambiguous = resolve_names(
+            HashMap::from([(String::from("in"), 1)]),
+            ["0.in", "0.IN"],
+            ALL,
+        );
+        assert_eq!(
+            ambiguous.unwrap_err().to_string(),
+            "Name in matches several IO names after normalization: 0.IN, 0.in"
+        );
+
+        let duplicate = resolve_names(
+            HashMap::from([(String::from("0.in"), 1), (String::from("in"), 2)]),
+            ["0.in"],
+            ALL,
+        );
+        assert_eq!(
+            duplicate.unwrap_err(),
+            NameError::Duplicate {
+                first: String::from("0.in"),
+                second: String::from("in"),
+                io_name: String::from("0.in"),
+            }
+        );
+    }
+}
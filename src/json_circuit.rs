@@ -0,0 +1,264 @@
+//! # JSON Circuit Module
+//!
+//! This module defines a JSON serialization of arithmetic circuits, `circuit.json`, written with
+//! `--format json`, so tools in other languages can read circuits with any JSON library instead of
+//! parsing Bristol text. Unlike `circuit.txt`, a JSON circuit carries its IO names and constants,
+//! and reads back into the same `BristolCircuit`.
+
+use crate::compiler::CircuitError;
+use bristol_circuit::{BristolCircuit, CircuitInfo, ConstantInfo, Gate};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    path::Path,
+};
+
+/// Version of the JSON circuit schema, increased when it changes incompatibly.
+pub const JSON_CIRCUIT_VERSION: u32 = 1;
+
+/// An arithmetic circuit, `circuit.json`. Signals and constants are sorted by wire, so the same
+/// circuit is always written the same way.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JsonCircuit {
+    /// Schema version, see `JSON_CIRCUIT_VERSION`.
+    pub version: u32,
+    pub wire_count: usize,
+    pub inputs: Vec<JsonSignal>,
+    pub outputs: Vec<JsonSignal>,
+    pub constants: Vec<JsonConstant>,
+    /// Widths of the inputs and outputs, if the circuit groups wires into wider values.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub io_widths: Option<JsonIoWidths>,
+    /// Gates in evaluation order.
+    pub gates: Vec<JsonGate>,
+}
+
+/// A named input or output wire.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JsonSignal {
+    pub name: String,
+    pub wire: usize,
+}
+
+/// A wire with a value known at compile time.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JsonConstant {
+    pub name: String,
+    /// Value as a decimal string, so big field elements aren't rounded by JSON readers.
+    pub value: String,
+    pub wire: usize,
+}
+
+/// Number of wires of each input and output.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JsonIoWidths {
+    pub inputs: Vec<usize>,
+    pub outputs: Vec<usize>,
+}
+
+/// A gate with its Bristol operation, e.g. `AAdd`, and its input and output wires.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JsonGate {
+    pub op: String,
+    pub inputs: Vec<usize>,
+    pub outputs: Vec<usize>,
+}
+
+impl JsonCircuit {
+    /// Describes a circuit in the JSON schema.
+    pub fn from_circuit(circuit: &BristolCircuit) -> Self {
+        let signals = |names: &HashMap<String, usize>| {
+            let mut signals = names
+                .iter()
+                .map(|(name, &wire)| JsonSignal {
+                    name: name.clone(),
+                    wire,
+                })
+                .collect::<Vec<JsonSignal>>();
+            signals.sort_by(|a, b| (a.wire, &a.name).cmp(&(b.wire, &b.name)));
+            signals
+        };
+        let mut constants = circuit
+            .info
+            .constants
+            .iter()
+            .map(|(name, constant)| JsonConstant {
+                name: name.clone(),
+                value: constant.value.clone(),
+                wire: constant.wire_index,
+            })
+            .collect::<Vec<JsonConstant>>();
+        constants.sort_by(|a, b| (a.wire, &a.name).cmp(&(b.wire, &b.name)));
+
+        JsonCircuit {
+            version: JSON_CIRCUIT_VERSION,
+            wire_count: circuit.wire_count,
+            inputs: signals(&circuit.info.input_name_to_wire_index),
+            outputs: signals(&circuit.info.output_name_to_wire_index),
+            constants,
+            io_widths: circuit
+                .io_widths
+                .as_ref()
+                .map(|(inputs, outputs)| JsonIoWidths {
+                    inputs: inputs.clone(),
+                    outputs: outputs.clone(),
+                }),
+            gates: circuit
+                .gates
+                .iter()
+                .map(|gate| JsonGate {
+                    op: gate.op.clone(),
+                    inputs: gate.inputs.clone(),
+                    outputs: gate.outputs.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Converts back to a circuit, rejecting newer schemas and wires out of range.
+    pub fn into_circuit(self) -> Result<BristolCircuit, CircuitError> {
+        if self.version > JSON_CIRCUIT_VERSION {
+            return Err(CircuitError::ParsingError {
+                message: format!(
+                    "JSON circuit version {} is newer than the supported version {}",
+                    self.version, JSON_CIRCUIT_VERSION
+                ),
+            });
+        }
+
+        let wires = self
+            .inputs
+            .iter()
+            .chain(&self.outputs)
+            .map(|signal| signal.wire)
+            .chain(self.constants.iter().map(|constant| constant.wire))
+            .chain(
+                self.gates
+                    .iter()
+                    .flat_map(|gate| gate.inputs.iter().chain(&gate.outputs).copied()),
+            );
+        for wire in wires {
+            if wire >= self.wire_count {
+                return Err(CircuitError::Inconsistency {
+                    message: format!(
+                        "wire {} is out of range, the circuit has {} wires",
+                        wire, self.wire_count
+                    ),
+                });
+            }
+        }
+
+        let names = |signals: Vec<JsonSignal>| {
+            signals
+                .into_iter()
+                .map(|signal| (signal.name, signal.wire))
+                .collect::<HashMap<String, usize>>()
+        };
+        Ok(BristolCircuit {
+            wire_count: self.wire_count,
+            info: CircuitInfo {
+                input_name_to_wire_index: names(self.inputs),
+                constants: self
+                    .constants
+                    .into_iter()
+                    .map(|constant| {
+                        (
+                            constant.name,
+                            ConstantInfo {
+                                value: constant.value,
+                                wire_index: constant.wire,
+                            },
+                        )
+                    })
+                    .collect(),
+                output_name_to_wire_index: names(self.outputs),
+            },
+            io_widths: self.io_widths.map(|widths| (widths.inputs, widths.outputs)),
+            gates: self
+                .gates
+                .into_iter()
+                .map(|gate| Gate {
+                    inputs: gate.inputs,
+                    outputs: gate.outputs,
+                    op: gate.op,
+                })
+                .collect(),
+        })
+    }
+
+    /// Reads a JSON circuit.
+    pub fn from_path(path: &Path) -> Result<Self, CircuitError> {
+        Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+    }
+
+    /// Writes the circuit as compact JSON, one line.
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<(), CircuitError> {
+        let mut writer = BufWriter::new(writer);
+        serde_json::to_writer(&mut writer, self)?;
+        writeln!(writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{a_gate_type::AGateType, compiler::Compiler, field::FieldElement};
+
+    fn circuit() -> BristolCircuit {
+        // out = (a + 3) * b
+        let mut compiler = Compiler::new();
+        compiler.add_signal(1, String::from("0.a"), None).unwrap();
+        compiler.add_signal(2, String::from("0.b"), None).unwrap();
+        compiler
+            .add_signal(3, String::from("0.three"), Some(FieldElement::from(3)))
+            .unwrap();
+        compiler.add_signal(4, String::from("0.t"), None).unwrap();
+        compiler.add_signal(5, String::from("0.out"), None).unwrap();
+        compiler.add_gate(AGateType::AAdd, 1, 3, 4).unwrap();
+        compiler.add_gate(AGateType::AMul, 4, 2, 5).unwrap();
+        compiler.add_inputs(HashMap::from([
+            (1, String::from("0.a")),
+            (2, String::from("0.b")),
+        ]));
+        compiler.add_outputs(HashMap::from([(5, String::from("0.out"))]));
+        compiler.build_circuit().unwrap()
+    }
+
+    #[test]
+    fn test_json_circuit_round_trip() {
+        let circuit = circuit();
+        let json = JsonCircuit::from_circuit(&circuit);
+        assert_eq!(json.version, JSON_CIRCUIT_VERSION);
+        assert_eq!(json.gates.len(), circuit.gates.len());
+        assert!(json
+            .inputs
+            .windows(2)
+            .all(|pair| pair[0].wire < pair[1].wire));
+
+        let mut buffer = Vec::new();
+        json.write(&mut buffer).unwrap();
+        let read: JsonCircuit = serde_json::from_slice(&buffer).unwrap();
+        assert_eq!(read, json);
+        assert_eq!(read.into_circuit().unwrap(), circuit);
+    }
+
+    #[test]
+    fn test_json_circuit_rejects_invalid() {
+        let mut json = JsonCircuit::from_circuit(&circuit());
+        json.gates[0].outputs[0] = json.wire_count;
+        assert!(matches!(
+            json.clone().into_circuit(),
+            Err(CircuitError::Inconsistency { .. })
+        ));
+
+        json.version = JSON_CIRCUIT_VERSION + 1;
+        assert!(matches!(
+            json.into_circuit(),
+            Err(CircuitError::ParsingError { .. })
+        ));
+    }
+}
@@ -3,11 +3,50 @@
 //! This library provides the functionality to convert a Circom program into an arithmetic circuit.
 
 pub mod a_gate_type;
+pub mod artifacts;
+pub mod audit;
+pub mod bristol_reader;
+pub mod builder;
+pub mod card;
+pub mod check;
 pub mod circom;
 pub mod cli;
 pub mod compiler;
+pub mod conversions;
+pub mod deps;
+pub mod diagnostics;
+pub mod diff;
+pub mod estimate;
+pub mod evaluate;
+pub mod events;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod field;
+pub mod fixed_point;
+pub mod io_names;
+pub mod json_circuit;
+pub mod log_trace;
+pub mod logging;
+pub mod lowering;
+pub mod metrics;
+pub mod naming;
+pub mod noise;
+pub mod package;
+pub mod passes;
+pub mod prebuild;
 pub mod process;
 pub mod program;
+pub mod provenance;
+pub mod r1cs;
+pub mod rewrite;
 pub mod runtime;
+pub mod scaffold;
+pub mod shrink;
+pub mod signal_map;
+pub mod simd;
+pub mod sources;
+pub mod stages;
+pub mod wire_types;
 
 mod topological_sort;
+mod union_find;
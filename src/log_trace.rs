@@ -0,0 +1,81 @@
+//! # Log Trace Module
+//!
+//! This module records the `log` calls of a program. Their arguments known at compile time are
+//! logged while compiling, and the signals they log are traced to the wires of the circuit in
+//! `log.json`, to instrument templates while debugging a conversion.
+
+use serde::{Deserialize, Serialize};
+
+/// A signal logged by a `log` call.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoggedSignal {
+    /// Name of the signal, e.g. `0.dense.out[2]`.
+    pub name: String,
+    /// Id of the signal in the compiler.
+    pub signal: u32,
+    /// Wire of the arithmetic circuit carrying the signal, `None` if no wire does, e.g. for a
+    /// signal that no gate reads.
+    pub wire: Option<usize>,
+}
+
+/// A `log` call, recorded each time the statement is processed.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogEntry {
+    /// Statement of the call, e.g. `main.circom:12`.
+    pub source: Option<String>,
+    /// Template instance the call was made in, e.g. `0.dense`.
+    pub context: String,
+    /// Arguments of the call separated by spaces: strings, values known at compile time, and the
+    /// names of the logged signals.
+    pub message: String,
+    pub signals: Vec<LoggedSignal>,
+}
+
+impl LogEntry {
+    /// Creates an entry for a call made in the given instance, its arguments not added yet.
+    pub fn new(context: String) -> Self {
+        Self {
+            source: None,
+            context,
+            message: String::new(),
+            signals: Vec::new(),
+        }
+    }
+
+    /// Appends a string or a value known at compile time to the message.
+    pub fn push_str(&mut self, argument: &str) {
+        if !self.message.is_empty() {
+            self.message.push(' ');
+        }
+        self.message.push_str(argument);
+    }
+
+    /// Appends a signal to the message and the logged signals.
+    pub fn push_signal(&mut self, name: String, signal: u32) {
+        self.push_str(&name);
+        self.signals.push(LoggedSignal {
+            name,
+            signal,
+            wire: None,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_entry() {
+        let mut entry = LogEntry::new(String::from("0.c"));
+        entry.push_str("sum:");
+        entry.push_signal(String::from("0.c.sum"), 4);
+        entry.push_str("10");
+
+        assert_eq!(entry.message, "sum: 0.c.sum 10");
+        assert_eq!(
+            serde_json::to_string(&entry).unwrap(),
+            r#"{"source":null,"context":"0.c","message":"sum: 0.c.sum 10","signals":[{"name":"0.c.sum","signal":4,"wire":null}]}"#
+        );
+    }
+}
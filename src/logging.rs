@@ -0,0 +1,48 @@
+//! # Logging Module
+//!
+//! This module defines the log targets of the compilation stages, so each one can be filtered on its
+//! own through `LOG_LEVEL`, e.g. `LOG_LEVEL=info,arithc::emit=debug`.
+
+use env_logger::{Builder, Env};
+use log::LevelFilter;
+
+/// Parsing and type analysis of the circom program.
+pub const PARSER: &str = "arithc::parser";
+/// Compile-time evaluation of statements and expressions.
+pub const PROCESS: &str = "arithc::process";
+/// Construction and optimization of the node and gate graph.
+pub const GRAPH: &str = "arithc::graph";
+/// Circuit building and artifact writing.
+pub const EMIT: &str = "arithc::emit";
+
+/// Returns the log level set by the given number of `-v` flags, if any.
+pub fn verbosity_level(verbosity: u8) -> Option<LevelFilter> {
+    match verbosity {
+        0 => None,
+        1 => Some(LevelFilter::Debug),
+        _ => Some(LevelFilter::Trace),
+    }
+}
+
+/// Initializes the logger from `LOG_LEVEL` (defaulting to `info`).
+/// A non-zero verbosity raises the default level, while per-target directives of `LOG_LEVEL` are kept.
+pub fn init_logger(verbosity: u8) {
+    let mut builder = Builder::from_env(Env::default().filter_or("LOG_LEVEL", "info"));
+    if let Some(level) = verbosity_level(verbosity) {
+        builder.filter_level(level);
+    }
+    builder.init();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verbosity_level() {
+        assert_eq!(verbosity_level(0), None);
+        assert_eq!(verbosity_level(1), Some(LevelFilter::Debug));
+        assert_eq!(verbosity_level(2), Some(LevelFilter::Trace));
+        assert_eq!(verbosity_level(5), Some(LevelFilter::Trace));
+    }
+}
@@ -0,0 +1,358 @@
+//! # Lowering Module
+//!
+//! This module lowers the gates boolify doesn't support (powers, integer divisions, remainders and
+//! shifts) into compositions of the gates it does: additions, subtractions, multiplications and
+//! comparisons. Gates are lowered for the integer width of the boolean circuit, so the lowered
+//! gates wrap around like the integers of boolify. The gates are emitted through a `GateSink`,
+//! e.g. the gate graph of the compiler with `Compiler::lower_for_boolify`. Targets lower the gates
+//! that only need the gates they support, e.g. powers by a constant with `Compiler::lower_for_target`.
+
+use crate::{a_gate_type::AGateType, cli::ValueType};
+
+/// Gates lowered by `lower_gate`, the others are supported by boolify.
+pub const LOWERED_GATES: [AGateType; 5] = [
+    AGateType::APow,
+    AGateType::AIntDiv,
+    AGateType::AMod,
+    AGateType::AShiftL,
+    AGateType::AShiftR,
+];
+
+/// An input or result of a lowered gate, known at compile time or carried by a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    Constant(u32),
+    Node(u32),
+}
+
+/// Receives the nodes and gates a gate is lowered into.
+pub trait GateSink {
+    /// Adds a constant node, returning its id.
+    fn constant(&mut self, value: u32) -> u32;
+
+    /// Adds a gate writing to a new node, returning the id of the node.
+    fn gate(&mut self, op: AGateType, lh_in: u32, rh_in: u32) -> u32;
+}
+
+/// Lowers a gate on unsigned integers of the given width, at most 32 bits, into gates of the sink,
+/// returning the operand holding its result. Gates only reading constants are folded, and gates
+/// not in `LOWERED_GATES` are emitted as is.
+///
+/// Like boolify, lowered gates don't take the sign into account: divisions and remainders are
+/// unsigned. Dividing by zero gives a quotient of all ones and the dividend as the remainder.
+/// Signed gates, e.g. `AShiftRArith`, are emitted as is, and boolify rejects them.
+pub fn lower_gate(
+    sink: &mut dyn GateSink,
+    op: AGateType,
+    lhs: Operand,
+    rhs: Operand,
+    width: usize,
+) -> Operand {
+    let mut lowering = Lowering {
+        sink,
+        width: width.clamp(1, 32) as u32,
+    };
+    let (lhs, rhs) = (lowering.truncate(lhs), lowering.truncate(rhs));
+
+    match op {
+        AGateType::APow => lowering.pow(lhs, rhs),
+        AGateType::AIntDiv => lowering.div_rem(lhs, rhs).0,
+        AGateType::AMod => lowering.div_rem(lhs, rhs).1,
+        AGateType::AShiftL => {
+            let factor = lowering.pow(Operand::Constant(2), rhs);
+            lowering.op(AGateType::AMul, lhs, factor)
+        }
+        AGateType::AShiftR => {
+            let divisor = lowering.pow(Operand::Constant(2), rhs);
+            let (quotient, _) = lowering.div_rem(lhs, divisor);
+            // Shifting by the width or more wraps the divisor around to zero, and the result is 0
+            let in_range = lowering.op(AGateType::ANeq, divisor, Operand::Constant(0));
+            lowering.op(AGateType::AMul, quotient, in_range)
+        }
+        op => lowering.op(op, lhs, rhs),
+    }
+}
+
+/// Returns the gates a gate is lowered into by `lower_gate`, without emitting them.
+pub fn lowered_ops(op: AGateType, lhs: Operand, rhs: Operand, width: usize) -> Vec<AGateType> {
+    #[derive(Default)]
+    struct Recorder {
+        ops: Vec<AGateType>,
+        nodes: u32,
+    }
+
+    impl GateSink for Recorder {
+        fn constant(&mut self, _value: u32) -> u32 {
+            self.nodes += 1;
+            self.nodes
+        }
+
+        fn gate(&mut self, op: AGateType, _lh_in: u32, _rh_in: u32) -> u32 {
+            self.ops.push(op);
+            self.nodes += 1;
+            self.nodes
+        }
+    }
+
+    let mut recorder = Recorder::default();
+    lower_gate(&mut recorder, op, lhs, rhs, width);
+    recorder.ops
+}
+
+/// Lowers gates into the gates of a sink.
+struct Lowering<'a> {
+    sink: &'a mut dyn GateSink,
+    width: u32,
+}
+
+impl Lowering<'_> {
+    /// Truncates a constant to the width.
+    fn truncate(&self, operand: Operand) -> Operand {
+        match operand {
+            Operand::Constant(value) => Operand::Constant(value & (u32::MAX >> (32 - self.width))),
+            node => node,
+        }
+    }
+
+    /// Emits a gate, or folds it when its result is known.
+    fn op(&mut self, op: AGateType, lhs: Operand, rhs: Operand) -> Operand {
+        use Operand::Constant;
+
+        match (op, lhs, rhs) {
+            (_, Constant(lhs), Constant(rhs)) => {
+                if let Some(value) = op.evaluate(lhs, rhs, ValueType::Uint) {
+                    return self.truncate(Constant(value));
+                }
+            }
+            (AGateType::AMul, Constant(0), _) | (AGateType::AMul, _, Constant(0)) => {
+                return Constant(0);
+            }
+            (AGateType::AMul, Constant(1), operand)
+            | (AGateType::AMul, operand, Constant(1))
+            | (AGateType::AAdd, Constant(0), operand)
+            | (AGateType::AAdd, operand, Constant(0))
+            | (AGateType::ASub, operand, Constant(0)) => return operand,
+            _ => {}
+        }
+
+        let lh_in = self.node(lhs);
+        let rh_in = self.node(rhs);
+        Operand::Node(self.sink.gate(op, lh_in, rh_in))
+    }
+
+    fn node(&mut self, operand: Operand) -> u32 {
+        match operand {
+            Operand::Constant(value) => self.sink.constant(value),
+            Operand::Node(node_id) => node_id,
+        }
+    }
+
+    /// Returns the bits of a value, most significant first. Doubling the value shifts its bits
+    /// left, and wraps around exactly when the bit shifted out is set.
+    fn bits(&mut self, mut value: Operand) -> Vec<Operand> {
+        (0..self.width)
+            .map(|_| {
+                let doubled = self.op(AGateType::AAdd, value, value);
+                let bit = self.op(AGateType::ALt, doubled, value);
+                value = doubled;
+                bit
+            })
+            .collect()
+    }
+
+    /// Returns `then` if the condition is 1, `otherwise` if it is 0.
+    fn select(&mut self, condition: Operand, then: Operand, otherwise: Operand) -> Operand {
+        let difference = self.op(AGateType::ASub, then, otherwise);
+        let offset = self.op(AGateType::AMul, condition, difference);
+        self.op(AGateType::AAdd, otherwise, offset)
+    }
+
+    /// Square and multiply, over the bits of the exponent.
+    fn pow(&mut self, base: Operand, exponent: Operand) -> Operand {
+        let mut result = Operand::Constant(1);
+        for bit in self.bits(exponent) {
+            result = self.op(AGateType::AMul, result, result);
+            result = match bit {
+                Operand::Constant(0) => result,
+                Operand::Constant(_) => self.op(AGateType::AMul, result, base),
+                Operand::Node(_) => {
+                    let multiplied = self.op(AGateType::AMul, result, base);
+                    self.select(bit, multiplied, result)
+                }
+            };
+        }
+
+        result
+    }
+
+    /// Restoring long division, over the bits of the dividend. Returns the quotient and the
+    /// remainder.
+    fn div_rem(&mut self, dividend: Operand, divisor: Operand) -> (Operand, Operand) {
+        let mut quotient = Operand::Constant(0);
+        let mut remainder = Operand::Constant(0);
+        for bit in self.bits(dividend) {
+            // The remainder is below the divisor, so when doubling it wraps around, the shifted
+            // remainder exceeds the divisor and the subtraction wraps back
+            let doubled = self.op(AGateType::AAdd, remainder, remainder);
+            let carry = self.op(AGateType::ALt, doubled, remainder);
+            let shifted = self.op(AGateType::AAdd, doubled, bit);
+            let fits = self.op(AGateType::AGEq, shifted, divisor);
+            let any = self.op(AGateType::AAdd, carry, fits);
+            let subtract = self.op(AGateType::ANeq, any, Operand::Constant(0));
+
+            let subtracted = self.op(AGateType::AMul, subtract, divisor);
+            remainder = self.op(AGateType::ASub, shifted, subtracted);
+            let quotient_doubled = self.op(AGateType::AAdd, quotient, quotient);
+            quotient = self.op(AGateType::AAdd, quotient_doubled, subtract);
+        }
+
+        (quotient, remainder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Evaluates the gates as they are emitted, on integers of the given width.
+    struct Evaluator {
+        width: u32,
+        values: Vec<u32>,
+        gates: Vec<AGateType>,
+    }
+
+    impl Evaluator {
+        fn new(width: u32) -> Self {
+            Self {
+                width,
+                values: Vec::new(),
+                gates: Vec::new(),
+            }
+        }
+
+        fn push(&mut self, value: u32) -> u32 {
+            self.values.push(value & (u32::MAX >> (32 - self.width)));
+            self.values.len() as u32 - 1
+        }
+
+        fn lower(&mut self, op: AGateType, lhs: u32, rhs: u32) -> u32 {
+            let lhs = Operand::Node(self.push(lhs));
+            let rhs = Operand::Node(self.push(rhs));
+            let width = self.width as usize;
+            match lower_gate(self, op, lhs, rhs, width) {
+                Operand::Constant(value) => value,
+                Operand::Node(node_id) => self.values[node_id as usize],
+            }
+        }
+    }
+
+    impl GateSink for Evaluator {
+        fn constant(&mut self, value: u32) -> u32 {
+            self.push(value)
+        }
+
+        fn gate(&mut self, op: AGateType, lh_in: u32, rh_in: u32) -> u32 {
+            self.gates.push(op);
+            let (lhs, rhs) = (self.values[lh_in as usize], self.values[rh_in as usize]);
+            self.push(op.evaluate(lhs, rhs, ValueType::Uint).unwrap())
+        }
+    }
+
+    #[test]
+    fn test_lower_gate() {
+        let mut evaluator = Evaluator::new(8);
+        for lhs in (0..256u32).step_by(7).chain([255]) {
+            for rhs in (0..256u32).step_by(13).chain([1, 2, 7, 8, 9, 255]) {
+                let expected = [
+                    (AGateType::APow, lhs.wrapping_pow(rhs) & 0xff),
+                    (AGateType::AIntDiv, lhs.checked_div(rhs).unwrap_or(0xff)),
+                    (AGateType::AMod, lhs.checked_rem(rhs).unwrap_or(lhs)),
+                    (AGateType::AShiftL, lhs.checked_shl(rhs).unwrap_or(0) & 0xff),
+                    (AGateType::AShiftR, lhs.checked_shr(rhs).unwrap_or(0)),
+                ];
+                for (op, expected) in expected {
+                    assert_eq!(
+                        evaluator.lower(op, lhs, rhs),
+                        expected,
+                        "{} {} {}",
+                        op,
+                        lhs,
+                        rhs
+                    );
+                }
+            }
+        }
+        assert!(evaluator.gates.iter().all(|op| !LOWERED_GATES.contains(op)));
+
+        // 32-bit integers
+        let mut evaluator = Evaluator::new(32);
+        assert_eq!(
+            evaluator.lower(AGateType::AIntDiv, u32::MAX, 7),
+            u32::MAX / 7
+        );
+        assert_eq!(
+            evaluator.lower(AGateType::AMod, u32::MAX, 1 << 31),
+            i32::MAX as u32
+        );
+        assert_eq!(
+            evaluator.lower(AGateType::APow, 3, 21),
+            3u32.wrapping_pow(21)
+        );
+        assert_eq!(evaluator.lower(AGateType::AShiftL, 3, 31), 1 << 31);
+        assert_eq!(evaluator.lower(AGateType::AShiftR, u32::MAX, 31), 1);
+        assert_eq!(evaluator.lower(AGateType::AShiftR, u32::MAX, 32), 0);
+    }
+
+    #[test]
+    fn test_lowered_ops() {
+        let node = Operand::Node(0);
+        let ops = lowered_ops(AGateType::APow, node, Operand::Constant(5), 32);
+        assert!(!ops.is_empty());
+        assert!(ops.iter().all(|&op| op == AGateType::AMul));
+        assert_eq!(
+            lowered_ops(AGateType::AShiftL, node, Operand::Constant(3), 32),
+            [AGateType::AMul]
+        );
+        assert!(
+            lowered_ops(AGateType::AIntDiv, node, Operand::Constant(3), 32)
+                .contains(&AGateType::ALt)
+        );
+    }
+
+    #[test]
+    fn test_lower_gate_constants() {
+        // Lowering a power by a constant exponent only multiplies
+        let mut evaluator = Evaluator::new(16);
+        let base = Operand::Node(evaluator.push(3));
+        let result = lower_gate(
+            &mut evaluator,
+            AGateType::APow,
+            base,
+            Operand::Constant(5),
+            16,
+        );
+        assert_eq!(result, Operand::Node(evaluator.values.len() as u32 - 1));
+        assert_eq!(evaluator.values[evaluator.values.len() - 1], 243);
+        assert!(evaluator.gates.iter().all(|&op| op == AGateType::AMul));
+
+        // Constants are truncated to the width, and gates only reading constants are folded
+        let result = lower_gate(
+            &mut evaluator,
+            AGateType::AShiftL,
+            Operand::Constant(0x1_0003),
+            Operand::Constant(2),
+            16,
+        );
+        assert_eq!(result, Operand::Constant(12));
+        assert_eq!(
+            lower_gate(
+                &mut evaluator,
+                AGateType::AAdd,
+                Operand::Constant(1),
+                Operand::Constant(2),
+                16
+            ),
+            Operand::Constant(3)
+        );
+    }
+}
@@ -1,50 +1,261 @@
-use boolify::boolify;
 use circom_2_arithc::{
-    cli::{build_output, Args},
-    program::{compile, ProgramError},
+    check::check_files,
+    cli::{Args, Command},
+    diagnostics::Diagnostic,
+    diff::diff_files,
+    evaluate::watch_files,
+    fixed_point::IoSchema,
+    logging::{init_logger, EMIT},
+    package::{compile_package, PackageManifest},
+    prebuild::{prebuild, PrebuildManifest},
+    program::{compile_and_write, estimate, ProgramError},
+    scaffold::init_project,
 };
 use clap::Parser;
 use dotenv::dotenv;
-use env_logger::{init_from_env, Env};
+use log::{error, info, warn};
 use serde_json::to_string_pretty;
-use std::{
-    fs::{self, File},
-    io::Write,
-};
+use std::{fs, io, process};
 
-fn main() -> Result<(), ProgramError> {
-    dotenv().ok();
-    init_from_env(Env::default().filter_or("LOG_LEVEL", "info"));
+fn main() {
+    if let Err(error) = run() {
+        for diagnostic in Diagnostic::from_errors(&error) {
+            eprintln!("{}\n", diagnostic);
+        }
+        if let ProgramError::Diagnostics(_) = error {
+            eprintln!("error: {}", error);
+        }
+        process::exit(1);
+    }
+}
 
+fn run() -> Result<(), ProgramError> {
+    dotenv().ok();
     let args = Args::parse();
+    init_logger(args.verbose);
 
-    let compiler = compile(&args)?;
-    let report = compiler.generate_circuit_report()?;
-
-    let output_dir = args.output.clone();
-    fs::create_dir_all(output_dir.clone())
-        .map_err(|_| ProgramError::OutputDirectoryCreationError)?;
-
-    let mut circuit = compiler.build_circuit()?;
-
-    if let Some(boolify_width) = args.boolify_width {
-        circuit = boolify(&circuit, boolify_width);
+    match &args.command {
+        Some(Command::Init { dir }) => {
+            let files = init_project(dir)?;
+            info!(
+                target: EMIT,
+                "Scaffolded a project of {} files in {}, run its tests with `cargo test`",
+                files.len(),
+                dir.display()
+            );
+            return Ok(());
+        }
+        Some(Command::Evaluate {
+            circuit,
+            info,
+            inputs,
+            outputs,
+            watch,
+            signals,
+            watch_log,
+        }) => {
+            let info = info
+                .clone()
+                .unwrap_or_else(|| circuit.with_file_name("circuit_info.json"));
+            let signals = signals.clone().or_else(|| {
+                let path = circuit.with_file_name("signals.sym");
+                (!watch.is_empty() && path.exists()).then_some(path)
+            });
+            let (values, watches) = watch_files(
+                circuit,
+                &info,
+                inputs,
+                args.value_type,
+                &args.normalize_names,
+                watch,
+                signals.as_deref(),
+            )?;
+            for hit in watches.hits() {
+                info!(target: EMIT, "{}", hit);
+            }
+            if let Some(path) = watch_log {
+                fs::write(path, to_string_pretty(watches.hits())?)?;
+                info!(
+                    target: EMIT,
+                    "Wrote {} watched values to {}",
+                    watches.hits().len(),
+                    path.display()
+                );
+            }
+            let Some(values) = values else {
+                if let Some(hit) = watches.break_hit() {
+                    warn!(target: EMIT, "Stopped at {}", hit);
+                }
+                return Ok(());
+            };
+            let json = to_string_pretty(&values)?;
+            match outputs {
+                Some(path) => {
+                    fs::write(path, json)?;
+                    info!(target: EMIT, "Wrote {} outputs to {}", values.len(), path.display());
+                }
+                None => println!("{}", json),
+            }
+            return Ok(());
+        }
+        Some(Command::Diff {
+            old,
+            new,
+            output,
+            dot,
+        }) => {
+            let diff = diff_files(old, new, output)?;
+            info!(
+                target: EMIT,
+                "Cone of {}: {}",
+                output,
+                to_string_pretty(&diff.counts())?
+            );
+            match dot {
+                Some(path) => {
+                    diff.write_dot(&mut fs::File::create(path)?)?;
+                    info!(target: EMIT, "Wrote {}", path.display());
+                }
+                None => diff.write_dot(&mut io::stdout())?,
+            }
+            return Ok(());
+        }
+        Some(Command::Check { circuit, info }) => {
+            let info = info
+                .clone()
+                .unwrap_or_else(|| circuit.with_file_name("circuit_info.json"));
+            let check = check_files(circuit, &info)?;
+            info!(
+                target: EMIT,
+                "{}: {} gates, {} wires, {} inputs, {} outputs, {} constants",
+                circuit.display(),
+                check.gate_count,
+                check.wire_count,
+                check.input_count,
+                check.output_count,
+                check.constant_count
+            );
+            for (op, count) in &check.gates {
+                info!(target: EMIT, "  {} {}", op, count);
+            }
+            for problem in &check.problems {
+                error!(target: EMIT, "{}", problem);
+            }
+            if check.problem_count > check.problems.len() {
+                error!(
+                    target: EMIT,
+                    "{} more problems",
+                    check.problem_count - check.problems.len()
+                );
+            }
+            if !check.is_valid() {
+                return Err(ProgramError::CircuitCheckFailed(check.problem_count));
+            }
+            return Ok(());
+        }
+        Some(Command::Encode {
+            schema,
+            inputs,
+            encoded,
+        }) => {
+            let schema = IoSchema::from_path(schema)?;
+            let Some(inputs) = inputs else {
+                for range in schema.ranges(args.value_type) {
+                    info!(target: EMIT, "{}", range);
+                }
+                return Ok(());
+            };
+            let values = schema.encode_inputs(
+                &serde_json::from_str(&fs::read_to_string(inputs)?)?,
+                args.value_type,
+            )?;
+            let json = to_string_pretty(&values)?;
+            match encoded {
+                Some(path) => {
+                    fs::write(path, json)?;
+                    info!(target: EMIT, "Wrote {} inputs to {}", values.len(), path.display());
+                }
+                None => println!("{}", json),
+            }
+            return Ok(());
+        }
+        Some(Command::Prebuild { manifest }) => {
+            let manifest = PrebuildManifest::from_path(manifest)?;
+            for (name, archive) in prebuild(&args, &manifest)? {
+                info!(
+                    target: EMIT,
+                    "{} {} of {} gates to {}",
+                    if archive.reused { "Kept" } else { "Wrote" },
+                    name,
+                    archive.gate_count,
+                    archive.path.display()
+                );
+            }
+            return Ok(());
+        }
+        None => {}
     }
 
-    let output_file_path = build_output(&output_dir, "circuit", "txt");
-    circuit.write_bristol(&mut File::create(output_file_path)?)?;
-
-    // let output_file_path_json = build_output(&output_dir, "circuit", "json");
-    // File::create(output_file_path_json)?.write_all(serde_json::to_string_pretty(&circuit)?.as_bytes())?;
+    let manifest = match (&args.package, &args.input_dir) {
+        (Some(package), _) => Some(PackageManifest::from_path(package)?),
+        (None, Some(dir)) => {
+            let manifest = PackageManifest::from_dir(dir)?;
+            if manifest.circuits.is_empty() {
+                warn!(
+                    target: EMIT,
+                    "No .circom file in {} declares a main component",
+                    dir.display()
+                );
+            }
+            Some(manifest)
+        }
+        (None, None) => None,
+    };
+    if let Some(manifest) = manifest {
+        let artifacts = compile_package(&args, &manifest)?;
+        for (name, circuit) in &artifacts.circuits {
+            info!(
+                target: EMIT,
+                "Wrote {} gates and {} wires of {} to {} in {:.2?}",
+                circuit.gate_count,
+                circuit.wire_count,
+                name,
+                circuit.circuit.display(),
+                circuit.compile_time
+            );
+        }
+        return Ok(());
+    }
 
-    // let output_debug_path_json = build_output(&output_dir, "debug", "json");
-    // File::create(output_debug_path_json)?.write_all(serde_json::to_string_pretty(&compiler)?.as_bytes())?;
+    if args.estimate {
+        if args.boolify_width.is_some() {
+            warn!(
+                target: EMIT,
+                "The estimate is of the arithmetic circuit, before boolification"
+            );
+        }
+        let estimate = estimate(&args)?;
+        println!("{}", to_string_pretty(&estimate)?);
+        info!(
+            target: EMIT,
+            "Estimated {} gates, {} wires and {} bytes of circuit files",
+            estimate.gate_count,
+            estimate.wire_count,
+            estimate.total_bytes()
+        );
+        return Ok(());
+    }
 
-    let output_file_path = build_output(&output_dir, "circuit_info", "json");
-    File::create(output_file_path)?.write_all(to_string_pretty(&circuit.info)?.as_bytes())?;
+    let artifacts = compile_and_write(&args)?;
 
-    let report_file_path = build_output(&output_dir, "report", "json");
-    File::create(report_file_path)?.write_all(to_string_pretty(&report)?.as_bytes())?;
+    info!(
+        target: EMIT,
+        "Wrote {} gates and {} wires to {} in {:.2?}",
+        artifacts.gate_count,
+        artifacts.wire_count,
+        artifacts.circuit.display(),
+        artifacts.compile_time
+    );
 
     Ok(())
 }
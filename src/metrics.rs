@@ -0,0 +1,91 @@
+//! # Metrics Module
+//!
+//! This module exports circuit statistics in the Prometheus textfile format.
+
+use crate::a_gate_type::bristol_op_name;
+use bristol_circuit::BristolCircuit;
+use std::{collections::BTreeMap, io, io::Write, time::Duration};
+
+/// Writes the circuit statistics in the Prometheus text exposition format.
+pub fn write_metrics<W: Write>(
+    writer: &mut W,
+    circuit: &BristolCircuit,
+    compile_time: Duration,
+) -> io::Result<()> {
+    // Count gates by operation, sorted so the output is deterministic
+    let mut gates_by_op = BTreeMap::<&str, usize>::new();
+    for gate in &circuit.gates {
+        *gates_by_op.entry(bristol_op_name(&gate.op)).or_default() += 1;
+    }
+
+    writeln!(writer, "# HELP gates_total Number of gates in the circuit.")?;
+    writeln!(writer, "# TYPE gates_total gauge")?;
+    for (op, count) in gates_by_op {
+        writeln!(
+            writer,
+            "gates_total{{op=\"{}\"}} {}",
+            escape_label(op),
+            count
+        )?;
+    }
+
+    writeln!(writer, "# HELP wires_total Number of wires in the circuit.")?;
+    writeln!(writer, "# TYPE wires_total gauge")?;
+    writeln!(writer, "wires_total {}", circuit.wire_count)?;
+
+    writeln!(
+        writer,
+        "# HELP compile_seconds Time spent compiling the circuit."
+    )?;
+    writeln!(writer, "# TYPE compile_seconds gauge")?;
+    writeln!(writer, "compile_seconds {}", compile_time.as_secs_f64())?;
+
+    Ok(())
+}
+
+/// Escapes a label value as required by the text format.
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bristol_circuit::{CircuitInfo, Gate};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_write_metrics() {
+        let gate = |op: &str| Gate {
+            inputs: vec![0, 1],
+            outputs: vec![2],
+            op: op.to_string(),
+        };
+        let circuit = BristolCircuit {
+            wire_count: 5,
+            info: CircuitInfo {
+                input_name_to_wire_index: HashMap::new(),
+                constants: HashMap::new(),
+                output_name_to_wire_index: HashMap::new(),
+            },
+            gates: vec![gate("AMul"), gate("AAdd"), gate("AMul")],
+            io_widths: None,
+        };
+
+        let mut buffer = Vec::new();
+        write_metrics(&mut buffer, &circuit, Duration::from_millis(1500)).unwrap();
+        let metrics = String::from_utf8(buffer).unwrap();
+
+        assert!(metrics.contains("gates_total{op=\"AAdd\"} 1\ngates_total{op=\"AMul\"} 2\n"));
+        assert!(metrics.contains("wires_total 5\n"));
+        assert!(metrics.contains("compile_seconds 1.5\n"));
+    }
+
+    #[test]
+    fn test_escape_label() {
+        assert_eq!(escape_label("a\"b\\c"), "a\\\"b\\\\c");
+    }
+}
@@ -0,0 +1,107 @@
+//! # Naming Module
+//!
+//! This module defines the strategies used to name signals, constants and IO in the compiled circuit.
+
+use crate::cli::NamingStyle;
+
+/// Maps the hierarchical names produced during compilation to the names emitted in the artifacts.
+pub trait NamingStrategy {
+    /// Returns the emitted name for the signal `id`, whose hierarchical name is `name`.
+    fn signal_name(&self, name: &str, id: u32) -> String;
+
+    /// Returns the emitted name for the constant signal `id`, whose hierarchical name is `name`.
+    fn constant_name(&self, name: &str, id: u32) -> String {
+        self.signal_name(&format!("{}_{}", name, id), id)
+    }
+}
+
+/// Keeps the hierarchical names, e.g. `0.out[1]` or `0.const_signal_8_1`.
+pub struct Hierarchical;
+
+impl NamingStrategy for Hierarchical {
+    fn signal_name(&self, name: &str, _id: u32) -> String {
+        name.to_string()
+    }
+}
+
+/// Names signals by their numeric id, e.g. `s12` for signals and `c3` for constants.
+pub struct FlatNumeric;
+
+impl NamingStrategy for FlatNumeric {
+    fn signal_name(&self, _name: &str, id: u32) -> String {
+        format!("s{}", id)
+    }
+
+    fn constant_name(&self, _name: &str, id: u32) -> String {
+        format!("c{}", id)
+    }
+}
+
+/// Names signals by a hash of their hierarchical name, e.g. `h6f0a2c3b9d1e4f57`.
+/// The names stay stable across compilations as long as the hierarchical name doesn't change.
+pub struct Hash;
+
+impl NamingStrategy for Hash {
+    fn signal_name(&self, name: &str, _id: u32) -> String {
+        format!("h{:016x}", fnv1a(name.as_bytes()))
+    }
+}
+
+/// Returns the naming strategy for the given style.
+pub fn naming_strategy(style: NamingStyle) -> Box<dyn NamingStrategy> {
+    match style {
+        NamingStyle::Hierarchical => Box::new(Hierarchical),
+        NamingStyle::FlatNumeric => Box::new(FlatNumeric),
+        NamingStyle::Hash => Box::new(Hash),
+    }
+}
+
+/// 64-bit FNV-1a hash, used because it is stable across platforms and releases.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    bytes.iter().fold(OFFSET_BASIS, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(PRIME)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hierarchical_naming() {
+        let strategy = naming_strategy(NamingStyle::Hierarchical);
+        assert_eq!(strategy.signal_name("0.out[1]", 4), "0.out[1]");
+        assert_eq!(
+            strategy.constant_name("0.const_signal_8", 1),
+            "0.const_signal_8_1"
+        );
+    }
+
+    #[test]
+    fn test_flat_numeric_naming() {
+        let strategy = naming_strategy(NamingStyle::FlatNumeric);
+        assert_eq!(strategy.signal_name("0.out[1]", 4), "s4");
+        assert_eq!(strategy.constant_name("0.const_signal_8", 1), "c1");
+    }
+
+    #[test]
+    fn test_hash_naming() {
+        let strategy = naming_strategy(NamingStyle::Hash);
+        let name = strategy.signal_name("0.out[1]", 4);
+
+        assert_eq!(name.len(), 17);
+        assert!(name.starts_with('h'));
+        // Stable regardless of the signal id
+        assert_eq!(name, strategy.signal_name("0.out[1]", 9));
+        assert_ne!(name, strategy.signal_name("0.out[2]", 4));
+    }
+
+    #[test]
+    fn test_fnv1a() {
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+}
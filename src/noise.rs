@@ -0,0 +1,204 @@
+//! # Noise Module
+//!
+//! This module adds the insertion points of differential privacy noise to the outputs of a
+//! compiled circuit. The noise itself is calibrated and sampled at runtime: the circuit gets an
+//! input per share of the noise of each marked output and adds them to the output, so the MPC
+//! runtime only supplies the noise shares.
+
+use crate::{
+    a_gate_type::AGateType,
+    compiler::{CircuitError, Compiler},
+    program::ProgramError,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+};
+
+/// An output, or an array of them, released with noise added, e.g. `0.out` for every element of
+/// `out`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NoisePoint {
+    pub signal: String,
+    /// Number of noise inputs added to each element, e.g. one per party so that no party knows
+    /// the noise.
+    #[serde(default = "default_shares")]
+    pub shares: usize,
+}
+
+fn default_shares() -> usize {
+    1
+}
+
+/// Outputs noise is added to, `{ "outputs": [...] }`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NoiseConfig {
+    #[serde(default)]
+    pub outputs: Vec<NoisePoint>,
+}
+
+impl NoiseConfig {
+    /// Reads a noise config from a JSON file.
+    pub fn from_path(path: &Path) -> Result<Self, ProgramError> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+}
+
+/// Adds the noise inputs of the config to the outputs of the circuit. Each output is the sum of
+/// its noiseless value, renamed with a `noiseless.` prefix, and of the noise inputs, named with a
+/// `noise<share>.` prefix, e.g. `noise0.0.out[1]`. The noise inputs are added after the inputs of
+/// the circuit.
+pub fn add_noise(compiler: &mut Compiler, config: &NoiseConfig) -> Result<(), ProgramError> {
+    let mut seen = HashSet::new();
+    for point in &config.outputs {
+        if point.shares == 0 {
+            return Err(ProgramError::InvalidNoise(format!(
+                "{} needs at least one share",
+                point.signal
+            )));
+        }
+        if !seen.insert(point.signal.as_str()) {
+            return Err(ProgramError::InvalidNoise(format!(
+                "{} is listed twice",
+                point.signal
+            )));
+        }
+
+        let signals = compiler.output_signals(&point.signal);
+        if signals.is_empty() {
+            return Err(CircuitError::UnknownSignal(point.signal.clone()).into());
+        }
+
+        let mut noise_inputs = HashMap::new();
+        for signal in signals {
+            let name = compiler
+                .io_name(signal)
+                .map(str::to_string)
+                .unwrap_or_default();
+            compiler.rename_signal(signal, format!("noiseless.{}", name))?;
+
+            // Shares are added one at a time, the last sum being the output
+            let mut sum = signal;
+            for share in 0..point.shares {
+                let noise = compiler.next_signal_id();
+                let noise_name = format!("noise{}.{}", share, name);
+                compiler.add_signal(noise, noise_name.clone(), None)?;
+                noise_inputs.insert(noise, noise_name);
+
+                let noisy = compiler.next_signal_id();
+                let noisy_name = if share + 1 == point.shares {
+                    name.clone()
+                } else {
+                    format!("noisy{}.{}", share, name)
+                };
+                compiler.add_signal(noisy, noisy_name, None)?;
+                compiler.add_gate(AGateType::AAdd, sum, noise, noisy)?;
+                sum = noisy;
+            }
+            compiler.replace_io(&[signal], sum, name)?;
+        }
+        compiler.add_inputs(noise_inputs);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::field::FieldElement;
+
+    /// out[i] = in[i] * in[i]
+    fn compiler() -> Compiler {
+        let mut compiler = Compiler::new();
+        for i in 0..2 {
+            let (input, output) = (1 + 2 * i, 2 + 2 * i);
+            compiler
+                .add_signal(input, format!("0.in[{}]", i), None)
+                .unwrap();
+            compiler
+                .add_signal(output, format!("0.out[{}]", i), None)
+                .unwrap();
+            compiler
+                .add_gate(AGateType::AMul, input, input, output)
+                .unwrap();
+            compiler.add_inputs(HashMap::from([(input, format!("0.in[{}]", i))]));
+            compiler.add_outputs(HashMap::from([(output, format!("0.out[{}]", i))]));
+        }
+        compiler
+    }
+
+    fn point(signal: &str, shares: usize) -> NoisePoint {
+        NoisePoint {
+            signal: signal.to_string(),
+            shares,
+        }
+    }
+
+    #[test]
+    fn test_noise_config() {
+        let config: NoiseConfig = serde_json::from_str(
+            r#"{ "outputs": [{ "signal": "0.out" }, { "signal": "0.y", "shares": 3 }] }"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.outputs, vec![point("0.out", 1), point("0.y", 3)]);
+    }
+
+    #[test]
+    fn test_add_noise() {
+        let mut compiler = compiler();
+        let config = NoiseConfig {
+            outputs: vec![point("0.out", 2)],
+        };
+        add_noise(&mut compiler, &config).unwrap();
+        compiler.debug_assert_valid().unwrap();
+
+        let inputs = [
+            ("0.in[0]", 3),
+            ("0.in[1]", 4),
+            ("noise0.0.out[0]", 10),
+            ("noise1.0.out[0]", 20),
+            ("noise0.0.out[1]", 30),
+            ("noise1.0.out[1]", 40),
+        ]
+        .iter()
+        .map(|&(name, value)| (name.to_string(), FieldElement::from(value)))
+        .collect();
+        let outputs = compiler.evaluate_outputs(&inputs).unwrap();
+        assert_eq!(outputs["0.out[0]"], FieldElement::from(9 + 10 + 20));
+        assert_eq!(outputs["0.out[1]"], FieldElement::from(16 + 30 + 40));
+
+        // Noise inputs come after the inputs of the circuit
+        let circuit = compiler.build_circuit().unwrap();
+        let wires = &circuit.info.input_name_to_wire_index;
+        assert_eq!(wires["0.in[0]"], 0);
+        assert_eq!(wires["0.in[1]"], 1);
+        assert_eq!(wires.len(), 6);
+        assert_eq!(circuit.gates.len(), 6);
+    }
+
+    #[test]
+    fn test_add_noise_errors() {
+        let apply = |outputs: Vec<NoisePoint>| {
+            add_noise(&mut compiler(), &NoiseConfig { outputs })
+                .unwrap_err()
+                .to_string()
+        };
+
+        assert_eq!(
+            apply(vec![point("0.in", 1)]),
+            "Circuit error: Unknown signal 0.in"
+        );
+        assert_eq!(
+            apply(vec![point("0.out", 0)]),
+            "Invalid noise insertion point: 0.out needs at least one share"
+        );
+        assert_eq!(
+            apply(vec![point("0.out", 1), point("0.out", 2)]),
+            "Invalid noise insertion point: 0.out is listed twice"
+        );
+    }
+}
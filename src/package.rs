@@ -0,0 +1,436 @@
+//! # Package Module
+//!
+//! This module compiles several main components listed in a manifest, or found in a directory,
+//! into a package directory, with the artifacts of each circuit in its own subdirectory and a pool
+//! of the constants used across the circuits. Circuits are compiled in parallel, from a single
+//! parse of their sources when they can be parsed together.
+
+use crate::{
+    artifacts::to_canonical_json,
+    circom::{parser::try_parse, VERSION},
+    cli::{build_output, Args},
+    deps::DependencyGraph,
+    logging::EMIT,
+    program::{compile_and_write, compile_and_write_archive, Artifacts, ProgramError},
+    sources::find_main,
+};
+use bristol_circuit::CircuitInfo;
+use circom_program_structure::{
+    ast::{Expression, Statement},
+    program_archive::ProgramArchive,
+};
+use log::debug;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+/// Prefix of the templates calling the main template of each circuit in the shared program.
+const MAIN_WRAPPER: &str = "__package_main_";
+
+/// Number of shared programs written, so concurrent packages use distinct scratch directories.
+static SHARED_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Circuits of a package, `{ "circuits": { "name": "path/to/main.circom" } }`. Entry files are
+/// relative to the manifest.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackageManifest {
+    pub circuits: BTreeMap<String, PathBuf>,
+}
+
+impl PackageManifest {
+    /// Reads a manifest, resolving its entry files relative to its directory.
+    pub fn from_path(path: &Path) -> Result<Self, ProgramError> {
+        let mut manifest: PackageManifest = serde_json::from_str(&fs::read_to_string(path)?)?;
+        let base = path.parent().unwrap_or(Path::new(""));
+        for input in manifest.circuits.values_mut() {
+            *input = base.join(&input);
+        }
+
+        Ok(manifest)
+    }
+
+    /// Lists the `.circom` files of a directory declaring a main component, named after their
+    /// file stem. Other files, e.g. the templates they include, are left out.
+    pub fn from_dir(dir: &Path) -> Result<Self, ProgramError> {
+        let main = Regex::new(r"(?m)^\s*component\s+main\b").unwrap();
+        let mut circuits = BTreeMap::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "circom") {
+                continue;
+            }
+            if !main.is_match(&fs::read_to_string(&path)?) {
+                continue;
+            }
+            if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) {
+                circuits.insert(name.to_string(), path.clone());
+            }
+        }
+
+        Ok(Self { circuits })
+    }
+}
+
+/// Constant values deduplicated across the circuits of a package, `constants.json`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConstantsPool {
+    /// Distinct values, in the order they are first used.
+    pub values: Vec<String>,
+    /// Index in `values` of each constant, by circuit and constant name.
+    pub circuits: BTreeMap<String, BTreeMap<String, usize>>,
+    /// Index in `values` of each value.
+    #[serde(skip)]
+    index: HashMap<String, usize>,
+}
+
+impl PartialEq for ConstantsPool {
+    fn eq(&self, other: &Self) -> bool {
+        self.values == other.values && self.circuits == other.circuits
+    }
+}
+
+impl Eq for ConstantsPool {}
+
+impl ConstantsPool {
+    /// Adds the constants of a circuit to the pool.
+    pub fn add_circuit(&mut self, name: &str, info: &CircuitInfo) {
+        let mut constants = info.constants.iter().collect::<Vec<_>>();
+        constants.sort_by_key(|(_, constant)| constant.wire_index);
+
+        // Deserialized pools start without an index
+        if self.index.len() != self.values.len() {
+            self.index = (self.values.iter().cloned())
+                .enumerate()
+                .map(|(index, value)| (value, index))
+                .collect();
+        }
+
+        let mut indices = BTreeMap::new();
+        for (constant_name, constant) in constants {
+            let index = *self.index.entry(constant.value.clone()).or_insert_with(|| {
+                self.values.push(constant.value.clone());
+                self.values.len() - 1
+            });
+            indices.insert(constant_name.clone(), index);
+        }
+
+        self.circuits.insert(name.to_string(), indices);
+    }
+}
+
+/// Paths of the artifacts written by `compile_package`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PackageArtifacts {
+    /// Artifacts of each circuit, written to a subdirectory named after the circuit.
+    pub circuits: BTreeMap<String, Artifacts>,
+    /// Constants pool, `constants.json`.
+    pub constants: PathBuf,
+}
+
+/// Compiles each circuit of a manifest with the given arguments, writing its artifacts to
+/// `<output>/<name>/`, and writes the constants pool of the package to `<output>/constants.json`.
+/// Metrics, events and audit log files are written to each circuit directory under their given
+/// file name. Up to `--jobs` circuits are compiled in parallel, and the first error by circuit
+/// name is returned.
+pub fn compile_package(
+    args: &Args,
+    manifest: &PackageManifest,
+) -> Result<PackageArtifacts, ProgramError> {
+    let entries = manifest.circuits.iter().collect::<Vec<_>>();
+    let jobs = args
+        .jobs
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, |jobs| jobs.get()))
+        .clamp(1, entries.len().max(1));
+
+    let shared = SharedProgram::parse(args, manifest)?;
+    if shared.is_none() {
+        debug!(target: EMIT, "Circuits don't parse together, parsing them one by one");
+    }
+
+    // Workers take the next circuit until there are none left
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(BTreeMap::new());
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            scope.spawn(|| {
+                while let Some(&(name, input)) = entries.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    let result = compile_circuit(args, name, input, shared.as_ref());
+                    results.lock().unwrap().insert(name.clone(), result);
+                }
+            });
+        }
+    });
+
+    let mut circuits = BTreeMap::new();
+    let mut pool = ConstantsPool::default();
+    for (name, result) in results.into_inner().unwrap() {
+        let artifacts = result?;
+        let info: CircuitInfo =
+            serde_json::from_str(&fs::read_to_string(&artifacts.circuit_info)?)?;
+        pool.add_circuit(&name, &info);
+        circuits.insert(name, artifacts);
+    }
+
+    let constants_path = build_output(&args.output, "constants", "json");
+    File::create(&constants_path)?.write_all(to_canonical_json(&pool)?.as_bytes())?;
+    debug!(target: EMIT, "Wrote {}", constants_path.display());
+
+    Ok(PackageArtifacts {
+        circuits,
+        constants: constants_path,
+    })
+}
+
+/// Compiles a circuit of a package into `<output>/<name>/`, from the shared program if any.
+fn compile_circuit(
+    args: &Args,
+    name: &str,
+    input: &Path,
+    shared: Option<&SharedProgram>,
+) -> Result<Artifacts, ProgramError> {
+    let mut circuit_args = args.clone();
+    circuit_args.input = input.to_path_buf();
+    circuit_args.output = args.output.join(name);
+    circuit_args.metrics_file = in_dir(&circuit_args.output, &args.metrics_file);
+    circuit_args.events_file = in_dir(&circuit_args.output, &args.events_file);
+    circuit_args.audit_log = in_dir(&circuit_args.output, &args.audit_log);
+
+    debug!(target: EMIT, "Compiling {} from {}", name, input.display());
+    match shared.and_then(|shared| shared.program(name)) {
+        Some((program_archive, deps)) => {
+            compile_and_write_archive(&circuit_args, program_archive, deps)
+        }
+        None => compile_and_write(&circuit_args),
+    }
+}
+
+/// The circuits of a package parsed in a single run of the parser, so the files they include are
+/// parsed once. The parser takes a single main component, so the entry files are copied without
+/// their main component, and a scratch program includes them and calls the main template of each
+/// circuit from a template of its own.
+struct SharedProgram {
+    program_archive: ProgramArchive,
+    /// Main component of each circuit.
+    mains: BTreeMap<String, SharedMain>,
+}
+
+struct SharedMain {
+    call: Expression,
+    public: Vec<String>,
+    deps: DependencyGraph,
+}
+
+impl SharedProgram {
+    /// Parses the circuits of a manifest together. Returns `None` if they don't parse together,
+    /// e.g. if entry files define the same template or one has no main component.
+    fn parse(args: &Args, manifest: &PackageManifest) -> Result<Option<Self>, ProgramError> {
+        let scratch = ScratchDir(std::env::temp_dir().join(format!(
+            "circom_2_arithc_package_{}_{}",
+            process::id(),
+            SHARED_COUNT.fetch_add(1, Ordering::Relaxed)
+        )));
+
+        let mut root = format!("pragma circom {};\n", VERSION);
+        let mut link_libraries = args.link_libraries.clone();
+        let mut entries = Vec::new();
+        for (index, (name, input)) in manifest.circuits.iter().enumerate() {
+            let source = fs::read_to_string(input)?;
+            let Some(main) = find_main(&source) else {
+                return Ok(None);
+            };
+            let Some(file_name) = input.file_name() else {
+                return Ok(None);
+            };
+
+            // Blanking the declaration keeps the lines of the copy those of the entry file
+            let mut copy = source.clone();
+            let blank = source[main.range.clone()].replace(|c| c != '\n', " ");
+            copy.replace_range(main.range.clone(), &blank);
+            let copy_path = scratch.0.join(index.to_string()).join(file_name);
+            fs::create_dir_all(scratch.0.join(index.to_string()))?;
+            fs::write(&copy_path, copy)?;
+
+            // Includes of the copy resolve from the directory of the entry file
+            link_libraries.push(input.parent().unwrap_or(Path::new(".")).to_path_buf());
+            root.push_str(&format!(
+                "include \"{}/{}\";\ntemplate {}{}() {{\n    component instance = {};\n}}\n",
+                index,
+                file_name.to_string_lossy(),
+                MAIN_WRAPPER,
+                index,
+                main.call
+            ));
+            entries.push((name.clone(), input, source, main.public));
+        }
+        root.push_str(&format!("component main = {}0();\n", MAIN_WRAPPER));
+
+        let root_path = scratch.0.join("package.circom");
+        fs::write(&root_path, root)?;
+        let Some(mut program_archive) = try_parse(&root_path, link_libraries) else {
+            return Ok(None);
+        };
+
+        // Dependencies are listed with the entry file in place of its copy
+        let storage = program_archive.file_library.to_storage();
+        let library = (0..)
+            .map_while(|file_id| storage.get(file_id).ok())
+            .filter(|file| !Path::new(file.name()).starts_with(&scratch.0))
+            .map(|file| (file.name().clone(), file.source().clone()))
+            .collect::<Vec<(String, String)>>();
+
+        let mut mains = BTreeMap::new();
+        for (index, (name, input, source, public)) in entries.into_iter().enumerate() {
+            let wrapper = format!("{}{}", MAIN_WRAPPER, index);
+            let Some(call) = program_archive
+                .templates
+                .remove(&wrapper)
+                .and_then(|template| main_call(template.get_body()).cloned())
+            else {
+                return Ok(None);
+            };
+            program_archive.template_keys.remove(&wrapper);
+
+            let entry_path = input.to_string_lossy().to_string();
+            let mut sources = vec![(entry_path.clone(), source)];
+            sources.extend(library.iter().cloned());
+            let deps = DependencyGraph::from_sources(&sources).reachable_from(&entry_path);
+
+            mains.insert(name, SharedMain { call, public, deps });
+        }
+
+        Ok(Some(SharedProgram {
+            program_archive,
+            mains,
+        }))
+    }
+
+    /// Returns the program of a circuit, along with the dependency graph of its files.
+    fn program(&self, name: &str) -> Option<(ProgramArchive, DependencyGraph)> {
+        let main = self.mains.get(name)?;
+        let mut program_archive = self.program_archive.clone();
+        program_archive.initial_template_call = main.call.clone();
+        program_archive.public_inputs = main.public.clone();
+
+        Some((program_archive, main.deps.clone()))
+    }
+}
+
+/// Returns the template call of `component instance = ...` in the body of a wrapper template.
+fn main_call(statement: &Statement) -> Option<&Expression> {
+    match statement {
+        Statement::Substitution { rhe, .. } => Some(rhe),
+        Statement::Block { stmts, .. } => stmts.iter().find_map(main_call),
+        Statement::InitializationBlock {
+            initializations, ..
+        } => initializations.iter().find_map(main_call),
+        _ => None,
+    }
+}
+
+/// A directory removed when dropped.
+struct ScratchDir(PathBuf);
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Moves a file to the given directory, keeping its name.
+fn in_dir(dir: &Path, file: &Option<PathBuf>) -> Option<PathBuf> {
+    file.as_ref()
+        .and_then(|file| file.file_name())
+        .map(|name| dir.join(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bristol_circuit::ConstantInfo;
+
+    fn info(constants: &[(&str, &str, usize)]) -> CircuitInfo {
+        CircuitInfo {
+            input_name_to_wire_index: HashMap::new(),
+            constants: constants
+                .iter()
+                .map(|&(name, value, wire_index)| {
+                    let value = value.to_string();
+                    (name.to_string(), ConstantInfo { value, wire_index })
+                })
+                .collect(),
+            output_name_to_wire_index: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_constants_pool() {
+        let mut pool = ConstantsPool::default();
+        pool.add_circuit("add", &info(&[("0.two", "2", 3), ("0.one", "1", 2)]));
+        pool.add_circuit("mul", &info(&[("0.c", "2", 1), ("0.d", "7", 2)]));
+
+        assert_eq!(pool.values, ["1", "2", "7"]);
+        assert_eq!(pool.circuits["add"]["0.one"], 0);
+        assert_eq!(pool.circuits["add"]["0.two"], 1);
+        assert_eq!(pool.circuits["mul"]["0.c"], 1);
+        assert_eq!(pool.circuits["mul"]["0.d"], 2);
+
+        // Pools read back keep deduplicating
+        let mut pool: ConstantsPool =
+            serde_json::from_str(&serde_json::to_string(&pool).unwrap()).unwrap();
+        pool.add_circuit("sub", &info(&[("0.e", "7", 1), ("0.f", "9", 2)]));
+        assert_eq!(pool.values, ["1", "2", "7", "9"]);
+        assert_eq!(pool.circuits["sub"]["0.e"], 2);
+    }
+
+    #[test]
+    fn test_package_manifest_from_path() {
+        let dir = std::env::temp_dir().join("circom_2_arithc_package_manifest");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("package.json");
+        fs::write(
+            &path,
+            r#"{ "circuits": { "add": "circuits/add.circom", "mul": "mul.circom" } }"#,
+        )
+        .unwrap();
+
+        let manifest = PackageManifest::from_path(&path).unwrap();
+        assert_eq!(manifest.circuits["add"], dir.join("circuits/add.circom"));
+        assert_eq!(manifest.circuits["mul"], dir.join("mul.circom"));
+    }
+
+    #[test]
+    fn test_package_manifest_from_dir() {
+        let dir = std::env::temp_dir().join("circom_2_arithc_package_dir");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let main = "pragma circom 2.1.0;\ninclude \"lib.circom\";\n\ncomponent main = Id();\n";
+        fs::write(dir.join("a.circom"), main).unwrap();
+        fs::write(dir.join("b.circom"), main).unwrap();
+        fs::write(
+            dir.join("lib.circom"),
+            "pragma circom 2.1.0;\n\n// component main is declared by each circuit\ntemplate Id() {}\n",
+        )
+        .unwrap();
+        fs::write(dir.join("notes.txt"), main).unwrap();
+
+        let manifest = PackageManifest::from_dir(&dir).unwrap();
+        assert_eq!(
+            manifest.circuits,
+            BTreeMap::from([
+                (String::from("a"), dir.join("a.circom")),
+                (String::from("b"), dir.join("b.circom")),
+            ])
+        );
+    }
+}
@@ -0,0 +1,673 @@
+//! # Passes Module
+//!
+//! This module runs the transformations applied to the gate graph once the program is processed
+//! as a pipeline of passes, in three stages: analysis, optimization and emission. The built-in
+//! passes (e.g. binding the weights or rebalancing additions) are registered in a `PassRegistry`,
+//! which library users can extend with their own passes by implementing `Pass`.
+
+use crate::{
+    a_gate_type::AGateType,
+    cli::{Args, ValueType},
+    compiler::Compiler,
+    events::EventSink,
+    field::FieldElement,
+    io_names::resolve_names,
+    logging::GRAPH,
+    noise::{add_noise, NoiseConfig},
+    program::{flatten_values, ProgramError},
+    rewrite::BalanceAdditions,
+    stages::{apply_stages, StagesConfig},
+};
+use log::info;
+use serde::Serialize;
+use std::{
+    fmt, fs,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+/// Stage of the pipeline a pass runs in. Stages run in order, and the passes of a stage in
+/// registration order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PassStage {
+    /// Completes and checks the graph, e.g. binds the weights.
+    Analysis,
+    /// Simplifies the graph.
+    Optimization,
+    /// Prepares the graph for the circuit, e.g. checks the gates supported by the target.
+    Emission,
+}
+
+impl fmt::Display for PassStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PassStage::Analysis => write!(f, "analysis"),
+            PassStage::Optimization => write!(f, "optimization"),
+            PassStage::Emission => write!(f, "emission"),
+        }
+    }
+}
+
+/// State shared by the passes of a compilation.
+pub struct PassContext<'a> {
+    pub args: &'a Args,
+    pub events: &'a mut EventSink,
+}
+
+/// A transformation of the gate graph, run after the program is processed and its inputs and
+/// outputs are registered.
+pub trait Pass {
+    /// Name of the pass, unique in a registry, e.g. `balance_additions`.
+    fn name(&self) -> &str;
+
+    /// Stage the pass runs in.
+    fn stage(&self) -> PassStage;
+
+    /// Runs the pass on the compiled graph.
+    fn run(&self, compiler: &mut Compiler, context: &mut PassContext) -> Result<(), ProgramError>;
+}
+
+/// Time taken by a pass, and its effect on the number of gates.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PassTiming {
+    pub name: String,
+    pub stage: PassStage,
+    pub elapsed: Duration,
+    pub gates_before: usize,
+    pub gates_after: usize,
+}
+
+/// Ordered passes of a compilation. `PassRegistry::default()` holds the built-in passes.
+#[derive(Clone)]
+pub struct PassRegistry {
+    passes: Vec<Rc<dyn Pass>>,
+}
+
+impl Default for PassRegistry {
+    fn default() -> Self {
+        let mut registry = Self::new();
+        registry.register(ConstantOutputs);
+        registry.register(BindWeights);
+        registry.register(ApplyStages);
+        registry.register(AddNoise);
+        registry.register(FoldConstants);
+        registry.register(ReduceDivisions);
+        registry.register(BalanceAdditionsPass);
+        registry.register(FuseConstantMul);
+        registry.register(PruneGates);
+        registry.register(PruneInputs);
+        registry.register(LowerGates);
+        registry.register(CheckTarget);
+        registry
+    }
+}
+
+impl PassRegistry {
+    /// Creates a registry without any pass, not even the built-in ones.
+    pub fn new() -> Self {
+        Self { passes: Vec::new() }
+    }
+
+    /// Adds a pass after the passes already registered.
+    pub fn register(&mut self, pass: impl Pass + 'static) {
+        self.passes.push(Rc::new(pass));
+    }
+
+    /// Adds a pass right before the named one. Passes still run in the order of their stages.
+    pub fn register_before(
+        &mut self,
+        name: &str,
+        pass: impl Pass + 'static,
+    ) -> Result<(), ProgramError> {
+        let index = self.position(name)?;
+        self.passes.insert(index, Rc::new(pass));
+        Ok(())
+    }
+
+    /// Adds a pass right after the named one. Passes still run in the order of their stages.
+    pub fn register_after(
+        &mut self,
+        name: &str,
+        pass: impl Pass + 'static,
+    ) -> Result<(), ProgramError> {
+        let index = self.position(name)?;
+        self.passes.insert(index + 1, Rc::new(pass));
+        Ok(())
+    }
+
+    /// Removes the named pass.
+    pub fn remove(&mut self, name: &str) -> Result<(), ProgramError> {
+        let index = self.position(name)?;
+        self.passes.remove(index);
+        Ok(())
+    }
+
+    /// Returns the names of the passes, in the order they run.
+    pub fn names(&self) -> Vec<&str> {
+        self.ordered().map(|pass| pass.name()).collect()
+    }
+
+    /// Runs the passes, stage by stage, returning the time taken by each one.
+    pub fn run(
+        &self,
+        compiler: &mut Compiler,
+        context: &mut PassContext,
+    ) -> Result<Vec<PassTiming>, ProgramError> {
+        let mut timings = Vec::with_capacity(self.passes.len());
+        for pass in self.ordered() {
+            let gates_before = compiler.gates().len();
+            let start = Instant::now();
+
+            pass.run(compiler, context)?;
+
+            let timing = PassTiming {
+                name: pass.name().to_string(),
+                stage: pass.stage(),
+                elapsed: start.elapsed(),
+                gates_before,
+                gates_after: compiler.gates().len(),
+            };
+            context.events.pass_finished(
+                &timing.name,
+                timing.elapsed,
+                timing.gates_after as u64,
+            )?;
+            info!(
+                target: GRAPH,
+                "Ran {} pass {} in {:?}: {} -> {} gates",
+                timing.stage,
+                timing.name,
+                timing.elapsed,
+                timing.gates_before,
+                timing.gates_after
+            );
+            timings.push(timing);
+        }
+
+        Ok(timings)
+    }
+
+    /// Returns the passes in the order they run.
+    fn ordered(&self) -> impl Iterator<Item = &Rc<dyn Pass>> {
+        let mut passes = self.passes.iter().collect::<Vec<_>>();
+        passes.sort_by_key(|pass| pass.stage());
+        passes.into_iter()
+    }
+
+    fn position(&self, name: &str) -> Result<usize, ProgramError> {
+        self.passes
+            .iter()
+            .position(|pass| pass.name() == name)
+            .ok_or_else(|| ProgramError::UnknownPass(name.to_string()))
+    }
+}
+
+/// Warns about the outputs that don't depend on any input.
+struct ConstantOutputs;
+
+impl Pass for ConstantOutputs {
+    fn name(&self) -> &str {
+        "constant_outputs"
+    }
+
+    fn stage(&self) -> PassStage {
+        PassStage::Analysis
+    }
+
+    fn run(&self, compiler: &mut Compiler, context: &mut PassContext) -> Result<(), ProgramError> {
+        for (name, value) in compiler.constant_outputs() {
+            let message = match value {
+                Some(value) => format!(
+                    "Output {} doesn't depend on any input, it always evaluates to {}",
+                    name, value
+                ),
+                None => format!(
+                    "Output {} doesn't depend on any input, and its value is undefined",
+                    name
+                ),
+            };
+            context.events.warn(GRAPH, &message)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Binds the inputs listed in `Args::weights` to constants.
+struct BindWeights;
+
+impl Pass for BindWeights {
+    fn name(&self) -> &str {
+        "bind_weights"
+    }
+
+    fn stage(&self) -> PassStage {
+        PassStage::Analysis
+    }
+
+    fn run(&self, compiler: &mut Compiler, context: &mut PassContext) -> Result<(), ProgramError> {
+        if let Some(weights) = &context.args.weights {
+            let weights = serde_json::from_str(&fs::read_to_string(weights)?)?;
+            let weights = resolve_names(
+                flatten_values(&weights)?,
+                compiler.input_names(),
+                &context.args.normalize_names,
+            )?;
+            for (name, input) in &weights.normalized {
+                context.events.warn(
+                    GRAPH,
+                    &format!(
+                        "Weight {} bound to input {} after normalizing its name",
+                        name, input
+                    ),
+                )?;
+            }
+            let (value_type, prime) = (compiler.value_type(), compiler.prime());
+            let values = weights
+                .values
+                .into_iter()
+                .map(|(name, value)| (name, FieldElement::from_word(value, value_type, prime)))
+                .collect();
+            compiler.bind_constants(&values)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Attaches the input and output stages of `Args::stages`.
+struct ApplyStages;
+
+impl Pass for ApplyStages {
+    fn name(&self) -> &str {
+        "apply_stages"
+    }
+
+    fn stage(&self) -> PassStage {
+        PassStage::Analysis
+    }
+
+    fn run(&self, compiler: &mut Compiler, context: &mut PassContext) -> Result<(), ProgramError> {
+        if let Some(stages) = &context.args.stages {
+            apply_stages(compiler, &StagesConfig::from_path(stages)?)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Adds the noise inputs of `Args::noise` to the outputs.
+struct AddNoise;
+
+impl Pass for AddNoise {
+    fn name(&self) -> &str {
+        "add_noise"
+    }
+
+    fn stage(&self) -> PassStage {
+        PassStage::Analysis
+    }
+
+    fn run(&self, compiler: &mut Compiler, context: &mut PassContext) -> Result<(), ProgramError> {
+        if let Some(noise) = &context.args.noise {
+            add_noise(compiler, &NoiseConfig::from_path(noise)?)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Evaluates the gates that only depend on constants, if enabled by the arguments.
+struct FoldConstants;
+
+impl Pass for FoldConstants {
+    fn name(&self) -> &str {
+        "fold_constants"
+    }
+
+    fn stage(&self) -> PassStage {
+        PassStage::Optimization
+    }
+
+    fn run(&self, compiler: &mut Compiler, context: &mut PassContext) -> Result<(), ProgramError> {
+        if !context.args.fold_constants {
+            return Ok(());
+        }
+
+        let folded = compiler.fold_constants();
+        info!(target: GRAPH, "Folded {} gates only reading constants", folded);
+
+        Ok(())
+    }
+}
+
+/// Reduces the divisions by a constant to shifts or multiplications, if enabled by the arguments.
+struct ReduceDivisions;
+
+impl Pass for ReduceDivisions {
+    fn name(&self) -> &str {
+        "reduce_divisions"
+    }
+
+    fn stage(&self) -> PassStage {
+        PassStage::Optimization
+    }
+
+    fn run(&self, compiler: &mut Compiler, context: &mut PassContext) -> Result<(), ProgramError> {
+        let Some(mode) = context.args.reduce_divisions else {
+            return Ok(());
+        };
+
+        let reduced = compiler.reduce_divisions(mode);
+        info!(target: GRAPH, "Reduced {} divisions by a constant", reduced);
+
+        Ok(())
+    }
+}
+
+/// Rebalances chains of additions into trees, if enabled by the arguments or the target.
+struct BalanceAdditionsPass;
+
+impl Pass for BalanceAdditionsPass {
+    fn name(&self) -> &str {
+        "balance_additions"
+    }
+
+    fn stage(&self) -> PassStage {
+        PassStage::Optimization
+    }
+
+    fn run(&self, compiler: &mut Compiler, context: &mut PassContext) -> Result<(), ProgramError> {
+        let args = context.args;
+        if !args.balance_additions && !args.target.balances_additions() {
+            return Ok(());
+        }
+
+        if args.value_type == ValueType::Sfloat {
+            context.events.warn(
+                GRAPH,
+                "Additions of Sfloat values aren't associative, they are not rebalanced",
+            )?;
+        } else {
+            // A single pass rebalances every chain, the second one checks that nothing is left
+            let stats = compiler.rewrite(&[&BalanceAdditions], 2);
+            let chains = stats
+                .rewrites
+                .get("balance_additions")
+                .copied()
+                .unwrap_or(0);
+            info!(target: GRAPH, "Rebalanced {} chains of additions", chains);
+        }
+
+        Ok(())
+    }
+}
+
+/// Fuses the multiplications by a constant into `ACMul` gates, if enabled by the arguments.
+/// Boolean circuits keep `AMul` gates, which boolify handles.
+struct FuseConstantMul;
+
+impl Pass for FuseConstantMul {
+    fn name(&self) -> &str {
+        "fuse_constant_mul"
+    }
+
+    fn stage(&self) -> PassStage {
+        PassStage::Optimization
+    }
+
+    fn run(&self, compiler: &mut Compiler, context: &mut PassContext) -> Result<(), ProgramError> {
+        let args = context.args;
+        if !args.fuse_constant_mul || args.boolify_width.is_some() {
+            return Ok(());
+        }
+
+        let fused = compiler.fuse_constant_multiplications();
+        info!(
+            target: GRAPH,
+            "Fused {} multiplications by a constant into ACMul gates", fused
+        );
+
+        Ok(())
+    }
+}
+
+/// Removes the gates that don't reach any output, unless disabled by the arguments. Partial
+/// circuits of `Args::stop_after` are kept whole.
+struct PruneGates;
+
+impl Pass for PruneGates {
+    fn name(&self) -> &str {
+        "prune_gates"
+    }
+
+    fn stage(&self) -> PassStage {
+        PassStage::Optimization
+    }
+
+    fn run(&self, compiler: &mut Compiler, context: &mut PassContext) -> Result<(), ProgramError> {
+        if context.args.no_prune || context.args.stop_after.is_some() {
+            return Ok(());
+        }
+
+        let pruned = compiler.prune_dead_gates();
+        info!(target: GRAPH, "Pruned {} gates not reaching any output", pruned);
+
+        Ok(())
+    }
+}
+
+/// Removes the inputs not read by any gate, or fails in strict mode.
+struct PruneInputs;
+
+impl Pass for PruneInputs {
+    fn name(&self) -> &str {
+        "prune_inputs"
+    }
+
+    fn stage(&self) -> PassStage {
+        PassStage::Optimization
+    }
+
+    fn run(&self, compiler: &mut Compiler, context: &mut PassContext) -> Result<(), ProgramError> {
+        let unused_inputs = compiler.unused_inputs();
+        if unused_inputs.is_empty() {
+            return Ok(());
+        }
+        if context.args.strict {
+            return Err(ProgramError::UnusedInputs(unused_inputs));
+        }
+
+        context.events.warn(
+            GRAPH,
+            &format!(
+                "Pruning inputs not read by any gate: {}",
+                unused_inputs.join(", ")
+            ),
+        )?;
+        compiler.prune_inputs(&unused_inputs);
+
+        Ok(())
+    }
+}
+
+/// Lowers the gates boolify doesn't support into gates it does for boolean circuits, or the gates
+/// the target doesn't support if enabled by the target.
+struct LowerGates;
+
+impl Pass for LowerGates {
+    fn name(&self) -> &str {
+        "lower_gates"
+    }
+
+    fn stage(&self) -> PassStage {
+        PassStage::Emission
+    }
+
+    fn run(&self, compiler: &mut Compiler, context: &mut PassContext) -> Result<(), ProgramError> {
+        let args = context.args;
+        if let Some(width) = args.boolify_width {
+            let lowered = compiler.lower_for_boolify(width);
+            info!(
+                target: GRAPH,
+                "Lowered {} gates unsupported by boolify", lowered
+            );
+        } else if args.target.lowers_gates() {
+            let lowered = compiler.lower_for_target();
+            info!(
+                target: GRAPH,
+                "Lowered {} gates unsupported by the target", lowered
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks that the target supports every gate of the graph.
+struct CheckTarget;
+
+impl Pass for CheckTarget {
+    fn name(&self) -> &str {
+        "check_target"
+    }
+
+    fn stage(&self) -> PassStage {
+        PassStage::Emission
+    }
+
+    fn run(&self, compiler: &mut Compiler, context: &mut PassContext) -> Result<(), ProgramError> {
+        let target = context.args.target;
+        let Some(supported) = target.supported_gates() else {
+            return Ok(());
+        };
+
+        let mut unsupported = Vec::new();
+        for gate in compiler.gates() {
+            // Multiplications by a constant are lowered to `AMul` for targets without `ACMul`
+            let op = match gate.op {
+                AGateType::ACMul => AGateType::AMul,
+                op => op,
+            };
+            if !supported.contains(&op) && !unsupported.contains(&op) {
+                unsupported.push(op);
+            }
+        }
+        if !unsupported.is_empty() {
+            return Err(ProgramError::UnsupportedByTarget {
+                target,
+                ops: unsupported.iter().map(AGateType::to_string).collect(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::RefCell, path::PathBuf};
+
+    /// Records the passes it runs in.
+    struct Probe {
+        name: &'static str,
+        stage: PassStage,
+        log: Rc<RefCell<Vec<&'static str>>>,
+    }
+
+    impl Pass for Probe {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn stage(&self) -> PassStage {
+            self.stage
+        }
+
+        fn run(&self, compiler: &mut Compiler, _: &mut PassContext) -> Result<(), ProgramError> {
+            self.log.borrow_mut().push(self.name);
+            compiler.add_gate(AGateType::AAdd, 1, 2, 3)?;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_pass_registry_order() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let probe = |name, stage| Probe {
+            name,
+            stage,
+            log: log.clone(),
+        };
+
+        let mut registry = PassRegistry::new();
+        registry.register(probe("emit", PassStage::Emission));
+        registry.register(probe("analyse", PassStage::Analysis));
+        registry.register(probe("optimize", PassStage::Optimization));
+        registry
+            .register_before("optimize", probe("lower", PassStage::Optimization))
+            .unwrap();
+        registry
+            .register_after("analyse", probe("annotate", PassStage::Analysis))
+            .unwrap();
+        assert_eq!(
+            registry.names(),
+            vec!["analyse", "annotate", "lower", "optimize", "emit"]
+        );
+
+        registry.remove("lower").unwrap();
+        assert!(matches!(
+            registry.remove("lower"),
+            Err(ProgramError::UnknownPass(name)) if name == "lower"
+        ));
+
+        let args = Args::new(PathBuf::new(), PathBuf::new(), ValueType::default(), None);
+        let mut events = EventSink::default();
+        let mut compiler = Compiler::new();
+        for id in 1..=3 {
+            compiler
+                .add_signal(id, format!("signal{}", id), None)
+                .unwrap();
+        }
+        let timings = registry
+            .run(
+                &mut compiler,
+                &mut PassContext {
+                    args: &args,
+                    events: &mut events,
+                },
+            )
+            .unwrap();
+
+        assert_eq!(
+            *log.borrow(),
+            vec!["analyse", "annotate", "optimize", "emit"]
+        );
+        assert_eq!(timings[1].name, "annotate");
+        assert_eq!(timings[1].stage, PassStage::Analysis);
+        assert_eq!((timings[1].gates_before, timings[1].gates_after), (1, 2));
+    }
+
+    #[test]
+    fn test_pass_registry_default() {
+        assert_eq!(
+            PassRegistry::default().names(),
+            vec![
+                "constant_outputs",
+                "bind_weights",
+                "apply_stages",
+                "add_noise",
+                "fold_constants",
+                "reduce_divisions",
+                "balance_additions",
+                "fuse_constant_mul",
+                "prune_gates",
+                "prune_inputs",
+                "lower_gates",
+                "check_target"
+            ]
+        );
+    }
+}
@@ -0,0 +1,421 @@
+//! # Prebuild Module
+//!
+//! This module compiles template instances, e.g. the layers of a circomlib-ml model, into IR
+//! archives: the processed gate graph of each instance along with the hashes of the files it was
+//! compiled from. Model families sharing layers prebuild them once, and an archive is only
+//! compiled again when its template, parameters, compile options or sources change. Compiling
+//! with `Args::prebuilt` links the up to date archives in place of the instances they hold.
+
+use crate::{
+    artifacts::{to_canonical_json, ARTIFACTS_VERSION},
+    builder::SourceTree,
+    circom::parser::parse_project,
+    cli::{
+        build_output, Args, ConstraintEquality, DivisionReduction, InstanceNaming, OutputFormat,
+        Prime, Target, ValueType,
+    },
+    compiler::Compiler,
+    deps::{DependencyFile, DependencyGraph},
+    events::EventSink,
+    field::FieldElement,
+    logging::EMIT,
+    naming::fnv1a,
+    passes::PassRegistry,
+    program::{compile_archive, runtime_for, ProgramError},
+};
+use circom_circom_algebra::num_bigint::BigInt;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// Template instances to prebuild, `{ "templates": [{ "name": "dense_4_2", "include":
+/// "Dense.circom", "template": "Dense", "args": [4, 2, 1] }] }`. Included files are relative to
+/// the manifest.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrebuildManifest {
+    pub templates: Vec<TemplateInstance>,
+}
+
+impl PrebuildManifest {
+    /// Reads a manifest, resolving the included files relative to its directory.
+    pub fn from_path(path: &Path) -> Result<Self, ProgramError> {
+        let mut manifest: PrebuildManifest = serde_json::from_str(&fs::read_to_string(path)?)?;
+        let base = path.parent().unwrap_or(Path::new(""));
+        for instance in &mut manifest.templates {
+            instance.include = base.join(&instance.include);
+        }
+
+        Ok(manifest)
+    }
+}
+
+/// A template with its parameters, compiled as the main component of its archive.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TemplateInstance {
+    /// Name of the archive, written to `<name>.ir.json`.
+    pub name: String,
+    /// File declaring the template.
+    pub include: PathBuf,
+    pub template: String,
+    /// Parameters of the template, numbers or arrays of numbers.
+    #[serde(default)]
+    pub args: Vec<Value>,
+}
+
+impl TemplateInstance {
+    /// Returns the source of a program instantiating the template as its main component.
+    pub fn main_source(&self) -> String {
+        let args = self
+            .args
+            .iter()
+            .map(Value::to_string)
+            .collect::<Vec<String>>()
+            .join(", ");
+        format!(
+            "pragma circom 2.1.0;\n\ninclude {:?};\n\ncomponent main = {}({});\n",
+            self.include.display().to_string(),
+            self.template,
+            args
+        )
+    }
+}
+
+/// Options of a compilation that change the processed gate graph, hashed into `options_key`.
+#[derive(Serialize)]
+struct CompileOptions<'a> {
+    value_type: ValueType,
+    prime: Prime,
+    target: Target,
+    signed_gates: bool,
+    format: OutputFormat,
+    link_libraries: &'a [PathBuf],
+    balance_additions: bool,
+    fold_constants: bool,
+    fuse_constant_mul: bool,
+    reduce_divisions: Option<DivisionReduction>,
+    no_prune: bool,
+    boolify_width: Option<usize>,
+    constraint_equality: ConstraintEquality,
+    instance_naming: InstanceNaming,
+    stop_after: Option<&'a str>,
+    /// Hashes of the contents of the weights, stages and noise files.
+    weights: Option<String>,
+    stages: Option<String>,
+    noise: Option<String>,
+}
+
+/// Returns the key of the options of `args` that change the processed gate graph, e.g. the
+/// target, the signed gates or the constant folding. Archives are only up to date for the key
+/// they were compiled with.
+pub fn options_key(args: &Args) -> Result<String, ProgramError> {
+    let file_hash = |path: &Option<PathBuf>| -> Result<Option<String>, ProgramError> {
+        Ok(match path {
+            Some(path) => Some(format!("{:016x}", fnv1a(&fs::read(path)?))),
+            None => None,
+        })
+    };
+    let options = CompileOptions {
+        value_type: args.value_type,
+        prime: args.prime,
+        target: args.target,
+        signed_gates: args.signed_gates,
+        format: args.format,
+        link_libraries: &args.link_libraries,
+        balance_additions: args.balance_additions,
+        fold_constants: args.fold_constants,
+        fuse_constant_mul: args.fuse_constant_mul,
+        reduce_divisions: args.reduce_divisions,
+        no_prune: args.no_prune,
+        boolify_width: args.boolify_width,
+        constraint_equality: args.constraint_equality,
+        instance_naming: args.instance_naming,
+        stop_after: args.stop_after.as_deref(),
+        weights: file_hash(&args.weights)?,
+        stages: file_hash(&args.stages)?,
+        noise: file_hash(&args.noise)?,
+    };
+
+    Ok(format!(
+        "{:016x}",
+        fnv1a(to_canonical_json(&options)?.as_bytes())
+    ))
+}
+
+/// A prebuilt template instance, `<name>.ir.json`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IrArchive {
+    /// Layout version of the archive, see `ARTIFACTS_VERSION`.
+    pub version: u32,
+    pub template: String,
+    pub args: Vec<Value>,
+    /// Key of the compile options the instance was compiled with, see `options_key`. Empty for
+    /// archives written before it was recorded, which are never up to date.
+    #[serde(default)]
+    pub options: String,
+    /// Files the instance was compiled from, the generated main component left out.
+    pub sources: Vec<DependencyFile>,
+    /// Processed gate graph of the instance, after the passes.
+    pub compiler: Compiler,
+}
+
+impl IrArchive {
+    /// Reads an archive, rejecting archives of a newer layout than `ARTIFACTS_VERSION` and values
+    /// outside the field of its compiler.
+    pub fn from_path(path: &Path) -> Result<Self, ProgramError> {
+        let archive: IrArchive = serde_json::from_str(&fs::read_to_string(path)?)?;
+        if archive.version > ARTIFACTS_VERSION {
+            return Err(ProgramError::UnsupportedArtifacts {
+                version: archive.version,
+            });
+        }
+        archive.compiler.check_field_elements()?;
+
+        Ok(archive)
+    }
+
+    /// Returns the sources of the archive that changed or no longer exist since it was built.
+    pub fn stale_sources(&self) -> Vec<&str> {
+        self.sources
+            .iter()
+            .filter(|file| {
+                fs::read(&file.path).map_or(true, |source| {
+                    format!("{:016x}", fnv1a(&source)) != file.hash
+                })
+            })
+            .map(|file| file.path.as_str())
+            .collect()
+    }
+
+    /// Returns whether the archive was compiled with the options of the given key, see
+    /// `options_key`, in the current layout and from the current sources.
+    pub fn is_up_to_date(&self, options: &str) -> bool {
+        self.version == ARTIFACTS_VERSION
+            && self.options == options
+            && self.stale_sources().is_empty()
+    }
+
+    /// Returns whether the archive is the up to date instance of the manifest entry.
+    fn is_current(&self, instance: &TemplateInstance, options: &str) -> bool {
+        self.template == instance.template
+            && self.args == instance.args
+            && self.is_up_to_date(options)
+    }
+}
+
+/// The up to date archives of a directory, linked in place of the template instances they hold,
+/// see `Args::prebuilt`.
+#[derive(Debug, Default)]
+pub struct PrebuiltInstances {
+    archives: HashMap<(String, Vec<FieldElement>), Compiler>,
+}
+
+impl PrebuiltInstances {
+    /// Loads the archives `<dir>/*.ir.json` compiled with the options of `args`. Archives that
+    /// can't be read or aren't up to date are skipped, so the instances they hold are processed.
+    pub fn load(dir: &Path, args: &Args) -> Result<Self, ProgramError> {
+        let options = options_key(args)?;
+        let mut paths = fs::read_dir(dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<PathBuf>, _>>()?;
+        paths.retain(|path| path.to_string_lossy().ends_with(".ir.json"));
+        paths.sort();
+
+        let mut archives = HashMap::new();
+        for path in paths {
+            let archive = match IrArchive::from_path(&path) {
+                Ok(archive) if archive.is_up_to_date(&options) => archive,
+                Ok(_) => {
+                    debug!(target: EMIT, "Skipped {}, out of date", path.display());
+                    continue;
+                }
+                Err(error) => {
+                    debug!(target: EMIT, "Skipped {}: {}", path.display(), error);
+                    continue;
+                }
+            };
+            // Only number parameters are known when a template is instantiated
+            let Some(template_args) = archive
+                .args
+                .iter()
+                .map(|value| {
+                    let value = value.as_number()?.to_string().parse::<BigInt>().ok()?;
+                    Some(FieldElement::from_bigint(&value, args.prime))
+                })
+                .collect::<Option<Vec<FieldElement>>>()
+            else {
+                debug!(target: EMIT, "Skipped {}, array parameters", path.display());
+                continue;
+            };
+            archives.insert((archive.template, template_args), archive.compiler);
+        }
+
+        Ok(Self { archives })
+    }
+
+    /// Returns the processed gate graph of the instance of `template` with the given parameters.
+    pub fn get(&self, template: &str, args: &[FieldElement]) -> Option<&Compiler> {
+        self.archives.get(&(template.to_string(), args.to_vec()))
+    }
+
+    /// Returns the number of loaded archives.
+    pub fn len(&self) -> usize {
+        self.archives.len()
+    }
+
+    /// Returns whether no archive was loaded.
+    pub fn is_empty(&self) -> bool {
+        self.archives.is_empty()
+    }
+}
+
+/// An archive written or kept by `prebuild`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PrebuiltArchive {
+    pub path: PathBuf,
+    pub gate_count: usize,
+    /// Whether the archive was already up to date, and not compiled again.
+    pub reused: bool,
+}
+
+/// Compiles each template instance of a manifest with the given arguments into an archive
+/// `<output>/<name>.ir.json`. Archives already up to date are kept as they are, the others are
+/// compiled again, including archives that can't be read, e.g. of another layout.
+pub fn prebuild(
+    args: &Args,
+    manifest: &PrebuildManifest,
+) -> Result<BTreeMap<String, PrebuiltArchive>, ProgramError> {
+    fs::create_dir_all(&args.output).map_err(|_| ProgramError::OutputDirectoryCreationError)?;
+    let options = options_key(args)?;
+
+    let mut archives = BTreeMap::new();
+    for instance in &manifest.templates {
+        let path = build_output(&args.output, &instance.name, "ir.json");
+        if path.exists() {
+            match IrArchive::from_path(&path) {
+                Ok(archive) if archive.is_current(instance, &options) => {
+                    debug!(target: EMIT, "Kept {}, up to date", path.display());
+                    let gate_count = archive.compiler.gates().len();
+                    archives.insert(
+                        instance.name.clone(),
+                        PrebuiltArchive {
+                            path,
+                            gate_count,
+                            reused: true,
+                        },
+                    );
+                    continue;
+                }
+                Ok(_) => {}
+                Err(error) => debug!(target: EMIT, "Rebuilding {}: {}", path.display(), error),
+            }
+        }
+
+        // Instances are compiled whole, not linked from other archives
+        let tree = SourceTree::write(&instance.main_source(), None)?;
+        let instance_args = Args {
+            input: tree.main(),
+            prebuilt: None,
+            ..args.clone()
+        };
+        let program_archive = parse_project(&instance_args)?;
+        let main = tree.main().display().to_string();
+        let sources = DependencyGraph::from_file_library(&program_archive.file_library)
+            .files
+            .into_iter()
+            .filter(|file| file.path != main)
+            .collect();
+        let compiler = compile_archive(
+            &instance_args,
+            runtime_for(&instance_args)?,
+            &PassRegistry::default(),
+            program_archive,
+            &mut EventSink::create(None)?,
+        )?;
+
+        let archive = IrArchive {
+            version: ARTIFACTS_VERSION,
+            template: instance.template.clone(),
+            args: instance.args.clone(),
+            options: options.clone(),
+            sources,
+            compiler,
+        };
+        File::create(&path)?.write_all(to_canonical_json(&archive)?.as_bytes())?;
+        debug!(target: EMIT, "Wrote {}", path.display());
+        archives.insert(
+            instance.name.clone(),
+            PrebuiltArchive {
+                path,
+                gate_count: archive.compiler.gates().len(),
+                reused: false,
+            },
+        );
+    }
+
+    Ok(archives)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_main_source() {
+        let manifest: PrebuildManifest = serde_json::from_str(
+            r#"{
+                "templates": [
+                    { "name": "mul", "include": "lib/matMul.circom", "template": "matMul", "args": [1, 2, 1] },
+                    { "name": "scale", "include": "scale.circom", "template": "Scale", "args": [[1, 2]] },
+                    { "name": "relu", "include": "ReLU.circom", "template": "ReLU" }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            manifest.templates[0].main_source(),
+            "pragma circom 2.1.0;\n\ninclude \"lib/matMul.circom\";\n\ncomponent main = matMul(1, 2, 1);\n"
+        );
+        assert!(manifest.templates[1]
+            .main_source()
+            .ends_with("component main = Scale([1,2]);\n"));
+        assert!(manifest.templates[2]
+            .main_source()
+            .ends_with("component main = ReLU();\n"));
+    }
+
+    #[test]
+    fn test_options_key() {
+        let args = Args::new("main.circom".into(), "./".into(), ValueType::Sint, None);
+        let key = options_key(&args).unwrap();
+
+        // Output paths don't change the graph
+        let other_output = Args {
+            output: "./other".into(),
+            ..args.clone()
+        };
+        assert_eq!(options_key(&other_output).unwrap(), key);
+
+        let folded = Args {
+            fold_constants: true,
+            ..args.clone()
+        };
+        let lowered = Args {
+            target: Target::Mpz,
+            ..args.clone()
+        };
+        let signed = Args {
+            signed_gates: true,
+            ..args.clone()
+        };
+        for changed in [folded, lowered, signed] {
+            assert_ne!(options_key(&changed).unwrap(), key);
+        }
+    }
+}
@@ -3,20 +3,72 @@
 //! Handles execution of statements and expressions for arithmetic circuit generation within a `Runtime` environment.
 
 use crate::a_gate_type::AGateType;
-use crate::compiler::Compiler;
-use crate::program::ProgramError;
+use crate::cli::{ConstraintEquality, Prime, ValueType};
+use crate::compiler::{Compiler, InstanceReport, TemplateParam};
+use crate::field::FieldElement;
+use crate::log_trace::LogEntry;
+use crate::logging::PROCESS;
+use crate::program::{ProgramError, SourceLocation};
 use crate::runtime::{
-    generate_u32, increment_indices, u32_to_access, Context, DataAccess, DataType, NestedValue,
-    Runtime, RuntimeError, Signal, SubAccess, RETURN_VAR,
+    u32_to_access, Context, DataAccess, DataType, Indices, NestedValue, Runtime, RuntimeError,
+    Signal, SignalGen, StepStats, SubAccess, RETURN_VAR,
 };
-use circom_circom_algebra::num_traits::ToPrimitive;
 use circom_program_structure::ast::{
-    Access, AssignOp, Expression, ExpressionInfixOpcode, ExpressionPrefixOpcode, Statement,
+    Access, AssignOp, Expression, ExpressionInfixOpcode, ExpressionPrefixOpcode, LogArgument,
+    Statement, VariableType,
 };
 use circom_program_structure::program_archive::ProgramArchive;
-use std::cell::RefCell;
-use std::collections::HashMap;
-use std::rc::Rc;
+use log::info;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::panic;
+use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+/// Number of signal ids reserved for each component processed by a worker, from which the signals
+/// of the component and of its subcomponents are allocated.
+pub const INSTANCE_SIGNAL_RANGE: u32 = 1 << 20;
+
+/// Workers processing the components instantiated by the main component, each on its own thread.
+/// A component is built in its own circuit, allocating signal ids from a range reserved when it is
+/// instantiated, and the circuits are merged in the order the components were instantiated, so
+/// the result doesn't depend on the order the workers finish in.
+pub struct InstanceWorkers {
+    program_archive: Arc<ProgramArchive>,
+    workers: usize,
+    pending: VecDeque<PendingInstance>,
+}
+
+impl InstanceWorkers {
+    /// Creates workers processing up to `workers` components of the program at once.
+    pub fn new(program_archive: Arc<ProgramArchive>, workers: usize) -> Self {
+        Self {
+            program_archive,
+            workers: workers.max(1),
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+/// A component being processed by a worker, with its input and output signals, which are also
+/// declared in the circuit wiring it.
+struct PendingInstance {
+    worker: JoinHandle<Result<ProcessedInstance, ProgramError>>,
+    io_signals: Vec<u32>,
+}
+
+/// Circuit of a component built by a worker, and the steps it took.
+struct ProcessedInstance {
+    compiler: Compiler,
+    steps: StepStats,
+}
+
+/// Input and output signals of a component, sent by its worker as soon as they are declared.
+struct InstanceIo {
+    signals: HashMap<String, Signal>,
+    names: BTreeMap<u32, String>,
+}
 
 /// Processes a sequence of statements.
 pub fn process_statements(
@@ -26,7 +78,20 @@ pub fn process_statements(
     statements: &[Statement],
 ) -> Result<(), ProgramError> {
     for statement in statements {
-        process_statement(ac, runtime, program_archive, statement)?;
+        if !runtime.is_recovering() {
+            process_statement(ac, runtime, program_archive, statement)?;
+            continue;
+        }
+
+        // Skip the failing statement, dropping the contexts of the calls it left unfinished
+        let depth = runtime.context_depth();
+        match process_statement(ac, runtime, program_archive, statement) {
+            Err(error) if error.is_recoverable() => {
+                runtime.restore_context_depth(depth);
+                runtime.record_error(&error);
+            }
+            result => result?,
+        }
     }
 
     Ok(())
@@ -38,6 +103,82 @@ pub fn process_statement(
     runtime: &mut Runtime,
     program_archive: &ProgramArchive,
     statement: &Statement,
+) -> Result<(), ProgramError> {
+    runtime.count_statement()?;
+    let depth = runtime.context_depth();
+
+    // Signals, gates and connections are attributed to the innermost statement being processed
+    let line = statement_line(program_archive, statement);
+    let source = line
+        .as_ref()
+        .map(|(file, line)| Arc::from(format!("{}:{}", file, line)));
+    let outer_source = ac.replace_source(source);
+    let result = execute_statement(ac, runtime, program_archive, statement);
+    ac.replace_source(outer_source);
+
+    // Blocks only group statements, the statement that failed in them is more precise
+    if matches!(
+        statement,
+        Statement::Block { .. } | Statement::InitializationBlock { .. }
+    ) {
+        return result;
+    }
+
+    result.map_err(|error| match line {
+        Some((file, line)) => {
+            let (column, text) = statement_column(program_archive, statement).unwrap_or_default();
+            error.in_statement(SourceLocation {
+                file,
+                line,
+                column,
+                text,
+                instance: runtime.context_name_at(depth),
+            })
+        }
+        None => error,
+    })
+}
+
+/// Returns the file and line of a statement.
+fn statement_line(
+    program_archive: &ProgramArchive,
+    statement: &Statement,
+) -> Option<(String, usize)> {
+    let meta = statement.get_meta();
+    let file_id = meta.file_id?;
+    let file_library = program_archive.get_file_library();
+    let file = file_library.to_storage().get(file_id).ok()?;
+    let line = file_library.get_line(meta.start, file_id)?;
+    Some((file.name().to_string(), line))
+}
+
+/// Returns the column of a statement, from 1, and the source line it starts on. Only looked up
+/// for statements that failed.
+fn statement_column(
+    program_archive: &ProgramArchive,
+    statement: &Statement,
+) -> Option<(usize, String)> {
+    let meta = statement.get_meta();
+    let file_library = program_archive.get_file_library();
+    let source = file_library.to_storage().get(meta.file_id?).ok()?.source();
+    let start = meta.start.min(source.len());
+    let line_start = source[..start].rfind('\n').map_or(0, |index| index + 1);
+    let line_end = source[start..]
+        .find('\n')
+        .map_or(source.len(), |index| start + index);
+    let column = source[line_start..start].chars().count() + 1;
+    let text = source[line_start..line_end]
+        .trim_end_matches('\r')
+        .to_string();
+    Some((column, text))
+}
+
+/// Executes a single statement.
+fn execute_statement(
+    ac: &mut Compiler,
+    runtime: &mut Runtime,
+    program_archive: &ProgramArchive,
+    statement: &Statement,
 ) -> Result<(), ProgramError> {
     match statement {
         Statement::InitializationBlock {
@@ -51,6 +192,21 @@ pub fn process_statement(
             op,
             ..
         } => handle_substitution(ac, runtime, program_archive, var, access, rhe, op),
+        Statement::MultSubstitution { lhe, op, rhe, .. } => {
+            handle_mult_substitution(ac, runtime, program_archive, lhe, rhe, op)
+        }
+        Statement::UnderscoreSubstitution { rhe, .. } => {
+            // The value is discarded, but the gates and components it creates are kept
+            match rhe {
+                Expression::AnonymousComp { .. } => {
+                    process_tuple(ac, runtime, program_archive, rhe)?;
+                }
+                _ => {
+                    process_expression(ac, runtime, program_archive, rhe)?;
+                }
+            }
+            Ok(())
+        }
         Statement::Declaration {
             xtype,
             name,
@@ -68,40 +224,36 @@ pub fn process_statement(
             let dimensions: Vec<u32> = dim_access
                 .iter()
                 .map(|dim_access| {
-                    ctx.get_variable_value(dim_access)?
-                        .ok_or(ProgramError::EmptyDataItem)
+                    let value = ctx
+                        .get_variable_value(dim_access)?
+                        .ok_or(ProgramError::EmptyDataItem)?;
+                    to_index(&value)
                 })
                 .collect::<Result<Vec<u32>, ProgramError>>()?;
             ctx.declare_item(data_type.clone(), name, &dimensions, signal_gen)?;
 
+            // Tags (e.g. `{binary}`) apply to every element of the signal
+            let tags = match xtype {
+                VariableType::Signal(_, tags) => tags.clone(),
+                _ => Vec::new(),
+            };
+            if !tags.is_empty() {
+                ctx.set_signal_tags(name, tags.clone())?;
+            }
+
             // If the declared item is a signal we should add it to the arithmetic circuit
+            // Zero sized arrays (e.g. `out[n]` with `n = 0`) hold no signals
             if data_type == DataType::Signal {
-                let mut signal_access = DataAccess::new(name, Vec::new());
-
-                if dimensions.is_empty() {
+                for indices in Indices::new(&dimensions) {
+                    let signal_access = DataAccess::new(name, u32_to_access(&indices));
                     let signal_id = ctx.get_signal_id(&signal_access)?;
                     ac.add_signal(
                         signal_id,
                         signal_access.access_str(ctx.get_ctx_name()),
                         None,
                     )?;
-                } else {
-                    let mut indices: Vec<u32> = vec![0; dimensions.len()];
-
-                    loop {
-                        // Set access and get signal id for the current indices
-                        signal_access.set_access(u32_to_access(&indices));
-                        let signal_id = ctx.get_signal_id(&signal_access)?;
-                        ac.add_signal(
-                            signal_id,
-                            signal_access.access_str(ctx.get_ctx_name()),
-                            None,
-                        )?;
-
-                        // Increment indices
-                        if !increment_indices(&mut indices, &dimensions)? {
-                            break;
-                        }
+                    if !tags.is_empty() {
+                        ac.set_signal_tags(signal_id, tags.clone())?;
                     }
                 }
             }
@@ -120,7 +272,7 @@ pub fn process_statement(
                 .get_variable_value(&access)?
                 .ok_or(ProgramError::EmptyDataItem)?;
 
-            if result == 0 {
+            if result.is_zero() {
                 if let Some(else_statement) = else_case {
                     runtime.push_context(true, "IF_FALSE".to_string())?;
                     process_statement(ac, runtime, program_archive, else_statement)?;
@@ -145,7 +297,7 @@ pub fn process_statement(
                     .get_variable_value(&access)?
                     .ok_or(ProgramError::EmptyDataItem)?;
 
-                if result == 0 {
+                if result.is_zero() {
                     break;
                 }
 
@@ -178,13 +330,16 @@ pub fn process_statement(
                 .get_variable_value(&access)?
                 .ok_or(ProgramError::EmptyDataItem)?;
 
-            if result == 0 {
+            if result.is_zero() {
                 return Err(ProgramError::RuntimeError(RuntimeError::AssertionFailed));
             }
 
             Ok(())
         }
-        _ => Err(ProgramError::StatementNotImplemented),
+        Statement::ConstraintEquality { lhe, rhe, .. } => {
+            handle_constraint_equality(ac, runtime, program_archive, lhe, rhe)
+        }
+        Statement::LogCall { args, .. } => handle_log_call(ac, runtime, program_archive, args),
     }
 }
 
@@ -199,77 +354,204 @@ fn handle_substitution(
     op: &AssignOp,
 ) -> Result<(), ProgramError> {
     let lh_access = build_access(ac, runtime, program_archive, var, access)?;
+
+    // Let the template instance know which component it is assigned to, for context naming
+    if let (Expression::Call { id, .. }, _) = strip_parallel(rhe) {
+        if program_archive.contains_template(id) {
+            runtime.set_pending_component(lh_access.path_str());
+        }
+    }
+
     let rh_access = process_expression(ac, runtime, program_archive, rhe)?;
+    assign(ac, runtime, &lh_access, &rh_access, op)
+}
+
+/// Handles a `log` call: arguments known at compile time are logged, and the signals are
+/// recorded in the circuit to be traced to their wires.
+fn handle_log_call(
+    ac: &mut Compiler,
+    runtime: &mut Runtime,
+    program_archive: &ProgramArchive,
+    args: &[LogArgument],
+) -> Result<(), ProgramError> {
+    let mut entry = LogEntry::new(runtime.current_context()?.get_ctx_name());
+    for arg in args {
+        let expression = match arg {
+            LogArgument::LogStr(string) => {
+                entry.push_str(string);
+                continue;
+            }
+            LogArgument::LogExp(expression) => expression,
+        };
+
+        let access = process_expression(ac, runtime, program_archive, expression)?;
+        let ctx = runtime.current_context()?;
+        if ctx.get_item_data_type(&access.get_name())? == DataType::Variable {
+            let value = ctx
+                .get_variable_value(&access)?
+                .ok_or(ProgramError::EmptyDataItem)?;
+            entry.push_str(&value.to_string());
+        } else {
+            let signal_id = ctx.get_signal_id(&access)?;
+            entry.push_signal(access.access_str(ctx.get_ctx_name()), signal_id);
+        }
+    }
 
+    info!(target: PROCESS, "log ({}): {}", entry.context, entry.message);
+    ac.add_log_entry(entry);
+
+    Ok(())
+}
+
+/// Handles a constraint equality, `lhe === rhe`, as set by `Runtime::set_constraint_equality`.
+/// Unless they are ignored, sides known at compile time must be equal, and the sides of signals
+/// are compared by an `AEq` gate recorded as a check in the circuit.
+fn handle_constraint_equality(
+    ac: &mut Compiler,
+    runtime: &mut Runtime,
+    program_archive: &ProgramArchive,
+    lhe: &Expression,
+    rhe: &Expression,
+) -> Result<(), ProgramError> {
+    if runtime.constraint_equality() == ConstraintEquality::Ignore {
+        return Ok(());
+    }
+
+    let check = handle_infix_op(
+        ac,
+        runtime,
+        program_archive,
+        &ExpressionInfixOpcode::Eq,
+        lhe,
+        rhe,
+    )?;
+    let ctx = runtime.current_context()?;
+    if ctx.get_item_data_type(&check.get_name())? == DataType::Variable {
+        let equal = ctx
+            .get_variable_value(&check)?
+            .ok_or(ProgramError::EmptyDataItem)?;
+        if equal.is_zero() {
+            return Err(ProgramError::RuntimeError(
+                RuntimeError::ConstraintNotSatisfied,
+            ));
+        }
+        return Ok(());
+    }
+
+    ac.add_constraint_check(ctx.get_signal_id(&check)?);
+    Ok(())
+}
+
+/// Handles a substitution to a tuple of targets, e.g. `(a, b) <== Template()(x, y)`.
+/// Targets named `_` are skipped.
+fn handle_mult_substitution(
+    ac: &mut Compiler,
+    runtime: &mut Runtime,
+    program_archive: &ProgramArchive,
+    lhe: &Expression,
+    rhe: &Expression,
+    op: &AssignOp,
+) -> Result<(), ProgramError> {
+    let targets = match lhe {
+        Expression::Tuple { values, .. } => values,
+        _ => return Err(ProgramError::OperationNotSupported),
+    };
+    let mut values = process_tuple(ac, runtime, program_archive, rhe)?;
+    if targets.len() != values.len() {
+        return Err(ProgramError::TupleLengthMismatch {
+            expected: targets.len(),
+            found: values.len(),
+        });
+    }
+
+    // Variables are read before any target is assigned, e.g. `(a, b) = (b, a)` swaps them
+    let signal_gen = runtime.get_signal_gen();
+    let ctx = runtime.current_context()?;
+    for value in &mut values {
+        if ctx.get_item_data_type(&value.get_name())? == DataType::Variable {
+            let content = ctx.get_variable_content(value)?;
+            *value = ctx.declare_random_item(signal_gen.clone(), DataType::Variable)?;
+            ctx.set_variable_content(value, content)?;
+        }
+    }
+
+    for (target, rh_access) in targets.iter().zip(values.iter()) {
+        match target {
+            Expression::Variable { name, .. } if name == "_" => {}
+            Expression::Variable { name, access, .. } => {
+                let lh_access = build_access(ac, runtime, program_archive, name, access)?;
+                assign(ac, runtime, &lh_access, rh_access, op)?;
+            }
+            _ => return Err(ProgramError::OperationNotSupported),
+        }
+    }
+
+    Ok(())
+}
+
+/// Evaluates the right-hand side of a tuple substitution: a tuple expression, or an anonymous
+/// component whose outputs are returned in declaration order.
+fn process_tuple(
+    ac: &mut Compiler,
+    runtime: &mut Runtime,
+    program_archive: &ProgramArchive,
+    expression: &Expression,
+) -> Result<Vec<DataAccess>, ProgramError> {
+    match expression {
+        Expression::Tuple { values, .. } => values
+            .iter()
+            .map(|value| process_expression(ac, runtime, program_archive, value))
+            .collect(),
+        Expression::AnonymousComp { .. } => {
+            handle_anonymous_comp(ac, runtime, program_archive, expression)
+        }
+        _ => Err(ProgramError::ExpressionNotImplemented),
+    }
+}
+
+/// Assigns the evaluated right-hand side of a substitution to its left-hand side.
+fn assign(
+    ac: &mut Compiler,
+    runtime: &mut Runtime,
+    lh_access: &DataAccess,
+    rh_access: &DataAccess,
+    op: &AssignOp,
+) -> Result<(), ProgramError> {
     let signal_gen = runtime.get_signal_gen();
     let ctx = runtime.current_context()?;
-    match ctx.get_item_data_type(var)? {
+    match ctx.get_item_data_type(&lh_access.get_name())? {
         DataType::Variable => {
-            // Assign the evaluated right-hand side to the left-hand side
-            let value = ctx.get_variable_value(&rh_access)?;
-            ctx.set_variable(&lh_access, value)?;
+            // Assign the evaluated right-hand side to the left-hand side, whole arrays included
+            let value = ctx.get_variable_content(rh_access)?;
+            let (expected, found) = (shape(&ctx.get_variable_content(lh_access)?), shape(&value));
+            if expected != found {
+                return Err(ProgramError::ShapeMismatch { expected, found });
+            }
+            ctx.set_variable_content(lh_access, value)?;
         }
         DataType::Component => match op {
             AssignOp::AssignVar => {
                 // Component instantiation
-                let signal_map = ctx.get_component_map(&rh_access)?;
-                ctx.set_component(&lh_access, signal_map)?;
+                let signal_map = ctx.get_component_map(rh_access)?;
+                ctx.set_component(lh_access, signal_map)?;
             }
-            AssignOp::AssignConstraintSignal => {
-                // Component signal assignment
-                match ctx.get_component_signal_content(&lh_access)? {
-                    NestedValue::Array(signal) => {
-                        let assigned_signal_array =
-                            match get_signal_content_for_access(ctx, &rh_access)? {
-                                NestedValue::Array(array) => array,
-                                _ => return Err(ProgramError::InvalidDataType),
-                            };
-
-                        connect_signal_arrays(ac, &signal, &assigned_signal_array)?;
-                    }
-                    NestedValue::Value(_) => {
-                        let component_signal = ctx.get_component_signal_id(&lh_access)?;
-                        let assigned_signal =
-                            get_signal_for_access(ac, ctx, signal_gen, &rh_access)?;
-
-                        ac.add_connection(assigned_signal, component_signal)?;
-                    }
-                }
+            AssignOp::AssignConstraintSignal | AssignOp::AssignSignal => {
+                // Component signal assignment, connected like signals are. The instance was
+                // created by its template assignment, so its inputs can be wired by any number of
+                // statements after it, e.g. one element per loop iteration
+                let component_signal = ctx.get_component_signal_content(lh_access)?;
+                let assigned_signal =
+                    get_signal_content_for_access(ac, ctx, signal_gen, rh_access)?;
+
+                connect_signals(ac, &component_signal, &assigned_signal)?;
             }
-            _ => return Err(ProgramError::OperationNotSupported),
         },
         DataType::Signal => {
-            match rhe {
-                Expression::Variable { .. } => match ctx.get_signal_content(&lh_access)? {
-                    NestedValue::Array(signal) => {
-                        // Connect the signals in the arrays
-                        let assigned_signal_array =
-                            match get_signal_content_for_access(ctx, &rh_access)? {
-                                NestedValue::Array(array) => array,
-                                _ => return Err(ProgramError::InvalidDataType),
-                            };
-
-                        connect_signal_arrays(ac, &signal, &assigned_signal_array)?;
-                    }
-                    NestedValue::Value(signal_id) => {
-                        let gate_output_id =
-                            get_signal_for_access(ac, ctx, signal_gen, &rh_access)?;
+            // Whole arrays and sub-arrays are connected element-wise
+            let signal = ctx.get_signal_content(lh_access)?;
+            let assigned_signal = get_signal_content_for_access(ac, ctx, signal_gen, rh_access)?;
 
-                        ac.add_connection(gate_output_id, signal_id)?;
-                    }
-                },
-                Expression::Call { .. }
-                | Expression::InfixOp { .. }
-                | Expression::PrefixOp { .. }
-                | Expression::Number(_, _) => {
-                    // Get the signal identifiers and connect them
-                    let given_output_id = ctx.get_signal_id(&lh_access)?;
-                    let gate_output_id = get_signal_for_access(ac, ctx, signal_gen, &rh_access)?;
-
-                    ac.add_connection(gate_output_id, given_output_id)?;
-                }
-                _ => return Err(ProgramError::SignalSubstitutionNotImplemented),
-            }
+            connect_signals(ac, &signal, &assigned_signal)?;
         }
     }
 
@@ -283,6 +565,8 @@ pub fn process_expression(
     program_archive: &ProgramArchive,
     expression: &Expression,
 ) -> Result<DataAccess, ProgramError> {
+    runtime.count_expression()?;
+
     match expression {
         Expression::Call { id, args, .. } => handle_call(ac, runtime, program_archive, id, args),
         Expression::InfixOp {
@@ -297,16 +581,61 @@ pub fn process_expression(
                 .current_context()?
                 .declare_random_item(signal_gen, DataType::Variable)?;
 
-            runtime.current_context()?.set_variable(
-                &access,
-                Some(value.to_u32().ok_or(ProgramError::ParsingError)?),
-            )?;
+            runtime
+                .current_context()?
+                .set_variable(&access, Some(FieldElement::from_bigint(value, ac.prime())))?;
 
             Ok(access)
         }
         Expression::Variable { name, access, .. } => {
             build_access(ac, runtime, program_archive, name, access)
         }
+        Expression::InlineSwitchOp {
+            cond,
+            if_true,
+            if_false,
+            ..
+        } => handle_inline_switch_op(ac, runtime, program_archive, cond, if_true, if_false),
+        Expression::ParallelOp { rhe, .. } => {
+            // `parallel` only affects witness generation, the instance is recorded as parallel
+            if let Expression::Call { id, .. } = rhe.as_ref() {
+                if program_archive.contains_template(id) {
+                    runtime.set_pending_parallel();
+                }
+            }
+            process_expression(ac, runtime, program_archive, rhe)
+        }
+        Expression::AnonymousComp { id, .. } => {
+            let mut outputs = handle_anonymous_comp(ac, runtime, program_archive, expression)?;
+            if outputs.len() != 1 {
+                return Err(ProgramError::InvalidAnonymousComponent {
+                    template: id.to_string(),
+                    reason: format!(
+                        "expected a single output signal, found {}, assign them to a tuple",
+                        outputs.len()
+                    ),
+                });
+            }
+            Ok(outputs.remove(0))
+        }
+        Expression::ArrayInLine { values, .. } => {
+            let elements = values
+                .iter()
+                .map(|value| process_expression(ac, runtime, program_archive, value))
+                .collect::<Result<Vec<DataAccess>, ProgramError>>()?;
+            handle_array(ac, runtime, &elements)
+        }
+        Expression::UniformArray {
+            value, dimension, ..
+        } => {
+            let element = process_expression(ac, runtime, program_archive, value)?;
+            let dimension_access = process_expression(ac, runtime, program_archive, dimension)?;
+            let dimension = runtime
+                .current_context()?
+                .get_variable_value(&dimension_access)?
+                .ok_or(ProgramError::EmptyDataItem)?;
+            handle_array(ac, runtime, &vec![element; to_index(&dimension)? as usize])
+        }
         _ => Err(ProgramError::ExpressionNotImplemented),
     }
 }
@@ -321,6 +650,7 @@ fn handle_call(
 ) -> Result<DataAccess, ProgramError> {
     // Determine if the call is to a function or a template and get argument names and body
     let is_function = program_archive.contains_function(id);
+    let parallel = runtime.take_pending_parallel();
     let (arg_names, body) = if is_function {
         let function_data = program_archive.get_function_data(id);
         (
@@ -347,27 +677,67 @@ fn handle_call(
                     .ok_or(ProgramError::EmptyDataItem)
             })
         })
-        .collect::<Result<Vec<u32>, ProgramError>>()?;
+        .collect::<Result<Vec<FieldElement>, ProgramError>>()?;
 
     // Create a new execution context
-    runtime.push_context(false, id.to_string())?;
+    let ctx_name = if is_function {
+        id.to_string()
+    } else {
+        let component = runtime.pending_component().map(str::to_string);
+        let ctx_name = runtime.instance_context_name(id)?;
+        let params = arg_names
+            .iter()
+            .zip(&arg_values)
+            .map(|(name, value)| template_param(name, value, ac.prime()))
+            .collect();
+        ac.add_instance(InstanceReport {
+            instance: ctx_name.clone(),
+            template: id.to_string(),
+            component,
+            params,
+            parallel: parallel || program_archive.get_template_data(id).is_parallel(),
+        });
+        ctx_name
+    };
 
-    // Set arguments in the new context
-    for (arg_name, &arg_value) in arg_names.iter().zip(&arg_values) {
-        let signal_gen = runtime.get_signal_gen();
-        runtime
-            .current_context()?
-            .declare_item(DataType::Variable, arg_name, &[], signal_gen)?;
-        runtime
-            .current_context()?
-            .set_variable(&DataAccess::new(arg_name, vec![]), Some(arg_value))?;
+    // Instances held by an up to date archive are linked instead of processed
+    if !is_function {
+        let prebuilt = runtime.prebuilt();
+        if let Some(archive) = prebuilt
+            .as_ref()
+            .and_then(|prebuilt| prebuilt.get(id, &arg_values))
+        {
+            let component_return =
+                link_prebuilt(ac, runtime, program_archive, id, &ctx_name, archive)?;
+            return declare_component(runtime, id, component_return);
+        }
+    }
+
+    // The components of the main component are processed by workers, if any
+    if !is_function && runtime.instance_workers().is_some() {
+        let args = arg_names.into_iter().zip(arg_values).collect();
+        let component_return = spawn_instance(ac, runtime, id, &ctx_name, args)?;
+        return declare_component(runtime, id, component_return);
     }
 
+    runtime.push_context(false, ctx_name.clone())?;
+    declare_arguments(runtime, arg_names.into_iter().zip(arg_values))?;
+
     // Process the function/template body
-    process_statements(ac, runtime, program_archive, &body)?;
+    let gate_count = ac.gates().len();
+    if is_function {
+        process_statements(ac, runtime, program_archive, &body)?;
+    } else {
+        ac.enter_template(id);
+        let result = process_statements(ac, runtime, program_archive, &body);
+        ac.exit_template();
+        result?;
+        check_gate_budget(runtime, id, &ctx_name, ac.gates().len() - gate_count)?;
+        runtime.count_instance(id, &ctx_name)?;
+    }
 
     // Get return values
-    let mut function_return: Option<u32> = None;
+    let mut function_return: Option<FieldElement> = None;
     let mut component_return: HashMap<String, Signal> = HashMap::new();
 
     if is_function {
@@ -392,93 +762,525 @@ fn handle_call(
 
     // Return to parent context
     runtime.pop_context(false)?;
+    if !is_function {
+        return declare_component(runtime, id, component_return);
+    }
+
     let signal_gen = runtime.get_signal_gen();
     let ctx = runtime.current_context()?;
     let return_access =
-        DataAccess::new(&format!("{}_{}_{}", id, RETURN_VAR, generate_u32()), vec![]);
+        DataAccess::new(&ctx.unique_name(&format!("{}_{}", id, RETURN_VAR)), vec![]);
+    ctx.declare_item(
+        DataType::Variable,
+        &return_access.get_name(),
+        &[],
+        signal_gen,
+    )?;
+    ctx.set_variable(&return_access, function_return)?;
 
-    if is_function {
-        ctx.declare_item(
-            DataType::Variable,
-            &return_access.get_name(),
-            &[],
-            signal_gen,
-        )?;
-        ctx.set_variable(&return_access, function_return)?;
-    } else {
-        ctx.declare_item(
-            DataType::Component,
-            &return_access.get_name(),
-            &[],
-            signal_gen,
-        )?;
-        ctx.set_component(&return_access, component_return)?;
+    Ok(return_access)
+}
+
+/// Declares the arguments of a call in the current context.
+fn declare_arguments(
+    runtime: &mut Runtime,
+    args: impl IntoIterator<Item = (String, FieldElement)>,
+) -> Result<(), ProgramError> {
+    for (arg_name, arg_value) in args {
+        let signal_gen = runtime.get_signal_gen();
+        runtime
+            .current_context()?
+            .declare_item(DataType::Variable, &arg_name, &[], signal_gen)?;
+        runtime
+            .current_context()?
+            .set_variable(&DataAccess::new(&arg_name, vec![]), Some(arg_value))?;
     }
 
+    Ok(())
+}
+
+/// Declares a template instance in the current context, given the signals of its inputs and
+/// outputs. Returns the access to the component.
+fn declare_component(
+    runtime: &mut Runtime,
+    id: &str,
+    component_return: HashMap<String, Signal>,
+) -> Result<DataAccess, ProgramError> {
+    let signal_gen = runtime.get_signal_gen();
+    let ctx = runtime.current_context()?;
+    let return_access =
+        DataAccess::new(&ctx.unique_name(&format!("{}_{}", id, RETURN_VAR)), vec![]);
+    ctx.declare_item(
+        DataType::Component,
+        &return_access.get_name(),
+        &[],
+        signal_gen,
+    )?;
+    ctx.set_component(&return_access, component_return)?;
+
     Ok(return_access)
 }
 
-/// Handles an infix operation.
-/// - If both inputs are variables, it directly computes the operation.
-/// - If one or both inputs are signals, it constructs the corresponding circuit gate.
-///
-/// Returns the access to a variable containing the result of the operation or the signal of the output gate.
-fn handle_infix_op(
+/// Links the prebuilt circuit of a template instance, see `PrebuiltInstances`, instead of
+/// processing its body. Its inputs and outputs are declared in the context of the instance like
+/// in a processed instance, with the dimensions of the signals of the archive, and its other
+/// signals are moved to a range of ids reserved for it. Returns the signals of the inputs and
+/// outputs of the instance.
+fn link_prebuilt(
     ac: &mut Compiler,
     runtime: &mut Runtime,
     program_archive: &ProgramArchive,
-    op: &ExpressionInfixOpcode,
-    lhe: &Expression,
-    rhe: &Expression,
-) -> Result<DataAccess, ProgramError> {
-    let lhe_access = process_expression(ac, runtime, program_archive, lhe)?;
-    let rhe_access = process_expression(ac, runtime, program_archive, rhe)?;
+    id: &str,
+    ctx_name: &str,
+    archive: &Compiler,
+) -> Result<HashMap<String, Signal>, ProgramError> {
+    let signal_count = archive.next_signal_id();
+    let start = runtime
+        .get_signal_gen()
+        .partition(1, signal_count)?
+        .remove(0)
+        .peek();
+    let mut map = (0..signal_count)
+        .map(|signal_id| (signal_id, start + signal_id))
+        .collect::<HashMap<u32, u32>>();
+
+    runtime.push_context(false, ctx_name.to_string())?;
+    ac.enter_template(id);
+    let template_data = program_archive.get_template_data(id);
+    let mut component_return = HashMap::new();
+    for name in template_data
+        .get_inputs()
+        .keys()
+        .chain(template_data.get_outputs().keys())
+    {
+        let archive_ids = archive
+            .get_item_signals(&format!("0.{}", name))
+            .into_iter()
+            .map(|(signal_id, signal_name)| (signal_name, signal_id))
+            .collect::<HashMap<String, u32>>();
+        let dims = item_dimensions(name, archive_ids.keys())?;
 
-    let signal_gen: Rc<RefCell<u32>> = runtime.get_signal_gen();
-    let ctx = runtime.current_context()?;
+        let signal_gen = runtime.get_signal_gen();
+        let ctx = runtime.current_context()?;
+        ctx.declare_item(DataType::Signal, name, &dims, signal_gen)?;
+        for indices in Indices::new(&dims) {
+            let access = DataAccess::new(name, u32_to_access(&indices));
+            let signal_id = ctx.get_signal_id(&access)?;
+            ac.add_signal(signal_id, access.access_str(ctx.get_ctx_name()), None)?;
+            let archive_id = archive_ids
+                .get(&format!("0.{}", access.path_str()))
+                .ok_or_else(|| ProgramError::InvalidPrebuiltInstance(id.to_string()))?;
+            map.insert(*archive_id, signal_id);
+        }
+        component_return.insert(name.to_string(), ctx.get_signal(name)?);
+    }
+    ac.exit_template();
+    runtime.pop_context(false)?;
 
-    // Determine the data types of the left and right operands
-    let lhs_data_type = ctx.get_item_data_type(&lhe_access.get_name())?;
-    let rhs_data_type = ctx.get_item_data_type(&rhe_access.get_name())?;
+    let gate_count = ac.gates().len();
+    ac.link_instance(archive.duplicate(), ctx_name, &map)?;
+    check_gate_budget(runtime, id, ctx_name, ac.gates().len() - gate_count)?;
+    runtime.count_instance(id, ctx_name)?;
 
-    // Handle the case where both inputs are variables
-    if lhs_data_type == DataType::Variable && rhs_data_type == DataType::Variable {
-        let lhs_value = ctx
-            .get_variable_value(&lhe_access)?
-            .ok_or(ProgramError::EmptyDataItem)?;
-        let rhs_value = ctx
-            .get_variable_value(&rhe_access)?
-            .ok_or(ProgramError::EmptyDataItem)?;
+    Ok(component_return)
+}
 
-        let op_res = execute_op(lhs_value, rhs_value, op)?;
-        let item_access = ctx.declare_random_item(signal_gen, DataType::Variable)?;
-        ctx.set_variable(&item_access, Some(op_res))?;
+/// Returns the dimensions of a signal item of a prebuilt instance from the names of its signals,
+/// e.g. `[2, 3]` for `0.a[0][0]` to `0.a[1][2]`. An item without signals is an empty array.
+fn item_dimensions<'a>(
+    name: &str,
+    signal_names: impl Iterator<Item = &'a String>,
+) -> Result<Vec<u32>, ProgramError> {
+    let mut dims: Option<Vec<u32>> = None;
+    for signal_name in signal_names {
+        let indices = signal_name
+            .split('[')
+            .skip(1)
+            .map(|index| index.trim_end_matches(']').parse::<u32>())
+            .collect::<Result<Vec<u32>, _>>()
+            .map_err(|_| ProgramError::InvalidPrebuiltInstance(name.to_string()))?;
+        let dims = dims.get_or_insert_with(|| vec![0; indices.len()]);
+        if dims.len() != indices.len() {
+            return Err(ProgramError::InvalidPrebuiltInstance(name.to_string()));
+        }
+        for (dim, index) in dims.iter_mut().zip(indices) {
+            *dim = (*dim).max(index + 1);
+        }
+    }
 
-        return Ok(item_access);
+    Ok(dims.unwrap_or_else(|| vec![0]))
+}
+
+/// Processes a template instance on a worker thread, in its own circuit and runtime, allocating
+/// signal ids from a reserved range. Returns the signals of the inputs and outputs of the instance
+/// as soon as the worker declared them, which are declared in this circuit too so they can be
+/// wired, while the worker processes the rest of the body. The circuit of the instance is merged
+/// by `join_instances`.
+fn spawn_instance(
+    ac: &mut Compiler,
+    runtime: &mut Runtime,
+    id: &str,
+    ctx_name: &str,
+    args: Vec<(String, FieldElement)>,
+) -> Result<HashMap<String, Signal>, ProgramError> {
+    let (program_archive, busy) = match runtime.instance_workers() {
+        Some(workers) => (
+            Arc::clone(&workers.program_archive),
+            workers.pending.len() >= workers.workers,
+        ),
+        None => return Err(ProgramError::CallError),
+    };
+    // Free a worker by merging the oldest instance
+    if busy {
+        join_next_instance(ac, runtime)?;
     }
 
-    // Handle cases where one or both inputs are signals
-    let lhs_id = get_signal_for_access(ac, ctx, signal_gen.clone(), &lhe_access)?;
-    let rhs_id = get_signal_for_access(ac, ctx, signal_gen.clone(), &rhe_access)?;
+    let signal_gen = runtime
+        .get_signal_gen()
+        .partition(1, INSTANCE_SIGNAL_RANGE)?
+        .remove(0);
+    let mut worker_runtime = runtime.fork(signal_gen);
+    let mut worker_ac = ac.fork();
+    let (io_sender, io_receiver) = mpsc::channel();
+    let (id, ctx_name) = (id.to_string(), ctx_name.to_string());
+    let worker = thread::spawn(move || -> Result<ProcessedInstance, ProgramError> {
+        process_instance(
+            &mut worker_ac,
+            &mut worker_runtime,
+            &program_archive,
+            &id,
+            &ctx_name,
+            args,
+            io_sender,
+        )?;
+        Ok(ProcessedInstance {
+            compiler: worker_ac,
+            steps: worker_runtime.step_stats(),
+        })
+    });
 
-    // Construct the corresponding circuit gate
-    let gate_type = AGateType::from(op);
-    let output_signal = ctx.declare_random_item(signal_gen, DataType::Signal)?;
-    let output_id = ctx.get_signal_id(&output_signal)?;
+    // Without the signals, the worker failed before declaring them
+    let Ok(io) = io_receiver.recv() else {
+        join_worker(worker)?;
+        return Err(ProgramError::CallError);
+    };
+    for (&signal_id, name) in &io.names {
+        ac.add_signal(signal_id, name.clone(), None)?;
+    }
 
-    // Add output signal and gate to the circuit
-    ac.add_signal(
-        output_id,
-        output_signal.access_str(ctx.get_ctx_name()),
-        None,
-    )?;
-    ac.add_gate(gate_type, lhs_id, rhs_id, output_id)?;
+    if let Some(workers) = runtime.instance_workers() {
+        workers.pending.push_back(PendingInstance {
+            worker,
+            io_signals: io.names.into_keys().collect(),
+        });
+    }
 
-    Ok(output_signal)
+    Ok(io.signals)
 }
 
-/// Handles a prefix operation.
-/// - If input is a variable, it directly computes the operation.
+/// Processes the body of a template instance on a worker, sending the signals of its inputs and
+/// outputs as soon as the statements processed so far declared them.
+fn process_instance(
+    ac: &mut Compiler,
+    runtime: &mut Runtime,
+    program_archive: &ProgramArchive,
+    id: &str,
+    ctx_name: &str,
+    args: Vec<(String, FieldElement)>,
+    io_sender: Sender<InstanceIo>,
+) -> Result<(), ProgramError> {
+    runtime.push_context(false, ctx_name.to_string())?;
+    declare_arguments(runtime, args)?;
+
+    let template_data = program_archive.get_template_data(id);
+    let io_names = template_data
+        .get_inputs()
+        .keys()
+        .chain(template_data.get_outputs().keys())
+        .collect::<Vec<&String>>();
+
+    let mut io_sender = Some(io_sender);
+    ac.enter_template(id);
+    for statement in template_data.get_body_as_vec() {
+        process_statement(ac, runtime, program_archive, statement)?;
+
+        let ctx = runtime.current_context()?;
+        if io_names.iter().all(|name| ctx.get_signal(name).is_ok()) {
+            if let Some(io_sender) = io_sender.take() {
+                // The caller is gone if it failed, the instance isn't needed anymore
+                let _ = io_sender.send(instance_io(ac, ctx, ctx_name, &io_names)?);
+            }
+        }
+    }
+    ac.exit_template();
+    check_gate_budget(runtime, id, ctx_name, ac.gates().len())?;
+
+    // Fails on the signals that were never declared
+    if let Some(io_sender) = io_sender {
+        let ctx = runtime.current_context()?;
+        let _ = io_sender.send(instance_io(ac, ctx, ctx_name, &io_names)?);
+    }
+
+    Ok(())
+}
+
+/// Returns the signals of the inputs and outputs of a template instance, declared in its context.
+fn instance_io(
+    ac: &Compiler,
+    ctx: &Context,
+    ctx_name: &str,
+    io_names: &[&String],
+) -> Result<InstanceIo, ProgramError> {
+    let mut io = InstanceIo {
+        signals: HashMap::new(),
+        names: BTreeMap::new(),
+    };
+    for &name in io_names {
+        io.signals.insert(name.clone(), ctx.get_signal(name)?);
+        io.names
+            .extend(ac.get_item_signals(&format!("{}.{}", ctx_name, name)));
+    }
+
+    Ok(io)
+}
+
+/// Waits for a worker to finish, propagating its panic if it panicked.
+fn join_worker(
+    worker: JoinHandle<Result<ProcessedInstance, ProgramError>>,
+) -> Result<ProcessedInstance, ProgramError> {
+    worker
+        .join()
+        .unwrap_or_else(|payload| panic::resume_unwind(payload))
+}
+
+/// Merges the oldest instance processed by a worker into the circuit, once processed.
+/// Returns false if there is none.
+fn join_next_instance(ac: &mut Compiler, runtime: &mut Runtime) -> Result<bool, ProgramError> {
+    let Some(instance) = runtime
+        .instance_workers()
+        .and_then(|workers| workers.pending.pop_front())
+    else {
+        return Ok(false);
+    };
+
+    let processed = join_worker(instance.worker)?;
+    runtime.add_steps(processed.steps)?;
+    ac.merge_instance(processed.compiler, &instance.io_signals)?;
+
+    Ok(true)
+}
+
+/// Merges the instances processed by workers into the circuit, in the order they were
+/// instantiated. Does nothing if there are no workers.
+pub fn join_instances(ac: &mut Compiler, runtime: &mut Runtime) -> Result<(), ProgramError> {
+    while join_next_instance(ac, runtime)? {}
+    Ok(())
+}
+
+/// Handles an anonymous component, e.g. `Num2Bits(8)(in)` or `Mux(n)(c <== c, s <== s)`.
+/// The template is instantiated like a named component, then its inputs are connected to the
+/// signal arguments, given in declaration order or by name.
+///
+/// Returns the accesses to the output signals of the instance, in declaration order.
+fn handle_anonymous_comp(
+    ac: &mut Compiler,
+    runtime: &mut Runtime,
+    program_archive: &ProgramArchive,
+    expression: &Expression,
+) -> Result<Vec<DataAccess>, ProgramError> {
+    let (id, is_parallel, params, signals, names) = match expression {
+        Expression::AnonymousComp {
+            id,
+            is_parallel,
+            params,
+            signals,
+            names,
+            ..
+        } => (id, *is_parallel, params, signals, names.as_deref()),
+        _ => return Err(ProgramError::ExpressionNotImplemented),
+    };
+    if !program_archive.contains_template(id) {
+        return Err(ProgramError::UndefinedFunctionOrTemplate);
+    }
+    let invalid = |reason: String| ProgramError::InvalidAnonymousComponent {
+        template: id.to_string(),
+        reason,
+    };
+
+    let template_data = program_archive.get_template_data(id);
+    let inputs: Vec<String> = match names {
+        Some(names) => names.iter().map(|(_, name)| name.clone()).collect(),
+        None => template_data
+            .get_declaration_inputs()
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect(),
+    };
+    if inputs.len() != signals.len() {
+        return Err(invalid(format!(
+            "expected {} signal arguments, found {}",
+            inputs.len(),
+            signals.len()
+        )));
+    }
+    // Arguments are evaluated in the parent context, before the instance is created
+    let mut arguments = Vec::new();
+    for signal in signals {
+        let access = process_expression(ac, runtime, program_archive, signal)?;
+        let signal_gen = runtime.get_signal_gen();
+        let ctx = runtime.current_context()?;
+        arguments.push(get_signal_content_for_access(ac, ctx, signal_gen, &access)?);
+    }
+
+    if is_parallel {
+        runtime.set_pending_parallel();
+    }
+    let component = handle_call(ac, runtime, program_archive, id, params)?;
+    let ctx = runtime.current_context()?;
+    for (input, argument) in inputs.iter().zip(arguments.iter()) {
+        if !template_data.get_inputs().contains_key(input) {
+            return Err(invalid(format!("{} is not an input signal", input)));
+        }
+        let input_access = DataAccess::new(
+            &component.get_name(),
+            vec![SubAccess::Component(input.clone())],
+        );
+        let input_signal = ctx.get_component_signal_content(&input_access)?;
+        connect_signals(ac, &input_signal, argument)?;
+    }
+
+    Ok(template_data
+        .get_declaration_outputs()
+        .iter()
+        .map(|(output, _)| {
+            DataAccess::new(
+                &component.get_name(),
+                vec![SubAccess::Component(output.clone())],
+            )
+        })
+        .collect())
+}
+
+/// Handles an array literal, given the accesses to its evaluated elements.
+/// - If all elements are variables, the array is a variable.
+/// - Otherwise it is a signal array, with constants for the variable elements.
+///
+/// Returns the access to the array.
+fn handle_array(
+    ac: &mut Compiler,
+    runtime: &mut Runtime,
+    elements: &[DataAccess],
+) -> Result<DataAccess, ProgramError> {
+    let signal_gen = runtime.get_signal_gen();
+    let ctx = runtime.current_context()?;
+
+    let mut is_variable = true;
+    for element in elements {
+        is_variable &= ctx.get_item_data_type(&element.get_name())? == DataType::Variable;
+    }
+
+    if is_variable {
+        let values = elements
+            .iter()
+            .map(|element| ctx.get_variable_content(element))
+            .collect::<Result<Vec<_>, RuntimeError>>()?;
+        check_element_shapes(&values)?;
+
+        let access = ctx.declare_random_item(signal_gen, DataType::Variable)?;
+        ctx.set_variable_content(&access, NestedValue::Array(values))?;
+        Ok(access)
+    } else {
+        let mut signals = Vec::new();
+        for element in elements {
+            signals.push(get_signal_content_for_access(
+                ac,
+                ctx,
+                signal_gen.clone(),
+                element,
+            )?);
+        }
+        check_element_shapes(&signals)?;
+
+        Ok(ctx.declare_random_signal(NestedValue::Array(signals))?)
+    }
+}
+
+/// Checks that all elements of an array have the same shape.
+fn check_element_shapes<T>(elements: &[NestedValue<T>]) -> Result<(), ProgramError> {
+    if let Some(first) = elements.first() {
+        let expected = shape(first);
+        for element in elements {
+            let found = shape(element);
+            if found != expected {
+                return Err(ProgramError::ShapeMismatch { expected, found });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Handles an infix operation.
+/// - If both inputs are variables, it directly computes the operation.
+/// - If one or both inputs are signals, it constructs the corresponding circuit gate.
+///
+/// Returns the access to a variable containing the result of the operation or the signal of the output gate.
+fn handle_infix_op(
+    ac: &mut Compiler,
+    runtime: &mut Runtime,
+    program_archive: &ProgramArchive,
+    op: &ExpressionInfixOpcode,
+    lhe: &Expression,
+    rhe: &Expression,
+) -> Result<DataAccess, ProgramError> {
+    let lhe_access = process_expression(ac, runtime, program_archive, lhe)?;
+    let rhe_access = process_expression(ac, runtime, program_archive, rhe)?;
+
+    let signal_gen: SignalGen = runtime.get_signal_gen();
+    let ctx = runtime.current_context()?;
+
+    // Determine the data types of the left and right operands
+    let lhs_data_type = ctx.get_item_data_type(&lhe_access.get_name())?;
+    let rhs_data_type = ctx.get_item_data_type(&rhe_access.get_name())?;
+
+    // Handle the case where both inputs are variables
+    if lhs_data_type == DataType::Variable && rhs_data_type == DataType::Variable {
+        let lhs_value = ctx
+            .get_variable_value(&lhe_access)?
+            .ok_or(ProgramError::EmptyDataItem)?;
+        let rhs_value = ctx
+            .get_variable_value(&rhe_access)?
+            .ok_or(ProgramError::EmptyDataItem)?;
+
+        let op_res = execute_op(&lhs_value, &rhs_value, op, ac.value_type(), ac.prime())?;
+        let item_access = ctx.declare_random_item(signal_gen, DataType::Variable)?;
+        ctx.set_variable(&item_access, Some(op_res))?;
+
+        return Ok(item_access);
+    }
+
+    // Handle cases where one or both inputs are signals
+    let lhs_id = get_signal_for_access(ac, ctx, signal_gen.clone(), &lhe_access)?;
+    let rhs_id = get_signal_for_access(ac, ctx, signal_gen.clone(), &rhe_access)?;
+
+    // Construct the corresponding circuit gate
+    let gate_type = ac.gate_variant(AGateType::from(op));
+    let output_signal = ctx.declare_random_item(signal_gen, DataType::Signal)?;
+    let output_id = ctx.get_signal_id(&output_signal)?;
+
+    // Add output signal and gate to the circuit
+    ac.add_signal(
+        output_id,
+        output_signal.access_str(ctx.get_ctx_name()),
+        None,
+    )?;
+    ac.add_gate(gate_type, lhs_id, rhs_id, output_id)?;
+
+    Ok(output_signal)
+}
+
+/// Handles a prefix operation.
+/// - If input is a variable, it directly computes the operation.
 /// - If input is a signal, it handles it like an infix op against a constant.
 ///
 /// Returns the access to a variable containing the result of the operation or the signal of the output gate.
@@ -491,7 +1293,7 @@ fn handle_prefix_op(
 ) -> Result<DataAccess, ProgramError> {
     let rhe_access = process_expression(ac, runtime, program_archive, rhe)?;
 
-    let signal_gen: Rc<RefCell<u32>> = runtime.get_signal_gen();
+    let signal_gen: SignalGen = runtime.get_signal_gen();
     let ctx = runtime.current_context()?;
 
     // Determine the data type of the operand
@@ -503,21 +1305,21 @@ fn handle_prefix_op(
             .get_variable_value(&rhe_access)?
             .ok_or(ProgramError::EmptyDataItem)?;
 
-        let op_res = execute_prefix_op(op, rhs_value)?;
+        let op_res = execute_prefix_op(op, &rhs_value, ac.value_type(), ac.prime())?;
         let item_access = ctx.declare_random_item(signal_gen, DataType::Variable)?;
         ctx.set_variable(&item_access, Some(op_res))?;
 
         return Ok(item_access);
     }
 
-    let (lhs_value, infix_op) = to_equivalent_infix(op);
-    let lhs_id = make_constant(ac, ctx, signal_gen.clone(), lhs_value)?;
+    let (lhs_value, infix_op) = to_equivalent_infix(op, ac.value_type(), ac.prime());
+    let lhs_id = make_constant(ac, ctx, signal_gen.clone(), &lhs_value)?;
 
     // Handle signal input
     let rhs_id = get_signal_for_access(ac, ctx, signal_gen.clone(), &rhe_access)?;
 
     // Construct the corresponding circuit gate
-    let gate_type = AGateType::from(&infix_op);
+    let gate_type = ac.gate_variant(AGateType::from(&infix_op));
     let output_signal = ctx.declare_random_item(signal_gen, DataType::Signal)?;
     let output_id = ctx.get_signal_id(&output_signal)?;
 
@@ -532,13 +1334,99 @@ fn handle_prefix_op(
     Ok(output_signal)
 }
 
+/// Handles an inline switch, `cond ? if_true : if_false`.
+/// - If the condition is a variable, only the selected branch is processed.
+/// - If the condition is a signal, both branches are processed and the selection is emitted as
+///   `if_false + cond * (if_true - if_false)`, i.e. `cond * if_true + (1 - cond) * if_false` with
+///   a single multiplication. The condition is expected to be 0 or 1.
+///
+/// Returns the access to the selected branch or to the signal of the output gate.
+fn handle_inline_switch_op(
+    ac: &mut Compiler,
+    runtime: &mut Runtime,
+    program_archive: &ProgramArchive,
+    cond: &Expression,
+    if_true: &Expression,
+    if_false: &Expression,
+) -> Result<DataAccess, ProgramError> {
+    let cond_access = process_expression(ac, runtime, program_archive, cond)?;
+
+    let ctx = runtime.current_context()?;
+    if ctx.get_item_data_type(&cond_access.get_name())? == DataType::Variable {
+        let cond_value = ctx
+            .get_variable_value(&cond_access)?
+            .ok_or(ProgramError::EmptyDataItem)?;
+        let branch = if cond_value.is_zero() {
+            if_false
+        } else {
+            if_true
+        };
+
+        return process_expression(ac, runtime, program_archive, branch);
+    }
+
+    let true_access = process_expression(ac, runtime, program_archive, if_true)?;
+    let false_access = process_expression(ac, runtime, program_archive, if_false)?;
+
+    let signal_gen: SignalGen = runtime.get_signal_gen();
+    let ctx = runtime.current_context()?;
+    let cond_id = get_signal_for_access(ac, ctx, signal_gen.clone(), &cond_access)?;
+    let true_id = get_signal_for_access(ac, ctx, signal_gen.clone(), &true_access)?;
+    let false_id = get_signal_for_access(ac, ctx, signal_gen.clone(), &false_access)?;
+
+    let (_, difference_id) = add_gate_output(
+        ac,
+        ctx,
+        signal_gen.clone(),
+        AGateType::ASub,
+        true_id,
+        false_id,
+    )?;
+    let (_, scaled_id) = add_gate_output(
+        ac,
+        ctx,
+        signal_gen.clone(),
+        AGateType::AMul,
+        cond_id,
+        difference_id,
+    )?;
+    let (output_signal, _) =
+        add_gate_output(ac, ctx, signal_gen, AGateType::AAdd, false_id, scaled_id)?;
+
+    Ok(output_signal)
+}
+
+/// Adds a gate of the value type along with a new signal for its output.
+///
+/// Returns the access to the output signal and its id.
+fn add_gate_output(
+    ac: &mut Compiler,
+    ctx: &mut Context,
+    signal_gen: SignalGen,
+    gate_type: AGateType,
+    lhs_id: u32,
+    rhs_id: u32,
+) -> Result<(DataAccess, u32), ProgramError> {
+    let output_signal = ctx.declare_random_item(signal_gen, DataType::Signal)?;
+    let output_id = ctx.get_signal_id(&output_signal)?;
+
+    ac.add_signal(
+        output_id,
+        output_signal.access_str(ctx.get_ctx_name()),
+        None,
+    )?;
+    ac.add_gate(ac.gate_variant(gate_type), lhs_id, rhs_id, output_id)?;
+
+    Ok((output_signal, output_id))
+}
+
 /// Returns a signal id for a given access
 /// - If the access is a signal or a component, it returns the corresponding signal id.
 /// - If the access is a variable, it adds a constant variable to the circuit and returns the corresponding signal id.
 fn get_signal_for_access(
     ac: &mut Compiler,
     ctx: &mut Context,
-    signal_gen: Rc<RefCell<u32>>,
+    signal_gen: SignalGen,
     access: &DataAccess,
 ) -> Result<u32, ProgramError> {
     match ctx.get_item_data_type(&access.get_name())? {
@@ -549,7 +1437,7 @@ fn get_signal_for_access(
                 .get_variable_value(access)?
                 .ok_or(ProgramError::EmptyDataItem)?;
 
-            make_constant(ac, ctx, signal_gen, value)
+            make_constant(ac, ctx, signal_gen, &value)
         }
         DataType::Component => Ok(ctx.get_component_signal_id(access)?),
     }
@@ -558,9 +1446,11 @@ fn get_signal_for_access(
 fn make_constant(
     ac: &mut Compiler,
     ctx: &mut Context,
-    signal_gen: Rc<RefCell<u32>>,
-    value: u32,
+    signal_gen: SignalGen,
+    value: &FieldElement,
 ) -> Result<u32, ProgramError> {
+    // Constants are kept as field elements, they only have to fit in a word of the value type
+    // once they are emitted as wires of a word backend
     let signal_access = DataAccess::new(&format!("const_signal_{}", value), vec![]);
     // Try to get signal id if it exists
     if let Ok(id) = ctx.get_signal_id(&signal_access) {
@@ -572,50 +1462,114 @@ fn make_constant(
         ac.add_signal(
             signal_id,
             signal_access.access_str(ctx.get_ctx_name()),
-            Some(value),
+            Some(value.clone()),
         )?;
         Ok(signal_id)
     }
 }
 
 /// Returns the content of a signal for a given access
+/// - If the access is a signal or a component, it returns the corresponding signal ids.
+/// - If the access is a variable, it adds constants to the circuit for each of its values.
 fn get_signal_content_for_access(
-    ctx: &Context,
+    ac: &mut Compiler,
+    ctx: &mut Context,
+    signal_gen: SignalGen,
     access: &DataAccess,
 ) -> Result<NestedValue<u32>, ProgramError> {
     match ctx.get_item_data_type(&access.get_name())? {
         DataType::Signal => Ok(ctx.get_signal_content(access)?),
         DataType::Component => Ok(ctx.get_component_signal_content(access)?),
-        _ => Err(ProgramError::InvalidDataType),
+        DataType::Variable => {
+            let content = ctx.get_variable_content(access)?;
+            make_constant_content(ac, ctx, signal_gen, &content)
+        }
+    }
+}
+
+/// Adds constants to the circuit for each value of a variable content
+fn make_constant_content(
+    ac: &mut Compiler,
+    ctx: &mut Context,
+    signal_gen: SignalGen,
+    content: &NestedValue<Option<FieldElement>>,
+) -> Result<NestedValue<u32>, ProgramError> {
+    match content {
+        NestedValue::Value(value) => {
+            let value = value.as_ref().ok_or(ProgramError::EmptyDataItem)?;
+            Ok(NestedValue::Value(make_constant(
+                ac, ctx, signal_gen, value,
+            )?))
+        }
+        NestedValue::Array(values) => Ok(NestedValue::Array(
+            values
+                .iter()
+                .map(|value| make_constant_content(ac, ctx, signal_gen.clone(), value))
+                .collect::<Result<Vec<NestedValue<u32>>, ProgramError>>()?,
+        )),
     }
 }
 
-/// Connects two composed signals
-fn connect_signal_arrays(
+/// Connects two signals element-wise, checking that both have the same shape
+fn connect_signals(
     ac: &mut Compiler,
-    a: &[NestedValue<u32>],
-    b: &[NestedValue<u32>],
+    a: &NestedValue<u32>,
+    b: &NestedValue<u32>,
 ) -> Result<(), ProgramError> {
-    // Verify that the arrays have the same length
-    if a.len() != b.len() {
-        return Err(ProgramError::InvalidDataType);
+    let (expected, found) = (shape(a), shape(b));
+    if expected != found {
+        return Err(ProgramError::ShapeMismatch { expected, found });
     }
 
-    for (a, b) in a.iter().zip(b.iter()) {
-        match (a, b) {
-            (NestedValue::Value(a), NestedValue::Value(b)) => {
-                ac.add_connection(*a, *b)?;
-            }
-            (NestedValue::Array(a), NestedValue::Array(b)) => {
-                connect_signal_arrays(ac, a, b)?;
+    let mut connections = Vec::new();
+    collect_connections(a, b, &mut connections)?;
+    ac.add_aliases(&connections);
+
+    Ok(())
+}
+
+/// Pairs up the signal ids of two equally shaped signals
+fn collect_connections(
+    a: &NestedValue<u32>,
+    b: &NestedValue<u32>,
+    connections: &mut Vec<(u32, u32)>,
+) -> Result<(), ProgramError> {
+    match (a, b) {
+        (NestedValue::Value(a), NestedValue::Value(b)) => {
+            connections.push((*a, *b));
+        }
+        (NestedValue::Array(a), NestedValue::Array(b)) if a.len() == b.len() => {
+            for (a, b) in a.iter().zip(b.iter()) {
+                collect_connections(a, b, connections)?;
             }
-            _ => return Err(ProgramError::InvalidDataType),
+        }
+        _ => {
+            return Err(ProgramError::ShapeMismatch {
+                expected: shape(a),
+                found: shape(b),
+            })
         }
     }
 
     Ok(())
 }
 
+/// Returns the dimensions of a nested value, e.g. `[2, 3]` for a 2x3 array
+fn shape<T>(value: &NestedValue<T>) -> Vec<usize> {
+    let mut dimensions = Vec::new();
+    let mut current = value;
+
+    while let NestedValue::Array(values) = current {
+        dimensions.push(values.len());
+        match values.first() {
+            Some(first) => current = first,
+            None => break,
+        }
+    }
+
+    dimensions
+}
+
 /// Builds a DataAccess from an Access array
 fn build_access(
     ac: &mut Compiler,
@@ -634,7 +1588,7 @@ fn build_access(
                     .current_context()?
                     .get_variable_value(&index_access)?
                     .ok_or(ProgramError::EmptyDataItem)?;
-                access_vec.push(SubAccess::Array(index));
+                access_vec.push(SubAccess::Array(to_index(&index)?));
             }
             Access::ComponentAccess(signal) => {
                 access_vec.push(SubAccess::Component(signal.to_string()));
@@ -645,121 +1599,154 @@ fn build_access(
     Ok(DataAccess::new(name, access_vec))
 }
 
-/// Executes an operation on two u32 values, performing the specified arithmetic or logical computation.
-fn execute_op(lhs: u32, rhs: u32, op: &ExpressionInfixOpcode) -> Result<u32, ProgramError> {
+/// Returns the expression annotated by `parallel`, e.g. `Foo()` for `parallel Foo()`, and whether
+/// it was annotated.
+pub fn strip_parallel(expression: &Expression) -> (&Expression, bool) {
+    match expression {
+        Expression::ParallelOp { rhe, .. } => (rhe, true),
+        expression => (expression, false),
+    }
+}
+
+/// Returns a template parameter with its value, signed, for the instance report.
+pub fn template_param(name: &str, value: &FieldElement, prime: Prime) -> TemplateParam {
+    let (magnitude, negative) = value.to_integer(true, prime);
+    TemplateParam {
+        name: name.to_string(),
+        value: format!("{}{}", if negative { "-" } else { "" }, magnitude),
+    }
+}
+
+/// Checks the number of gates contributed by a template instance against the template budget.
+pub fn check_gate_budget(
+    runtime: &Runtime,
+    template: &str,
+    instance: &str,
+    gates: usize,
+) -> Result<(), ProgramError> {
+    match runtime.gate_budget(template) {
+        Some(budget) if gates > budget => Err(ProgramError::GateBudgetExceeded {
+            template: template.to_string(),
+            instance: instance.to_string(),
+            gates,
+            budget,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Converts a compile-time value to an array index or dimension.
+fn to_index(value: &FieldElement) -> Result<u32, ProgramError> {
+    value.to_u32().ok_or_else(|| ProgramError::ValueOutOfRange {
+        value: value.to_string(),
+        target: String::from("indices"),
+    })
+}
+
+/// Executes an operation on two compile-time values, performing the specified arithmetic or
+/// logical computation. Values are field elements, so arithmetic is modulo the selected prime instead of
+/// wrapping at 32 bits, and only fails to fit once the result is emitted to the circuit.
+/// `/` multiplies by the inverse in the field, while `\` divides integers.
+/// With a signed value type, elements above `p / 2` are negative: comparisons, integer divisions,
+/// right shifts and bitwise operations are signed. With an unsigned value type, subtraction fails on
+/// underflow.
+pub(crate) fn execute_op(
+    lhs: &FieldElement,
+    rhs: &FieldElement,
+    op: &ExpressionInfixOpcode,
+    value_type: ValueType,
+    prime: Prime,
+) -> Result<FieldElement, ProgramError> {
+    let signed = value_type.is_signed();
+    let compare = || lhs.cmp_integer(rhs, signed, prime);
+
     let res = match op {
-        ExpressionInfixOpcode::Mul => lhs * rhs,
-        ExpressionInfixOpcode::Div => {
-            if rhs == 0 {
+        ExpressionInfixOpcode::Mul => lhs.mul(rhs, prime),
+        ExpressionInfixOpcode::Div => lhs
+            .div(rhs, prime)
+            .ok_or_else(|| ProgramError::OperationError("Division by zero".to_string()))?,
+        ExpressionInfixOpcode::IntDiv => {
+            if rhs.is_zero() {
                 return Err(ProgramError::OperationError("Division by zero".to_string()));
             }
 
-            lhs / rhs
+            // Integer division, rounding towards zero
+            let (lhs, lhs_negative) = lhs.to_integer(signed, prime);
+            let (rhs, rhs_negative) = rhs.to_integer(signed, prime);
+            FieldElement::from_integer(&(lhs / rhs), lhs_negative != rhs_negative, prime)
         }
-        ExpressionInfixOpcode::Add => lhs + rhs,
+        ExpressionInfixOpcode::Add => lhs.add(rhs, prime),
         ExpressionInfixOpcode::Sub => {
-            if lhs < rhs {
+            if !signed && compare() == Ordering::Less {
                 return Err(ProgramError::OperationError(
                     "Subtraction underflow".to_string(),
                 ));
             }
 
-            lhs - rhs
-        }
-        ExpressionInfixOpcode::Pow => lhs.pow(rhs),
-        ExpressionInfixOpcode::IntDiv => {
-            if rhs == 0 {
-                return Err(ProgramError::OperationError(
-                    "Integer division by zero".to_string(),
-                ));
-            }
-
-            lhs / rhs
+            lhs.sub(rhs, prime)
         }
+        ExpressionInfixOpcode::Pow => lhs.pow(rhs, prime),
         ExpressionInfixOpcode::Mod => {
-            if rhs == 0 {
+            if rhs.is_zero() {
                 return Err(ProgramError::OperationError("Modulo by zero".to_string()));
             }
 
-            lhs % rhs
-        }
-        ExpressionInfixOpcode::ShiftL => lhs << rhs,
-        ExpressionInfixOpcode::ShiftR => lhs >> rhs,
-        ExpressionInfixOpcode::LesserEq => {
-            if lhs <= rhs {
-                1
-            } else {
-                0
-            }
-        }
-        ExpressionInfixOpcode::GreaterEq => {
-            if lhs >= rhs {
-                1
-            } else {
-                0
-            }
-        }
-        ExpressionInfixOpcode::Lesser => {
-            if lhs < rhs {
-                1
-            } else {
-                0
-            }
-        }
-        ExpressionInfixOpcode::Greater => {
-            if lhs > rhs {
-                1
-            } else {
-                0
-            }
+            // The remainder takes the sign of the dividend
+            let (lhs, lhs_negative) = lhs.to_integer(signed, prime);
+            let (rhs, _) = rhs.to_integer(signed, prime);
+            FieldElement::from_integer(&(lhs % rhs), lhs_negative, prime)
         }
-        ExpressionInfixOpcode::Eq => {
-            if lhs == rhs {
-                1
-            } else {
-                0
-            }
-        }
-        ExpressionInfixOpcode::NotEq => {
-            if lhs != rhs {
-                1
-            } else {
-                0
-            }
-        }
-        ExpressionInfixOpcode::BoolOr => {
-            if lhs != 0 || rhs != 0 {
-                1
-            } else {
-                0
-            }
-        }
-        ExpressionInfixOpcode::BoolAnd => {
-            if lhs != 0 && rhs != 0 {
-                1
-            } else {
-                0
-            }
-        }
-        ExpressionInfixOpcode::BitOr => lhs | rhs,
-        ExpressionInfixOpcode::BitAnd => lhs & rhs,
-        ExpressionInfixOpcode::BitXor => lhs ^ rhs,
+        // Shifting by a negative amount shifts in the other direction
+        ExpressionInfixOpcode::ShiftL => match rhs.to_integer(signed, prime) {
+            (shift, false) => lhs.shl(&shift, prime),
+            (shift, true) => lhs.shr(&shift, signed, prime),
+        },
+        ExpressionInfixOpcode::ShiftR => match rhs.to_integer(signed, prime) {
+            (shift, false) => lhs.shr(&shift, signed, prime),
+            (shift, true) => lhs.shl(&shift, prime),
+        },
+        ExpressionInfixOpcode::LesserEq => (compare() != Ordering::Greater).into(),
+        ExpressionInfixOpcode::GreaterEq => (compare() != Ordering::Less).into(),
+        ExpressionInfixOpcode::Lesser => (compare() == Ordering::Less).into(),
+        ExpressionInfixOpcode::Greater => (compare() == Ordering::Greater).into(),
+        ExpressionInfixOpcode::Eq => (lhs == rhs).into(),
+        ExpressionInfixOpcode::NotEq => (lhs != rhs).into(),
+        ExpressionInfixOpcode::BoolOr => (!lhs.is_zero() || !rhs.is_zero()).into(),
+        ExpressionInfixOpcode::BoolAnd => (!lhs.is_zero() && !rhs.is_zero()).into(),
+        ExpressionInfixOpcode::BitOr => lhs.bitwise(rhs, signed, prime, |lhs, rhs| lhs | rhs),
+        ExpressionInfixOpcode::BitAnd => lhs.bitwise(rhs, signed, prime, |lhs, rhs| lhs & rhs),
+        ExpressionInfixOpcode::BitXor => lhs.bitwise(rhs, signed, prime, |lhs, rhs| lhs ^ rhs),
     };
 
     Ok(res)
 }
 
-/// Executes a prefix operation on a u32 value, performing the specified arithmetic or logical computation.
-fn execute_prefix_op(op: &ExpressionPrefixOpcode, rhs: u32) -> Result<u32, ProgramError> {
-    let (lhs_value, infix_op) = to_equivalent_infix(op);
-    execute_op(lhs_value, rhs, &infix_op)
+/// Executes a prefix operation on a compile-time value, performing the specified arithmetic or
+/// logical computation.
+fn execute_prefix_op(
+    op: &ExpressionPrefixOpcode,
+    rhs: &FieldElement,
+    value_type: ValueType,
+    prime: Prime,
+) -> Result<FieldElement, ProgramError> {
+    let (lhs_value, infix_op) = to_equivalent_infix(op, value_type, prime);
+    execute_op(&lhs_value, rhs, &infix_op, value_type, prime)
 }
 
-fn to_equivalent_infix(op: &ExpressionPrefixOpcode) -> (u32, ExpressionInfixOpcode) {
+/// Returns the infix operation equivalent to a prefix operation, with its left-hand side. The
+/// complement flips the bits of a 32-bit word, i.e. it is `-1 ^ x` for signed values.
+fn to_equivalent_infix(
+    op: &ExpressionPrefixOpcode,
+    value_type: ValueType,
+    prime: Prime,
+) -> (FieldElement, ExpressionInfixOpcode) {
     match op {
-        ExpressionPrefixOpcode::Sub => (0, ExpressionInfixOpcode::Sub),
-        ExpressionPrefixOpcode::BoolNot => (0, ExpressionInfixOpcode::Eq),
-        ExpressionPrefixOpcode::Complement => (u32::MAX, ExpressionInfixOpcode::BitXor),
+        ExpressionPrefixOpcode::Sub => (FieldElement::from(0), ExpressionInfixOpcode::Sub),
+        ExpressionPrefixOpcode::BoolNot => (FieldElement::from(0), ExpressionInfixOpcode::Eq),
+        ExpressionPrefixOpcode::Complement => (
+            FieldElement::from_word(u32::MAX, value_type, prime),
+            ExpressionInfixOpcode::BitXor,
+        ),
     }
 }
 
@@ -768,72 +1755,351 @@ mod tests {
     use super::*;
     use circom_program_structure::ast::{ExpressionInfixOpcode, ExpressionPrefixOpcode};
 
+    const BN128: Prime = Prime::Bn128;
+
+    /// Executes an operation on 32-bit words of the value type, failing if the result doesn't
+    /// fit in a word.
+    fn word_op(
+        lhs: u32,
+        rhs: u32,
+        op: &ExpressionInfixOpcode,
+        value_type: ValueType,
+    ) -> Result<u32, ProgramError> {
+        let lhs = FieldElement::from_word(lhs, value_type, BN128);
+        let rhs = FieldElement::from_word(rhs, value_type, BN128);
+        to_word(execute_op(&lhs, &rhs, op, value_type, BN128)?, value_type)
+    }
+
+    fn word_prefix_op(
+        op: &ExpressionPrefixOpcode,
+        rhs: u32,
+        value_type: ValueType,
+    ) -> Result<u32, ProgramError> {
+        let rhs = FieldElement::from_word(rhs, value_type, BN128);
+        to_word(execute_prefix_op(op, &rhs, value_type, BN128)?, value_type)
+    }
+
+    fn to_word(value: FieldElement, value_type: ValueType) -> Result<u32, ProgramError> {
+        value
+            .to_word(value_type, BN128)
+            .ok_or_else(|| ProgramError::ValueOutOfRange {
+                value: value.to_string(),
+                target: format!("{:?} wires", value_type),
+            })
+    }
+
     #[test]
-    fn test_execute_op() {
-        assert_eq!(execute_op(3, 4, &ExpressionInfixOpcode::Add).unwrap(), 7);
-        assert_eq!(execute_op(10, 5, &ExpressionInfixOpcode::Sub).unwrap(), 5);
-        assert_eq!(execute_op(6, 3, &ExpressionInfixOpcode::Mul).unwrap(), 18);
-        assert_eq!(execute_op(9, 3, &ExpressionInfixOpcode::Div).unwrap(), 3);
-        assert_eq!(execute_op(7, 3, &ExpressionInfixOpcode::Mod).unwrap(), 1);
-        assert_eq!(execute_op(2, 3, &ExpressionInfixOpcode::Pow).unwrap(), 8);
+    fn test_word_op() {
+        assert_eq!(
+            word_op(3, 4, &ExpressionInfixOpcode::Add, ValueType::Uint).unwrap(),
+            7
+        );
+        assert_eq!(
+            word_op(10, 5, &ExpressionInfixOpcode::Sub, ValueType::Uint).unwrap(),
+            5
+        );
         assert_eq!(
-            execute_op(8, 2, &ExpressionInfixOpcode::ShiftL).unwrap(),
+            word_op(6, 3, &ExpressionInfixOpcode::Mul, ValueType::Uint).unwrap(),
+            18
+        );
+        assert_eq!(
+            word_op(9, 3, &ExpressionInfixOpcode::Div, ValueType::Uint).unwrap(),
+            3
+        );
+        assert_eq!(
+            word_op(7, 3, &ExpressionInfixOpcode::Mod, ValueType::Uint).unwrap(),
+            1
+        );
+        assert_eq!(
+            word_op(2, 3, &ExpressionInfixOpcode::Pow, ValueType::Uint).unwrap(),
+            8
+        );
+        assert_eq!(
+            word_op(8, 2, &ExpressionInfixOpcode::ShiftL, ValueType::Uint).unwrap(),
             32
         );
-        assert_eq!(execute_op(8, 2, &ExpressionInfixOpcode::ShiftR).unwrap(), 2);
-        assert_eq!(execute_op(5, 5, &ExpressionInfixOpcode::Eq).unwrap(), 1);
-        assert_eq!(execute_op(5, 4, &ExpressionInfixOpcode::NotEq).unwrap(), 1);
-        assert_eq!(execute_op(1, 0, &ExpressionInfixOpcode::BoolOr).unwrap(), 1);
         assert_eq!(
-            execute_op(1, 1, &ExpressionInfixOpcode::BoolAnd).unwrap(),
+            word_op(8, 2, &ExpressionInfixOpcode::ShiftR, ValueType::Uint).unwrap(),
+            2
+        );
+        assert_eq!(
+            word_op(5, 5, &ExpressionInfixOpcode::Eq, ValueType::Uint).unwrap(),
+            1
+        );
+        assert_eq!(
+            word_op(5, 4, &ExpressionInfixOpcode::NotEq, ValueType::Uint).unwrap(),
+            1
+        );
+        assert_eq!(
+            word_op(1, 0, &ExpressionInfixOpcode::BoolOr, ValueType::Uint).unwrap(),
+            1
+        );
+        assert_eq!(
+            word_op(1, 1, &ExpressionInfixOpcode::BoolAnd, ValueType::Uint).unwrap(),
             1
         );
-        assert_eq!(execute_op(1, 1, &ExpressionInfixOpcode::BitOr).unwrap(), 1);
-        assert_eq!(execute_op(1, 1, &ExpressionInfixOpcode::BitAnd).unwrap(), 1);
-        assert_eq!(execute_op(1, 1, &ExpressionInfixOpcode::BitXor).unwrap(), 0);
+        assert_eq!(
+            word_op(1, 1, &ExpressionInfixOpcode::BitOr, ValueType::Uint).unwrap(),
+            1
+        );
+        assert_eq!(
+            word_op(1, 1, &ExpressionInfixOpcode::BitAnd, ValueType::Uint).unwrap(),
+            1
+        );
+        assert_eq!(
+            word_op(1, 1, &ExpressionInfixOpcode::BitXor, ValueType::Uint).unwrap(),
+            0
+        );
     }
 
     #[test]
     fn test_execute_op_errors() {
-        assert!(execute_op(10, 0, &ExpressionInfixOpcode::Div).is_err());
-        assert!(execute_op(10, 0, &ExpressionInfixOpcode::IntDiv).is_err());
-        assert!(execute_op(10, 0, &ExpressionInfixOpcode::Mod).is_err());
+        assert!(word_op(10, 0, &ExpressionInfixOpcode::Div, ValueType::Uint).is_err());
+        assert!(word_op(10, 0, &ExpressionInfixOpcode::IntDiv, ValueType::Uint).is_err());
+        assert!(word_op(10, 0, &ExpressionInfixOpcode::Mod, ValueType::Uint).is_err());
     }
 
     #[test]
-    fn test_execute_prefix_op() {
+    fn test_word_prefix_op() {
+        let value_type = ValueType::default();
+
         assert_eq!(
-            execute_prefix_op(&ExpressionPrefixOpcode::Sub, 5)
-                .unwrap_err()
-                .to_string(),
-            "Operation error: Subtraction underflow"
-        ); // 0 - 5
+            word_prefix_op(&ExpressionPrefixOpcode::Sub, 5, value_type).unwrap(),
+            -5i32 as u32
+        ); // -5
         assert_eq!(
-            execute_prefix_op(&ExpressionPrefixOpcode::BoolNot, 0).unwrap(),
+            word_prefix_op(&ExpressionPrefixOpcode::BoolNot, 0, value_type).unwrap(),
             1
         ); // !0 == 1
         assert_eq!(
-            execute_prefix_op(&ExpressionPrefixOpcode::BoolNot, 1).unwrap(),
+            word_prefix_op(&ExpressionPrefixOpcode::BoolNot, 1, value_type).unwrap(),
             0
         ); // !1 == 0
         assert_eq!(
-            execute_prefix_op(&ExpressionPrefixOpcode::Complement, 0b1010).unwrap(),
+            word_prefix_op(&ExpressionPrefixOpcode::Complement, 0b1010, value_type).unwrap(),
             0b1111_1111_1111_1111_1111_1111_1111_0101
         ); // ~0b1010
     }
 
+    #[test]
+    fn test_execute_op_signed() {
+        let minus_two = -2i32 as u32;
+        let (sint, uint) = (ValueType::Sint, ValueType::Uint);
+
+        assert_eq!(
+            word_op(3, 5, &ExpressionInfixOpcode::Sub, sint).unwrap(),
+            minus_two
+        );
+        assert!(word_op(3, 5, &ExpressionInfixOpcode::Sub, uint).is_err());
+        assert_eq!(
+            word_op(minus_two, 3, &ExpressionInfixOpcode::Add, sint).unwrap(),
+            1
+        );
+        assert_eq!(
+            word_op(minus_two, 1, &ExpressionInfixOpcode::Lesser, sint).unwrap(),
+            1
+        );
+        assert_eq!(
+            word_op(minus_two, 1, &ExpressionInfixOpcode::Lesser, uint).unwrap(),
+            0
+        );
+        assert_eq!(
+            word_op(minus_two, 1, &ExpressionInfixOpcode::ShiftR, sint).unwrap(),
+            u32::MAX
+        );
+        assert_eq!(
+            word_op(minus_two, 1, &ExpressionInfixOpcode::ShiftR, uint).unwrap(),
+            i32::MAX as u32
+        );
+        // Shifting out every bit keeps only the sign
+        assert_eq!(
+            word_op(minus_two, 40, &ExpressionInfixOpcode::ShiftR, sint).unwrap(),
+            u32::MAX
+        );
+        assert_eq!(
+            word_op(minus_two, 40, &ExpressionInfixOpcode::ShiftR, uint).unwrap(),
+            0
+        );
+        assert_eq!(
+            word_op(2, 40, &ExpressionInfixOpcode::ShiftR, sint).unwrap(),
+            0
+        );
+        assert_eq!(
+            word_op(minus_two, 2, &ExpressionInfixOpcode::IntDiv, sint).unwrap(),
+            -1i32 as u32
+        );
+        assert!(word_op(
+            i32::MIN as u32,
+            -1i32 as u32,
+            &ExpressionInfixOpcode::Div,
+            sint
+        )
+        .is_err());
+        assert_eq!(
+            word_prefix_op(&ExpressionPrefixOpcode::Sub, 5, uint)
+                .unwrap_err()
+                .to_string(),
+            "Operation error: Subtraction underflow"
+        );
+    }
+
+    #[test]
+    fn test_execute_op_field_division() {
+        let sint = ValueType::Sint;
+        for prime in [BN128, Prime::Goldilocks] {
+            let (seven, two) = (FieldElement::from(7), FieldElement::from(2));
+            let quotient =
+                execute_op(&seven, &two, &ExpressionInfixOpcode::Div, sint, prime).unwrap();
+            assert_eq!(quotient.mul(&two, prime), seven);
+            assert_ne!(quotient, FieldElement::from(3));
+            assert_eq!(
+                execute_op(&seven, &two, &ExpressionInfixOpcode::IntDiv, sint, prime).unwrap(),
+                FieldElement::from(3)
+            );
+        }
+    }
+
+    #[test]
+    fn test_check_gate_budget() {
+        let mut runtime = Runtime::new();
+        runtime.set_gate_budgets(HashMap::from([("Dense".to_string(), 10)]));
+
+        assert!(check_gate_budget(&runtime, "Dense", "dense[0]", 10).is_ok());
+        assert!(check_gate_budget(&runtime, "ReLU", "relu[0]", 100).is_ok());
+        assert_eq!(
+            check_gate_budget(&runtime, "Dense", "dense[1]", 11)
+                .unwrap_err()
+                .to_string(),
+            "Template Dense (instance dense[1]) contributed 11 gates, exceeding its budget of 10"
+        );
+    }
+
+    #[test]
+    fn test_shape() {
+        let value = NestedValue::Value(1);
+        assert_eq!(shape(&value), Vec::<usize>::new());
+
+        let array = NestedValue::Array(vec![
+            NestedValue::Array(vec![NestedValue::Value(1); 3]),
+            NestedValue::Array(vec![NestedValue::Value(2); 3]),
+        ]);
+        assert_eq!(shape(&array), vec![2, 3]);
+    }
+
+    #[test]
+    fn test_collect_connections() {
+        let a = NestedValue::Array(vec![NestedValue::Value(1), NestedValue::Value(2)]);
+        let b = NestedValue::Array(vec![NestedValue::Value(3), NestedValue::Value(4)]);
+        let mut connections = Vec::new();
+        collect_connections(&a, &b, &mut connections).unwrap();
+        assert_eq!(connections, vec![(1, 3), (2, 4)]);
+
+        let c = NestedValue::Array(vec![NestedValue::Value(5)]);
+        let result = collect_connections(&a, &c, &mut connections);
+        assert!(matches!(result, Err(ProgramError::ShapeMismatch { .. })));
+    }
+
     #[test]
     fn test_to_equivalent_infix() {
-        let (value, opcode) = to_equivalent_infix(&ExpressionPrefixOpcode::Sub);
-        assert_eq!(value, 0);
+        let uint = ValueType::Uint;
+        let (value, opcode) = to_equivalent_infix(&ExpressionPrefixOpcode::Sub, uint, BN128);
+        assert_eq!(value, FieldElement::from(0));
         assert!(matches!(opcode, ExpressionInfixOpcode::Sub));
 
-        let (value, opcode) = to_equivalent_infix(&ExpressionPrefixOpcode::BoolNot);
-        assert_eq!(value, 0);
+        let (value, opcode) = to_equivalent_infix(&ExpressionPrefixOpcode::BoolNot, uint, BN128);
+        assert_eq!(value, FieldElement::from(0));
         assert!(matches!(opcode, ExpressionInfixOpcode::Eq));
 
-        let (value, opcode) = to_equivalent_infix(&ExpressionPrefixOpcode::Complement);
-        assert_eq!(value, u32::MAX);
+        let (value, opcode) = to_equivalent_infix(&ExpressionPrefixOpcode::Complement, uint, BN128);
+        assert_eq!(value, FieldElement::from(u32::MAX));
         assert!(matches!(opcode, ExpressionInfixOpcode::BitXor));
+
+        let (value, _) =
+            to_equivalent_infix(&ExpressionPrefixOpcode::Complement, ValueType::Sint, BN128);
+        assert_eq!(value.to_word(ValueType::Sint, BN128), Some(u32::MAX));
+    }
+
+    #[test]
+    fn test_execute_op_big_values() {
+        let sint = ValueType::Sint;
+        let parse = |value: &str| FieldElement::from_bigint(&value.parse().unwrap(), BN128);
+        let p_minus_one =
+            parse("21888242871839275222246405745257275088548364400416034343698204186575808495616");
+
+        // p - 1 is -1 in the field
+        let sum = execute_op(
+            &p_minus_one,
+            &FieldElement::from(3),
+            &ExpressionInfixOpcode::Add,
+            sint,
+            BN128,
+        )
+        .unwrap();
+        assert_eq!(sum, FieldElement::from(2));
+
+        // 10**18 doesn't fit in 32 bits, but dividing it back down does
+        let scale = execute_op(
+            &FieldElement::from(10),
+            &FieldElement::from(18),
+            &ExpressionInfixOpcode::Pow,
+            sint,
+            BN128,
+        )
+        .unwrap();
+        assert_eq!(scale, parse("1000000000000000000"));
+        assert_eq!(scale.to_word(sint, BN128), None);
+        let value = execute_op(
+            &scale.mul(&FieldElement::from(7), BN128),
+            &scale,
+            &ExpressionInfixOpcode::IntDiv,
+            sint,
+            BN128,
+        )
+        .unwrap();
+        assert_eq!(value.to_word(sint, BN128), Some(7));
+        let negative = execute_op(
+            &p_minus_one.mul(&scale, BN128),
+            &FieldElement::from(1000),
+            &ExpressionInfixOpcode::IntDiv,
+            sint,
+            BN128,
+        )
+        .unwrap();
+        assert_eq!(negative, parse("-1000000000000000"));
+    }
+
+    #[test]
+    fn test_execute_op_primes() {
+        let sint = ValueType::Sint;
+        let goldilocks = Prime::Goldilocks;
+        let two_to_the_64 = |prime| {
+            execute_op(
+                &FieldElement::from(2),
+                &FieldElement::from(64),
+                &ExpressionInfixOpcode::Pow,
+                sint,
+                prime,
+            )
+            .unwrap()
+        };
+
+        // 2**64 wraps around in the Goldilocks field, but not in the BN254 field
+        assert_eq!(two_to_the_64(goldilocks), FieldElement::from(u32::MAX));
+        assert_eq!(two_to_the_64(BN128).to_string(), "18446744073709551616");
+
+        // p - 1 is negative in its own field only
+        let p_minus_one = FieldElement::from_integer(&18446744069414584320u64.into(), false, BN128);
+        let lesser = |prime| {
+            execute_op(
+                &p_minus_one,
+                &FieldElement::from(0),
+                &ExpressionInfixOpcode::Lesser,
+                sint,
+                prime,
+            )
+            .unwrap()
+        };
+        assert_eq!(lesser(goldilocks), FieldElement::from(1));
+        assert_eq!(lesser(BN128), FieldElement::from(0));
     }
 }
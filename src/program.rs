@@ -3,32 +3,568 @@
 //! This module processes the circom input program to build the arithmetic circuit.
 
 use crate::{
+    a_gate_type::AGateType,
+    artifacts::{to_canonical_json, ArtifactManifest, ARTIFACTS_VERSION},
+    audit::AuditLog,
+    bristol_reader::BristolReadError,
+    card::CircuitCard,
     circom::{parser::parse_project, type_analysis::analyse_project},
-    cli::Args,
-    compiler::{CircuitError, Compiler},
-    process::{process_expression, process_statements},
-    runtime::{DataAccess, DataType, Runtime, RuntimeError},
+    cli::{build_output, Args, InstanceNaming, OutputFormat, Target},
+    compiler::{CircuitError, CircuitLimits, CircuitReport, Compiler, InstanceReport},
+    conversions::{plan_conversions, ConversionConfig},
+    deps::DependencyGraph,
+    diagnostics::Diagnostic,
+    estimate::{estimate_emission, EmissionEstimate},
+    evaluate::EvaluateError,
+    events::EventSink,
+    field::FieldElement,
+    fixed_point::FixedPointError,
+    io_names::NameError,
+    json_circuit::JsonCircuit,
+    logging::{EMIT, GRAPH, PROCESS},
+    metrics::write_metrics,
+    passes::{PassContext, PassRegistry},
+    prebuild::PrebuiltInstances,
+    process::{
+        check_gate_budget, join_instances, process_expression, process_statements, strip_parallel,
+        template_param, InstanceWorkers,
+    },
+    provenance::{boolify_provenance, WireProvenance},
+    r1cs::R1cs,
+    runtime::{index_context_namer, DataAccess, DataType, Runtime, RuntimeError, StopAfter},
+    simd::find_subcircuit_groups,
+    wire_types::{infer_circuit_wire_types, WireType},
+};
+use boolify::boolify;
+use bristol_circuit::{BristolCircuit, BristolCircuitError, CircuitInfo};
+use circom_program_structure::{ast::Expression, program_archive::ProgramArchive};
+use log::{debug, error, info};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error as _,
+    fmt,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
 };
-use bristol_circuit::BristolCircuitError;
-use circom_program_structure::ast::Expression;
-use std::io;
 use thiserror::Error;
 
+/// Paths and summary statistics of the artifacts written by `compile_and_write`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Artifacts {
+    /// Bristol circuit, `circuit.txt`, or constraint system, `circuit.r1cs`, depending on
+    /// `Args::format`.
+    pub circuit: PathBuf,
+    /// Input, output and constant wires of the Bristol circuit, `circuit_info.json`.
+    pub circuit_info: PathBuf,
+    /// Input and output wires of the constraint system, `circuit.sym`, only written for the
+    /// R1CS format.
+    pub sym: Option<PathBuf>,
+    /// Compilation report, `report.json`.
+    pub report: PathBuf,
+    /// Markdown summary of the circuit for audits and deployment documents, `CIRCUIT_CARD.md`.
+    pub card: PathBuf,
+    /// Parsed files and their includes, `deps.json`.
+    pub deps: PathBuf,
+    /// Version and files of the artifacts, `manifest.json`, see `Artifacts::load`.
+    pub manifest: PathBuf,
+    /// Prometheus metrics, only written if `Args::metrics_file` is set.
+    pub metrics: Option<PathBuf>,
+    /// Compilation events, only written if `Args::events_file` is set.
+    pub events: Option<PathBuf>,
+    /// Gates and connections with their source statements, only appended to if
+    /// `Args::audit_log` is set.
+    pub audit_log: Option<PathBuf>,
+    /// SHA-256 hash, in hex, of the entries the compilation appended to the audit log.
+    pub audit_hash: Option<String>,
+    /// Type of each wire, `wire_types.json`, only written if `Args::wire_types` is set.
+    pub wire_types: Option<PathBuf>,
+    /// Input names of the circuit with null values, `inputs.json`, only written if
+    /// `Args::inputs_template` is set.
+    pub inputs_template: Option<PathBuf>,
+    /// Subcircuits repeated in the circuit, `simd_groups.json`, only written if
+    /// `Args::simd_groups` is set.
+    pub simd_groups: Option<PathBuf>,
+    /// Circom signals carried by each wire of the arithmetic circuit, `signals.sym`, only written
+    /// if `Args::signal_map` is set.
+    pub signal_map: Option<PathBuf>,
+    /// Gate graph of the compiler, `circuit.dot`, only written if `Args::emit_graphviz` is set.
+    /// Written before the circuit is built, so it is there to debug build errors.
+    pub graphviz: Option<PathBuf>,
+    /// Bit wires of each arithmetic wire, `provenance.json`, only written if
+    /// `Args::boolify_width` is set.
+    pub provenance: Option<PathBuf>,
+    /// A2B and B2A conversions of the wires needed in both forms, `conversions.json`, only
+    /// written if `Args::conversions` is set.
+    pub conversions: Option<PathBuf>,
+    /// `log` calls of the program with the wires of the logged signals, `log.json`, only written
+    /// if the program makes any.
+    pub log: Option<PathBuf>,
+    pub gate_count: usize,
+    pub wire_count: usize,
+    pub compile_time: Duration,
+}
+
+/// Runs the full pipeline: compiles the program, builds the circuit (boolified if
+/// `Args::boolify_width` is set) and writes the artifacts to the output directory.
+pub fn compile_and_write(args: &Args) -> Result<Artifacts, ProgramError> {
+    let start = Instant::now();
+    let mut events = EventSink::create(args.events_file.as_deref())?;
+
+    events.phase_started("parse")?;
+    let program_archive = parse_project(args)?;
+    let deps = DependencyGraph::from_file_library(&program_archive.file_library);
+    events.phase_finished("parse")?;
+
+    write_archive(args, program_archive, deps, start, events)
+}
+
+/// Same as `compile_and_write`, for a program parsed beforehand along with the dependency graph of
+/// its files, e.g. by `package::compile_package`.
+pub(crate) fn compile_and_write_archive(
+    args: &Args,
+    program_archive: ProgramArchive,
+    deps: DependencyGraph,
+) -> Result<Artifacts, ProgramError> {
+    let start = Instant::now();
+    let events = EventSink::create(args.events_file.as_deref())?;
+    write_archive(args, program_archive, deps, start, events)
+}
+
+/// Compiles a parsed program, builds its circuit and writes the artifacts, see
+/// `compile_and_write`.
+fn write_archive(
+    args: &Args,
+    program_archive: ProgramArchive,
+    deps: DependencyGraph,
+    start: Instant,
+    mut events: EventSink,
+) -> Result<Artifacts, ProgramError> {
+    let template = main_template_name(&program_archive);
+    let output_dir = args.output.clone();
+    fs::create_dir_all(output_dir.clone())
+        .map_err(|_| ProgramError::OutputDirectoryCreationError)?;
+
+    let compiled = compile_archive(
+        args,
+        runtime_for(args)?,
+        &PassRegistry::default(),
+        program_archive,
+        &mut events,
+    );
+
+    let mut compiler = match compiled {
+        Err(ProgramError::Diagnostics(diagnostics)) => {
+            let path = build_output(&output_dir, "diagnostics", "json");
+            File::create(&path)?.write_all(to_canonical_json(&diagnostics)?.as_bytes())?;
+            debug!(target: EMIT, "Wrote {}", path.display());
+            return Err(ProgramError::Diagnostics(diagnostics));
+        }
+        compiled => compiled?,
+    };
+    let audit_hash = compiler.finish_audit_log()?;
+    let graphviz_path = if args.emit_graphviz {
+        let path = build_output(&output_dir, "circuit", "dot");
+        let mut writer = BufWriter::new(File::create(&path)?);
+        compiler.write_dot(&mut writer)?;
+        writer.flush()?;
+        debug!(target: EMIT, "Wrote {}", path.display());
+        Some(path)
+    } else {
+        None
+    };
+    let mut report = compiler.generate_circuit_report()?;
+    report.set_metadata(args.metadata());
+    let inputs_template = if args.inputs_template {
+        Some(compiler.generate_input_template()?)
+    } else {
+        None
+    };
+
+    events.phase_started("build")?;
+    let (mut circuit, node_wires) = compiler.build_circuit_wires()?;
+    let signal_map = args.signal_map.then(|| compiler.signal_map(&node_wires));
+    let log_trace = compiler.log_trace(&node_wires);
+    let mut wire_tags = compiler.wire_tags(&node_wires);
+    let mut wire_types = args
+        .wire_types
+        .then(|| infer_circuit_wire_types(&circuit, args.value_type));
+    let conversions = match &args.conversions {
+        Some(_) if args.boolify_width.is_some() => {
+            return Err(ProgramError::InvalidConversions(String::from(
+                "boolean circuits have no arithmetic wires to convert",
+            )));
+        }
+        Some(path) => {
+            let config = ConversionConfig::from_path(path)?;
+            let plan = plan_conversions(&circuit, &compiler.signal_map(&node_wires), &config)?;
+            report.add_gate_counts(plan.counts());
+            Some(plan)
+        }
+        None => None,
+    };
+    events.phase_finished("build")?;
+
+    let mut provenance: Option<Vec<WireProvenance>> = None;
+    if let Some(boolify_width) = args.boolify_width {
+        events.phase_started("boolify")?;
+        let boolean_circuit = boolify_circuit(&circuit, boolify_width, &report, &mut events)?;
+        provenance = Some(boolify_provenance(
+            &circuit,
+            &boolean_circuit,
+            boolify_width,
+        ));
+        circuit = boolean_circuit;
+        // Tags apply to the arithmetic wires, not to their bits
+        wire_tags.clear();
+        // Every wire of a boolean circuit is a bit
+        if let Some(wire_types) = &mut wire_types {
+            *wire_types = vec![WireType::Bool; circuit.wire_count];
+        }
+        events.phase_finished("boolify")?;
+    }
+
+    CircuitLimits {
+        max_wires: args.max_wires,
+        max_gates: args.max_gates,
+    }
+    .check(&circuit)?;
+
+    let compile_time = start.elapsed();
+    events.count("circuit_gates", circuit.gates.len() as u64)?;
+    events.count("circuit_wires", circuit.wire_count as u64)?;
+
+    events.phase_started("write")?;
+
+    let (circuit_path, sym_path) = match args.format {
+        OutputFormat::Bristol => {
+            let path = build_output(&output_dir, "circuit", "txt");
+            circuit.write_bristol(&mut File::create(&path)?)?;
+            (path, None)
+        }
+        OutputFormat::R1cs => {
+            let r1cs = R1cs::from_circuit(&circuit, args.value_type, args.prime)?;
+            let path = build_output(&output_dir, "circuit", "r1cs");
+            r1cs.write(&mut File::create(&path)?)?;
+            let sym_path = build_output(&output_dir, "circuit", "sym");
+            let mut sym = BufWriter::new(File::create(&sym_path)?);
+            r1cs.write_sym(&mut sym)?;
+            sym.flush()?;
+            debug!(target: EMIT, "Wrote {}", sym_path.display());
+            (path, Some(sym_path))
+        }
+        OutputFormat::Json => {
+            let path = build_output(&output_dir, "circuit", "json");
+            JsonCircuit::from_circuit(&circuit).write(&mut File::create(&path)?)?;
+            (path, None)
+        }
+    };
+    debug!(target: EMIT, "Wrote {}", circuit_path.display());
+
+    let circuit_info_path = build_output(&output_dir, "circuit_info", "json");
+    File::create(&circuit_info_path)?
+        .write_all(circuit_info_json(&circuit.info, &wire_tags, args)?.as_bytes())?;
+    debug!(target: EMIT, "Wrote {}", circuit_info_path.display());
+
+    let report_path = build_output(&output_dir, "report", "json");
+    File::create(&report_path)?.write_all(to_canonical_json(&report)?.as_bytes())?;
+    debug!(target: EMIT, "Wrote {}", report_path.display());
+
+    let card_path = build_output(&output_dir, "CIRCUIT_CARD", "md");
+    let mut card = CircuitCard::new(&template, &circuit, &report, args);
+    if let Some(audit_hash) = &audit_hash {
+        card.config
+            .push((String::from("Audit log"), audit_hash.clone()));
+    }
+    card.write_markdown(&mut File::create(&card_path)?)?;
+    debug!(target: EMIT, "Wrote {}", card_path.display());
+
+    let deps_path = build_output(&output_dir, "deps", "json");
+    File::create(&deps_path)?.write_all(to_canonical_json(&deps)?.as_bytes())?;
+    debug!(target: EMIT, "Wrote {}", deps_path.display());
+
+    let wire_types_path = match &wire_types {
+        Some(wire_types) => {
+            let path = build_output(&output_dir, "wire_types", "json");
+            File::create(&path)?.write_all(to_canonical_json(wire_types)?.as_bytes())?;
+            debug!(target: EMIT, "Wrote {}", path.display());
+            Some(path)
+        }
+        None => None,
+    };
+
+    let inputs_template_path = match &inputs_template {
+        Some(inputs_template) => {
+            let path = build_output(&output_dir, "inputs", "json");
+            File::create(&path)?.write_all(to_canonical_json(inputs_template)?.as_bytes())?;
+            debug!(target: EMIT, "Wrote {}", path.display());
+            Some(path)
+        }
+        None => None,
+    };
+
+    let simd_groups_path = if args.simd_groups {
+        let path = build_output(&output_dir, "simd_groups", "json");
+        let groups = find_subcircuit_groups(&circuit);
+        File::create(&path)?.write_all(to_canonical_json(&groups)?.as_bytes())?;
+        debug!(target: EMIT, "Wrote {}", path.display());
+        Some(path)
+    } else {
+        None
+    };
+
+    let signal_map_path = match &signal_map {
+        Some(signal_map) => {
+            let path = build_output(&output_dir, "signals", "sym");
+            let mut sym = BufWriter::new(File::create(&path)?);
+            signal_map.write(&mut sym)?;
+            sym.flush()?;
+            debug!(target: EMIT, "Wrote {}", path.display());
+            Some(path)
+        }
+        None => None,
+    };
+
+    let provenance_path = match &provenance {
+        Some(provenance) => {
+            let path = build_output(&output_dir, "provenance", "json");
+            File::create(&path)?.write_all(to_canonical_json(provenance)?.as_bytes())?;
+            debug!(target: EMIT, "Wrote {}", path.display());
+            Some(path)
+        }
+        None => None,
+    };
+
+    let conversions_path = match &conversions {
+        Some(conversions) => {
+            let path = build_output(&output_dir, "conversions", "json");
+            File::create(&path)?.write_all(to_canonical_json(conversions)?.as_bytes())?;
+            debug!(target: EMIT, "Wrote {}", path.display());
+            Some(path)
+        }
+        None => None,
+    };
+
+    let log_path = if log_trace.is_empty() {
+        None
+    } else {
+        let path = build_output(&output_dir, "log", "json");
+        File::create(&path)?.write_all(to_canonical_json(&log_trace)?.as_bytes())?;
+        debug!(target: EMIT, "Wrote {}", path.display());
+        Some(path)
+    };
+
+    if let Some(metrics_file) = &args.metrics_file {
+        write_metrics(&mut File::create(metrics_file)?, &circuit, compile_time)?;
+        debug!(target: EMIT, "Wrote {}", metrics_file.display());
+    }
+    let artifacts = Artifacts {
+        circuit: circuit_path,
+        circuit_info: circuit_info_path,
+        sym: sym_path,
+        report: report_path,
+        card: card_path,
+        deps: deps_path,
+        manifest: build_output(&output_dir, "manifest", "json"),
+        metrics: args.metrics_file.clone(),
+        events: args.events_file.clone(),
+        audit_log: args.audit_log.clone(),
+        audit_hash,
+        wire_types: wire_types_path,
+        inputs_template: inputs_template_path,
+        simd_groups: simd_groups_path,
+        signal_map: signal_map_path,
+        graphviz: graphviz_path,
+        provenance: provenance_path,
+        conversions: conversions_path,
+        log: log_path,
+        gate_count: circuit.gates.len(),
+        wire_count: circuit.wire_count,
+        compile_time,
+    };
+
+    let mut manifest = ArtifactManifest::new(&artifacts, args.format, &output_dir);
+    manifest.metadata = args.metadata();
+    File::create(&artifacts.manifest)?.write_all(to_canonical_json(&manifest)?.as_bytes())?;
+    debug!(target: EMIT, "Wrote {}", artifacts.manifest.display());
+    events.phase_finished("write")?;
+
+    Ok(artifacts)
+}
+
+/// Serializes the IO wires of a circuit for `circuit_info.json`, along with the prime its constants
+/// were computed in, the engine it was compiled for and the signal tags of its wires, if any.
+pub(crate) fn circuit_info_json(
+    info: &CircuitInfo,
+    tags: &BTreeMap<usize, Vec<String>>,
+    args: &Args,
+) -> Result<String, serde_json::Error> {
+    let mut json = serde_json::to_value(info)?;
+    if let Value::Object(fields) = &mut json {
+        fields.insert(String::from("prime"), serde_json::to_value(args.prime)?);
+        fields.insert(String::from("target"), serde_json::to_value(args.target)?);
+        if !tags.is_empty() {
+            fields.insert(String::from("tags"), serde_json::to_value(tags)?);
+        }
+    }
+    to_canonical_json(&json)
+}
+
+/// Returns the name of the template instantiated by the main component.
+fn main_template_name(program_archive: &ProgramArchive) -> String {
+    match strip_parallel(program_archive.get_main_expression()).0 {
+        Expression::Call { id, .. } => id.clone(),
+        _ => String::from("main"),
+    }
+}
+
 /// Parses a given Circom program and constructs an arithmetic circuit from it.
 pub fn compile(args: &Args) -> Result<Compiler, ProgramError> {
-    let mut compiler = Compiler::new();
+    compile_with_runtime(args, runtime_for(args)?)
+}
+
+/// Creates a runtime configured by the given arguments.
+pub(crate) fn runtime_for(args: &Args) -> Result<Runtime, ProgramError> {
     let mut runtime = Runtime::new();
-    let mut program_archive = parse_project(args)?;
+    if args.instance_naming == InstanceNaming::Index {
+        runtime.set_context_namer(Arc::new(index_context_namer));
+    }
+    if let Some(max_steps) = args.max_steps {
+        runtime.set_max_steps(max_steps);
+    }
+    if let Some(gate_budgets) = &args.gate_budgets {
+        let gate_budgets = fs::read_to_string(gate_budgets)?;
+        runtime.set_gate_budgets(serde_json::from_str(&gate_budgets)?);
+    }
+    if let Some(stop_after) = &args.stop_after {
+        runtime.set_stop_after(StopAfter::parse(stop_after));
+    }
+    runtime.set_recover(args.recover);
+    runtime.set_constraint_equality(args.constraint_equality);
+    if let Some(prebuilt) = &args.prebuilt {
+        runtime.set_prebuilt(Arc::new(PrebuiltInstances::load(prebuilt, args)?));
+    }
+
+    Ok(runtime)
+}
+
+/// Same as `compile`, but runs the program in the given runtime, e.g. one with a custom context
+/// namer set through `Runtime::set_context_namer`. The step limit, gate budgets, stop point,
+/// recovery mode, constraint equality handling and prebuilt archives of `args` are not applied to
+/// the given runtime.
+pub fn compile_with_runtime(args: &Args, runtime: Runtime) -> Result<Compiler, ProgramError> {
+    compile_with_passes(args, runtime, &PassRegistry::default())
+}
+
+/// Same as `compile_with_runtime`, but transforms the processed graph with the given passes
+/// instead of the built-in ones, e.g. a `PassRegistry::default()` extended with custom passes.
+pub fn compile_with_passes(
+    args: &Args,
+    runtime: Runtime,
+    passes: &PassRegistry,
+) -> Result<Compiler, ProgramError> {
+    let mut events = EventSink::create(args.events_file.as_deref())?;
+
+    events.phase_started("parse")?;
+    let program_archive = parse_project(args)?;
+    events.phase_finished("parse")?;
+
+    compile_archive(args, runtime, passes, program_archive, &mut events)
+}
+
+/// Same as `compile`, but in recovery mode: returns the compiled program, if it compiled, along
+/// with every error and warning of the compilation instead of failing on the first error.
+pub fn compile_with_diagnostics(args: &Args) -> (Option<Compiler>, Vec<Diagnostic>) {
+    let mut events = match EventSink::create(args.events_file.as_deref()) {
+        Ok(events) => events,
+        Err(error) => return (None, Diagnostic::from_errors(&error.into())),
+    };
+
+    let compiled = runtime_for(args).and_then(|mut runtime| {
+        runtime.set_recover(true);
+        events.phase_started("parse")?;
+        let program_archive = parse_project(args)?;
+        events.phase_finished("parse")?;
+        compile_archive(
+            args,
+            runtime,
+            &PassRegistry::default(),
+            program_archive,
+            &mut events,
+        )
+    });
+
+    let mut diagnostics = match &compiled {
+        Ok(_) => Vec::new(),
+        Err(error) => Diagnostic::from_errors(error),
+    };
+    diagnostics.extend_from_slice(events.warnings());
+    (compiled.ok(), diagnostics)
+}
+
+/// Compiles the program and predicts the gate and wire counts of its circuit and the sizes of the
+/// circuit files, without building the circuit or writing anything, see `estimate_emission`. The
+/// passes are run, so the estimate is of the optimized circuit, but before `Args::boolify_width`
+/// is applied.
+pub fn estimate(args: &Args) -> Result<EmissionEstimate, ProgramError> {
+    let compiler = compile(args)?;
+    Ok(estimate_emission(&compiler, args)?)
+}
+
+/// Analyses and runs an already parsed program, recording its phases in `events`.
+pub(crate) fn compile_archive(
+    args: &Args,
+    mut runtime: Runtime,
+    passes: &PassRegistry,
+    mut program_archive: ProgramArchive,
+    events: &mut EventSink,
+) -> Result<Compiler, ProgramError> {
+    let mut compiler = Compiler::new();
+    // The value type and the prime drive the compile-time evaluation, so they are set before processing
+    compiler.update_type(args.value_type)?;
+    compiler.set_prime(args.prime);
+    compiler.set_target(args.target);
+    compiler.set_signed_gates(args.signed_gates);
+    compiler.set_field_constants(args.format == OutputFormat::R1cs);
+    if let Some(audit_log) = &args.audit_log {
+        compiler.set_audit_log(AuditLog::open(audit_log)?);
+    }
 
+    events.phase_started("analyse")?;
     analyse_project(&mut program_archive)?;
+    events.phase_finished("analyse")?;
+    let program_archive = Arc::new(program_archive);
+
+    // The stop point, the recovered errors and the audit log follow the order of processing
+    if let Some(workers) = args.instance_workers {
+        if args.stop_after.is_some() || runtime.is_recovering() || compiler.is_auditing() {
+            events.warn(
+                PROCESS,
+                "Ignoring --instance-workers, components are processed one after the other with --stop-after, --recover or --audit-log",
+            )?;
+        } else {
+            runtime
+                .set_instance_workers(InstanceWorkers::new(Arc::clone(&program_archive), workers));
+        }
+    }
 
-    match program_archive.get_main_expression() {
-        Expression::Call { id, args, .. } => {
+    let (main, parallel) = strip_parallel(program_archive.get_main_expression());
+    match main {
+        Expression::Call {
+            id,
+            args: call_args,
+            ..
+        } => {
             let template_data = program_archive.get_template_data(id);
 
+            events.phase_started("process")?;
+
             // Get values
-            let mut values: Vec<Option<u32>> = Vec::new();
-            for expression in args {
+            let mut values: Vec<Option<FieldElement>> = Vec::new();
+            for expression in call_args {
                 let access =
                     process_expression(&mut compiler, &mut runtime, &program_archive, expression)?;
                 let value = runtime.current_context()?.get_variable_value(&access)?;
@@ -37,7 +573,22 @@ pub fn compile(args: &Args) -> Result<Compiler, ProgramError> {
 
             // Get and declare arguments
             let names = template_data.get_name_of_params();
-            for (name, &value) in names.iter().zip(values.iter()) {
+            compiler.add_instance(InstanceReport {
+                instance: String::from("0"),
+                template: id.clone(),
+                component: None,
+                params: names
+                    .iter()
+                    .zip(&values)
+                    .filter_map(|(name, value)| {
+                        value
+                            .as_ref()
+                            .map(|value| template_param(name, value, compiler.prime()))
+                    })
+                    .collect(),
+                parallel: parallel || template_data.is_parallel(),
+            });
+            for (name, value) in names.iter().zip(values) {
                 let signal_gen = runtime.get_signal_gen();
                 runtime.current_context()?.declare_item(
                     DataType::Variable,
@@ -52,27 +603,204 @@ pub fn compile(args: &Args) -> Result<Compiler, ProgramError> {
 
             // Process the main component
             let statements = template_data.get_body_as_vec();
-            process_statements(&mut compiler, &mut runtime, &program_archive, statements)?;
+            compiler.enter_template(id);
+            if let Err(error) =
+                process_statements(&mut compiler, &mut runtime, &program_archive, statements)
+            {
+                match error.root() {
+                    ProgramError::RuntimeError(RuntimeError::Stopped(instance)) => events.warn(
+                        PROCESS,
+                        &format!(
+                            "Processing stopped after instance {}, the circuit is partial",
+                            instance
+                        ),
+                    )?,
+                    _ => return Err(error),
+                }
+            }
+            join_instances(&mut compiler, &mut runtime)?;
+            check_gate_budget(&runtime, id, "0", compiler.gates().len())?;
+            if !compiler.log_entries().is_empty() {
+                events.warn(
+                    PROCESS,
+                    &format!(
+                        "{} log calls were processed, their strings are treated as no-ops and don't affect the circuit",
+                        compiler.log_entries().len()
+                    ),
+                )?;
+            }
+            events.phase_finished("process")?;
 
-            for (ikey, (_ivs, _ivh)) in template_data.get_inputs().iter() {
-                let filter = format!("0.{}", ikey);
-                compiler.add_inputs(compiler.get_signals(filter));
+            let diagnostics = runtime.diagnostics();
+            if !diagnostics.is_empty() {
+                for diagnostic in diagnostics {
+                    error!(
+                        target: PROCESS,
+                        "{} (in {})", diagnostic.message, diagnostic.context
+                    );
+                }
+                return Err(ProgramError::Diagnostics(diagnostics.to_vec()));
             }
 
-            for (okey, (_ovs, _ovh)) in template_data.get_outputs().iter() {
-                let filter = format!("0.{}", okey);
-                let signals = compiler.get_signals(filter);
+            events.phase_started("graph")?;
+            let stats = compiler.resolve_aliases()?;
+            info!(
+                target: GRAPH,
+                "Resolved {} aliases, collapsing {} alias chains",
+                stats.aliases, stats.chains_collapsed
+            );
+
+            let stats = compiler.compact_signals()?;
+            info!(
+                target: GRAPH,
+                "Compacted {} signals from an id space of {}",
+                stats.signals,
+                stats.id_span
+            );
+
+            if args.self_check {
+                compiler.debug_assert_valid()?;
+            }
+
+            // Public inputs first, then private ones, each in declaration order
+            let public_inputs = program_archive.get_public_inputs_main_component();
+            let (public, private): (Vec<_>, Vec<_>) = template_data
+                .get_declaration_inputs()
+                .iter()
+                .partition(|(name, _)| public_inputs.contains(name));
+            for (ikey, _) in public.into_iter().chain(private) {
+                let name = format!("0.{}", ikey);
+                compiler.add_inputs(compiler.get_item_signals(&name));
+            }
+
+            for (okey, _) in template_data.get_declaration_outputs() {
+                let name = format!("0.{}", okey);
+                let signals = compiler.get_item_signals(&name);
                 compiler.add_outputs(signals);
             }
+            compiler.add_constraint_outputs(runtime.constraint_equality())?;
         }
         _ => return Err(ProgramError::MainExpressionNotACall),
     }
 
-    compiler.update_type(args.value_type)?;
+    let steps = runtime.step_stats();
+    info!(
+        target: PROCESS,
+        "Evaluated {} statements and {} expressions at compile time",
+        steps.statements, steps.expressions
+    );
+    events.count("statements", steps.statements)?;
+    events.count("expressions", steps.expressions)?;
+    events.count("gates", compiler.gates().len() as u64)?;
+
+    let timings = passes.run(&mut compiler, &mut PassContext { args, events })?;
+    compiler.set_pass_timings(timings);
+
+    compiler.set_naming(args.naming);
+    compiler.set_gate_order(args.gate_order);
+
+    if let Some(party_mapping) = &args.party_mapping {
+        let party_mapping = fs::read_to_string(party_mapping)?;
+        compiler.set_party_mapping(serde_json::from_str(&party_mapping)?);
+    }
+
+    if args.self_check {
+        compiler.debug_assert_valid()?;
+    }
+    events.phase_finished("graph")?;
 
     Ok(compiler)
 }
 
+/// Converts a circuit to a boolean circuit operating on integers of the given width, warning about
+/// the values and operations whose semantics are not preserved. Fails on signed gates, see
+/// `Args::signed_gates`.
+pub(crate) fn boolify_circuit(
+    circuit: &BristolCircuit,
+    width: usize,
+    report: &CircuitReport,
+    events: &mut EventSink,
+) -> Result<BristolCircuit, ProgramError> {
+    // Boolify only has unsigned gates, lowering signed ones would change their results
+    let mut signed_ops = circuit
+        .gates
+        .iter()
+        .filter_map(|gate| AGateType::parse_bristol_op(&gate.op))
+        .filter(|(op, _)| op.is_signed())
+        .map(|(op, _)| op.to_string())
+        .collect::<Vec<String>>();
+    if !signed_ops.is_empty() {
+        signed_ops.sort();
+        signed_ops.dedup();
+        return Err(ProgramError::UnsupportedByBoolify(signed_ops));
+    }
+
+    if report.value_type().is_signed() {
+        events.warn(
+            EMIT,
+            &format!(
+                "Boolify doesn't take the value type into account, comparisons, divisions and right shifts of negative {:?} values may not match the compile-time semantics",
+                report.value_type()
+            ),
+        )?;
+    }
+    if let Some(range) = report
+        .constant_range()
+        .filter(|range| range.width() as usize > width)
+    {
+        events.warn(
+            EMIT,
+            &format!(
+                "Boolify width {} truncates constants, the largest has a magnitude of {} and needs {} bits",
+                width,
+                range.max_magnitude(),
+                range.width()
+            ),
+        )?;
+    }
+
+    Ok(boolify(circuit, width))
+}
+
+/// Flattens a JSON object of numbers and nested arrays of numbers into the values of each signal,
+/// e.g. `{"0.w": [1, 2]}` into `0.w[0] = 1` and `0.w[1] = 2`. Negative numbers are stored in two's
+/// complement.
+pub(crate) fn flatten_values(json: &Value) -> Result<HashMap<String, u32>, ProgramError> {
+    fn flatten(
+        name: String,
+        value: &Value,
+        values: &mut HashMap<String, u32>,
+    ) -> Result<(), ProgramError> {
+        match value {
+            Value::Array(elements) => {
+                for (index, element) in elements.iter().enumerate() {
+                    flatten(format!("{}[{}]", name, index), element, values)?;
+                }
+            }
+            Value::Number(number) => {
+                let value = number
+                    .as_i64()
+                    .filter(|&n| n >= i32::MIN as i64 && n <= u32::MAX as i64)
+                    .ok_or_else(|| ProgramError::InvalidValue(name.clone()))?;
+                values.insert(name, value as u32);
+            }
+            _ => return Err(ProgramError::InvalidValue(name)),
+        }
+
+        Ok(())
+    }
+
+    let object = json
+        .as_object()
+        .ok_or_else(|| ProgramError::InvalidValue(String::from("<root>")))?;
+    let mut values = HashMap::new();
+    for (name, value) in object {
+        flatten(name.clone(), value, &mut values)?;
+    }
+
+    Ok(values)
+}
+
 /// Program errors
 #[derive(Error, Debug)]
 pub enum ProgramError {
@@ -81,15 +809,43 @@ pub enum ProgramError {
     #[error("Call error")]
     CallError,
     #[error("Circuit error: {0}")]
-    CircuitError(CircuitError),
+    CircuitError(#[source] CircuitError),
+    #[error("Compilation failed with {} errors", .0.len())]
+    Diagnostics(Vec<Diagnostic>),
     #[error("Empty data item")]
     EmptyDataItem,
     #[error("Expression not implemented")]
     ExpressionNotImplemented,
+    #[error("{} already exists", .0.display())]
+    FileAlreadyExists(PathBuf),
+    #[error("Template {template} (instance {instance}) contributed {gates} gates, exceeding its budget of {budget}")]
+    GateBudgetExceeded {
+        template: String,
+        instance: String,
+        gates: usize,
+        budget: usize,
+    },
     #[error("Input initialization error")]
     InputInitializationError,
+    #[error("{source}")]
+    InStatement {
+        location: SourceLocation,
+        source: Box<ProgramError>,
+    },
+    #[error("Invalid anonymous component {template}: {reason}")]
+    InvalidAnonymousComponent { template: String, reason: String },
+    #[error("Invalid conversions: {0}")]
+    InvalidConversions(String),
     #[error("Invalid data type")]
     InvalidDataType,
+    #[error("Invalid noise insertion point: {0}")]
+    InvalidNoise(String),
+    #[error("Invalid prebuilt instance of {0}: its signals don't match the declarations")]
+    InvalidPrebuiltInstance(String),
+    #[error("Invalid stage: {0}")]
+    InvalidStage(String),
+    #[error("Invalid value {0}: expected a 32-bit integer or an array")]
+    InvalidValue(String),
     #[error("IO error: {0}")]
     IOError(#[from] io::Error),
     #[error("JSON serialization error: {0}")]
@@ -105,13 +861,305 @@ pub enum ProgramError {
     #[error("Parsing error")]
     ParsingError,
     #[error("Runtime error: {0}")]
-    RuntimeError(RuntimeError),
+    RuntimeError(#[source] RuntimeError),
+    #[error("Shape mismatch: expected {expected:?}, found {found:?}")]
+    ShapeMismatch {
+        expected: Vec<usize>,
+        found: Vec<usize>,
+    },
     #[error("Statement not implemented")]
     StatementNotImplemented,
     #[error("Signal substitution not implemented")]
     SignalSubstitutionNotImplemented,
+    #[error("Value {value} is out of range for {target}")]
+    ValueOutOfRange { value: String, target: String },
+    #[error("Target {target:?} doesn't support {} gates", .ops.join(", "))]
+    UnsupportedByTarget { target: Target, ops: Vec<String> },
+    #[error("Boolify doesn't support {} gates", .0.join(", "))]
+    UnsupportedByBoolify(Vec<String>),
+    #[error("Tuple of {found} values assigned to {expected} targets")]
+    TupleLengthMismatch { expected: usize, found: usize },
     #[error("Undefined function or template")]
     UndefinedFunctionOrTemplate,
+    #[error(
+        "Artifacts of version {version} are not supported, this compiler reads up to version {}",
+        ARTIFACTS_VERSION
+    )]
+    UnsupportedArtifacts { version: u32 },
+    #[error("Unknown pass {0}")]
+    UnknownPass(String),
+    #[error("Inputs not read by any gate: {}", .0.join(", "))]
+    UnusedInputs(Vec<String>),
     #[error(transparent)]
     BristolCircuitError(#[from] BristolCircuitError),
+    #[error(transparent)]
+    BristolReadError(#[from] BristolReadError),
+    #[error("Circuit check found {0} problems")]
+    CircuitCheckFailed(usize),
+    #[error(transparent)]
+    EvaluateError(#[from] EvaluateError),
+    #[error(transparent)]
+    FixedPointError(#[from] FixedPointError),
+    #[error(transparent)]
+    NameError(#[from] NameError),
+}
+
+impl ProgramError {
+    /// Attaches the statement the error occurred in. Errors are attached to every statement
+    /// enclosing the failing one, so the locations form a trace from the innermost statement out.
+    pub fn in_statement(self, location: SourceLocation) -> Self {
+        ProgramError::InStatement {
+            location,
+            source: Box::new(self),
+        }
+    }
+
+    /// Returns the error without the statements it occurred in.
+    pub fn root(&self) -> &ProgramError {
+        match self {
+            ProgramError::InStatement { source, .. } => source.root(),
+            error => error,
+        }
+    }
+
+    /// Returns the statements the error occurred in, innermost first.
+    pub fn locations(&self) -> Vec<&SourceLocation> {
+        let mut locations = Vec::new();
+        let mut error = self;
+        while let ProgramError::InStatement { location, source } = error {
+            locations.push(location);
+            error = source;
+        }
+        locations.reverse();
+        locations
+    }
+
+    /// Returns the messages of the underlying causes of the error, outermost first. Causes
+    /// already included in the message of the error they caused are left out.
+    pub fn causes(&self) -> Vec<String> {
+        let mut causes = Vec::new();
+        let root = self.root();
+        let mut message = root.to_string();
+        let mut cause = root.source();
+        while let Some(error) = cause {
+            let cause_message = error.to_string();
+            if !message.ends_with(&cause_message) {
+                causes.push(cause_message.clone());
+            }
+            message = cause_message;
+            cause = error.source();
+        }
+        causes
+    }
+
+    /// Returns the category of the error.
+    pub fn kind(&self) -> ErrorKind {
+        match self.root() {
+            ProgramError::AnalysisError | ProgramError::ParsingError => ErrorKind::Syntax,
+            ProgramError::CallError
+            | ProgramError::Diagnostics(_)
+            | ProgramError::EmptyDataItem
+            | ProgramError::InputInitializationError
+            | ProgramError::InvalidAnonymousComponent { .. }
+            | ProgramError::InvalidDataType
+            | ProgramError::MainExpressionNotACall
+            | ProgramError::OperationError(_)
+            | ProgramError::ShapeMismatch { .. }
+            | ProgramError::TupleLengthMismatch { .. }
+            | ProgramError::UndefinedFunctionOrTemplate
+            | ProgramError::ValueOutOfRange { .. } => ErrorKind::Program,
+            ProgramError::RuntimeError(
+                RuntimeError::StepLimitExceeded(_)
+                | RuntimeError::Stopped(_)
+                | RuntimeError::SignalIdsExhausted,
+            )
+            | ProgramError::GateBudgetExceeded { .. }
+            | ProgramError::CircuitError(CircuitError::LimitExceeded { .. }) => ErrorKind::Limit,
+            ProgramError::RuntimeError(RuntimeError::NestedComponentAccess(_)) => {
+                ErrorKind::Unsupported
+            }
+            ProgramError::RuntimeError(_) => ErrorKind::Program,
+            ProgramError::ExpressionNotImplemented
+            | ProgramError::OperationNotSupported
+            | ProgramError::SignalSubstitutionNotImplemented
+            | ProgramError::StatementNotImplemented
+            | ProgramError::UnsupportedArtifacts { .. }
+            | ProgramError::UnsupportedByBoolify(_)
+            | ProgramError::UnsupportedByTarget { .. } => ErrorKind::Unsupported,
+            ProgramError::CircuitCheckFailed(_)
+            | ProgramError::CircuitError(_)
+            | ProgramError::UnusedInputs(_) => ErrorKind::Circuit,
+            ProgramError::EvaluateError(
+                EvaluateError::MissingInput(_)
+                | EvaluateError::UnknownInput(_)
+                | EvaluateError::UnknownSignal(_),
+            ) => ErrorKind::InvalidInput,
+            ProgramError::EvaluateError(EvaluateError::BristolReadError(_)) => ErrorKind::Io,
+            ProgramError::EvaluateError(_) => ErrorKind::Circuit,
+            ProgramError::FixedPointError(_)
+            | ProgramError::InvalidConversions(_)
+            | ProgramError::InvalidNoise(_)
+            | ProgramError::InvalidPrebuiltInstance(_)
+            | ProgramError::InvalidStage(_)
+            | ProgramError::InvalidValue(_)
+            | ProgramError::JsonSerializationError(_)
+            | ProgramError::NameError(_)
+            | ProgramError::UnknownPass(_) => ErrorKind::InvalidInput,
+            ProgramError::BristolCircuitError(_)
+            | ProgramError::BristolReadError(_)
+            | ProgramError::FileAlreadyExists(_)
+            | ProgramError::IOError(_)
+            | ProgramError::OutputDirectoryCreationError => ErrorKind::Io,
+            ProgramError::InStatement { source, .. } => source.kind(),
+        }
+    }
+
+    /// Returns the stable code of the error, e.g. `E0223` for an index out of bounds. The second
+    /// digit is the category of the error, in the order of `ErrorKind`.
+    pub fn code(&self) -> &'static str {
+        match self.root() {
+            ProgramError::BristolCircuitError(_) => "E0101",
+            ProgramError::FileAlreadyExists(_) => "E0102",
+            ProgramError::IOError(_) => "E0103",
+            ProgramError::OutputDirectoryCreationError => "E0104",
+            ProgramError::EvaluateError(EvaluateError::BristolReadError(_)) => "E0105",
+            ProgramError::BristolReadError(_) => "E0106",
+            ProgramError::InvalidNoise(_) => "E0201",
+            ProgramError::InvalidStage(_) => "E0202",
+            ProgramError::InvalidValue(_) => "E0203",
+            ProgramError::JsonSerializationError(_) => "E0204",
+            ProgramError::NameError(_) => "E0205",
+            ProgramError::UnknownPass(_) => "E0206",
+            ProgramError::EvaluateError(EvaluateError::MissingInput(_)) => "E0207",
+            ProgramError::EvaluateError(EvaluateError::UnknownInput(_)) => "E0208",
+            ProgramError::InvalidConversions(_) => "E0209",
+            ProgramError::EvaluateError(EvaluateError::UnknownSignal(_)) => "E0210",
+            ProgramError::FixedPointError(_) => "E0211",
+            ProgramError::InvalidPrebuiltInstance(_) => "E0212",
+            ProgramError::AnalysisError => "E0301",
+            ProgramError::ParsingError => "E0302",
+            ProgramError::Diagnostics(_) => "E0400",
+            ProgramError::CallError => "E0401",
+            ProgramError::EmptyDataItem => "E0402",
+            ProgramError::InputInitializationError => "E0403",
+            ProgramError::InvalidAnonymousComponent { .. } => "E0404",
+            ProgramError::InvalidDataType => "E0405",
+            ProgramError::MainExpressionNotACall => "E0406",
+            ProgramError::OperationError(_) => "E0407",
+            ProgramError::ShapeMismatch { .. } => "E0408",
+            ProgramError::TupleLengthMismatch { .. } => "E0409",
+            ProgramError::UndefinedFunctionOrTemplate => "E0410",
+            ProgramError::ValueOutOfRange { .. } => "E0411",
+            ProgramError::RuntimeError(error) => match error {
+                RuntimeError::AccessError => "E0420",
+                RuntimeError::ContextRetrievalError => "E0421",
+                RuntimeError::EmptyContextStack => "E0422",
+                RuntimeError::IndexOutOfBounds => "E0423",
+                RuntimeError::ItemAlreadyDeclared => "E0424",
+                RuntimeError::ItemNotDeclared(_) => "E0425",
+                RuntimeError::NoContextToInheritFrom => "E0426",
+                RuntimeError::NotAValue => "E0427",
+                RuntimeError::UnsupportedDataType => "E0428",
+                RuntimeError::AssertionFailed => "E0429",
+                RuntimeError::ConstraintNotSatisfied => "E0430",
+                RuntimeError::ComponentNotInstantiated(_) => "E0431",
+                RuntimeError::NestedComponentAccess(_) => "E0507",
+                RuntimeError::StepLimitExceeded(_) => "E0601",
+                RuntimeError::Stopped(_) => "E0602",
+                RuntimeError::SignalIdsExhausted => "E0604",
+            },
+            ProgramError::ExpressionNotImplemented => "E0501",
+            ProgramError::OperationNotSupported => "E0502",
+            ProgramError::SignalSubstitutionNotImplemented => "E0503",
+            ProgramError::StatementNotImplemented => "E0504",
+            ProgramError::UnsupportedArtifacts { .. } => "E0505",
+            ProgramError::UnsupportedByTarget { .. } => "E0506",
+            ProgramError::UnsupportedByBoolify(_) => "E0508",
+            ProgramError::GateBudgetExceeded { .. } => "E0603",
+            ProgramError::CircuitError(CircuitError::LimitExceeded { .. }) => "E0605",
+            ProgramError::CircuitError(_) => "E0701",
+            ProgramError::UnusedInputs(_) => "E0702",
+            ProgramError::CircuitCheckFailed(_) => "E0704",
+            ProgramError::EvaluateError(_) => "E0703",
+            ProgramError::InStatement { source, .. } => source.code(),
+        }
+    }
+
+    /// Returns a displayable report of the error: its message, the statements it occurred in
+    /// and its underlying causes.
+    pub fn report(&self) -> ErrorReport<'_> {
+        ErrorReport(self)
+    }
+
+    /// Returns whether processing can skip the statement that failed with this error and go on
+    /// in recovery mode. Exceeded limits and IO errors are not recoverable.
+    pub fn is_recoverable(&self) -> bool {
+        !matches!(
+            self.root(),
+            ProgramError::Diagnostics(_)
+                | ProgramError::GateBudgetExceeded { .. }
+                | ProgramError::IOError(_)
+                | ProgramError::RuntimeError(
+                    RuntimeError::StepLimitExceeded(_)
+                        | RuntimeError::Stopped(_)
+                        | RuntimeError::SignalIdsExhausted
+                )
+        )
+    }
+}
+
+/// Category of a program error, to handle errors without matching every variant.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Reading or writing a file failed.
+    Io,
+    /// An input or configuration file is invalid, e.g. the weights or the stages.
+    InvalidInput,
+    /// The circom source couldn't be parsed or analysed.
+    Syntax,
+    /// The program fails at compile time, e.g. an index out of bounds or a shape mismatch.
+    Program,
+    /// The program uses a feature the compiler doesn't support.
+    Unsupported,
+    /// A compile-time limit was reached, e.g. the step limit or a gate budget.
+    Limit,
+    /// The circuit couldn't be built or evaluated.
+    Circuit,
+}
+
+/// Statement an error occurred in.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub file: String,
+    pub line: usize,
+    /// Column the statement starts at, from 1.
+    pub column: usize,
+    /// Source line the statement starts on.
+    pub text: String,
+    /// Name of the template instance the statement was processed in, e.g. `0.c[2]`.
+    pub instance: String,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{} (in {})", self.file, self.line, self.instance)
+    }
+}
+
+/// Report of an error, its message followed by the statements it occurred in and its causes.
+/// Causes already included in the message of the error they caused are not repeated.
+pub struct ErrorReport<'a>(&'a ProgramError);
+
+impl fmt::Display for ErrorReport<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.root())?;
+        for location in self.0.locations() {
+            write!(f, "\n  at {}", location)?;
+        }
+        for cause in self.0.causes() {
+            write!(f, "\ncaused by: {}", cause)?;
+        }
+
+        Ok(())
+    }
 }
@@ -0,0 +1,355 @@
+//! # Provenance Module
+//!
+//! This module relates the wires of a boolified circuit back to the arithmetic wires they were
+//! produced from, so boolean-level values can be traced back to the original signals.
+
+use crate::{
+    cli::ValueType,
+    evaluate::{Stepper, Watches},
+};
+use bristol_circuit::{BristolCircuit, CircuitInfo};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// Number of input assignments the bits of intermediate wires are matched on, one per bit of a
+/// lane, see `evaluate_boolean_lanes`.
+const SAMPLES: usize = u64::BITS as usize;
+
+/// Seed of the sampled input assignments, so the matched wires are the same on every compilation.
+const SAMPLE_SEED: u64 = 0x2a;
+
+/// The bit wires an arithmetic wire was expanded into by boolify.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WireProvenance {
+    /// Wire of the arithmetic circuit.
+    pub arithmetic_wire: usize,
+    /// Names of the inputs, outputs and constants using the wire, empty for intermediate wires.
+    pub names: Vec<String>,
+    /// Wires of the boolean circuit holding the bits of the value, least significant first.
+    /// Bits of intermediate wires no boolean wire was matched to are `None`.
+    pub bit_wires: Vec<Option<usize>>,
+}
+
+/// Maps the wires of an arithmetic circuit to their bundle of `bit_width` bit wires in the boolean
+/// circuit produced from it, sorted by arithmetic wire.
+///
+/// Boolify lays out each named value as consecutive wires starting at the wire it is named at,
+/// least significant bit first, and names missing from the boolean circuit are skipped.
+/// Intermediate wires have no name to match on: both circuits are evaluated on the same
+/// pseudo-random input assignments, and each bit is matched to the first boolean wire taking the
+/// same values. Bits whose value doesn't change over the assignments, e.g. the high bits of a
+/// comparison, aren't matched, and intermediate wires without any matched bit aren't listed.
+pub fn boolify_provenance(
+    arithmetic: &BristolCircuit,
+    boolean: &BristolCircuit,
+    bit_width: usize,
+) -> Vec<WireProvenance> {
+    let arithmetic_wires = named_wires(&arithmetic.info);
+    let boolean_wires = named_wires(&boolean.info);
+
+    let mut provenance = BTreeMap::<usize, WireProvenance>::new();
+    for (name, arithmetic_wire) in arithmetic_wires {
+        let start = match boolean_wires.get(&name) {
+            Some(&start) => start,
+            None => continue,
+        };
+
+        let entry = provenance
+            .entry(arithmetic_wire)
+            .or_insert_with(|| WireProvenance {
+                arithmetic_wire,
+                names: Vec::new(),
+                bit_wires: (start..start + bit_width).map(Some).collect(),
+            });
+        entry.names.push(name);
+    }
+
+    for (arithmetic_wire, bit_wires) in match_sampled_bits(arithmetic, boolean, bit_width) {
+        provenance
+            .entry(arithmetic_wire)
+            .or_insert_with(|| WireProvenance {
+                arithmetic_wire,
+                names: Vec::new(),
+                bit_wires,
+            });
+    }
+
+    provenance.into_values().collect()
+}
+
+/// Evaluates a boolean circuit on up to 64 input assignments at once: bit `k` of the lane of a
+/// wire is its value in assignment `k`. Inputs take the values of each assignment, keyed by name,
+/// as `bit_width` wires from the wire they are named at, least significant bit first. Returns the
+/// lane of each wire, or `None` if the circuit has other gates than `AND`, `XOR`, `INV`, `NOT`,
+/// `EQ` and `EQW`, or reads a wire before it is set.
+pub fn evaluate_boolean_lanes(
+    boolean: &BristolCircuit,
+    inputs: &HashMap<String, Vec<u32>>,
+    bit_width: usize,
+) -> Option<Vec<u64>> {
+    let mut lanes = vec![None; boolean.wire_count];
+    for (name, &start) in &boolean.info.input_name_to_wire_index {
+        let values = inputs.get(name)?;
+        for bit in 0..bit_width {
+            let lane = values.iter().enumerate().fold(0, |lane, (k, &value)| {
+                lane | (value.checked_shr(bit as u32).unwrap_or(0) as u64 & 1) << k
+            });
+            *lanes.get_mut(start + bit)? = Some(lane);
+        }
+    }
+    for constant in boolean.info.constants.values() {
+        let lane = if constant.value == "0" { 0 } else { u64::MAX };
+        *lanes.get_mut(constant.wire_index)? = Some(lane);
+    }
+
+    for gate in &boolean.gates {
+        let read = |wire: usize| lanes.get(wire).copied().flatten();
+        let lane = match (gate.op.as_str(), &gate.inputs[..]) {
+            ("AND", &[lhs, rhs]) => read(lhs)? & read(rhs)?,
+            ("XOR", &[lhs, rhs]) => read(lhs)? ^ read(rhs)?,
+            ("INV" | "NOT", &[input]) => !read(input)?,
+            ("EQW", &[input]) => read(input)?,
+            // The input of EQ is the bit itself
+            ("EQ", &[bit]) => 0u64.wrapping_sub(bit as u64 & 1),
+            _ => return None,
+        };
+        for &output in &gate.outputs {
+            *lanes.get_mut(output)? = Some(lane);
+        }
+    }
+
+    Some(lanes.into_iter().map(Option::unwrap_or_default).collect())
+}
+
+/// Matches the bits of each arithmetic wire to the first boolean wire taking the same values on
+/// `SAMPLES` input assignments. Returns the bit wires of the arithmetic wires with at least one
+/// matched bit, or nothing if either circuit can't be evaluated.
+fn match_sampled_bits(
+    arithmetic: &BristolCircuit,
+    boolean: &BristolCircuit,
+    bit_width: usize,
+) -> BTreeMap<usize, Vec<Option<usize>>> {
+    let inputs = sample_inputs(&arithmetic.info, bit_width);
+    let Some(lanes) = evaluate_boolean_lanes(boolean, &inputs, bit_width) else {
+        return BTreeMap::new();
+    };
+    let Some(arithmetic_lanes) = evaluate_arithmetic_lanes(arithmetic, &inputs, bit_width) else {
+        return BTreeMap::new();
+    };
+
+    // Lanes that don't change over the assignments would match any constant bit
+    let mut wire_of_lane = HashMap::<u64, usize>::new();
+    for (wire, &lane) in lanes.iter().enumerate() {
+        if lane != 0 && lane != u64::MAX {
+            wire_of_lane.entry(lane).or_insert(wire);
+        }
+    }
+
+    arithmetic_lanes
+        .chunks(bit_width)
+        .enumerate()
+        .filter_map(|(arithmetic_wire, bit_lanes)| {
+            let bit_wires = bit_lanes
+                .iter()
+                .map(|lane| wire_of_lane.get(lane).copied())
+                .collect::<Vec<Option<usize>>>();
+            bit_wires
+                .iter()
+                .any(Option::is_some)
+                .then_some((arithmetic_wire, bit_wires))
+        })
+        .collect()
+}
+
+/// Draws `SAMPLES` assignments of `bit_width`-bit values of the inputs, keyed by name.
+fn sample_inputs(info: &CircuitInfo, bit_width: usize) -> HashMap<String, Vec<u32>> {
+    let mask = u32::MAX
+        .checked_shr(32 - bit_width.min(32) as u32)
+        .unwrap_or(0);
+    let mut names = info.input_name_to_wire_index.keys().collect::<Vec<_>>();
+    names.sort();
+
+    // SplitMix64, so samples don't depend on the platform
+    let mut state = SAMPLE_SEED;
+    let mut next = || {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        (z ^ (z >> 31)) as u32 & mask
+    };
+
+    names
+        .into_iter()
+        .map(|name| (name.clone(), (0..SAMPLES).map(|_| next()).collect()))
+        .collect()
+}
+
+/// Evaluates an arithmetic circuit with unsigned semantics, like boolify, on each sampled
+/// assignment. Returns the lanes of the `bit_width` bits of each wire, wire after wire, or `None`
+/// if an assignment can't be evaluated.
+fn evaluate_arithmetic_lanes(
+    arithmetic: &BristolCircuit,
+    inputs: &HashMap<String, Vec<u32>>,
+    bit_width: usize,
+) -> Option<Vec<u64>> {
+    let mut lanes = vec![0u64; arithmetic.wire_count * bit_width];
+    for k in 0..SAMPLES {
+        let assignment = inputs
+            .iter()
+            .map(|(name, values)| (name.clone(), values[k]))
+            .collect::<HashMap<String, u32>>();
+        let mut stepper =
+            Stepper::new(arithmetic, &assignment, ValueType::Uint, Watches::default()).ok()?;
+        stepper.run().ok()?;
+
+        for wire in 0..arithmetic.wire_count {
+            let value = stepper.value(wire).unwrap_or_default();
+            // Bits above the 32 bits of the evaluated values aren't known
+            for bit in 0..bit_width.min(32) {
+                lanes[wire * bit_width + bit] |= ((value >> bit) as u64 & 1) << k;
+            }
+        }
+    }
+
+    Some(lanes)
+}
+
+/// Returns the wire of each input, output and constant, keyed and sorted by name.
+fn named_wires(info: &CircuitInfo) -> BTreeMap<String, usize> {
+    info.input_name_to_wire_index
+        .iter()
+        .chain(&info.output_name_to_wire_index)
+        .map(|(name, &wire)| (name.clone(), wire))
+        .chain(
+            info.constants
+                .iter()
+                .map(|(name, constant)| (name.clone(), constant.wire_index)),
+        )
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bristol_circuit::{ConstantInfo, Gate};
+    use std::collections::HashMap;
+
+    fn circuit(
+        wire_count: usize,
+        inputs: &[(&str, usize)],
+        constants: &[(&str, usize)],
+        outputs: &[(&str, usize)],
+    ) -> BristolCircuit {
+        let names = |names: &[(&str, usize)]| {
+            names
+                .iter()
+                .map(|&(name, wire)| (name.to_string(), wire))
+                .collect::<HashMap<String, usize>>()
+        };
+
+        BristolCircuit {
+            wire_count,
+            info: CircuitInfo {
+                input_name_to_wire_index: names(inputs),
+                constants: constants
+                    .iter()
+                    .map(|&(name, wire_index)| {
+                        let value = String::from("1");
+                        (name.to_string(), ConstantInfo { value, wire_index })
+                    })
+                    .collect(),
+                output_name_to_wire_index: names(outputs),
+            },
+            io_widths: None,
+            gates: vec![Gate {
+                inputs: vec![0, 1],
+                outputs: vec![wire_count - 1],
+                op: String::from("AAdd"),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_boolify_provenance() {
+        let arithmetic = circuit(
+            4,
+            &[("0.a", 0), ("0.b", 1)],
+            &[("0.c", 2)],
+            &[("0.out", 3), ("0.copy", 3)],
+        );
+        let boolean = circuit(
+            30,
+            &[("0.a", 0), ("0.b", 4)],
+            &[],
+            &[("0.out", 20), ("0.copy", 20)],
+        );
+
+        assert_eq!(
+            boolify_provenance(&arithmetic, &boolean, 4),
+            vec![
+                WireProvenance {
+                    arithmetic_wire: 0,
+                    names: vec![String::from("0.a")],
+                    bit_wires: vec![Some(0), Some(1), Some(2), Some(3)],
+                },
+                WireProvenance {
+                    arithmetic_wire: 1,
+                    names: vec![String::from("0.b")],
+                    bit_wires: vec![Some(4), Some(5), Some(6), Some(7)],
+                },
+                WireProvenance {
+                    arithmetic_wire: 3,
+                    names: vec![String::from("0.copy"), String::from("0.out")],
+                    bit_wires: vec![Some(20), Some(21), Some(22), Some(23)],
+                },
+            ]
+        );
+    }
+
+    fn gate(op: &str, inputs: &[usize], output: usize) -> Gate {
+        Gate {
+            inputs: inputs.to_vec(),
+            outputs: vec![output],
+            op: String::from(op),
+        }
+    }
+
+    #[test]
+    fn test_boolify_provenance_intermediate_wires() {
+        // out = (a ^ b) & a, on 2 bits
+        let mut arithmetic = circuit(4, &[("0.a", 0), ("0.b", 1)], &[], &[("0.out", 3)]);
+        arithmetic.gates = vec![gate("AXor", &[0, 1], 2), gate("ABitAnd", &[2, 0], 3)];
+        let mut boolean = circuit(8, &[("0.a", 0), ("0.b", 2)], &[], &[("0.out", 6)]);
+        boolean.gates = vec![
+            gate("XOR", &[0, 2], 4),
+            gate("XOR", &[1, 3], 5),
+            gate("AND", &[4, 0], 6),
+            gate("AND", &[5, 1], 7),
+        ];
+
+        let provenance = boolify_provenance(&arithmetic, &boolean, 2);
+        assert_eq!(
+            provenance[2],
+            WireProvenance {
+                arithmetic_wire: 2,
+                names: Vec::new(),
+                bit_wires: vec![Some(4), Some(5)],
+            }
+        );
+        assert_eq!(provenance[3].bit_wires, vec![Some(6), Some(7)]);
+    }
+
+    #[test]
+    fn test_evaluate_boolean_lanes() {
+        let mut boolean = circuit(5, &[("0.a", 0)], &[("0.one", 2)], &[("0.out", 4)]);
+        boolean.gates = vec![gate("AND", &[0, 2], 3), gate("INV", &[1], 4)];
+        let inputs = HashMap::from([(String::from("0.a"), vec![0b01, 0b10, 0b11])]);
+
+        let lanes = evaluate_boolean_lanes(&boolean, &inputs, 2).unwrap();
+        assert_eq!(lanes, vec![0b101, 0b110, u64::MAX, 0b101, !0b110]);
+
+        boolean.gates.push(gate("AAdd", &[0, 1], 4));
+        assert_eq!(evaluate_boolean_lanes(&boolean, &inputs, 2), None);
+    }
+}
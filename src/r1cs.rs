@@ -0,0 +1,470 @@
+//! # R1CS Module
+//!
+//! This module exports a compiled circuit as a rank-1 constraint system over the prime field
+//! selected with `--prime`, in the iden3 `.r1cs` binary format used by circom and snarkjs, along with a `.sym` file
+//! naming the wires of the inputs and outputs.
+
+use crate::{
+    a_gate_type::AGateType,
+    cli::{Prime, ValueType},
+    compiler::{checked_u32, CircuitError},
+    field::{modulus, FieldElement},
+};
+use bristol_circuit::BristolCircuit;
+use circom_circom_algebra::num_bigint::BigUint;
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::Write,
+};
+
+/// The BN254 scalar field prime, in little-endian order.
+pub const BN254_PRIME: [u8; 32] = [
+    0x01, 0x00, 0x00, 0xf0, 0x93, 0xf5, 0xe1, 0x43, 0x91, 0x70, 0xb9, 0x79, 0x48, 0xe8, 0x33, 0x28,
+    0x5d, 0x58, 0x81, 0x81, 0xb6, 0x45, 0x50, 0xb8, 0x29, 0xa0, 0x31, 0xe1, 0x72, 0x4e, 0x64, 0x30,
+];
+
+const MAGIC: &[u8; 4] = b"r1cs";
+const VERSION: u32 = 1;
+const HEADER_SECTION: u32 = 1;
+const CONSTRAINTS_SECTION: u32 = 2;
+const WIRE_TO_LABEL_SECTION: u32 = 3;
+
+/// A sum of wires multiplied by coefficients, sorted by wire. Wire 0 is the constant 1.
+pub type LinearCombination = Vec<(u32, FieldElement)>;
+
+/// A constraint `a * b = c`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Constraint {
+    pub a: LinearCombination,
+    pub b: LinearCombination,
+    pub c: LinearCombination,
+}
+
+/// A rank-1 constraint system. Wires are laid out as the constant 1, the outputs, the inputs and
+/// the intermediate values, and each wire is its own label.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct R1cs {
+    pub wire_count: u32,
+    pub public_outputs: u32,
+    pub public_inputs: u32,
+    pub private_inputs: u32,
+    pub constraints: Vec<Constraint>,
+    /// Names of the input and output wires, sorted by wire.
+    pub names: Vec<(u32, String)>,
+    /// Prime field of the constraints.
+    pub prime: Prime,
+}
+
+impl R1cs {
+    /// Builds the constraints of a Bristol circuit produced by the compiler, with one constraint
+    /// per gate. Only additions, subtractions and multiplications can be expressed as constraints.
+    /// Outputs are public and inputs are private. Constants are field elements, see
+    /// `Compiler::set_field_constants`, while the constants of `ACMul` gates are words of the
+    /// value type.
+    pub fn from_circuit(
+        circuit: &BristolCircuit,
+        value_type: ValueType,
+        prime: Prime,
+    ) -> Result<Self, CircuitError> {
+        // Wire ids are `u32`, the constant one wire included
+        checked_u32("R1CS wires", circuit.wire_count + 1)?;
+
+        let info = &circuit.info;
+        let mut constants = HashMap::new();
+        for constant in info.constants.values() {
+            let value =
+                constant
+                    .value
+                    .parse::<BigUint>()
+                    .map_err(|error| CircuitError::ParsingError {
+                        message: format!("Constant {}: {}", constant.value, error),
+                    })?;
+            let value = FieldElement::from_integer(&value, false, prime);
+            constants.insert(constant.wire_index, vec![(0, value)]);
+        }
+
+        let mut outputs = info
+            .output_name_to_wire_index
+            .iter()
+            .map(|(name, &wire)| (wire, name))
+            .collect::<Vec<_>>();
+        outputs.sort();
+        let mut inputs = info
+            .input_name_to_wire_index
+            .iter()
+            .map(|(name, &wire)| (wire, name))
+            .collect::<Vec<_>>();
+        inputs.sort();
+
+        // Outputs sharing a wire in the circuit share it in the constraint system too
+        let mut wires = HashMap::<usize, u32>::new();
+        let mut names = Vec::new();
+        let assign = |wires: &mut HashMap<usize, u32>, wire: usize| {
+            let next_wire = wires.len() as u32 + 1;
+            *wires.entry(wire).or_insert(next_wire)
+        };
+        for (wire, name) in &outputs {
+            names.push((assign(&mut wires, *wire), name.to_string()));
+        }
+        let public_outputs = wires.len() as u32;
+        for (wire, name) in &inputs {
+            names.push((assign(&mut wires, *wire), name.to_string()));
+        }
+
+        let mut constraints = Vec::new();
+        let one = || vec![(0, FieldElement::from(1))];
+        let single = |wire: u32| vec![(wire, FieldElement::from(1))];
+
+        // Constant outputs have no gate constraining them
+        for (wire, _) in &outputs {
+            if let Some(value) = constants.get(wire) {
+                constraints.push(Constraint {
+                    a: combine(value, &[], false, prime),
+                    b: one(),
+                    c: single(wires[wire]),
+                });
+            }
+        }
+
+        for gate in &circuit.gates {
+            let (op, constant) = AGateType::parse_bristol_op(&gate.op)
+                .ok_or_else(|| CircuitError::UnsupportedGateType(gate.op.clone()))?;
+            let term = |wire: usize| match constants.get(&wire) {
+                Some(value) => Ok(combine(value, &[], false, prime)),
+                None => wires.get(&wire).map(|&wire| single(wire)).ok_or_else(|| {
+                    CircuitError::Inconsistency {
+                        message: format!("Wire {} is used before being assigned", wire),
+                    }
+                }),
+            };
+            let lhs = term(gate.inputs[0])?;
+            let rhs = match constant {
+                Some(value) => {
+                    let value = FieldElement::from_word(value, value_type, prime);
+                    combine(&[(0, value)], &[], false, prime)
+                }
+                None => term(gate.inputs[1])?,
+            };
+
+            let out = assign(&mut wires, gate.outputs[0]);
+            let (a, b) = match op {
+                AGateType::AAdd => (combine(&lhs, &rhs, false, prime), one()),
+                AGateType::ASub => (combine(&lhs, &rhs, true, prime), one()),
+                AGateType::AMul | AGateType::ACMul => (lhs, rhs),
+                _ => return Err(CircuitError::UnsupportedGateType(format!("{} in R1CS", op))),
+            };
+            constraints.push(Constraint {
+                a,
+                b,
+                c: single(out),
+            });
+        }
+
+        names.sort();
+        Ok(R1cs {
+            wire_count: wires.len() as u32 + 1,
+            public_outputs,
+            public_inputs: 0,
+            private_inputs: inputs.len() as u32,
+            constraints,
+            names,
+            prime,
+        })
+    }
+
+    /// Writes the constraint system in the iden3 `.r1cs` binary format.
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<(), CircuitError> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&3u32.to_le_bytes())?;
+
+        let prime = field_bytes(modulus(self.prime), self.prime);
+        let mut header = Vec::new();
+        header.extend((prime.len() as u32).to_le_bytes());
+        header.extend(prime);
+        header.extend(self.wire_count.to_le_bytes());
+        header.extend(self.public_outputs.to_le_bytes());
+        header.extend(self.public_inputs.to_le_bytes());
+        header.extend(self.private_inputs.to_le_bytes());
+        header.extend((self.wire_count as u64).to_le_bytes());
+        header.extend(checked_u32("constraints", self.constraints.len())?.to_le_bytes());
+        write_section(writer, HEADER_SECTION, &header)?;
+
+        let mut constraints = Vec::new();
+        for constraint in &self.constraints {
+            for combination in [&constraint.a, &constraint.b, &constraint.c] {
+                constraints.extend((combination.len() as u32).to_le_bytes());
+                for (wire, coefficient) in combination {
+                    constraints.extend(wire.to_le_bytes());
+                    constraints.extend(field_bytes(coefficient.representative(), self.prime));
+                }
+            }
+        }
+        write_section(writer, CONSTRAINTS_SECTION, &constraints)?;
+
+        let labels = (0..self.wire_count as u64)
+            .flat_map(u64::to_le_bytes)
+            .collect::<Vec<u8>>();
+        write_section(writer, WIRE_TO_LABEL_SECTION, &labels)?;
+
+        Ok(())
+    }
+
+    /// Writes the names of the input and output wires in the circom `.sym` format, one
+    /// `label,wire,component,name` line per name.
+    pub fn write_sym<W: Write>(&self, writer: &mut W) -> Result<(), CircuitError> {
+        for (wire, name) in &self.names {
+            writeln!(writer, "{},{},0,{}", wire, wire, name)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the size in bytes of the `.r1cs` file of a constraint system with `wire_count` wires,
+/// including the constant one, and `constraints` constraints with `terms` terms in total over
+/// their linear combinations, without building it.
+pub fn file_size(wire_count: u64, constraints: u64, terms: u64, prime: Prime) -> u64 {
+    let field_size = modulus(prime).to_bytes_le().len().div_ceil(8) as u64 * 8;
+    let section = 4 + 8;
+    let header = section + 4 + field_size + 4 * 4 + 8 + 4;
+    let constraints = section + constraints * 3 * 4 + terms * (4 + field_size);
+    let labels = section + wire_count * 8;
+
+    MAGIC.len() as u64 + 4 + 4 + header + constraints + labels
+}
+
+/// Returns `lhs + rhs`, or `lhs - rhs` if `negate` is set, merging the terms of the same wire and
+/// dropping zero terms.
+fn combine(
+    lhs: &[(u32, FieldElement)],
+    rhs: &[(u32, FieldElement)],
+    negate: bool,
+    prime: Prime,
+) -> LinearCombination {
+    let mut terms = BTreeMap::<u32, FieldElement>::new();
+    for (wire, coefficient) in lhs {
+        let term = terms.entry(*wire).or_default();
+        *term = term.add(coefficient, prime);
+    }
+    for (wire, coefficient) in rhs {
+        let term = terms.entry(*wire).or_default();
+        *term = if negate {
+            term.sub(coefficient, prime)
+        } else {
+            term.add(coefficient, prime)
+        };
+    }
+
+    terms
+        .into_iter()
+        .filter(|(_, coefficient)| !coefficient.is_zero())
+        .collect()
+}
+
+/// Encodes a value in little-endian order, padded to the field size: the bytes of the prime
+/// rounded up to a multiple of 8, as in circom.
+fn field_bytes(value: &BigUint, prime: Prime) -> Vec<u8> {
+    let size = modulus(prime).to_bytes_le().len().div_ceil(8) * 8;
+    let mut bytes = value.to_bytes_le();
+    bytes.resize(size, 0);
+    bytes
+}
+
+/// Writes a section, its type and size followed by its content.
+fn write_section<W: Write>(
+    writer: &mut W,
+    section: u32,
+    content: &[u8],
+) -> Result<(), CircuitError> {
+    writer.write_all(&section.to_le_bytes())?;
+    writer.write_all(&(content.len() as u64).to_le_bytes())?;
+    writer.write_all(content)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bristol_circuit::{CircuitInfo, ConstantInfo, Gate};
+
+    /// Returns the element of a small integer in the BN254 field.
+    fn element(value: i64) -> FieldElement {
+        let magnitude = BigUint::from(value.unsigned_abs());
+        FieldElement::from_integer(&magnitude, value < 0, Prime::Bn128)
+    }
+
+    /// Encodes a small integer as a little-endian element of the field.
+    fn field_element(value: i64, prime: Prime) -> Vec<u8> {
+        let magnitude = BigUint::from(value.unsigned_abs());
+        field_bytes(
+            FieldElement::from_integer(&magnitude, value < 0, prime).representative(),
+            prime,
+        )
+    }
+
+    fn gate(lhs: usize, rhs: usize, out: usize, op: &str) -> Gate {
+        Gate {
+            inputs: vec![lhs, rhs],
+            outputs: vec![out],
+            op: op.to_string(),
+        }
+    }
+
+    // 0: a, 1: b, 2: constant 3, 3: a * b, 4: out = a * b - 3
+    fn circuit(op: &str) -> BristolCircuit {
+        BristolCircuit {
+            wire_count: 5,
+            info: CircuitInfo {
+                input_name_to_wire_index: HashMap::from([
+                    (String::from("0.a"), 0),
+                    (String::from("0.b"), 1),
+                ]),
+                constants: HashMap::from([(
+                    String::from("0.c"),
+                    ConstantInfo {
+                        value: String::from("3"),
+                        wire_index: 2,
+                    },
+                )]),
+                output_name_to_wire_index: HashMap::from([(String::from("0.out"), 4)]),
+            },
+            io_widths: None,
+            gates: vec![gate(0, 1, 3, "AMul"), gate(3, 2, 4, op)],
+        }
+    }
+
+    #[test]
+    fn test_r1cs_from_circuit() {
+        let r1cs = R1cs::from_circuit(&circuit("ASub"), ValueType::Sint, Prime::Bn128).unwrap();
+
+        // 0: one, 1: out, 2: a, 3: b, 4: a * b
+        assert_eq!(r1cs.wire_count, 5);
+        assert_eq!(r1cs.public_outputs, 1);
+        assert_eq!(r1cs.private_inputs, 2);
+        assert_eq!(
+            r1cs.constraints,
+            vec![
+                Constraint {
+                    a: vec![(2, element(1))],
+                    b: vec![(3, element(1))],
+                    c: vec![(4, element(1))],
+                },
+                Constraint {
+                    a: vec![(0, element(-3)), (4, element(1))],
+                    b: vec![(0, element(1))],
+                    c: vec![(1, element(1))],
+                },
+            ]
+        );
+        assert_eq!(
+            r1cs.names,
+            vec![
+                (1, String::from("0.out")),
+                (2, String::from("0.a")),
+                (3, String::from("0.b"))
+            ]
+        );
+
+        let mut sym = Vec::new();
+        r1cs.write_sym(&mut sym).unwrap();
+        assert_eq!(
+            String::from_utf8(sym).unwrap(),
+            "1,1,0,0.out\n2,2,0,0.a\n3,3,0,0.b\n"
+        );
+    }
+
+    #[test]
+    fn test_r1cs_field_constants() {
+        // Constants that don't fit in a word are carried as field elements
+        let mut with_big = circuit("AAdd");
+        let big = BigUint::from(10u32).pow(18);
+        with_big.info.constants.get_mut("0.c").unwrap().value = big.to_string();
+        let r1cs = R1cs::from_circuit(&with_big, ValueType::Sint, Prime::Bn128).unwrap();
+        assert_eq!(
+            r1cs.constraints[1].a,
+            vec![
+                (0, FieldElement::from_integer(&big, false, Prime::Bn128)),
+                (4, element(1))
+            ]
+        );
+
+        // Inline constants are words of the value type
+        let mut with_inline = circuit("AAdd");
+        with_inline.gates[1] = Gate {
+            inputs: vec![3],
+            outputs: vec![4],
+            op: format!("ACMul:{}", u32::MAX),
+        };
+        let r1cs = R1cs::from_circuit(&with_inline, ValueType::Sint, Prime::Bn128).unwrap();
+        assert_eq!(r1cs.constraints[1].b, vec![(0, element(-1))]);
+        let r1cs = R1cs::from_circuit(&with_inline, ValueType::Uint, Prime::Bn128).unwrap();
+        assert_eq!(r1cs.constraints[1].b, vec![(0, element(u32::MAX as i64))]);
+    }
+
+    #[test]
+    fn test_r1cs_unsupported_gate() {
+        let result = R1cs::from_circuit(&circuit("ALt"), ValueType::Sint, Prime::Bn128);
+        assert!(matches!(result, Err(CircuitError::UnsupportedGateType(_))));
+    }
+
+    #[test]
+    fn test_r1cs_too_many_wires() {
+        let mut circuit = circuit("AAdd");
+        circuit.wire_count = u32::MAX as usize;
+        let result = R1cs::from_circuit(&circuit, ValueType::Sint, Prime::Bn128);
+        assert!(matches!(
+            result,
+            Err(CircuitError::LimitExceeded { limit, .. }) if limit == u32::MAX as usize
+        ));
+    }
+
+    #[test]
+    fn test_r1cs_write() {
+        let r1cs = R1cs::from_circuit(&circuit("AAdd"), ValueType::Sint, Prime::Bn128).unwrap();
+        let mut bytes = Vec::new();
+        r1cs.write(&mut bytes).unwrap();
+
+        assert_eq!(&bytes[..4], b"r1cs");
+        assert_eq!(u32::from_le_bytes(bytes[4..8].try_into().unwrap()), 1);
+        assert_eq!(u32::from_le_bytes(bytes[8..12].try_into().unwrap()), 3);
+        // Header section: type, size, then the field size and prime
+        assert_eq!(u32::from_le_bytes(bytes[12..16].try_into().unwrap()), 1);
+        assert_eq!(u64::from_le_bytes(bytes[16..24].try_into().unwrap()), 64);
+        assert_eq!(&bytes[28..60], &BN254_PRIME);
+
+        // Header, constraints (3 combinations of 1 or 2 terms of 36 bytes each) and labels
+        let constraints = 2 * 3 * 4 + 7 * 36;
+        assert_eq!(
+            bytes.len(),
+            12 + (12 + 64) + (12 + constraints) + (12 + 5 * 8)
+        );
+        assert_eq!(bytes.len() as u64, file_size(5, 2, 7, Prime::Bn128));
+    }
+
+    #[test]
+    fn test_field_element() {
+        let mut one = [0; 32];
+        one[0] = 1;
+        assert_eq!(field_element(1, Prime::Bn128), one);
+
+        // prime - 1
+        let mut minus_one = BN254_PRIME;
+        minus_one[0] = 0;
+        assert_eq!(field_element(-1, Prime::Bn128), minus_one);
+
+        // prime - 2 borrows from the second byte
+        let mut minus_two = BN254_PRIME;
+        minus_two[0] = 0xff;
+        minus_two[1] = 0xff;
+        minus_two[2] = 0xff;
+        minus_two[3] = 0xef;
+        assert_eq!(field_element(-2, Prime::Bn128), minus_two);
+
+        // Goldilocks elements take 8 bytes
+        assert_eq!(
+            field_element(-1, Prime::Goldilocks),
+            18446744069414584320u64.to_le_bytes()
+        );
+    }
+}
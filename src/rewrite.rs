@@ -0,0 +1,489 @@
+//! # Rewrite Module
+//!
+//! This module provides a small framework to rewrite patterns of the gate graph, applied iteratively
+//! until no rule matches. Rules are declared in Rust by implementing `RewriteRule`.
+
+use crate::{a_gate_type::AGateType, compiler::ArithmeticGate};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Gate graph the rewrite rules operate on.
+/// Keeps track of the gate producing each node and of the gates using it, once per input.
+/// Rewrites that compute new values produce them in fresh nodes, so the signals bound to the
+/// existing nodes keep their value or are removed with their node.
+pub struct GateGraph {
+    gates: Vec<Option<ArithmeticGate>>,
+    producers: HashMap<u32, usize>,
+    consumers: HashMap<u32, Vec<usize>>,
+    protected: HashSet<u32>,
+    removed: Vec<u32>,
+    added: Vec<u32>,
+    next_node: u32,
+}
+
+impl GateGraph {
+    /// Creates a graph from the given gates.
+    /// The values of the `protected` nodes (e.g. circuit outputs) are preserved by the rewrites.
+    pub fn new(gates: Vec<ArithmeticGate>, protected: HashSet<u32>) -> Self {
+        let mut graph = Self {
+            gates: Vec::with_capacity(gates.len()),
+            producers: HashMap::new(),
+            consumers: HashMap::new(),
+            protected,
+            removed: Vec::new(),
+            added: Vec::new(),
+            next_node: 0,
+        };
+
+        for gate in gates {
+            graph.next_node = graph
+                .next_node
+                .max(gate.lh_in.max(gate.rh_in).max(gate.out) + 1);
+            let index = graph.gates.len();
+            graph.producers.insert(gate.out, index);
+            graph.add_uses(index, &gate);
+            graph.gates.push(Some(gate));
+        }
+
+        graph
+    }
+
+    /// Sets the id of the next fresh node, e.g. above the nodes that no gate uses.
+    pub fn with_next_node(mut self, next_node: u32) -> Self {
+        self.next_node = self.next_node.max(next_node);
+        self
+    }
+
+    /// Returns the number of gate slots, including removed gates.
+    pub fn len(&self) -> usize {
+        self.gates.len()
+    }
+
+    /// Returns true if the graph has no gate slots.
+    pub fn is_empty(&self) -> bool {
+        self.gates.is_empty()
+    }
+
+    /// Returns the gate at the given index, unless it was removed.
+    pub fn gate(&self, index: usize) -> Option<&ArithmeticGate> {
+        self.gates.get(index)?.as_ref()
+    }
+
+    /// Returns the gate producing the given node, if any.
+    pub fn producer(&self, node_id: u32) -> Option<&ArithmeticGate> {
+        self.gate(*self.producers.get(&node_id)?)
+    }
+
+    /// Returns the number of gate inputs using the given node.
+    pub fn uses(&self, node_id: u32) -> usize {
+        self.consumers.get(&node_id).map_or(0, Vec::len)
+    }
+
+    /// Returns the gates using the given node, once per input.
+    pub fn consumers(&self, node_id: u32) -> impl Iterator<Item = &ArithmeticGate> + '_ {
+        self.consumers
+            .get(&node_id)
+            .into_iter()
+            .flatten()
+            .filter_map(|&index| self.gate(index))
+    }
+
+    /// Returns true if the value of the node can be changed, i.e. it is used by a single gate
+    /// input and isn't protected.
+    pub fn is_intermediate(&self, node_id: u32) -> bool {
+        self.uses(node_id) == 1 && !self.protected.contains(&node_id)
+    }
+
+    /// Replaces the operation and inputs of the gate at the given index, keeping its output node.
+    pub fn set_gate(&mut self, index: usize, op: AGateType, lh_in: u32, rh_in: u32) {
+        let gate = match self.gates[index].take() {
+            Some(gate) => gate,
+            None => return,
+        };
+        self.remove_uses(index, &gate);
+
+        let gate = ArithmeticGate::new(op, lh_in, rh_in, gate.out);
+        self.add_uses(index, &gate);
+        self.gates[index] = Some(gate);
+    }
+
+    /// Replaces the operation and inputs of the gate producing the given node.
+    pub fn set_producer(&mut self, node_id: u32, op: AGateType, lh_in: u32, rh_in: u32) {
+        if let Some(&index) = self.producers.get(&node_id) {
+            self.set_gate(index, op, lh_in, rh_in);
+        }
+    }
+
+    /// Adds a gate producing a fresh node, returning the node.
+    pub fn add_gate(&mut self, op: AGateType, lh_in: u32, rh_in: u32) -> u32 {
+        let out = self.next_node;
+        self.next_node += 1;
+
+        let index = self.gates.len();
+        let gate = ArithmeticGate::new(op, lh_in, rh_in, out);
+        self.producers.insert(out, index);
+        self.add_uses(index, &gate);
+        self.gates.push(Some(gate));
+        self.added.push(out);
+
+        out
+    }
+
+    /// Removes the gate producing the given node.
+    pub fn remove_producer(&mut self, node_id: u32) {
+        if let Some(index) = self.producers.remove(&node_id) {
+            if let Some(gate) = self.gates[index].take() {
+                self.remove_uses(index, &gate);
+                self.removed.push(node_id);
+            }
+        }
+    }
+
+    /// Consumes the graph, returning the remaining gates, the nodes whose producer was removed
+    /// and that are no longer used, and the fresh nodes produced by the remaining gates.
+    pub fn into_parts(self) -> (Vec<ArithmeticGate>, Vec<u32>, Vec<u32>) {
+        let added = self
+            .added
+            .iter()
+            .copied()
+            .filter(|node_id| self.producers.contains_key(node_id))
+            .collect::<Vec<u32>>();
+        let mut dead = self
+            .removed
+            .iter()
+            .copied()
+            .filter(|&node_id| self.uses(node_id) == 0 && !self.added.contains(&node_id))
+            .collect::<Vec<u32>>();
+        dead.sort_unstable();
+
+        (self.gates.into_iter().flatten().collect(), dead, added)
+    }
+
+    fn add_uses(&mut self, index: usize, gate: &ArithmeticGate) {
+        for node_id in [gate.lh_in, gate.rh_in] {
+            self.consumers.entry(node_id).or_default().push(index);
+        }
+    }
+
+    fn remove_uses(&mut self, index: usize, gate: &ArithmeticGate) {
+        for node_id in [gate.lh_in, gate.rh_in] {
+            if let Some(consumers) = self.consumers.get_mut(&node_id) {
+                if let Some(position) = consumers.iter().position(|&i| i == index) {
+                    consumers.swap_remove(position);
+                }
+            }
+        }
+    }
+}
+
+/// A rewrite of a pattern of the gate graph.
+pub trait RewriteRule {
+    /// Name of the rule, used in the statistics.
+    fn name(&self) -> &'static str;
+
+    /// Tries to rewrite the pattern rooted at the gate with the given index.
+    /// Returns true if the graph was changed.
+    fn apply(&self, graph: &mut GateGraph, index: usize) -> bool;
+}
+
+/// Statistics of the rewrites.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RewriteStats {
+    /// Number of rewrites applied by each rule.
+    pub rewrites: BTreeMap<&'static str, usize>,
+    /// Number of passes over the gates.
+    pub iterations: usize,
+}
+
+/// Applies the rules to every gate until none of them matches, or `max_iterations` passes were made.
+pub fn apply_rules(
+    graph: &mut GateGraph,
+    rules: &[&dyn RewriteRule],
+    max_iterations: usize,
+) -> RewriteStats {
+    let mut stats = RewriteStats::default();
+
+    while stats.iterations < max_iterations {
+        stats.iterations += 1;
+        let mut changed = false;
+
+        for index in 0..graph.len() {
+            for rule in rules {
+                if graph.gate(index).is_some() && rule.apply(graph, index) {
+                    *stats.rewrites.entry(rule.name()).or_default() += 1;
+                    changed = true;
+                }
+            }
+        }
+
+        if !changed {
+            break;
+        }
+    }
+
+    stats
+}
+
+/// Factors a common operand out of a sum of products: `(a * b) + (a * c)` to `a * (b + c)`.
+/// Only applies when the products aren't used anywhere else. The sum `b + c` is a fresh node.
+pub struct FactorCommonOperand;
+
+impl RewriteRule for FactorCommonOperand {
+    fn name(&self) -> &'static str {
+        "factor_common_operand"
+    }
+
+    fn apply(&self, graph: &mut GateGraph, index: usize) -> bool {
+        let (lhs, rhs) = match graph.gate(index) {
+            Some(gate) if gate.op == AGateType::AAdd && gate.lh_in != gate.rh_in => {
+                (gate.lh_in, gate.rh_in)
+            }
+            _ => return false,
+        };
+        if !graph.is_intermediate(lhs) || !graph.is_intermediate(rhs) {
+            return false;
+        }
+
+        let (lhs_gate, rhs_gate) = match (graph.producer(lhs), graph.producer(rhs)) {
+            (Some(l), Some(r)) if l.op == AGateType::AMul && r.op == AGateType::AMul => (l, r),
+            _ => return false,
+        };
+
+        // Find the common operand of both products
+        let common = [
+            (lhs_gate.lh_in, lhs_gate.rh_in),
+            (lhs_gate.rh_in, lhs_gate.lh_in),
+        ]
+        .into_iter()
+        .find_map(|(a, b)| {
+            if a == rhs_gate.lh_in {
+                Some((a, b, rhs_gate.rh_in))
+            } else if a == rhs_gate.rh_in {
+                Some((a, b, rhs_gate.lh_in))
+            } else {
+                None
+            }
+        });
+        let (a, b, c) = match common {
+            Some(common) => common,
+            None => return false,
+        };
+
+        // Both products are left unused
+        let sum = graph.add_gate(AGateType::AAdd, b, c);
+        graph.set_gate(index, AGateType::AMul, a, sum);
+        graph.remove_producer(lhs);
+        graph.remove_producer(rhs);
+
+        true
+    }
+}
+
+/// Rebalances chains of additions, e.g. from `sum += x[i]` in a loop, into adder trees of
+/// logarithmic depth: `((a + b) + c) + d` to `(a + b) + (c + d)`. Only applies to additions whose
+/// partial sums aren't used anywhere else, and relies on additions being associative, which
+/// doesn't hold for floating point values. The partial sums of the tree are fresh nodes, the
+/// ones of the chain are removed.
+pub struct BalanceAdditions;
+
+impl RewriteRule for BalanceAdditions {
+    fn name(&self) -> &'static str {
+        "balance_additions"
+    }
+
+    fn apply(&self, graph: &mut GateGraph, index: usize) -> bool {
+        let (root, lhs, rhs) = match graph.gate(index) {
+            Some(gate) if gate.op == AGateType::AAdd => (gate.out, gate.lh_in, gate.rh_in),
+            _ => return false,
+        };
+        // The chain is rebalanced from its last addition
+        if graph.is_intermediate(root)
+            && graph.consumers(root).all(|gate| gate.op == AGateType::AAdd)
+        {
+            return false;
+        }
+
+        // Collect the operands of the chain from left to right, along with the partial sums
+        let mut operands = Vec::new();
+        let mut partial_sums = Vec::new();
+        let mut depth = 0;
+        let mut stack = vec![(rhs, 1), (lhs, 1)];
+        while let Some((node_id, node_depth)) = stack.pop() {
+            let addition = match graph.producer(node_id) {
+                Some(gate) if gate.op == AGateType::AAdd && graph.is_intermediate(node_id) => {
+                    Some((gate.lh_in, gate.rh_in))
+                }
+                _ => None,
+            };
+            match addition {
+                Some((lh_in, rh_in)) => {
+                    partial_sums.push(node_id);
+                    stack.push((rh_in, node_depth + 1));
+                    stack.push((lh_in, node_depth + 1));
+                }
+                None => {
+                    operands.push(node_id);
+                    depth = depth.max(node_depth);
+                }
+            }
+        }
+
+        // A balanced tree of n operands has a depth of ceil(log2(n))
+        let balanced_depth = operands.len().next_power_of_two().trailing_zeros() as usize;
+        if depth <= balanced_depth {
+            return false;
+        }
+
+        // Add the operands pairwise, level by level
+        let mut level = operands;
+        while level.len() > 2 {
+            let mut next_level = Vec::with_capacity(level.len().div_ceil(2));
+            for pair in level.chunks(2) {
+                match *pair {
+                    [lh_in, rh_in] => {
+                        next_level.push(graph.add_gate(AGateType::AAdd, lh_in, rh_in));
+                    }
+                    [operand] => next_level.push(operand),
+                    _ => unreachable!(),
+                }
+            }
+            level = next_level;
+        }
+        graph.set_gate(index, AGateType::AAdd, level[0], level[1]);
+        for node_id in partial_sums {
+            graph.remove_producer(node_id);
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gate(op: AGateType, lh_in: u32, rh_in: u32, out: u32) -> ArithmeticGate {
+        ArithmeticGate::new(op, lh_in, rh_in, out)
+    }
+
+    #[test]
+    fn test_factor_common_operand() {
+        // out(6) = a(0) * b(1) + c(2) * a(0)
+        let mut graph = GateGraph::new(
+            vec![
+                gate(AGateType::AMul, 0, 1, 4),
+                gate(AGateType::AMul, 2, 0, 5),
+                gate(AGateType::AAdd, 4, 5, 6),
+            ],
+            HashSet::from([6]),
+        );
+
+        let stats = apply_rules(&mut graph, &[&FactorCommonOperand], 10);
+
+        assert_eq!(stats.rewrites["factor_common_operand"], 1);
+        assert_eq!(stats.iterations, 2);
+
+        // The sum is a fresh node, so signals bound to the products don't get its value
+        let (gates, dead, added) = graph.into_parts();
+        assert_eq!(
+            gates,
+            vec![
+                gate(AGateType::AMul, 0, 7, 6),
+                gate(AGateType::AAdd, 1, 2, 7)
+            ]
+        );
+        assert_eq!(dead, vec![4, 5]);
+        assert_eq!(added, vec![7]);
+    }
+
+    #[test]
+    fn test_factor_common_operand_shared_products() {
+        // The product a * b is also used by another gate, so it can't be rewritten
+        let gates = vec![
+            gate(AGateType::AMul, 0, 1, 4),
+            gate(AGateType::AMul, 0, 2, 5),
+            gate(AGateType::AAdd, 4, 5, 6),
+            gate(AGateType::ASub, 4, 3, 7),
+        ];
+        let mut graph = GateGraph::new(gates, HashSet::from([6, 7]));
+        let stats = apply_rules(&mut graph, &[&FactorCommonOperand], 10);
+        assert!(stats.rewrites.is_empty());
+
+        // Protected products are kept too
+        let gates = vec![
+            gate(AGateType::AMul, 0, 1, 4),
+            gate(AGateType::AMul, 0, 2, 5),
+            gate(AGateType::AAdd, 4, 5, 6),
+        ];
+        let mut graph = GateGraph::new(gates, HashSet::from([5, 6]));
+        let stats = apply_rules(&mut graph, &[&FactorCommonOperand], 10);
+        assert!(stats.rewrites.is_empty());
+    }
+
+    /// Evaluates the node and returns its value and depth, with each leaf `i` worth `2^i`.
+    fn evaluate(graph: &GateGraph, node_id: u32) -> (u32, usize) {
+        match graph.producer(node_id) {
+            Some(gate) => {
+                let (lhs, lhs_depth) = evaluate(graph, gate.lh_in);
+                let (rhs, rhs_depth) = evaluate(graph, gate.rh_in);
+                (lhs + rhs, lhs_depth.max(rhs_depth) + 1)
+            }
+            None => (1 << node_id, 0),
+        }
+    }
+
+    #[test]
+    fn test_balance_additions() {
+        // out(14) = ((((((x0 + x1) + x2) + x3) + x4) + x5) + x6) + x7
+        let mut gates = vec![gate(AGateType::AAdd, 0, 1, 8)];
+        for leaf in 2..8 {
+            gates.push(gate(AGateType::AAdd, leaf + 6, leaf, leaf + 7));
+        }
+        let mut graph = GateGraph::new(gates, HashSet::from([14]));
+        assert_eq!(evaluate(&graph, 14), (0xff, 7));
+
+        let stats = apply_rules(&mut graph, &[&BalanceAdditions], 10);
+        assert_eq!(stats.rewrites["balance_additions"], 1);
+        assert_eq!(stats.iterations, 2);
+        assert_eq!(evaluate(&graph, 14), (0xff, 3));
+
+        // The partial sums of the chain are replaced by fresh nodes
+        let (gates, dead, added) = graph.into_parts();
+        assert_eq!(gates.len(), 7);
+        assert_eq!(dead, (8..14).collect::<Vec<u32>>());
+        assert_eq!(added, (15..21).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn test_balance_additions_shared_partial_sums() {
+        // out(10) = (((x0 + x1) + x2) + x3) + x4, with x0 + x1 + x2 also an output
+        let gates = vec![
+            gate(AGateType::AAdd, 0, 1, 6),
+            gate(AGateType::AAdd, 6, 2, 7),
+            gate(AGateType::AAdd, 7, 3, 8),
+            gate(AGateType::AAdd, 8, 4, 10),
+        ];
+        let mut graph = GateGraph::new(gates, HashSet::from([7, 10]));
+        let stats = apply_rules(&mut graph, &[&BalanceAdditions], 10);
+
+        // The shared partial sum is an operand of the rest of the chain, which is short enough
+        assert!(stats.rewrites.is_empty());
+        assert_eq!(evaluate(&graph, 7), (0b111, 2));
+        assert_eq!(evaluate(&graph, 10), (0b11111, 4));
+
+        // Chains under other gates are rebalanced from their last addition
+        let gates = vec![
+            gate(AGateType::AAdd, 0, 1, 6),
+            gate(AGateType::AAdd, 6, 2, 7),
+            gate(AGateType::AAdd, 7, 3, 8),
+            gate(AGateType::AMul, 8, 4, 10),
+        ];
+        let mut graph = GateGraph::new(gates, HashSet::from([10]));
+        let stats = apply_rules(&mut graph, &[&BalanceAdditions], 10);
+        assert_eq!(stats.rewrites["balance_additions"], 1);
+        assert_eq!(evaluate(&graph, 8), (0b1111, 2));
+        assert_eq!(graph.producer(8).unwrap().lh_in, 11);
+        assert_eq!(graph.producer(11).unwrap().rh_in, 1);
+        assert_eq!(graph.producer(12).unwrap().lh_in, 2);
+        assert!(graph.producer(6).is_none() && graph.producer(7).is_none());
+    }
+}
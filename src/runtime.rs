@@ -2,19 +2,34 @@
 //!
 //! This module manages the main runtime, keeping track of the multiple contexts and data items in the program.
 
+use crate::cli::ConstraintEquality;
+pub use crate::diagnostics::Diagnostic;
+use crate::field::FieldElement;
+use crate::prebuild::PrebuiltInstances;
+use crate::process::InstanceWorkers;
 use crate::program::ProgramError;
 use circom_program_structure::ast::VariableType;
-use rand::{thread_rng, Rng};
 use std::{
-    cell::RefCell,
     collections::{HashMap, HashSet, VecDeque},
     fmt::Write,
-    rc::Rc,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
 };
 use thiserror::Error;
 
 pub const RETURN_VAR: &str = "function_return_value";
 
+/// Prefix of the names generated for intermediate values, e.g. `%random_3`. Circom identifiers
+/// can't contain it, so generated names never clash with the names of a program.
+pub const INTERNAL_PREFIX: char = '%';
+
+/// Returns whether a signal name, e.g. `0.%random_3[1]`, was generated for an intermediate value.
+pub fn is_internal_name(name: &str) -> bool {
+    name.contains(INTERNAL_PREFIX)
+}
+
 /// Data type
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum DataType {
@@ -51,11 +66,103 @@ pub enum SubAccess {
     Component(String),
 }
 
+/// Information about a template instantiation, used to name its context.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InstanceInfo<'a> {
+    /// Name of the instantiated template.
+    pub template: &'a str,
+    /// Component the instance is assigned to, e.g. `dense[3]`, if known.
+    pub component: Option<&'a str>,
+    /// Name of the context the template is instantiated from.
+    pub parent: &'a str,
+}
+
+/// Callback that names the context of a template instance.
+pub type ContextNamer = Arc<dyn Fn(&InstanceInfo) -> String + Send + Sync>;
+
+/// Names instance contexts after the component they are assigned to, e.g. `dense[3]`.
+/// Instances created inside other instances are qualified by their parent, e.g. `outer[1].inner[0]`.
+pub fn index_context_namer(info: &InstanceInfo) -> String {
+    match info.component {
+        Some(component) if info.parent == "0" => component.to_string(),
+        Some(component) => format!("{}.{}", info.parent, component),
+        None => info.template.to_string(),
+    }
+}
+
+/// Number of statements and expressions evaluated at compile time.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StepStats {
+    pub statements: u64,
+    pub expressions: u64,
+}
+
+impl StepStats {
+    /// Returns the total number of evaluation steps.
+    pub fn total(&self) -> u64 {
+        self.statements + self.expressions
+    }
+}
+
+/// Point at which processing stops early, leaving a partial circuit.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StopAfter {
+    /// After the given number of template instances were processed.
+    Instances(u64),
+    /// After the first instance of the given template, or the instance with the given name.
+    Component(String),
+}
+
+impl StopAfter {
+    /// Parses a number of instances, or a template or instance name.
+    pub fn parse(value: &str) -> Self {
+        match value.parse() {
+            Ok(count) => StopAfter::Instances(count),
+            Err(_) => StopAfter::Component(value.to_string()),
+        }
+    }
+}
+
 /// Manages a stack of execution contexts for a runtime environment.
-#[derive(Debug, Default)]
+#[derive(Default)]
 pub struct Runtime {
     contexts: VecDeque<Context>,
-    next_signal_id: Rc<RefCell<u32>>,
+    next_signal_id: SignalGen,
+    context_namer: Option<ContextNamer>,
+    pending_component: Option<String>,
+    pending_parallel: bool,
+    steps: StepStats,
+    max_steps: Option<u64>,
+    gate_budgets: HashMap<String, usize>,
+    stop_after: Option<StopAfter>,
+    instances: u64,
+    recover: bool,
+    diagnostics: Vec<Diagnostic>,
+    constraint_equality: ConstraintEquality,
+    instance_workers: Option<InstanceWorkers>,
+    prebuilt: Option<Arc<PrebuiltInstances>>,
+}
+
+impl std::fmt::Debug for Runtime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Runtime")
+            .field("contexts", &self.contexts)
+            .field("next_signal_id", &self.next_signal_id)
+            .field("context_namer", &self.context_namer.is_some())
+            .field("pending_component", &self.pending_component)
+            .field("pending_parallel", &self.pending_parallel)
+            .field("steps", &self.steps)
+            .field("max_steps", &self.max_steps)
+            .field("gate_budgets", &self.gate_budgets)
+            .field("stop_after", &self.stop_after)
+            .field("instances", &self.instances)
+            .field("recover", &self.recover)
+            .field("diagnostics", &self.diagnostics)
+            .field("constraint_equality", &self.constraint_equality)
+            .field("instance_workers", &self.instance_workers.is_some())
+            .field("prebuilt", &self.prebuilt.is_some())
+            .finish()
+    }
 }
 
 impl Runtime {
@@ -63,10 +170,242 @@ impl Runtime {
     pub fn new() -> Self {
         Self {
             contexts: VecDeque::from([Context::new("0".to_string())]),
-            next_signal_id: Rc::new(RefCell::new(0)),
+            next_signal_id: SignalGen::default(),
+            context_namer: None,
+            pending_component: None,
+            pending_parallel: false,
+            steps: StepStats::default(),
+            max_steps: None,
+            gate_budgets: HashMap::new(),
+            stop_after: None,
+            instances: 0,
+            recover: false,
+            diagnostics: Vec::new(),
+            constraint_equality: ConstraintEquality::default(),
+            instance_workers: None,
+            prebuilt: None,
+        }
+    }
+
+    /// Creates a runtime with the settings of this one and a new context stack, allocating signal
+    /// ids from `signal_gen`, e.g. to process a component on a worker thread. The stop point isn't
+    /// kept, the instances processed by each runtime are counted separately.
+    pub fn fork(&self, signal_gen: SignalGen) -> Runtime {
+        Runtime {
+            next_signal_id: signal_gen,
+            context_namer: self.context_namer.clone(),
+            max_steps: self.max_steps,
+            gate_budgets: self.gate_budgets.clone(),
+            recover: self.recover,
+            constraint_equality: self.constraint_equality,
+            prebuilt: self.prebuilt.clone(),
+            ..Runtime::new()
         }
     }
 
+    /// Processes the components instantiated from now on with the given workers, see
+    /// `process::InstanceWorkers`.
+    pub fn set_instance_workers(&mut self, instance_workers: InstanceWorkers) {
+        self.instance_workers = Some(instance_workers);
+    }
+
+    /// Returns the workers processing the components instantiated by this runtime, if any.
+    pub fn instance_workers(&mut self) -> Option<&mut InstanceWorkers> {
+        self.instance_workers.as_mut()
+    }
+
+    /// Links the template instances held by the given archives instead of processing them, see
+    /// `Args::prebuilt`.
+    pub fn set_prebuilt(&mut self, prebuilt: Arc<PrebuiltInstances>) {
+        self.prebuilt = Some(prebuilt);
+    }
+
+    /// Returns the archives linked in place of the template instances they hold, if any.
+    pub fn prebuilt(&self) -> Option<Arc<PrebuiltInstances>> {
+        self.prebuilt.clone()
+    }
+
+    /// Enables the recovery mode, in which statements failing with a recoverable error are
+    /// skipped and the error is recorded, instead of aborting the processing.
+    pub fn set_recover(&mut self, recover: bool) {
+        self.recover = recover;
+    }
+
+    /// Returns whether the recovery mode is enabled.
+    pub fn is_recovering(&self) -> bool {
+        self.recover
+    }
+
+    /// Sets how constraint equalities, `a === b`, are handled.
+    pub fn set_constraint_equality(&mut self, constraint_equality: ConstraintEquality) {
+        self.constraint_equality = constraint_equality;
+    }
+
+    /// Returns how constraint equalities are handled.
+    pub fn constraint_equality(&self) -> ConstraintEquality {
+        self.constraint_equality
+    }
+
+    /// Returns the name of the context that was current when the stack had the given number of
+    /// contexts, e.g. to name the caller of a call that failed. Empty if there is none.
+    pub fn context_name_at(&self, depth: usize) -> String {
+        self.contexts
+            .len()
+            .checked_sub(depth)
+            .and_then(|index| self.contexts.get(index))
+            .map_or_else(String::new, |context| context.get_ctx_name())
+    }
+
+    /// Records an error in the current context.
+    pub fn record_diagnostic(&mut self, message: String) {
+        let context = self.context_name_at(self.contexts.len());
+        self.diagnostics.push(Diagnostic::error(context, message));
+    }
+
+    /// Records a program error in the current context, along with its code and span.
+    pub fn record_error(&mut self, error: &ProgramError) {
+        let context = self.context_name_at(self.contexts.len());
+        self.diagnostics.push(Diagnostic {
+            context,
+            ..Diagnostic::from_error(error)
+        });
+    }
+
+    /// Returns the errors recorded in recovery mode, in the order they occurred.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// Returns the number of contexts in the stack.
+    pub fn context_depth(&self) -> usize {
+        self.contexts.len()
+    }
+
+    /// Drops the contexts pushed above the given depth, e.g. by a call that failed before
+    /// popping its context.
+    pub fn restore_context_depth(&mut self, depth: usize) {
+        while self.contexts.len() > depth {
+            self.contexts.pop_front();
+        }
+    }
+
+    /// Sets the point after which processing stops with `RuntimeError::Stopped`.
+    pub fn set_stop_after(&mut self, stop_after: StopAfter) {
+        self.stop_after = Some(stop_after);
+    }
+
+    /// Counts a processed template instance, failing with `RuntimeError::Stopped` if processing
+    /// should stop after it.
+    pub fn count_instance(&mut self, template: &str, instance: &str) -> Result<(), RuntimeError> {
+        self.instances += 1;
+        let stop = match &self.stop_after {
+            Some(StopAfter::Instances(count)) => self.instances >= *count,
+            Some(StopAfter::Component(name)) => name == template || name == instance,
+            None => false,
+        };
+
+        if stop {
+            Err(RuntimeError::Stopped(instance.to_string()))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Sets the maximum number of gates each instance of a template may contribute, keyed by
+    /// template name. The gates of nested components count towards the budget of their parents.
+    pub fn set_gate_budgets(&mut self, gate_budgets: HashMap<String, usize>) {
+        self.gate_budgets = gate_budgets;
+    }
+
+    /// Returns the gate budget of the given template, if any.
+    pub fn gate_budget(&self, template: &str) -> Option<usize> {
+        self.gate_budgets.get(template).copied()
+    }
+
+    /// Sets the maximum number of statements and expressions evaluated before compilation is
+    /// aborted with `RuntimeError::StepLimitExceeded`.
+    pub fn set_max_steps(&mut self, max_steps: u64) {
+        self.max_steps = Some(max_steps);
+    }
+
+    /// Returns the number of statements and expressions evaluated so far.
+    pub fn step_stats(&self) -> StepStats {
+        self.steps
+    }
+
+    /// Counts the evaluation of a statement, failing if the step limit is exceeded.
+    pub fn count_statement(&mut self) -> Result<(), RuntimeError> {
+        self.steps.statements += 1;
+        self.check_step_limit()
+    }
+
+    /// Counts the evaluation of an expression, failing if the step limit is exceeded.
+    pub fn count_expression(&mut self) -> Result<(), RuntimeError> {
+        self.steps.expressions += 1;
+        self.check_step_limit()
+    }
+
+    /// Counts the steps of a part of the program processed by another runtime, e.g. one returned by
+    /// `fork`, failing if the step limit is exceeded.
+    pub fn add_steps(&mut self, steps: StepStats) -> Result<(), RuntimeError> {
+        self.steps.statements += steps.statements;
+        self.steps.expressions += steps.expressions;
+        self.check_step_limit()
+    }
+
+    fn check_step_limit(&self) -> Result<(), RuntimeError> {
+        match self.max_steps {
+            Some(max_steps) if self.steps.total() > max_steps => {
+                Err(RuntimeError::StepLimitExceeded(max_steps))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Sets the callback used to name the contexts of template instances.
+    /// Without one, contexts are named after their template.
+    pub fn set_context_namer(&mut self, namer: ContextNamer) {
+        self.context_namer = Some(namer);
+    }
+
+    /// Records the component the next template instance will be assigned to.
+    pub fn set_pending_component(&mut self, component: String) {
+        self.pending_component = Some(component);
+    }
+
+    /// Returns the component the next template instance will be assigned to, if known.
+    pub fn pending_component(&self) -> Option<&str> {
+        self.pending_component.as_deref()
+    }
+
+    /// Records that the next template instance is annotated with `parallel`.
+    pub fn set_pending_parallel(&mut self) {
+        self.pending_parallel = true;
+    }
+
+    /// Returns whether the next template instance is annotated with `parallel`, consuming the
+    /// annotation.
+    pub fn take_pending_parallel(&mut self) -> bool {
+        std::mem::take(&mut self.pending_parallel)
+    }
+
+    /// Returns the context name for a new instance of the given template, consuming the pending
+    /// component name.
+    pub fn instance_context_name(&mut self, template: &str) -> Result<String, RuntimeError> {
+        let component = self.pending_component.take();
+        let namer = match &self.context_namer {
+            Some(namer) => namer.clone(),
+            None => return Ok(template.to_string()),
+        };
+
+        let parent = self.current_context()?.get_ctx_name();
+        Ok(namer(&InstanceInfo {
+            template,
+            component: component.as_deref(),
+            parent: &parent,
+        }))
+    }
+
     /// Adds a new context onto the stack, optionally inheriting from the current context.
     pub fn push_context(&mut self, inherit: bool, id: String) -> Result<(), RuntimeError> {
         let new_context = if inherit {
@@ -111,17 +450,76 @@ impl Runtime {
             .ok_or(RuntimeError::EmptyContextStack)
     }
 
-    /// Returns a clone of the Rc<RefCell<u32>> for next_signal_id.
-    pub fn get_signal_gen(&self) -> Rc<RefCell<u32>> {
-        Rc::clone(&self.next_signal_id)
+    /// Returns the allocator of signal ids, sharing its counter with the runtime.
+    pub fn get_signal_gen(&self) -> SignalGen {
+        self.next_signal_id.clone()
+    }
+
+    /// Allocates signal ids from the given allocator instead, e.g. one of the ranges of
+    /// `SignalGen::partition` for a runtime processing part of a program on another thread.
+    pub fn set_signal_gen(&mut self, signal_gen: SignalGen) {
+        self.next_signal_id = signal_gen;
     }
+}
 
-    /// Generates a new unique signal ID.
-    fn gen_signal(next_signal_id: Rc<RefCell<u32>>) -> u32 {
-        let mut id_ref = next_signal_id.borrow_mut();
-        let id = *id_ref;
-        *id_ref += 1;
-        id
+/// Allocator of signal ids. Clones share the same counter, which is atomic so that an allocator
+/// can be shared between threads, or split with `partition` into allocators of disjoint ranges,
+/// e.g. one per worker, whose ids don't depend on the order the workers run in.
+#[derive(Clone, Debug)]
+pub struct SignalGen {
+    next: Arc<AtomicU32>,
+    end: u32,
+}
+
+impl Default for SignalGen {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl SignalGen {
+    /// Creates an allocator of the ids from `start`.
+    pub fn new(start: u32) -> Self {
+        Self::with_range(start, u32::MAX)
+    }
+
+    /// Creates an allocator of the ids from `start` to `end`, excluded.
+    pub fn with_range(start: u32, end: u32) -> Self {
+        Self {
+            next: Arc::new(AtomicU32::new(start)),
+            end,
+        }
+    }
+
+    /// Returns the id the next allocation returns, without allocating it.
+    pub fn peek(&self) -> u32 {
+        self.next.load(Ordering::Relaxed)
+    }
+
+    /// Allocates a new id.
+    pub fn next_id(&self) -> Result<u32, RuntimeError> {
+        self.next
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |id| {
+                (id < self.end).then_some(id + 1)
+            })
+            .map_err(|_| RuntimeError::SignalIdsExhausted)
+    }
+
+    /// Reserves `count` consecutive ranges of `size` ids, returning an allocator for each.
+    pub fn partition(&self, count: u32, size: u32) -> Result<Vec<SignalGen>, RuntimeError> {
+        let total = count
+            .checked_mul(size)
+            .ok_or(RuntimeError::SignalIdsExhausted)?;
+        let start = self
+            .next
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |id| {
+                id.checked_add(total).filter(|&end| end <= self.end)
+            })
+            .map_err(|_| RuntimeError::SignalIdsExhausted)?;
+
+        Ok((0..count)
+            .map(|i| SignalGen::with_range(start + i * size, start + (i + 1) * size))
+            .collect())
     }
 }
 
@@ -130,9 +528,16 @@ impl Runtime {
 pub struct Context {
     ctx_name: String,
     names: HashSet<String>,
+    /// Signals hoisted into this context from its blocks.
+    hoisted: HashSet<String>,
+    /// Hoisted signals this context may declare again, i.e. the next execution of the block
+    /// that declared them.
+    redeclarable: HashSet<String>,
     variables: HashMap<String, Variable>,
     signals: HashMap<String, Signal>,
     components: HashMap<String, Component>,
+    /// Suffix of the next generated name, see `unique_name`.
+    next_name: u32,
 }
 
 impl Context {
@@ -141,9 +546,12 @@ impl Context {
         Self {
             ctx_name,
             names: HashSet::new(),
+            hoisted: HashSet::new(),
+            redeclarable: HashSet::new(),
             variables: HashMap::new(),
             signals: HashMap::new(),
             components: HashMap::new(),
+            next_name: 0,
         }
     }
 
@@ -152,9 +560,12 @@ impl Context {
         Self {
             ctx_name: self.ctx_name.clone(),
             names: self.names.clone(),
+            hoisted: self.hoisted.clone(),
+            redeclarable: self.hoisted.union(&self.redeclarable).cloned().collect(),
             variables: self.variables.clone(),
             signals: self.signals.clone(),
             components: self.components.clone(),
+            next_name: self.next_name,
         }
     }
 
@@ -163,8 +574,17 @@ impl Context {
     }
 
     /// Merges changes from the given context into this context.
-    /// Signals are not merged, as they are read-only.
+    /// Signals declared in the child context are hoisted into this context, like circom does with
+    /// signals declared inside conditional and loop bodies. Inherited signals are read-only.
     pub fn merge(&mut self, child: &Context) -> Result<(), RuntimeError> {
+        for (name, signal) in &child.signals {
+            if self.signals.get(name) != Some(signal) {
+                self.names.insert(name.clone());
+                self.hoisted.insert(name.clone());
+                self.signals.insert(name.clone(), signal.clone());
+            }
+        }
+
         for (name, variable) in &child.variables {
             if self.variables.contains_key(name) {
                 self.variables.insert(name.clone(), variable.clone());
@@ -183,6 +603,9 @@ impl Context {
             }
         }
 
+        // Names generated in the child may have been hoisted, so they aren't generated again
+        self.next_name = self.next_name.max(child.next_name);
+
         Ok(())
     }
 
@@ -192,19 +615,23 @@ impl Context {
         data_type: DataType,
         name: &str,
         dimensions: &[u32],
-        next_signal_id: Rc<RefCell<u32>>,
+        next_signal_id: SignalGen,
     ) -> Result<(), RuntimeError> {
         // Parse name
         let name = name.to_string();
 
-        // Check availability. Ignore variables redeclaration.
-        if !self.names.insert(name.clone()) && data_type != DataType::Variable {
+        // Check availability. Ignore variables redeclaration, and allow signals hoisted from a
+        // block to be declared again, once, by the next execution of the block (e.g. a loop
+        // iteration). The scope they were hoisted into can't declare them again.
+        let redeclarable = data_type == DataType::Variable
+            || (data_type == DataType::Signal && self.redeclarable.remove(&name));
+        if !self.names.insert(name.clone()) && !redeclarable {
             return Err(RuntimeError::ItemAlreadyDeclared);
         }
 
         match data_type {
             DataType::Signal => {
-                let signal = Signal::new(dimensions, next_signal_id);
+                let signal = Signal::new(dimensions, next_signal_id)?;
                 self.signals.insert(name, signal);
             }
             DataType::Variable => {
@@ -220,17 +647,51 @@ impl Context {
         Ok(())
     }
 
-    /// Declares a new item with a random name.
+    /// Returns an internal name with the given prefix that isn't declared in the context, e.g.
+    /// `%random_3`.
+    /// Names are numbered in the order they are generated, so the names of a program don't
+    /// change from one compilation to the next.
+    pub fn unique_name(&mut self, prefix: &str) -> String {
+        loop {
+            let name = format!("{}{}_{}", INTERNAL_PREFIX, prefix, self.next_name);
+            self.next_name += 1;
+            if !self.names.contains(&name) {
+                return name;
+            }
+        }
+    }
+
+    /// Declares a new item with a generated name, for intermediate values.
     pub fn declare_random_item(
         &mut self,
-        next_signal_id: Rc<RefCell<u32>>,
+        next_signal_id: SignalGen,
         data_type: DataType,
     ) -> Result<DataAccess, RuntimeError> {
-        let name = format!("random_{}", generate_u32());
+        let name = self.unique_name("random");
         self.declare_item(data_type, &name, &[], next_signal_id)?;
         Ok(DataAccess::new(&name, vec![]))
     }
 
+    /// Declares a signal with a generated name holding the given signal ids, e.g. an array
+    /// literal of existing signals.
+    pub fn declare_random_signal(
+        &mut self,
+        content: NestedValue<u32>,
+    ) -> Result<DataAccess, RuntimeError> {
+        let name = self.unique_name("random");
+        if !self.names.insert(name.clone()) {
+            return Err(RuntimeError::ItemAlreadyDeclared);
+        }
+        self.signals.insert(
+            name.clone(),
+            Signal {
+                value: Arc::new(content),
+                tags: Vec::new(),
+            },
+        );
+        Ok(DataAccess::new(&name, vec![]))
+    }
+
     /// Returns the data type of an item.
     pub fn get_item_data_type(&self, name: &str) -> Result<DataType, RuntimeError> {
         if self.variables.contains_key(name) {
@@ -251,7 +712,7 @@ impl Context {
     pub fn set_variable(
         &mut self,
         access: &DataAccess,
-        value: Option<u32>,
+        value: Option<FieldElement>,
     ) -> Result<(), RuntimeError> {
         let variable =
             self.variables
@@ -264,6 +725,24 @@ impl Context {
         variable.set(&access_to_u32(access.get_access())?, value)
     }
 
+    /// Sets the content of a variable at the given access, replacing a whole sub-array if the
+    /// access doesn't point to a single value.
+    pub fn set_variable_content(
+        &mut self,
+        access: &DataAccess,
+        content: NestedValue<Option<FieldElement>>,
+    ) -> Result<(), RuntimeError> {
+        let variable =
+            self.variables
+                .get_mut(&access.name)
+                .ok_or(RuntimeError::ItemNotDeclared(format!(
+                    "set_variable_content: {:?}",
+                    access
+                )))?;
+
+        variable.set_content(&access_to_u32(access.get_access())?, content)
+    }
+
     /// Gets a variable whole content.
     pub fn get_variable(&self, name: &str) -> Result<Variable, RuntimeError> {
         self.variables
@@ -279,7 +758,7 @@ impl Context {
     pub fn get_variable_content(
         &self,
         access: &DataAccess,
-    ) -> Result<NestedValue<Option<u32>>, RuntimeError> {
+    ) -> Result<NestedValue<Option<FieldElement>>, RuntimeError> {
         let variable = self
             .variables
             .get(&access.name)
@@ -292,7 +771,10 @@ impl Context {
     }
 
     /// Gets the content of a variable.
-    pub fn get_variable_value(&self, access: &DataAccess) -> Result<Option<u32>, RuntimeError> {
+    pub fn get_variable_value(
+        &self,
+        access: &DataAccess,
+    ) -> Result<Option<FieldElement>, RuntimeError> {
         let variable = self
             .variables
             .get(&access.name)
@@ -315,6 +797,31 @@ impl Context {
             .cloned()
     }
 
+    /// Sets the tags of a signal, applying to all of its elements.
+    pub fn set_signal_tags(&mut self, name: &str, tags: Vec<String>) -> Result<(), RuntimeError> {
+        let signal = self
+            .signals
+            .get_mut(name)
+            .ok_or(RuntimeError::ItemNotDeclared(format!(
+                "set_signal_tags: {}",
+                name
+            )))?;
+        signal.tags = tags;
+
+        Ok(())
+    }
+
+    /// Gets the tags of a signal.
+    pub fn get_signal_tags(&self, name: &str) -> Result<&[String], RuntimeError> {
+        self.signals
+            .get(name)
+            .map(|signal| signal.tags.as_slice())
+            .ok_or(RuntimeError::ItemNotDeclared(format!(
+                "get_signal_tags: {}",
+                name
+            )))
+    }
+
     /// Gets a signal content at the specified index path.
     pub fn get_signal_content(
         &self,
@@ -349,10 +856,7 @@ impl Context {
     }
 
     /// Gets a component.
-    pub fn get_component_map(
-        &self,
-        access: &DataAccess,
-    ) -> Result<HashMap<String, Signal>, RuntimeError> {
+    pub fn get_component_map(&self, access: &DataAccess) -> Result<SignalMap, RuntimeError> {
         let component = self
             .components
             .get(&access.name)
@@ -361,12 +865,14 @@ impl Context {
                 access
             )))?;
 
-        component.get_map(&access_to_u32(access.get_access())?)
+        component
+            .get_map(&access_to_u32(access.get_access())?)
+            .map_err(|error| name_instance(&access.name, error))
     }
 
     /// Gets the id of a component's signal.
     pub fn get_component_signal_id(&self, access: &DataAccess) -> Result<u32, RuntimeError> {
-        let (component_access, signal_access) = process_component_access(access)?;
+        let (component_access, signal_path) = split_component_access(access)?;
         let component =
             self.components
                 .get(&component_access.name)
@@ -375,10 +881,9 @@ impl Context {
                     access
                 )))?;
 
-        component.get_signal_id(
-            &access_to_u32(component_access.get_access())?,
-            &signal_access,
-        )
+        component
+            .get_signal_id(&access_to_u32(component_access.get_access())?, &signal_path)
+            .map_err(|error| name_instance(&component_access.name, error))
     }
 
     /// Gets the content of a component's signal.
@@ -386,7 +891,7 @@ impl Context {
         &self,
         access: &DataAccess,
     ) -> Result<NestedValue<u32>, RuntimeError> {
-        let (component_access, signal_access) = process_component_access(access)?;
+        let (component_access, signal_path) = split_component_access(access)?;
         let component =
             self.components
                 .get(&component_access.name)
@@ -395,17 +900,16 @@ impl Context {
                     access
                 )))?;
 
-        component.get_signal_content(
-            &access_to_u32(component_access.get_access())?,
-            &signal_access,
-        )
+        component
+            .get_signal_content(&access_to_u32(component_access.get_access())?, &signal_path)
+            .map_err(|error| name_instance(&component_access.name, error))
     }
 
     /// Sets a component's input/output signal map.
     pub fn set_component(
         &mut self,
         access: &DataAccess,
-        map: HashMap<String, Signal>,
+        map: impl Into<SignalMap>,
     ) -> Result<(), RuntimeError> {
         let component =
             self.components
@@ -415,38 +919,41 @@ impl Context {
                     access
                 )))?;
 
-        component.set_signal_map(&access_to_u32(access.get_access())?, map)
+        component.set_signal_map(&access_to_u32(access.get_access())?, map.into())
     }
 }
 
 /// Represents a signal that holds a single id or a nested structure of values with unique IDs.
+/// Ids never change once generated, so the structure is shared between clones.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Signal {
-    value: NestedValue<u32>,
+    value: Arc<NestedValue<u32>>,
+    /// Tags of the declaration, e.g. `binary` for `signal input in{binary}[n]`.
+    tags: Vec<String>,
 }
 
 impl Signal {
     /// Constructs a new Signal as a nested structure based on provided dimensions.
-    fn new(dimensions: &[u32], next_signal_id: Rc<RefCell<u32>>) -> Self {
+    fn new(dimensions: &[u32], next_signal_id: SignalGen) -> Result<Self, RuntimeError> {
         fn create_nested_signal(
             dimensions: &[u32],
-            next_signal_id: Rc<RefCell<u32>>,
-        ) -> NestedValue<u32> {
+            next_signal_id: &SignalGen,
+        ) -> Result<NestedValue<u32>, RuntimeError> {
             if let Some((&first, rest)) = dimensions.split_first() {
                 let array = (0..first)
-                    .map(|_| create_nested_signal(rest, next_signal_id.clone()))
-                    .collect();
-                NestedValue::Array(array)
+                    .map(|_| create_nested_signal(rest, next_signal_id))
+                    .collect::<Result<_, _>>()?;
+                Ok(NestedValue::Array(array))
             } else {
                 // Generate a new signal ID
-                let id = Runtime::gen_signal(next_signal_id);
-                NestedValue::Value(id)
+                Ok(NestedValue::Value(next_signal_id.next_id()?))
             }
         }
 
-        Self {
-            value: create_nested_signal(dimensions, next_signal_id),
-        }
+        Ok(Self {
+            value: Arc::new(create_nested_signal(dimensions, &next_signal_id)?),
+            tags: Vec::new(),
+        })
     }
 
     /// Retrieves the nested value at the specified index path.
@@ -456,8 +963,8 @@ impl Signal {
 
     // Retrieves the id of the signal at the specified index path.
     fn get_id(&self, index_path: &[u32]) -> Result<u32, RuntimeError> {
-        match self.get(index_path)? {
-            NestedValue::Value(id) => Ok(id),
+        match get_nested_ref(&self.value, index_path)? {
+            NestedValue::Value(id) => Ok(*id),
             NestedValue::Array(_) => Err(RuntimeError::NotAValue),
         }
     }
@@ -466,7 +973,7 @@ impl Signal {
 /// Represents a variable that can hold a single value or nested structure of values.
 #[derive(Clone, Debug)]
 pub struct Variable {
-    value: NestedValue<Option<u32>>,
+    value: NestedValue<Option<FieldElement>>,
 }
 
 impl Variable {
@@ -485,7 +992,7 @@ impl Variable {
     }
 
     /// Sets the content of the variable at the specified index path.
-    fn set(&mut self, index_path: &[u32], val: Option<u32>) -> Result<(), RuntimeError> {
+    fn set(&mut self, index_path: &[u32], val: Option<FieldElement>) -> Result<(), RuntimeError> {
         let inner_value = get_mut_nested_value(&mut self.value, index_path)?;
 
         match inner_value {
@@ -497,13 +1004,23 @@ impl Variable {
         }
     }
 
+    /// Sets the nested content of the variable at the specified index path.
+    fn set_content(
+        &mut self,
+        index_path: &[u32],
+        content: NestedValue<Option<FieldElement>>,
+    ) -> Result<(), RuntimeError> {
+        *get_mut_nested_value(&mut self.value, index_path)? = content;
+        Ok(())
+    }
+
     /// Retrieves the content of the variable at the specified index path.
-    fn get(&self, index_path: &[u32]) -> Result<NestedValue<Option<u32>>, RuntimeError> {
+    fn get(&self, index_path: &[u32]) -> Result<NestedValue<Option<FieldElement>>, RuntimeError> {
         get_nested_value(&self.value, index_path)
     }
 
     /// Retrieves the value of the variable at the specified index path.
-    fn get_value(&self, index_path: &[u32]) -> Result<Option<u32>, RuntimeError> {
+    fn get_value(&self, index_path: &[u32]) -> Result<Option<FieldElement>, RuntimeError> {
         match self.get(index_path)? {
             NestedValue::Value(val) => Ok(val),
             NestedValue::Array(_) => Err(RuntimeError::NotAValue),
@@ -511,16 +1028,22 @@ impl Variable {
     }
 }
 
-/// Stores a component's input/output signals with their respective identifiers.
+/// Input and output signals of a component instance, by name. Shared between the contexts and
+/// components referring to the instance, since it isn't modified once the instance is created.
+pub type SignalMap = Arc<HashMap<String, Signal>>;
+
+/// Stores a component's input/output signals with their respective identifiers. The elements of
+/// a component array are instantiated one by one, e.g. `c[i] = Foo()` in a loop, and have no
+/// signals until then.
 #[derive(Clone, Debug)]
 pub struct Component {
-    signal_map: NestedValue<HashMap<String, Signal>>,
+    signal_map: NestedValue<Option<SignalMap>>,
 }
 
 impl Component {
     /// Constructs a new Component as a nested structure based on provided dimensions.
     fn new(dimensions: &[u32]) -> Self {
-        let mut signal_map = NestedValue::Value(HashMap::new());
+        let mut signal_map = NestedValue::Value(None);
 
         // Construct the nested structure in reverse order to ensure the correct dimensionality.
         for &dimension in dimensions.iter().rev() {
@@ -532,11 +1055,21 @@ impl Component {
     }
 
     /// Retrieves the component signal map at the specified index path.
-    fn get_map(&self, index_path: &[u32]) -> Result<HashMap<String, Signal>, RuntimeError> {
-        let nested_val = get_nested_value(&self.signal_map, index_path)?;
-
-        match nested_val {
-            NestedValue::Value(map) => Ok(map),
+    fn get_map(&self, index_path: &[u32]) -> Result<SignalMap, RuntimeError> {
+        self.instance(index_path).map(Arc::clone)
+    }
+
+    /// Returns the signal map of the instance at the specified index path, failing if it hasn't
+    /// been instantiated yet.
+    fn instance(&self, index_path: &[u32]) -> Result<&SignalMap, RuntimeError> {
+        match get_nested_ref(&self.signal_map, index_path)? {
+            NestedValue::Value(Some(map)) => Ok(map),
+            NestedValue::Value(None) => Err(RuntimeError::ComponentNotInstantiated(
+                index_path
+                    .iter()
+                    .map(|index| format!("[{}]", index))
+                    .collect(),
+            )),
             NestedValue::Array(_) => Err(RuntimeError::NotAValue),
         }
     }
@@ -545,7 +1078,7 @@ impl Component {
     fn set_signal_map(
         &mut self,
         component_access: &[u32],
-        map: HashMap<String, Signal>,
+        map: SignalMap,
     ) -> Result<(), RuntimeError> {
         let nested_val = get_mut_nested_value(&mut self.signal_map, component_access)?;
 
@@ -554,7 +1087,7 @@ impl Component {
             NestedValue::Array(_) => return Err(RuntimeError::NotAValue),
         };
 
-        *nested_map = map;
+        *nested_map = Some(map);
 
         Ok(())
     }
@@ -563,22 +1096,9 @@ impl Component {
     fn get_signal_content(
         &self,
         component_access: &[u32],
-        signal_access: &DataAccess,
+        signal_path: &[DataAccess],
     ) -> Result<NestedValue<u32>, RuntimeError> {
-        let nested_val = get_nested_value(&self.signal_map, component_access)?;
-
-        let map = match nested_val {
-            NestedValue::Value(map) => map,
-            NestedValue::Array(_) => return Err(RuntimeError::NotAValue),
-        };
-
-        let signal = map
-            .get(&signal_access.get_name())
-            .ok_or(RuntimeError::ItemNotDeclared(format!(
-                "get_signal_id: {:?}",
-                signal_access
-            )))?;
-
+        let (signal, signal_access) = self.get_signal(component_access, signal_path)?;
         signal.get(&access_to_u32(signal_access.get_access())?)
     }
 
@@ -586,26 +1106,66 @@ impl Component {
     fn get_signal_id(
         &self,
         component_access: &[u32],
-        signal_access: &DataAccess,
+        signal_path: &[DataAccess],
     ) -> Result<u32, RuntimeError> {
-        let nested_val = get_nested_value(&self.signal_map, component_access)?;
+        let (signal, signal_access) = self.get_signal(component_access, signal_path)?;
+        signal.get_id(&access_to_u32(signal_access.get_access())?)
+    }
 
-        let map = match nested_val {
-            NestedValue::Value(map) => map,
-            NestedValue::Array(_) => return Err(RuntimeError::NotAValue),
-        };
+    /// Returns a signal of the component at the specified index path, along with the access of
+    /// its last segment. Signals of nested items, e.g. `b.c` for `a.b.c`, are stored under their
+    /// dotted name, so only the last segment of a nested path can be indexed.
+    fn get_signal<'a>(
+        &self,
+        component_access: &[u32],
+        signal_path: &'a [DataAccess],
+    ) -> Result<(&Signal, &'a DataAccess), RuntimeError> {
+        let map = self.instance(component_access)?;
+        let (signal_access, parents) = signal_path.split_last().ok_or(RuntimeError::AccessError)?;
+
+        let name = path_names(signal_path);
+        if let Some(signal) = map.get(&name) {
+            if parents.iter().all(|parent| parent.get_access().is_empty()) {
+                return Ok((signal, signal_access));
+            }
+        }
+        if !parents.is_empty() {
+            return Err(RuntimeError::NestedComponentAccess(path_str(signal_path)));
+        }
 
-        let signal = map
-            .get(&signal_access.get_name())
-            .ok_or(RuntimeError::ItemNotDeclared(format!(
-                "get_signal_id: {:?}",
-                signal_access
-            )))?;
+        Err(RuntimeError::ItemNotDeclared(format!(
+            "get_signal_id: {:?}",
+            signal_access
+        )))
+    }
+}
 
-        signal.get_id(&access_to_u32(signal_access.get_access())?)
+/// Names the component in the error of an element that isn't instantiated, e.g. `c[1]`.
+fn name_instance(name: &str, error: RuntimeError) -> RuntimeError {
+    match error {
+        RuntimeError::ComponentNotInstantiated(index) => {
+            RuntimeError::ComponentNotInstantiated(format!("{}{}", name, index))
+        }
+        error => error,
     }
 }
 
+/// Returns the dotted name of a nested path, e.g. `b.c`.
+fn path_names(path: &[DataAccess]) -> String {
+    path.iter()
+        .map(DataAccess::get_name)
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Returns the access string of a nested path, e.g. `b[1].c`.
+fn path_str(path: &[DataAccess]) -> String {
+    path.iter()
+        .map(DataAccess::path_str)
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
 /// Data Access structure.
 /// - The name property is used to access variables, signals and components (by name).
 /// - The access property is used to access an array index or a component signal.
@@ -646,8 +1206,12 @@ impl DataAccess {
 
     /// Gets the access string for labeling of the data item.
     pub fn access_str(&self, ctx_name: String) -> String {
-        let mut ret = format!("{}.", ctx_name);
-        ret.write_str(self.get_name().as_str()).ok();
+        format!("{}.{}", ctx_name, self.path_str())
+    }
+
+    /// Gets the access string of the data item without its context, e.g. `dense[3]`.
+    pub fn path_str(&self) -> String {
+        let mut ret = self.get_name();
         for sub in self.get_access() {
             match sub {
                 SubAccess::Array(index) => {
@@ -665,50 +1229,64 @@ impl DataAccess {
 /// Processes an access to a component's signal.
 /// Returns a tuple containing the component access, and the signal access.
 /// (component_access, signal_access)
+///
+/// Only one level of nesting is accepted, see `split_component_access` for deeper paths.
 pub fn process_component_access(
     access: &DataAccess,
 ) -> Result<(DataAccess, DataAccess), RuntimeError> {
-    let mut initial_path = Vec::new();
-    let mut final_path = Vec::new();
-    let mut signal_name = String::new();
-    let mut has_signal = false;
+    let (component_access, mut signal_path) = split_component_access(access)?;
+    if signal_path.len() > 1 {
+        return Err(RuntimeError::NestedComponentAccess(access.path_str()));
+    }
+
+    Ok((component_access, signal_path.remove(0)))
+}
+
+/// Splits an access to a component's signal at each component sub access, at any depth.
+/// Returns the component access, and the access of each nested item, the signal being last, e.g.
+/// `a[0]`, then `b[1]` and `c` for `a[0].b[1].c`.
+pub fn split_component_access(
+    access: &DataAccess,
+) -> Result<(DataAccess, Vec<DataAccess>), RuntimeError> {
+    let mut component_path = Vec::new();
+    let mut signal_path: Vec<(String, Vec<u32>)> = Vec::new();
 
     for sub_access in access.get_access() {
         match sub_access {
-            SubAccess::Array(index) => {
-                if has_signal {
-                    final_path.push(*index);
-                } else {
-                    initial_path.push(*index);
-                }
-            }
-            SubAccess::Component(name) => {
-                if has_signal {
-                    // We shouldn't have more than one signal in a sub access.
-                    return Err(RuntimeError::AccessError);
-                }
-                signal_name.clone_from(name);
-                has_signal = true;
-            }
+            SubAccess::Array(index) => match signal_path.last_mut() {
+                Some((_, path)) => path.push(*index),
+                None => component_path.push(*index),
+            },
+            SubAccess::Component(name) => signal_path.push((name.clone(), Vec::new())),
         }
     }
 
-    if !has_signal {
+    if signal_path.is_empty() {
         return Err(RuntimeError::AccessError);
     }
 
     Ok((
-        DataAccess::new(&access.get_name(), u32_to_access(&initial_path)),
-        DataAccess::new(&signal_name, u32_to_access(&final_path)),
+        DataAccess::new(&access.get_name(), u32_to_access(&component_path)),
+        signal_path
+            .iter()
+            .map(|(name, path)| DataAccess::new(name, u32_to_access(path)))
+            .collect(),
     ))
 }
 
-/// Generic function to navigate through NestedValue and return the inner value.
-/// The clone could be removed but then the type would need to implement Copy. Leaving it for now.
+/// Generic function to navigate through NestedValue and return a clone of the inner value.
 pub fn get_nested_value<T: Clone>(
     nested_value: &NestedValue<T>,
     index_path: &[u32],
 ) -> Result<NestedValue<T>, RuntimeError> {
+    get_nested_ref(nested_value, index_path).cloned()
+}
+
+/// Generic function to navigate through NestedValue and return a reference to the inner value.
+pub fn get_nested_ref<'a, T>(
+    nested_value: &'a NestedValue<T>,
+    index_path: &[u32],
+) -> Result<&'a NestedValue<T>, RuntimeError> {
     let mut current_level = nested_value;
 
     for &index in index_path {
@@ -722,7 +1300,7 @@ pub fn get_nested_value<T: Clone>(
         }
     }
 
-    Ok(current_level.clone())
+    Ok(current_level)
 }
 
 /// Generic function to navigate through NestedValue and return a mutable reference to the inner value.
@@ -762,7 +1340,54 @@ pub fn access_to_u32(sub_accesses: &[SubAccess]) -> Result<Vec<u32>, RuntimeErro
         .collect()
 }
 
-/// Increments a multi-dimensional array index.
+/// Iterator over the indices of a multi-dimensional array of the given shape, in row-major order,
+/// e.g. `[0, 0]`, `[0, 1]`, `[1, 0]` and `[1, 1]` for a 2x2 array. An array with a zero sized
+/// dimension has no indices, and a scalar (an empty shape) has a single empty index.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Indices {
+    shape: Vec<u32>,
+    next: Option<Vec<u32>>,
+    remaining: usize,
+}
+
+impl Indices {
+    pub fn new(shape: &[u32]) -> Self {
+        let remaining = shape.iter().map(|&length| length as usize).product();
+        Self {
+            shape: shape.to_vec(),
+            next: (remaining > 0).then(|| vec![0; shape.len()]),
+            remaining,
+        }
+    }
+
+    pub fn shape(&self) -> &[u32] {
+        &self.shape
+    }
+}
+
+impl Iterator for Indices {
+    type Item = Vec<u32>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let current = self.next.take()?;
+        let mut next = current.clone();
+        // Indices and shape have the same length, so incrementing can't fail
+        if increment_indices(&mut next, &self.shape).unwrap_or(false) {
+            self.next = Some(next);
+        }
+        self.remaining -= 1;
+
+        Some(current)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl ExactSizeIterator for Indices {}
+
+/// Increments a multi-dimensional array index, see `Indices` to iterate over all of them.
 /// Returns a boolean that indicates if there are more elements to traverse.
 ///
 /// * `indices` - A vector representing the current position in a multi-dimensional array.
@@ -772,6 +1397,11 @@ pub fn increment_indices(indices: &mut [u32], limits: &[u32]) -> Result<bool, Ru
         return Err(RuntimeError::AccessError);
     }
 
+    // An array with a zero sized dimension has no elements to traverse.
+    if limits.contains(&0) {
+        return Ok(false);
+    }
+
     let mut carry = true;
     for (index, &limit) in indices.iter_mut().zip(limits.iter()).rev() {
         if carry {
@@ -787,11 +1417,6 @@ pub fn increment_indices(indices: &mut [u32], limits: &[u32]) -> Result<bool, Ru
     Ok(!carry)
 }
 
-/// Generates a random u32.
-pub fn generate_u32() -> u32 {
-    thread_rng().gen()
-}
-
 #[derive(Error, Debug)]
 pub enum RuntimeError {
     #[error("Access Error")]
@@ -806,6 +1431,10 @@ pub enum RuntimeError {
     ItemAlreadyDeclared,
     #[error("Item not declared: {0}")]
     ItemNotDeclared(String),
+    #[error("Component {0} is accessed before it is instantiated")]
+    ComponentNotInstantiated(String),
+    #[error("Unsupported access to a nested component signal: {0}")]
+    NestedComponentAccess(String),
     #[error("No context to inherit from")]
     NoContextToInheritFrom,
     #[error("Data Item content is not a single value")]
@@ -814,6 +1443,14 @@ pub enum RuntimeError {
     UnsupportedDataType,
     #[error("Assertion failed")]
     AssertionFailed,
+    #[error("Constraint not satisfied: the sides are known at compile time and differ")]
+    ConstraintNotSatisfied,
+    #[error("Step limit exceeded: evaluated more than {0} statements and expressions")]
+    StepLimitExceeded(u64),
+    #[error("Processing stopped after instance {0}")]
+    Stopped(String),
+    #[error("Signal ids exhausted: the allocator ran out of its range")]
+    SignalIdsExhausted,
 }
 
 impl From<RuntimeError> for ProgramError {
@@ -851,6 +1488,84 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_runtime_step_limit() {
+        let mut runtime = Runtime::new();
+        runtime.set_max_steps(3);
+
+        runtime.count_statement().unwrap();
+        runtime.count_expression().unwrap();
+        runtime.count_expression().unwrap();
+        assert_eq!(
+            runtime.step_stats(),
+            StepStats {
+                statements: 1,
+                expressions: 2
+            }
+        );
+
+        let result = runtime.count_statement();
+        assert!(matches!(result, Err(RuntimeError::StepLimitExceeded(3))));
+        assert_eq!(runtime.step_stats().total(), 4);
+    }
+
+    #[test]
+    fn test_runtime_stop_after() {
+        assert_eq!(StopAfter::parse("2"), StopAfter::Instances(2));
+        assert_eq!(
+            StopAfter::parse("Dense"),
+            StopAfter::Component(String::from("Dense"))
+        );
+
+        let mut runtime = Runtime::new();
+        runtime.count_instance("Dense", "dense[0]").unwrap();
+
+        runtime.set_stop_after(StopAfter::Instances(3));
+        runtime.count_instance("Dense", "dense[1]").unwrap();
+        let result = runtime.count_instance("Dense", "dense[2]");
+        assert!(matches!(result, Err(RuntimeError::Stopped(name)) if name == "dense[2]"));
+
+        let mut runtime = Runtime::new();
+        runtime.set_stop_after(StopAfter::Component(String::from("relu[1]")));
+        runtime.count_instance("ReLU", "relu[0]").unwrap();
+        let result = runtime.count_instance("ReLU", "relu[1]");
+        assert!(matches!(result, Err(RuntimeError::Stopped(name)) if name == "relu[1]"));
+    }
+
+    #[test]
+    fn test_runtime_context_name_at() {
+        let mut runtime = Runtime::new();
+        let depth = runtime.context_depth();
+        runtime.push_context(false, String::from("sum_0")).unwrap();
+
+        assert_eq!(runtime.context_name_at(depth), "0");
+        assert_eq!(runtime.context_name_at(depth + 1), "sum_0");
+        assert_eq!(runtime.context_name_at(depth + 2), "");
+    }
+
+    #[test]
+    fn test_runtime_diagnostics() {
+        let mut runtime = Runtime::new();
+        assert!(!runtime.is_recovering());
+        runtime.set_recover(true);
+
+        let depth = runtime.context_depth();
+        runtime.push_context(false, String::from("sum_0")).unwrap();
+        runtime.push_context(true, String::new()).unwrap();
+        runtime.record_diagnostic(String::from("Index out of bounds"));
+        runtime.restore_context_depth(depth);
+        runtime.record_diagnostic(String::from("Empty data item"));
+
+        assert_eq!(runtime.context_depth(), depth);
+        assert_eq!(
+            runtime.diagnostics(),
+            [
+                Diagnostic::error(String::from("sum_0"), String::from("Index out of bounds")),
+                Diagnostic::error(String::from("0"), String::from("Empty data item")),
+            ]
+        );
+    }
+
     #[test]
     fn test_runtime_push_pop_context_with_inheritance() {
         let mut runtime = Runtime::new();
@@ -868,7 +1583,7 @@ mod tests {
                 .unwrap()
                 .set_variable(
                     &DataAccess::new("var1", vec![SubAccess::Array(0), SubAccess::Array(0)]),
-                    Some(42),
+                    Some(FieldElement::from(42)),
                 )
                 .unwrap();
         }
@@ -883,7 +1598,7 @@ mod tests {
                     vec![SubAccess::Array(0), SubAccess::Array(0)],
                 ))
                 .unwrap();
-            assert_eq!(var1_value, Some(42));
+            assert_eq!(var1_value, Some(FieldElement::from(42)));
         }
 
         runtime.pop_context(true).unwrap();
@@ -896,7 +1611,7 @@ mod tests {
                     vec![SubAccess::Array(0), SubAccess::Array(0)],
                 ))
                 .unwrap();
-            assert_eq!(var1_value, Some(42));
+            assert_eq!(var1_value, Some(FieldElement::from(42)));
         }
     }
 
@@ -917,7 +1632,7 @@ mod tests {
                 .unwrap()
                 .set_variable(
                     &DataAccess::new("var1", vec![SubAccess::Array(0), SubAccess::Array(0)]),
-                    Some(42),
+                    Some(FieldElement::from(42)),
                 )
                 .unwrap();
         }
@@ -929,7 +1644,7 @@ mod tests {
                 .unwrap()
                 .set_variable(
                     &DataAccess::new("var1", vec![SubAccess::Array(0), SubAccess::Array(0)]),
-                    Some(100),
+                    Some(FieldElement::from(100)),
                 )
                 .unwrap();
         }
@@ -944,23 +1659,61 @@ mod tests {
                     vec![SubAccess::Array(0), SubAccess::Array(0)],
                 ))
                 .unwrap();
-            assert_eq!(var1_value, Some(100));
+            assert_eq!(var1_value, Some(FieldElement::from(100)));
         }
     }
 
     #[test]
     fn test_runtime_generate_signal_id() {
         let runtime = Runtime::new();
-        let signal_id1 = Runtime::gen_signal(runtime.get_signal_gen());
-        let signal_id2 = Runtime::gen_signal(runtime.get_signal_gen());
+        let signal_id1 = runtime.get_signal_gen().next_id().unwrap();
+        let signal_id2 = runtime.get_signal_gen().next_id().unwrap();
         assert_eq!(signal_id1, 0);
         assert_eq!(signal_id2, 1);
     }
 
+    #[test]
+    fn test_signal_gen_partition() {
+        let signal_gen = SignalGen::new(10);
+        let ranges = signal_gen.partition(2, 3).unwrap();
+        assert_eq!(signal_gen.peek(), 16);
+
+        // Each worker allocates from its own range, whichever runs first
+        let ids = std::thread::scope(|scope| {
+            let workers = ranges
+                .iter()
+                .rev()
+                .map(|range| scope.spawn(|| (0..3).map(|_| range.next_id().unwrap()).collect()))
+                .collect::<Vec<_>>();
+            workers
+                .into_iter()
+                .map(|worker| worker.join().unwrap())
+                .collect::<Vec<Vec<u32>>>()
+        });
+        assert_eq!(ids, vec![vec![13, 14, 15], vec![10, 11, 12]]);
+        assert!(matches!(
+            ranges[0].next_id(),
+            Err(RuntimeError::SignalIdsExhausted)
+        ));
+        assert!(matches!(
+            SignalGen::with_range(0, 4).partition(2, 3),
+            Err(RuntimeError::SignalIdsExhausted)
+        ));
+
+        // Runtimes can be moved to and shared between threads
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Runtime>();
+        assert_send_sync::<Context>();
+
+        // Compilers built on a worker thread can be merged on another one
+        fn assert_send<T: Send>() {}
+        assert_send::<crate::compiler::Compiler>();
+    }
+
     #[test]
     fn test_context_declare_item() {
         let mut context = Context::new("ctx1".to_string());
-        let next_signal_id = Rc::new(RefCell::new(0));
+        let next_signal_id = SignalGen::default();
 
         context
             .declare_item(DataType::Signal, "signal1", &[2, 2], next_signal_id.clone())
@@ -992,25 +1745,94 @@ mod tests {
     #[test]
     fn test_context_set_get_variable() {
         let mut context = Context::new("ctx1".to_string());
-        let next_signal_id = Rc::new(RefCell::new(0));
+        let next_signal_id = SignalGen::default();
         context
             .declare_item(DataType::Variable, "var1", &[2, 2], next_signal_id)
             .unwrap();
 
         let access = DataAccess::new("var1", vec![SubAccess::Array(0), SubAccess::Array(0)]);
-        context.set_variable(&access, Some(42)).unwrap();
+        context
+            .set_variable(&access, Some(FieldElement::from(42)))
+            .unwrap();
 
         let value = context.get_variable_value(&access).unwrap();
-        assert_eq!(value, Some(42));
+        assert_eq!(value, Some(FieldElement::from(42)));
 
         let content = context.get_variable_content(&access).unwrap();
-        assert_eq!(content, NestedValue::Value(Some(42)));
+        assert_eq!(content, NestedValue::Value(Some(FieldElement::from(42))));
+    }
+
+    #[test]
+    fn test_context_set_variable_content() {
+        let mut context = Context::new("ctx1".to_string());
+        let next_signal_id = SignalGen::default();
+        context
+            .declare_item(DataType::Variable, "var1", &[2, 2], next_signal_id)
+            .unwrap();
+
+        let row = NestedValue::Array(vec![
+            NestedValue::Value(Some(FieldElement::from(1))),
+            NestedValue::Value(Some(FieldElement::from(2))),
+        ]);
+        let access = DataAccess::new("var1", vec![SubAccess::Array(1)]);
+        context.set_variable_content(&access, row.clone()).unwrap();
+
+        assert_eq!(context.get_variable_content(&access).unwrap(), row);
+        assert_eq!(
+            context
+                .get_variable_value(&DataAccess::new(
+                    "var1",
+                    vec![SubAccess::Array(0), SubAccess::Array(0)]
+                ))
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_context_unique_name() {
+        let mut context = Context::new("ctx1".to_string());
+        let next_signal_id = SignalGen::default();
+        context
+            .declare_item(DataType::Variable, "%random_1", &[], next_signal_id.clone())
+            .unwrap();
+
+        // Names are numbered in order, skipping declared ones
+        assert_eq!(context.unique_name("random"), "%random_0");
+        assert_eq!(context.unique_name("random"), "%random_2");
+
+        // Blocks continue the numbering of their parent, and names hoisted from them aren't
+        // generated again
+        let mut child = context.new_with_inheritance();
+        let access = child
+            .declare_random_item(next_signal_id, DataType::Signal)
+            .unwrap();
+        assert_eq!(access.get_name(), "%random_3");
+        context.merge(&child).unwrap();
+        assert_eq!(context.unique_name("random"), "%random_4");
+
+        // The same declarations give the same names
+        let mut other = Context::new("ctx1".to_string());
+        assert_eq!(other.unique_name("random"), "%random_0");
+    }
+
+    #[test]
+    fn test_context_declare_random_signal() {
+        let mut context = Context::new("ctx1".to_string());
+        let content = NestedValue::Array(vec![NestedValue::Value(4), NestedValue::Value(2)]);
+
+        let access = context.declare_random_signal(content.clone()).unwrap();
+        assert_eq!(
+            context.get_item_data_type(&access.get_name()).unwrap(),
+            DataType::Signal
+        );
+        assert_eq!(context.get_signal_content(&access).unwrap(), content);
     }
 
     #[test]
     fn test_context_get_signal() {
         let mut context = Context::new("ctx1".to_string());
-        let next_signal_id = Rc::new(RefCell::new(0));
+        let next_signal_id = SignalGen::default();
         context
             .declare_item(DataType::Signal, "signal1", &[2, 2], next_signal_id.clone())
             .unwrap();
@@ -1027,10 +1849,28 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_context_signal_tags() {
+        let mut context = Context::new("ctx1".to_string());
+        context
+            .declare_item(DataType::Signal, "in", &[2], SignalGen::default())
+            .unwrap();
+        assert!(context.get_signal_tags("in").unwrap().is_empty());
+
+        context
+            .set_signal_tags("in", vec![String::from("binary")])
+            .unwrap();
+        assert_eq!(context.get_signal_tags("in").unwrap(), ["binary"]);
+        assert!(matches!(
+            context.set_signal_tags("out", Vec::new()),
+            Err(RuntimeError::ItemNotDeclared(_))
+        ));
+    }
+
     #[test]
     fn test_context_get_component_map() {
         let mut context = Context::new("ctx1".to_string());
-        let next_signal_id = Rc::new(RefCell::new(0));
+        let next_signal_id = SignalGen::default();
         context
             .declare_item(
                 DataType::Component,
@@ -1041,14 +1881,14 @@ mod tests {
             .unwrap();
 
         let mut signal_map = HashMap::new();
-        let signal = Signal::new(&[], next_signal_id.clone());
+        let signal = Signal::new(&[], next_signal_id.clone()).unwrap();
         signal_map.insert("signal1".to_string(), signal);
 
         let access = DataAccess::new("component1", vec![SubAccess::Array(0)]);
         context.set_component(&access, signal_map.clone()).unwrap();
 
         let retrieved_map = context.get_component_map(&access).unwrap();
-        assert_eq!(retrieved_map, signal_map);
+        assert_eq!(*retrieved_map, signal_map);
 
         let result = context.get_component_map(&DataAccess::new("nonexistent_component", vec![]));
         assert!(result.is_err());
@@ -1061,7 +1901,7 @@ mod tests {
     #[test]
     fn test_context_merge() {
         let mut parent_context = Context::new("parent".to_string());
-        let next_signal_id = Rc::new(RefCell::new(0));
+        let next_signal_id = SignalGen::default();
 
         parent_context
             .declare_item(DataType::Variable, "var1", &[2, 2], next_signal_id.clone())
@@ -1071,23 +1911,29 @@ mod tests {
             .unwrap();
 
         let access_var1 = DataAccess::new("var1", vec![SubAccess::Array(0), SubAccess::Array(0)]);
-        parent_context.set_variable(&access_var1, Some(42)).unwrap();
+        parent_context
+            .set_variable(&access_var1, Some(FieldElement::from(42)))
+            .unwrap();
 
         let mut child_context = parent_context.new_with_inheritance();
 
-        child_context.set_variable(&access_var1, Some(100)).unwrap();
+        child_context
+            .set_variable(&access_var1, Some(FieldElement::from(100)))
+            .unwrap();
 
         child_context
             .declare_item(DataType::Variable, "var2", &[1], next_signal_id.clone())
             .unwrap();
         let access_var2 = DataAccess::new("var2", vec![SubAccess::Array(0)]);
-        child_context.set_variable(&access_var2, Some(7)).unwrap();
+        child_context
+            .set_variable(&access_var2, Some(FieldElement::from(7)))
+            .unwrap();
 
         parent_context.merge(&child_context).unwrap();
 
         assert_eq!(
             parent_context.get_variable_value(&access_var1).unwrap(),
-            Some(100)
+            Some(FieldElement::from(100))
         );
 
         let result = parent_context.get_variable_value(&access_var2);
@@ -1098,17 +1944,107 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_context_component_array_instances() {
+        let mut context = Context::new("ctx1".to_string());
+        let next_signal_id = SignalGen::default();
+        context
+            .declare_item(DataType::Component, "c", &[2], next_signal_id.clone())
+            .unwrap();
+        let signal = Signal::new(&[2], next_signal_id).unwrap();
+        context
+            .set_component(
+                &DataAccess::new("c", vec![SubAccess::Array(0)]),
+                HashMap::from([(String::from("in"), signal)]),
+            )
+            .unwrap();
+
+        // Instantiated elements are wired independently of the others
+        let element = |index, signal_index| {
+            DataAccess::new(
+                "c",
+                vec![
+                    SubAccess::Array(index),
+                    SubAccess::Component(String::from("in")),
+                    SubAccess::Array(signal_index),
+                ],
+            )
+        };
+        assert_eq!(context.get_component_signal_id(&element(0, 1)).unwrap(), 1);
+        let error = context.get_component_signal_id(&element(1, 0)).unwrap_err();
+        assert!(matches!(
+            &error,
+            RuntimeError::ComponentNotInstantiated(name) if name == "c[1]"
+        ));
+        assert_eq!(
+            error.to_string(),
+            "Component c[1] is accessed before it is instantiated"
+        );
+        assert!(matches!(
+            context.get_component_map(&DataAccess::new("c", vec![SubAccess::Array(1)])),
+            Err(RuntimeError::ComponentNotInstantiated(_))
+        ));
+    }
+
+    #[test]
+    fn test_context_merge_hoists_signals() {
+        let mut parent_context = Context::new("parent".to_string());
+        let next_signal_id = SignalGen::default();
+
+        parent_context
+            .declare_item(DataType::Signal, "sig1", &[], next_signal_id.clone())
+            .unwrap();
+
+        // First execution of a block declaring a signal array
+        let mut child_context = parent_context.new_with_inheritance();
+        child_context
+            .declare_item(DataType::Signal, "sig2", &[2], next_signal_id.clone())
+            .unwrap();
+        parent_context.merge(&child_context).unwrap();
+
+        let access_sig2 = DataAccess::new("sig2", vec![SubAccess::Array(1)]);
+        assert_eq!(parent_context.get_signal_id(&access_sig2).unwrap(), 2);
+
+        // A second execution declares it again, shadowing the previous one
+        let mut child_context = parent_context.new_with_inheritance();
+        child_context
+            .declare_item(DataType::Signal, "sig2", &[2], next_signal_id.clone())
+            .unwrap();
+        parent_context.merge(&child_context).unwrap();
+
+        assert_eq!(parent_context.get_signal_id(&access_sig2).unwrap(), 4);
+
+        // A block can't declare it twice, and neither can the scope it was hoisted into
+        let mut child_context = parent_context.new_with_inheritance();
+        child_context
+            .declare_item(DataType::Signal, "sig2", &[2], next_signal_id.clone())
+            .unwrap();
+        let result =
+            child_context.declare_item(DataType::Signal, "sig2", &[2], next_signal_id.clone());
+        assert!(matches!(result, Err(RuntimeError::ItemAlreadyDeclared)));
+        let result =
+            parent_context.declare_item(DataType::Signal, "sig2", &[2], next_signal_id.clone());
+        assert!(matches!(result, Err(RuntimeError::ItemAlreadyDeclared)));
+
+        // Signals declared outside of blocks can't be declared again
+        let mut child_context = parent_context.new_with_inheritance();
+        let result = child_context.declare_item(DataType::Signal, "sig1", &[], next_signal_id);
+        assert!(matches!(result, Err(RuntimeError::ItemAlreadyDeclared)));
+    }
+
     #[test]
     fn test_context_merge_with_return() {
         let mut parent_context = Context::new("parent".to_string());
-        let next_signal_id = Rc::new(RefCell::new(0));
+        let next_signal_id = SignalGen::default();
 
         parent_context
             .declare_item(DataType::Variable, "var1", &[2, 2], next_signal_id.clone())
             .unwrap();
 
         let access_var1 = DataAccess::new("var1", vec![SubAccess::Array(0), SubAccess::Array(0)]);
-        parent_context.set_variable(&access_var1, Some(42)).unwrap();
+        parent_context
+            .set_variable(&access_var1, Some(FieldElement::from(42)))
+            .unwrap();
 
         let mut child_context = parent_context.new_with_inheritance();
 
@@ -1118,22 +2054,24 @@ mod tests {
             .unwrap();
         let access_return_var = DataAccess::new(RETURN_VAR, vec![SubAccess::Array(0)]);
         child_context
-            .set_variable(&access_return_var, Some(777))
+            .set_variable(&access_return_var, Some(FieldElement::from(777)))
             .unwrap();
 
-        child_context.set_variable(&access_var1, Some(100)).unwrap();
+        child_context
+            .set_variable(&access_var1, Some(FieldElement::from(100)))
+            .unwrap();
 
         parent_context.merge(&child_context).unwrap();
 
         assert_eq!(
             parent_context.get_variable_value(&access_var1).unwrap(),
-            Some(100)
+            Some(FieldElement::from(100))
         );
         assert_eq!(
             parent_context
                 .get_variable_value(&access_return_var)
                 .unwrap(),
-            Some(777)
+            Some(FieldElement::from(777))
         );
     }
 
@@ -1150,7 +2088,7 @@ mod tests {
         }
 
         let mut context = Context::new("ctx1".to_string());
-        let next_signal_id = Rc::new(RefCell::new(0));
+        let next_signal_id = SignalGen::default();
         context
             .declare_item(DataType::Variable, "var1", &[2, 2], next_signal_id.clone())
             .unwrap();
@@ -1166,7 +2104,7 @@ mod tests {
             panic!("Unexpected error type");
         }
 
-        let result = context.set_variable(&access, Some(42));
+        let result = context.set_variable(&access, Some(FieldElement::from(42)));
         assert!(result.is_err());
         if let Err(RuntimeError::ItemNotDeclared(_)) = result {
         } else {
@@ -1176,8 +2114,8 @@ mod tests {
 
     #[test]
     fn test_signal_new() {
-        let signal = Signal::new(&[2, 3], Rc::new(RefCell::new(0)));
-        if let NestedValue::Array(level1) = &signal.value {
+        let signal = Signal::new(&[2, 3], SignalGen::default()).unwrap();
+        if let NestedValue::Array(level1) = &*signal.value {
             assert_eq!(level1.len(), 2);
             if let NestedValue::Array(level2) = &level1[0] {
                 assert_eq!(level2.len(), 3);
@@ -1196,7 +2134,7 @@ mod tests {
 
     #[test]
     fn test_signal_get() {
-        let signal = Signal::new(&[2, 2], Rc::new(RefCell::new(0)));
+        let signal = Signal::new(&[2, 2], SignalGen::default()).unwrap();
         assert_eq!(signal.get(&[0, 0]).unwrap(), NestedValue::Value(0));
         assert_eq!(signal.get(&[1, 1]).unwrap(), NestedValue::Value(3));
 
@@ -1210,7 +2148,7 @@ mod tests {
 
     #[test]
     fn test_signal_get_id() {
-        let signal = Signal::new(&[2, 2], Rc::new(RefCell::new(0)));
+        let signal = Signal::new(&[2, 2], SignalGen::default()).unwrap();
         assert_eq!(signal.get_id(&[0, 0]).unwrap(), 0);
         assert_eq!(signal.get_id(&[1, 1]).unwrap(), 3);
         let result = signal.get_id(&[2, 0]);
@@ -1246,7 +2184,7 @@ mod tests {
         let mut variable = Variable::new(&[2, 2]);
 
         // Test setting a value out of bounds
-        let result = variable.set(&[2, 0], Some(42));
+        let result = variable.set(&[2, 0], Some(FieldElement::from(42)));
         assert!(result.is_err());
         if let Err(RuntimeError::IndexOutOfBounds) = result {
         } else {
@@ -1270,7 +2208,7 @@ mod tests {
             if let NestedValue::Array(level2) = &level1[0] {
                 assert_eq!(level2.len(), 3);
                 if let NestedValue::Value(map) = &level2[0] {
-                    assert_eq!(map.len(), 0);
+                    assert!(map.is_none());
                 } else {
                     panic!("Innermost value is not a Value");
                 }
@@ -1286,31 +2224,50 @@ mod tests {
     fn test_component_set_and_get_signal_map() {
         let mut component = Component::new(&[1]);
         let mut signal_map = HashMap::new();
-        let signal = Signal::new(&[], Rc::new(RefCell::new(0)));
+        let signal = Signal::new(&[], SignalGen::default()).unwrap();
         signal_map.insert("signal1".to_string(), signal);
 
         component
-            .set_signal_map(&[0], signal_map.clone())
+            .set_signal_map(&[0], Arc::new(signal_map.clone()))
             .expect("Setting signal map failed");
 
         let retrieved_map = component.get_map(&[0]).expect("Getting signal map failed");
-        assert_eq!(retrieved_map, signal_map);
+        assert_eq!(*retrieved_map, signal_map);
+    }
+
+    #[test]
+    fn test_component_signal_map_shared() {
+        let mut component = Component::new(&[2]);
+        let mut signal_map = HashMap::new();
+        let signal = Signal::new(&[4, 4], SignalGen::default()).unwrap();
+        signal_map.insert("signal1".to_string(), signal.clone());
+        let signal_map: SignalMap = Arc::new(signal_map);
+
+        component
+            .set_signal_map(&[0], Arc::clone(&signal_map))
+            .expect("Setting signal map failed");
+        let copy = component.clone();
+
+        // Copies of the component and of its signals share the same structures
+        let retrieved_map = copy.get_map(&[0]).expect("Getting signal map failed");
+        assert!(Arc::ptr_eq(&retrieved_map, &signal_map));
+        assert!(Arc::ptr_eq(&retrieved_map["signal1"].value, &signal.value));
     }
 
     #[test]
     fn test_component_get_signal_content() {
         let mut component = Component::new(&[1]);
         let mut signal_map = HashMap::new();
-        let signal = Signal::new(&[], Rc::new(RefCell::new(0)));
+        let signal = Signal::new(&[], SignalGen::default()).unwrap();
         signal_map.insert("signal1".to_string(), signal);
 
         component
-            .set_signal_map(&[0], signal_map)
+            .set_signal_map(&[0], Arc::new(signal_map))
             .expect("Setting signal map failed");
 
         let access = DataAccess::new("signal1", vec![]);
         let content = component
-            .get_signal_content(&[0], &access)
+            .get_signal_content(&[0], &[access])
             .expect("Getting signal content failed");
 
         assert_eq!(content, NestedValue::Value(0));
@@ -1320,16 +2277,16 @@ mod tests {
     fn test_component_get_signal_id() {
         let mut component = Component::new(&[1]);
         let mut signal_map = HashMap::new();
-        let signal = Signal::new(&[], Rc::new(RefCell::new(0)));
+        let signal = Signal::new(&[], SignalGen::default()).unwrap();
         signal_map.insert("signal1".to_string(), signal);
 
         component
-            .set_signal_map(&[0], signal_map)
+            .set_signal_map(&[0], Arc::new(signal_map))
             .expect("Setting signal map failed");
 
         let access = DataAccess::new("signal1", vec![]);
         let id = component
-            .get_signal_id(&[0], &access)
+            .get_signal_id(&[0], &[access])
             .expect("Getting signal ID failed");
 
         assert_eq!(id, 0);
@@ -1339,30 +2296,30 @@ mod tests {
     fn test_component_nested_signal_map() {
         let mut component = Component::new(&[2]);
         let mut signal_map_0 = HashMap::new();
-        let signal_0 = Signal::new(&[], Rc::new(RefCell::new(0)));
+        let signal_0 = Signal::new(&[], SignalGen::default()).unwrap();
         signal_map_0.insert("signal1".to_string(), signal_0);
 
         component
-            .set_signal_map(&[0], signal_map_0)
+            .set_signal_map(&[0], Arc::new(signal_map_0))
             .expect("Setting signal map failed");
 
         let mut signal_map_1 = HashMap::new();
-        let signal_1 = Signal::new(&[], Rc::new(RefCell::new(1)));
+        let signal_1 = Signal::new(&[], SignalGen::new(1)).unwrap();
         signal_map_1.insert("signal2".to_string(), signal_1);
 
         component
-            .set_signal_map(&[1], signal_map_1)
+            .set_signal_map(&[1], Arc::new(signal_map_1))
             .expect("Setting signal map failed");
 
         let access_0 = DataAccess::new("signal1", vec![]);
         let id_0 = component
-            .get_signal_id(&[0], &access_0)
+            .get_signal_id(&[0], &[access_0])
             .expect("Getting signal ID failed for index 0");
         assert_eq!(id_0, 0);
 
         let access_1 = DataAccess::new("signal2", vec![]);
         let id_1 = component
-            .get_signal_id(&[1], &access_1)
+            .get_signal_id(&[1], &[access_1])
             .expect("Getting signal ID failed for index 1");
         assert_eq!(id_1, 1);
     }
@@ -1419,6 +2376,45 @@ mod tests {
         assert_eq!(access_string, "ctx.variable[0].field");
     }
 
+    #[test]
+    fn test_data_access_path_str() {
+        let access = DataAccess::new("dense", vec![SubAccess::Array(3)]);
+        assert_eq!(access.path_str(), "dense[3]");
+    }
+
+    #[test]
+    fn test_runtime_pending_parallel() {
+        let mut runtime = Runtime::new();
+        assert!(!runtime.take_pending_parallel());
+
+        // The annotation only applies to the next instance
+        runtime.set_pending_parallel();
+        assert!(runtime.take_pending_parallel());
+        assert!(!runtime.take_pending_parallel());
+    }
+
+    #[test]
+    fn test_runtime_instance_context_name() {
+        let mut runtime = Runtime::new();
+        runtime.set_pending_component("dense[3]".to_string());
+        assert_eq!(runtime.instance_context_name("Dense").unwrap(), "Dense");
+
+        runtime.set_context_namer(Arc::new(index_context_namer));
+        runtime.set_pending_component("dense[3]".to_string());
+        assert_eq!(runtime.instance_context_name("Dense").unwrap(), "dense[3]");
+
+        // The pending component is consumed
+        assert_eq!(runtime.instance_context_name("Dense").unwrap(), "Dense");
+
+        // Nested instances are qualified by their parent
+        runtime.push_context(false, "dense[3]".to_string()).unwrap();
+        runtime.set_pending_component("inner[0]".to_string());
+        assert_eq!(
+            runtime.instance_context_name("Inner").unwrap(),
+            "dense[3].inner[0]"
+        );
+    }
+
     #[test]
     fn test_data_access_access_str_with_multiple_components() {
         let access = DataAccess::new(
@@ -1456,6 +2452,81 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_data_access_split_component_access() {
+        let access = DataAccess::new(
+            "component",
+            vec![
+                SubAccess::Array(0),
+                SubAccess::Component("subcomponent".to_string()),
+                SubAccess::Array(1),
+                SubAccess::Component("signal".to_string()),
+                SubAccess::Array(2),
+            ],
+        );
+
+        let result = split_component_access(&access).unwrap();
+        assert_eq!(
+            result,
+            (
+                DataAccess::new("component", vec![SubAccess::Array(0)]),
+                vec![
+                    DataAccess::new("subcomponent", vec![SubAccess::Array(1)]),
+                    DataAccess::new("signal", vec![SubAccess::Array(2)]),
+                ],
+            )
+        );
+
+        assert!(matches!(
+            process_component_access(&access),
+            Err(RuntimeError::NestedComponentAccess(path))
+                if path == "component[0].subcomponent[1].signal[2]"
+        ));
+        assert!(matches!(
+            split_component_access(&DataAccess::new("component", vec![SubAccess::Array(0)])),
+            Err(RuntimeError::AccessError)
+        ));
+    }
+
+    #[test]
+    fn test_component_nested_signal() {
+        let mut component = Component::new(&[]);
+        let signal_map = HashMap::from([
+            (
+                "bus.x".to_string(),
+                Signal::new(&[2], SignalGen::default()).unwrap(),
+            ),
+            (
+                "out".to_string(),
+                Signal::new(&[], SignalGen::new(2)).unwrap(),
+            ),
+        ]);
+        component
+            .set_signal_map(&[], Arc::new(signal_map))
+            .expect("Setting signal map failed");
+
+        let path = [
+            DataAccess::new("bus", vec![]),
+            DataAccess::new("x", vec![SubAccess::Array(1)]),
+        ];
+        assert_eq!(component.get_signal_id(&[], &path).unwrap(), 1);
+
+        // Intermediate items can't be indexed, and must exist
+        let indexed = [
+            DataAccess::new("bus", vec![SubAccess::Array(0)]),
+            DataAccess::new("x", vec![]),
+        ];
+        assert!(matches!(
+            component.get_signal_content(&[], &indexed),
+            Err(RuntimeError::NestedComponentAccess(path)) if path == "bus[0].x"
+        ));
+        let missing = [DataAccess::new("out", vec![]), DataAccess::new("y", vec![])];
+        assert!(matches!(
+            component.get_signal_id(&[], &missing),
+            Err(RuntimeError::NestedComponentAccess(path)) if path == "out.y"
+        ));
+    }
+
     #[test]
     fn test_util_get_nested_value() {
         let nested_value = NestedValue::Array(vec![
@@ -1540,4 +2611,45 @@ mod tests {
         assert!(!increment_indices(&mut indices, &limits).unwrap());
         assert_eq!(indices, vec![0, 0]);
     }
+
+    #[test]
+    fn test_indices() {
+        let indices = Indices::new(&[2, 3]);
+        assert_eq!(indices.shape(), [2, 3]);
+        assert_eq!(indices.len(), 6);
+        assert_eq!(
+            indices.collect::<Vec<_>>(),
+            vec![
+                vec![0, 0],
+                vec![0, 1],
+                vec![0, 2],
+                vec![1, 0],
+                vec![1, 1],
+                vec![1, 2]
+            ]
+        );
+
+        // Scalars have a single empty index, arrays with a zero sized dimension none
+        assert_eq!(
+            Indices::new(&[]).collect::<Vec<_>>(),
+            vec![Vec::<u32>::new()]
+        );
+        assert_eq!(Indices::new(&[3, 0, 2]).len(), 0);
+        assert_eq!(Indices::new(&[0]).next(), None);
+
+        let mut indices = Indices::new(&[1, 2]);
+        assert_eq!(indices.len(), 2);
+        indices.next();
+        assert_eq!(indices.len(), 1);
+        assert_eq!(indices.next(), Some(vec![0, 1]));
+        assert_eq!(indices.next(), None);
+    }
+
+    #[test]
+    fn test_util_increment_indices_zero_limit() {
+        let mut indices = vec![0, 0];
+        let limits = vec![2, 0];
+        assert!(!increment_indices(&mut indices, &limits).unwrap());
+        assert_eq!(indices, vec![0, 0]);
+    }
 }
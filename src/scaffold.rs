@@ -0,0 +1,225 @@
+//! # Scaffold Module
+//!
+//! This module writes the files of a new project for the `init` subcommand: an example circuit,
+//! a template of its inputs, and a Rust test harness compiling and evaluating the circuit with this
+//! library, so new users can start from a working setup.
+
+use crate::{logging::EMIT, program::ProgramError};
+use log::debug;
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// Example circuit, `circuits/main.circom`.
+const EXAMPLE_CIRCUIT: &str = r#"pragma circom 2.1.0;
+
+// Dot product of two vectors, compile it with:
+//   circom-2-arithc --input circuits/main.circom --output output/
+template DotProduct(n) {
+    signal input a[n];
+    signal input b[n];
+    signal output out;
+
+    var sum = 0;
+    for (var i = 0; i < n; i++) {
+        sum += a[i] * b[i];
+    }
+
+    out <== sum;
+}
+
+component main = DotProduct(3);
+"#;
+
+/// Values of the inputs of the example circuit, `inputs.template.json`. Inputs are named after
+/// the main component, `0`.
+const INPUTS_TEMPLATE: &str = r#"{
+  "0.a[0]": 0,
+  "0.a[1]": 0,
+  "0.a[2]": 0,
+  "0.b[0]": 0,
+  "0.b[1]": 0,
+  "0.b[2]": 0
+}
+"#;
+
+/// Test harness of the example circuit, `tests/circuit.rs`.
+const TEST_HARNESS: &str = r#"//! Compiles the circuit and checks its outputs, run with `cargo test`.
+
+use circom_2_arithc::{builder::CompilerBuilder, cli::ValueType, field::FieldElement};
+use std::collections::HashMap;
+
+/// Compiles `circuits/main.circom` and evaluates its outputs, keyed by name, on the given inputs,
+/// as words of the value type.
+fn evaluate(inputs: &HashMap<String, u32>) -> HashMap<String, u32> {
+    let compiler = CompilerBuilder::from_path("circuits/main.circom")
+        .value_type(ValueType::Sint)
+        .compile()
+        .unwrap()
+        .compiler;
+    let (value_type, prime) = (compiler.value_type(), compiler.prime());
+    let inputs = inputs
+        .iter()
+        .map(|(name, &value)| {
+            let value = FieldElement::from_word(value, value_type, prime);
+            (name.clone(), value)
+        })
+        .collect();
+    compiler
+        .evaluate_outputs(&inputs)
+        .unwrap()
+        .into_iter()
+        .map(|(name, value)| (name, value.to_word(value_type, prime).unwrap()))
+        .collect()
+}
+
+/// Reads the inputs template, replacing the values of the given inputs.
+fn inputs(values: &[(&str, u32)]) -> HashMap<String, u32> {
+    let template = std::fs::read_to_string("inputs.template.json").unwrap();
+    let mut inputs: HashMap<String, u32> = serde_json::from_str(&template).unwrap();
+    for (name, value) in values {
+        inputs.insert(name.to_string(), *value);
+    }
+    inputs
+}
+
+#[test]
+fn test_zero() {
+    let outputs = evaluate(&inputs(&[]));
+    assert_eq!(outputs["0.out"], 0);
+}
+
+#[test]
+fn test_dot_product() {
+    let outputs = evaluate(&inputs(&[
+        ("0.a[0]", 1),
+        ("0.a[1]", 2),
+        ("0.a[2]", 3),
+        ("0.b[0]", 4),
+        ("0.b[1]", 5),
+        ("0.b[2]", 6),
+    ]));
+    assert_eq!(outputs["0.out"], 32);
+}
+"#;
+
+/// Writes the files of a new project to the given directory, creating it if needed, and returns
+/// their paths. Fails without writing anything if one of the files already exists.
+pub fn init_project(dir: &Path) -> Result<Vec<PathBuf>, ProgramError> {
+    let files = [
+        (PathBuf::from("Cargo.toml"), cargo_manifest(dir)),
+        (
+            PathBuf::from("circuits/main.circom"),
+            EXAMPLE_CIRCUIT.to_string(),
+        ),
+        (
+            PathBuf::from("inputs.template.json"),
+            INPUTS_TEMPLATE.to_string(),
+        ),
+        (PathBuf::from("tests/circuit.rs"), TEST_HARNESS.to_string()),
+    ];
+
+    if let Some((path, _)) = files.iter().find(|(path, _)| dir.join(path).exists()) {
+        return Err(ProgramError::FileAlreadyExists(dir.join(path)));
+    }
+
+    let mut written = Vec::new();
+    for (path, content) in files {
+        let path = dir.join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        File::create(&path)?.write_all(content.as_bytes())?;
+        debug!(target: EMIT, "Wrote {}", path.display());
+        written.push(path);
+    }
+
+    Ok(written)
+}
+
+/// Cargo manifest of the project, running the test harness against this library.
+fn cargo_manifest(dir: &Path) -> String {
+    format!(
+        r#"[package]
+name = "{}"
+version = "0.1.0"
+edition = "2021"
+resolver = "1" # Fixes lalrpop issue, see: https://github.com/lalrpop/lalrpop/issues/616
+
+[dev-dependencies]
+circom-2-arithc = {{ git = "https://github.com/namnc/circom-2-arithc" }}
+serde_json = "1.0"
+"#,
+        package_name(dir)
+    )
+}
+
+/// Derives a Cargo package name from the project directory, e.g. `My Circuits` to `my-circuits`.
+fn package_name(dir: &Path) -> String {
+    // The directory may not exist yet, it only needs to be resolved for e.g. `.`
+    let dir = fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+    let name = dir
+        .file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let name = name
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<&str>>()
+        .join("-");
+
+    match name.chars().next() {
+        Some(first) if first.is_ascii_alphabetic() => name,
+        _ => String::from("circuit"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_init_project() {
+        let dir = std::env::temp_dir().join("circom_2_arithc_init/My Circuits");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let files = init_project(&dir).unwrap();
+        assert_eq!(files.len(), 4);
+        assert!(files.iter().all(|file| file.is_file()));
+
+        let manifest = fs::read_to_string(dir.join("Cargo.toml")).unwrap();
+        assert!(manifest.contains("name = \"my-circuits\""));
+
+        let inputs: HashMap<String, u32> =
+            serde_json::from_str(&fs::read_to_string(dir.join("inputs.template.json")).unwrap())
+                .unwrap();
+        assert_eq!(inputs.len(), 6);
+        assert!(inputs.values().all(|&value| value == 0));
+
+        // Existing projects are left untouched
+        fs::write(dir.join("tests/circuit.rs"), "// edited").unwrap();
+        assert!(matches!(
+            init_project(&dir),
+            Err(ProgramError::FileAlreadyExists(path)) if path == dir.join("Cargo.toml")
+        ));
+        assert_eq!(
+            fs::read_to_string(dir.join("tests/circuit.rs")).unwrap(),
+            "// edited"
+        );
+    }
+
+    #[test]
+    fn test_package_name() {
+        let dir = std::env::temp_dir().join("circom_2_arithc_init/42");
+        fs::create_dir_all(&dir).unwrap();
+        assert_eq!(package_name(&dir), "circuit");
+        assert_eq!(
+            package_name(Path::new("/nonexistent/New_Project")),
+            "new-project"
+        );
+    }
+}
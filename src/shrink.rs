@@ -0,0 +1,203 @@
+//! # Shrink Module
+//!
+//! This module shrinks an input assignment on which a circuit misbehaves (e.g. its outputs don't
+//! match a reference implementation) into a minimal reproducer, since the failing assignments of
+//! big circuits are too large to debug by hand.
+
+use crate::cli::ValueType;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// A failing input assignment along with its shrunk reproducer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShrunkInputs {
+    /// The assignment the failure was found with.
+    pub original: BTreeMap<String, u32>,
+    /// The smallest failing assignment found.
+    pub shrunk: BTreeMap<String, u32>,
+    /// Number of times the failure was checked while shrinking.
+    pub checks: usize,
+}
+
+/// Shrinks a failing input assignment, for which `fails` returns true, by moving each value
+/// towards zero as long as the assignment keeps failing. Values are compared by magnitude, as two's
+/// complement for the signed value types. Inputs are shrunk in name order, repeatedly, until none
+/// of them can be reduced.
+pub fn shrink_inputs<F>(
+    inputs: &HashMap<String, u32>,
+    value_type: ValueType,
+    mut fails: F,
+) -> ShrunkInputs
+where
+    F: FnMut(&HashMap<String, u32>) -> bool,
+{
+    let mut names = inputs.keys().cloned().collect::<Vec<String>>();
+    names.sort();
+
+    let mut current = inputs.clone();
+    let mut checks = 0;
+    let mut progress = true;
+    while progress {
+        progress = false;
+        for name in &names {
+            let value = current[name];
+            let (magnitude, negative) = to_magnitude(value, value_type);
+            if magnitude == 0 {
+                continue;
+            }
+
+            let mut check = |magnitude: u64| {
+                checks += 1;
+                current.insert(name.clone(), from_magnitude(magnitude, negative));
+                fails(&current)
+            };
+
+            // The smallest failing magnitude is searched between 0 and the current one, assuming
+            // that failures are monotonic in the value
+            let shrunk = if check(0) {
+                0
+            } else {
+                let (mut passing, mut failing) = (0, magnitude);
+                while failing - passing > 1 {
+                    let middle = passing + (failing - passing) / 2;
+                    if check(middle) {
+                        failing = middle;
+                    } else {
+                        passing = middle;
+                    }
+                }
+                failing
+            };
+
+            current.insert(name.clone(), from_magnitude(shrunk, negative));
+            progress |= shrunk < magnitude;
+        }
+    }
+
+    ShrunkInputs {
+        original: inputs.clone().into_iter().collect(),
+        shrunk: current.into_iter().collect(),
+        checks,
+    }
+}
+
+/// Splits a value into its magnitude and sign.
+fn to_magnitude(value: u32, value_type: ValueType) -> (u64, bool) {
+    let signed = value as i32;
+    if value_type.is_signed() && signed < 0 {
+        ((signed as i64).unsigned_abs(), true)
+    } else {
+        (value as u64, false)
+    }
+}
+
+/// Builds a value from its magnitude and sign.
+fn from_magnitude(magnitude: u64, negative: bool) -> u32 {
+    if negative {
+        (magnitude as i64).wrapping_neg() as u32
+    } else {
+        magnitude as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{a_gate_type::AGateType, compiler::Compiler, field::FieldElement};
+
+    fn inputs(values: &[(&str, u32)]) -> HashMap<String, u32> {
+        values
+            .iter()
+            .map(|&(name, value)| (name.to_string(), value))
+            .collect()
+    }
+
+    #[test]
+    fn test_shrink_inputs() {
+        // Fails whenever a is at least 1000, b doesn't matter
+        let original = inputs(&[("a", 123456), ("b", 99)]);
+        let shrunk = shrink_inputs(&original, ValueType::Uint, |inputs| inputs["a"] >= 1000);
+
+        assert_eq!(shrunk.original, original.into_iter().collect());
+        assert_eq!(
+            shrunk.shrunk,
+            BTreeMap::from([(String::from("a"), 1000), (String::from("b"), 0)])
+        );
+        assert!(shrunk.checks < 64);
+    }
+
+    #[test]
+    fn test_shrink_inputs_signed() {
+        // Fails whenever a is at most -10
+        let original = inputs(&[("a", -500i32 as u32)]);
+        let fails = |inputs: &HashMap<String, u32>| (inputs["a"] as i32) <= -10;
+
+        let shrunk = shrink_inputs(&original, ValueType::Sint, fails);
+        assert_eq!(shrunk.shrunk["a"], -10i32 as u32);
+
+        // Unsigned, -500 is a large positive value shrunk to the smallest failing one
+        let shrunk = shrink_inputs(&original, ValueType::Uint, fails);
+        assert_eq!(shrunk.shrunk["a"], i32::MIN as u32);
+    }
+
+    #[test]
+    fn test_shrink_inputs_dependent() {
+        // Fails whenever a + b is at least 10, shrinking b lets a shrink further
+        let original = inputs(&[("a", 40), ("b", 70)]);
+        let shrunk = shrink_inputs(&original, ValueType::Uint, |inputs| {
+            inputs["a"] + inputs["b"] >= 10
+        });
+
+        assert_eq!(shrunk.shrunk["a"], 0);
+        assert_eq!(shrunk.shrunk["b"], 10);
+    }
+
+    #[test]
+    fn test_shrink_circuit_mismatch() {
+        // out = a * b + c, checked against a reference that is wrong once a exceeds 100
+        let mut compiler = Compiler::new();
+        compiler.add_signal(1, String::from("0.a"), None).unwrap();
+        compiler.add_signal(2, String::from("0.b"), None).unwrap();
+        compiler.add_signal(3, String::from("0.c"), None).unwrap();
+        compiler.add_signal(4, String::from("0.t"), None).unwrap();
+        compiler.add_signal(5, String::from("0.out"), None).unwrap();
+        compiler.add_gate(AGateType::AMul, 1, 2, 4).unwrap();
+        compiler.add_gate(AGateType::AAdd, 4, 3, 5).unwrap();
+        compiler.add_inputs(HashMap::from([
+            (1, String::from("0.a")),
+            (2, String::from("0.b")),
+            (3, String::from("0.c")),
+        ]));
+        compiler.add_outputs(HashMap::from([(5, String::from("0.out"))]));
+
+        let reference = |inputs: &HashMap<String, u32>| {
+            let (a, b, c) = (inputs["0.a"], inputs["0.b"], inputs["0.c"]);
+            a.min(100).wrapping_mul(b).wrapping_add(c)
+        };
+        let (value_type, prime) = (compiler.value_type(), compiler.prime());
+        let fails = |inputs: &HashMap<String, u32>| {
+            let elements = inputs
+                .iter()
+                .map(|(name, &value)| {
+                    (
+                        name.clone(),
+                        FieldElement::from_word(value, value_type, prime),
+                    )
+                })
+                .collect();
+            let outputs = compiler.evaluate_outputs(&elements).unwrap();
+            outputs["0.out"].to_word(value_type, prime) != Some(reference(inputs))
+        };
+
+        let original = inputs(&[("0.a", 5000), ("0.b", 300), ("0.c", 77)]);
+        assert!(fails(&original));
+
+        let shrunk = shrink_inputs(&original, ValueType::Sint, fails);
+        assert_eq!(
+            shrunk.shrunk,
+            inputs(&[("0.a", 101), ("0.b", 1), ("0.c", 0)])
+                .into_iter()
+                .collect()
+        );
+    }
+}
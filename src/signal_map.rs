@@ -0,0 +1,106 @@
+//! # Signal Map Module
+//!
+//! This module maps the wires of a Bristol circuit back to the Circom signals they carry, to
+//! trace a wire id to e.g. `0.dense_23.weights[1][3]` when debugging a circuit.
+
+use std::io::{self, BufRead, Write};
+
+/// A Circom signal carried by a wire.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SignalMapEntry {
+    pub wire: usize,
+    /// Fully qualified name of the signal, e.g. `0.dense_23.weights[1][3]`.
+    pub name: String,
+    /// Templates the signal was declared in, outermost first, e.g. `Main/Dense`.
+    pub template: String,
+}
+
+/// The signals carried by the wires of a circuit, sorted by wire then name. Signals merged into
+/// the same wire, e.g. by `<==`, each get an entry.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SignalMap {
+    pub entries: Vec<SignalMapEntry>,
+}
+
+impl SignalMap {
+    /// Creates a signal map from its entries, in any order.
+    pub fn new(mut entries: Vec<SignalMapEntry>) -> Self {
+        entries.sort_unstable();
+        Self { entries }
+    }
+
+    /// Returns the names of the signals carried by a wire.
+    pub fn wire_names(&self, wire: usize) -> Vec<&str> {
+        let start = self.entries.partition_point(|entry| entry.wire < wire);
+        self.entries[start..]
+            .iter()
+            .take_while(|entry| entry.wire == wire)
+            .map(|entry| entry.name.as_str())
+            .collect()
+    }
+
+    /// Reads a map written by `SignalMap::write`.
+    pub fn read<R: BufRead>(reader: R) -> io::Result<Self> {
+        let mut entries = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            let invalid = || io::Error::new(io::ErrorKind::InvalidData, line.clone());
+            let mut fields = line.splitn(3, ',');
+            let (Some(wire), Some(name), Some(template)) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                return Err(invalid());
+            };
+            entries.push(SignalMapEntry {
+                wire: wire.parse().map_err(|_| invalid())?,
+                name: name.to_string(),
+                template: template.to_string(),
+            });
+        }
+
+        Ok(Self::new(entries))
+    }
+
+    /// Writes the map in a `.sym`-like format, one `wire,name,template` line per signal.
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        for entry in &self.entries {
+            writeln!(writer, "{},{},{}", entry.wire, entry.name, entry.template)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(wire: usize, name: &str, template: &str) -> SignalMapEntry {
+        SignalMapEntry {
+            wire,
+            name: name.to_string(),
+            template: template.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_signal_map() {
+        let map = SignalMap::new(vec![
+            entry(2, "0.out", "Main"),
+            entry(0, "0.in[0]", "Main"),
+            entry(2, "0.dense.out", "Main/Dense"),
+        ]);
+
+        assert_eq!(map.wire_names(2), vec!["0.dense.out", "0.out"]);
+        assert!(map.wire_names(1).is_empty());
+
+        let mut sym = Vec::new();
+        map.write(&mut sym).unwrap();
+        assert_eq!(
+            String::from_utf8(sym.clone()).unwrap(),
+            "0,0.in[0],Main\n2,0.dense.out,Main/Dense\n2,0.out,Main\n"
+        );
+        assert_eq!(SignalMap::read(sym.as_slice()).unwrap(), map);
+        assert!(SignalMap::read("x,0.in,Main".as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,244 @@
+//! # SIMD Module
+//!
+//! This module finds repeated isomorphic subcircuits, e.g. the same per-neuron computation
+//! repeated for every neuron of a layer, so SIMD-capable backends can evaluate their instances in
+//! vectorized batches. Subcircuits are the fanout-free regions of the circuit: the gates computing
+//! a wire that is read by several gates or is an output, back to the wires read by several gates,
+//! inputs and constants. Each gate belongs to a single region, so groups never overlap.
+
+use bristol_circuit::BristolCircuit;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+/// Smallest number of gates of a reported subcircuit, single gates are already batched by
+/// operation.
+const MIN_TEMPLATE_GATES: usize = 2;
+
+/// Operand of a gate of a subcircuit template.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operand {
+    /// Input of the subcircuit, indexing the input wires of each instance.
+    Input(usize),
+    /// Output of a previous gate of the template.
+    Gate(usize),
+}
+
+/// Gate of a subcircuit template.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct TemplateGate {
+    pub op: String,
+    pub inputs: Vec<Operand>,
+}
+
+/// Occurrence of a subcircuit in the circuit.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SubcircuitInstance {
+    /// Wires bound to the inputs of the template, in order.
+    pub inputs: Vec<usize>,
+    /// Wires of the outputs of the template, i.e. of its last gate.
+    pub outputs: Vec<usize>,
+}
+
+/// Subcircuit repeated in the circuit.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SubcircuitGroup {
+    /// Gates of the subcircuit in evaluation order, the last one produces its output.
+    pub template: Vec<TemplateGate>,
+    pub instances: Vec<SubcircuitInstance>,
+}
+
+/// Finds the subcircuits of at least two gates occurring at least twice in the circuit. Groups are
+/// sorted by the number of gates they cover, largest first.
+pub fn find_subcircuit_groups(circuit: &BristolCircuit) -> Vec<SubcircuitGroup> {
+    let mut producers = HashMap::<usize, usize>::new();
+    let mut readers = HashMap::<usize, usize>::new();
+    for (index, gate) in circuit.gates.iter().enumerate() {
+        if let [output] = gate.outputs[..] {
+            producers.insert(output, index);
+        }
+        for &input in &gate.inputs {
+            *readers.entry(input).or_default() += 1;
+        }
+    }
+    let outputs = circuit
+        .info
+        .output_name_to_wire_index
+        .values()
+        .copied()
+        .collect::<HashSet<usize>>();
+
+    // A wire is internal to a region if its only reader is in the region
+    let is_internal = |wire: usize| {
+        producers.contains_key(&wire) && readers.get(&wire) == Some(&1) && !outputs.contains(&wire)
+    };
+
+    let mut groups = HashMap::<Vec<TemplateGate>, Vec<SubcircuitInstance>>::new();
+    let mut roots = Vec::new();
+    for (index, gate) in circuit.gates.iter().enumerate() {
+        match gate.outputs[..] {
+            [output] if !is_internal(output) => roots.push((index, output)),
+            _ => {}
+        }
+    }
+    for (root, output) in roots {
+        let (template, inputs) = region_template(circuit, root, &producers, &is_internal);
+        if template.len() >= MIN_TEMPLATE_GATES {
+            groups
+                .entry(template)
+                .or_default()
+                .push(SubcircuitInstance {
+                    inputs,
+                    outputs: vec![output],
+                });
+        }
+    }
+
+    let mut groups = groups
+        .into_iter()
+        .filter(|(_, instances)| instances.len() >= 2)
+        .map(|(template, instances)| SubcircuitGroup {
+            template,
+            instances,
+        })
+        .collect::<Vec<_>>();
+    groups.sort_by_key(|group| {
+        (
+            std::cmp::Reverse(group.template.len() * group.instances.len()),
+            group.instances[0].outputs[0],
+        )
+    });
+
+    groups
+}
+
+/// Returns the gates of the region computed by a gate as a template, in evaluation order, along
+/// with the wires it reads from outside the region, in order of first use.
+fn region_template(
+    circuit: &BristolCircuit,
+    root: usize,
+    producers: &HashMap<usize, usize>,
+    is_internal: &impl Fn(usize) -> bool,
+) -> (Vec<TemplateGate>, Vec<usize>) {
+    let mut template = Vec::new();
+    let mut inputs = Vec::new();
+    let mut input_indices = HashMap::<usize, usize>::new();
+
+    // Depth-first, as regions can be long chains, e.g. `sum += x[i]`
+    let mut stack = vec![(root, Vec::new())];
+    while let Some((index, operands)) = stack.last_mut() {
+        let gate = &circuit.gates[*index];
+        let Some(&wire) = gate.inputs.get(operands.len()) else {
+            let (index, operands) = stack.pop().unwrap();
+            template.push(TemplateGate {
+                op: circuit.gates[index].op.clone(),
+                inputs: operands,
+            });
+            if let Some((_, parent_operands)) = stack.last_mut() {
+                parent_operands.push(Operand::Gate(template.len() - 1));
+            }
+            continue;
+        };
+
+        if is_internal(wire) {
+            stack.push((producers[&wire], Vec::new()));
+        } else {
+            let input = *input_indices.entry(wire).or_insert_with(|| {
+                inputs.push(wire);
+                inputs.len() - 1
+            });
+            operands.push(Operand::Input(input));
+        }
+    }
+
+    (template, inputs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bristol_circuit::{CircuitInfo, Gate};
+
+    fn gate(inputs: Vec<usize>, output: usize, op: &str) -> Gate {
+        Gate {
+            inputs,
+            outputs: vec![output],
+            op: op.to_string(),
+        }
+    }
+
+    /// Two neurons `out[i] = a[i] * w[i] + b`, and a final `out[0] - out[1]`.
+    fn circuit() -> BristolCircuit {
+        BristolCircuit {
+            wire_count: 12,
+            info: CircuitInfo {
+                input_name_to_wire_index: HashMap::from([
+                    (String::from("0.a[0]"), 0),
+                    (String::from("0.a[1]"), 1),
+                    (String::from("0.w[0]"), 2),
+                    (String::from("0.w[1]"), 3),
+                    (String::from("0.b"), 4),
+                ]),
+                constants: HashMap::new(),
+                output_name_to_wire_index: HashMap::from([
+                    (String::from("0.out[0]"), 6),
+                    (String::from("0.out[1]"), 8),
+                    (String::from("0.diff"), 9),
+                ]),
+            },
+            io_widths: None,
+            gates: vec![
+                gate(vec![0, 2], 5, "AMul"),
+                gate(vec![5, 4], 6, "AAdd"),
+                gate(vec![1, 3], 7, "AMul"),
+                gate(vec![7, 4], 8, "AAdd"),
+                gate(vec![6, 8], 9, "ASub"),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_find_subcircuit_groups() {
+        let groups = find_subcircuit_groups(&circuit());
+
+        assert_eq!(
+            groups,
+            vec![SubcircuitGroup {
+                template: vec![
+                    TemplateGate {
+                        op: String::from("AMul"),
+                        inputs: vec![Operand::Input(0), Operand::Input(1)],
+                    },
+                    TemplateGate {
+                        op: String::from("AAdd"),
+                        inputs: vec![Operand::Gate(0), Operand::Input(2)],
+                    },
+                ],
+                instances: vec![
+                    SubcircuitInstance {
+                        inputs: vec![0, 2, 4],
+                        outputs: vec![6],
+                    },
+                    SubcircuitInstance {
+                        inputs: vec![1, 3, 4],
+                        outputs: vec![8],
+                    },
+                ],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_find_subcircuit_groups_shared_operands() {
+        // `x * x + 1` and `x * y + 1` have the same shape, but not the same structure
+        let mut circuit = circuit();
+        circuit.gates[0] = gate(vec![0, 0], 5, "AMul");
+        assert!(find_subcircuit_groups(&circuit).is_empty());
+
+        // Regions end at wires read by several gates, so the first neuron is split
+        circuit.gates[0] = gate(vec![0, 2], 5, "AMul");
+        assert_eq!(find_subcircuit_groups(&circuit).len(), 1);
+        circuit.gates.push(gate(vec![5, 5], 10, "AMul"));
+        assert!(find_subcircuit_groups(&circuit).is_empty());
+    }
+}
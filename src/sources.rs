@@ -0,0 +1,159 @@
+//! # Sources Module
+//!
+//! This module scans Circom sources for their includes and main component without parsing them,
+//! ignoring what is commented out. `includes` is the include scanner of the crate, shared by the
+//! include resolver of in-memory sources and the dependency graph.
+
+use regex::Regex;
+use std::{ops::Range, sync::OnceLock};
+
+/// The `component main` declaration of a source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MainDeclaration {
+    /// Byte range of the declaration in the source, final `;` included.
+    pub range: Range<usize>,
+    /// Inputs listed in `{public [...]}`.
+    pub public: Vec<String>,
+    /// The template call, e.g. `Sum(2)`.
+    pub call: String,
+}
+
+/// Replaces the comments of a source with spaces, keeping line breaks and byte offsets, so
+/// patterns matched on the result skip commented code. Comment markers in strings are kept.
+pub fn blank_comments(source: &str) -> String {
+    let mut blanked = String::with_capacity(source.len());
+    let mut chars = source.chars().peekable();
+    let blank = |blanked: &mut String, c: char| {
+        if c == '\n' {
+            blanked.push(c);
+        } else {
+            blanked.extend(std::iter::repeat_n(' ', c.len_utf8()));
+        }
+    };
+
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('"', _) => {
+                blanked.push(c);
+                while let Some(c) = chars.next() {
+                    blanked.push(c);
+                    match c {
+                        '\\' => blanked.extend(chars.next()),
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            ('/', Some('/')) => {
+                while let Some(&c) = chars.peek() {
+                    if c == '\n' {
+                        break;
+                    }
+                    blank(&mut blanked, c);
+                    chars.next();
+                }
+                blank(&mut blanked, '/');
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                blanked.push_str("  ");
+                let mut previous = None;
+                for c in chars.by_ref() {
+                    blank(&mut blanked, c);
+                    if previous == Some('*') && c == '/' {
+                        break;
+                    }
+                    previous = Some(c);
+                }
+            }
+            _ => blanked.push(c),
+        }
+    }
+
+    blanked
+}
+
+/// `include "path";`, with or without whitespace, anywhere on a line.
+static INCLUDE: OnceLock<Regex> = OnceLock::new();
+
+/// Returns the paths of the files included by a source, in order.
+pub fn includes(source: &str) -> Vec<String> {
+    let include = INCLUDE.get_or_init(|| Regex::new(r#"\binclude\s*"([^"]+)"\s*;"#).unwrap());
+
+    let blanked = blank_comments(source);
+    include
+        .captures_iter(&blanked)
+        .map(|capture| capture[1].to_string())
+        .collect()
+}
+
+/// Returns the main component declaration of a source, if it has one.
+pub fn find_main(source: &str) -> Option<MainDeclaration> {
+    static MAIN: OnceLock<Regex> = OnceLock::new();
+    let main = MAIN.get_or_init(|| {
+        Regex::new(r"\bcomponent\s+main\s*(?:\{\s*public\s*\[([^\]]*)\]\s*\}\s*)?=\s*([^;]+);")
+            .unwrap()
+    });
+
+    let blanked = blank_comments(source);
+    let capture = main.captures(&blanked)?;
+    let public = capture.get(1).map_or(Vec::new(), |public| {
+        public
+            .as_str()
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(String::from)
+            .collect()
+    });
+
+    Some(MainDeclaration {
+        range: capture.get(0)?.range(),
+        public,
+        call: capture[2].trim().to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blank_comments() {
+        let source = "a // b\n/* c\n*/ d \"// e\" /* é */";
+        let blanked = blank_comments(source);
+
+        assert_eq!(blanked.len(), source.len());
+        assert_eq!(blanked, "a     \n    \n   d \"// e\"         ");
+    }
+
+    #[test]
+    fn test_includes() {
+        let source = "include \"a.circom\";\n// include \"b.circom\";\n/*\ninclude \"c.circom\";\n*/\ninclude\"lib/d.circom\" ;";
+        assert_eq!(includes(source), ["a.circom", "lib/d.circom"]);
+        assert_eq!(
+            includes("pragma circom 2.1.0; include \"e.circom\"; // include \"f.circom\";"),
+            ["e.circom"]
+        );
+    }
+
+    #[test]
+    fn test_find_main() {
+        let source =
+            "/* component main = Old(); */\ncomponent main {public [a, b]} = Sum(2,\n  3);\n";
+        let main = find_main(source).unwrap();
+
+        assert_eq!(main.public, ["a", "b"]);
+        assert_eq!(main.call, "Sum(2,\n  3)");
+        assert_eq!(
+            &source[main.range],
+            "component main {public [a, b]} = Sum(2,\n  3);"
+        );
+
+        assert_eq!(find_main("// component main = Sum();\n"), None);
+        assert_eq!(
+            find_main("component main = Id();").unwrap().public,
+            Vec::<String>::new()
+        );
+    }
+}
@@ -0,0 +1,325 @@
+//! # Stages Module
+//!
+//! This module attaches standard processing stages to the IO of a compiled circuit: input
+//! encodings (e.g. scaling to fixed point or one-hot encoding) in front of its inputs and output
+//! decodings behind its outputs, so deployments don't have to wrap every circuit by hand.
+
+use crate::{
+    a_gate_type::AGateType,
+    compiler::{CircuitError, Compiler},
+    field::FieldElement,
+    program::ProgramError,
+};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+/// A parametrized adapter applied to each element of an input or output.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "adapter", rename_all = "snake_case")]
+pub enum Adapter {
+    /// Multiplies the value by `factor`.
+    Scale { factor: i64 },
+    /// Divides the value by `divisor`, see `AGateType::AIntDiv`.
+    Divide { divisor: i64 },
+    /// Adds `offset` to the value.
+    Offset { offset: i64 },
+    /// Replaces a one-dimensional input array with a single input holding an index: the element
+    /// at the index is 1 and the others are 0. Only applies to inputs.
+    OneHot,
+}
+
+/// An adapter applied to the input or output `signal`, e.g. `0.in` for every element of `in`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Stage {
+    pub signal: String,
+    #[serde(flatten)]
+    pub adapter: Adapter,
+}
+
+/// Stages attached to a circuit, `{ "pre": [...], "post": [...] }`. Pre-processing stages run in
+/// order on the inputs before the circuit, post-processing stages run in order on its outputs.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StagesConfig {
+    #[serde(default)]
+    pub pre: Vec<Stage>,
+    #[serde(default)]
+    pub post: Vec<Stage>,
+}
+
+impl StagesConfig {
+    /// Reads a stages config from a JSON file.
+    pub fn from_path(path: &Path) -> Result<Self, ProgramError> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+}
+
+/// Attaches the stages of the config to the IO of the circuit. The inputs and outputs keep their
+/// names, the signals they replace are renamed with a `pre<index>.` or `post<index>.` prefix.
+pub fn apply_stages(compiler: &mut Compiler, config: &StagesConfig) -> Result<(), ProgramError> {
+    // Each stage is attached in front of the current inputs, so the last one goes first
+    for (index, stage) in config.pre.iter().enumerate().rev() {
+        let signals = compiler.input_signals(&stage.signal);
+        if signals.is_empty() {
+            return Err(CircuitError::UnknownSignal(stage.signal.clone()).into());
+        }
+        let prefix = format!("pre{}", index);
+
+        match &stage.adapter {
+            Adapter::OneHot => attach_one_hot(compiler, stage, &signals, &prefix)?,
+            adapter => {
+                let (op, value) = element_op(adapter)?;
+                let constant = add_constant(compiler, &prefix, value)?;
+                for signal in signals {
+                    let name = io_name(compiler, signal);
+                    let input = compiler.next_signal_id();
+                    compiler.rename_signal(signal, format!("{}.{}", prefix, name))?;
+                    compiler.add_signal(input, name.clone(), None)?;
+                    compiler.add_gate(op, input, constant, signal)?;
+                    compiler.replace_io(&[signal], input, name)?;
+                }
+            }
+        }
+    }
+
+    for (index, stage) in config.post.iter().enumerate() {
+        let signals = compiler.output_signals(&stage.signal);
+        if signals.is_empty() {
+            return Err(CircuitError::UnknownSignal(stage.signal.clone()).into());
+        }
+        let prefix = format!("post{}", index);
+
+        let (op, value) = element_op(&stage.adapter)?;
+        let constant = add_constant(compiler, &prefix, value)?;
+        for signal in signals {
+            let name = io_name(compiler, signal);
+            let output = compiler.next_signal_id();
+            compiler.rename_signal(signal, format!("{}.{}", prefix, name))?;
+            compiler.add_signal(output, name.clone(), None)?;
+            compiler.add_gate(op, signal, constant, output)?;
+            compiler.replace_io(&[signal], output, name)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Feeds the elements of an input array from the comparison of a new index input with their
+/// position.
+fn attach_one_hot(
+    compiler: &mut Compiler,
+    stage: &Stage,
+    signals: &[u32],
+    prefix: &str,
+) -> Result<(), ProgramError> {
+    let elements = signals
+        .iter()
+        .map(|&signal| io_name(compiler, signal))
+        .collect::<Vec<String>>();
+    let one_dimensional = elements
+        .iter()
+        .enumerate()
+        .all(|(position, name)| *name == format!("{}[{}]", stage.signal, position));
+    if !one_dimensional {
+        return Err(ProgramError::InvalidStage(format!(
+            "one_hot needs a one-dimensional input array, {} isn't one",
+            stage.signal
+        )));
+    }
+
+    let index = compiler.next_signal_id();
+    compiler.add_signal(index, stage.signal.clone(), None)?;
+    for (position, (&signal, name)) in signals.iter().zip(elements).enumerate() {
+        let constant = add_constant(compiler, prefix, position as i64)?;
+        compiler.rename_signal(signal, format!("{}.{}", prefix, name))?;
+        compiler.add_gate(AGateType::AEq, index, constant, signal)?;
+    }
+    compiler.replace_io(signals, index, stage.signal.clone())?;
+
+    Ok(())
+}
+
+/// Returns the gate applying an element-wise adapter, with its constant operand.
+fn element_op(adapter: &Adapter) -> Result<(AGateType, i64), ProgramError> {
+    match *adapter {
+        Adapter::Scale { factor } => Ok((AGateType::AMul, factor)),
+        Adapter::Divide { divisor: 0 } => Err(ProgramError::InvalidStage(String::from(
+            "divide needs a non-zero divisor",
+        ))),
+        Adapter::Divide { divisor } => Ok((AGateType::AIntDiv, divisor)),
+        Adapter::Offset { offset } => Ok((AGateType::AAdd, offset)),
+        Adapter::OneHot => Err(ProgramError::InvalidStage(String::from(
+            "one_hot only applies to inputs",
+        ))),
+    }
+}
+
+/// Adds a constant signal holding a 32-bit word of the value type.
+fn add_constant(compiler: &mut Compiler, prefix: &str, value: i64) -> Result<u32, ProgramError> {
+    let value_type = compiler.value_type();
+    let word = if value_type.is_signed() {
+        i32::try_from(value).ok().map(|value| value as u32)
+    } else {
+        u32::try_from(value).ok()
+    };
+    let word = word.ok_or_else(|| ProgramError::ValueOutOfRange {
+        value: value.to_string(),
+        target: format!("{:?} wires", value_type),
+    })?;
+
+    let element = FieldElement::from_word(word, value_type, compiler.prime());
+
+    let id = compiler.next_signal_id();
+    compiler.add_signal(id, format!("{}.const_{}", prefix, value), Some(element))?;
+    Ok(id)
+}
+
+/// Returns the IO name of a signal, which stages keep.
+fn io_name(compiler: &Compiler, signal: u32) -> String {
+    compiler
+        .io_name(signal)
+        .map(str::to_string)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// out = in[0] + in[1]
+    fn compiler() -> Compiler {
+        let mut compiler = Compiler::new();
+        compiler
+            .add_signal(1, String::from("0.in[0]"), None)
+            .unwrap();
+        compiler
+            .add_signal(2, String::from("0.in[1]"), None)
+            .unwrap();
+        compiler.add_signal(3, String::from("0.out"), None).unwrap();
+        compiler.add_gate(AGateType::AAdd, 1, 2, 3).unwrap();
+        compiler.add_inputs(HashMap::from([
+            (1, String::from("0.in[0]")),
+            (2, String::from("0.in[1]")),
+        ]));
+        compiler.add_outputs(HashMap::from([(3, String::from("0.out"))]));
+        compiler
+    }
+
+    fn stage(signal: &str, adapter: Adapter) -> Stage {
+        Stage {
+            signal: signal.to_string(),
+            adapter,
+        }
+    }
+
+    fn evaluate(compiler: &Compiler, inputs: &[(&str, u32)]) -> u32 {
+        let inputs = inputs
+            .iter()
+            .map(|&(name, value)| (name.to_string(), FieldElement::from(value)))
+            .collect();
+        compiler.evaluate_outputs(&inputs).unwrap()["0.out"]
+            .to_word(compiler.value_type(), compiler.prime())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_stages_config() {
+        let config: StagesConfig = serde_json::from_str(
+            r#"{
+                "pre": [{ "signal": "0.in", "adapter": "scale", "factor": 100 }],
+                "post": [{ "signal": "0.out", "adapter": "divide", "divisor": 10 }]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config,
+            StagesConfig {
+                pre: vec![stage("0.in", Adapter::Scale { factor: 100 })],
+                post: vec![stage("0.out", Adapter::Divide { divisor: 10 })],
+            }
+        );
+    }
+
+    #[test]
+    fn test_apply_stages() {
+        let mut compiler = compiler();
+        let config = StagesConfig {
+            // (in * 10 + 1) for each element
+            pre: vec![
+                stage("0.in", Adapter::Scale { factor: 10 }),
+                stage("0.in", Adapter::Offset { offset: 1 }),
+            ],
+            post: vec![stage("0.out", Adapter::Divide { divisor: 4 })],
+        };
+        apply_stages(&mut compiler, &config).unwrap();
+
+        // ((2 * 10 + 1) + (3 * 10 + 1)) / 4
+        assert_eq!(evaluate(&compiler, &[("0.in[0]", 2), ("0.in[1]", 3)]), 13);
+        compiler.debug_assert_valid().unwrap();
+
+        let circuit = compiler.build_circuit().unwrap();
+        let mut inputs = circuit
+            .info
+            .input_name_to_wire_index
+            .into_iter()
+            .collect::<Vec<_>>();
+        inputs.sort();
+        assert_eq!(
+            inputs,
+            vec![(String::from("0.in[0]"), 0), (String::from("0.in[1]"), 1)]
+        );
+        assert!(circuit.info.output_name_to_wire_index.contains_key("0.out"));
+        assert_eq!(circuit.gates.len(), 6);
+    }
+
+    #[test]
+    fn test_apply_stages_one_hot() {
+        let mut compiler = compiler();
+        let config = StagesConfig {
+            pre: vec![stage("0.in", Adapter::OneHot)],
+            post: Vec::new(),
+        };
+        apply_stages(&mut compiler, &config).unwrap();
+
+        assert_eq!(evaluate(&compiler, &[("0.in", 1)]), 1);
+        assert_eq!(evaluate(&compiler, &[("0.in", 5)]), 0);
+        let circuit = compiler.build_circuit().unwrap();
+        assert_eq!(
+            circuit.info.input_name_to_wire_index,
+            HashMap::from([(String::from("0.in"), 0)])
+        );
+    }
+
+    #[test]
+    fn test_apply_stages_errors() {
+        let apply = |pre: Vec<Stage>, post: Vec<Stage>| {
+            apply_stages(&mut compiler(), &StagesConfig { pre, post })
+                .unwrap_err()
+                .to_string()
+        };
+
+        assert_eq!(
+            apply(vec![stage("0.x", Adapter::OneHot)], Vec::new()),
+            "Circuit error: Unknown signal 0.x"
+        );
+        assert_eq!(
+            apply(Vec::new(), vec![stage("0.out", Adapter::OneHot)]),
+            "Invalid stage: one_hot only applies to inputs"
+        );
+        assert_eq!(
+            apply(
+                Vec::new(),
+                vec![stage("0.out", Adapter::Divide { divisor: 0 })]
+            ),
+            "Invalid stage: divide needs a non-zero divisor"
+        );
+        assert_eq!(
+            apply(
+                vec![stage("0.in", Adapter::Scale { factor: 1 << 40 })],
+                Vec::new()
+            ),
+            "Value 1099511627776 is out of range for Sint wires"
+        );
+    }
+}
@@ -3,12 +3,22 @@ use crate::compiler::CircuitError;
 pub fn topological_sort(
     len: usize,
     get_deps: &dyn Fn(usize) -> Vec<usize>,
+) -> Result<Vec<usize>, CircuitError> {
+    topological_sort_from(len, &[], get_deps)
+}
+
+/// Same as `topological_sort`, but visits the dependencies of the given roots first, so each root
+/// is preceded by the elements it depends on that aren't needed by a previous root.
+pub fn topological_sort_from(
+    len: usize,
+    roots: &[usize],
+    get_deps: &dyn Fn(usize) -> Vec<usize>,
 ) -> Result<Vec<usize>, CircuitError> {
     let mut sorted = Vec::with_capacity(len);
     let mut visiting = vec![false; len];
     let mut visited = vec![false; len];
 
-    for i in 0..len {
+    for i in roots.iter().copied().chain(0..len) {
         topological_sort_visit(i, &mut visiting, &mut visited, get_deps, &mut sorted)?;
     }
 
@@ -20,6 +30,23 @@ pub fn topological_sort(
     Ok(sorted)
 }
 
+/// Reorders topologically sorted elements by level, i.e. the length of their longest dependency
+/// chain, keeping the topological order within each level.
+pub fn level_order(sorted: Vec<usize>, get_deps: &dyn Fn(usize) -> Vec<usize>) -> Vec<usize> {
+    let mut levels = vec![0; sorted.len()];
+    for &i in &sorted {
+        levels[i] = get_deps(i)
+            .into_iter()
+            .map(|j| levels[j] + 1)
+            .max()
+            .unwrap_or(0);
+    }
+
+    let mut ordered = sorted;
+    ordered.sort_by_key(|&i| levels[i]);
+    ordered
+}
+
 fn topological_sort_visit(
     i: usize,
     visiting: &mut [bool],
@@ -0,0 +1,88 @@
+//! # Union-Find Module
+//!
+//! This module provides a disjoint-set forest over ids, used to group connected signals and to
+//! follow nodes as they are merged.
+
+use std::collections::HashMap;
+
+/// Disjoint sets of ids, with path compression. Ids that were never merged are their own set.
+#[derive(Debug, Default, Clone)]
+pub struct UnionFind {
+    parent: HashMap<u32, u32>,
+}
+
+impl UnionFind {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the representative of the set of an id.
+    pub fn find(&mut self, id: u32) -> u32 {
+        let mut root = id;
+        while let Some(&next) = self.parent.get(&root) {
+            root = next;
+        }
+
+        let mut current = id;
+        while current != root {
+            current = self.parent.insert(current, root).unwrap_or(root);
+        }
+
+        root
+    }
+
+    /// Merges the sets of two ids, the representative of `b` representing the merged set.
+    /// Returns false if they were already in the same set.
+    pub fn union(&mut self, a: u32, b: u32) -> bool {
+        let (root_a, root_b) = (self.find(a), self.find(b));
+        if root_a == root_b {
+            return false;
+        }
+
+        self.parent.insert(root_a, root_b);
+        true
+    }
+
+    /// Merges the set of an id into a new set represented by `root`, an id never seen before, e.g.
+    /// the id of a node two nodes are merged into.
+    pub fn link(&mut self, id: u32, root: u32) {
+        let current = self.find(id);
+        if current != root {
+            self.parent.insert(current, root);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_union_find() {
+        let mut sets = UnionFind::new();
+        assert_eq!(sets.find(1), 1);
+
+        assert!(sets.union(1, 2));
+        assert!(sets.union(3, 2));
+        assert!(!sets.union(1, 3));
+        assert_eq!(sets.find(1), 2);
+        assert_eq!(sets.find(3), 2);
+        assert_eq!(sets.find(4), 4);
+
+        sets.link(3, 10);
+        sets.link(4, 10);
+        for id in [1, 2, 3, 4] {
+            assert_eq!(sets.find(id), 10);
+        }
+    }
+
+    #[test]
+    fn test_union_find_long_chain() {
+        let mut sets = UnionFind::new();
+        for id in 0..100_000 {
+            sets.link(id, id + 1);
+        }
+        assert_eq!(sets.find(0), 100_000);
+        assert_eq!(sets.parent[&50_000], 100_000);
+    }
+}
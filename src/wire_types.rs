@@ -0,0 +1,177 @@
+//! # Wire Types Module
+//!
+//! This module infers the type of each wire of the circuit, so protocol compilers downstream don't
+//! have to re-derive it. Types form a lattice ordered by width: `Bool < Uint(k) < Uint(k') < Field`
+//! for `k < k'`.
+
+use crate::{a_gate_type::AGateType, cli::ValueType};
+use bristol_circuit::BristolCircuit;
+use serde::{Deserialize, Serialize};
+
+/// Type of a wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WireType {
+    /// Either 0 or 1.
+    Bool,
+    /// Unsigned integer of the given number of bits.
+    Uint(u32),
+    /// Any value.
+    Field,
+}
+
+impl WireType {
+    /// Returns the number of bits of the type, `None` for `Field`.
+    pub fn width(&self) -> Option<u32> {
+        match self {
+            WireType::Bool => Some(1),
+            WireType::Uint(width) => Some(*width),
+            WireType::Field => None,
+        }
+    }
+
+    /// Returns the smallest type containing both types.
+    pub fn join(self, other: WireType) -> WireType {
+        match (self.width(), other.width()) {
+            (Some(lhs), Some(rhs)) => WireType::from_width(lhs.max(rhs)),
+            _ => WireType::Field,
+        }
+    }
+
+    /// Returns the largest type contained in both types.
+    pub fn meet(self, other: WireType) -> WireType {
+        match (self.width(), other.width()) {
+            (Some(lhs), Some(rhs)) => WireType::from_width(lhs.min(rhs)),
+            (Some(_), None) => self,
+            (None, _) => other,
+        }
+    }
+
+    /// Returns the smallest type of a constant. Negative values of signed types are `Field`.
+    pub fn of_constant(value: u32, value_type: ValueType) -> WireType {
+        if value_type.is_signed() && (value as i32) < 0 {
+            return WireType::Field;
+        }
+
+        WireType::from_width(u32::BITS - value.leading_zeros())
+    }
+
+    /// Returns the type of the output of a gate, given the types of its inputs.
+    pub fn of_gate(op: AGateType, lhs: WireType, rhs: WireType) -> WireType {
+        match op {
+            _ if op.result_is_boolean() => WireType::Bool,
+            AGateType::ABitAnd => lhs.meet(rhs),
+            AGateType::ABitOr | AGateType::AXor => lhs.join(rhs),
+            _ => WireType::Field,
+        }
+    }
+
+    fn from_width(width: u32) -> WireType {
+        if width <= 1 {
+            WireType::Bool
+        } else {
+            WireType::Uint(width)
+        }
+    }
+}
+
+/// Infers the type of each wire of a Bristol circuit produced by the compiler, indexed by wire.
+/// Inputs and wires produced by unknown gates are `Field`.
+pub fn infer_circuit_wire_types(circuit: &BristolCircuit, value_type: ValueType) -> Vec<WireType> {
+    let mut types = vec![WireType::Field; circuit.wire_count];
+
+    for constant in circuit.info.constants.values() {
+        if let Ok(value) = constant.value.parse::<u32>() {
+            types[constant.wire_index] = WireType::of_constant(value, value_type);
+        }
+    }
+
+    // Gates are in topological order, so their inputs are typed before their outputs
+    for gate in &circuit.gates {
+        let wire_type = match (AGateType::parse_bristol_op(&gate.op), &gate.inputs[..]) {
+            (Some((op, None)), &[lhs, rhs]) => WireType::of_gate(op, types[lhs], types[rhs]),
+            (Some((op, Some(constant))), &[lhs]) => {
+                WireType::of_gate(op, types[lhs], WireType::of_constant(constant, value_type))
+            }
+            _ => continue,
+        };
+        for &output in &gate.outputs {
+            types[output] = wire_type;
+        }
+    }
+
+    types
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bristol_circuit::{CircuitInfo, ConstantInfo, Gate};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_wire_type_lattice() {
+        assert_eq!(WireType::Bool.join(WireType::Uint(8)), WireType::Uint(8));
+        assert_eq!(WireType::Uint(8).join(WireType::Field), WireType::Field);
+        assert_eq!(WireType::Uint(8).meet(WireType::Bool), WireType::Bool);
+        assert_eq!(WireType::Field.meet(WireType::Uint(4)), WireType::Uint(4));
+
+        assert_eq!(WireType::of_constant(0, ValueType::Uint), WireType::Bool);
+        assert_eq!(WireType::of_constant(1, ValueType::Uint), WireType::Bool);
+        assert_eq!(
+            WireType::of_constant(255, ValueType::Uint),
+            WireType::Uint(8)
+        );
+        assert_eq!(
+            WireType::of_constant(-1i32 as u32, ValueType::Uint),
+            WireType::Uint(32)
+        );
+        assert_eq!(
+            WireType::of_constant(-1i32 as u32, ValueType::Sint),
+            WireType::Field
+        );
+    }
+
+    #[test]
+    fn test_infer_circuit_wire_types() {
+        // 0: input, 1: constant 12, 2: 0 < 12, 3: 0 & 12, 4: 2 ^ 3, 5: 0 * 12
+        let gate = |lhs: usize, rhs: usize, out: usize, op: &str| Gate {
+            inputs: vec![lhs, rhs],
+            outputs: vec![out],
+            op: op.to_string(),
+        };
+        let circuit = BristolCircuit {
+            wire_count: 6,
+            info: CircuitInfo {
+                input_name_to_wire_index: HashMap::from([(String::from("0.a"), 0)]),
+                constants: HashMap::from([(
+                    String::from("0.c"),
+                    ConstantInfo {
+                        value: String::from("12"),
+                        wire_index: 1,
+                    },
+                )]),
+                output_name_to_wire_index: HashMap::new(),
+            },
+            io_widths: None,
+            gates: vec![
+                gate(0, 1, 2, "ALt"),
+                gate(0, 1, 3, "ABitAnd"),
+                gate(2, 3, 4, "AXor"),
+                gate(0, 1, 5, "AMul"),
+            ],
+        };
+
+        assert_eq!(
+            infer_circuit_wire_types(&circuit, ValueType::Sint),
+            vec![
+                WireType::Field,
+                WireType::Uint(4),
+                WireType::Bool,
+                WireType::Uint(4),
+                WireType::Uint(4),
+                WireType::Field,
+            ]
+        );
+    }
+}
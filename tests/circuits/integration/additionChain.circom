@@ -0,0 +1,16 @@
+pragma circom 2.1.0;
+
+// Sum of an input array accumulated in a loop
+template additionChain (n) {
+    signal input in[n];
+    signal output out;
+
+    var sum = 0;
+    for (var i = 0; i < n; i++) {
+        sum += in[i];
+    }
+
+    out <== sum;
+}
+
+component main = additionChain(16);
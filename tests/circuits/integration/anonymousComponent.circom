@@ -0,0 +1,25 @@
+pragma circom 2.1.0;
+
+// Weighted sum of two inputs
+template WeightedSum(w) {
+    signal input a;
+    signal input b;
+    signal output out;
+
+    out <== a * w + b;
+}
+
+// Anonymous components with positional and named signal arguments, used in expressions
+template anonymousComponent() {
+    signal input x;
+    signal input y;
+    signal output positional;
+    signal output named;
+    signal output nested;
+
+    positional <== WeightedSum(2)(x, y);
+    named <== WeightedSum(3)(b <== x, a <== y);
+    nested <== WeightedSum(1)(WeightedSum(10)(x, y), 1) * 2;
+}
+
+component main = anonymousComponent();
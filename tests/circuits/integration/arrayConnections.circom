@@ -0,0 +1,38 @@
+pragma circom 2.1.0;
+
+template Double(n) {
+    signal input in[n];
+    signal output out[n];
+
+    for (var i = 0; i < n; i++) {
+        out[i] <== in[i] * 2;
+    }
+}
+
+// Whole array and sub-array connections
+template arrayConnections() {
+    signal input a[2][3];
+    signal output out[2][3];
+    signal output row[3];
+    signal output weights[3];
+
+    signal doubled[2][3];
+    for (var i = 0; i < 2; i++) {
+        for (var j = 0; j < 3; j++) {
+            doubled[i][j] <== a[i][j] * 2;
+        }
+    }
+    out <== doubled;
+
+    component double = Double(3);
+    double.in <== a[1];
+    row <== double.out;
+
+    var w[3];
+    w[0] = 4;
+    w[1] = 5;
+    w[2] = 6;
+    weights <== w;
+}
+
+component main = arrayConnections();
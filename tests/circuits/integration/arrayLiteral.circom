@@ -0,0 +1,20 @@
+pragma circom 2.1.0;
+
+// Array literals assigned to variables and signal arrays
+template arrayLiteral() {
+    signal input x;
+    signal input y;
+    signal output out[3];
+    signal output weighted;
+
+    var w[3] = [1, 2, 3];
+    var m[2][2] = [[1, 0], [w[2], 4]];
+
+    // Mixed signal and constant elements are connected element-wise
+    out <== [x, y + m[1][1], w[1]];
+
+    signal pair[2] <== [x * m[1][0], y];
+    weighted <== pair[0] + pair[1] * w[1];
+}
+
+component main = arrayLiteral();
@@ -0,0 +1,11 @@
+pragma circom 2.1.0;
+
+// A compile-time value that doesn't fit in a 32-bit wire
+template bigConstantOverflow() {
+    signal input a;
+    signal output out;
+
+    out <== a + 10 ** 18;
+}
+
+component main = bigConstantOverflow();
@@ -0,0 +1,16 @@
+pragma circom 2.1.0;
+
+// Compile-time values beyond 32 bits, computed in the field
+template bigConstants() {
+    signal input a;
+    signal output out;
+
+    var p = 21888242871839275222246405745257275088548364400416034343698204186575808495617;
+    var scale = 10 ** 18;
+    var three = scale * 3 \ scale;
+    var five = (p - 1) * (p - 5);
+
+    out <== a * three + five;
+}
+
+component main = bigConstants();
@@ -0,0 +1,17 @@
+pragma circom 2.1.0;
+
+// Signals declared inside conditional bodies are hoisted to the template scope
+template blockSignals(N) {
+    signal input a;
+    signal input b;
+    signal output out;
+
+    if (N > 1) {
+        signal product;
+        product <== a * b;
+    }
+
+    out <== product + a;
+}
+
+component main = blockSignals(2);
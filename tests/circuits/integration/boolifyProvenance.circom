@@ -0,0 +1,11 @@
+pragma circom 2.0.0;
+
+template boolifyProvenance() {
+    signal input a;
+    signal input b;
+    signal output out;
+
+    out <== (a + b) * a;
+}
+
+component main = boolifyProvenance();
@@ -0,0 +1,29 @@
+pragma circom 2.1.0;
+
+// Scales the input by 1..n, producing n outputs
+template Scale(n) {
+    signal input in;
+    signal output out[n];
+
+    for (var i = 0; i < n; i++) {
+        out[i] <== in * (i + 1);
+    }
+}
+
+// Component array where each instance has a parameter dependent shape
+template componentArray(N) {
+    signal input a;
+    signal output out[N - 1];
+
+    component c[N];
+    for (var i = 0; i < N; i++) {
+        c[i] = Scale(i);
+        c[i].in <== a;
+
+        if (i > 0) {
+            out[i - 1] <== c[i].out[i - 1];
+        }
+    }
+}
+
+component main = componentArray(4);
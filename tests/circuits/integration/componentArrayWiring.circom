@@ -0,0 +1,33 @@
+pragma circom 2.1.0;
+
+template MulAdd() {
+    signal input a;
+    signal input b[2];
+    signal output out;
+
+    out <== a * b[0] + b[1];
+}
+
+// Component array whose inputs are wired by several statements after its instantiation
+template componentArrayWiring(N) {
+    signal input x[N];
+    signal input y[N][2];
+    signal output out[N];
+
+    component c[N];
+    for (var i = 0; i < N; i++) {
+        c[i] = MulAdd();
+    }
+    for (var i = 0; i < N; i++) {
+        c[i].a <== x[i];
+    }
+    for (var i = 0; i < N; i++) {
+        c[i].b[0] <== y[i][0];
+    }
+    for (var i = 0; i < N; i++) {
+        c[i].b[1] <-- y[i][1];
+        out[i] <== c[i].out;
+    }
+}
+
+component main = componentArrayWiring(3);
@@ -0,0 +1,24 @@
+// This circuit should fail because c[1] is wired without being instantiated
+
+pragma circom 2.1.0;
+
+template Id() {
+    signal input in;
+    signal output out;
+
+    out <== in;
+}
+
+template componentNotInstantiated() {
+    signal input a;
+    signal output out[2];
+
+    component c[2];
+    c[0] = Id();
+    for (var i = 0; i < 2; i++) {
+        c[i].in <== a;
+        out[i] <== c[i].out;
+    }
+}
+
+component main = componentNotInstantiated();
@@ -0,0 +1,13 @@
+pragma circom 2.1.0;
+
+// Powers and left shifts by a constant only need multiplications
+template constantPowers() {
+    signal input a;
+    signal output cube;
+    signal output shifted;
+
+    cube <== a ** 3;
+    shifted <== a << 2;
+}
+
+component main = constantPowers();
@@ -0,0 +1,15 @@
+pragma circom 2.1.0;
+
+// Constraint equalities on signals and on values known at compile time
+template constraintEquality (n) {
+    signal input a;
+    signal input b;
+    signal output out;
+
+    out <== a * b;
+    out === a * b;
+    a === b;
+    n * 2 === 6;
+}
+
+component main = constraintEquality(3);
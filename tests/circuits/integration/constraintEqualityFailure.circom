@@ -0,0 +1,12 @@
+pragma circom 2.1.0;
+
+// Constraint equality whose sides are known at compile time and differ
+template constraintEqualityFailure (n) {
+    signal input a;
+    signal output out;
+
+    out <== a;
+    n * 2 === 5;
+}
+
+component main = constraintEqualityFailure(3);
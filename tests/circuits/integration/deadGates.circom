@@ -0,0 +1,14 @@
+pragma circom 2.1.0;
+
+// An intermediate signal that no output depends on
+template deadGates () {
+    signal input a;
+    signal input b;
+    signal output out;
+
+    signal unused;
+    unused <== (a + b) * b;
+    out <== a * b;
+}
+
+component main = deadGates();
@@ -0,0 +1,13 @@
+pragma circom 2.1.0;
+
+// Mean of two inputs and an exact third of the first, divided by constants
+template divisions () {
+    signal input in[2];
+    signal output mean;
+    signal output third;
+
+    mean <== (in[0] + in[1]) \ 2;
+    third <== in[0] / 3;
+}
+
+component main = divisions();
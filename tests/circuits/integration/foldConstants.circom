@@ -0,0 +1,18 @@
+pragma circom 2.1.0;
+
+// Inputs multiplied by a hard-coded scale factor, squared in the circuit
+template foldConstants () {
+    signal input in[2];
+    signal output out[2];
+
+    signal scale;
+    scale <== 10;
+    signal scale2;
+    scale2 <== scale * scale;
+
+    for (var i = 0; i < 2; i++) {
+        out[i] <== in[i] * scale2;
+    }
+}
+
+component main = foldConstants();
@@ -0,0 +1,18 @@
+pragma circom 2.1.0;
+
+// A signal hoisted from a conditional body can't be declared again in the template scope
+template hoistedRedeclaration() {
+    signal input a;
+    signal output out;
+
+    if (1 > 0) {
+        signal product;
+        product <== a * a;
+    }
+
+    signal product;
+    product <== a;
+    out <== product;
+}
+
+component main = hoistedRedeclaration();
@@ -0,0 +1,19 @@
+pragma circom 2.1.0;
+
+// Inline switches on a variable and on a signal condition
+template inlineSwitch () {
+    signal input c;
+    signal input a;
+    signal input b;
+    signal output out;
+    signal output scaled;
+
+    var n = 3;
+    // The branch that isn't selected is never evaluated
+    var k = n > 2 ? 10 : 1 / 0;
+    scaled <== a * k;
+
+    out <== c ? a : b;
+}
+
+component main = inlineSwitch();
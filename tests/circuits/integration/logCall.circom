@@ -0,0 +1,14 @@
+pragma circom 2.1.0;
+
+// Log calls shouldn't abort compilation nor affect the circuit
+template logCall() {
+    signal input a;
+    signal input b;
+    signal output out;
+
+    log("Computing the product of", a, "and", b);
+    out <== a * b;
+    log("Done");
+}
+
+component main = logCall();
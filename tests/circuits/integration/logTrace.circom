@@ -0,0 +1,14 @@
+pragma circom 2.1.0;
+
+// Logs a value known at compile time and a signal
+template logTrace (n) {
+    signal input a;
+    signal input b;
+    signal output out;
+
+    signal product <== a * b;
+    log("n =", n * 2, "product:", product);
+    out <== product + n;
+}
+
+component main = logTrace(3);
@@ -0,0 +1,34 @@
+pragma circom 2.1.0;
+
+// Dense layer of identical neurons, each a weighted sum of the inputs plus a bias
+template Neuron(n) {
+    signal input x[n];
+    signal input w[n];
+    signal input b;
+    signal output out;
+
+    var sum = b;
+    for (var i = 0; i < n; i++) {
+        sum += x[i] * w[i];
+    }
+
+    out <== sum;
+}
+
+template Dense(n, m) {
+    signal input x[n];
+    signal input w[m][n];
+    signal input b[m];
+    signal output out[m];
+
+    component neurons[m];
+    for (var j = 0; j < m; j++) {
+        neurons[j] = Neuron(n);
+        neurons[j].x <== x;
+        neurons[j].w <== w[j];
+        neurons[j].b <== b[j];
+        out[j] <== neurons[j].out;
+    }
+}
+
+component main = Dense(3, 4);
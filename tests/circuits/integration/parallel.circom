@@ -0,0 +1,31 @@
+pragma circom 2.1.0;
+
+template parallel Square() {
+    signal input in;
+    signal output out;
+
+    out <== in * in;
+}
+
+template Double() {
+    signal input in;
+    signal output out;
+
+    out <== in + in;
+}
+
+// `parallel` annotations on a template, a component and an anonymous component
+template parallelOps() {
+    signal input a;
+    signal output out[3];
+
+    component square = Square();
+    component double = parallel Double();
+    square.in <== a;
+    double.in <== a;
+    out[0] <== square.out;
+    out[1] <== double.out;
+    out[2] <== parallel Double()(a);
+}
+
+component main = parallelOps();
@@ -0,0 +1,17 @@
+pragma circom 2.0.0;
+
+include "../machine-learning/circomlib-matrix/matMul.circom";
+
+// Offset matrix product, its matMul instance linked from a prebuilt archive
+template OffsetMatMul() {
+    signal input a[1][2];
+    signal input b[2][1];
+    signal output out;
+
+    component mul = matMul(1, 2, 1);
+    mul.a <== a;
+    mul.b <== b;
+    out <== mul.out[0][0] + 1;
+}
+
+component main = OffsetMatMul();
@@ -0,0 +1,17 @@
+pragma circom 2.1.0;
+
+// Outputs whose names start with the name of an input
+template prefixNames() {
+    signal input in;
+    signal input c[2];
+
+    signal output inNegated;
+    signal output complementC[2];
+
+    inNegated <== -in;
+
+    complementC[0] <== ~c[0];
+    complementC[1] <== ~c[1];
+}
+
+component main = prefixNames();
@@ -0,0 +1,12 @@
+pragma circom 2.1.0;
+
+// 2**64 - 2**32 + 1 is the Goldilocks prime, so it is 0 in the Goldilocks field only
+template primeWrap () {
+    signal input a;
+    signal output out;
+
+    var wrapped = 2**64 - 2**32 + 1;
+    out <== a + wrapped;
+}
+
+component main = primeWrap();
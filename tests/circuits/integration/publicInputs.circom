@@ -0,0 +1,16 @@
+pragma circom 2.1.0;
+
+// Public inputs are ordered before private ones, each in declaration order
+template publicInputs() {
+    signal input a;
+    signal input b[2];
+    signal input c;
+
+    signal output sum;
+    signal output product;
+
+    sum <== a + b[0] + b[1];
+    product <== b[1] * c;
+}
+
+component main {public [c, b]} = publicInputs();
@@ -0,0 +1,17 @@
+pragma circom 2.1.0;
+
+// Two independent out of bounds errors, reported together in recovery mode
+template recover() {
+    signal input a;
+    signal output out;
+    signal first[2];
+    signal second[2];
+
+    var i = 2;
+    first[i] <== a;
+    second[i + 1] <== a;
+
+    out <== a + 1;
+}
+
+component main = recover();
@@ -0,0 +1,12 @@
+pragma circom 2.1.0;
+
+// Weighted sum of two inputs plus their product
+template scaledSum () {
+    signal input a;
+    signal input b;
+    signal output out;
+
+    out <== 3 * a + b * 5 + a * b;
+}
+
+component main = scaledSum();
@@ -0,0 +1,11 @@
+pragma circom 2.1.0;
+
+// Connecting arrays of different lengths
+template shapeMismatch() {
+    signal input a[2];
+    signal output out[3];
+
+    out <== a;
+}
+
+component main = shapeMismatch();
@@ -0,0 +1,20 @@
+pragma circom 2.1.0;
+
+// A single computed value feeding several outputs
+template sharedOutputs() {
+    signal input a;
+    signal input b;
+
+    signal output product;
+    signal output productCopy;
+    signal output sum[2];
+
+    signal t <== a * b;
+    product <== t;
+    productCopy <== t;
+
+    sum[0] <== a + b;
+    sum[1] <== sum[0];
+}
+
+component main = sharedOutputs();
@@ -0,0 +1,24 @@
+pragma circom 2.1.0;
+
+template And() {
+    signal input {binary} a;
+    signal input {binary} b;
+    signal output {binary} out;
+
+    out <== a * b;
+}
+
+template signalTags() {
+    signal input {binary} x[2];
+    signal input y;
+    signal output {binary} out;
+    signal output sum;
+
+    component and = And();
+    and.a <== x[0];
+    and.b <== x[1];
+    out <== and.out;
+    sum <== x[0] + y;
+}
+
+component main = signalTags();
@@ -0,0 +1,14 @@
+pragma circom 2.1.0;
+
+// Gates whose result depends on the value type
+template signedGates() {
+    signal input a;
+    signal input b;
+    signal output lt;
+    signal output shr;
+
+    lt <== a < b;
+    shr <== a >> b;
+}
+
+component main = signedGates();
@@ -0,0 +1,14 @@
+pragma circom 2.1.0;
+
+// Compile-time operations whose result depends on the value type
+template signedOps() {
+    signal output lt;
+    signal output shr;
+
+    var a = 4294967294; // -2 as a signed value
+
+    lt <== a < 1;
+    shr <== a >> 1;
+}
+
+component main = signedOps();
@@ -0,0 +1,33 @@
+pragma circom 2.1.0;
+
+// Sum and difference of two inputs
+template SumDiff() {
+    signal input a;
+    signal input b;
+    signal output sum;
+    signal output diff;
+
+    sum <== a + b;
+    diff <== a - b;
+}
+
+// Tuple substitutions of anonymous component outputs and of variables
+template tuple() {
+    signal input x;
+    signal input y;
+    signal output s;
+    signal output d;
+    signal output p;
+
+    (s, d) <== SumDiff()(x, y);
+
+    var i = 1;
+    var j = 2;
+    (i, j) = (j, i);
+
+    signal t;
+    (t, _) <== SumDiff()(x * i, y);
+    p <== t * j;
+}
+
+component main = tuple();
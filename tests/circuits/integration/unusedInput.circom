@@ -0,0 +1,13 @@
+pragma circom 2.1.0;
+
+// The input b isn't read by any gate
+template unusedInput() {
+    signal input a;
+    signal input b;
+
+    signal output out;
+
+    out <== a * a;
+}
+
+component main = unusedInput();
@@ -0,0 +1,16 @@
+pragma circom 2.1.0;
+
+// Affine map whose weights and bias are supplied at compile time
+template weights() {
+    signal input x[2];
+    signal input w[2][2];
+    signal input bias[2];
+
+    signal output out[2];
+
+    for (var i = 0; i < 2; i++) {
+        out[i] <== w[i][0] * x[0] + w[i][1] * x[1] + bias[i];
+    }
+}
+
+component main = weights();
@@ -0,0 +1,17 @@
+pragma circom 2.1.0;
+
+// Outputs of comparisons are booleans, masks bound the width of the result
+template wireTypes() {
+    signal input a;
+    signal input b;
+
+    signal output lt;
+    signal output low;
+    signal output sum;
+
+    lt <== a < b;
+    low <== a & 15;
+    sum <== a + b;
+}
+
+component main = wireTypes();
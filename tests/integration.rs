@@ -30,6 +30,11 @@ enum ArithmeticOperation {
     BOOLAND,
     BITOR,
     BITAND,
+    SHIFTRARITH,
+    SLT,
+    SLEQ,
+    SGT,
+    SGEQ,
 }
 
 impl From<AGateType> for ArithmeticOperation {
@@ -42,7 +47,7 @@ impl From<AGateType> for ArithmeticOperation {
             AGateType::AGt => ArithmeticOperation::GT,
             AGateType::ALEq => ArithmeticOperation::LEQ,
             AGateType::ALt => ArithmeticOperation::LT,
-            AGateType::AMul => ArithmeticOperation::MUL,
+            AGateType::AMul | AGateType::ACMul => ArithmeticOperation::MUL,
             AGateType::ANeq => ArithmeticOperation::NEQ,
             AGateType::ASub => ArithmeticOperation::SUB,
             AGateType::AXor => ArithmeticOperation::XOR,
@@ -55,6 +60,11 @@ impl From<AGateType> for ArithmeticOperation {
             AGateType::ABoolAnd => ArithmeticOperation::BOOLAND,
             AGateType::ABitOr => ArithmeticOperation::BITOR,
             AGateType::ABitAnd => ArithmeticOperation::BITAND,
+            AGateType::AShiftRArith => ArithmeticOperation::SHIFTRARITH,
+            AGateType::ASLt => ArithmeticOperation::SLT,
+            AGateType::ASLEq => ArithmeticOperation::SLEQ,
+            AGateType::ASGt => ArithmeticOperation::SGT,
+            AGateType::ASGEq => ArithmeticOperation::SGEQ,
         }
     }
 }
@@ -112,6 +122,13 @@ impl Executable<u32, CircuitMemory<u32>> for ArithmeticGate {
             ArithmeticOperation::BOOLAND => (a != 0 && b != 0) as u32,
             ArithmeticOperation::BITOR => a | b,
             ArithmeticOperation::BITAND => a & b,
+            ArithmeticOperation::SHIFTRARITH => {
+                (a as i32).checked_shr(b).unwrap_or((a as i32) >> 31) as u32
+            }
+            ArithmeticOperation::SLT => ((a as i32) < (b as i32)) as u32,
+            ArithmeticOperation::SLEQ => ((a as i32) <= (b as i32)) as u32,
+            ArithmeticOperation::SGT => ((a as i32) > (b as i32)) as u32,
+            ArithmeticOperation::SGEQ => ((a as i32) >= (b as i32)) as u32,
         };
 
         memory.write(self.outputs[0], result).unwrap();
@@ -252,7 +269,35 @@ impl ArithmeticCircuit {
 mod integration_tests {
     use super::*;
     use bristol_circuit::ConstantInfo;
-    use circom_2_arithc::{cli::Args, program::compile};
+    use circom_2_arithc::{
+        artifacts::ARTIFACTS_VERSION,
+        builder::CompilerBuilder,
+        check::{check_files, CheckProblem},
+        cli::{
+            Args, ConstraintEquality, DivisionReduction, InstanceNaming, NameNormalization,
+            OutputFormat, Prime, Target,
+        },
+        compiler::{CircuitError, CircuitReport, Compiler},
+        conversions::ConversionKind,
+        deps::{dependency_graph, DependencyGraph},
+        diagnostics::{Diagnostic, Severity},
+        evaluate::{evaluate_circuit, evaluate_files, watch_files, Watchpoint},
+        field::FieldElement,
+        json_circuit::JsonCircuit,
+        lowering::LOWERED_GATES,
+        package::{compile_package, ConstantsPool, PackageManifest},
+        passes::{Pass, PassContext, PassRegistry, PassStage},
+        prebuild::{prebuild, IrArchive, PrebuildManifest, PrebuiltInstances},
+        program::{
+            compile, compile_and_write, compile_with_diagnostics, estimate, Artifacts, ErrorKind,
+            ProgramError,
+        },
+        provenance::{boolify_provenance, evaluate_boolean_lanes, WireProvenance},
+        runtime::RuntimeError,
+        scaffold::init_project,
+        wire_types::WireType,
+    };
+    use std::{cell::Cell, collections::BTreeMap, path::Path, rc::Rc};
 
     fn simulation_test(
         circuit_path: &str,
@@ -276,6 +321,14 @@ mod integration_tests {
         }
     }
 
+    /// Returns the field elements of the given values, keyed by signal name.
+    fn elements(values: &[(&str, u32)]) -> HashMap<String, FieldElement> {
+        values
+            .iter()
+            .map(|&(name, value)| (name.to_string(), FieldElement::from(value)))
+            .collect()
+    }
+
     #[test]
     fn test_add_zero() {
         simulation_test(
@@ -332,6 +385,38 @@ mod integration_tests {
         );
     }
 
+    #[test]
+    fn test_lower_for_boolify() {
+        let mut compiler_input = Args::new(
+            "tests/circuits/integration/infixOps.circom".into(),
+            "./".into(),
+            ValueType::Uint,
+            None,
+        );
+        compiler_input.boolify_width = Some(32);
+        let compiler = compile(&compiler_input).unwrap();
+        assert!(compiler
+            .gates()
+            .iter()
+            .all(|gate| !LOWERED_GATES.contains(&gate.op)));
+
+        // The lowered gates evaluate like the original ones
+        let inputs = (0..6)
+            .map(|i| (format!("0.x{}", i), i))
+            .collect::<HashMap<String, u32>>();
+        let outputs =
+            evaluate_circuit(&compiler.build_circuit().unwrap(), &inputs, ValueType::Uint).unwrap();
+        for (name, expected) in [
+            ("0.idiv_4_3", 1),
+            ("0.pow_2_4", 16),
+            ("0.mod_5_3", 2),
+            ("0.shl_5_1", 10),
+            ("0.shr_5_1", 2),
+        ] {
+            assert_eq!(outputs[name], expected, "{}", name);
+        }
+    }
+
     #[test]
     fn test_matrix_element_multiplication() {
         simulation_test(
@@ -364,6 +449,38 @@ mod integration_tests {
         );
     }
 
+    #[test]
+    fn test_inline_switch() {
+        simulation_test(
+            "tests/circuits/integration/inlineSwitch.circom",
+            &[("0.c", 1), ("0.a", 7), ("0.b", 3)],
+            &[("0.out", 7), ("0.scaled", 70)],
+        );
+        simulation_test(
+            "tests/circuits/integration/inlineSwitch.circom",
+            &[("0.c", 0), ("0.a", 7), ("0.b", 3)],
+            &[("0.out", 3), ("0.scaled", 70)],
+        );
+    }
+
+    #[test]
+    fn test_anonymous_component() {
+        simulation_test(
+            "tests/circuits/integration/anonymousComponent.circom",
+            &[("0.x", 4), ("0.y", 5)],
+            &[("0.positional", 13), ("0.named", 19), ("0.nested", 92)],
+        );
+    }
+
+    #[test]
+    fn test_tuple() {
+        simulation_test(
+            "tests/circuits/integration/tuple.circom",
+            &[("0.x", 7), ("0.y", 3)],
+            &[("0.s", 10), ("0.d", 4), ("0.p", 17)],
+        );
+    }
+
     #[test]
     fn test_x_eq_x() {
         simulation_test(
@@ -374,103 +491,2544 @@ mod integration_tests {
     }
 
     #[test]
-    fn test_out_of_bounds() {
+    fn test_block_signals() {
+        simulation_test(
+            "tests/circuits/integration/blockSignals.circom",
+            &[("0.a", 3), ("0.b", 5)],
+            &[("0.out", 18)],
+        );
+    }
+
+    #[test]
+    fn test_hoisted_redeclaration() {
         let compiler_input = Args::new(
-            "tests/circuits/integration/indexOutOfBounds.circom".into(),
+            "tests/circuits/integration/hoistedRedeclaration.circom".into(),
+            "./".into(),
+            ValueType::Sint,
+            None,
+        );
+        let error = compile(&compiler_input).unwrap_err();
+
+        assert_eq!(error.code(), "E0424");
+        assert_eq!(error.to_string(), "Runtime error: Item already declared");
+    }
+
+    #[test]
+    fn test_component_array() {
+        simulation_test(
+            "tests/circuits/integration/componentArray.circom",
+            &[("0.a", 5)],
+            &[("0.out[0]", 5), ("0.out[1]", 10), ("0.out[2]", 15)],
+        );
+    }
+
+    #[test]
+    fn test_component_array_wiring() {
+        simulation_test(
+            "tests/circuits/integration/componentArrayWiring.circom",
+            &[
+                ("0.x[0]", 1),
+                ("0.x[1]", 2),
+                ("0.x[2]", 3),
+                ("0.y[0][0]", 4),
+                ("0.y[0][1]", 5),
+                ("0.y[1][0]", 6),
+                ("0.y[1][1]", 7),
+                ("0.y[2][0]", 8),
+                ("0.y[2][1]", 9),
+            ],
+            &[("0.out[0]", 9), ("0.out[1]", 19), ("0.out[2]", 33)],
+        );
+    }
+
+    #[test]
+    fn test_instance_workers() {
+        // Components processed by workers give the circuit of the serial processing
+        for path in [
+            "tests/circuits/integration/neurons.circom",
+            "tests/circuits/integration/componentArrayWiring.circom",
+            "tests/circuits/integration/anonymousComponent.circom",
+        ] {
+            let compile_with = |workers: Option<usize>| {
+                let mut compiler_input = Args::new(path.into(), "./".into(), ValueType::Sint, None);
+                compiler_input.instance_workers = workers;
+                compile(&compiler_input).unwrap()
+            };
+            let serial = compile_with(None);
+            let inputs = serial
+                .input_names()
+                .enumerate()
+                .map(|(i, name)| (name.to_string(), i as u32 + 1))
+                .collect::<HashMap<String, u32>>();
+            let serial_outputs =
+                evaluate_circuit(&serial.build_circuit().unwrap(), &inputs, ValueType::Sint)
+                    .unwrap();
+
+            for workers in [1, 2, 8] {
+                let parallel = compile_with(Some(workers));
+                assert_eq!(parallel.gates().len(), serial.gates().len(), "{}", path);
+                assert_eq!(parallel.instances(), serial.instances(), "{}", path);
+                assert!(parallel.input_names().eq(serial.input_names()), "{}", path);
+                assert!(
+                    parallel.output_names().eq(serial.output_names()),
+                    "{}",
+                    path
+                );
+                assert_eq!(
+                    evaluate_circuit(&parallel.build_circuit().unwrap(), &inputs, ValueType::Sint)
+                        .unwrap(),
+                    serial_outputs,
+                    "{}",
+                    path
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_component_array_index_naming() {
+        let mut compiler_input = Args::new(
+            "tests/circuits/integration/componentArray.circom".into(),
             "./".into(),
             ValueType::Sint,
             None,
         );
+        compiler_input.instance_naming = InstanceNaming::Index;
+        let compiler = compile(&compiler_input).unwrap();
+
+        assert_eq!(compiler.get_signals("c[2].out".to_string()).len(), 2);
+        assert_eq!(compiler.get_signals("Scale.".to_string()).len(), 0);
+    }
+
+    #[test]
+    fn test_gate_budgets() {
+        let budgets_path = std::env::temp_dir().join("circom_2_arithc_gate_budgets.json");
+        std::fs::write(&budgets_path, r#"{"Scale": 1}"#).unwrap();
+
+        let mut compiler_input = Args::new(
+            "tests/circuits/integration/componentArray.circom".into(),
+            "./".into(),
+            ValueType::Sint,
+            None,
+        );
+        compiler_input.instance_naming = InstanceNaming::Index;
+        compiler_input.gate_budgets = Some(budgets_path);
         let circuit = compile(&compiler_input);
 
         assert!(circuit.is_err());
         assert_eq!(
             circuit.unwrap_err().to_string(),
-            "Runtime error: Index out of bounds"
+            "Template Scale (instance c[2]) contributed 2 gates, exceeding its budget of 1"
         );
     }
 
     #[test]
-    fn test_constant_sum() {
-        let compiler_input = Args::new(
-            "tests/circuits/integration/constantSum.circom".into(),
+    fn test_stop_after() {
+        // Scale(i) contributes i gates
+        let mut compiler_input = Args::new(
+            "tests/circuits/integration/componentArray.circom".into(),
             "./".into(),
             ValueType::Sint,
             None,
         );
-        let circuit_res = compile(&compiler_input);
+        compiler_input.stop_after = Some(String::from("3"));
+        let compiler = compile(&compiler_input).unwrap();
+        assert_eq!(compiler.gates().len(), 3);
 
-        assert!(circuit_res.is_ok());
+        compiler_input.instance_naming = InstanceNaming::Index;
+        compiler_input.stop_after = Some(String::from("c[1]"));
+        let compiler = compile(&compiler_input).unwrap();
+        assert_eq!(compiler.gates().len(), 1);
+        compiler.build_circuit().unwrap();
+    }
 
-        let circuit = circuit_res.unwrap().build_circuit().unwrap();
+    #[test]
+    fn test_log_call() {
+        simulation_test(
+            "tests/circuits/integration/logCall.circom",
+            &[("0.a", 3), ("0.b", 7)],
+            &[("0.out", 21)],
+        );
+    }
 
-        assert_eq!(circuit.info.constants.len(), 1);
+    #[test]
+    fn test_log_call_diagnostic() {
+        let compiler_input = Args::new(
+            "tests/circuits/integration/logCall.circom".into(),
+            "./".into(),
+            ValueType::Sint,
+            None,
+        );
+        let (compiler, diagnostics) = compile_with_diagnostics(&compiler_input);
+        assert!(compiler.is_some());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
         assert_eq!(
-            circuit.info.constants.get("0.const_signal_8_1"),
-            Some(&ConstantInfo {
-                value: "8".to_string(), // 5 + 3
-                wire_index: 0
-            })
+            diagnostics[0].message,
+            "2 log calls were processed, their strings are treated as no-ops and don't affect the circuit"
         );
     }
 
     #[test]
-    fn test_direct_output() {
+    fn test_self_check() {
+        let mut compiler_input = Args::new(
+            "tests/circuits/integration/matElemMul.circom".into(),
+            "./".into(),
+            ValueType::Sint,
+            None,
+        );
+        compiler_input.self_check = true;
+
+        let compiler = compile(&compiler_input).unwrap();
+        assert!(compiler.debug_assert_valid().is_ok());
+    }
+
+    #[test]
+    fn test_array_literal() {
+        simulation_test(
+            "tests/circuits/integration/arrayLiteral.circom",
+            &[("0.x", 3), ("0.y", 5)],
+            &[
+                ("0.out[0]", 3),
+                ("0.out[1]", 9),
+                ("0.out[2]", 2),
+                ("0.weighted", 19),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_array_connections() {
+        simulation_test(
+            "tests/circuits/integration/arrayConnections.circom",
+            &[
+                ("0.a[0][0]", 1),
+                ("0.a[0][1]", 2),
+                ("0.a[0][2]", 3),
+                ("0.a[1][0]", 4),
+                ("0.a[1][1]", 5),
+                ("0.a[1][2]", 6),
+            ],
+            &[
+                ("0.out[0][0]", 2),
+                ("0.out[0][2]", 6),
+                ("0.out[1][1]", 10),
+                ("0.row[0]", 8),
+                ("0.row[1]", 10),
+                ("0.row[2]", 12),
+                ("0.weights[0]", 4),
+                ("0.weights[1]", 5),
+                ("0.weights[2]", 6),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_boolify_provenance() {
         let compiler_input = Args::new(
-            "tests/circuits/integration/directOutput.circom".into(),
+            "tests/circuits/integration/boolifyProvenance.circom".into(),
             "./".into(),
+            ValueType::Uint,
+            None,
+        );
+        let circuit = compile(&compiler_input).unwrap().build_circuit().unwrap();
+        let boolean = boolify::boolify(&circuit, 8);
+        let provenance = boolify_provenance(&circuit, &boolean, 8);
+
+        // The bits of the listed wires carry the arithmetic values in boolify's own output
+        let inputs = HashMap::from([
+            (String::from("0.a"), vec![5]),
+            (String::from("0.b"), vec![9]),
+        ]);
+        let lanes = evaluate_boolean_lanes(&boolean, &inputs, 8).unwrap();
+        let value = |entry: &WireProvenance| {
+            entry
+                .bit_wires
+                .iter()
+                .enumerate()
+                .map(|(bit, wire)| (lanes[wire.unwrap()] as u32 & 1) << bit)
+                .sum::<u32>()
+        };
+
+        let named = |name: &str| {
+            provenance
+                .iter()
+                .find(|entry| entry.names.iter().any(|n| n == name))
+                .unwrap()
+        };
+        assert_eq!(value(named("0.a")), 5);
+        assert_eq!(value(named("0.b")), 9);
+        assert_eq!(value(named("0.out")), 70);
+
+        let intermediate = provenance
+            .iter()
+            .filter(|entry| entry.names.is_empty())
+            .collect::<Vec<_>>();
+        assert_eq!(intermediate.len(), 1);
+        assert_eq!(value(intermediate[0]), 14);
+    }
+
+    #[test]
+    fn test_compile_and_write() {
+        let output_dir = std::env::temp_dir().join("circom_2_arithc_compile_and_write");
+        let compiler_input = Args::new(
+            "tests/circuits/integration/sum.circom".into(),
+            output_dir.clone(),
             ValueType::Sint,
             None,
         );
-        let circuit_res = compile(&compiler_input);
+        let artifacts = compile_and_write(&compiler_input).unwrap();
 
-        assert!(circuit_res.is_ok());
+        assert_eq!(artifacts.circuit, output_dir.join("circuit.txt"));
+        assert_eq!(artifacts.circuit_info, output_dir.join("circuit_info.json"));
+        assert_eq!(artifacts.report, output_dir.join("report.json"));
+        assert_eq!(artifacts.card, output_dir.join("CIRCUIT_CARD.md"));
+        assert_eq!(artifacts.deps, output_dir.join("deps.json"));
+        assert_eq!(artifacts.metrics, None);
+        assert_eq!(artifacts.events, None);
+        assert_eq!(artifacts.wire_types, None);
+        assert_eq!(artifacts.provenance, None);
+        assert_eq!(artifacts.sym, None);
+        assert_eq!(artifacts.gate_count, 1);
 
-        let circuit = circuit_res.unwrap().build_circuit().unwrap();
+        assert!(artifacts.circuit.exists());
+        assert!(artifacts.circuit_info.exists());
+        assert!(artifacts.report.exists());
+        assert!(artifacts.deps.exists());
+        assert!(artifacts.card.exists());
+    }
 
-        let expected_output = HashMap::from([("0.out".to_string(), 0)]);
-        assert_eq!(circuit.info.output_name_to_wire_index, expected_output);
-        assert_eq!(circuit.info.constants.len(), 1);
-        assert_eq!(
-            circuit.info.constants.get("0.const_signal_42_1"),
-            Some(&ConstantInfo {
-                value: "42".to_string(),
-                wire_index: 0
+    #[test]
+    fn test_report_gate_statistics() {
+        let output_dir = std::env::temp_dir().join("circom_2_arithc_report_gate_statistics");
+        let compiler_input = Args::new(
+            "tests/circuits/integration/matElemMul.circom".into(),
+            output_dir,
+            ValueType::Sint,
+            None,
+        );
+        let artifacts = compile_and_write(&compiler_input).unwrap();
+        let report = CircuitReport::from_path(&artifacts.report).unwrap();
+
+        assert_eq!(report.gate_counts().get("AMul"), Some(&4));
+        assert_eq!(report.gate_counts().values().sum::<usize>(), 4);
+        assert_eq!(report.multiplicative_depth(), 1);
+        assert_eq!(report.constant_count(), 0);
+        assert_eq!(report.wire_count(), 12);
+    }
+
+    #[test]
+    fn test_circuit_limits() {
+        let output_dir = std::env::temp_dir().join("circom_2_arithc_circuit_limits");
+        let mut compiler_input = Args::new(
+            "tests/circuits/integration/matElemMul.circom".into(),
+            output_dir.clone(),
+            ValueType::Sint,
+            None,
+        );
+        compiler_input.max_wires = Some(12);
+        compiler_input.max_gates = Some(4);
+        assert!(compile_and_write(&compiler_input).is_ok());
+
+        compiler_input.max_wires = Some(11);
+        let error = compile_and_write(&compiler_input).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Limit);
+        assert_eq!(error.code(), "E0605");
+        assert!(matches!(
+            error,
+            ProgramError::CircuitError(CircuitError::LimitExceeded {
+                count: 12,
+                limit: 11,
+                ..
             })
+        ));
+    }
+
+    #[test]
+    fn test_load_artifacts() {
+        let output_dir = std::env::temp_dir().join("circom_2_arithc_load_artifacts");
+        let mut compiler_input = Args::new(
+            "tests/circuits/integration/sum.circom".into(),
+            output_dir.clone(),
+            ValueType::Sint,
+            None,
+        );
+        compiler_input.signal_map = true;
+        compiler_input.meta = vec![(String::from("model_version"), String::from("1.2"))];
+        compiler_input.emit_graphviz = true;
+        let artifacts = compile_and_write(&compiler_input).unwrap();
+        assert_eq!(artifacts.manifest, output_dir.join("manifest.json"));
+
+        let loaded = Artifacts::load(&output_dir).unwrap();
+        let manifest = loaded.manifest.unwrap();
+        assert_eq!(manifest.version, ARTIFACTS_VERSION);
+        assert_eq!(manifest.format, OutputFormat::Bristol);
+        assert_eq!(manifest.files["signal_map"], "signals.sym");
+        assert!(!manifest.files.contains_key("wire_types"));
+        assert_eq!(manifest.metadata, compiler_input.metadata());
+        assert_eq!(manifest.files["graphviz"], "circuit.dot");
+        let dot = std::fs::read_to_string(artifacts.graphviz.unwrap()).unwrap();
+        assert!(dot.starts_with("digraph circuit {"));
+
+        let circuit = loaded.circuit.unwrap();
+        assert_eq!(circuit.gates.len(), artifacts.gate_count);
+        assert_eq!(
+            loaded.circuit_info.unwrap().input_name_to_wire_index,
+            circuit.info.input_name_to_wire_index
         );
+        let report = loaded.report.unwrap();
+        assert_eq!(report.inputs().len(), 2);
+        assert_eq!(report.metadata()["model_version"], "1.2");
+        assert!(loaded.deps.is_some());
+        assert!(loaded.signal_map.is_some());
+        assert!(loaded.wire_types.is_none());
+        assert!(loaded.log.is_none());
+        assert!(loaded.conversions.is_none());
     }
 
-    #[ignore]
     #[test]
-    fn test_under_constrained() {
-        // FIXME: There should be an error instead (zero comes from default initialization, not from
-        //        running the circuit)
-        simulation_test(
-            "tests/circuits/integration/underConstrained.circom",
-            &[],
-            &[("0.x", 0)],
+    fn test_canonical_artifacts() {
+        let write = |name: &str| {
+            let output_dir = std::env::temp_dir().join(name);
+            let compiler_input = Args::new(
+                "tests/circuits/integration/matElemMul.circom".into(),
+                output_dir.clone(),
+                ValueType::Sint,
+                None,
+            );
+            compile_and_write(&compiler_input).unwrap();
+            output_dir
+        };
+        let first = write("circom_2_arithc_canonical_first");
+        let second = write("circom_2_arithc_canonical_second");
+
+        // The maps the metadata is built from iterate in a different order in each compilation
+        for file in [
+            "circuit.txt",
+            "circuit_info.json",
+            "report.json",
+            "deps.json",
+            "manifest.json",
+        ] {
+            let json = std::fs::read_to_string(first.join(file)).unwrap();
+            assert_eq!(json, std::fs::read_to_string(second.join(file)).unwrap());
+        }
+        let info = std::fs::read_to_string(first.join("circuit_info.json")).unwrap();
+        let position = |key: &str| info.find(key).unwrap();
+        assert!(position("\"0.a[0][0]\"") < position("\"0.a[0][1]\""));
+        assert!(position("\"constants\"") < position("\"input_name_to_wire_index\""));
+    }
+
+    #[test]
+    fn test_log_trace() {
+        let output_dir = std::env::temp_dir().join("circom_2_arithc_log_trace");
+        let compiler_input = Args::new(
+            "tests/circuits/integration/logTrace.circom".into(),
+            output_dir.clone(),
+            ValueType::Sint,
+            None,
+        );
+
+        let (circuit, trace) = compile(&compiler_input)
+            .unwrap()
+            .build_circuit_with_log_trace()
+            .unwrap();
+        assert_eq!(trace.len(), 1);
+        assert_eq!(trace[0].context, "0");
+        assert_eq!(trace[0].message, "n = 6 product: 0.product");
+        let product = trace[0].signals[0].wire.unwrap();
+        // The logged wire is the output of the multiplication
+        assert!(circuit
+            .gates
+            .iter()
+            .any(|gate| gate.op == "AMul" && gate.outputs == [product]));
+
+        let artifacts = compile_and_write(&compiler_input).unwrap();
+        assert_eq!(artifacts.log, Some(output_dir.join("log.json")));
+        let loaded = Artifacts::load(&output_dir).unwrap();
+        assert_eq!(loaded.manifest.unwrap().files["log"], "log.json");
+        assert_eq!(loaded.log.unwrap(), trace);
+    }
+
+    #[test]
+    fn test_conversions() {
+        let output_dir = std::env::temp_dir().join("circom_2_arithc_conversions");
+        std::fs::create_dir_all(&output_dir).unwrap();
+        let config_path = output_dir.join("conversions_config.json");
+        std::fs::write(
+            &config_path,
+            r#"{ "width": 8, "wires": [{ "signal": "0.?" }, { "signal": "0.out", "kind": "b2a" }] }"#,
+        )
+        .unwrap();
+        let mut compiler_input = Args::new(
+            "tests/circuits/integration/scaledSum.circom".into(),
+            output_dir.clone(),
+            ValueType::Sint,
+            None,
+        );
+        compiler_input.conversions = Some(config_path);
+
+        let artifacts = compile_and_write(&compiler_input).unwrap();
+        assert_eq!(
+            artifacts.conversions,
+            Some(output_dir.join("conversions.json"))
         );
+        let loaded = Artifacts::load(&output_dir).unwrap();
+        let plan = loaded.conversions.unwrap();
+        let circuit = loaded.circuit.unwrap();
+        assert_eq!(plan.conversions.len(), 3);
+        assert_eq!(plan.wire_count, circuit.wire_count + 3 * 8);
+        let out = circuit.info.output_name_to_wire_index["0.out"];
+        assert!(plan
+            .conversions
+            .iter()
+            .any(|conversion| conversion.arithmetic_wire == out
+                && conversion.kind == ConversionKind::B2a));
+
+        // Conversions are counted along with the gates
+        let report = loaded.report.unwrap();
+        assert_eq!(report.gate_counts()["A2B"], 2);
+        assert_eq!(report.gate_counts()["B2A"], 1);
+
+        // Boolean circuits have no arithmetic wires
+        compiler_input.boolify_width = Some(8);
+        assert!(matches!(
+            compile_and_write(&compiler_input),
+            Err(ProgramError::InvalidConversions(_))
+        ));
     }
 
-    #[ignore]
     #[test]
-    fn test_prefix_ops() {
-        // FIXME: The compiler sees several of the outputs as inputs, leading to the error below
-        //        CircuitError(Inconsistency {
-        //            message: "Node 10 used for both input 0.complementC and output 0.complementC"
-        //        })
-        simulation_test(
-            "tests/circuits/integration/prefixOps.circom",
-            &[("0.a", 0), ("0.b", 1), ("0.c", 2)],
-            &[
-                ("0.negateA", 0),                                          // -0
-                ("0.notA", 1),                                             // !0
-                ("0.notB", 0),                                             // !1
-                ("0.notC", 0),                                             // !2
-                ("0.complementA", 0b_11111111_11111111_11111111_11111111), // ~0
-                ("0.complementB", 0b_11111111_11111111_11111111_11111110), // ~1
-                ("0.complementC", 0b_11111111_11111111_11111111_11111101), // ~2
-            ],
+    fn test_dependency_graph() {
+        let compiler_input = Args::new(
+            "tests/circuits/machine-learning/PointwiseConv2D.circom".into(),
+            "./".into(),
+            ValueType::Sint,
+            None,
+        );
+        let graph = dependency_graph(&compiler_input).unwrap();
+
+        assert!(graph.files.len() > 1);
+        assert!(graph.files.iter().all(|file| file.hash.len() == 16));
+        assert!(graph.includes.iter().any(|edge| {
+            edge.from.ends_with("PointwiseConv2D.circom") && edge.to.ends_with("matElemMul.circom")
+        }));
+    }
+
+    #[test]
+    fn test_max_steps() {
+        let mut compiler_input = Args::new(
+            "tests/circuits/integration/componentArray.circom".into(),
+            "./".into(),
+            ValueType::Sint,
+            None,
+        );
+        compiler_input.max_steps = Some(10);
+        let circuit = compile(&compiler_input);
+
+        assert!(circuit.is_err());
+        assert_eq!(
+            circuit.unwrap_err().to_string(),
+            "Runtime error: Step limit exceeded: evaluated more than 10 statements and expressions"
+        );
+    }
+
+    #[test]
+    fn test_shape_mismatch() {
+        let compiler_input = Args::new(
+            "tests/circuits/integration/shapeMismatch.circom".into(),
+            "./".into(),
+            ValueType::Sint,
+            None,
+        );
+        let circuit = compile(&compiler_input);
+
+        assert!(circuit.is_err());
+        assert_eq!(
+            circuit.unwrap_err().to_string(),
+            "Shape mismatch: expected [3], found [2]"
         );
     }
+
+    #[test]
+    fn test_out_of_bounds() {
+        let compiler_input = Args::new(
+            "tests/circuits/integration/indexOutOfBounds.circom".into(),
+            "./".into(),
+            ValueType::Sint,
+            None,
+        );
+        let circuit = compile(&compiler_input);
+
+        assert!(circuit.is_err());
+        assert_eq!(
+            circuit.unwrap_err().to_string(),
+            "Runtime error: Index out of bounds"
+        );
+    }
+
+    #[test]
+    fn test_component_not_instantiated() {
+        let compiler_input = Args::new(
+            "tests/circuits/integration/componentNotInstantiated.circom".into(),
+            "./".into(),
+            ValueType::Sint,
+            None,
+        );
+        let error = compile(&compiler_input).unwrap_err();
+
+        assert_eq!(error.code(), "E0431");
+        assert_eq!(
+            error.to_string(),
+            "Runtime error: Component c[1] is accessed before it is instantiated"
+        );
+    }
+
+    #[test]
+    fn test_error_context() {
+        let mut compiler_input = Args::new(
+            "tests/circuits/integration/indexOutOfBounds.circom".into(),
+            "./".into(),
+            ValueType::Sint,
+            None,
+        );
+        let error = compile(&compiler_input).unwrap_err();
+
+        assert_eq!(error.kind(), ErrorKind::Program);
+        assert!(matches!(
+            error.root(),
+            ProgramError::RuntimeError(RuntimeError::IndexOutOfBounds)
+        ));
+
+        // The failing assignment, then the loop it is in
+        let locations = error.locations();
+        assert_eq!(
+            locations
+                .iter()
+                .map(|location| (location.line, location.instance.as_str()))
+                .collect::<Vec<_>>(),
+            vec![(9, "0"), (8, "0")]
+        );
+        assert!(locations[0].file.ends_with("indexOutOfBounds.circom"));
+
+        let report = error.report().to_string();
+        let lines = report.lines().collect::<Vec<&str>>();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "Runtime error: Index out of bounds");
+        assert!(lines[1].ends_with("indexOutOfBounds.circom:9 (in 0)"));
+
+        // Underlying errors stay reachable through the standard error chain
+        let mut cause = std::error::Error::source(&error);
+        let mut runtime_error = None;
+        while let Some(error) = cause {
+            runtime_error = runtime_error.or(error.downcast_ref::<RuntimeError>());
+            cause = error.source();
+        }
+        assert!(matches!(
+            runtime_error,
+            Some(RuntimeError::IndexOutOfBounds)
+        ));
+
+        compiler_input.max_steps = Some(10);
+        assert_eq!(
+            compile(&compiler_input).unwrap_err().kind(),
+            ErrorKind::Limit
+        );
+    }
+
+    #[test]
+    fn test_recover() {
+        let output_dir = std::env::temp_dir().join("circom_2_arithc_recover");
+        let mut compiler_input = Args::new(
+            "tests/circuits/integration/recover.circom".into(),
+            output_dir.clone(),
+            ValueType::Sint,
+            None,
+        );
+        assert_eq!(
+            compile(&compiler_input).unwrap_err().to_string(),
+            "Runtime error: Index out of bounds"
+        );
+
+        compiler_input.recover = true;
+        let error = compile_and_write(&compiler_input).unwrap_err();
+        assert_eq!(error.to_string(), "Compilation failed with 2 errors");
+
+        let diagnostics: Vec<Diagnostic> = serde_json::from_str(
+            &std::fs::read_to_string(output_dir.join("diagnostics.json")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(diagnostics.len(), 2);
+        for diagnostic in &diagnostics {
+            assert_eq!(diagnostic.context, "0");
+            assert_eq!(diagnostic.message, "Runtime error: Index out of bounds");
+            assert_eq!(diagnostic.code.as_deref(), Some("E0423"));
+        }
+        let spans = diagnostics
+            .iter()
+            .map(|diagnostic| {
+                let span = diagnostic.span.as_ref().unwrap();
+                (span.line, span.column, span.text.as_str())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            spans,
+            vec![
+                (11, 5, "    first[i] <== a;"),
+                (12, 5, "    second[i + 1] <== a;")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compile_with_diagnostics() {
+        let compiler_input = Args::new(
+            "tests/circuits/integration/recover.circom".into(),
+            "./".into(),
+            ValueType::Sint,
+            None,
+        );
+        let (compiler, diagnostics) = compile_with_diagnostics(&compiler_input);
+        assert!(compiler.is_none());
+        assert_eq!(diagnostics.len(), 2);
+        assert!(diagnostics
+            .iter()
+            .all(|diagnostic| diagnostic.severity == Severity::Error));
+
+        let rendered = diagnostics[0].to_string();
+        let lines = rendered.lines().collect::<Vec<&str>>();
+        assert_eq!(lines[0], "error[E0423]: Runtime error: Index out of bounds");
+        assert!(lines[1].ends_with("recover.circom:11:5"));
+        assert_eq!(lines[3], "11 |     first[i] <== a;");
+        assert_eq!(lines[4], "   |     ^");
+
+        // Programs that compile only report their warnings
+        let compiler_input = Args::new(
+            "tests/circuits/integration/constantSum.circom".into(),
+            "./".into(),
+            ValueType::Sint,
+            None,
+        );
+        let (compiler, diagnostics) = compile_with_diagnostics(&compiler_input);
+        assert!(compiler.is_some());
+        assert!(diagnostics
+            .iter()
+            .all(|diagnostic| diagnostic.severity == Severity::Warning));
+    }
+
+    #[test]
+    fn test_constant_sum() {
+        let compiler_input = Args::new(
+            "tests/circuits/integration/constantSum.circom".into(),
+            "./".into(),
+            ValueType::Sint,
+            None,
+        );
+        let circuit_res = compile(&compiler_input);
+
+        assert!(circuit_res.is_ok());
+
+        let circuit = circuit_res.unwrap().build_circuit().unwrap();
+
+        assert_eq!(circuit.info.constants.len(), 1);
+        assert_eq!(
+            circuit.info.constants.get("0.const_signal_8_1"),
+            Some(&ConstantInfo {
+                value: "8".to_string(), // 5 + 3
+                wire_index: 0
+            })
+        );
+    }
+
+    #[test]
+    fn test_direct_output() {
+        let compiler_input = Args::new(
+            "tests/circuits/integration/directOutput.circom".into(),
+            "./".into(),
+            ValueType::Sint,
+            None,
+        );
+        let circuit_res = compile(&compiler_input);
+
+        assert!(circuit_res.is_ok());
+
+        let circuit = circuit_res.unwrap().build_circuit().unwrap();
+
+        let expected_output = HashMap::from([("0.out".to_string(), 0)]);
+        assert_eq!(circuit.info.output_name_to_wire_index, expected_output);
+        assert_eq!(circuit.info.constants.len(), 1);
+        assert_eq!(
+            circuit.info.constants.get("0.const_signal_42_1"),
+            Some(&ConstantInfo {
+                value: "42".to_string(),
+                wire_index: 0
+            })
+        );
+    }
+
+    #[test]
+    fn test_constant_outputs() {
+        let compiler_input = Args::new(
+            "tests/circuits/integration/directOutput.circom".into(),
+            "./".into(),
+            ValueType::Sint,
+            None,
+        );
+        let compiler = compile(&compiler_input).unwrap();
+
+        assert_eq!(
+            compiler.constant_outputs(),
+            vec![("0.out".to_string(), Some(FieldElement::from(42)))]
+        );
+    }
+
+    #[test]
+    fn test_signed_ops() {
+        let constant_outputs = |value_type| {
+            let compiler_input = Args::new(
+                "tests/circuits/integration/signedOps.circom".into(),
+                "./".into(),
+                value_type,
+                None,
+            );
+            compile(&compiler_input)
+                .unwrap()
+                .constant_outputs()
+                .into_iter()
+                .map(|(name, value)| {
+                    (
+                        name,
+                        value.and_then(|value| value.to_word(value_type, Prime::Bn128)),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            constant_outputs(ValueType::Sint),
+            vec![
+                ("0.lt".to_string(), Some(1)),
+                ("0.shr".to_string(), Some(u32::MAX))
+            ]
+        );
+        assert_eq!(
+            constant_outputs(ValueType::Uint),
+            vec![
+                ("0.lt".to_string(), Some(0)),
+                ("0.shr".to_string(), Some(i32::MAX as u32))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_signed_gates() {
+        let signed_gates_circuit = |value_type, signed_gates| {
+            let mut compiler_input = Args::new(
+                "tests/circuits/integration/signedGates.circom".into(),
+                "./".into(),
+                value_type,
+                None,
+            );
+            compiler_input.signed_gates = signed_gates;
+            compile(&compiler_input).unwrap().build_circuit().unwrap()
+        };
+        let gate_ops = |value_type, signed_gates| {
+            let mut ops = signed_gates_circuit(value_type, signed_gates)
+                .gates
+                .into_iter()
+                .map(|gate| gate.op)
+                .collect::<Vec<String>>();
+            ops.sort();
+            ops
+        };
+
+        // Signed gates are opt-in
+        assert_eq!(gate_ops(ValueType::Sint, false), ["ALt", "AShiftR"]);
+        assert_eq!(gate_ops(ValueType::Sint, true), ["ASLt", "AShiftRArith"]);
+        assert_eq!(gate_ops(ValueType::Uint, true), ["ALt", "AShiftR"]);
+
+        let arithmetic_circuit =
+            ArithmeticCircuit::new_from_bristol(signed_gates_circuit(ValueType::Sint, true))
+                .unwrap();
+        for (b, shr) in [(1, u32::MAX), (40, u32::MAX)] {
+            let inputs = HashMap::from([
+                (String::from("0.a"), -2i32 as u32),
+                (String::from("0.b"), b),
+            ]);
+            let outputs = arithmetic_circuit.run(inputs).unwrap();
+            assert_eq!(outputs["0.lt"], 1);
+            assert_eq!(outputs["0.shr"], shr);
+        }
+
+        // Boolify has no signed gates to lower them to
+        let mut compiler_input = Args::new(
+            "tests/circuits/integration/signedGates.circom".into(),
+            std::env::temp_dir().join("circom_2_arithc_signed_gates"),
+            ValueType::Sint,
+            Some(32),
+        );
+        compiler_input.signed_gates = true;
+        assert!(matches!(
+            compile_and_write(&compiler_input),
+            Err(ProgramError::UnsupportedByBoolify(ops)) if ops == ["ASLt", "AShiftRArith"]
+        ));
+    }
+
+    #[test]
+    fn test_big_constants() {
+        simulation_test(
+            "tests/circuits/integration/bigConstants.circom",
+            &[("0.a", 4)],
+            &[("0.out", 17)],
+        );
+
+        let output_dir = std::env::temp_dir().join("circom_2_arithc_big_constants");
+        let mut compiler_input = Args::new(
+            "tests/circuits/integration/bigConstantOverflow.circom".into(),
+            output_dir,
+            ValueType::Sint,
+            None,
+        );
+        // The constant only has to fit in a word once it is emitted as a wire
+        let compiler = compile(&compiler_input).unwrap();
+        assert_eq!(
+            compiler.build_circuit().unwrap_err().to_string(),
+            "Value 1000000000000000000 is out of range for Sint wires"
+        );
+
+        // R1CS coefficients are field elements
+        compiler_input.format = OutputFormat::R1cs;
+        let artifacts = compile_and_write(&compiler_input).unwrap();
+        let r1cs = std::fs::read(&artifacts.circuit).unwrap();
+        let mut coefficient = 1_000_000_000_000_000_000u64.to_le_bytes().to_vec();
+        coefficient.resize(32, 0);
+        assert!(r1cs.windows(32).any(|window| window == coefficient));
+    }
+
+    #[test]
+    fn test_prime() {
+        let output_dir = std::env::temp_dir().join("circom_2_arithc_prime");
+        let mut compiler_input = Args::new(
+            "tests/circuits/integration/primeWrap.circom".into(),
+            output_dir.clone(),
+            ValueType::Sint,
+            None,
+        );
+        assert_eq!(
+            compile(&compiler_input)
+                .unwrap()
+                .build_circuit()
+                .unwrap_err()
+                .to_string(),
+            "Value 18446744069414584321 is out of range for Sint wires"
+        );
+
+        compiler_input.prime = Prime::Goldilocks;
+        let circuit = compile(&compiler_input).unwrap().build_circuit().unwrap();
+        let arithmetic_circuit = ArithmeticCircuit::new_from_bristol(circuit).unwrap();
+        let outputs = arithmetic_circuit
+            .run(HashMap::from([(String::from("0.a"), 5)]))
+            .unwrap();
+        assert_eq!(outputs["0.out"], 5);
+
+        let artifacts = compile_and_write(&compiler_input).unwrap();
+        let circuit_info: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(artifacts.circuit_info).unwrap())
+                .unwrap();
+        assert_eq!(circuit_info["prime"], "goldilocks");
+    }
+
+    #[test]
+    fn test_signal_tags() {
+        let output_dir = std::env::temp_dir().join("circom_2_arithc_signal_tags");
+        let mut compiler_input = Args::new(
+            "tests/circuits/integration/signalTags.circom".into(),
+            output_dir,
+            ValueType::Sint,
+            None,
+        );
+        let artifacts = compile_and_write(&compiler_input).unwrap();
+        let circuit_info: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&artifacts.circuit_info).unwrap())
+                .unwrap();
+        let wire = |name: &str| {
+            circuit_info["input_name_to_wire_index"][name]
+                .as_u64()
+                .or(circuit_info["output_name_to_wire_index"][name].as_u64())
+                .unwrap()
+                .to_string()
+        };
+        let tags = &circuit_info["tags"];
+        for name in ["0.x[0]", "0.x[1]", "0.out"] {
+            assert_eq!(tags[wire(name)], serde_json::json!(["binary"]), "{}", name);
+        }
+        for name in ["0.y", "0.sum"] {
+            assert!(tags.get(wire(name)).is_none(), "{}", name);
+        }
+
+        // Tags apply to arithmetic wires, boolean circuits have none
+        compiler_input.boolify_width = Some(32);
+        let artifacts = compile_and_write(&compiler_input).unwrap();
+        let circuit_info: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&artifacts.circuit_info).unwrap())
+                .unwrap();
+        assert!(circuit_info.get("tags").is_none());
+    }
+
+    #[ignore]
+    #[test]
+    fn test_under_constrained() {
+        // FIXME: There should be an error instead (zero comes from default initialization, not from
+        //        running the circuit)
+        simulation_test(
+            "tests/circuits/integration/underConstrained.circom",
+            &[],
+            &[("0.x", 0)],
+        );
+    }
+
+    #[test]
+    fn test_prefix_ops() {
+        simulation_test(
+            "tests/circuits/integration/prefixOps.circom",
+            &[("0.a", 0), ("0.b", 1), ("0.c", 2)],
+            &[
+                ("0.negateA", 0),                                          // -0
+                ("0.notA", 1),                                             // !0
+                ("0.notB", 0),                                             // !1
+                ("0.notC", 0),                                             // !2
+                ("0.complementA", 0b_11111111_11111111_11111111_11111111), // ~0
+                ("0.complementB", 0b_11111111_11111111_11111111_11111110), // ~1
+                ("0.complementC", 0b_11111111_11111111_11111111_11111101), // ~2
+            ],
+        );
+    }
+
+    #[test]
+    fn test_prefix_names() {
+        let compiler_input = Args::new(
+            "tests/circuits/integration/prefixNames.circom".into(),
+            "./".into(),
+            ValueType::Sint,
+            None,
+        );
+        let circuit = compile(&compiler_input).unwrap().build_circuit().unwrap();
+
+        let mut inputs = circuit
+            .info
+            .input_name_to_wire_index
+            .keys()
+            .cloned()
+            .collect::<Vec<String>>();
+        inputs.sort();
+        assert_eq!(inputs, vec!["0.c[0]", "0.c[1]", "0.in"]);
+        assert_eq!(circuit.info.output_name_to_wire_index.len(), 3);
+
+        simulation_test(
+            "tests/circuits/integration/prefixNames.circom",
+            &[("0.in", 0), ("0.c[0]", 0), ("0.c[1]", 1)],
+            &[
+                ("0.inNegated", 0),
+                ("0.complementC[0]", 0b_11111111_11111111_11111111_11111111),
+                ("0.complementC[1]", 0b_11111111_11111111_11111111_11111110),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_public_inputs_order() {
+        let compiler_input = Args::new(
+            "tests/circuits/integration/publicInputs.circom".into(),
+            "./".into(),
+            ValueType::Sint,
+            None,
+        );
+        let circuit = compile(&compiler_input).unwrap().build_circuit().unwrap();
+
+        let inputs = &circuit.info.input_name_to_wire_index;
+        assert_eq!(inputs["0.b[0]"], 0);
+        assert_eq!(inputs["0.b[1]"], 1);
+        assert_eq!(inputs["0.c"], 2);
+        assert_eq!(inputs["0.a"], 3);
+
+        let outputs = &circuit.info.output_name_to_wire_index;
+        assert!(outputs["0.sum"] < outputs["0.product"]);
+    }
+
+    #[test]
+    fn test_inputs_template() {
+        let output_dir = std::env::temp_dir().join("circom_2_arithc_inputs_template");
+        let mut compiler_input = Args::new(
+            "tests/circuits/integration/componentArrayWiring.circom".into(),
+            output_dir.clone(),
+            ValueType::Sint,
+            None,
+        );
+        compiler_input.inputs_template = true;
+        let artifacts = compile_and_write(&compiler_input).unwrap();
+
+        let inputs_path = artifacts.inputs_template.unwrap();
+        assert_eq!(inputs_path, output_dir.join("inputs.json"));
+        let inputs: BTreeMap<String, Option<u32>> =
+            serde_json::from_str(&std::fs::read_to_string(inputs_path).unwrap()).unwrap();
+
+        // Every input wire of the circuit is listed, without the component signals wired to them
+        let info: bristol_circuit::CircuitInfo =
+            serde_json::from_str(&std::fs::read_to_string(artifacts.circuit_info).unwrap())
+                .unwrap();
+        let mut names = info
+            .input_name_to_wire_index
+            .keys()
+            .cloned()
+            .collect::<Vec<String>>();
+        names.sort();
+        assert_eq!(inputs.keys().cloned().collect::<Vec<String>>(), names);
+        assert_eq!(inputs.len(), 9);
+        assert!(inputs.contains_key("0.y[2][1]"));
+        assert!(inputs.values().all(Option::is_none));
+    }
+
+    #[test]
+    fn test_wire_types() {
+        let output_dir = std::env::temp_dir().join("circom_2_arithc_wire_types");
+        let mut compiler_input = Args::new(
+            "tests/circuits/integration/wireTypes.circom".into(),
+            output_dir.clone(),
+            ValueType::Sint,
+            None,
+        );
+        compiler_input.wire_types = true;
+        let artifacts = compile_and_write(&compiler_input).unwrap();
+
+        let wire_types_path = artifacts.wire_types.unwrap();
+        assert_eq!(wire_types_path, output_dir.join("wire_types.json"));
+        let wire_types: Vec<WireType> =
+            serde_json::from_str(&std::fs::read_to_string(wire_types_path).unwrap()).unwrap();
+        assert_eq!(wire_types.len(), artifacts.wire_count);
+
+        let info: bristol_circuit::CircuitInfo =
+            serde_json::from_str(&std::fs::read_to_string(artifacts.circuit_info).unwrap())
+                .unwrap();
+        let outputs = &info.output_name_to_wire_index;
+        assert_eq!(wire_types[outputs["0.lt"]], WireType::Bool);
+        assert_eq!(wire_types[outputs["0.low"]], WireType::Uint(4));
+        assert_eq!(wire_types[outputs["0.sum"]], WireType::Field);
+        assert_eq!(
+            wire_types[info.input_name_to_wire_index["0.a"]],
+            WireType::Field
+        );
+    }
+
+    #[test]
+    fn test_r1cs_format() {
+        let output_dir = std::env::temp_dir().join("circom_2_arithc_r1cs");
+        let mut compiler_input = Args::new(
+            "tests/circuits/integration/sum.circom".into(),
+            output_dir.clone(),
+            ValueType::Sint,
+            None,
+        );
+        compiler_input.format = OutputFormat::R1cs;
+        let artifacts = compile_and_write(&compiler_input).unwrap();
+
+        assert_eq!(artifacts.circuit, output_dir.join("circuit.r1cs"));
+        let r1cs = std::fs::read(&artifacts.circuit).unwrap();
+        assert_eq!(&r1cs[..4], b"r1cs");
+
+        let sym_path = artifacts.sym.unwrap();
+        assert_eq!(sym_path, output_dir.join("circuit.sym"));
+        assert_eq!(
+            std::fs::read_to_string(sym_path).unwrap(),
+            "1,1,0,0.out\n2,2,0,0.a\n3,3,0,0.b\n"
+        );
+
+        compiler_input.input = "tests/circuits/integration/signedGates.circom".into();
+        assert!(compile_and_write(&compiler_input).is_err());
+    }
+
+    #[test]
+    fn test_json_format() {
+        let output_dir = std::env::temp_dir().join("circom_2_arithc_json");
+        let mut compiler_input = Args::new(
+            "tests/circuits/integration/sum.circom".into(),
+            output_dir.clone(),
+            ValueType::Sint,
+            None,
+        );
+        compiler_input.format = OutputFormat::Json;
+        let artifacts = compile_and_write(&compiler_input).unwrap();
+
+        assert_eq!(artifacts.circuit, output_dir.join("circuit.json"));
+        assert!(artifacts.sym.is_none());
+        let json = JsonCircuit::from_path(&artifacts.circuit).unwrap();
+        assert_eq!(json.gates.len(), 1);
+        assert_eq!(json.gates[0].op, "AAdd");
+        assert_eq!(
+            json.inputs
+                .iter()
+                .map(|signal| signal.name.as_str())
+                .collect::<Vec<_>>(),
+            ["0.a", "0.b"]
+        );
+        assert_eq!(json.outputs[0].name, "0.out");
+
+        // The loaded circuit evaluates the same as the compiled one
+        let circuit = Artifacts::load(&output_dir).unwrap().circuit.unwrap();
+        assert_eq!(circuit, json.into_circuit().unwrap());
+        let outputs = evaluate_circuit(
+            &circuit,
+            &HashMap::from([(String::from("0.a"), 2), (String::from("0.b"), 3)]),
+            ValueType::Sint,
+        )
+        .unwrap();
+        assert_eq!(outputs["0.out"], 5);
+    }
+
+    #[test]
+    fn test_package() {
+        let output_dir = std::env::temp_dir().join("circom_2_arithc_package");
+        let manifest =
+            PackageManifest::from_path("tests/circuits/integration/package.json".as_ref()).unwrap();
+        let compiler_input = Args::new(
+            "./input/circuit.circom".into(),
+            output_dir.clone(),
+            ValueType::Sint,
+            None,
+        );
+        let artifacts = compile_package(&compiler_input, &manifest).unwrap();
+
+        assert_eq!(artifacts.circuits.len(), 3);
+        for (name, circuit) in &artifacts.circuits {
+            assert_eq!(circuit.circuit, output_dir.join(name).join("circuit.txt"));
+            assert!(circuit.circuit.exists());
+        }
+        assert_eq!(artifacts.circuits["sum"].gate_count, 1);
+
+        assert_eq!(artifacts.constants, output_dir.join("constants.json"));
+        let pool: ConstantsPool =
+            serde_json::from_str(&std::fs::read_to_string(artifacts.constants).unwrap()).unwrap();
+        let values = pool.values.iter().collect::<std::collections::HashSet<_>>();
+        assert_eq!(values.len(), pool.values.len());
+        assert!(pool.circuits["sum"].is_empty());
+        for (name, circuit) in &artifacts.circuits {
+            let info: bristol_circuit::CircuitInfo =
+                serde_json::from_str(&std::fs::read_to_string(&circuit.circuit_info).unwrap())
+                    .unwrap();
+            for (constant, index) in &pool.circuits[name] {
+                assert_eq!(info.constants[constant].value, pool.values[*index]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_package_shared_includes() {
+        let input_dir = std::env::temp_dir().join("circom_2_arithc_package_shared");
+        let output_dir = std::env::temp_dir().join("circom_2_arithc_package_shared_output");
+        let _ = std::fs::remove_dir_all(&input_dir);
+        std::fs::create_dir_all(input_dir.join("lib")).unwrap();
+        std::fs::write(
+            input_dir.join("lib/double.circom"),
+            "pragma circom 2.1.0;\n\ntemplate Double() {\n    signal input in;\n    signal output out;\n    out <== in + in;\n}\n",
+        )
+        .unwrap();
+        for (name, template) in [("one", "One"), ("two", "Two")] {
+            std::fs::write(
+                input_dir.join(format!("{}.circom", name)),
+                format!(
+                    "pragma circom 2.1.0;\ninclude \"lib/double.circom\";\n\ntemplate {0}() {{\n    signal input a;\n    signal output out;\n    component double = Double();\n    double.in <== a;\n    out <== double.out;\n}}\n\n/* component main = Old(); */\ncomponent main {{public [a]}} = {0}();\n",
+                    template
+                ),
+            )
+            .unwrap();
+        }
+
+        let manifest = PackageManifest::from_dir(&input_dir).unwrap();
+        let compiler_input = Args::new(
+            "./input/circuit.circom".into(),
+            output_dir.clone(),
+            ValueType::Sint,
+            None,
+        );
+        let artifacts = compile_package(&compiler_input, &manifest).unwrap();
+
+        for (name, circuit) in &artifacts.circuits {
+            let loaded = Artifacts::load(output_dir.join(name)).unwrap();
+            let outputs = evaluate_circuit(
+                &loaded.circuit.unwrap(),
+                &HashMap::from([(String::from("0.a"), 3)]),
+                ValueType::Sint,
+            )
+            .unwrap();
+            assert_eq!(outputs["0.out"], 6);
+
+            // Each circuit only depends on its own entry file and the library
+            let deps: DependencyGraph =
+                serde_json::from_str(&std::fs::read_to_string(&circuit.deps).unwrap()).unwrap();
+            let mut files = deps
+                .files
+                .iter()
+                .map(|file| {
+                    let path = Path::new(&file.path);
+                    path.file_name().unwrap().to_string_lossy().to_string()
+                })
+                .collect::<Vec<_>>();
+            files.sort();
+            assert_eq!(
+                files,
+                [String::from("double.circom"), format!("{}.circom", name)]
+            );
+        }
+    }
+
+    #[test]
+    fn test_input_dir() {
+        let input_dir = std::env::temp_dir().join("circom_2_arithc_input_dir");
+        let output_dir = std::env::temp_dir().join("circom_2_arithc_input_dir_output");
+        let _ = std::fs::remove_dir_all(&input_dir);
+        std::fs::create_dir_all(&input_dir).unwrap();
+        for name in ["sum", "addZero"] {
+            std::fs::copy(
+                format!("tests/circuits/integration/{}.circom", name),
+                input_dir.join(format!("{}.circom", name)),
+            )
+            .unwrap();
+        }
+        std::fs::write(
+            input_dir.join("lib.circom"),
+            "pragma circom 2.1.0;\n\ntemplate Id() {\n    signal input in;\n    signal output out;\n    out <== in;\n}\n",
+        )
+        .unwrap();
+
+        let manifest = PackageManifest::from_dir(&input_dir).unwrap();
+        assert_eq!(
+            manifest.circuits.keys().collect::<Vec<_>>(),
+            ["addZero", "sum"]
+        );
+
+        let mut compiler_input = Args::new(
+            "./input/circuit.circom".into(),
+            output_dir.clone(),
+            ValueType::Sint,
+            None,
+        );
+        compiler_input.jobs = Some(2);
+        let artifacts = compile_package(&compiler_input, &manifest).unwrap();
+        assert_eq!(artifacts.circuits.len(), 2);
+        for (name, circuit) in &artifacts.circuits {
+            assert_eq!(circuit.circuit, output_dir.join(name).join("circuit.txt"));
+            assert!(circuit.circuit.exists());
+        }
+        assert_eq!(artifacts.circuits["sum"].gate_count, 1);
+    }
+
+    #[test]
+    fn test_prebuild() {
+        let output_dir = std::env::temp_dir().join("circom_2_arithc_prebuild");
+        let _ = std::fs::remove_dir_all(&output_dir);
+        let manifest =
+            PrebuildManifest::from_path("tests/circuits/integration/prebuild.json".as_ref())
+                .unwrap();
+        let mut compiler_input = Args::new(
+            "./input/circuit.circom".into(),
+            output_dir.clone(),
+            ValueType::Sint,
+            None,
+        );
+
+        let archives = prebuild(&compiler_input, &manifest).unwrap();
+        assert_eq!(archives.len(), 2);
+        assert_eq!(archives["mul"].path, output_dir.join("mul.ir.json"));
+        assert_eq!(archives["elem_mul"].gate_count, 4);
+        assert!(archives.values().all(|archive| !archive.reused));
+
+        let archive = IrArchive::from_path(&archives["mul"].path).unwrap();
+        assert_eq!(archive.template, "matMul");
+        assert!(archive.stale_sources().is_empty());
+        assert!(archive
+            .sources
+            .iter()
+            .any(|file| file.path.ends_with("matMul.circom")));
+        let outputs = archive
+            .compiler
+            .evaluate_outputs(&elements(&[
+                ("0.a[0][0]", 2),
+                ("0.a[0][1]", 3),
+                ("0.b[0][0]", 4),
+                ("0.b[1][0]", 5),
+            ]))
+            .unwrap();
+        assert_eq!(outputs["0.out[0][0]"], FieldElement::from(23));
+
+        // Up to date archives are kept, and compiled again for another value type
+        let archives = prebuild(&compiler_input, &manifest).unwrap();
+        assert!(archives.values().all(|archive| archive.reused));
+        compiler_input.value_type = ValueType::Uint;
+        let archives = prebuild(&compiler_input, &manifest).unwrap();
+        assert!(archives.values().all(|archive| !archive.reused));
+
+        // Other compile options and unreadable archives are compiled again too
+        compiler_input.fold_constants = true;
+        let archives = prebuild(&compiler_input, &manifest).unwrap();
+        assert!(archives.values().all(|archive| !archive.reused));
+        std::fs::write(&archives["mul"].path, "{").unwrap();
+        let archives = prebuild(&compiler_input, &manifest).unwrap();
+        assert!(!archives["mul"].reused);
+        assert!(archives["elem_mul"].reused);
+
+        // Only the archives compiled with the same options are linked
+        let prebuilt = PrebuiltInstances::load(&output_dir, &compiler_input).unwrap();
+        assert_eq!(prebuilt.len(), 2);
+        let other_target = Args {
+            target: Target::Mpz,
+            ..compiler_input.clone()
+        };
+        assert!(PrebuiltInstances::load(&output_dir, &other_target)
+            .unwrap()
+            .is_empty());
+
+        // A linked instance computes the same as the processed one
+        let compile_offset_mul = |prebuilt: Option<&Path>| {
+            compile(&Args {
+                input: "tests/circuits/integration/prebuiltMatMul.circom".into(),
+                prebuilt: prebuilt.map(Path::to_path_buf),
+                ..compiler_input.clone()
+            })
+            .unwrap()
+        };
+        let inputs = elements(&[
+            ("0.a[0][0]", 2),
+            ("0.a[0][1]", 3),
+            ("0.b[0][0]", 4),
+            ("0.b[1][0]", 5),
+        ]);
+        let linked = compile_offset_mul(Some(&output_dir))
+            .evaluate_outputs(&inputs)
+            .unwrap();
+        assert_eq!(linked["0.out"], FieldElement::from(24));
+        assert_eq!(
+            linked,
+            compile_offset_mul(None).evaluate_outputs(&inputs).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_shared_outputs() {
+        let compiler_input = Args::new(
+            "tests/circuits/integration/sharedOutputs.circom".into(),
+            "./".into(),
+            ValueType::Sint,
+            None,
+        );
+        let circuit = compile(&compiler_input).unwrap().build_circuit().unwrap();
+
+        let outputs = &circuit.info.output_name_to_wire_index;
+        assert_eq!(outputs["0.product"], outputs["0.productCopy"]);
+        assert_eq!(outputs["0.sum[0]"], outputs["0.sum[1]"]);
+
+        simulation_test(
+            "tests/circuits/integration/sharedOutputs.circom",
+            &[("0.a", 3), ("0.b", 4)],
+            &[
+                ("0.product", 12),
+                ("0.productCopy", 12),
+                ("0.sum[0]", 7),
+                ("0.sum[1]", 7),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_unused_inputs() {
+        let mut compiler_input = Args::new(
+            "tests/circuits/integration/unusedInput.circom".into(),
+            "./".into(),
+            ValueType::Sint,
+            None,
+        );
+        let circuit = compile(&compiler_input).unwrap().build_circuit().unwrap();
+
+        let inputs = &circuit.info.input_name_to_wire_index;
+        assert!(inputs.contains_key("0.a"));
+        assert!(!inputs.contains_key("0.b"));
+
+        compiler_input.strict = true;
+        let result = compile(&compiler_input);
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Inputs not read by any gate: 0.b"
+        );
+    }
+
+    #[test]
+    fn test_weights() {
+        let weights_path = std::env::temp_dir().join("circom_2_arithc_weights.json");
+        std::fs::write(
+            &weights_path,
+            r#"{"0.w": [[1, 2], [3, 4]], "0.bias": [5, 6]}"#,
+        )
+        .unwrap();
+
+        let mut compiler_input = Args::new(
+            "tests/circuits/integration/weights.circom".into(),
+            "./".into(),
+            ValueType::Sint,
+            None,
+        );
+        compiler_input.weights = Some(weights_path);
+        let circuit = compile(&compiler_input).unwrap().build_circuit().unwrap();
+
+        let mut inputs = circuit
+            .info
+            .input_name_to_wire_index
+            .keys()
+            .cloned()
+            .collect::<Vec<String>>();
+        inputs.sort();
+        assert_eq!(inputs, vec!["0.x[0]", "0.x[1]"]);
+
+        let arithmetic_circuit = ArithmeticCircuit::new_from_bristol(circuit).unwrap();
+        let outputs = arithmetic_circuit
+            .run(HashMap::from([
+                (String::from("0.x[0]"), 10),
+                (String::from("0.x[1]"), 100),
+            ]))
+            .unwrap();
+        assert_eq!(outputs["0.out[0]"], 215);
+        assert_eq!(outputs["0.out[1]"], 436);
+    }
+
+    #[test]
+    fn test_stages() {
+        let stages_path = std::env::temp_dir().join("circom_2_arithc_stages.json");
+        std::fs::write(
+            &stages_path,
+            r#"{
+                "pre": [{ "signal": "0.a", "adapter": "scale", "factor": 100 }],
+                "post": [
+                    { "signal": "0.out", "adapter": "offset", "offset": 1 },
+                    { "signal": "0.out", "adapter": "divide", "divisor": 10 }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let mut compiler_input = Args::new(
+            "tests/circuits/integration/sum.circom".into(),
+            "./".into(),
+            ValueType::Sint,
+            None,
+        );
+        compiler_input.stages = Some(stages_path);
+        let circuit = compile(&compiler_input).unwrap().build_circuit().unwrap();
+        let arithmetic_circuit = ArithmeticCircuit::new_from_bristol(circuit).unwrap();
+
+        // (3 * 100 + 8 + 1) / 10
+        let outputs = arithmetic_circuit
+            .run(HashMap::from([
+                (String::from("0.a"), 3),
+                (String::from("0.b"), 8),
+            ]))
+            .unwrap();
+        assert_eq!(outputs["0.out"], 30);
+    }
+
+    #[test]
+    fn test_noise() {
+        let noise_path = std::env::temp_dir().join("circom_2_arithc_noise.json");
+        std::fs::write(
+            &noise_path,
+            r#"{ "outputs": [{ "signal": "0.out", "shares": 2 }] }"#,
+        )
+        .unwrap();
+
+        let mut compiler_input = Args::new(
+            "tests/circuits/integration/sum.circom".into(),
+            "./".into(),
+            ValueType::Sint,
+            None,
+        );
+        compiler_input.noise = Some(noise_path);
+        let circuit = compile(&compiler_input).unwrap().build_circuit().unwrap();
+
+        // The noise shares are supplied after the inputs of the circuit
+        let inputs = &circuit.info.input_name_to_wire_index;
+        assert_eq!(inputs.len(), 4);
+        assert!(inputs["noise0.0.out"] >= 2 && inputs["noise1.0.out"] >= 2);
+
+        let arithmetic_circuit = ArithmeticCircuit::new_from_bristol(circuit).unwrap();
+        let outputs = arithmetic_circuit
+            .run(HashMap::from([
+                (String::from("0.a"), 3),
+                (String::from("0.b"), 8),
+                (String::from("noise0.0.out"), 5),
+                (String::from("noise1.0.out"), 7),
+            ]))
+            .unwrap();
+        assert_eq!(outputs["0.out"], 3 + 8 + 5 + 7);
+    }
+
+    #[test]
+    fn test_fuse_constant_mul() {
+        let mut compiler_input = Args::new(
+            "tests/circuits/integration/scaledSum.circom".into(),
+            "./".into(),
+            ValueType::Sint,
+            None,
+        );
+        let inputs = HashMap::from([(String::from("0.a"), 2), (String::from("0.b"), 7)]);
+
+        let unfused = compile(&compiler_input).unwrap().build_circuit().unwrap();
+        compiler_input.fuse_constant_mul = true;
+        let fused = compile(&compiler_input).unwrap().build_circuit().unwrap();
+
+        let ops = fused
+            .gates
+            .iter()
+            .map(|gate| gate.op.as_str())
+            .collect::<Vec<_>>();
+        assert!(ops.contains(&"ACMul:3") && ops.contains(&"ACMul:5"));
+        assert_eq!(ops.iter().filter(|&&op| op == "AMul").count(), 1);
+        assert_eq!(fused.gates.len(), unfused.gates.len());
+        assert_eq!(fused.wire_count, unfused.wire_count - 2);
+        assert_eq!(
+            evaluate_circuit(&fused, &inputs, ValueType::Sint).unwrap()["0.out"],
+            3 * 2 + 7 * 5 + 2 * 7
+        );
+
+        // Targets without ACMul get the multiplications by a constant wire back
+        compiler_input.target = Target::Mpz;
+        let lowered = compile(&compiler_input).unwrap().build_circuit().unwrap();
+        assert!(lowered
+            .gates
+            .iter()
+            .all(|gate| !gate.op.starts_with("ACMul")));
+        assert_eq!(lowered.wire_count, unfused.wire_count);
+    }
+
+    #[test]
+    fn test_prune_dead_gates() {
+        let mut compiler_input = Args::new(
+            "tests/circuits/integration/deadGates.circom".into(),
+            "./".into(),
+            ValueType::Sint,
+            None,
+        );
+        let inputs = HashMap::from([(String::from("0.a"), 3), (String::from("0.b"), 7)]);
+
+        let pruned = compile(&compiler_input).unwrap().build_circuit().unwrap();
+        assert_eq!(pruned.gates.len(), 1);
+        assert_eq!(pruned.wire_count, 3);
+        assert_eq!(
+            evaluate_circuit(&pruned, &inputs, ValueType::Sint).unwrap()["0.out"],
+            21
+        );
+
+        compiler_input.no_prune = true;
+        let unpruned = compile(&compiler_input).unwrap().build_circuit().unwrap();
+        assert_eq!(unpruned.gates.len(), 3);
+        assert_eq!(unpruned.wire_count, 5);
+    }
+
+    #[test]
+    fn test_fold_constants() {
+        let mut compiler_input = Args::new(
+            "tests/circuits/integration/foldConstants.circom".into(),
+            "./".into(),
+            ValueType::Sint,
+            None,
+        );
+        let inputs = HashMap::from([(String::from("0.in[0]"), 2), (String::from("0.in[1]"), 3)]);
+
+        let unfolded = compile(&compiler_input).unwrap().build_circuit().unwrap();
+        compiler_input.fold_constants = true;
+        let folded = compile(&compiler_input).unwrap().build_circuit().unwrap();
+
+        // The square of the scale factor is a constant
+        assert_eq!(folded.gates.len(), unfolded.gates.len() - 1);
+        assert!(folded
+            .info
+            .constants
+            .values()
+            .any(|constant| constant.value == "100"));
+        let outputs = evaluate_circuit(&folded, &inputs, ValueType::Sint).unwrap();
+        assert_eq!(outputs["0.out[0]"], 200);
+        assert_eq!(outputs["0.out[1]"], 300);
+        assert_eq!(
+            evaluate_circuit(&unfolded, &inputs, ValueType::Sint).unwrap(),
+            outputs
+        );
+    }
+
+    #[test]
+    fn test_reduce_divisions() {
+        let mut compiler_input = Args::new(
+            "tests/circuits/integration/divisions.circom".into(),
+            "./".into(),
+            ValueType::Sint,
+            None,
+        );
+        let inputs = HashMap::from([
+            (String::from("0.in[0]"), -9i32 as u32),
+            (String::from("0.in[1]"), 2),
+        ]);
+        let divisions = |circuit: &BristolCircuit| {
+            circuit
+                .gates
+                .iter()
+                .filter(|gate| gate.op == "ADiv" || gate.op == "AIntDiv")
+                .count()
+        };
+
+        compiler_input.reduce_divisions = Some(DivisionReduction::Integer);
+        let integer = compile(&compiler_input).unwrap().build_circuit().unwrap();
+        assert_eq!(divisions(&integer), 1);
+        let outputs = evaluate_circuit(&integer, &inputs, ValueType::Sint).unwrap();
+        assert_eq!(outputs["0.mean"] as i32, -3);
+        assert_eq!(outputs["0.third"] as i32, -3);
+
+        // The input isn't provably a multiple of 3, so its division by 3 is kept for words
+        compiler_input.reduce_divisions = Some(DivisionReduction::Field);
+        let field = compile(&compiler_input).unwrap().build_circuit().unwrap();
+        assert_eq!(divisions(&field), 1);
+        assert_eq!(
+            evaluate_circuit(&field, &inputs, ValueType::Sint).unwrap(),
+            outputs
+        );
+    }
+
+    #[test]
+    fn test_diff() {
+        use circom_2_arithc::diff::{diff_cones, Change};
+
+        let mut compiler_input = Args::new(
+            "tests/circuits/integration/foldConstants.circom".into(),
+            "./".into(),
+            ValueType::Sint,
+            None,
+        );
+        let unfolded = compile(&compiler_input).unwrap().build_circuit().unwrap();
+        compiler_input.fold_constants = true;
+        let folded = compile(&compiler_input).unwrap().build_circuit().unwrap();
+
+        assert!(diff_cones(&unfolded, &unfolded, "0.out[0]")
+            .unwrap()
+            .is_empty());
+
+        // The square of the scale factor is gone, and the product reads the folded constant
+        let diff = diff_cones(&unfolded, &folded, "0.out[0]").unwrap();
+        assert!(!diff.is_empty());
+        assert_eq!(diff.counts().get(&Change::Removed), Some(&1));
+        assert!(diff
+            .nodes
+            .iter()
+            .any(|node| !node.is_gate && node.label == "100" && node.change == Change::Added));
+    }
+
+    #[test]
+    fn test_estimate() {
+        let output_dir = std::env::temp_dir().join("circom_2_arithc_estimate");
+        let mut compiler_input = Args::new(
+            "tests/circuits/integration/scaledSum.circom".into(),
+            output_dir.clone(),
+            ValueType::Sint,
+            None,
+        );
+        compiler_input.fuse_constant_mul = true;
+        let file_size = |path: &std::path::Path| std::fs::metadata(path).unwrap().len();
+
+        let predicted = estimate(&compiler_input).unwrap();
+        let artifacts = compile_and_write(&compiler_input).unwrap();
+        assert_eq!(predicted.format, OutputFormat::Bristol);
+        assert_eq!(predicted.gate_count, artifacts.gate_count);
+        assert_eq!(predicted.wire_count, artifacts.wire_count);
+        assert_eq!((predicted.input_count, predicted.output_count), (2, 1));
+        let written = file_size(&artifacts.circuit);
+        assert!(predicted.files["circuit.txt"].abs_diff(written) * 10 <= written);
+
+        // The R1CS layout is fixed, only the symbol names vary
+        compiler_input.format = OutputFormat::R1cs;
+        let predicted = estimate(&compiler_input).unwrap();
+        let artifacts = compile_and_write(&compiler_input).unwrap();
+        assert_eq!(
+            predicted.files["circuit.r1cs"],
+            file_size(&artifacts.circuit)
+        );
+        assert_eq!(
+            predicted.files["circuit.sym"],
+            file_size(artifacts.sym.as_ref().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_constraint_equality() {
+        let mut compiler_input = Args::new(
+            "tests/circuits/integration/constraintEquality.circom".into(),
+            "./".into(),
+            ValueType::Sint,
+            None,
+        );
+        let inputs = |a, b| HashMap::from([(String::from("0.a"), a), (String::from("0.b"), b)]);
+
+        // Ignored by default
+        let circuit = compile(&compiler_input).unwrap().build_circuit().unwrap();
+        assert_eq!(circuit.info.output_name_to_wire_index.len(), 1);
+
+        compiler_input.constraint_equality = ConstraintEquality::Output;
+        let circuit = compile(&compiler_input).unwrap().build_circuit().unwrap();
+        let outputs = evaluate_circuit(&circuit, &inputs(2, 3), ValueType::Sint).unwrap();
+        assert_eq!(outputs["0.out"], 6);
+        assert_eq!(outputs["0.constraints[0]"], 1);
+        assert_eq!(outputs["0.constraints[1]"], 0);
+
+        compiler_input.constraint_equality = ConstraintEquality::Assert;
+        let circuit = compile(&compiler_input).unwrap().build_circuit().unwrap();
+        let outputs = evaluate_circuit(&circuit, &inputs(3, 3), ValueType::Sint).unwrap();
+        assert_eq!(outputs["0.constraints_ok"], 1);
+        let outputs = evaluate_circuit(&circuit, &inputs(2, 3), ValueType::Sint).unwrap();
+        assert_eq!(outputs["0.constraints_ok"], 0);
+
+        // Sides known at compile time are checked when compiling
+        compiler_input.input = "tests/circuits/integration/constraintEqualityFailure.circom".into();
+        let error = compile(&compiler_input).unwrap_err();
+        assert!(matches!(
+            error.root(),
+            ProgramError::RuntimeError(RuntimeError::ConstraintNotSatisfied)
+        ));
+        assert_eq!(error.code(), "E0430");
+        compiler_input.constraint_equality = ConstraintEquality::Ignore;
+        assert!(compile(&compiler_input).is_ok());
+    }
+
+    #[test]
+    fn test_balance_additions() {
+        let mut compiler_input = Args::new(
+            "tests/circuits/integration/additionChain.circom".into(),
+            "./".into(),
+            ValueType::Sint,
+            None,
+        );
+        let depth = |circuit: &BristolCircuit| {
+            let mut depths = HashMap::<usize, usize>::new();
+            for gate in &circuit.gates {
+                let depth = gate
+                    .inputs
+                    .iter()
+                    .map(|wire| depths.get(wire).copied().unwrap_or(0))
+                    .max()
+                    .unwrap_or(0)
+                    + 1;
+                depths.insert(gate.outputs[0], depth);
+            }
+            depths.values().copied().max().unwrap_or(0)
+        };
+        let inputs = (0..16)
+            .map(|i| (format!("0.in[{}]", i), i + 1))
+            .collect::<HashMap<String, u32>>();
+
+        let chain = compile(&compiler_input).unwrap().build_circuit().unwrap();
+        assert_eq!(depth(&chain), 16);
+
+        compiler_input.balance_additions = true;
+        let tree = compile(&compiler_input).unwrap().build_circuit().unwrap();
+        // 17 operands, counting the initial 0
+        assert_eq!(depth(&tree), 5);
+        assert_eq!(tree.gates.len(), chain.gates.len());
+
+        let outputs = ArithmeticCircuit::new_from_bristol(tree)
+            .unwrap()
+            .run(inputs)
+            .unwrap();
+        assert_eq!(outputs["0.out"], 136);
+    }
+
+    #[test]
+    fn test_target() {
+        let output_dir = std::env::temp_dir().join("circom_2_arithc_target");
+        let mut compiler_input = Args::new(
+            "tests/circuits/integration/additionChain.circom".into(),
+            output_dir.clone(),
+            ValueType::Sint,
+            None,
+        );
+        compiler_input.target = Target::Mpz;
+
+        // The preset rebalances additions, like --balance-additions
+        let mut balanced_input = compiler_input.clone();
+        balanced_input.target = Target::Generic;
+        balanced_input.balance_additions = true;
+        let balanced = compile(&balanced_input).unwrap();
+        let compiler = compile(&compiler_input).unwrap();
+        assert_eq!(compiler.gates(), balanced.gates());
+
+        let artifacts = compile_and_write(&compiler_input).unwrap();
+        let circuit_info: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(artifacts.circuit_info).unwrap())
+                .unwrap();
+        assert_eq!(circuit_info["target"], "mpz");
+        let report = CircuitReport::from_path(&artifacts.report).unwrap();
+        assert_eq!(report.target(), Target::Mpz);
+
+        // Powers and shifts by a constant are lowered to multiplications for the engine
+        compiler_input.input = "tests/circuits/integration/constantPowers.circom".into();
+        let circuit = compile(&compiler_input).unwrap().build_circuit().unwrap();
+        assert!(circuit
+            .gates
+            .iter()
+            .all(|gate| ["AAdd", "ASub", "AMul"].contains(&gate.op.as_str())));
+        let inputs = HashMap::from([(String::from("0.a"), 5)]);
+        let outputs = evaluate_circuit(&circuit, &inputs, ValueType::Sint).unwrap();
+        assert_eq!((outputs["0.cube"], outputs["0.shifted"]), (125, 20));
+
+        // Comparisons can't be evaluated by the engine
+        compiler_input.input = "tests/circuits/integration/infixOps.circom".into();
+        assert!(matches!(
+            compile(&compiler_input),
+            Err(ProgramError::UnsupportedByTarget {
+                target: Target::Mpz,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_init_project() {
+        let dir = std::env::temp_dir().join("circom_2_arithc_init_project");
+        let _ = std::fs::remove_dir_all(&dir);
+        init_project(&dir).unwrap();
+
+        let compiler_input = Args::new(
+            dir.join("circuits/main.circom"),
+            dir.join("output"),
+            ValueType::Sint,
+            None,
+        );
+        let compiler = compile(&compiler_input).unwrap();
+
+        let mut inputs: HashMap<String, u32> = serde_json::from_str(
+            &std::fs::read_to_string(dir.join("inputs.template.json")).unwrap(),
+        )
+        .unwrap();
+        for (value, input) in inputs.values_mut().enumerate() {
+            *input = value as u32;
+        }
+        let expected = (0..3)
+            .map(|i| inputs[&format!("0.a[{}]", i)] * inputs[&format!("0.b[{}]", i)])
+            .sum::<u32>();
+        let inputs = inputs
+            .into_iter()
+            .map(|(name, value)| (name, FieldElement::from(value)))
+            .collect();
+        let outputs = compiler.evaluate_outputs(&inputs).unwrap();
+        assert_eq!(outputs["0.out"], FieldElement::from(expected));
+    }
+
+    #[test]
+    fn test_evaluate_files() {
+        let output_dir = std::env::temp_dir().join("circom_2_arithc_evaluate_files");
+        let compiler_input = Args::new(
+            "tests/circuits/integration/matElemMul.circom".into(),
+            output_dir.clone(),
+            ValueType::Sint,
+            None,
+        );
+        let artifacts = compile_and_write(&compiler_input).unwrap();
+
+        let inputs_path = output_dir.join("inputs.json");
+        std::fs::write(
+            &inputs_path,
+            r#"{"0.a": [[1, 2], [3, 4]], "0.b": [[5, 6], [7, -8]]}"#,
+        )
+        .unwrap();
+        let outputs = evaluate_files(
+            &artifacts.circuit,
+            &artifacts.circuit_info,
+            &inputs_path,
+            ValueType::Sint,
+            &[],
+        )
+        .unwrap();
+        assert_eq!(
+            outputs.into_iter().collect::<Vec<_>>(),
+            vec![
+                (String::from("0.out[0][0]"), 5),
+                (String::from("0.out[0][1]"), 12),
+                (String::from("0.out[1][0]"), 21),
+                (String::from("0.out[1][1]"), -32i32 as u32),
+            ]
+        );
+
+        // Inputs must match the circuit
+        std::fs::write(&inputs_path, r#"{"0.a": [[1, 2], [3, 4]]}"#).unwrap();
+        let error = evaluate_files(
+            &artifacts.circuit,
+            &artifacts.circuit_info,
+            &inputs_path,
+            ValueType::Sint,
+            &[],
+        )
+        .unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Missing value for input 0.b["));
+    }
+
+    #[test]
+    fn test_check_files() {
+        let output_dir = std::env::temp_dir().join("circom_2_arithc_check_files");
+        let compiler_input = Args::new(
+            "tests/circuits/integration/scaledSum.circom".into(),
+            output_dir.clone(),
+            ValueType::Sint,
+            None,
+        );
+        let artifacts = compile_and_write(&compiler_input).unwrap();
+
+        let check = check_files(&artifacts.circuit, &artifacts.circuit_info).unwrap();
+        assert!(check.is_valid(), "{:?}", check.problems);
+        assert_eq!((check.input_count, check.output_count), (2, 1));
+        assert!(check.gate_count > 1);
+        assert_eq!(check.gates.values().sum::<usize>(), check.gate_count);
+
+        // Gates written in reverse read wires before they are computed
+        let bristol = std::fs::read_to_string(&artifacts.circuit).unwrap();
+        let (header, gates) = bristol.split_once("\n\n").unwrap();
+        let reversed = format!(
+            "{}\n\n{}\n",
+            header,
+            gates.lines().rev().collect::<Vec<_>>().join("\n")
+        );
+        let reversed_path = output_dir.join("reversed.txt");
+        std::fs::write(&reversed_path, reversed).unwrap();
+        let check = check_files(&reversed_path, &artifacts.circuit_info).unwrap();
+        assert!(!check.is_valid());
+        assert!(matches!(
+            check.problems[0],
+            CheckProblem::UnorderedGate { gate: 0, .. }
+        ));
+    }
+
+    #[test]
+    fn test_watch_files() {
+        let output_dir = std::env::temp_dir().join("circom_2_arithc_watch_files");
+        let mut compiler_input = Args::new(
+            "tests/circuits/integration/neurons.circom".into(),
+            output_dir.clone(),
+            ValueType::Sint,
+            None,
+        );
+        compiler_input.signal_map = true;
+        let artifacts = compile_and_write(&compiler_input).unwrap();
+        let signal_map = artifacts.signal_map.unwrap();
+
+        // out[j] = x[0] + x[1] + x[2] + b[j] = 6 + j
+        let inputs_path = output_dir.join("inputs.json");
+        std::fs::write(
+            &inputs_path,
+            r#"{"0.x": [1, 2, 3], "0.w": [[1, 1, 1], [1, 1, 1], [1, 1, 1], [1, 1, 1]], "0.b": [0, 1, 2, 3]}"#,
+        )
+        .unwrap();
+        let circuit_info = Artifacts::load(&output_dir).unwrap().circuit_info.unwrap();
+        let out_wire = circuit_info.output_name_to_wire_index["0.out[1]"].to_string();
+        let sym = std::fs::read_to_string(&signal_map).unwrap();
+        let neuron_out = sym
+            .lines()
+            .map(|line| line.split(',').collect::<Vec<&str>>())
+            .find(|line| line[0] == out_wire && line[2] == "Dense/Neuron")
+            .map(|line| line[1].to_string())
+            .unwrap();
+
+        // The neuron output is an intermediate signal, resolved through the signal map
+        let watchpoints = [
+            "0.out[0]".parse::<Watchpoint>().unwrap(),
+            format!("{}>=7", neuron_out).parse().unwrap(),
+        ];
+        let (outputs, watches) = watch_files(
+            &artifacts.circuit,
+            &artifacts.circuit_info,
+            &inputs_path,
+            ValueType::Sint,
+            &[],
+            &watchpoints,
+            Some(&signal_map),
+        )
+        .unwrap();
+        assert!(outputs.is_none());
+        let values = watches
+            .hits()
+            .iter()
+            .map(|hit| (hit.name.as_str(), hit.value))
+            .collect::<Vec<_>>();
+        assert_eq!(values, [("0.out[0]", 6), (neuron_out.as_str(), 7)]);
+        assert_eq!(watches.break_hit().unwrap().name, neuron_out);
+
+        let error = watch_files(
+            &artifacts.circuit,
+            &artifacts.circuit_info,
+            &inputs_path,
+            ValueType::Sint,
+            &[],
+            &watchpoints,
+            None,
+        )
+        .unwrap_err();
+        assert_eq!(error.code(), "E0210");
+    }
+
+    #[test]
+    fn test_normalize_names() {
+        let output_dir = std::env::temp_dir().join("circom_2_arithc_normalize_names");
+        std::fs::create_dir_all(&output_dir).unwrap();
+        let weights_path = output_dir.join("weights.json");
+        std::fs::write(&weights_path, r#"{"B": [[5, 6], [7, 8]]}"#).unwrap();
+        let mut compiler_input = Args::new(
+            "tests/circuits/integration/matElemMul.circom".into(),
+            output_dir.clone(),
+            ValueType::Sint,
+            None,
+        );
+        compiler_input.weights = Some(weights_path);
+
+        // Weights must match input names exactly by default
+        assert!(matches!(
+            compile(&compiler_input),
+            Err(ProgramError::CircuitError(_))
+        ));
+
+        compiler_input.normalize_names = vec![NameNormalization::Prefix, NameNormalization::Case];
+        let artifacts = compile_and_write(&compiler_input).unwrap();
+
+        let inputs_path = output_dir.join("inputs.json");
+        std::fs::write(
+            &inputs_path,
+            r#"{"a_0_0": 1, "a_0_1": 2, "0.a[1][0]": 3, "A_1_1": 4}"#,
+        )
+        .unwrap();
+        let outputs = evaluate_files(
+            &artifacts.circuit,
+            &artifacts.circuit_info,
+            &inputs_path,
+            ValueType::Sint,
+            &[
+                NameNormalization::Prefix,
+                NameNormalization::Case,
+                NameNormalization::Brackets,
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            outputs.into_values().collect::<Vec<_>>(),
+            vec![5, 12, 21, 32]
+        );
+    }
+
+    #[test]
+    fn test_constant_range() {
+        let output_dir = std::env::temp_dir().join("circom_2_arithc_constant_range");
+        let events_path = std::env::temp_dir().join("circom_2_arithc_constant_range.jsonl");
+        let mut compiler_input = Args::new(
+            "tests/circuits/integration/bigConstants.circom".into(),
+            output_dir,
+            ValueType::Sint,
+            Some(3),
+        );
+        compiler_input.events_file = Some(events_path.clone());
+        let artifacts = compile_and_write(&compiler_input).unwrap();
+
+        // Field values are reduced to 3 and 5 at compile time, 5 needs a sign bit
+        let report = CircuitReport::from_path(&artifacts.report).unwrap();
+        let range = report.constant_range().unwrap();
+        assert_eq!(range.max_magnitude(), &FieldElement::from(5));
+        assert_eq!(range.width(), 4);
+
+        let card = std::fs::read_to_string(&artifacts.card).unwrap();
+        assert!(card.contains("| Max constant | 5 (4 bits) |"));
+
+        let events = std::fs::read_to_string(events_path).unwrap();
+        assert!(events.contains("Boolify width 3 truncates constants"));
+    }
+
+    #[test]
+    fn test_template_instances() {
+        let compiler_input = Args::new(
+            "tests/circuits/integration/componentArray.circom".into(),
+            std::env::temp_dir().join("circom_2_arithc_template_instances"),
+            ValueType::Sint,
+            None,
+        );
+        let artifacts = compile_and_write(&compiler_input).unwrap();
+
+        // The main component comes first, followed by the instances it creates
+        let report = CircuitReport::from_path(&artifacts.report).unwrap();
+        let instances = report
+            .instances()
+            .iter()
+            .map(|instance| instance.to_string())
+            .collect::<Vec<String>>();
+        assert_eq!(
+            instances,
+            [
+                "componentArray(N=4)",
+                "Scale(n=0)",
+                "Scale(n=1)",
+                "Scale(n=2)",
+                "Scale(n=3)"
+            ]
+        );
+        assert_eq!(report.instances()[0].component, None);
+        assert_eq!(report.instances()[2].component.as_deref(), Some("c[1]"));
+
+        let card = std::fs::read_to_string(&artifacts.card).unwrap();
+        assert!(card.contains("| `Scale(n=2)` | 1 |"));
+    }
+
+    #[test]
+    fn test_parallel() {
+        simulation_test(
+            "tests/circuits/integration/parallel.circom",
+            &[("0.a", 3)],
+            &[("0.out[0]", 9), ("0.out[1]", 6), ("0.out[2]", 6)],
+        );
+
+        // The annotation is recorded on the instances, the main component has none
+        let compiler_input = Args::new(
+            "tests/circuits/integration/parallel.circom".into(),
+            "./".into(),
+            ValueType::Sint,
+            None,
+        );
+        let compiler = compile(&compiler_input).unwrap();
+        let parallel = compiler
+            .instances()
+            .iter()
+            .map(|instance| (instance.template.as_str(), instance.parallel))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            parallel,
+            [
+                ("parallelOps", false),
+                ("Square", true),
+                ("Double", true),
+                ("Double", true)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compiler_builder() {
+        // Includes of in-memory sources are resolved in the link libraries
+        let source = r#"
+            pragma circom 2.1.0;
+            include "matMul.circom";
+            component main = matMul(1, 2, 1);
+        "#;
+        let compilation = CompilerBuilder::from_source(source)
+            .link_library("tests/circuits/machine-learning/circomlib-matrix")
+            .compile()
+            .unwrap();
+        let outputs = compilation
+            .compiler
+            .evaluate_outputs(&elements(&[
+                ("0.a[0][0]", 2),
+                ("0.a[0][1]", 3),
+                ("0.b[0][0]", 4),
+                ("0.b[1][0]", 5),
+            ]))
+            .unwrap();
+        assert_eq!(outputs["0.out[0][0]"], FieldElement::from(23));
+        assert_eq!(compilation.report.outputs().len(), 1);
+
+        let compilation = CompilerBuilder::from_path("tests/circuits/integration/sum.circom")
+            .value_type(ValueType::Uint)
+            .prime(Prime::Goldilocks)
+            .compile()
+            .unwrap();
+        let args = Args::new(
+            "tests/circuits/integration/sum.circom".into(),
+            "./".into(),
+            ValueType::Uint,
+            None,
+        );
+        assert_eq!(
+            compilation.circuit.gates.len(),
+            compile(&args).unwrap().build_circuit().unwrap().gates.len()
+        );
+        assert_eq!(compilation.report.value_type(), ValueType::Uint);
+        assert_eq!(compilation.report.prime(), Prime::Goldilocks);
+
+        let boolean = CompilerBuilder::from_path("tests/circuits/integration/sum.circom")
+            .boolify_width(8)
+            .compile()
+            .unwrap();
+        assert!(boolean.circuit.gates.iter().all(|gate| gate.op != "AAdd"));
+    }
+
+    #[test]
+    fn test_include_resolver() {
+        let sources = HashMap::from([
+            (
+                "gates/double.circom",
+                r#"
+                    pragma circom 2.1.0;
+                    include "scale.circom";
+                    template Double() {
+                        signal input in;
+                        signal output out;
+                        component scale = Scale(2);
+                        scale.in <== in;
+                        out <== scale.out;
+                    }
+                "#,
+            ),
+            (
+                "gates/scale.circom",
+                r#"
+                    pragma circom 2.1.0;
+                    template Scale(k) {
+                        signal input in;
+                        signal output out;
+                        out <== in * k;
+                    }
+                "#,
+            ),
+        ]);
+        let source = r#"
+            pragma circom 2.1.0;
+            include "gates/double.circom";
+            component main = Double();
+        "#;
+
+        let compilation = CompilerBuilder::from_source(source)
+            .include_resolver(move |path| sources.get(path).map(|source| source.to_string()))
+            .compile()
+            .unwrap();
+        let outputs = compilation
+            .compiler
+            .evaluate_outputs(&elements(&[("0.in", 21)]))
+            .unwrap();
+        assert_eq!(outputs["0.out"], FieldElement::from(42));
+
+        // Missing includes fail like missing files
+        let result = CompilerBuilder::from_source(source)
+            .include_resolver(|_| None)
+            .compile();
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::Syntax);
+    }
+
+    #[test]
+    fn test_passes() {
+        /// Counts the multiplications left by the built-in optimizations.
+        struct CountMultiplications(Rc<Cell<usize>>);
+
+        impl Pass for CountMultiplications {
+            fn name(&self) -> &str {
+                "count_multiplications"
+            }
+
+            fn stage(&self) -> PassStage {
+                PassStage::Optimization
+            }
+
+            fn run(
+                &self,
+                compiler: &mut Compiler,
+                _: &mut PassContext,
+            ) -> Result<(), ProgramError> {
+                let count = compiler
+                    .gates()
+                    .iter()
+                    .filter(|gate| gate.op == AGateType::AMul)
+                    .count();
+                self.0.set(count);
+                Ok(())
+            }
+        }
+
+        let count = Rc::new(Cell::new(0));
+        let compilation =
+            CompilerBuilder::from_path("tests/circuits/integration/matElemMul.circom")
+                .pass(CountMultiplications(count.clone()))
+                .compile()
+                .unwrap();
+        assert_eq!(count.get(), 4);
+
+        let passes = compilation
+            .compiler
+            .pass_timings()
+            .iter()
+            .map(|timing| timing.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            passes,
+            vec![
+                "constant_outputs",
+                "bind_weights",
+                "apply_stages",
+                "add_noise",
+                "fold_constants",
+                "reduce_divisions",
+                "balance_additions",
+                "fuse_constant_mul",
+                "prune_gates",
+                "prune_inputs",
+                "lower_gates",
+                "count_multiplications",
+                "check_target"
+            ]
+        );
+
+        let mut registry = PassRegistry::default();
+        assert!(matches!(
+            registry.register_before("unknown_pass", CountMultiplications(count.clone())),
+            Err(ProgramError::UnknownPass(_))
+        ));
+        registry.remove("constant_outputs").unwrap();
+        let compilation =
+            CompilerBuilder::from_path("tests/circuits/integration/matElemMul.circom")
+                .passes(registry)
+                .compile()
+                .unwrap();
+        assert_eq!(compilation.compiler.pass_timings().len(), 7);
+    }
+
+    #[test]
+    fn test_signal_map() {
+        let output_dir = std::env::temp_dir().join("circom_2_arithc_signal_map");
+        let mut compiler_input = Args::new(
+            "tests/circuits/integration/neurons.circom".into(),
+            output_dir,
+            ValueType::Sint,
+            None,
+        );
+        compiler_input.signal_map = true;
+        let artifacts = compile_and_write(&compiler_input).unwrap();
+
+        let sym = std::fs::read_to_string(artifacts.signal_map.unwrap()).unwrap();
+        let lines = sym
+            .lines()
+            .map(|line| line.split(',').collect::<Vec<&str>>())
+            .collect::<Vec<_>>();
+        assert!(lines.iter().all(|line| line.len() == 3));
+        assert!(!sym.contains('%'));
+
+        // Outputs are declared in the main template, and share their wire with a neuron output
+        let circuit = compile(&compiler_input).unwrap().build_circuit().unwrap();
+        for j in 0..4 {
+            let name = format!("0.out[{}]", j);
+            let wire = circuit.info.output_name_to_wire_index[&name].to_string();
+            assert!(lines.contains(&vec![wire.as_str(), name.as_str(), "Dense"]));
+            assert!(lines
+                .iter()
+                .any(|line| line[0] == wire && line[2] == "Dense/Neuron"));
+        }
+    }
+
+    #[test]
+    fn test_simd_groups() {
+        let output_dir = std::env::temp_dir().join("circom_2_arithc_simd_groups");
+        let mut compiler_input = Args::new(
+            "tests/circuits/integration/neurons.circom".into(),
+            output_dir,
+            ValueType::Sint,
+            None,
+        );
+        compiler_input.simd_groups = true;
+        let artifacts = compile_and_write(&compiler_input).unwrap();
+
+        let groups: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(artifacts.simd_groups.unwrap()).unwrap())
+                .unwrap();
+        let groups = groups.as_array().unwrap();
+        assert_eq!(groups.len(), 1);
+
+        // One instance per neuron: 3 multiplications and 3 additions of 7 inputs
+        let template = groups[0]["template"].as_array().unwrap();
+        let instances = groups[0]["instances"].as_array().unwrap();
+        assert_eq!(template.len(), 6);
+        assert_eq!(instances.len(), 4);
+
+        let circuit = compile(&compiler_input).unwrap().build_circuit().unwrap();
+        let outputs = &circuit.info.output_name_to_wire_index;
+        for (j, instance) in instances.iter().enumerate() {
+            assert_eq!(instance["inputs"].as_array().unwrap().len(), 7);
+            assert_eq!(
+                instance["outputs"],
+                serde_json::json!([outputs[&format!("0.out[{}]", j)]])
+            );
+        }
+    }
+
+    #[test]
+    fn test_audit_log() {
+        let output_dir = std::env::temp_dir().join("circom_2_arithc_audit_log");
+        let audit_path = std::env::temp_dir().join("circom_2_arithc_audit_log.jsonl");
+        let _ = std::fs::remove_file(&audit_path);
+        let mut compiler_input = Args::new(
+            "tests/circuits/integration/sum.circom".into(),
+            output_dir,
+            ValueType::Sint,
+            None,
+        );
+        compiler_input.audit_log = Some(audit_path.clone());
+        let artifacts = compile_and_write(&compiler_input).unwrap();
+
+        let audit_log = std::fs::read_to_string(&audit_path).unwrap();
+        let entries = audit_log
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert!(entries.iter().any(|entry| entry["entry"] == "gate"
+            && entry["op"] == "AAdd"
+            && entry["source"]
+                .as_str()
+                .is_some_and(|source| source.ends_with("sum.circom:9"))));
+
+        let audit_hash = artifacts.audit_hash.unwrap();
+        assert_eq!(audit_hash.len(), 64);
+        let card = std::fs::read_to_string(&artifacts.card).unwrap();
+        assert!(card.contains(&format!("| Audit log | {} |", audit_hash)));
+
+        // The log is append-only, so a second compilation extends it, but the hash only covers
+        // the entries of the compilation
+        let artifacts = compile_and_write(&compiler_input).unwrap();
+        let extended = std::fs::read_to_string(&audit_path).unwrap();
+        assert!(extended.starts_with(&audit_log));
+        assert_eq!(extended.len(), 2 * audit_log.len());
+        assert_eq!(artifacts.audit_hash, Some(audit_hash));
+    }
+
+    #[test]
+    fn test_events_file() {
+        let output_dir = std::env::temp_dir().join("circom_2_arithc_events_file");
+        let events_path = std::env::temp_dir().join("circom_2_arithc_events_file.jsonl");
+        let mut compiler_input = Args::new(
+            "tests/circuits/integration/constantSum.circom".into(),
+            output_dir,
+            ValueType::Sint,
+            None,
+        );
+        compiler_input.events_file = Some(events_path.clone());
+        let artifacts = compile_and_write(&compiler_input).unwrap();
+        assert_eq!(artifacts.events, Some(events_path.clone()));
+
+        let events = std::fs::read_to_string(events_path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect::<Vec<serde_json::Value>>();
+
+        let phases = events
+            .iter()
+            .filter(|event| event["event"] == "phase_finished")
+            .map(|event| event["phase"].as_str().unwrap())
+            .collect::<Vec<&str>>();
+        assert_eq!(
+            phases,
+            vec!["parse", "analyse", "process", "graph", "build", "write"]
+        );
+
+        assert!(events.iter().any(|event| event["event"] == "count"
+            && event["name"] == "circuit_gates"
+            && event["value"] == artifacts.gate_count as u64));
+        assert!(events.iter().any(|event| event["event"] == "warning"));
+    }
 }